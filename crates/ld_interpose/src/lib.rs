@@ -0,0 +1,229 @@
+// src/lib.rs
+//
+// The plumbing every LD_PRELOAD shim in this workspace needs for each
+// function it interposes: an exported symbol with the C signature, a cache
+// of the real function behind it, found with dlsym(RTLD_NEXT) on the first
+// call, and a way out when it can't be found. `interpose!` writes all of it
+// from a table, and hands the real function and the caller's arguments to
+// a handler of the same name in the module named for the group, so a shim
+// only writes safe-to-call handlers.
+//
+// dlsym may allocate, and a malloc replacement that logs or starts threads
+// from inside the allocator lands right back in an interposed function
+// while the lookup is still running. Every lookup sets a per-thread flag,
+// and a call made while it is set gets no function rather than recursing
+// into dlsym.
+
+use std::cell::Cell;
+use std::ffi::{CStr, c_void};
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+// bionic's, which the libc crate doesn't expose
+#[cfg(target_os = "android")]
+const RTLD_NEXT: *mut c_void = if cfg!(target_pointer_width = "64") {
+    -1isize as *mut c_void
+} else {
+    -2isize as *mut c_void
+};
+#[cfg(not(target_os = "android"))]
+use libc::RTLD_NEXT;
+
+thread_local! {
+    // set while this thread is inside dlsym; const-initialized, so reading it never
+    // allocates
+    static RESOLVING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether this thread is in the middle of a lookup.
+pub fn in_progress() -> bool {
+    RESOLVING.with(Cell::get)
+}
+
+/// Runs the lookup `f` with the flag set, or returns `None` if this thread is already
+/// inside one.
+pub fn resolving<T>(f: impl FnOnce() -> T) -> Option<T> {
+    if RESOLVING.replace(true) {
+        return None;
+    }
+    let found = f();
+    RESOLVING.set(false);
+    Some(found)
+}
+
+/// Looks up the default version of `symbol` in the objects after the caller's.
+pub fn next(symbol: &CStr) -> *mut c_void {
+    unsafe { libc::dlsym(RTLD_NEXT, symbol.as_ptr()) }
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+unsafe extern "C" {
+    // not exposed by the libc crate
+    fn dlvsym(
+        handle: *mut c_void,
+        symbol: *const libc::c_char,
+        version: *const libc::c_char,
+    ) -> *mut c_void;
+}
+
+/// Looks up `symbol@version` in the objects after the caller's, for a symbol whose
+/// default version isn't the one every caller bound to.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+pub fn next_versioned(symbol: &CStr, version: &CStr) -> *mut c_void {
+    unsafe { dlvsym(RTLD_NEXT, symbol.as_ptr(), version.as_ptr()) }
+}
+
+/// [`next`] cached in `cache`, or `None` when called from inside another lookup on this
+/// thread or when the symbol can't be found (retried next time).
+pub fn cached_next(cache: &AtomicPtr<c_void>, symbol: &'static CStr) -> Option<*mut c_void> {
+    let mut sym = cache.load(Ordering::Acquire);
+    if sym.is_null() {
+        sym = resolving(|| next(symbol))?;
+        if sym.is_null() {
+            return None;
+        }
+        cache.store(sym, Ordering::Release);
+    }
+    Some(sym)
+}
+
+/// Looks up `symbol` in the objects loaded globally, the way the program's own calls bind:
+/// for the API of a library that is preloaded alongside (the propagator's `otel_posix_*`
+/// functions) but may not be there at all.
+pub fn global(symbol: &CStr) -> *mut c_void {
+    unsafe { libc::dlsym(libc::RTLD_DEFAULT, symbol.as_ptr()) }
+}
+
+// what `cached_global` keeps for a symbol that isn't there
+const MISSING: *mut c_void = usize::MAX as *mut c_void;
+
+/// [`global`] cached in `cache`, or `None` when called from inside another lookup on this
+/// thread or when the symbol can't be found. Unlike [`cached_next`], a missing symbol is
+/// remembered, so a shim asking on every call doesn't pay for a lookup each time; a
+/// library loaded later isn't seen.
+pub fn cached_global(cache: &AtomicPtr<c_void>, symbol: &'static CStr) -> Option<*mut c_void> {
+    let mut sym = cache.load(Ordering::Acquire);
+    if sym.is_null() {
+        sym = resolving(|| global(symbol))?;
+        if sym.is_null() {
+            sym = MISSING;
+        }
+        cache.store(sym, Ordering::Release);
+    }
+    (sym != MISSING).then_some(sym)
+}
+
+/// Writes each entry's exported function. Handlers take the real function first, typed
+/// as they declare it (so a variadic one can be called as such), then the arguments.
+///
+/// Entries are grouped by the module their handlers are in, relative to the crate root;
+/// attributes on a group (a `cfg` picking it with a feature) go on each of its functions.
+/// The optional `lookup = path;` first names the function that finds and caches the real
+/// one, [`cached_next`] by default.
+// `crate::$module` is meant: the handlers are the calling crate's
+#[allow(clippy::crate_in_macro_def)]
+#[macro_export]
+macro_rules! interpose {
+    // a group's entries, with the group's attributes carried along as one token tree
+    (@group $lookup:path; $group:tt $module:ident {
+        $(
+            $(#[$attr:meta])*
+            fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?, else $unavailable:expr;
+        )*
+    }) => {$(
+        $crate::interpose! { @item $group {
+            $(#[$attr])*
+            ///
+            /// # Safety
+            ///
+            #[doc = concat!("Same contract as the interposed library's `", stringify!($name), "`.")]
+            #[unsafe(no_mangle)]
+            pub unsafe extern "C" fn $name($($arg: $ty),*) $(-> $ret)? {
+                static REAL: std::sync::atomic::AtomicPtr<std::ffi::c_void> =
+                    std::sync::atomic::AtomicPtr::new(std::ptr::null_mut());
+                const SYMBOL: &std::ffi::CStr =
+                    match std::ffi::CStr::from_bytes_with_nul(concat!(stringify!($name), "\0").as_bytes()) {
+                        Ok(symbol) => symbol,
+                        Err(_) => panic!("symbol names have no NUL"),
+                    };
+                match $lookup(&REAL, SYMBOL) {
+                    // the handler's first parameter says what the real function is
+                    Some(real) => unsafe {
+                        crate::$module::$name(std::mem::transmute_copy(&real), $($arg),*)
+                    },
+                    None => $unavailable,
+                }
+            }
+        }}
+    )*};
+    (@item [$(#[$group:meta])*] { $($item:tt)* }) => {
+        $(#[$group])*
+        $($item)*
+    };
+    (
+        lookup = $lookup:path;
+        $($(#[$group:meta])* $module:ident { $($entries:tt)* })*
+    ) => {$(
+        $crate::interpose! { @group $lookup; [$(#[$group])*] $module { $($entries)* } }
+    )*};
+    ($($(#[$group:meta])* $module:ident { $($entries:tt)* })*) => {
+        $crate::interpose! {
+            lookup = $crate::cached_next;
+            $($(#[$group])* $module { $($entries)* })*
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    type GetppidFn = unsafe extern "C" fn() -> libc::pid_t;
+
+    /// The handler for the `getppid` the test binary exports below.
+    unsafe fn getppid(real: GetppidFn) -> libc::pid_t {
+        CALLS.fetch_add(1, Ordering::Relaxed);
+        unsafe { real() }
+    }
+
+    mod exported {
+        interpose! {
+            tests {
+                fn getppid() -> libc::pid_t, else -1;
+            }
+        }
+    }
+
+    #[test]
+    fn calls_reach_the_handler_and_the_real_function() {
+        let real = next(c"getppid");
+        assert!(!real.is_null());
+        let real = unsafe { std::mem::transmute::<*mut c_void, GetppidFn>(real) };
+        let before = CALLS.load(Ordering::Relaxed);
+        assert_eq!(unsafe { libc::getppid() }, unsafe { real() });
+        assert_eq!(CALLS.load(Ordering::Relaxed), before + 1);
+    }
+
+    #[test]
+    fn nested_lookups_get_nothing() {
+        let cache = AtomicPtr::new(std::ptr::null_mut());
+        assert_eq!(resolving(|| cached_next(&cache, c"getpid")), Some(None));
+        assert!(cache.load(Ordering::Relaxed).is_null());
+        assert!(!in_progress());
+        assert!(cached_next(&cache, c"getpid").is_some());
+        let missing = AtomicPtr::new(std::ptr::null_mut());
+        assert_eq!(cached_next(&missing, c"no_such_symbol_anywhere"), None);
+    }
+
+    #[test]
+    fn missing_globals_are_remembered() {
+        let cache = AtomicPtr::new(std::ptr::null_mut());
+        assert!(cached_global(&cache, c"getpid").is_some());
+        let missing = AtomicPtr::new(std::ptr::null_mut());
+        assert_eq!(cached_global(&missing, c"no_such_symbol_anywhere"), None);
+        assert_eq!(missing.load(Ordering::Relaxed), MISSING);
+        assert_eq!(cached_global(&missing, c"no_such_symbol_anywhere"), None);
+    }
+}
@@ -0,0 +1,87 @@
+// src/lib.rs
+//
+// An LD_PRELOAD tracer for the waits a program doesn't show: the sleeps
+// and slow polls inside the third-party libraries it links, a retry loop's
+// back-off or a client library blocking on its socket. nanosleep, usleep,
+// poll and select are interposed and timed (wait.rs), and a call that
+// blocked for OTEL_BLOCKING_TRACER_THRESHOLD_US or more (1 ms by default)
+// is recorded as an event on the calling thread's span, with what it was
+// asked to wait for and how it ended (record.rs).
+//
+// sleep, clock_nanosleep, ppoll, pselect and epoll_wait aren't interposed,
+// and neither are the waits glibc makes internally; a call made before the
+// real functions are found goes to the kernel unrecorded.
+
+mod record;
+mod wait;
+
+use libc::{c_int, fd_set, nfds_t, pollfd, timespec, timeval, useconds_t};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+const DEFAULT_THRESHOLD: Duration = Duration::from_millis(1);
+
+static THRESHOLD_NANOS: AtomicU64 = AtomicU64::new(DEFAULT_THRESHOLD.as_nanos() as u64);
+
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+#[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__mod_init_func"))]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(configure);
+}
+
+fn configure() {
+    if let Ok(value) = std::env::var("OTEL_BLOCKING_TRACER_THRESHOLD_US") {
+        match value.trim().parse() {
+            Ok(micros) => set_threshold(Duration::from_micros(micros)),
+            Err(_) => eprintln!(
+                "otel_blocking_tracer: invalid OTEL_BLOCKING_TRACER_THRESHOLD_US {value:?}, using {}",
+                DEFAULT_THRESHOLD.as_micros()
+            ),
+        }
+    }
+}
+
+/// Sets the shortest blocking call that is recorded.
+pub fn set_threshold(threshold: Duration) {
+    THRESHOLD_NANOS.store(
+        threshold.as_nanos().try_into().unwrap_or(u64::MAX),
+        Ordering::Relaxed,
+    );
+}
+
+fn threshold() -> Duration {
+    Duration::from_nanos(THRESHOLD_NANOS.load(Ordering::Relaxed))
+}
+
+ld_interpose::interpose! {
+    wait {
+        /// Interposed `nanosleep` that records a long sleep.
+        fn nanosleep(req: *const timespec, rem: *mut timespec) -> c_int,
+            else unsafe { wait::sys_nanosleep(req, rem) };
+
+        /// Interposed `usleep` that records a long sleep.
+        fn usleep(usec: useconds_t) -> c_int,
+            else unsafe { wait::sys_usleep(usec) };
+
+        /// Interposed `poll` that records a long wait for its descriptors.
+        fn poll(fds: *mut pollfd, nfds: nfds_t, timeout: c_int) -> c_int,
+            else unsafe { wait::sys_poll(fds, nfds, timeout) };
+
+        /// Interposed `select` that records a long wait for its descriptors.
+        fn select(
+            nfds: c_int,
+            readfds: *mut fd_set,
+            writefds: *mut fd_set,
+            exceptfds: *mut fd_set,
+            timeout: *mut timeval,
+        ) -> c_int,
+            else unsafe { wait::sys_select(nfds, readfds, writefds, exceptfds, timeout) };
+    }
+}
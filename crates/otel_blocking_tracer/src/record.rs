@@ -0,0 +1,51 @@
+// src/record.rs
+//
+// Recording a wait. One of the threshold or longer, made by a thread
+// serving a trace, is added as an event on its current span; shorter ones,
+// and ones made outside a trace, aren't recorded at all, so a program that
+// polls often and briefly pays a clock read per call and nothing more.
+
+use otel_posix_propagator_sys::{self as propagator, Attribute, Errno};
+use std::ffi::CStr;
+use std::time::Duration;
+
+/// A call that may block, before it is made.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Wait {
+    /// The function called, which names the event.
+    pub(crate) function: &'static CStr,
+    /// How long it was asked to sleep, or to wait at most; `None` for no limit.
+    pub(crate) timeout: Option<Duration>,
+    /// The descriptors it waits on, for `poll` and `select`.
+    pub(crate) fds: Option<usize>,
+}
+
+/// Records `wait`, which took `waited` and returned `result`, if it was long enough.
+pub(crate) fn waited(wait: Wait, result: i64, waited: Duration) {
+    if waited < crate::threshold() || !propagator::serving() {
+        return;
+    }
+    let errno = Errno::keep();
+    let mut attributes = vec![Attribute::double(
+        c"otel_posix.blocking.wait_time",
+        waited.as_secs_f64(),
+    )];
+    attributes.extend(
+        wait.timeout
+            .map(|t| Attribute::double(c"otel_posix.blocking.timeout", t.as_secs_f64())),
+    );
+    if let Some(fds) = wait.fds {
+        attributes.push(Attribute::int(c"otel_posix.blocking.fds", fds as i64));
+        if result >= 0 {
+            attributes.push(Attribute::int(c"otel_posix.blocking.ready", result));
+        }
+    }
+    if result < 0 {
+        attributes.push(Attribute::int(
+            c"otel_posix.blocking.errno",
+            errno.get().into(),
+        ));
+    }
+    // stamped with when the wait began
+    propagator::add_event(wait.function, propagator::began(waited), &attributes);
+}
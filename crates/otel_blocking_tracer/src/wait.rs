@@ -0,0 +1,201 @@
+// src/wait.rs
+//
+// The interposed sleep and poll functions. Each notes what it was asked
+// for (how long to sleep, the timeout, the descriptors waited on) before
+// the real call, which may overwrite it, then times the call. select's
+// descriptors are the ones set in its three sets, not its nfds, which is
+// only one more than the highest of them.
+//
+// A call made before the real function is found goes to the kernel
+// instead, through nanosleep, ppoll or pselect6, without being recorded.
+
+use crate::record::{self, Wait};
+use libc::{c_int, fd_set, nfds_t, pollfd, timespec, timeval, useconds_t};
+use otel_posix_propagator_sys as propagator;
+use std::ffi::c_void;
+use std::time::{Duration, Instant};
+
+pub(crate) type NanosleepFn = unsafe extern "C" fn(*const timespec, *mut timespec) -> c_int;
+pub(crate) type UsleepFn = unsafe extern "C" fn(useconds_t) -> c_int;
+pub(crate) type PollFn = unsafe extern "C" fn(*mut pollfd, nfds_t, c_int) -> c_int;
+pub(crate) type SelectFn =
+    unsafe extern "C" fn(c_int, *mut fd_set, *mut fd_set, *mut fd_set, *mut timeval) -> c_int;
+
+/// Makes the call `f`, recording it as `wait` when it blocked long enough.
+fn timed(wait: impl FnOnce() -> Wait, f: impl FnOnce() -> c_int) -> c_int {
+    let Some(_busy) = propagator::enter() else {
+        return f();
+    };
+    let wait = wait();
+    let started = Instant::now();
+    let result = f();
+    record::waited(wait, result.into(), started.elapsed());
+    result
+}
+
+/// The duration `ts` points at, or `None` for a null or invalid one.
+unsafe fn duration(ts: *const timespec) -> Option<Duration> {
+    let ts = unsafe { ts.as_ref() }?;
+    let nanos = u32::try_from(ts.tv_nsec)
+        .ok()
+        .filter(|&n| n < 1_000_000_000)?;
+    Some(Duration::new(u64::try_from(ts.tv_sec).ok()?, nanos))
+}
+
+/// The descriptors below `nfds` set in any of `sets`.
+unsafe fn selected(nfds: c_int, sets: [*const fd_set; 3]) -> usize {
+    (0..nfds.clamp(0, libc::FD_SETSIZE as c_int))
+        .filter(|&fd| {
+            sets.iter()
+                .any(|&set| !set.is_null() && unsafe { libc::FD_ISSET(fd, set) })
+        })
+        .count()
+}
+
+pub(crate) unsafe fn sys_nanosleep(req: *const timespec, rem: *mut timespec) -> c_int {
+    unsafe { libc::syscall(libc::SYS_nanosleep, req, rem) as c_int }
+}
+
+pub(crate) unsafe fn sys_usleep(usec: useconds_t) -> c_int {
+    let req = timespec {
+        tv_sec: (usec / 1_000_000) as _,
+        tv_nsec: ((usec % 1_000_000) * 1000) as _,
+    };
+    unsafe { sys_nanosleep(&req, std::ptr::null_mut()) }
+}
+
+pub(crate) unsafe fn sys_poll(fds: *mut pollfd, nfds: nfds_t, timeout: c_int) -> c_int {
+    let ts = timespec {
+        tv_sec: (timeout / 1000) as _,
+        tv_nsec: ((timeout % 1000) * 1_000_000) as _,
+    };
+    let ts: *const timespec = if timeout < 0 { std::ptr::null() } else { &ts };
+    let sigmask: *const libc::sigset_t = std::ptr::null();
+    unsafe { libc::syscall(libc::SYS_ppoll, fds, nfds, ts, sigmask, 0) as c_int }
+}
+
+pub(crate) unsafe fn sys_select(
+    nfds: c_int,
+    readfds: *mut fd_set,
+    writefds: *mut fd_set,
+    exceptfds: *mut fd_set,
+    timeout: *mut timeval,
+) -> c_int {
+    let ts = unsafe { timeout.as_ref() }.map(|tv| timespec {
+        tv_sec: tv.tv_sec,
+        tv_nsec: (tv.tv_usec * 1000) as _,
+    });
+    let ts = ts
+        .as_ref()
+        .map_or(std::ptr::null(), |ts| ts as *const timespec);
+    // no signal mask to swap in
+    let sigmask: *const c_void = std::ptr::null();
+    unsafe {
+        libc::syscall(
+            libc::SYS_pselect6,
+            nfds,
+            readfds,
+            writefds,
+            exceptfds,
+            ts,
+            sigmask,
+        ) as c_int
+    }
+}
+
+pub(crate) unsafe fn nanosleep(
+    real: NanosleepFn,
+    req: *const timespec,
+    rem: *mut timespec,
+) -> c_int {
+    timed(
+        || Wait {
+            function: c"nanosleep",
+            timeout: unsafe { duration(req) },
+            fds: None,
+        },
+        || unsafe { real(req, rem) },
+    )
+}
+
+pub(crate) unsafe fn usleep(real: UsleepFn, usec: useconds_t) -> c_int {
+    timed(
+        || Wait {
+            function: c"usleep",
+            timeout: Some(Duration::from_micros(usec.into())),
+            fds: None,
+        },
+        || unsafe { real(usec) },
+    )
+}
+
+pub(crate) unsafe fn poll(real: PollFn, fds: *mut pollfd, nfds: nfds_t, timeout: c_int) -> c_int {
+    timed(
+        || Wait {
+            function: c"poll",
+            timeout: u64::try_from(timeout).ok().map(Duration::from_millis),
+            fds: Some(nfds as usize),
+        },
+        || unsafe { real(fds, nfds, timeout) },
+    )
+}
+
+pub(crate) unsafe fn select(
+    real: SelectFn,
+    nfds: c_int,
+    readfds: *mut fd_set,
+    writefds: *mut fd_set,
+    exceptfds: *mut fd_set,
+    timeout: *mut timeval,
+) -> c_int {
+    timed(
+        || Wait {
+            function: c"select",
+            timeout: unsafe { timeout.as_ref() }.and_then(|tv| {
+                let micros = u64::try_from(tv.tv_usec).ok()?;
+                Some(
+                    Duration::from_secs(u64::try_from(tv.tv_sec).ok()?)
+                        + Duration::from_micros(micros),
+                )
+            }),
+            fds: Some(unsafe {
+                selected(nfds, [readfds, writefds, exceptfds].map(|s| s.cast_const()))
+            }),
+        },
+        || unsafe { real(nfds, readfds, writefds, exceptfds, timeout) },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_counts_the_descriptors_set() {
+        let mut read: fd_set = unsafe { std::mem::zeroed() };
+        let mut write: fd_set = unsafe { std::mem::zeroed() };
+        unsafe {
+            libc::FD_SET(3, &mut read);
+            libc::FD_SET(7, &mut read);
+            libc::FD_SET(7, &mut write);
+            libc::FD_SET(9, &mut write);
+        }
+        let sets = [&raw const read, &raw const write, std::ptr::null()];
+        assert_eq!(unsafe { selected(10, sets) }, 3);
+        // nfds leaves out the descriptors at and above it
+        assert_eq!(unsafe { selected(8, sets) }, 2);
+        assert_eq!(unsafe { selected(0, sets) }, 0);
+    }
+
+    #[test]
+    fn invalid_sleeps_have_no_timeout() {
+        let ts = |tv_sec, tv_nsec| timespec { tv_sec, tv_nsec };
+        assert_eq!(
+            unsafe { duration(&ts(1, 500_000_000)) },
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(unsafe { duration(&ts(-1, 0)) }, None);
+        assert_eq!(unsafe { duration(&ts(0, 1_000_000_000)) }, None);
+        assert_eq!(unsafe { duration(std::ptr::null()) }, None);
+    }
+}
@@ -0,0 +1,51 @@
+/* Sleeps and polls for 50 ms each way the tracer interposes, on a thread of its own
+ * so its waits land on that thread's lifetime span, then polls a pipe that is ready at once
+ * and sleeps for less than the threshold. */
+#include <poll.h>
+#include <pthread.h>
+#include <stdio.h>
+#include <sys/select.h>
+#include <time.h>
+#include <unistd.h>
+
+#include "preload_testkit.h"
+
+static void *block(void *arg) {
+    (void)arg;
+    int fds[2];
+    if (pipe(fds) != 0)
+        return "pipe";
+    struct timespec req = {0, 50 * 1000 * 1000};
+    if (nanosleep(&req, NULL) != 0 || usleep(50 * 1000) != 0)
+        return "sleep";
+    struct pollfd polled[2] = {{fds[0], POLLIN, 0}, {fds[1], 0, 0}};
+    if (poll(polled, 2, 50) != 0)
+        return "poll";
+    fd_set read;
+    FD_ZERO(&read);
+    FD_SET(fds[0], &read);
+    struct timeval timeout = {0, 50 * 1000};
+    if (select(fds[0] + 1, &read, NULL, NULL, &timeout) != 0)
+        return "select";
+    /* neither is recorded */
+    if (write(fds[1], "x", 1) != 1 || poll(polled, 1, -1) != 1 || usleep(100) != 0)
+        return "ready";
+    close(fds[0]);
+    close(fds[1]);
+    return NULL;
+}
+
+int main(void) {
+    if (testkit_start() != 0)
+        return 1;
+    pthread_t thread;
+    void *failed;
+    if (pthread_create(&thread, NULL, block, NULL) != 0 || pthread_join(thread, &failed) != 0)
+        return 1;
+    if (failed != NULL) {
+        fprintf(stderr, "%s failed\n", (const char *)failed);
+        return 1;
+    }
+    puts("done");
+    return 0;
+}
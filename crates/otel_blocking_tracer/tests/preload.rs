@@ -0,0 +1,40 @@
+#![cfg(target_os = "linux")]
+
+use preload_testkit::{Fixture, Run, SPAN, TRACE};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCKED: f64 = 0.05;
+
+    #[test]
+    fn long_sleeps_and_polls_are_recorded() {
+        let program = Fixture::c("tests/fixtures/block.c").compile();
+        let out = Run::traced(&program, "otel_blocking_tracer")
+            // a span for the worker thread, for its waits to land on
+            .env("OTEL_POSIX_PROP_MODE", "lifetime")
+            .env("OTEL_BLOCKING_TRACER_THRESHOLD_US", "10000")
+            .output()
+            .success();
+        assert_eq!(out.lines(), ["done"]);
+
+        let spans = out.spans();
+        let thread = spans.child(TRACE, SPAN, "thread");
+        let names: Vec<&str> = thread.events.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["nanosleep", "usleep", "poll", "select"], "{thread}");
+        for event in &thread.events {
+            assert_eq!(event.float("otel_posix.blocking.timeout"), Some(BLOCKED));
+            let waited = event.float("otel_posix.blocking.wait_time").unwrap();
+            assert!(waited >= BLOCKED / 2.0, "{thread}");
+            assert_eq!(event.int("otel_posix.blocking.errno"), None);
+        }
+        for (name, fds) in [("poll", 2), ("select", 1)] {
+            let event = thread.events_named(name).next().unwrap();
+            assert_eq!(event.int("otel_posix.blocking.fds"), Some(fds));
+            assert_eq!(event.int("otel_posix.blocking.ready"), Some(0));
+        }
+        let usleep = thread.events_named("usleep").next().unwrap();
+        assert_eq!(usleep.int("otel_posix.blocking.fds"), None);
+    }
+}
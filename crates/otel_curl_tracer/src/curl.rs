@@ -0,0 +1,173 @@
+// src/curl.rs
+//
+// The parts of libcurl's ABI this library uses, written out here since
+// its headers may not be installed where the library is built: the codes
+// and option and info numbers, the header list, and the functions it calls
+// itself. Those are libcurl's own, looked up past this library (it exports
+// curl_easy_setopt too), and only once a handle has been seen, so by then
+// libcurl is loaded. The numbers are part of libcurl's stable ABI.
+//
+// curl_easy_setopt and curl_easy_getinfo are variadic, each taking one
+// argument after the option, which is a long, a pointer or a curl_off_t,
+// all passed alike on the ABIs this runs on.
+
+use libc::{c_char, c_int, c_long, c_void};
+use std::ffi::CStr;
+use std::sync::atomic::AtomicPtr;
+
+/// An easy handle, `CURL *`.
+pub(crate) type Easy = *mut c_void;
+/// A multi handle, `CURLM *`.
+pub(crate) type Multi = *mut c_void;
+
+/// `struct curl_slist`, a header list.
+#[repr(C)]
+pub(crate) struct Slist {
+    pub(crate) data: *mut c_char,
+    pub(crate) next: *mut Slist,
+}
+
+/// `CURLMsg`, what curl_multi_info_read returns.
+#[repr(C)]
+pub(crate) struct Message {
+    pub(crate) msg: c_int,
+    pub(crate) easy_handle: Easy,
+    pub(crate) data: MessageData,
+}
+
+/// What a `CURLMsg` carries: for `CURLMSG_DONE`, the transfer's code.
+#[repr(C)]
+pub(crate) union MessageData {
+    whatever: *mut c_void,
+    pub(crate) result: c_int,
+}
+
+pub(crate) const CURLE_OK: c_int = 0;
+pub(crate) const CURLE_FAILED_INIT: c_int = 2;
+pub(crate) const CURLM_OK: c_int = 0;
+pub(crate) const CURLM_INTERNAL_ERROR: c_int = 4;
+pub(crate) const CURLMSG_DONE: c_int = 1;
+
+// CURLOPTTYPE_SLISTPOINT + 23
+pub(crate) const CURLOPT_HTTPHEADER: c_int = 10023;
+
+// CURLINFO_STRING, CURLINFO_LONG and CURLINFO_OFF_T, and the numbers within each
+const CURLINFO_EFFECTIVE_URL: c_int = 0x10_0000 + 1;
+const CURLINFO_RESPONSE_CODE: c_int = 0x20_0000 + 2;
+const CURLINFO_SIZE_DOWNLOAD_T: c_int = 0x60_0000 + 8;
+const CURLINFO_PRIMARY_IP: c_int = 0x10_0000 + 32;
+const CURLINFO_PRIMARY_PORT: c_int = 0x20_0000 + 40;
+const CURLINFO_EFFECTIVE_METHOD: c_int = 0x10_0000 + 58;
+
+type SetoptFn = unsafe extern "C" fn(Easy, c_int, ...) -> c_int;
+type GetinfoFn = unsafe extern "C" fn(Easy, c_int, ...) -> c_int;
+type SlistAppendFn = unsafe extern "C" fn(*mut Slist, *const c_char) -> *mut Slist;
+type SlistFreeAllFn = unsafe extern "C" fn(*mut Slist);
+type StrerrorFn = unsafe extern "C" fn(c_int) -> *const c_char;
+
+static SETOPT: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static GETINFO: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static SLIST_APPEND: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static SLIST_FREE_ALL: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static STRERROR: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+/// libcurl's `symbol`, as an `F`.
+fn function<F>(cache: &AtomicPtr<c_void>, symbol: &'static CStr) -> Option<F> {
+    let f = ld_interpose::cached_next(cache, symbol)?;
+    Some(unsafe { std::mem::transmute_copy::<*mut c_void, F>(&f) })
+}
+
+/// Sets `handle`'s header list to `headers`, returning whether curl took it.
+pub(crate) fn set_headers(handle: Easy, headers: *mut Slist) -> bool {
+    function::<SetoptFn>(&SETOPT, c"curl_easy_setopt")
+        .is_some_and(|f| unsafe { f(handle, CURLOPT_HTTPHEADER, headers) } == CURLE_OK)
+}
+
+/// A copy of the list `headers` with `header` appended, or `None` if curl couldn't make
+/// one.
+pub(crate) fn copy_with(headers: *const Slist, header: &CStr) -> Option<*mut Slist> {
+    let append = function::<SlistAppendFn>(&SLIST_APPEND, c"curl_slist_append")?;
+    let mut copy = std::ptr::null_mut();
+    for h in self::headers(headers).chain([header]) {
+        // the list is left as it was when this fails
+        let longer = unsafe { append(copy, h.as_ptr()) };
+        if longer.is_null() {
+            free_all(copy);
+            return None;
+        }
+        copy = longer;
+    }
+    Some(copy)
+}
+
+pub(crate) fn free_all(headers: *mut Slist) {
+    if let Some(f) = function::<SlistFreeAllFn>(&SLIST_FREE_ALL, c"curl_slist_free_all") {
+        unsafe { f(headers) };
+    }
+}
+
+/// The headers in the list `headers`.
+pub(crate) fn headers<'a>(mut headers: *const Slist) -> impl Iterator<Item = &'a CStr> {
+    std::iter::from_fn(move || {
+        while let Some(item) = unsafe { headers.as_ref() } {
+            headers = item.next;
+            if !item.data.is_null() {
+                return Some(unsafe { CStr::from_ptr(item.data) });
+            }
+        }
+        None
+    })
+}
+
+/// What curl says went wrong, for the code `code`.
+pub(crate) fn strerror(code: c_int) -> Option<&'static CStr> {
+    let f = function::<StrerrorFn>(&STRERROR, c"curl_easy_strerror")?;
+    let s = unsafe { f(code) };
+    (!s.is_null()).then(|| unsafe { CStr::from_ptr(s) })
+}
+
+/// What a finished transfer on a handle was, as far as curl knows it.
+#[derive(Debug, Default)]
+pub(crate) struct Info<'a> {
+    pub(crate) url: Option<&'a CStr>,
+    pub(crate) method: Option<&'a CStr>,
+    pub(crate) status: i64,
+    pub(crate) peer: Option<&'a CStr>,
+    pub(crate) peer_port: i64,
+    pub(crate) received: i64,
+}
+
+/// What curl knows of `handle`'s last transfer; the strings last until the handle's
+/// next one.
+pub(crate) fn info<'a>(handle: Easy) -> Info<'a> {
+    let Some(f) = function::<GetinfoFn>(&GETINFO, c"curl_easy_getinfo") else {
+        return Info::default();
+    };
+    let string = |info| {
+        let mut s: *const c_char = std::ptr::null();
+        let got = unsafe { f(handle, info, &raw mut s) };
+        (got == CURLE_OK && !s.is_null() && unsafe { *s } != 0)
+            .then(|| unsafe { CStr::from_ptr(s) })
+    };
+    let long = |info| {
+        let mut n: c_long = 0;
+        if unsafe { f(handle, info, &raw mut n) } != CURLE_OK {
+            n = 0;
+        }
+        // a long is 64 bits wherever this is built
+        n as i64
+    };
+    let mut received: i64 = 0;
+    if unsafe { f(handle, CURLINFO_SIZE_DOWNLOAD_T, &raw mut received) } != CURLE_OK {
+        received = 0;
+    }
+    Info {
+        url: string(CURLINFO_EFFECTIVE_URL),
+        // curl 7.72 and later
+        method: string(CURLINFO_EFFECTIVE_METHOD),
+        status: long(CURLINFO_RESPONSE_CODE),
+        peer: string(CURLINFO_PRIMARY_IP),
+        peer_port: long(CURLINFO_PRIMARY_PORT),
+        received,
+    }
+}
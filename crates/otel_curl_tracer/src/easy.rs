@@ -0,0 +1,70 @@
+// src/easy.rs
+//
+// The interposed easy-interface functions. curl_easy_perform is a whole
+// transfer on the calling thread, begun and recorded around the real call
+// (record.rs). curl_easy_setopt notes the header list a program sets, and
+// duphandle, reset and cleanup keep that in step with the handle
+// (handles.rs).
+//
+// curl_easy_setopt is variadic, with one argument after the option. It is
+// exported with that as a plain pointer, which on the ABIs this runs on is
+// passed where a long or curl_off_t would be, and handed on as it came.
+
+use crate::curl::{CURLE_OK, CURLOPT_HTTPHEADER, Easy};
+use crate::handles;
+use crate::record::Transfer;
+use libc::{c_int, c_void};
+use otel_posix_propagator_sys as propagator;
+
+pub(crate) type SetoptFn = unsafe extern "C" fn(Easy, c_int, ...) -> c_int;
+pub(crate) type PerformFn = unsafe extern "C" fn(Easy) -> c_int;
+pub(crate) type DuphandleFn = unsafe extern "C" fn(Easy) -> Easy;
+pub(crate) type HandleFn = unsafe extern "C" fn(Easy);
+
+pub(crate) unsafe fn curl_easy_setopt(
+    real: SetoptFn,
+    handle: Easy,
+    option: c_int,
+    param: *mut c_void,
+) -> c_int {
+    let result = unsafe { real(handle, option, param) };
+    if option == CURLOPT_HTTPHEADER && result == CURLE_OK {
+        handles::set_headers(handle, param.cast());
+    }
+    result
+}
+
+pub(crate) unsafe fn curl_easy_perform(real: PerformFn, handle: Easy) -> c_int {
+    let Some(_busy) = propagator::enter() else {
+        return unsafe { real(handle) };
+    };
+    let transfer = Transfer::begin(handle, handles::headers(handle));
+    let result = unsafe { real(handle) };
+    if let Some(transfer) = transfer {
+        transfer.end(handle, handles::headers(handle), Some(result));
+    }
+    result
+}
+
+pub(crate) unsafe fn curl_easy_duphandle(real: DuphandleFn, handle: Easy) -> Easy {
+    let copy = unsafe { real(handle) };
+    if !copy.is_null() {
+        handles::duplicate(handle, copy);
+    }
+    copy
+}
+
+pub(crate) unsafe fn curl_easy_reset(real: HandleFn, handle: Easy) {
+    // one still in a multi transfer goes unrecorded
+    if let Some(transfer) = handles::forget(handle) {
+        transfer.cancel(handle, std::ptr::null_mut());
+    }
+    unsafe { real(handle) }
+}
+
+pub(crate) unsafe fn curl_easy_cleanup(real: HandleFn, handle: Easy) {
+    if let Some(transfer) = handles::forget(handle) {
+        transfer.cancel(handle, std::ptr::null_mut());
+    }
+    unsafe { real(handle) }
+}
@@ -0,0 +1,87 @@
+// src/handles.rs
+//
+// What is known of each easy handle: the header list the program set on
+// it, which the traceparent is added to a copy of (curl has no way to ask
+// for it back), and the transfer in progress on it, if it was handed to a
+// multi handle. A handle the program never set headers on and that isn't
+// in a multi transfer has no entry.
+
+use crate::curl::{Easy, Slist};
+use crate::record::Transfer;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+#[derive(Default)]
+struct Handle {
+    // the program's own, never ours
+    headers: usize,
+    transfer: Option<Transfer>,
+}
+
+static HANDLES: Mutex<BTreeMap<usize, Handle>> = Mutex::new(BTreeMap::new());
+
+fn lock() -> MutexGuard<'static, BTreeMap<usize, Handle>> {
+    HANDLES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Runs `f` on `handle`'s entry, dropping the entry if it is left empty.
+fn update<T>(handle: Easy, f: impl FnOnce(&mut Handle) -> T) -> T {
+    let mut handles = lock();
+    let entry = handles.entry(handle as usize).or_default();
+    let out = f(entry);
+    if entry.headers == 0 && entry.transfer.is_none() {
+        handles.remove(&(handle as usize));
+    }
+    out
+}
+
+/// Notes that the program set `handle`'s header list to `headers`.
+pub(crate) fn set_headers(handle: Easy, headers: *mut Slist) {
+    update(handle, |h| h.headers = headers as usize);
+}
+
+/// The header list the program set on `handle`, or null.
+pub(crate) fn headers(handle: Easy) -> *mut Slist {
+    lock().get(&(handle as usize)).map_or(0, |h| h.headers) as *mut Slist
+}
+
+/// Notes that `copy` was made from `handle`, with its header list.
+pub(crate) fn duplicate(handle: Easy, copy: Easy) {
+    set_headers(copy, headers(handle));
+}
+
+/// Notes `transfer` as in progress on `handle`, returning the one it replaces.
+pub(crate) fn start(handle: Easy, transfer: Transfer) -> Option<Transfer> {
+    update(handle, |h| h.transfer.replace(transfer))
+}
+
+/// The transfer in progress on `handle`, no longer noted.
+pub(crate) fn take(handle: Easy) -> Option<Transfer> {
+    update(handle, |h| h.transfer.take())
+}
+
+/// Forgets `handle`, which is being reset or cleaned up, returning its transfer.
+pub(crate) fn forget(handle: Easy) -> Option<Transfer> {
+    lock().remove(&(handle as usize)).and_then(|h| h.transfer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_lists_are_kept_by_handle() {
+        let (handle, copy) = (0x1000 as Easy, 0x2000 as Easy);
+        let list = 0x3000 as *mut Slist;
+        assert!(headers(handle).is_null());
+        set_headers(handle, list);
+        duplicate(handle, copy);
+        assert_eq!(headers(copy), list);
+        assert!(take(handle).is_none());
+        assert_eq!(headers(handle), list);
+        assert!(forget(handle).is_none());
+        assert!(headers(handle).is_null());
+        set_headers(copy, std::ptr::null_mut());
+        assert!(lock().is_empty());
+    }
+}
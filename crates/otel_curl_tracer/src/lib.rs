@@ -0,0 +1,65 @@
+// src/lib.rs
+//
+// An LD_PRELOAD tracer for programs that make HTTP requests with libcurl,
+// the many C tools that can't be changed to trace them. Each transfer a
+// thread serving a trace makes, with curl_easy_perform (easy.rs) or
+// through a multi handle (multi.rs), gets a traceparent header naming a
+// span of its own, so the server's spans join the trace, and is then
+// recorded as that span: a client span with the method, URL, server and
+// response status (record.rs).
+//
+// The header goes in through curl's own header list, so it is sent
+// however curl sends the request, TLS and HTTP/2 included. The program's
+// list is kept as it set it (handles.rs); the traceparent is added to a
+// copy. libcurl's ABI, which this is built without the headers for, is in
+// curl.rs.
+//
+// Only http and https transfers are traced, the other protocols having no
+// headers to carry a traceparent. A program that sets a traceparent of its
+// own keeps it, and its transfer is recorded without one being added.
+
+mod curl;
+mod easy;
+mod handles;
+mod multi;
+mod record;
+mod url;
+
+use libc::{c_int, c_void};
+
+ld_interpose::interpose! {
+    easy {
+        /// Interposed `curl_easy_setopt` that notes the header list a program sets.
+        fn curl_easy_setopt(handle: *mut c_void, option: c_int, param: *mut c_void) -> c_int,
+            else curl::CURLE_FAILED_INIT;
+
+        /// Interposed `curl_easy_perform` that traces the transfer.
+        fn curl_easy_perform(handle: *mut c_void) -> c_int,
+            else curl::CURLE_FAILED_INIT;
+
+        /// Interposed `curl_easy_duphandle` that notes the copy's header list.
+        fn curl_easy_duphandle(handle: *mut c_void) -> *mut c_void,
+            else std::ptr::null_mut();
+
+        /// Interposed `curl_easy_reset` that forgets the handle's header list.
+        fn curl_easy_reset(handle: *mut c_void),
+            else ();
+
+        /// Interposed `curl_easy_cleanup` that forgets the handle.
+        fn curl_easy_cleanup(handle: *mut c_void),
+            else ();
+    }
+    multi {
+        /// Interposed `curl_multi_add_handle` that begins tracing the transfer.
+        fn curl_multi_add_handle(multi: *mut c_void, handle: *mut c_void) -> c_int,
+            else curl::CURLM_INTERNAL_ERROR;
+
+        /// Interposed `curl_multi_info_read` that records the transfers it reports done.
+        fn curl_multi_info_read(multi: *mut c_void, queued: *mut c_int) -> *mut c_void,
+            else std::ptr::null_mut();
+
+        /// Interposed `curl_multi_remove_handle` that records a transfer not yet recorded.
+        fn curl_multi_remove_handle(multi: *mut c_void, handle: *mut c_void) -> c_int,
+            else curl::CURLM_INTERNAL_ERROR;
+    }
+}
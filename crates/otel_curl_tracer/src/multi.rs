@@ -0,0 +1,64 @@
+// src/multi.rs
+//
+// The interposed multi-interface functions. A handle added to a multi
+// handle is a transfer begun, with its traceparent set before curl sends
+// anything; curl_multi_perform and curl_multi_socket_action only drive it
+// along, so they are left alone. It is recorded when curl_multi_info_read
+// reports it done, with curl's code, or else when it is removed, without
+// one.
+
+use crate::curl::{CURLM_OK, CURLMSG_DONE, Easy, Message, Multi};
+use crate::handles;
+use crate::record::Transfer;
+use libc::{c_int, c_void};
+use otel_posix_propagator_sys as propagator;
+
+pub(crate) type HandleFn = unsafe extern "C" fn(Multi, Easy) -> c_int;
+pub(crate) type InfoReadFn = unsafe extern "C" fn(Multi, *mut c_int) -> *mut Message;
+
+pub(crate) unsafe fn curl_multi_add_handle(real: HandleFn, multi: Multi, handle: Easy) -> c_int {
+    let Some(_busy) = propagator::enter() else {
+        return unsafe { real(multi, handle) };
+    };
+    let transfer = Transfer::begin(handle, handles::headers(handle));
+    let result = unsafe { real(multi, handle) };
+    let Some(transfer) = transfer else {
+        return result;
+    };
+    if result == CURLM_OK {
+        // one never reported done or removed
+        if let Some(previous) = handles::start(handle, transfer) {
+            previous.cancel(handle, handles::headers(handle));
+        }
+    } else {
+        transfer.cancel(handle, handles::headers(handle));
+    }
+    result
+}
+
+pub(crate) unsafe fn curl_multi_info_read(
+    real: InfoReadFn,
+    multi: Multi,
+    queued: *mut c_int,
+) -> *mut c_void {
+    let message = unsafe { real(multi, queued) };
+    let Some(_busy) = propagator::enter() else {
+        return message.cast();
+    };
+    if let Some(done) = unsafe { message.as_ref() }.filter(|m| m.msg == CURLMSG_DONE)
+        && let Some(transfer) = handles::take(done.easy_handle)
+    {
+        let headers = handles::headers(done.easy_handle);
+        transfer.end(done.easy_handle, headers, Some(unsafe { done.data.result }));
+    }
+    message.cast()
+}
+
+pub(crate) unsafe fn curl_multi_remove_handle(real: HandleFn, multi: Multi, handle: Easy) -> c_int {
+    if let Some(_busy) = propagator::enter()
+        && let Some(transfer) = handles::take(handle)
+    {
+        transfer.end(handle, handles::headers(handle), None);
+    }
+    unsafe { real(multi, handle) }
+}
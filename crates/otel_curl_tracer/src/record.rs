@@ -0,0 +1,194 @@
+// src/record.rs
+//
+// A transfer, from when curl was given it to when it finished, and its
+// recording. When it starts, on a thread serving a trace, the span it will
+// be recorded as is given an id, and the traceparent naming that span goes
+// in the request's headers: a copy of the program's header list with it
+// added, set on the handle for the transfer and the program's own put back
+// afterwards. A request that already carries a traceparent is sent as it
+// is.
+//
+// Once it is over, the transfer is recorded as a client span with that id,
+// named for its method, beneath the span the thread was serving, if it was
+// an HTTP one; curl's other protocols pass the header list by.
+//
+// curl_easy_perform runs its transfer through a multi handle of its own,
+// calling curl_multi_add_handle and the rest just as a program would; the
+// thread is marked busy for the length of it, and the multi functions it
+// calls pass the transfer through rather than trace it again.
+
+use crate::curl::{self, Easy, Slist};
+use crate::url;
+use libc::c_int;
+use otel_posix_propagator_sys::{
+    self as propagator, Attribute, Errno, Kind, Span, TRACEPARENT_LEN, Traceparent, now,
+};
+use std::ffi::{CStr, CString};
+
+// "traceparent: " and a traceparent
+const HEADER_LEN: usize = 13 + TRACEPARENT_LEN;
+
+/// A new span id, as 16 hex digits and a NUL.
+fn span_id() -> Option<[u8; 17]> {
+    let mut id = [0u8; 8];
+    let got = unsafe { libc::getrandom(id.as_mut_ptr().cast(), id.len(), libc::GRND_NONBLOCK) };
+    if got != id.len() as isize || id == [0; 8] {
+        return None;
+    }
+    let mut hex = [0; 17];
+    for (i, byte) in id.iter().enumerate() {
+        hex[2 * i..2 * i + 2].copy_from_slice(format!("{byte:02x}").as_bytes());
+    }
+    Some(hex)
+}
+
+/// The traceparent header naming the span `span_id` of the trace `parent` is in.
+fn header(parent: &Traceparent, span_id: &[u8; 17]) -> [u8; HEADER_LEN + 1] {
+    let parent = parent.as_bytes();
+    let mut header = [0; HEADER_LEN + 1];
+    header[..13].copy_from_slice(b"traceparent: ");
+    // version and trace id, then the span id, then the flags
+    header[13..49].copy_from_slice(&parent[..36]);
+    header[49..65].copy_from_slice(&span_id[..16]);
+    header[65..HEADER_LEN].copy_from_slice(&parent[52..]);
+    header
+}
+
+/// Whether `headers` has a traceparent header already.
+fn has_traceparent(headers: *const Slist) -> bool {
+    curl::headers(headers).any(|h| {
+        h.to_bytes()
+            .get(..12)
+            .is_some_and(|name| name.eq_ignore_ascii_case(b"traceparent:"))
+    })
+}
+
+/// A transfer being recorded.
+#[derive(Debug)]
+pub(crate) struct Transfer {
+    traceparent: Traceparent,
+    // when the request carries it
+    span_id: Option<[u8; 17]>,
+    // the program's headers and the traceparent, while set on the handle
+    headers: *mut Slist,
+    start: u64,
+}
+
+// the list is only used by whoever holds the transfer
+unsafe impl Send for Transfer {}
+
+impl Transfer {
+    /// Starts a transfer on `handle`, whose header list the program set to `headers`, if
+    /// the thread is serving a trace, adding the traceparent to the request.
+    pub(crate) fn begin(handle: Easy, headers: *mut Slist) -> Option<Transfer> {
+        let traceparent = propagator::traceparent()?;
+        let mut transfer = Transfer {
+            traceparent,
+            span_id: None,
+            headers: std::ptr::null_mut(),
+            start: 0,
+        };
+        if let Some(id) = span_id().filter(|_| !has_traceparent(headers)) {
+            let header = header(&traceparent, &id);
+            let header = CStr::from_bytes_with_nul(&header).expect("a header has one NUL");
+            if let Some(ours) = curl::copy_with(headers, header) {
+                if curl::set_headers(handle, ours) {
+                    transfer.span_id = Some(id);
+                    transfer.headers = ours;
+                } else {
+                    curl::free_all(ours);
+                }
+            }
+        }
+        transfer.start = now();
+        Some(transfer)
+    }
+
+    /// Puts the program's header list, `headers`, back on `handle`.
+    pub(crate) fn cancel(self, handle: Easy, headers: *mut Slist) {
+        if !self.headers.is_null() {
+            curl::set_headers(handle, headers);
+            curl::free_all(self.headers);
+        }
+    }
+
+    /// Records the transfer as finished now, with the code `code` if it is known, and puts
+    /// the program's header list, `headers`, back on `handle`.
+    pub(crate) fn end(self, handle: Easy, headers: *mut Slist, code: Option<c_int>) {
+        let _errno = Errno::keep();
+        let end = now();
+        let (start, traceparent, span_id) = (self.start, self.traceparent, self.span_id);
+        self.cancel(handle, headers);
+        let info = curl::info(handle);
+        let Some(full) = info
+            .url
+            .and_then(|u| u.to_str().ok())
+            .filter(|u| url::is_http(u))
+        else {
+            return;
+        };
+
+        let full_url = CString::new(url::redact(full).as_ref()).expect("a URL has no NUL");
+        let server =
+            url::server(full).and_then(|(host, port)| Some((CString::new(host).ok()?, port)));
+        let status = CString::new(info.status.to_string()).expect("a number has no NUL");
+        let mut attributes = vec![Attribute::string(c"url.full", &full_url)];
+        attributes.extend(
+            info.method
+                .map(|m| Attribute::string(c"http.request.method", m)),
+        );
+        if let Some((host, port)) = &server {
+            attributes.push(Attribute::string(c"server.address", host));
+            attributes.push(Attribute::int(c"server.port", (*port).into()));
+        }
+        attributes.extend(
+            info.peer
+                .map(|p| Attribute::string(c"network.peer.address", p)),
+        );
+        if info.peer_port > 0 {
+            attributes.push(Attribute::int(c"network.peer.port", info.peer_port));
+        }
+        if info.status > 0 {
+            attributes.push(Attribute::int(c"http.response.status_code", info.status));
+            attributes.push(Attribute::int(c"http.response.body.size", info.received));
+        }
+        match code {
+            Some(code) if code != curl::CURLE_OK => {
+                attributes.push(Attribute::int(c"otel_posix.curl.code", code.into()));
+                attributes
+                    .extend(curl::strerror(code).map(|e| Attribute::string(c"error.type", e)));
+            }
+            _ if info.status >= 400 => attributes.push(Attribute::string(c"error.type", &status)),
+            _ => {}
+        }
+
+        let name = info.method.unwrap_or(c"HTTP");
+        let span = Span::new(traceparent.as_cstr(), name, start, end).kind(Kind::Client);
+        match &span_id {
+            Some(id) => span.id(CStr::from_bytes_with_nul(id).expect("a span id has one NUL")),
+            None => span,
+        }
+        .record(&attributes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_header_names_the_new_span() {
+        let parent =
+            Traceparent::new(b"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        let id = span_id().unwrap();
+        assert!(id[..16].iter().all(u8::is_ascii_hexdigit));
+        assert_eq!(id[16], 0);
+        assert_ne!(span_id(), Some(id));
+
+        let header = header(&parent, b"b7ad6b7169203331\0");
+        assert_eq!(
+            CStr::from_bytes_with_nul(&header).unwrap(),
+            c"traceparent: 00-4bf92f3577b34da6a3ce929d0e0e4736-b7ad6b7169203331-01"
+        );
+    }
+}
@@ -0,0 +1,73 @@
+/* Fetches the URL it is given, with curl_easy_perform or, given "multi", through a
+ * multi handle, sending a header of its own and any others it is given, and prints the
+ * response's status. Declares the little of libcurl it uses itself, since curl's headers
+ * may not be installed. */
+#include <stdio.h>
+#include <string.h>
+
+#include "preload_testkit.h"
+
+typedef void CURL;
+typedef void CURLM;
+struct curl_slist;
+
+#define CURLOPT_URL 10002
+#define CURLOPT_HTTPHEADER 10023
+#define CURLINFO_RESPONSE_CODE (0x200000 + 2)
+
+CURL *curl_easy_init(void);
+int curl_easy_setopt(CURL *curl, int option, ...);
+int curl_easy_perform(CURL *curl);
+int curl_easy_getinfo(CURL *curl, int info, ...);
+void curl_easy_cleanup(CURL *curl);
+struct curl_slist *curl_slist_append(struct curl_slist *list, const char *header);
+void curl_slist_free_all(struct curl_slist *list);
+CURLM *curl_multi_init(void);
+int curl_multi_add_handle(CURLM *multi, CURL *curl);
+int curl_multi_perform(CURLM *multi, int *running);
+int curl_multi_wait(CURLM *multi, void *fds, unsigned count, int timeout_ms, int *ready);
+void *curl_multi_info_read(CURLM *multi, int *queued);
+int curl_multi_remove_handle(CURLM *multi, CURL *curl);
+int curl_multi_cleanup(CURLM *multi);
+
+/* Runs the transfer through a multi handle, as an event loop would; returns 0 once it
+ * is done. */
+static int perform_multi(CURL *curl) {
+    CURLM *multi = curl_multi_init();
+    if (curl_multi_add_handle(multi, curl) != 0)
+        return -1;
+    int running = 1;
+    while (running > 0) {
+        if (curl_multi_perform(multi, &running) != 0)
+            return -1;
+        int ready = 0;
+        curl_multi_wait(multi, NULL, 0, 100, &ready);
+    }
+    int queued = 0;
+    int done = curl_multi_info_read(multi, &queued) != NULL;
+    curl_multi_remove_handle(multi, curl);
+    curl_multi_cleanup(multi);
+    return done ? 0 : -1;
+}
+
+int main(int argc, char **argv) {
+    if (argc < 3 || testkit_start() != 0)
+        return 1;
+    int multi = strcmp(argv[1], "multi") == 0;
+    CURL *curl = curl_easy_init();
+    struct curl_slist *headers = curl_slist_append(NULL, "X-Fixture: fetch");
+    for (int i = 3; i < argc; i++)
+        headers = curl_slist_append(headers, argv[i]);
+    long status = 0;
+    curl_easy_setopt(curl, CURLOPT_URL, argv[2]);
+    curl_easy_setopt(curl, CURLOPT_HTTPHEADER, headers);
+    int code = multi ? perform_multi(curl) : curl_easy_perform(curl);
+    if (code != 0 || curl_easy_getinfo(curl, CURLINFO_RESPONSE_CODE, &status) != 0) {
+        fprintf(stderr, "fetch: curl code %d\n", code);
+        return 1;
+    }
+    curl_easy_cleanup(curl);
+    curl_slist_free_all(headers);
+    printf("\nstatus %ld\n", status);
+    return 0;
+}
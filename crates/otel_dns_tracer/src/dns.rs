@@ -0,0 +1,141 @@
+// src/dns.rs
+//
+// The interposed resolver functions. Each forwards to the real one and
+// records the lookup once it returns, counting the addresses in the
+// result.
+//
+// They report failure differently. getaddrinfo returns an EAI_* code, and
+// EAI_SYSTEM means errno says the rest. gethostbyname_r returns 0 with no
+// result when the name wasn't found, the reason left in *h_errnop, and an
+// errno when it couldn't look at all: ERANGE asks for a larger buffer,
+// which callers retry with, so it is recorded too.
+
+use crate::record::Lookup;
+use libc::{addrinfo, c_char, c_int, hostent, size_t};
+use std::ffi::CStr;
+
+pub(crate) type GetaddrinfoFn = unsafe extern "C" fn(
+    *const c_char,
+    *const c_char,
+    *const addrinfo,
+    *mut *mut addrinfo,
+) -> c_int;
+pub(crate) type GethostbynameRFn = unsafe extern "C" fn(
+    *const c_char,
+    *mut hostent,
+    *mut c_char,
+    size_t,
+    *mut *mut hostent,
+    *mut c_int,
+) -> c_int;
+
+// netdb.h's, which the libc crate doesn't expose
+const TRY_AGAIN: c_int = 2;
+
+/// What `gethostbyname_r` gives back when the real one can't be found: a temporary
+/// failure, to be tried again.
+pub(crate) unsafe fn unavailable(result: *mut *mut hostent, h_errnop: *mut c_int) -> c_int {
+    unsafe {
+        *result = std::ptr::null_mut();
+        *h_errnop = TRY_AGAIN;
+    }
+    libc::EAGAIN
+}
+
+/// `s` as a string, unless it is null.
+unsafe fn string<'a>(s: *const c_char) -> Option<&'a CStr> {
+    (!s.is_null()).then(|| unsafe { CStr::from_ptr(s) })
+}
+
+/// The number of entries in the `addrinfo` list at `res`.
+unsafe fn addresses(mut res: *const addrinfo) -> usize {
+    let mut count = 0;
+    while !res.is_null() {
+        count += 1;
+        res = unsafe { (*res).ai_next };
+    }
+    count
+}
+
+/// The number of addresses in `host`.
+unsafe fn host_addresses(host: *const hostent) -> usize {
+    if host.is_null() || unsafe { (*host).h_addr_list }.is_null() {
+        return 0;
+    }
+    let list = unsafe { (*host).h_addr_list };
+    (0..)
+        .take_while(|&i| !unsafe { *list.add(i) }.is_null())
+        .count()
+}
+
+pub(crate) unsafe fn getaddrinfo(
+    real: GetaddrinfoFn,
+    node: *const c_char,
+    service: *const c_char,
+    hints: *const addrinfo,
+    res: *mut *mut addrinfo,
+) -> c_int {
+    let lookup = Lookup::start(c"getaddrinfo", unsafe { string(node) });
+    let code = unsafe { real(node, service, hints, res) };
+    if let Some(lookup) = lookup {
+        if code == 0 {
+            lookup.found(unsafe { addresses(*res) });
+        } else {
+            let errno = (code == libc::EAI_SYSTEM).then(|| unsafe { *libc::__errno_location() });
+            let reason = unsafe { string(libc::gai_strerror(code)) };
+            lookup.failed(code, errno, reason);
+        }
+    }
+    code
+}
+
+pub(crate) unsafe fn gethostbyname_r(
+    real: GethostbynameRFn,
+    name: *const c_char,
+    ret: *mut hostent,
+    buf: *mut c_char,
+    buflen: size_t,
+    result: *mut *mut hostent,
+    h_errnop: *mut c_int,
+) -> c_int {
+    let lookup = Lookup::start(c"gethostbyname_r", unsafe { string(name) });
+    let errno = unsafe { real(name, ret, buf, buflen, result, h_errnop) };
+    if let Some(lookup) = lookup {
+        let host = unsafe { *result };
+        if errno != 0 {
+            let reason = unsafe { string(libc::strerror(errno)) };
+            lookup.failed(unsafe { *h_errnop }, Some(errno), reason);
+        } else if host.is_null() {
+            let code = unsafe { *h_errnop };
+            lookup.failed(code, None, unsafe { string(libc::hstrerror(code)) });
+        } else {
+            lookup.found(unsafe { host_addresses(host) });
+        }
+    }
+    errno
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_are_counted() {
+        let mut second: addrinfo = unsafe { std::mem::zeroed() };
+        let mut first: addrinfo = unsafe { std::mem::zeroed() };
+        first.ai_next = &mut second;
+        assert_eq!(unsafe { addresses(&first) }, 2);
+        assert_eq!(unsafe { addresses(std::ptr::null()) }, 0);
+
+        let (a, b) = ([127u8, 0, 0, 1], [10u8, 0, 0, 1]);
+        let mut list = [
+            a.as_ptr().cast_mut().cast(),
+            b.as_ptr().cast_mut().cast(),
+            std::ptr::null_mut(),
+        ];
+        let mut host: hostent = unsafe { std::mem::zeroed() };
+        assert_eq!(unsafe { host_addresses(&host) }, 0);
+        host.h_addr_list = list.as_mut_ptr();
+        assert_eq!(unsafe { host_addresses(&host) }, 2);
+    }
+}
@@ -0,0 +1,41 @@
+// src/lib.rs
+//
+// An LD_PRELOAD tracer that puts name resolution on the trace that waited
+// for it. A resolver timing out, or a search list tried entry by entry,
+// stalls a request for seconds, and nothing in its trace says why.
+// getaddrinfo and gethostbyname_r are interposed, and a lookup made by a
+// thread serving a trace is recorded as a span beneath the thread's span,
+// with the name asked for, how many addresses came back, and the error
+// when it failed (dns.rs, record.rs).
+//
+// gethostbyname, gethostbyname2_r and getaddrinfo_a aren't interposed, and
+// glibc's gethostbyname doesn't go through the exported gethostbyname_r,
+// so lookups made with them aren't recorded; nor are the ones made on
+// threads serving no trace or by the propagator while it records.
+
+mod dns;
+mod record;
+
+use libc::{addrinfo, c_char, c_int, hostent, size_t};
+
+ld_interpose::interpose! {
+    dns {
+        /// Interposed `getaddrinfo` that records the lookup.
+        fn getaddrinfo(
+            node: *const c_char,
+            service: *const c_char,
+            hints: *const addrinfo,
+            res: *mut *mut addrinfo,
+        ) -> c_int, else libc::EAI_AGAIN;
+
+        /// Interposed `gethostbyname_r` that records the lookup.
+        fn gethostbyname_r(
+            name: *const c_char,
+            ret: *mut hostent,
+            buf: *mut c_char,
+            buflen: size_t,
+            result: *mut *mut hostent,
+            h_errnop: *mut c_int,
+        ) -> c_int, else unsafe { dns::unavailable(result, h_errnop) };
+    }
+}
@@ -0,0 +1,66 @@
+// src/record.rs
+//
+// Recording a lookup: timed from the call to its return, and recorded as a
+// span named for the function under the span of the trace the thread is
+// serving. Lookups on threads serving none aren't timed, and neither are
+// the ones the propagator makes while it records (an exporter connecting
+// to its collector). errno is kept for the caller, who may be about to
+// read it after EAI_SYSTEM.
+
+use libc::c_int;
+use otel_posix_propagator_sys::{
+    self as propagator, Attribute, Busy, Errno, Span, Traceparent, now,
+};
+use std::ffi::CStr;
+
+/// A lookup that is being recorded.
+pub(crate) struct Lookup<'a> {
+    name: &'static CStr,
+    host: Option<&'a CStr>,
+    traceparent: Traceparent,
+    start: u64,
+    _busy: Busy,
+}
+
+impl<'a> Lookup<'a> {
+    /// Starts timing the lookup of `host` by the function `name`, if the thread is serving
+    /// a trace and isn't recording one already.
+    pub(crate) fn start(name: &'static CStr, host: Option<&'a CStr>) -> Option<Self> {
+        let busy = propagator::enter()?;
+        Some(Lookup {
+            name,
+            host,
+            traceparent: propagator::traceparent()?,
+            start: now(),
+            _busy: busy,
+        })
+    }
+
+    /// Records the lookup as having found `count` addresses.
+    pub(crate) fn found(self, count: usize) {
+        let count = Attribute::int(c"otel_posix.dns.results", count as i64);
+        self.record(&mut vec![count]);
+    }
+
+    /// Records the lookup as having failed with the resolver's `code`, and `errno` when
+    /// that's where the cause is, described by `reason`.
+    pub(crate) fn failed(self, code: c_int, errno: Option<c_int>, reason: Option<&CStr>) {
+        let mut attributes = vec![
+            Attribute::int(c"otel_posix.dns.results", 0),
+            Attribute::int(c"otel_posix.dns.error", code.into()),
+        ];
+        attributes.extend(errno.map(|errno| Attribute::int(c"otel_posix.dns.errno", errno.into())));
+        attributes.extend(reason.map(|reason| Attribute::string(c"error.type", reason)));
+        self.record(&mut attributes);
+    }
+
+    fn record<'b>(&'b self, attributes: &mut Vec<Attribute<'b>>) {
+        let _errno = Errno::keep();
+        let end = now();
+        attributes.extend(
+            self.host
+                .map(|host| Attribute::string(c"dns.question.name", host)),
+        );
+        Span::new(self.traceparent.as_cstr(), self.name, self.start, end).record(attributes);
+    }
+}
@@ -0,0 +1,37 @@
+/* Looks localhost up with getaddrinfo and gethostbyname_r, the second time with a
+ * buffer too small first, and fails to look up a name with AI_NUMERICHOST. */
+#include <errno.h>
+#include <netdb.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "preload_testkit.h"
+
+int main(void) {
+    if (testkit_start() != 0)
+        return 1;
+    /* in /etc/hosts, so it needs no network */
+    struct addrinfo *res;
+    if (getaddrinfo("localhost", "80", NULL, &res) != 0) {
+        fputs("getaddrinfo localhost failed\n", stderr);
+        return 1;
+    }
+    freeaddrinfo(res);
+    /* not a number, and not allowed to be looked up */
+    struct addrinfo hints;
+    memset(&hints, 0, sizeof hints);
+    hints.ai_flags = AI_NUMERICHOST;
+    if (getaddrinfo("db.internal", NULL, &hints, &res) != EAI_NONAME) {
+        fputs("getaddrinfo db.internal didn't fail\n", stderr);
+        return 1;
+    }
+    struct hostent host, *result;
+    char small[1], buf[4096];
+    int h_errno_;
+    if (gethostbyname_r("localhost", &host, small, sizeof small, &result, &h_errno_) != ERANGE ||
+        gethostbyname_r("localhost", &host, buf, sizeof buf, &result, &h_errno_) != 0) {
+        fputs("gethostbyname_r localhost failed\n", stderr);
+        return 1;
+    }
+    return 0;
+}
@@ -0,0 +1,52 @@
+#![cfg(target_os = "linux")]
+
+use preload_testkit::{Fixture, Run, SPAN, TRACE};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_are_recorded() {
+        let program = Fixture::c("tests/fixtures/resolve.c").compile();
+        let out = Run::traced(&program, "otel_dns_tracer").output().success();
+
+        let spans = out.spans();
+        let lookups: Vec<_> = spans.children_of(TRACE, SPAN).collect();
+        let lookup = |name: &str, host: &str| {
+            let mut found = lookups
+                .iter()
+                .filter(|s| s.name == name && s.str("dns.question.name") == Some(host));
+            found
+                .next()
+                .unwrap_or_else(|| panic!("no {name} of {host} in\n{spans}"))
+        };
+        let found = lookup("getaddrinfo", "localhost");
+        assert!(
+            found.int("otel_posix.dns.results").is_some_and(|n| n >= 1),
+            "{found}"
+        );
+        assert_eq!(found.int("otel_posix.dns.error"), None);
+
+        let failed = lookup("getaddrinfo", "db.internal");
+        assert_eq!(
+            failed.int("otel_posix.dns.error"),
+            Some(libc::EAI_NONAME.into())
+        );
+        assert!(failed.str("error.type").is_some(), "{failed}");
+
+        let [small, found] = &lookups
+            .iter()
+            .filter(|s| s.name == "gethostbyname_r")
+            .collect::<Vec<_>>()[..]
+        else {
+            panic!("two gethostbyname_r lookups in\n{spans}");
+        };
+        assert_eq!(small.int("otel_posix.dns.errno"), Some(libc::ERANGE.into()));
+        assert_eq!(small.str("dns.question.name"), Some("localhost"));
+        assert!(
+            found.int("otel_posix.dns.results").is_some_and(|n| n >= 1),
+            "{found}"
+        );
+    }
+}
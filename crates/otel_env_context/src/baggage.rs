@@ -0,0 +1,80 @@
+// src/baggage.rs
+//
+// W3C baggage: a context's baggage to and from a `baggage` string. Values
+// are percent-encoded; an entry's properties (what follows its first `;`)
+// are kept as its metadata.
+
+use opentelemetry::Context;
+use opentelemetry::baggage::{Baggage, BaggageExt, KeyValueMetadata};
+
+/// The baggage of `cx` as a `baggage` value, or `None` if it has none.
+pub fn format_baggage(cx: &Context) -> Option<String> {
+    let baggage = cx.baggage();
+    (!baggage.is_empty()).then(|| baggage.to_string())
+}
+
+/// Parses a `baggage` value. Entries that don't parse, or that the baggage limits leave no
+/// room for, are skipped rather than failing the whole value, as the spec asks.
+pub fn parse_baggage(s: &str) -> Baggage {
+    s.split(',')
+        .filter_map(|member| {
+            let (pair, properties) = member.split_once(';').unwrap_or((member, ""));
+            let (key, value) = pair.split_once('=')?;
+            let key = key.trim();
+            if key.is_empty() {
+                return None;
+            }
+            Some(KeyValueMetadata::new(
+                key.to_string(),
+                percent_decode(value.trim())?,
+                properties.trim().to_string(),
+            ))
+        })
+        .collect()
+}
+
+/// `s` with its `%XX` escapes decoded; `None` if one is malformed or the result isn't
+/// UTF-8.
+pub(crate) fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::{KeyValue, StringValue};
+
+    #[test]
+    fn baggage_round_trip() {
+        let cx = Context::new().with_baggage([KeyValue::new("tenant", "a b,c=d")]);
+        let value = format_baggage(&cx).unwrap();
+        assert_eq!(value, "tenant=a%20b%2Cc%3Dd");
+        assert_eq!(
+            parse_baggage(&value).get("tenant"),
+            Some(&StringValue::from("a b,c=d"))
+        );
+        assert_eq!(format_baggage(&Context::new()), None);
+    }
+
+    #[test]
+    fn parse_skips_bad_entries() {
+        let baggage = parse_baggage(" region = eu ;ttl=30, broken, =x, bad=%zz,  user=bob ");
+        assert_eq!(baggage.len(), 2);
+        let (region, metadata) = baggage.get_with_metadata("region").unwrap();
+        assert_eq!((region.as_str(), metadata.as_str()), ("eu", "ttl=30"));
+        assert_eq!(baggage.get("user"), Some(&StringValue::from("bob")));
+    }
+}
@@ -0,0 +1,199 @@
+// src/lib.rs
+//
+// An OpenTelemetry Context to and from what carries it between processes:
+// the TRACEPARENT, TRACESTATE and BAGGAGE environment variables the W3C
+// environment-carrier convention uses, and a single command-line argument
+// for when the environment isn't passed on (sudo, ssh, a job scheduler).
+// The preload shim's exec/spawn injectors write the variables with it, and
+// an application reads back what the shim injected with `from_env` or
+// `from_process_env`, without pulling in the shim.
+//
+// The argument form is `traceparent=...,tracestate=...,baggage=...`, with
+// everything but letters, digits and `-._~` in the values percent-encoded,
+// so it needs no quoting in a shell and survives being split on spaces.
+
+#[cfg(all(feature = "otel-0_30", feature = "otel-0_31"))]
+compile_error!("features `otel-0_30` and `otel-0_31` are mutually exclusive");
+#[cfg(not(any(feature = "otel-0_30", feature = "otel-0_31")))]
+compile_error!("one of the features `otel-0_30` or `otel-0_31` is required");
+
+#[cfg(all(feature = "otel-0_31", not(feature = "otel-0_30")))]
+extern crate opentelemetry_0_31 as opentelemetry;
+
+mod baggage;
+mod w3c;
+
+pub use baggage::{format_baggage, parse_baggage};
+pub use w3c::{TRACEPARENT_LEN, extract, format_traceparent, inject, parse_traceparent};
+
+use baggage::percent_decode;
+use opentelemetry::Context;
+use opentelemetry::baggage::BaggageExt;
+use opentelemetry::trace::{SpanContext, TraceContextExt};
+
+/// Environment variable carrying the `traceparent`.
+pub const TRACEPARENT_VAR: &str = "TRACEPARENT";
+
+/// Environment variable carrying the `tracestate`.
+pub const TRACESTATE_VAR: &str = "TRACESTATE";
+
+/// Environment variable carrying the `baggage`.
+pub const BAGGAGE_VAR: &str = "BAGGAGE";
+
+/// [`inject`] in environment-variable form: `TRACEPARENT` and, if non-empty, `TRACESTATE`.
+pub fn trace_context_to_env(cx: &Context) -> Vec<(&'static str, String)> {
+    let Some((traceparent, tracestate)) = inject(cx) else {
+        return Vec::new();
+    };
+    let mut vars = vec![(TRACEPARENT_VAR, traceparent)];
+    vars.extend(tracestate.map(|state| (TRACESTATE_VAR, state)));
+    vars
+}
+
+/// [`format_baggage`] in environment-variable form.
+pub fn baggage_to_env(cx: &Context) -> Option<(&'static str, String)> {
+    format_baggage(cx).map(|baggage| (BAGGAGE_VAR, baggage))
+}
+
+/// Everything `cx` carries, as variables ready to add to a child's environment.
+pub fn to_env(cx: &Context) -> Vec<(&'static str, String)> {
+    let mut vars = trace_context_to_env(cx);
+    vars.extend(baggage_to_env(cx));
+    vars
+}
+
+/// Reads a span context back from environment variables, looked up with `var`
+/// (`|key| std::env::var(key).ok()` for the process environment).
+pub fn span_context_from_env(var: impl Fn(&str) -> Option<String>) -> Option<SpanContext> {
+    extract(&var(TRACEPARENT_VAR)?, var(TRACESTATE_VAR).as_deref())
+}
+
+/// Reads a Context back from environment variables, looked up with `var`: the span
+/// context as a remote parent, and the baggage. Whatever is missing or malformed is left
+/// out.
+pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Context {
+    let mut cx = Context::new();
+    if let Some(sc) = span_context_from_env(&var) {
+        cx = cx.with_remote_span_context(sc);
+    }
+    if let Some(baggage) = var(BAGGAGE_VAR) {
+        let baggage = parse_baggage(&baggage);
+        if !baggage.is_empty() {
+            cx = cx.with_baggage(baggage);
+        }
+    }
+    cx
+}
+
+/// [`from_env`] on this process's environment.
+pub fn from_process_env() -> Context {
+    from_env(|key| std::env::var(key).ok())
+}
+
+/// Everything `cx` carries as one command-line argument; `None` if it carries nothing.
+pub fn to_arg(cx: &Context) -> Option<String> {
+    let fields: Vec<String> = to_env(cx)
+        .into_iter()
+        .map(|(var, value)| format!("{}={}", var.to_ascii_lowercase(), percent_encode(&value)))
+        .collect();
+    (!fields.is_empty()).then(|| fields.join(","))
+}
+
+/// Reads a Context back from an argument [`to_arg`] made, or from a bare `traceparent`.
+/// Fields it doesn't know are ignored, and whatever is missing or malformed is left out.
+pub fn from_arg(arg: &str) -> Context {
+    let arg = arg.trim();
+    if !arg.contains('=') {
+        return from_env(|key| (key == TRACEPARENT_VAR).then(|| arg.to_string()));
+    }
+    from_env(|key| {
+        arg.split(',').find_map(|field| {
+            let (name, value) = field.split_once('=')?;
+            name.trim()
+                .eq_ignore_ascii_case(key)
+                .then(|| percent_decode(value.trim()))
+                .flatten()
+        })
+    })
+}
+
+/// `s` with everything but letters, digits and `-._~` percent-encoded.
+fn percent_encode(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::{KeyValue, StringValue};
+
+    const TP: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn context() -> Context {
+        let sc = extract(TP, Some("vendor=abc,other=1")).unwrap();
+        Context::new()
+            .with_remote_span_context(sc)
+            .with_baggage([KeyValue::new("tenant", "a b")])
+    }
+
+    fn lookup(vars: &[(&'static str, String)]) -> impl Fn(&str) -> Option<String> {
+        |key| vars.iter().find(|(k, _)| *k == key).map(|(_, v)| v.clone())
+    }
+
+    #[test]
+    fn env_round_trip() {
+        let vars = to_env(&context());
+        assert_eq!(
+            vars,
+            [
+                (TRACEPARENT_VAR, TP.to_string()),
+                (TRACESTATE_VAR, "vendor=abc,other=1".to_string()),
+                (BAGGAGE_VAR, "tenant=a%20b".to_string()),
+            ]
+        );
+        let cx = from_env(lookup(&vars));
+        assert_eq!(cx.span().span_context(), context().span().span_context());
+        assert_eq!(cx.baggage().get("tenant"), Some(&StringValue::from("a b")));
+
+        assert!(to_env(&Context::new()).is_empty());
+        let cx = from_env(|_| None);
+        assert!(!cx.has_active_span() && cx.baggage().is_empty());
+    }
+
+    #[test]
+    fn arg_round_trip() {
+        let arg = to_arg(&context()).unwrap();
+        assert_eq!(
+            arg,
+            format!(
+                "traceparent={TP},tracestate=vendor%3Dabc%2Cother%3D1,baggage=tenant%3Da%2520b"
+            )
+        );
+        assert!(
+            arg.bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"-._~=,%".contains(&b))
+        );
+        let cx = from_arg(&arg);
+        assert_eq!(cx.span().span_context(), context().span().span_context());
+        assert_eq!(cx.baggage().get("tenant"), Some(&StringValue::from("a b")));
+        assert_eq!(to_arg(&Context::new()), None);
+    }
+
+    #[test]
+    fn arg_accepts_a_bare_traceparent() {
+        let cx = from_arg(TP);
+        assert_eq!(format_traceparent(cx.span().span_context()), TP);
+        // unknown fields are skipped, and a malformed one costs only itself
+        let cx = from_arg(&format!("version=2,traceparent={TP},baggage=%zz"));
+        assert!(cx.has_active_span() && cx.baggage().is_empty());
+        assert!(!from_arg("garbage").has_active_span());
+    }
+}
@@ -0,0 +1,118 @@
+// src/w3c.rs
+//
+// W3C trace context: a span context to and from `traceparent` and
+// `tracestate` strings.
+
+use opentelemetry::Context;
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+use std::str::FromStr;
+
+/// Length of a version 00 `traceparent`: `"00-" + 32 + "-" + 16 + "-" + 2`.
+pub const TRACEPARENT_LEN: usize = 55;
+
+/// Formats `sc` as a version 00 `traceparent`.
+///
+/// ```
+/// use opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceId, TraceState};
+///
+/// let sc = SpanContext::new(
+///     TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+///     SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+///     TraceFlags::SAMPLED,
+///     true,
+///     TraceState::default(),
+/// );
+/// assert_eq!(
+///     otel_env_context::format_traceparent(&sc),
+///     "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+/// );
+/// ```
+pub fn format_traceparent(sc: &SpanContext) -> String {
+    format!(
+        "00-{}-{}-{:02x}",
+        sc.trace_id(),
+        sc.span_id(),
+        sc.trace_flags().to_u8()
+    )
+}
+
+fn is_lower_hex(s: &str, len: usize) -> bool {
+    s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Parses a `traceparent` into a remote span context with an empty trace state, or `None`
+/// if it is malformed or carries invalid ids.
+pub fn parse_traceparent(s: &str) -> Option<SpanContext> {
+    let mut parts = s.trim().split('-');
+    let (version, trace_id, span_id, flags) =
+        (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    // version 00 has exactly four fields; future versions may append more
+    if (version == "00" && parts.next().is_some()) || !is_lower_hex(version, 2) || version == "ff" {
+        return None;
+    }
+    if !is_lower_hex(trace_id, 32) || !is_lower_hex(span_id, 16) || !is_lower_hex(flags, 2) {
+        return None;
+    }
+    let sc = SpanContext::new(
+        TraceId::from_hex(trace_id).ok()?,
+        SpanId::from_hex(span_id).ok()?,
+        TraceFlags::new(u8::from_str_radix(flags, 16).ok()?),
+        true,
+        TraceState::default(),
+    );
+    sc.is_valid().then_some(sc)
+}
+
+/// Parses a `traceparent` and an optional `tracestate` into a remote span context. A
+/// malformed `tracestate` is dropped rather than failing the whole context, as the spec
+/// asks.
+pub fn extract(traceparent: &str, tracestate: Option<&str>) -> Option<SpanContext> {
+    let sc = parse_traceparent(traceparent)?;
+    let state = tracestate
+        .and_then(|s| TraceState::from_str(s.trim()).ok())
+        .unwrap_or_default();
+    Some(SpanContext::new(
+        sc.trace_id(),
+        sc.span_id(),
+        sc.trace_flags(),
+        true,
+        state,
+    ))
+}
+
+/// The `traceparent` and, if non-empty, `tracestate` of the span in `cx`; `None` without
+/// a valid span context.
+pub fn inject(cx: &Context) -> Option<(String, Option<String>)> {
+    let span = cx.span();
+    let sc = span.span_context();
+    if !sc.is_valid() {
+        return None;
+    }
+    let state = sc.trace_state().header();
+    Some((format_traceparent(sc), (!state.is_empty()).then_some(state)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TP: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn parse_rejects_malformed() {
+        assert!(parse_traceparent(TP).is_some());
+        assert!(
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7").is_none()
+        );
+        assert!(
+            parse_traceparent("ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").is_none()
+        );
+        assert!(
+            parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none()
+        );
+        assert!(
+            parse_traceparent("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01").is_none()
+        );
+        assert!(parse_traceparent("00-42-00f067aa0ba902b7-01").is_none());
+    }
+}
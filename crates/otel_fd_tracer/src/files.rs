@@ -0,0 +1,107 @@
+// src/files.rs
+//
+// The traced files' paths, by descriptor. A bit per descriptor says
+// whether there is one, so the reads and writes on everything else (most
+// of them, on sockets and pipes) are told to pass through without taking
+// the lock. Descriptors from 65536 up are never traced.
+//
+// A descriptor is forgotten when it is closed through close, or when open
+// hands its number out again; one closed some other way (dup2 over it,
+// close_range) keeps its path until then.
+
+use libc::c_int;
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::os::unix::ffi::OsStringExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+const MAX_FD: usize = 1 << 16;
+
+static TRACKED: [AtomicU64; MAX_FD / 64] = [const { AtomicU64::new(0) }; MAX_FD / 64];
+static PATHS: Mutex<BTreeMap<c_int, Arc<CStr>>> = Mutex::new(BTreeMap::new());
+
+fn lock() -> MutexGuard<'static, BTreeMap<c_int, Arc<CStr>>> {
+    PATHS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The word and bit saying whether `fd` is tracked, if it can be.
+fn bit(fd: c_int) -> Option<(&'static AtomicU64, u64)> {
+    let fd = usize::try_from(fd).ok().filter(|&fd| fd < MAX_FD)?;
+    Some((&TRACKED[fd / 64], 1 << (fd % 64)))
+}
+
+fn tracked(fd: c_int) -> bool {
+    bit(fd).is_some_and(|(word, bit)| word.load(Ordering::Acquire) & bit != 0)
+}
+
+/// Remembers that `fd` is the file at `path`.
+pub(crate) fn track(fd: c_int, path: Arc<CStr>) {
+    if let Some((word, bit)) = bit(fd) {
+        lock().insert(fd, path);
+        word.fetch_or(bit, Ordering::Release);
+    }
+}
+
+/// Forgets `fd`, returning its path if it was tracked.
+pub(crate) fn forget(fd: c_int) -> Option<Arc<CStr>> {
+    let (word, bit) = bit(fd)?;
+    if word.fetch_and(!bit, Ordering::AcqRel) & bit == 0 {
+        return None;
+    }
+    lock().remove(&fd)
+}
+
+/// The path of the file `fd` is, if it is tracked.
+pub(crate) fn path(fd: c_int) -> Option<Arc<CStr>> {
+    if !tracked(fd) {
+        return None;
+    }
+    lock().get(&fd).cloned()
+}
+
+/// `path`, opened relative to `dirfd`, as an absolute path: relative to the working
+/// directory for `AT_FDCWD`, or to the directory `dirfd` is when it is tracked. `None` for
+/// a relative path under a directory that isn't. `.` and `..` are left as they are.
+pub(crate) fn resolve(dirfd: c_int, path: &CStr) -> Option<Arc<CStr>> {
+    let bytes = path.to_bytes();
+    if bytes.starts_with(b"/") {
+        return Some(path.into());
+    }
+    let mut joined = if dirfd == libc::AT_FDCWD {
+        std::env::current_dir().ok()?.into_os_string().into_vec()
+    } else {
+        self::path(dirfd)?.to_bytes().to_vec()
+    };
+    if !joined.ends_with(b"/") {
+        joined.push(b'/');
+    }
+    joined.extend_from_slice(bytes);
+    CString::new(joined).ok().map(Arc::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_follow_the_descriptor() {
+        // a number no test opens
+        let fd = 60_000;
+        assert_eq!(path(fd), None);
+        track(fd, c"/srv/data".into());
+        assert_eq!(path(fd).as_deref(), Some(c"/srv/data"));
+        assert_eq!(
+            resolve(fd, c"index/0").as_deref(),
+            Some(c"/srv/data/index/0")
+        );
+        assert_eq!(resolve(fd, c"/etc/hosts").as_deref(), Some(c"/etc/hosts"));
+        assert_eq!(forget(fd).as_deref(), Some(c"/srv/data"));
+        assert_eq!(path(fd), None);
+        assert_eq!(resolve(fd, c"index/0"), None);
+        // out of range
+        track(MAX_FD as c_int, c"/srv/data".into());
+        assert_eq!(path(MAX_FD as c_int), None);
+        assert_eq!(forget(-1), None);
+    }
+}
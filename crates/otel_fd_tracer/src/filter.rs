@@ -0,0 +1,74 @@
+// src/filter.rs
+//
+// Which files are traced, by path: set from OTEL_FD_TRACER_PATHS and
+// OTEL_FD_TRACER_EXCLUDE_PATHS when the library is loaded, and checked once
+// per open, since a descriptor keeps the answer its path got.
+
+use std::sync::{PoisonError, RwLock};
+
+struct Filters {
+    include: Vec<Vec<u8>>,
+    exclude: Vec<Vec<u8>>,
+}
+
+static FILTERS: RwLock<Filters> = RwLock::new(Filters {
+    include: Vec::new(),
+    exclude: Vec::new(),
+});
+
+/// `prefixes` without the empty ones, or the trailing slashes that would keep them from
+/// matching the directory itself.
+fn prefixes(prefixes: &[&str]) -> Vec<Vec<u8>> {
+    prefixes
+        .iter()
+        .map(|p| p.trim())
+        .filter(|p| !p.is_empty())
+        .map(|p| match p.trim_end_matches('/') {
+            "" => b"/".to_vec(),
+            p => p.as_bytes().to_vec(),
+        })
+        .collect()
+}
+
+pub(crate) fn set(include: &[&str], exclude: &[&str]) {
+    *FILTERS.write().unwrap_or_else(PoisonError::into_inner) = Filters {
+        include: prefixes(include),
+        exclude: prefixes(exclude),
+    };
+}
+
+/// Whether `path` is `prefix` or a path beneath it.
+fn under(path: &[u8], prefix: &[u8]) -> bool {
+    prefix == b"/"
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest[0] == b'/')
+}
+
+/// Whether the file at `path` is traced.
+pub(crate) fn traced(path: &[u8]) -> bool {
+    let filters = FILTERS.read().unwrap_or_else(PoisonError::into_inner);
+    (filters.include.is_empty() || filters.include.iter().any(|p| under(path, p)))
+        && !filters.exclude.iter().any(|p| under(path, p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefixes_match_whole_components() {
+        let [lib] = &prefixes(&["/var/lib/"])[..] else {
+            panic!("one prefix");
+        };
+        assert!(under(b"/var/lib", lib));
+        assert!(under(b"/var/lib/db/wal", lib));
+        assert!(!under(b"/var/library", lib));
+        assert!(!under(b"/var", lib));
+        assert_eq!(
+            prefixes(&["", " / ", "/tmp"]),
+            [b"/".to_vec(), b"/tmp".to_vec()]
+        );
+        assert!(under(b"/anything", b"/"));
+    }
+}
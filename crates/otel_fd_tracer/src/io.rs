@@ -0,0 +1,130 @@
+// src/io.rs
+//
+// The interposed file functions. Each forwards to the real one, recording
+// the call when it is on a traced file (record.rs). The opens are where a
+// descriptor's path is learned, or its number forgotten when it now names
+// something untraced (files.rs).
+//
+// open and openat are variadic, taking the mode only with O_CREAT or
+// O_TMPFILE. They are exported with the mode as a plain parameter, which
+// on the ABIs this runs on is passed where a variadic one is; when the
+// caller left it out it holds garbage, which the real function, called as
+// variadic, ignores just as it would have.
+
+use crate::record::Call;
+use crate::{files, filter};
+use libc::{c_char, c_int, c_void, mode_t, size_t, ssize_t};
+use otel_posix_propagator_sys as propagator;
+use std::ffi::CStr;
+
+pub(crate) type OpenFn = unsafe extern "C" fn(*const c_char, c_int, ...) -> c_int;
+pub(crate) type OpenatFn = unsafe extern "C" fn(c_int, *const c_char, c_int, ...) -> c_int;
+pub(crate) type ReadFn = unsafe extern "C" fn(c_int, *mut c_void, size_t) -> ssize_t;
+pub(crate) type WriteFn = unsafe extern "C" fn(c_int, *const c_void, size_t) -> ssize_t;
+pub(crate) type FdFn = unsafe extern "C" fn(c_int) -> c_int;
+
+/// `openat` as a raw syscall, for calls made before the real functions are found.
+pub(crate) unsafe fn sys_openat(
+    dirfd: c_int,
+    path: *const c_char,
+    flags: c_int,
+    mode: mode_t,
+) -> c_int {
+    unsafe { libc::syscall(libc::SYS_openat, dirfd, path, flags, mode) as c_int }
+}
+
+pub(crate) unsafe fn open(real: OpenFn, path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    unsafe { opened(libc::AT_FDCWD, path, || real(path, flags, mode)) }
+}
+
+pub(crate) unsafe fn open64(
+    real: OpenFn,
+    path: *const c_char,
+    flags: c_int,
+    mode: mode_t,
+) -> c_int {
+    unsafe { opened(libc::AT_FDCWD, path, || real(path, flags, mode)) }
+}
+
+pub(crate) unsafe fn openat(
+    real: OpenatFn,
+    dirfd: c_int,
+    path: *const c_char,
+    flags: c_int,
+    mode: mode_t,
+) -> c_int {
+    unsafe { opened(dirfd, path, || real(dirfd, path, flags, mode)) }
+}
+
+pub(crate) unsafe fn openat64(
+    real: OpenatFn,
+    dirfd: c_int,
+    path: *const c_char,
+    flags: c_int,
+    mode: mode_t,
+) -> c_int {
+    unsafe { opened(dirfd, path, || real(dirfd, path, flags, mode)) }
+}
+
+/// Makes the open `open` of `path`, relative to `dirfd`, tracking the descriptor it
+/// returns and recording the call when the path is traced.
+unsafe fn opened(dirfd: c_int, path: *const c_char, open: impl FnOnce() -> c_int) -> c_int {
+    let Some(_busy) = propagator::enter().filter(|_| !path.is_null()) else {
+        let fd = open();
+        files::forget(fd);
+        return fd;
+    };
+    let path = files::resolve(dirfd, unsafe { CStr::from_ptr(path) })
+        .filter(|path| filter::traced(path.to_bytes()));
+    let call = path.as_ref().and_then(|_| Call::start(c"open"));
+    let fd = open();
+    match &path {
+        Some(path) if fd >= 0 => files::track(fd, path.clone()),
+        _ => drop(files::forget(fd)),
+    }
+    if let (Some(call), Some(path)) = (call, &path) {
+        call.finish(path, fd, fd.into(), false);
+    }
+    fd
+}
+
+/// Makes the call `f` on `fd`, recording it as `name` when `fd` is a traced file.
+fn on_file(name: &'static CStr, fd: c_int, transferred: bool, f: impl FnOnce() -> i64) -> i64 {
+    let Some(_busy) = propagator::enter() else {
+        return f();
+    };
+    let Some((path, call)) = files::path(fd).and_then(|path| Some((path, Call::start(name)?)))
+    else {
+        return f();
+    };
+    let result = f();
+    call.finish(&path, fd, result, transferred);
+    result
+}
+
+pub(crate) unsafe fn read(real: ReadFn, fd: c_int, buf: *mut c_void, count: size_t) -> ssize_t {
+    on_file(c"read", fd, true, || unsafe { real(fd, buf, count) as i64 }) as ssize_t
+}
+
+pub(crate) unsafe fn write(real: WriteFn, fd: c_int, buf: *const c_void, count: size_t) -> ssize_t {
+    on_file(c"write", fd, true, || unsafe {
+        real(fd, buf, count) as i64
+    }) as ssize_t
+}
+
+pub(crate) unsafe fn fsync(real: FdFn, fd: c_int) -> c_int {
+    on_file(c"fsync", fd, false, || unsafe { real(fd) }.into()) as c_int
+}
+
+pub(crate) unsafe fn close(real: FdFn, fd: c_int) -> c_int {
+    // forgotten first, so a number the kernel hands out again is never taken for this file
+    let Some(path) = files::forget(fd) else {
+        return unsafe { real(fd) };
+    };
+    let call = propagator::enter().and_then(|busy| Some((busy, Call::start(c"close")?)));
+    let result = unsafe { real(fd) };
+    if let Some((_busy, call)) = call {
+        call.finish(&path, fd, result.into(), false);
+    }
+    result
+}
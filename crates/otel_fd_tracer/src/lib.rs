@@ -0,0 +1,150 @@
+// src/lib.rs
+//
+// An LD_PRELOAD tracer that puts file I/O on the trace that did it, for
+// services that can't be changed to record it themselves. open, openat,
+// read, write, close and fsync are interposed, and a call on a file whose
+// path passes the filters (filter.rs), made by a thread serving a trace, is
+// recorded with the path, the bytes it moved and how long it took: as a
+// span beneath the thread's span, or with OTEL_FD_TRACER_MODE=events, as an
+// event on it (record.rs). OTEL_FD_TRACER_SAMPLE_RATIO of the calls are
+// recorded, all of them by default, so a service reading in 4 KiB chunks
+// can be traced without a span per chunk.
+//
+// A descriptor's path is only known when it was opened after the library
+// was loaded, so the other calls are only recorded on those (files.rs);
+// sockets, pipes, the standard streams and inherited descriptors are passed
+// straight through, as are the files the propagator writes while it
+// records.
+
+mod files;
+mod filter;
+mod io;
+mod record;
+
+use libc::{c_char, c_int, c_void, mode_t, size_t, ssize_t};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+// what isn't a file, or is one the kernel makes up on every read
+const DEFAULT_EXCLUDE: &str = "/proc:/sys:/dev";
+
+static EVENTS: AtomicBool = AtomicBool::new(false);
+static SAMPLE_RATIO: AtomicU64 = AtomicU64::new(1f64.to_bits());
+
+/// How a call is recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// A span beneath the thread's span, named for the call.
+    Spans,
+    /// An event on the thread's span, named for the call, with its duration.
+    Events,
+}
+
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+#[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__mod_init_func"))]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(configure);
+}
+
+fn configure() {
+    let var = |name| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+    match var("OTEL_FD_TRACER_MODE").as_deref().map(str::trim) {
+        None | Some("spans") => {}
+        Some("events") => set_mode(Mode::Events),
+        Some(other) => {
+            eprintln!("otel_fd_tracer: invalid OTEL_FD_TRACER_MODE {other:?}, using spans")
+        }
+    }
+    if let Some(value) = var("OTEL_FD_TRACER_SAMPLE_RATIO") {
+        match value.trim().parse::<f64>() {
+            Ok(ratio) if (0.0..=1.0).contains(&ratio) => set_sample_ratio(ratio),
+            _ => eprintln!(
+                "otel_fd_tracer: invalid OTEL_FD_TRACER_SAMPLE_RATIO {value:?}, recording every call"
+            ),
+        }
+    }
+    let include = var("OTEL_FD_TRACER_PATHS").unwrap_or_default();
+    let exclude = var("OTEL_FD_TRACER_EXCLUDE_PATHS").unwrap_or_else(|| DEFAULT_EXCLUDE.to_owned());
+    set_paths(
+        &include.split(':').collect::<Vec<_>>(),
+        &exclude.split(':').collect::<Vec<_>>(),
+    );
+}
+
+/// Sets how calls are recorded.
+pub fn set_mode(mode: Mode) {
+    EVENTS.store(mode == Mode::Events, Ordering::Relaxed);
+}
+
+fn mode() -> Mode {
+    if EVENTS.load(Ordering::Relaxed) {
+        Mode::Events
+    } else {
+        Mode::Spans
+    }
+}
+
+/// Sets the share of calls recorded, from 0 for none to 1 for all; values outside that
+/// are clamped.
+pub fn set_sample_ratio(ratio: f64) {
+    let ratio = if ratio.is_nan() {
+        0.0
+    } else {
+        ratio.clamp(0.0, 1.0)
+    };
+    SAMPLE_RATIO.store(ratio.to_bits(), Ordering::Relaxed);
+}
+
+fn sample_ratio() -> f64 {
+    f64::from_bits(SAMPLE_RATIO.load(Ordering::Relaxed))
+}
+
+/// Sets the paths whose files are traced: those under one of `include`, or anywhere when
+/// it is empty, and under none of `exclude`. Prefixes match whole path components, so
+/// `/var/lib` covers `/var/lib/db` but not `/var/library`. Only files opened afterwards
+/// are affected.
+pub fn set_paths(include: &[&str], exclude: &[&str]) {
+    filter::set(include, exclude);
+}
+
+ld_interpose::interpose! {
+    io {
+        /// Interposed `open` that records the opening of a traced file.
+        fn open(path: *const c_char, flags: c_int, mode: mode_t) -> c_int,
+            else unsafe { io::sys_openat(libc::AT_FDCWD, path, flags, mode) };
+
+        /// Interposed `open64` that records the opening of a traced file.
+        fn open64(path: *const c_char, flags: c_int, mode: mode_t) -> c_int,
+            else unsafe { io::sys_openat(libc::AT_FDCWD, path, flags, mode) };
+
+        /// Interposed `openat` that records the opening of a traced file.
+        fn openat(dirfd: c_int, path: *const c_char, flags: c_int, mode: mode_t) -> c_int,
+            else unsafe { io::sys_openat(dirfd, path, flags, mode) };
+
+        /// Interposed `openat64` that records the opening of a traced file.
+        fn openat64(dirfd: c_int, path: *const c_char, flags: c_int, mode: mode_t) -> c_int,
+            else unsafe { io::sys_openat(dirfd, path, flags, mode) };
+
+        /// Interposed `read` that records reads from a traced file.
+        fn read(fd: c_int, buf: *mut c_void, count: size_t) -> ssize_t,
+            else unsafe { libc::syscall(libc::SYS_read, fd, buf, count) as ssize_t };
+
+        /// Interposed `write` that records writes to a traced file.
+        fn write(fd: c_int, buf: *const c_void, count: size_t) -> ssize_t,
+            else unsafe { libc::syscall(libc::SYS_write, fd, buf, count) as ssize_t };
+
+        /// Interposed `close` that records the closing of a traced file.
+        fn close(fd: c_int) -> c_int,
+            else unsafe { libc::syscall(libc::SYS_close, fd) as c_int };
+
+        /// Interposed `fsync` that records flushes of a traced file.
+        fn fsync(fd: c_int) -> c_int,
+            else unsafe { libc::syscall(libc::SYS_fsync, fd) as c_int };
+    }
+}
@@ -0,0 +1,83 @@
+// src/record.rs
+//
+// Recording a call. Whether one is recorded is settled before it is made:
+// it has to be sampled, and its thread serving a trace, so the calls that
+// aren't pay for neither clock. The call is then timed, and recorded once
+// it returns, as a span under the thread's span or an event on it.
+
+use crate::Mode;
+use libc::c_int;
+use otel_posix_propagator_sys::{self as propagator, Attribute, Errno, Span, Traceparent, now};
+use std::cell::Cell;
+use std::ffi::CStr;
+
+thread_local! {
+    static RNG: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Whether to record the next call, with the configured ratio.
+fn sampled() -> bool {
+    let ratio = crate::sample_ratio();
+    if ratio >= 1.0 {
+        return true;
+    }
+    // xorshift64*, seeded from the thread-local's address so threads differ
+    let mut x = RNG.get();
+    if x == 0 {
+        x = RNG.with(|rng| rng as *const Cell<u64> as u64) | 1;
+    }
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    RNG.set(x);
+    // uniform in [0, 1)
+    ((x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64) < ratio
+}
+
+/// A call that is being recorded.
+pub(crate) struct Call {
+    name: &'static CStr,
+    traceparent: Traceparent,
+    start: u64,
+}
+
+impl Call {
+    /// Starts timing the call `name`, if it is to be recorded.
+    pub(crate) fn start(name: &'static CStr) -> Option<Call> {
+        if !sampled() {
+            return None;
+        }
+        Some(Call {
+            name,
+            traceparent: propagator::traceparent()?,
+            start: now(),
+        })
+    }
+
+    /// Records the call, made on `fd`, the file at `path`, now that it has returned
+    /// `result`; for a read or write, `transferred` says the result is a byte count.
+    pub(crate) fn finish(self, path: &CStr, fd: c_int, result: i64, transferred: bool) {
+        let errno = Errno::keep();
+        let end = now();
+        let mut attributes = vec![
+            Attribute::string(c"file.path", path),
+            Attribute::int(c"otel_posix.fd.fd", fd.into()),
+        ];
+        if result < 0 {
+            attributes.push(Attribute::int(c"otel_posix.fd.errno", errno.get().into()));
+        } else if transferred {
+            attributes.push(Attribute::int(c"otel_posix.fd.bytes", result));
+        }
+        match crate::mode() {
+            Mode::Spans => {
+                Span::new(self.traceparent.as_cstr(), self.name, self.start, end)
+                    .record(&attributes);
+            }
+            Mode::Events => {
+                let duration = end.saturating_sub(self.start) as f64 / 1e9;
+                attributes.push(Attribute::double(c"otel_posix.fd.duration", duration));
+                propagator::add_event(self.name, self.start, &attributes);
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+/* Writes "hello" to the file named by its argument, with open/write/fsync/close, and
+ * reads it back, on a thread of its own so its calls are on that thread's span in
+ * lifetime mode. */
+#include <fcntl.h>
+#include <pthread.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+#include "preload_testkit.h"
+
+static void *write_file(void *path) {
+    int fd = open(path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+    if (fd < 0 || write(fd, "hello", 5) != 5 || fsync(fd) != 0 || close(fd) != 0)
+        return "write";
+    char read_back[8] = "";
+    fd = open(path, O_RDONLY);
+    if (fd < 0 || read(fd, read_back, sizeof read_back) != 5 || close(fd) != 0 ||
+        memcmp(read_back, "hello", 5) != 0)
+        return "read";
+    return NULL;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 2 || testkit_start() != 0)
+        return 1;
+    pthread_t thread;
+    void *failed;
+    if (pthread_create(&thread, NULL, write_file, argv[1]) != 0 ||
+        pthread_join(thread, &failed) != 0)
+        return 1;
+    if (failed != NULL) {
+        perror((const char *)failed);
+        return 1;
+    }
+    return 0;
+}
@@ -0,0 +1,76 @@
+#![cfg(target_os = "linux")]
+
+use preload_testkit::{Fixture, Output, Run, SPAN, TRACE, scratch_dir};
+use std::path::Path;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the fixture on `file`, preloaded with the propagator and the tracer, with
+    /// `env` set.
+    fn write_file(file: &Path, env: &[(&str, &str)]) -> Output {
+        let program = Fixture::c("tests/fixtures/write_file.c").compile();
+        let mut run = Run::traced(&program, "otel_fd_tracer").arg(file);
+        for (key, value) in env {
+            run = run.env(key, value);
+        }
+        let out = run.output().success();
+        assert_eq!(std::fs::read_to_string(file).unwrap(), "hello");
+        out
+    }
+
+    #[test]
+    fn a_preloaded_program_is_traced() {
+        let file = scratch_dir().join("fd-tracer-preload.txt");
+        let spans = write_file(&file, &[]).spans();
+        let path = file.to_str().unwrap();
+        for name in ["open", "write", "fsync", "close", "read"] {
+            let span = spans.child(TRACE, SPAN, name);
+            assert_eq!(span.str("file.path"), Some(path), "{span}");
+        }
+        for name in ["write", "read"] {
+            let span = spans.child(TRACE, SPAN, name);
+            assert_eq!(span.int("otel_posix.fd.bytes"), Some(5), "{span}");
+        }
+    }
+
+    #[test]
+    fn excluded_and_unsampled_calls_are_not_recorded() {
+        let dir = scratch_dir().join("fd-tracer-excluded");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("data");
+        let excluded = [("OTEL_FD_TRACER_EXCLUDE_PATHS", dir.to_str().unwrap())];
+        let unsampled = [("OTEL_FD_TRACER_SAMPLE_RATIO", "0")];
+        for env in [&excluded[..], &unsampled[..]] {
+            let spans = write_file(&file, env).spans();
+            assert_eq!(
+                spans.children_of(TRACE, SPAN).count(),
+                0,
+                "{env:?}:\n{spans}"
+            );
+        }
+    }
+
+    #[test]
+    fn events_go_on_the_current_span() {
+        let file = scratch_dir().join("fd-tracer-events.txt");
+        let env = [
+            ("OTEL_FD_TRACER_MODE", "events"),
+            // a span for the worker thread, for the events to land on
+            ("OTEL_POSIX_PROP_MODE", "lifetime"),
+        ];
+        let spans = write_file(&file, &env).spans();
+        let [thread] = &spans.children_of(TRACE, SPAN).collect::<Vec<_>>()[..] else {
+            panic!("one span under the context:\n{spans}");
+        };
+        assert_eq!(thread.name, "thread");
+        let read = thread.events_named("read").next().unwrap();
+        assert_eq!(read.int("otel_posix.fd.bytes"), Some(5), "{thread}");
+        assert!(
+            read.float("otel_posix.fd.duration")
+                .is_some_and(|d| d >= 0.0)
+        );
+        assert!(thread.events_named("fsync").next().is_some(), "{thread}");
+    }
+}
@@ -0,0 +1,29 @@
+// src/fd.rs
+//
+// The interposed write, for programs (and runtimes, Rust's eprintln among
+// them) that write to file descriptor 2 directly. Any other descriptor is
+// passed straight through. Only what was actually written is recorded: a
+// short write's rest comes in the program's next call.
+//
+// glibc's stdio writes its buffers out with a write of its own, not this
+// one, so a line printed through stdio isn't seen twice.
+
+use crate::record;
+use libc::{STDERR_FILENO, c_int, c_void, size_t, ssize_t};
+use otel_posix_propagator_sys as propagator;
+
+pub(crate) type WriteFn = unsafe extern "C" fn(c_int, *const c_void, size_t) -> ssize_t;
+
+pub(crate) unsafe fn write(real: WriteFn, fd: c_int, buf: *const c_void, count: size_t) -> ssize_t {
+    if fd != STDERR_FILENO {
+        return unsafe { real(fd, buf, count) };
+    }
+    let Some(_busy) = propagator::enter() else {
+        return unsafe { real(fd, buf, count) };
+    };
+    let written = unsafe { real(fd, buf, count) };
+    if written > 0 {
+        record::written(unsafe { std::slice::from_raw_parts(buf.cast(), written as usize) });
+    }
+    written
+}
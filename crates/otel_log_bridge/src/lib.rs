@@ -0,0 +1,48 @@
+// src/lib.rs
+//
+// An LD_PRELOAD bridge from stderr to OTel logs, for the legacy programs
+// whose only logging is what they print there. Each line a program writes
+// to stderr, with fprintf, fputs or write, is forwarded as a log record
+// carrying the trace and span the writing thread is serving, so a line in a
+// backend leads to its trace without the program knowing of either. The
+// output itself is left as it was.
+//
+// fprintf is variadic, so it is a naked function that hands its arguments
+// on as a va_list (stdio.rs); the other functions are interposed as usual.
+// What a thread writes is put together into lines (lines.rs) and each one
+// emitted as a record (record.rs).
+//
+// The propagator exports the records once OTEL_LOGS_EXPORTER=otlp asks it
+// to. glibc's own writers (perror, err, warn, putc, fwrite) don't go
+// through the interposed functions, so what they write isn't recorded.
+
+mod fd;
+mod lines;
+mod record;
+mod stdio;
+
+use libc::{FILE, c_char, c_int, c_void, size_t, ssize_t};
+use stdio::VaList;
+
+ld_interpose::interpose! {
+    stdio {
+        /// Interposed `fputs` that forwards what it writes to stderr.
+        fn fputs(s: *const c_char, stream: *mut FILE) -> c_int,
+            else libc::EOF;
+
+        /// Interposed `vfprintf` that forwards what it writes to stderr.
+        fn vfprintf(stream: *mut FILE, format: *const c_char, ap: VaList) -> c_int,
+            else -1;
+    }
+    #[cfg(target_env = "gnu")]
+    stdio {
+        /// Interposed `__vfprintf_chk`, the `_FORTIFY_SOURCE` variant of `vfprintf`.
+        fn __vfprintf_chk(stream: *mut FILE, flag: c_int, format: *const c_char, ap: VaList) -> c_int,
+            else -1;
+    }
+    fd {
+        /// Interposed `write` that forwards what it writes to file descriptor 2.
+        fn write(fd: c_int, buf: *const c_void, count: size_t) -> ssize_t,
+            else -1;
+    }
+}
@@ -0,0 +1,96 @@
+// src/lines.rs
+//
+// What a thread writes to stderr, put together into lines. A program as
+// often writes a line in pieces (a prefix, the message, the newline) as
+// whole, so what comes before a newline waits in a per-thread buffer for
+// the rest. A record is one line, without its line ending; blank lines
+// aren't recorded. A line that grows past MAX_LINE without ending, a
+// progress bar redrawn with carriage returns say, is cut there. What a
+// thread leaves unfinished when it exits is dropped.
+
+use std::cell::RefCell;
+
+// the most of a line kept waiting for its newline
+pub(crate) const MAX_LINE: usize = 8192;
+
+thread_local! {
+    // the line this thread has begun
+    static PENDING: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Adds `bytes`, written by this thread, to its line in progress, calling `line` for each
+/// line they finish.
+pub(crate) fn written(bytes: &[u8], line: impl FnMut(&[u8])) {
+    let mut line = Some(line);
+    let _ = PENDING.try_with(|pending| {
+        if let Ok(mut pending) = pending.try_borrow_mut() {
+            split(&mut pending, bytes, line.take().expect("called once"));
+        }
+    });
+    // the thread is exiting: the lines finished here are still recorded
+    if let Some(line) = line {
+        split(&mut Vec::new(), bytes, line);
+    }
+}
+
+/// Adds `bytes` to the line in progress in `pending`, calling `line` for each one they
+/// finish.
+fn split(pending: &mut Vec<u8>, mut bytes: &[u8], mut line: impl FnMut(&[u8])) {
+    let mut finished = |l: &[u8]| {
+        let l = l.strip_suffix(b"\r").unwrap_or(l);
+        if !l.is_empty() {
+            line(l);
+        }
+    };
+    while let Some(end) = bytes.iter().position(|&b| b == b'\n') {
+        if pending.is_empty() {
+            finished(&bytes[..end]);
+        } else {
+            pending.extend_from_slice(&bytes[..end]);
+            finished(pending);
+            pending.clear();
+        }
+        bytes = &bytes[end + 1..];
+    }
+    pending.extend_from_slice(bytes);
+    while pending.len() >= MAX_LINE {
+        finished(&pending[..MAX_LINE]);
+        pending.drain(..MAX_LINE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(pending: &mut Vec<u8>, bytes: &[u8]) -> Vec<String> {
+        let mut lines = Vec::new();
+        split(pending, bytes, |l| {
+            lines.push(String::from_utf8_lossy(l).into_owned())
+        });
+        lines
+    }
+
+    #[test]
+    fn lines_are_put_together_from_pieces() {
+        let mut pending = Vec::new();
+        assert_eq!(lines(&mut pending, b"one\ntwo\r\n"), ["one", "two"]);
+        assert!(lines(&mut pending, b"app: ").is_empty());
+        assert!(lines(&mut pending, b"started").is_empty());
+        assert_eq!(
+            lines(&mut pending, b"\n\nthree\nfo"),
+            ["app: started", "three"]
+        );
+        assert_eq!(pending, b"fo");
+    }
+
+    #[test]
+    fn long_lines_are_cut() {
+        let mut pending = Vec::new();
+        let long = vec![b'.'; MAX_LINE + 10];
+        let cut = lines(&mut pending, &long);
+        assert_eq!(cut.len(), 1);
+        assert_eq!(cut[0].len(), MAX_LINE);
+        assert_eq!(pending.len(), 10);
+    }
+}
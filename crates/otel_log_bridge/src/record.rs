@@ -0,0 +1,35 @@
+// src/record.rs
+//
+// Recording what was written to stderr. Once the write has gone out, the
+// lines it finished are each emitted as a record, with the trace the thread
+// is serving, if it is serving one, and `log.iostream` saying where the line
+// went. stderr says nothing of how severe a line is, so records have no
+// severity.
+//
+// Emitting a record may itself write to stderr (the propagator's own
+// warnings, an exporter's), which mustn't be recorded in turn: the thread is
+// marked busy for the length of an interposed call, and writes made while it
+// is are only forwarded.
+
+use crate::lines;
+use libc::c_int;
+use otel_posix_propagator_sys::{self as propagator, Attribute, Errno, Traceparent, now};
+use std::ffi::CString;
+
+// the OTel data model's severity number for none given
+const SEVERITY_UNSPECIFIED: c_int = 0;
+
+/// Records the lines `bytes`, just written to stderr by this thread, finishes.
+pub(crate) fn written(bytes: &[u8]) {
+    let _errno = Errno::keep();
+    let time = now();
+    let traceparent = propagator::traceparent();
+    let parent = traceparent.as_ref().map(Traceparent::as_cstr);
+    lines::written(bytes, |line| {
+        // a NUL would end the message early
+        let body = line.iter().copied().filter(|&b| b != 0).collect::<Vec<_>>();
+        let body = CString::new(body).expect("NULs were taken out");
+        let attributes = [Attribute::string(c"log.iostream", c"stderr")];
+        propagator::emit_log(parent, SEVERITY_UNSPECIFIED, &body, time, &attributes);
+    });
+}
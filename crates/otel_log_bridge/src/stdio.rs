@@ -0,0 +1,370 @@
+// src/stdio.rs
+//
+// The interposed stdio functions. Only streams on file descriptor 2 are
+// looked at, stderr or a copy of it; the rest are passed straight through.
+//
+// A formatted message is formatted once, into a buffer, which is then
+// written to the stream whole and recorded; the real vfprintf isn't called
+// for it. The arguments come as a va_list, which both supported ABIs pass
+// by reference; formatting from a copy of it leaves the caller's as it was,
+// for the fallback to the real function when formatting fails. A
+// `_FORTIFY_SOURCE` call is formatted with glibc's checking vsnprintf, so it
+// is still checked.
+//
+// fprintf itself is variadic, which Rust can't define, so it is a naked
+// function: it stores the argument registers where a va_list expects to
+// find them, builds one over them and the caller's stack, and calls
+// `fprintf_va` with it, returning its result. glibc's `_FORTIFY_SOURCE`
+// builds call __fprintf_chk instead, which takes a flag before the format.
+
+use crate::record;
+use libc::{FILE, STDERR_FILENO, c_char, c_int, size_t};
+use otel_posix_propagator_sys as propagator;
+use std::ffi::{CStr, c_void};
+use std::sync::atomic::AtomicPtr;
+
+// a va_list argument, which both supported ABIs pass by reference
+pub(crate) type VaList = *mut c_void;
+
+// what a va_list refers to, copied as va_copy would: x86-64's is a 24-byte array,
+// AArch64's a 32-byte struct
+#[cfg(target_arch = "x86_64")]
+type VaListState = [u64; 3];
+#[cfg(target_arch = "aarch64")]
+type VaListState = [u64; 4];
+
+pub(crate) type FputsFn = unsafe extern "C" fn(*const c_char, *mut FILE) -> c_int;
+pub(crate) type VfprintfFn = unsafe extern "C" fn(*mut FILE, *const c_char, VaList) -> c_int;
+#[cfg(target_env = "gnu")]
+pub(crate) type VfprintfChkFn =
+    unsafe extern "C" fn(*mut FILE, c_int, *const c_char, VaList) -> c_int;
+
+static REAL_VFPRINTF: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+#[cfg(target_env = "gnu")]
+static REAL_VFPRINTF_CHK: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+unsafe extern "C" {
+    fn vsnprintf(s: *mut c_char, n: size_t, format: *const c_char, ap: VaList) -> c_int;
+    #[cfg(target_env = "gnu")]
+    fn __vsnprintf_chk(
+        s: *mut c_char,
+        n: size_t,
+        flag: c_int,
+        len: size_t,
+        format: *const c_char,
+        ap: VaList,
+    ) -> c_int;
+}
+
+/// Whether `stream` writes to file descriptor 2.
+fn is_stderr(stream: *mut FILE) -> bool {
+    if stream.is_null() {
+        return false;
+    }
+    // a stream without a descriptor (fmemopen's) sets errno, which %m may be about to print
+    let errno = unsafe { *libc::__errno_location() };
+    let fd = unsafe { libc::fileno(stream) };
+    unsafe { *libc::__errno_location() = errno };
+    fd == STDERR_FILENO
+}
+
+/// The message `format` makes of the arguments `ap` refers to, formatted with the
+/// `_FORTIFY_SOURCE` `flag` when there is one, or `None` if formatting failed. `ap` is
+/// left as it was.
+unsafe fn format(format: *const c_char, ap: VaList, flag: Option<c_int>) -> Option<Vec<u8>> {
+    let print = |buf: &mut Vec<u8>| {
+        let mut state = unsafe { ap.cast::<VaListState>().read() };
+        let ap = (&raw mut state).cast();
+        let (s, n) = (buf.as_mut_ptr().cast(), buf.len());
+        let len = match flag {
+            #[cfg(target_env = "gnu")]
+            Some(flag) => unsafe { __vsnprintf_chk(s, n, flag, n, format, ap) },
+            _ => unsafe { vsnprintf(s, n, format, ap) },
+        };
+        usize::try_from(len).ok()
+    };
+    let mut buf = vec![0; 256];
+    let len = print(&mut buf)?;
+    if len >= buf.len() {
+        // with room for the NUL this time
+        buf.resize(len + 1, 0);
+        print(&mut buf)?;
+    }
+    buf.truncate(len);
+    Some(buf)
+}
+
+/// Writes `format`'s message to `stream`, which is stderr, and records it; falls back to
+/// `real` if it can't be formatted.
+unsafe fn print(
+    stream: *mut FILE,
+    format: *const c_char,
+    ap: VaList,
+    flag: Option<c_int>,
+    real: impl FnOnce() -> c_int,
+) -> c_int {
+    // %m has to see the caller's errno
+    let errno = unsafe { *libc::__errno_location() };
+    let Some(message) = (unsafe { self::format(format, ap, flag) }) else {
+        unsafe { *libc::__errno_location() = errno };
+        return real();
+    };
+    let written = unsafe { libc::fwrite(message.as_ptr().cast(), 1, message.len(), stream) };
+    if written < message.len() {
+        return -1;
+    }
+    record::written(&message);
+    c_int::try_from(written).unwrap_or(c_int::MAX)
+}
+
+pub(crate) unsafe fn fputs(real: FputsFn, s: *const c_char, stream: *mut FILE) -> c_int {
+    if s.is_null() || !is_stderr(stream) {
+        return unsafe { real(s, stream) };
+    }
+    let Some(_busy) = propagator::enter() else {
+        return unsafe { real(s, stream) };
+    };
+    let result = unsafe { real(s, stream) };
+    if result >= 0 {
+        record::written(unsafe { CStr::from_ptr(s) }.to_bytes());
+    }
+    result
+}
+
+pub(crate) unsafe fn vfprintf(
+    real: VfprintfFn,
+    stream: *mut FILE,
+    format: *const c_char,
+    ap: VaList,
+) -> c_int {
+    if format.is_null() || !is_stderr(stream) {
+        return unsafe { real(stream, format, ap) };
+    }
+    let Some(_busy) = propagator::enter() else {
+        return unsafe { real(stream, format, ap) };
+    };
+    unsafe { print(stream, format, ap, None, || real(stream, format, ap)) }
+}
+
+#[cfg(target_env = "gnu")]
+pub(crate) unsafe fn __vfprintf_chk(
+    real: VfprintfChkFn,
+    stream: *mut FILE,
+    flag: c_int,
+    format: *const c_char,
+    ap: VaList,
+) -> c_int {
+    if format.is_null() || !is_stderr(stream) {
+        return unsafe { real(stream, flag, format, ap) };
+    }
+    let Some(_busy) = propagator::enter() else {
+        return unsafe { real(stream, flag, format, ap) };
+    };
+    unsafe {
+        print(stream, format, ap, Some(flag), || {
+            real(stream, flag, format, ap)
+        })
+    }
+}
+
+/// Called from the naked `fprintf` with its arguments as a va_list.
+unsafe extern "C" fn fprintf_va(stream: *mut FILE, format: *const c_char, ap: VaList) -> c_int {
+    // the real function can't be looked up from inside a lookup; the message is dropped then
+    let Some(real) = ld_interpose::cached_next(&REAL_VFPRINTF, c"vfprintf") else {
+        return -1;
+    };
+    let real = unsafe { std::mem::transmute::<*mut c_void, VfprintfFn>(real) };
+    unsafe { vfprintf(real, stream, format, ap) }
+}
+
+/// Called from the naked `__fprintf_chk` with its arguments as a va_list.
+#[cfg(target_env = "gnu")]
+unsafe extern "C" fn fprintf_chk_va(
+    stream: *mut FILE,
+    flag: c_int,
+    format: *const c_char,
+    ap: VaList,
+) -> c_int {
+    let Some(real) = ld_interpose::cached_next(&REAL_VFPRINTF_CHK, c"__vfprintf_chk") else {
+        return -1;
+    };
+    let real = unsafe { std::mem::transmute::<*mut c_void, VfprintfChkFn>(real) };
+    unsafe { __vfprintf_chk(real, stream, flag, format, ap) }
+}
+
+// x86-64: the six integer argument registers at 0, the eight vector ones at 48, and the
+// va_list at 176: the offsets of the next integer and vector argument in that area, the
+// caller's stack arguments (past the return address and the saved frame pointer) and
+// the area itself
+
+/// Interposed `fprintf` that forwards what it writes to stderr.
+///
+/// # Safety
+///
+/// Same contract as libc's `fprintf`.
+#[cfg(target_arch = "x86_64")]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fprintf(stream: *mut FILE, format: *const c_char) -> c_int {
+    std::arch::naked_asm!(
+        "push rbp",
+        "mov rbp, rsp",
+        "sub rsp, 208",
+        "mov qword ptr [rsp], rdi",
+        "mov qword ptr [rsp + 8], rsi",
+        "mov qword ptr [rsp + 16], rdx",
+        "mov qword ptr [rsp + 24], rcx",
+        "mov qword ptr [rsp + 32], r8",
+        "mov qword ptr [rsp + 40], r9",
+        "movdqu xmmword ptr [rsp + 48], xmm0",
+        "movdqu xmmword ptr [rsp + 64], xmm1",
+        "movdqu xmmword ptr [rsp + 80], xmm2",
+        "movdqu xmmword ptr [rsp + 96], xmm3",
+        "movdqu xmmword ptr [rsp + 112], xmm4",
+        "movdqu xmmword ptr [rsp + 128], xmm5",
+        "movdqu xmmword ptr [rsp + 144], xmm6",
+        "movdqu xmmword ptr [rsp + 160], xmm7",
+        // past stream and format
+        "mov dword ptr [rsp + 176], 16",
+        "mov dword ptr [rsp + 180], 48",
+        "lea rax, [rbp + 16]",
+        "mov qword ptr [rsp + 184], rax",
+        "mov qword ptr [rsp + 192], rsp",
+        "lea rdx, [rsp + 176]",
+        "call {fprintf_va}",
+        "leave",
+        "ret",
+        fprintf_va = sym fprintf_va,
+    )
+}
+
+/// Interposed `__fprintf_chk`, the `_FORTIFY_SOURCE` variant of [`fprintf`].
+///
+/// # Safety
+///
+/// Same contract as glibc's `__fprintf_chk`.
+#[cfg(all(target_env = "gnu", target_arch = "x86_64"))]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn __fprintf_chk(
+    stream: *mut FILE,
+    flag: c_int,
+    format: *const c_char,
+) -> c_int {
+    std::arch::naked_asm!(
+        "push rbp",
+        "mov rbp, rsp",
+        "sub rsp, 208",
+        "mov qword ptr [rsp], rdi",
+        "mov qword ptr [rsp + 8], rsi",
+        "mov qword ptr [rsp + 16], rdx",
+        "mov qword ptr [rsp + 24], rcx",
+        "mov qword ptr [rsp + 32], r8",
+        "mov qword ptr [rsp + 40], r9",
+        "movdqu xmmword ptr [rsp + 48], xmm0",
+        "movdqu xmmword ptr [rsp + 64], xmm1",
+        "movdqu xmmword ptr [rsp + 80], xmm2",
+        "movdqu xmmword ptr [rsp + 96], xmm3",
+        "movdqu xmmword ptr [rsp + 112], xmm4",
+        "movdqu xmmword ptr [rsp + 128], xmm5",
+        "movdqu xmmword ptr [rsp + 144], xmm6",
+        "movdqu xmmword ptr [rsp + 160], xmm7",
+        // past stream, flag and format
+        "mov dword ptr [rsp + 176], 24",
+        "mov dword ptr [rsp + 180], 48",
+        "lea rax, [rbp + 16]",
+        "mov qword ptr [rsp + 184], rax",
+        "mov qword ptr [rsp + 192], rsp",
+        "lea rcx, [rsp + 176]",
+        "call {fprintf_chk_va}",
+        "leave",
+        "ret",
+        fprintf_chk_va = sym fprintf_chk_va,
+    )
+}
+
+// AArch64: the eight integer argument registers at 16, the eight vector ones at 80, and
+// the va_list at 208: the caller's stack arguments, the ends of the two register areas,
+// and the (negative) offsets from those ends of the next integer and vector argument
+
+/// Interposed `fprintf` that forwards what it writes to stderr.
+///
+/// # Safety
+///
+/// Same contract as libc's `fprintf`.
+#[cfg(target_arch = "aarch64")]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn fprintf(stream: *mut FILE, format: *const c_char) -> c_int {
+    std::arch::naked_asm!(
+        "stp x29, x30, [sp, #-256]!",
+        "mov x29, sp",
+        "stp x0, x1, [sp, #16]",
+        "stp x2, x3, [sp, #32]",
+        "stp x4, x5, [sp, #48]",
+        "stp x6, x7, [sp, #64]",
+        "stp q0, q1, [sp, #80]",
+        "stp q2, q3, [sp, #112]",
+        "stp q4, q5, [sp, #144]",
+        "stp q6, q7, [sp, #176]",
+        "add x9, sp, #256",
+        "str x9, [sp, #208]",
+        "add x9, sp, #80",
+        "str x9, [sp, #216]",
+        "add x9, sp, #208",
+        "str x9, [sp, #224]",
+        // past stream and format
+        "mov w9, #-48",
+        "str w9, [sp, #232]",
+        "mov w9, #-128",
+        "str w9, [sp, #236]",
+        "add x2, sp, #208",
+        "bl {fprintf_va}",
+        "ldp x29, x30, [sp], #256",
+        "ret",
+        fprintf_va = sym fprintf_va,
+    )
+}
+
+/// Interposed `__fprintf_chk`, the `_FORTIFY_SOURCE` variant of [`fprintf`].
+///
+/// # Safety
+///
+/// Same contract as glibc's `__fprintf_chk`.
+#[cfg(all(target_env = "gnu", target_arch = "aarch64"))]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn __fprintf_chk(
+    stream: *mut FILE,
+    flag: c_int,
+    format: *const c_char,
+) -> c_int {
+    std::arch::naked_asm!(
+        "stp x29, x30, [sp, #-256]!",
+        "mov x29, sp",
+        "stp x0, x1, [sp, #16]",
+        "stp x2, x3, [sp, #32]",
+        "stp x4, x5, [sp, #48]",
+        "stp x6, x7, [sp, #64]",
+        "stp q0, q1, [sp, #80]",
+        "stp q2, q3, [sp, #112]",
+        "stp q4, q5, [sp, #144]",
+        "stp q6, q7, [sp, #176]",
+        "add x9, sp, #256",
+        "str x9, [sp, #208]",
+        "add x9, sp, #80",
+        "str x9, [sp, #216]",
+        "add x9, sp, #208",
+        "str x9, [sp, #224]",
+        // past stream, flag and format
+        "mov w9, #-40",
+        "str w9, [sp, #232]",
+        "mov w9, #-128",
+        "str w9, [sp, #236]",
+        "add x3, sp, #208",
+        "bl {fprintf_chk_va}",
+        "ldp x29, x30, [sp], #256",
+        "ret",
+        fprintf_chk_va = sym fprintf_chk_va,
+    )
+}
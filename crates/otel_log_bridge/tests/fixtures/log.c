@@ -0,0 +1,52 @@
+/* Logs to stderr the ways legacy programs do: a formatted message, a line put together
+ * from pieces, and a raw write in two; writes to a file of its own the same ways; prints
+ * a result on stdout. Built with _FORTIFY_SOURCE, fprintf is glibc's __fprintf_chk.
+ *
+ * Built with -rdynamic, its otel_posix_emit_log comes before the propagator's, so the
+ * records the bridge emits are printed on stdout, one line each, for the test to read
+ * back: the exporter writes out spans but not records. */
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+#include "otel_posix_pseudo_propegator.h"
+#include "preload_testkit.h"
+
+int otel_posix_emit_log(const char *parent, int severity_number, const char *body,
+                        uint64_t time, const struct OtelPosixAttribute *attributes,
+                        size_t count) {
+    (void)time;
+    char line[512];
+    int n = snprintf(line, sizeof line, "record %s %d", parent ? parent : "-", severity_number);
+    for (size_t i = 0; i < count; i++)
+        if (attributes[i].kind == OTEL_POSIX_VALUE_STRING)
+            n += snprintf(line + n, sizeof line - n, " %s=%s", attributes[i].key,
+                          attributes[i].string_value);
+    n += snprintf(line + n, sizeof line - n, ": %s\n", body);
+    return write(1, line, n) == n ? 0 : -1;
+}
+
+int main(void) {
+    if (testkit_start() != 0)
+        return 1;
+    fprintf(stderr, "log: started %d workers at %.1f%% load\n", 4, 12.5);
+    fputs("log: ", stderr);
+    fputs("step one\n", stderr);
+    if (write(2, "log: raw", 8) != 8 || write(2, " write\n", 7) != 7)
+        return 1;
+
+    FILE *file = tmpfile();
+    if (file == NULL)
+        return 1;
+    fprintf(file, "to a file %d\n", 7);
+    fputs("and more\n", file);
+    char written[64] = "";
+    rewind(file);
+    size_t n = fread(written, 1, sizeof written - 1, file);
+    fclose(file);
+    written[n] = '\0';
+    printf("%s", written);
+    printf("done\n");
+    return 0;
+}
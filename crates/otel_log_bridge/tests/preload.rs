@@ -0,0 +1,38 @@
+#![cfg(target_os = "linux")]
+
+use preload_testkit::{Fixture, Run, SPAN, TRACE};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stderr_lines_are_records_of_the_thread_s_span() {
+        let program = Fixture::c("tests/fixtures/log.c")
+            .include("../otel_posix_pseudo_propegator/include")
+            .flag("-O2")
+            .flag("-D_FORTIFY_SOURCE=2")
+            // or gcc makes its fputs of string literals fwrite
+            .flag("-fno-builtin-fputs")
+            .flag("-rdynamic")
+            .compile();
+        let out = Run::traced(&program, "otel_log_bridge").output().success();
+        let record = |body| format!("record 00-{TRACE}-{SPAN}-01 0 log.iostream=stderr: {body}");
+        assert_eq!(
+            out.lines(),
+            [
+                record("log: started 4 workers at 12.5% load"),
+                record("log: step one"),
+                record("log: raw write"),
+                // the file's lines aren't records
+                "to a file 7".to_string(),
+                "and more".to_string(),
+                "done".to_string(),
+            ]
+        );
+        assert_eq!(
+            out.stderr,
+            "log: started 4 workers at 12.5% load\nlog: step one\nlog: raw write\n"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+// src/arena.rs
+//
+// Memory for the allocations made before the real malloc family is found.
+// dlsym allocates while it looks them up (calloc, in glibc), and that lands
+// back in this library with nothing to forward to. Those few allocations
+// are carved from a static buffer, which starts out zeroed and is never
+// reused, so calloc's are zeroed too; free leaves them alone.
+
+use libc::c_int;
+use std::cell::UnsafeCell;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const SIZE: usize = 64 * 1024;
+
+// each allocation's size, kept in front of it for realloc; also its alignment
+const HEADER: usize = 16;
+
+#[repr(C, align(16))]
+struct Buffer(UnsafeCell<[u8; SIZE]>);
+
+// each allocation is handed to one caller, and the header written before it is
+unsafe impl Sync for Buffer {}
+
+static BUFFER: Buffer = Buffer(UnsafeCell::new([0; SIZE]));
+static USED: AtomicUsize = AtomicUsize::new(0);
+
+fn start() -> *mut u8 {
+    BUFFER.0.get().cast()
+}
+
+/// `size` bytes from the arena, zeroed, or null when it is used up.
+pub(crate) fn alloc(size: usize) -> *mut c_void {
+    let Some(total) = size
+        .checked_next_multiple_of(HEADER)
+        .and_then(|size| size.checked_add(HEADER))
+    else {
+        return std::ptr::null_mut();
+    };
+    let offset = USED.fetch_add(total, Ordering::Relaxed);
+    if offset.checked_add(total).is_none_or(|end| end > SIZE) {
+        return std::ptr::null_mut();
+    }
+    unsafe {
+        let header = start().add(offset);
+        header.cast::<usize>().write(size);
+        header.add(HEADER).cast()
+    }
+}
+
+/// [`alloc`] for `calloc`.
+pub(crate) fn calloc(n: usize, size: usize) -> *mut c_void {
+    n.checked_mul(size).map_or(std::ptr::null_mut(), alloc)
+}
+
+/// [`alloc`] for the aligned allocators: null for an alignment past the arena's own.
+pub(crate) fn aligned(alignment: usize, size: usize) -> *mut c_void {
+    if alignment > HEADER {
+        return std::ptr::null_mut();
+    }
+    alloc(size)
+}
+
+/// [`aligned`] for `posix_memalign`, which returns the allocation through `memptr`.
+///
+/// # Safety
+///
+/// `memptr` must be valid to write a pointer to.
+pub(crate) unsafe fn posix_memalign(
+    memptr: *mut *mut c_void,
+    alignment: usize,
+    size: usize,
+) -> c_int {
+    let ptr = aligned(alignment, size);
+    if ptr.is_null() {
+        return libc::ENOMEM;
+    }
+    unsafe { memptr.write(ptr) };
+    0
+}
+
+/// `realloc` from inside the lookup: a new arena allocation with the old one's contents.
+/// Only the arena's own allocations can be moved; anything else gets null.
+pub(crate) fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void {
+    if !ptr.is_null() && !contains(ptr) {
+        return std::ptr::null_mut();
+    }
+    let moved = alloc(size);
+    if !moved.is_null() && !ptr.is_null() {
+        let len = unsafe { self::size(ptr) }.min(size);
+        unsafe { std::ptr::copy_nonoverlapping(ptr.cast::<u8>(), moved.cast(), len) };
+    }
+    moved
+}
+
+/// Whether `ptr` is one of the arena's allocations.
+pub(crate) fn contains(ptr: *mut c_void) -> bool {
+    let addr = ptr as usize;
+    addr >= start() as usize && addr < start() as usize + SIZE
+}
+
+/// The size `ptr` was allocated with.
+///
+/// # Safety
+///
+/// `ptr` must be one of the arena's allocations.
+pub(crate) unsafe fn size(ptr: *mut c_void) -> usize {
+    unsafe { ptr.cast::<u8>().sub(HEADER).cast::<usize>().read() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocations_are_zeroed_aligned_and_movable() {
+        let a = calloc(3, 5);
+        assert!(contains(a) && (a as usize).is_multiple_of(HEADER));
+        assert_eq!(unsafe { size(a) }, 15);
+        let bytes = unsafe { std::slice::from_raw_parts_mut(a.cast::<u8>(), 15) };
+        assert!(bytes.iter().all(|&b| b == 0));
+        bytes.copy_from_slice(b"fifteen bytes!!");
+
+        let b = realloc(a, 32);
+        assert!(contains(b) && b != a);
+        assert_eq!(
+            unsafe { std::slice::from_raw_parts(b.cast::<u8>(), 15) },
+            b"fifteen bytes!!"
+        );
+        let elsewhere = std::ptr::dangling_mut::<c_void>();
+        assert!(!contains(elsewhere) && realloc(elsewhere, 8).is_null());
+        assert!(contains(aligned(8, 8)) && aligned(64, 8).is_null());
+        assert!(calloc(usize::MAX, 2).is_null() && alloc(SIZE).is_null());
+    }
+}
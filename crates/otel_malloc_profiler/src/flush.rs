@@ -0,0 +1,121 @@
+// src/flush.rs
+//
+// Records pending samples, every FLUSH_INTERVAL on a thread of its own and
+// once more at exit. Each sampled allocation's size goes into a histogram,
+// whether or not its thread was serving a trace. The samples taken under
+// one span are summed into a `malloc` span beneath it, from the first
+// sample to the last: how many were taken, the allocations and bytes they
+// stand for, and the largest allocation sampled.
+//
+// The samples can't be events on the span they were taken under: an event
+// goes on the calling thread's current span, and by the time the flusher
+// records it that is none of them.
+
+use crate::pending::{self, Sample};
+use otel_posix_propagator_sys::{self as propagator, Attribute, Span, Traceparent};
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+// held for a whole flush, so one that finds nothing pending waits for the samples another
+// has taken out to be recorded
+static FLUSHING: Mutex<()> = Mutex::new(());
+
+/// The samples taken under one span.
+#[derive(Default)]
+struct Totals {
+    samples: u64,
+    count: f64,
+    bytes: u64,
+    largest: u64,
+    first: u64,
+    last: u64,
+}
+
+impl Totals {
+    fn add(&mut self, sample: &Sample) {
+        if self.samples == 0 || sample.time < self.first {
+            self.first = sample.time;
+        }
+        self.last = self.last.max(sample.time);
+        self.samples += 1;
+        self.count += sample.count;
+        self.bytes += sample.bytes;
+        self.largest = self.largest.max(sample.size);
+    }
+}
+
+/// Starts the thread that flushes periodically, and flushes at exit.
+pub(crate) fn start() {
+    let _ = std::thread::Builder::new()
+        .name("otel-malloc-flush".into())
+        .spawn(|| {
+            loop {
+                std::thread::sleep(FLUSH_INTERVAL);
+                flush();
+            }
+        });
+    unsafe { libc::atexit(flush_at_exit) };
+}
+
+extern "C" fn flush_at_exit() {
+    let _ = std::panic::catch_unwind(flush);
+}
+
+/// Records the samples taken so far.
+pub fn flush() {
+    let _flushing = FLUSHING.lock().unwrap_or_else(PoisonError::into_inner);
+    // what the propagator allocates to record them isn't sampled in turn
+    let _busy = propagator::enter();
+    let mut spans: HashMap<Traceparent, Totals> = HashMap::new();
+    pending::drain(|sample| {
+        propagator::record_histogram(c"otel_posix.malloc.size", c"By", sample.size as f64, &[]);
+        if let Some(traceparent) = sample.traceparent {
+            spans.entry(traceparent).or_default().add(&sample);
+        }
+    });
+    for (traceparent, totals) in spans {
+        let span = Span::new(traceparent.as_cstr(), c"malloc", totals.first, totals.last);
+        span.record(&[
+            Attribute::int(c"otel_posix.malloc.samples", totals.samples as i64),
+            Attribute::double(c"otel_posix.malloc.estimated_count", totals.count),
+            Attribute::int(c"otel_posix.malloc.estimated_bytes", totals.bytes as i64),
+            Attribute::int(c"otel_posix.malloc.largest", totals.largest as i64),
+        ]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TP: &[u8] = b"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn samples_are_summed() {
+        let sample = |size, time| Sample {
+            traceparent: Traceparent::new(TP),
+            size,
+            count: 2.0,
+            bytes: size * 2,
+            time,
+        };
+        let mut totals = Totals::default();
+        for s in [sample(100, 30), sample(500, 10), sample(50, 20)] {
+            totals.add(&s);
+        }
+        assert_eq!(
+            (
+                totals.samples,
+                totals.bytes,
+                totals.largest,
+                totals.first,
+                totals.last
+            ),
+            (3, 1300, 500, 10, 30)
+        );
+        assert_eq!(totals.count, 6.0);
+    }
+}
@@ -0,0 +1,97 @@
+// src/lib.rs
+//
+// An LD_PRELOAD heap profiler that puts allocations on the trace that made
+// them. malloc, calloc, realloc and the aligned allocators are interposed
+// (malloc.rs), and a sample of what they allocate, one allocation per
+// OTEL_MALLOC_PROFILER_SAMPLE_BYTES allocated on average (512 KiB by
+// default, 0 for none), is kept with the trace the allocating thread is
+// serving (sample.rs). Once a second the samples' sizes are recorded in a
+// histogram, and those taken under each span are summed into a `malloc`
+// span beneath it, with the allocations and bytes they stand for
+// (flush.rs), so memory hotspots show up next to the latency they cause.
+//
+// free is interposed for the few allocations made before the real
+// functions are found (arena.rs). valloc and pvalloc aren't interposed,
+// and neither is what glibc allocates for itself without going through
+// these functions.
+
+mod arena;
+mod flush;
+mod malloc;
+mod pending;
+mod sample;
+
+pub use flush::flush;
+
+use libc::{c_int, c_void, size_t};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const DEFAULT_SAMPLE_BYTES: u64 = 512 * 1024;
+
+static SAMPLE_BYTES: AtomicU64 = AtomicU64::new(DEFAULT_SAMPLE_BYTES);
+
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+#[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__mod_init_func"))]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(configure);
+}
+
+fn configure() {
+    if let Ok(value) = std::env::var("OTEL_MALLOC_PROFILER_SAMPLE_BYTES") {
+        match value.trim().parse() {
+            Ok(bytes) => set_sample_bytes(bytes),
+            Err(_) => eprintln!(
+                "otel_malloc_profiler: invalid OTEL_MALLOC_PROFILER_SAMPLE_BYTES {value:?}, using {DEFAULT_SAMPLE_BYTES}"
+            ),
+        }
+    }
+    if sample_bytes() != 0 {
+        flush::start();
+    }
+}
+
+/// Sets the mean number of bytes allocated between samples; 0 stops sampling. Each thread
+/// goes on counting down what it drew before, and uses the new mean from its next sample.
+pub fn set_sample_bytes(bytes: u64) {
+    SAMPLE_BYTES.store(bytes, Ordering::Relaxed);
+}
+
+fn sample_bytes() -> u64 {
+    SAMPLE_BYTES.load(Ordering::Relaxed)
+}
+
+ld_interpose::interpose! {
+    malloc {
+        /// Interposed `malloc` that samples the allocation.
+        fn malloc(size: size_t) -> *mut c_void, else arena::alloc(size);
+
+        /// Interposed `calloc` that samples the allocation.
+        fn calloc(n: size_t, size: size_t) -> *mut c_void, else arena::calloc(n, size);
+
+        /// Interposed `realloc` that samples the new allocation.
+        fn realloc(ptr: *mut c_void, size: size_t) -> *mut c_void,
+            else arena::realloc(ptr, size);
+
+        /// Interposed `posix_memalign` that samples the allocation.
+        fn posix_memalign(memptr: *mut *mut c_void, alignment: size_t, size: size_t) -> c_int,
+            else unsafe { arena::posix_memalign(memptr, alignment, size) };
+
+        /// Interposed `aligned_alloc` that samples the allocation.
+        fn aligned_alloc(alignment: size_t, size: size_t) -> *mut c_void,
+            else arena::aligned(alignment, size);
+
+        /// Interposed `memalign` that samples the allocation.
+        fn memalign(alignment: size_t, size: size_t) -> *mut c_void,
+            else arena::aligned(alignment, size);
+
+        /// Interposed `free` that leaves the bootstrap allocations alone.
+        fn free(ptr: *mut c_void), else ();
+    }
+}
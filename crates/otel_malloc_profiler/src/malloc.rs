@@ -0,0 +1,87 @@
+// src/malloc.rs
+//
+// The interposed malloc family. Each forwards to the real function and
+// counts what it allocated towards the next sample; free only keeps the
+// arena's allocations (arena.rs) away from the real free. The aligned
+// allocators are counted like malloc, by the size asked for.
+
+use crate::{arena, sample};
+use libc::{c_int, size_t};
+use std::ffi::c_void;
+
+pub(crate) type MallocFn = unsafe extern "C" fn(size_t) -> *mut c_void;
+pub(crate) type CallocFn = unsafe extern "C" fn(size_t, size_t) -> *mut c_void;
+pub(crate) type ReallocFn = unsafe extern "C" fn(*mut c_void, size_t) -> *mut c_void;
+pub(crate) type FreeFn = unsafe extern "C" fn(*mut c_void);
+pub(crate) type PosixMemalignFn = unsafe extern "C" fn(*mut *mut c_void, size_t, size_t) -> c_int;
+// aligned_alloc's and memalign's
+pub(crate) type AlignedFn = unsafe extern "C" fn(size_t, size_t) -> *mut c_void;
+
+pub(crate) unsafe fn malloc(real: MallocFn, size: size_t) -> *mut c_void {
+    let ptr = unsafe { real(size) };
+    if !ptr.is_null() {
+        sample::allocated(size);
+    }
+    ptr
+}
+
+pub(crate) unsafe fn calloc(real: CallocFn, n: size_t, size: size_t) -> *mut c_void {
+    let ptr = unsafe { real(n, size) };
+    if !ptr.is_null() {
+        sample::allocated(n.saturating_mul(size));
+    }
+    ptr
+}
+
+pub(crate) unsafe fn realloc(real: ReallocFn, ptr: *mut c_void, size: size_t) -> *mut c_void {
+    let moved = if arena::contains(ptr) {
+        // out of the arena, with its contents; the arena keeps the old copy
+        let moved = unsafe { real(std::ptr::null_mut(), size) };
+        if !moved.is_null() {
+            let len = unsafe { arena::size(ptr) }.min(size);
+            unsafe { std::ptr::copy_nonoverlapping(ptr.cast::<u8>(), moved.cast(), len) };
+        }
+        moved
+    } else {
+        unsafe { real(ptr, size) }
+    };
+    if !moved.is_null() {
+        sample::allocated(size);
+    }
+    moved
+}
+
+pub(crate) unsafe fn posix_memalign(
+    real: PosixMemalignFn,
+    memptr: *mut *mut c_void,
+    alignment: size_t,
+    size: size_t,
+) -> c_int {
+    let result = unsafe { real(memptr, alignment, size) };
+    if result == 0 {
+        sample::allocated(size);
+    }
+    result
+}
+
+pub(crate) unsafe fn aligned_alloc(
+    real: AlignedFn,
+    alignment: size_t,
+    size: size_t,
+) -> *mut c_void {
+    let ptr = unsafe { real(alignment, size) };
+    if !ptr.is_null() {
+        sample::allocated(size);
+    }
+    ptr
+}
+
+pub(crate) unsafe fn memalign(real: AlignedFn, alignment: size_t, size: size_t) -> *mut c_void {
+    unsafe { aligned_alloc(real, alignment, size) }
+}
+
+pub(crate) unsafe fn free(real: FreeFn, ptr: *mut c_void) {
+    if !arena::contains(ptr) {
+        unsafe { real(ptr) }
+    }
+}
@@ -0,0 +1,85 @@
+// src/pending.rs
+//
+// Samples taken and not yet recorded. malloc can't record a span itself:
+// the propagator allocates and takes locks to do it, and may be what is
+// allocating. So a sample goes into one of a fixed set of slots, without
+// allocating or locking, and the flusher (flush.rs) takes it out from a
+// thread of its own. With the slots around where it looks all full, the
+// sample is dropped.
+
+use otel_posix_propagator_sys::Traceparent;
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+
+const SLOTS: usize = 4096;
+
+// slots a sample tries before it is dropped
+const PROBES: usize = 16;
+
+const FREE: u8 = 0;
+const BUSY: u8 = 1;
+const FULL: u8 = 2;
+
+/// One sampled allocation.
+pub(crate) struct Sample {
+    /// The trace the allocating thread was serving, if any.
+    pub(crate) traceparent: Option<Traceparent>,
+    pub(crate) size: u64,
+    /// The allocations and bytes it stands for.
+    pub(crate) count: f64,
+    pub(crate) bytes: u64,
+    /// When it was taken, in nanoseconds since the Unix epoch.
+    pub(crate) time: u64,
+}
+
+struct Slot {
+    state: AtomicU8,
+    sample: UnsafeCell<MaybeUninit<Sample>>,
+}
+
+// a slot's sample is only touched by whoever moved it to BUSY
+unsafe impl Sync for Slot {}
+
+static PENDING: [Slot; SLOTS] = [const {
+    Slot {
+        state: AtomicU8::new(FREE),
+        sample: UnsafeCell::new(MaybeUninit::uninit()),
+    }
+}; SLOTS];
+
+// where the next sample starts looking, so threads spread out
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// Keeps `sample` for the flusher; false if there was no room for it.
+pub(crate) fn push(sample: Sample) -> bool {
+    let start = NEXT.fetch_add(1, Ordering::Relaxed);
+    for i in 0..PROBES {
+        let slot = &PENDING[(start + i) % SLOTS];
+        if slot
+            .state
+            .compare_exchange(FREE, BUSY, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            unsafe { (*slot.sample.get()).write(sample) };
+            slot.state.store(FULL, Ordering::Release);
+            return true;
+        }
+    }
+    false
+}
+
+/// Takes every pending sample out and hands it to `f`.
+pub(crate) fn drain(mut f: impl FnMut(Sample)) {
+    for slot in &PENDING {
+        if slot
+            .state
+            .compare_exchange(FULL, BUSY, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            let sample = unsafe { (*slot.sample.get()).assume_init_read() };
+            slot.state.store(FREE, Ordering::Release);
+            f(sample);
+        }
+    }
+}
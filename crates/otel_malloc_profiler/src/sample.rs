@@ -0,0 +1,106 @@
+// src/sample.rs
+//
+// Which allocations are recorded. Each thread counts down the bytes it
+// allocates, and the allocation that takes the count past zero is sampled;
+// the next count is drawn from an exponential distribution with the
+// configured mean, so every byte has the same chance of being sampled and a
+// loop of small allocations is seen as fairly as one large one. A sample
+// stands for size / p bytes and 1 / p allocations, p being the chance an
+// allocation of its size had of being sampled, so summing them estimates
+// what was allocated.
+//
+// A sample is kept for the flusher to record, with the trace the thread is
+// serving, if any. Looking the trace up may allocate the first time; those
+// allocations are neither counted nor sampled.
+
+use crate::pending::{self, Sample};
+use otel_posix_propagator_sys as propagator;
+use std::cell::Cell;
+
+// REMAINING before the thread's first count is drawn
+const UNDRAWN: u64 = u64::MAX;
+
+thread_local! {
+    // plain data without destructors, so touching them from malloc never allocates
+    static REMAINING: Cell<u64> = const { Cell::new(UNDRAWN) };
+    static RNG: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Counts an allocation of `size` bytes, keeping it if it is sampled.
+pub(crate) fn allocated(size: usize) {
+    let interval = crate::sample_bytes();
+    if interval == 0 || size == 0 {
+        return;
+    }
+    let Some(_busy) = propagator::enter() else {
+        return;
+    };
+    let size = size as u64;
+    let remaining = match REMAINING.get() {
+        UNDRAWN => draw(interval),
+        remaining => remaining,
+    };
+    if size < remaining {
+        REMAINING.set(remaining - size);
+        return;
+    }
+    REMAINING.set(draw(interval));
+    keep(size, interval);
+}
+
+fn keep(size: u64, interval: u64) {
+    let (count, bytes) = estimate(size, interval);
+    pending::push(Sample {
+        traceparent: propagator::traceparent(),
+        size,
+        count,
+        bytes,
+        time: propagator::now(),
+    });
+}
+
+/// The allocations and bytes a sampled allocation of `size` stands for.
+fn estimate(size: u64, interval: u64) -> (f64, u64) {
+    let p = -(-(size as f64) / interval as f64).exp_m1();
+    (1.0 / p, (size as f64 / p).round() as u64)
+}
+
+/// The bytes until the next sample: exponentially distributed, with mean `interval`.
+fn draw(interval: u64) -> u64 {
+    // xorshift64*, seeded from the thread-local's address so threads differ
+    let mut x = RNG.get();
+    if x == 0 {
+        x = RNG.with(|rng| rng as *const Cell<u64> as u64) | 1;
+    }
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    RNG.set(x);
+    // uniform in (0, 1]
+    let u = ((x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) + 1) as f64 / (1u64 << 53) as f64;
+    (-u.ln() * interval as f64) as u64 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_stand_for_what_they_missed() {
+        // large allocations are nearly always sampled, so stand for about themselves
+        let (count, bytes) = estimate(64 << 20, 512 << 10);
+        assert!((count - 1.0).abs() < 1e-9 && bytes == 64 << 20);
+        // small ones for many like them
+        let (count, bytes) = estimate(64, 512 << 10);
+        assert!((count - 8192.5).abs() < 1.0);
+        assert!(bytes.abs_diff(512 << 10) < 64);
+    }
+
+    #[test]
+    fn draws_average_the_interval() {
+        let n = 100_000;
+        let mean = (0..n).map(|_| draw(1000)).sum::<u64>() / n;
+        assert!((950..1050).contains(&mean), "{mean}");
+        assert!((0..1000).all(|_| draw(1) >= 1));
+    }
+}
@@ -0,0 +1,53 @@
+/* Allocates 64 MiB and frees it, then 100 000 zeroed bytes, and 1000 bytes grown to
+ * 300 000; or, given "aligned", 48 MiB with posix_memalign, 40 MiB with aligned_alloc and
+ * 32 MiB with memalign, freeing each. Then waits out the profiler's flush interval a
+ * couple of times: its flush at exit comes after the propagator's. */
+#include <malloc.h>
+#include <stdlib.h>
+#include <string.h>
+#include <time.h>
+
+#include "preload_testkit.h"
+
+/* kept, so the allocations aren't optimized away */
+void *volatile sink;
+
+static int allocate(void) {
+    sink = malloc(64 << 20);
+    free(sink);
+    void *zeroed = calloc(1, 100000);
+    void *grown = malloc(1000);
+    sink = zeroed;
+    sink = grown = realloc(grown, 300000);
+    if (zeroed == NULL || grown == NULL)
+        return -1;
+    free(zeroed);
+    free(grown);
+    return 0;
+}
+
+static int allocate_aligned(void) {
+    void *p;
+    if (posix_memalign(&p, 4096, 48 << 20) != 0)
+        return -1;
+    sink = p;
+    free(p);
+    if ((sink = aligned_alloc(64, 40 << 20)) == NULL)
+        return -1;
+    free(sink);
+    if ((sink = memalign(256, 32 << 20)) == NULL)
+        return -1;
+    free(sink);
+    return 0;
+}
+
+int main(int argc, char **argv) {
+    if (testkit_start() != 0)
+        return 1;
+    int aligned = argc > 1 && strcmp(argv[1], "aligned") == 0;
+    if ((aligned ? allocate_aligned() : allocate()) != 0)
+        return 1;
+    struct timespec flushed = {2, 500 * 1000 * 1000};
+    nanosleep(&flushed, NULL);
+    return 0;
+}
@@ -0,0 +1,49 @@
+#![cfg(target_os = "linux")]
+
+use preload_testkit::{Fixture, Output, Run, SPAN, TRACE};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the fixture with `args`, sampling one allocation per `sample_bytes` allocated.
+    fn allocate(sample_bytes: &str, args: &[&str]) -> Output {
+        let program = Fixture::c("tests/fixtures/allocate.c").compile();
+        let mut run = Run::traced(&program, "otel_malloc_profiler")
+            .env("OTEL_MALLOC_PROFILER_SAMPLE_BYTES", sample_bytes);
+        for arg in args {
+            run = run.arg(arg);
+        }
+        run.output().success()
+    }
+
+    #[test]
+    fn allocations_are_summed_under_the_span() {
+        // every allocation
+        let spans = allocate("1", &[]).spans();
+        let malloc = spans.child(TRACE, SPAN, "malloc");
+        let samples = malloc.int("otel_posix.malloc.samples").unwrap();
+        let bytes = malloc.int("otel_posix.malloc.estimated_bytes").unwrap();
+        assert!(
+            samples >= 3 && bytes >= 100_000 + 1000 + 300_000,
+            "{malloc}"
+        );
+        assert_eq!(malloc.int("otel_posix.malloc.largest"), Some(64 << 20));
+    }
+
+    #[test]
+    fn aligned_allocations_are_sampled() {
+        let spans = allocate("1", &["aligned"]).spans();
+        let malloc = spans.child(TRACE, SPAN, "malloc");
+        // all three, and little else
+        let bytes = malloc.int("otel_posix.malloc.estimated_bytes").unwrap();
+        assert!((120 << 20..121 << 20).contains(&bytes), "{malloc}");
+        assert_eq!(malloc.int("otel_posix.malloc.largest"), Some(48 << 20));
+    }
+
+    #[test]
+    fn nothing_is_sampled_with_no_interval() {
+        let spans = allocate("0", &[]).spans();
+        assert_eq!(spans.named("malloc").count(), 0, "{spans}");
+    }
+}
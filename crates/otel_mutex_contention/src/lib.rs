@@ -0,0 +1,79 @@
+// src/lib.rs
+//
+// An LD_PRELOAD tracer that makes lock contention in binaries that can't be
+// changed observable. pthread_mutex_lock, pthread_rwlock_rdlock and
+// pthread_rwlock_wrlock are interposed; each first tries the lock, and
+// only when it is held does the real call get timed (lock.rs). A wait of
+// OTEL_MUTEX_CONTENTION_THRESHOLD_US or more (1 ms by default) is recorded
+// as an event on the waiting thread's span, with the lock's address and,
+// where it can be read, the thread holding it, and as a value in a
+// wait-time histogram (record.rs).
+//
+// The histogram takes every wait over the threshold, on a thread serving a
+// trace or not; only the event needs one. pthread_mutex_trylock and the
+// timed variants aren't interposed, and neither are the locks glibc or
+// Rust's standard library take without going through these functions.
+
+mod lock;
+mod record;
+
+use libc::{c_int, pthread_mutex_t, pthread_rwlock_t};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+const DEFAULT_THRESHOLD: Duration = Duration::from_millis(1);
+
+static THRESHOLD_NANOS: AtomicU64 = AtomicU64::new(DEFAULT_THRESHOLD.as_nanos() as u64);
+
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+#[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__mod_init_func"))]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(configure);
+}
+
+fn configure() {
+    if let Ok(value) = std::env::var("OTEL_MUTEX_CONTENTION_THRESHOLD_US") {
+        match value.trim().parse() {
+            Ok(micros) => set_threshold(Duration::from_micros(micros)),
+            Err(_) => eprintln!(
+                "otel_mutex_contention: invalid OTEL_MUTEX_CONTENTION_THRESHOLD_US {value:?}, using {}",
+                DEFAULT_THRESHOLD.as_micros()
+            ),
+        }
+    }
+}
+
+/// Sets the shortest wait for a lock that is recorded.
+pub fn set_threshold(threshold: Duration) {
+    THRESHOLD_NANOS.store(
+        threshold.as_nanos().try_into().unwrap_or(u64::MAX),
+        Ordering::Relaxed,
+    );
+}
+
+fn threshold() -> Duration {
+    Duration::from_nanos(THRESHOLD_NANOS.load(Ordering::Relaxed))
+}
+
+ld_interpose::interpose! {
+    lock {
+        /// Interposed `pthread_mutex_lock` that records a long wait for the mutex.
+        fn pthread_mutex_lock(mutex: *mut pthread_mutex_t) -> c_int,
+            else unsafe { lock::spin(|| libc::pthread_mutex_trylock(mutex)) };
+
+        /// Interposed `pthread_rwlock_rdlock` that records a long wait to read.
+        fn pthread_rwlock_rdlock(rwlock: *mut pthread_rwlock_t) -> c_int,
+            else unsafe { lock::spin(|| libc::pthread_rwlock_tryrdlock(rwlock)) };
+
+        /// Interposed `pthread_rwlock_wrlock` that records a long wait to write.
+        fn pthread_rwlock_wrlock(rwlock: *mut pthread_rwlock_t) -> c_int,
+            else unsafe { lock::spin(|| libc::pthread_rwlock_trywrlock(rwlock)) };
+    }
+}
@@ -0,0 +1,136 @@
+// src/lock.rs
+//
+// The interposed lock functions. Each tries the lock first, which is all
+// an uncontended lock costs; only when it is held (EBUSY) is the holder
+// noted and the real call timed. Any other answer from the try (a
+// recursive mutex at its limit, a deadlock an error-checking one detects)
+// is left for the real call to give.
+//
+// The holder is read from glibc's lock structures, where a mutex keeps its
+// owner's tid, and a rwlock the tid of the thread holding it to write.
+// Readers aren't kept, so a wait for readers has no holder; nor does any
+// wait elsewhere than glibc.
+
+use crate::record::{self, Kind};
+use libc::{c_int, pthread_mutex_t, pthread_rwlock_t};
+use otel_posix_propagator_sys as propagator;
+use std::time::Instant;
+
+pub(crate) type MutexFn = unsafe extern "C" fn(*mut pthread_mutex_t) -> c_int;
+pub(crate) type RwlockFn = unsafe extern "C" fn(*mut pthread_rwlock_t) -> c_int;
+
+/// Takes a lock with `try_lock` alone, yielding between tries, for calls made before
+/// the real functions are found.
+pub(crate) fn spin(mut try_lock: impl FnMut() -> c_int) -> c_int {
+    loop {
+        match try_lock() {
+            libc::EBUSY => std::thread::yield_now(),
+            result => return result,
+        }
+    }
+}
+
+/// The tid kept at `offset` bytes into the lock at `lock`, or `None` when it isn't set.
+#[cfg(all(target_os = "linux", target_env = "gnu", target_pointer_width = "64"))]
+unsafe fn tid_at<T>(lock: *const T, offset: usize) -> Option<c_int> {
+    use std::sync::atomic::{AtomicI32, Ordering};
+    // written by the holder as we read it
+    let tid = unsafe { (*lock.byte_add(offset).cast::<AtomicI32>()).load(Ordering::Relaxed) };
+    (tid > 0).then_some(tid)
+}
+
+/// The tid of the thread holding `mutex`: `__data.__owner`.
+unsafe fn mutex_holder(mutex: *const pthread_mutex_t) -> Option<c_int> {
+    #[cfg(all(target_os = "linux", target_env = "gnu", target_pointer_width = "64"))]
+    return unsafe { tid_at(mutex, 8) };
+    #[cfg(not(all(target_os = "linux", target_env = "gnu", target_pointer_width = "64")))]
+    return None;
+}
+
+/// The tid of the thread holding `rwlock` to write: `__data.__cur_writer`.
+unsafe fn writer(rwlock: *const pthread_rwlock_t) -> Option<c_int> {
+    #[cfg(all(target_os = "linux", target_env = "gnu", target_pointer_width = "64"))]
+    return unsafe { tid_at(rwlock, 24) };
+    #[cfg(not(all(target_os = "linux", target_env = "gnu", target_pointer_width = "64")))]
+    return None;
+}
+
+/// Takes the lock at `address`: with `try_lock` when it is free, or else by waiting in
+/// `lock`, recording the wait as `kind`, with the `holder` seen before it, when it was
+/// long enough.
+fn contended(
+    kind: Kind,
+    address: usize,
+    try_lock: impl FnOnce() -> c_int,
+    holder: impl FnOnce() -> Option<c_int>,
+    lock: impl FnOnce() -> c_int,
+) -> c_int {
+    let Some(_busy) = propagator::enter() else {
+        return lock();
+    };
+    match try_lock() {
+        0 => return 0,
+        libc::EBUSY => {}
+        // an error the real call will give again
+        _ => return lock(),
+    }
+    let holder = holder();
+    let started = Instant::now();
+    let result = lock();
+    record::waited(kind, address, holder, started.elapsed());
+    result
+}
+
+pub(crate) unsafe fn pthread_mutex_lock(real: MutexFn, mutex: *mut pthread_mutex_t) -> c_int {
+    contended(
+        Kind::Mutex,
+        mutex as usize,
+        || unsafe { libc::pthread_mutex_trylock(mutex) },
+        || unsafe { mutex_holder(mutex) },
+        || unsafe { real(mutex) },
+    )
+}
+
+pub(crate) unsafe fn pthread_rwlock_rdlock(real: RwlockFn, rwlock: *mut pthread_rwlock_t) -> c_int {
+    contended(
+        Kind::Read,
+        rwlock as usize,
+        || unsafe { libc::pthread_rwlock_tryrdlock(rwlock) },
+        || unsafe { writer(rwlock) },
+        || unsafe { real(rwlock) },
+    )
+}
+
+pub(crate) unsafe fn pthread_rwlock_wrlock(real: RwlockFn, rwlock: *mut pthread_rwlock_t) -> c_int {
+    contended(
+        Kind::Write,
+        rwlock as usize,
+        || unsafe { libc::pthread_rwlock_trywrlock(rwlock) },
+        || unsafe { writer(rwlock) },
+        || unsafe { real(rwlock) },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(all(target_os = "linux", target_env = "gnu", target_pointer_width = "64"))]
+    fn holders_are_read_from_the_lock() {
+        let mut mutex = libc::PTHREAD_MUTEX_INITIALIZER;
+        assert_eq!(unsafe { mutex_holder(&mutex) }, None);
+        assert_eq!(unsafe { libc::pthread_mutex_trylock(&mut mutex) }, 0);
+        let tid = unsafe { libc::gettid() };
+        assert_eq!(unsafe { mutex_holder(&mutex) }, Some(tid));
+        unsafe { libc::pthread_mutex_unlock(&mut mutex) };
+
+        let mut rwlock = libc::PTHREAD_RWLOCK_INITIALIZER;
+        assert_eq!(unsafe { libc::pthread_rwlock_tryrdlock(&mut rwlock) }, 0);
+        assert_eq!(unsafe { writer(&rwlock) }, None);
+        unsafe { libc::pthread_rwlock_unlock(&mut rwlock) };
+        assert_eq!(unsafe { libc::pthread_rwlock_trywrlock(&mut rwlock) }, 0);
+        assert_eq!(unsafe { writer(&rwlock) }, Some(tid));
+        unsafe { libc::pthread_rwlock_unlock(&mut rwlock) };
+    }
+}
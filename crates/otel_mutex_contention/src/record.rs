@@ -0,0 +1,70 @@
+// src/record.rs
+//
+// Recording a wait. One of the threshold or longer is added to the
+// histogram whatever the thread is doing, and as an event on its current
+// span when it is serving a trace; shorter ones aren't recorded at all, so
+// a busy but healthy lock costs a clock read per contended acquisition and
+// nothing more.
+
+use libc::c_int;
+use otel_posix_propagator_sys::{self as propagator, Attribute, Errno};
+use std::ffi::CStr;
+use std::time::Duration;
+
+/// What was waited for.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Kind {
+    /// A mutex, in `pthread_mutex_lock`.
+    Mutex,
+    /// A rwlock, to read.
+    Read,
+    /// A rwlock, to write.
+    Write,
+}
+
+impl Kind {
+    /// The event's name: the function waited in.
+    fn function(self) -> &'static CStr {
+        match self {
+            Kind::Mutex => c"pthread_mutex_lock",
+            Kind::Read => c"pthread_rwlock_rdlock",
+            Kind::Write => c"pthread_rwlock_wrlock",
+        }
+    }
+
+    /// The `otel_posix.lock.kind` it is recorded under.
+    fn as_cstr(self) -> &'static CStr {
+        match self {
+            Kind::Mutex => c"mutex",
+            Kind::Read => c"rwlock.read",
+            Kind::Write => c"rwlock.write",
+        }
+    }
+}
+
+/// Records a wait of `waited` for the lock at `address`, held by the thread `holder` when
+/// the wait began, if it was long enough.
+pub(crate) fn waited(kind: Kind, address: usize, holder: Option<c_int>, waited: Duration) {
+    if waited < crate::threshold() {
+        return;
+    }
+    let _errno = Errno::keep();
+    let seconds = waited.as_secs_f64();
+    let kind_attribute = Attribute::string(c"otel_posix.lock.kind", kind.as_cstr());
+    propagator::record_histogram(
+        c"otel_posix.lock.wait_time",
+        c"s",
+        seconds,
+        &[kind_attribute],
+    );
+    if propagator::serving() {
+        let mut attributes = vec![
+            Attribute::string(c"otel_posix.lock.kind", kind.as_cstr()),
+            Attribute::int(c"otel_posix.lock.address", address as i64),
+            Attribute::double(c"otel_posix.lock.wait_time", seconds),
+        ];
+        attributes.extend(holder.map(|tid| Attribute::int(c"otel_posix.lock.holder", tid.into())));
+        // stamped with when the wait began
+        propagator::add_event(kind.function(), propagator::began(waited), &attributes);
+    }
+}
@@ -0,0 +1,69 @@
+/* Holds a lock, a mutex or (with "rwlock") a rwlock to write, for 100 ms on a thread
+ * of its own, while another thread waits to take it, to read, and takes it again once
+ * it is free. Prints the holder's tid and the lock's address. */
+#define _GNU_SOURCE
+#include <pthread.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+#include "preload_testkit.h"
+
+static pthread_mutex_t mutex = PTHREAD_MUTEX_INITIALIZER;
+static pthread_rwlock_t rwlock = PTHREAD_RWLOCK_INITIALIZER;
+static int use_rwlock;
+static int held[2];
+
+static void lock(int exclusive) {
+    if (!use_rwlock)
+        pthread_mutex_lock(&mutex);
+    else if (exclusive)
+        pthread_rwlock_wrlock(&rwlock);
+    else
+        pthread_rwlock_rdlock(&rwlock);
+}
+
+static void unlock(void) {
+    if (use_rwlock)
+        pthread_rwlock_unlock(&rwlock);
+    else
+        pthread_mutex_unlock(&mutex);
+}
+
+static void *hold(void *arg) {
+    (void)arg;
+    lock(1);
+    pid_t tid = gettid();
+    if (write(held[1], &tid, sizeof tid) != sizeof tid)
+        return "write";
+    usleep(100 * 1000);
+    unlock();
+    return NULL;
+}
+
+static void *wait_for(void *arg) {
+    (void)arg;
+    lock(0);
+    unlock();
+    /* free, so not recorded */
+    lock(0);
+    unlock();
+    return NULL;
+}
+
+int main(int argc, char **argv) {
+    use_rwlock = argc > 1 && strcmp(argv[1], "rwlock") == 0;
+    if (testkit_start() != 0 || pipe(held) != 0)
+        return 1;
+    pthread_t holder, waiter;
+    pid_t tid;
+    void *failed;
+    if (pthread_create(&holder, NULL, hold, NULL) != 0 ||
+        read(held[0], &tid, sizeof tid) != sizeof tid ||
+        pthread_create(&waiter, NULL, wait_for, NULL) != 0 || pthread_join(waiter, NULL) != 0 ||
+        pthread_join(holder, &failed) != 0 || failed != NULL)
+        return 1;
+    printf("%d %ju\n", tid, (uintmax_t)(use_rwlock ? (void *)&rwlock : (void *)&mutex));
+    return 0;
+}
@@ -0,0 +1,58 @@
+#![cfg(target_os = "linux")]
+
+use preload_testkit::{Event, Fixture, Run, SPAN, TRACE};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HELD: f64 = 0.1;
+
+    /// Runs the fixture on `lock`, and checks the one wait recorded with `check`, given
+    /// the holder's tid and the lock's address.
+    fn contend(lock: &str, check: impl FnOnce(&Event, i64, i64)) {
+        let program = Fixture::c("tests/fixtures/contend.c").compile();
+        let out = Run::traced(&program, "otel_mutex_contention")
+            .arg(lock)
+            // spans for the threads, for the waits to land on
+            .env("OTEL_POSIX_PROP_MODE", "lifetime")
+            .env("OTEL_MUTEX_CONTENTION_THRESHOLD_US", "10000")
+            .output()
+            .success();
+        let [line] = &out.lines()[..] else {
+            panic!("one line: {}", out.stdout);
+        };
+        let (holder, address) = line.split_once(' ').unwrap();
+
+        let spans = out.spans();
+        let events: Vec<&Event> = spans
+            .children_of(TRACE, SPAN)
+            .flat_map(|s| &s.events)
+            .collect();
+        let [event] = &events[..] else {
+            panic!("one wait in\n{spans}");
+        };
+        check(event, holder.parse().unwrap(), address.parse().unwrap());
+    }
+
+    #[test]
+    fn mutex_waits_are_recorded() {
+        contend("mutex", |event, holder, address| {
+            assert_eq!(event.name, "pthread_mutex_lock");
+            assert_eq!(event.str("otel_posix.lock.kind"), Some("mutex"));
+            assert_eq!(event.int("otel_posix.lock.address"), Some(address));
+            assert_eq!(event.int("otel_posix.lock.holder"), Some(holder));
+            let waited = event.float("otel_posix.lock.wait_time").unwrap();
+            assert!(waited >= HELD / 2.0, "{waited}");
+        });
+    }
+
+    #[test]
+    fn rwlock_waits_are_recorded() {
+        contend("rwlock", |event, holder, _| {
+            assert_eq!(event.name, "pthread_rwlock_rdlock");
+            assert_eq!(event.str("otel_posix.lock.kind"), Some("rwlock.read"));
+            assert_eq!(event.int("otel_posix.lock.holder"), Some(holder));
+        });
+    }
+}
@@ -0,0 +1,303 @@
+// src/api.rs
+//
+// The bindings. The attributes are the propagator's `OtelPosixAttribute`,
+// tagged values borrowing their strings; a span is recorded once it is
+// over, under the span a traceparent names, with the kind and the id it
+// needs, and through the simplest of otel_posix_add_span, _kind and
+// _with_id that takes them.
+
+use crate::TRACEPARENT_LEN;
+use libc::{c_char, c_int, size_t};
+use std::ffi::{CStr, c_void};
+use std::marker::PhantomData;
+use std::sync::atomic::AtomicPtr;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+type CrashTraceparentFn = unsafe extern "C" fn(libc::pid_t) -> *const c_char;
+type AddSpanFn =
+    unsafe extern "C" fn(*const c_char, *const c_char, u64, u64, *const Attribute, size_t) -> c_int;
+type AddSpanKindFn = unsafe extern "C" fn(
+    *const c_char,
+    *const c_char,
+    c_int,
+    u64,
+    u64,
+    *const Attribute,
+    size_t,
+) -> c_int;
+type AddSpanWithIdFn = unsafe extern "C" fn(
+    *const c_char,
+    *const c_char,
+    *const c_char,
+    c_int,
+    u64,
+    u64,
+    *const Attribute,
+    size_t,
+) -> c_int;
+type AddEventFn = unsafe extern "C" fn(*const c_char, u64, *const Attribute, size_t) -> c_int;
+type RecordHistogramFn =
+    unsafe extern "C" fn(*const c_char, *const c_char, f64, *const Attribute, size_t) -> c_int;
+type EmitLogFn = unsafe extern "C" fn(
+    *const c_char,
+    c_int,
+    *const c_char,
+    u64,
+    *const Attribute,
+    size_t,
+) -> c_int;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+static CRASH_TRACEPARENT: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static ADD_SPAN: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static ADD_SPAN_KIND: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static ADD_SPAN_WITH_ID: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static ADD_EVENT: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static RECORD_HISTOGRAM: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static EMIT_LOG: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+// the propagator's OTEL_POSIX_VALUE_*
+const VALUE_STRING: c_int = 0;
+const VALUE_INT: c_int = 1;
+const VALUE_DOUBLE: c_int = 2;
+
+/// The propagator's `OtelPosixAttribute`, borrowing its string value.
+#[repr(C)]
+pub struct Attribute<'a> {
+    key: *const c_char,
+    kind: c_int,
+    string_value: *const c_char,
+    int_value: i64,
+    double_value: f64,
+    _value: PhantomData<&'a CStr>,
+}
+
+impl<'a> Attribute<'a> {
+    fn new(key: &'static CStr, kind: c_int) -> Self {
+        Attribute {
+            key: key.as_ptr(),
+            kind,
+            string_value: std::ptr::null(),
+            int_value: 0,
+            double_value: 0.0,
+            _value: PhantomData,
+        }
+    }
+
+    pub fn string(key: &'static CStr, value: &'a CStr) -> Self {
+        Attribute {
+            string_value: value.as_ptr(),
+            ..Attribute::new(key, VALUE_STRING)
+        }
+    }
+
+    pub fn int(key: &'static CStr, value: i64) -> Self {
+        Attribute {
+            int_value: value,
+            ..Attribute::new(key, VALUE_INT)
+        }
+    }
+
+    pub fn double(key: &'static CStr, value: f64) -> Self {
+        Attribute {
+            double_value: value,
+            ..Attribute::new(key, VALUE_DOUBLE)
+        }
+    }
+}
+
+/// A version 00 `traceparent`, NUL-terminated, kept by value so it can be read back
+/// after the thread's context has changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Traceparent([u8; TRACEPARENT_LEN + 1]);
+
+impl Traceparent {
+    /// `s` if it is as long as a version 00 `traceparent`; the propagator checks the rest.
+    pub fn new(s: &[u8]) -> Option<Self> {
+        if s.len() != TRACEPARENT_LEN || s.contains(&0) {
+            return None;
+        }
+        let mut copy = [0; TRACEPARENT_LEN + 1];
+        copy[..TRACEPARENT_LEN].copy_from_slice(s);
+        Some(Traceparent(copy))
+    }
+
+    /// The `traceparent`, without its NUL.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[..TRACEPARENT_LEN]
+    }
+
+    pub fn as_cstr(&self) -> &CStr {
+        CStr::from_bytes_with_nul(&self.0).expect("a traceparent has one NUL")
+    }
+}
+
+/// The `traceparent` of the trace the calling thread is serving, if the propagator has
+/// one for it.
+pub fn traceparent() -> Option<Traceparent> {
+    let tp = crash_traceparent()?;
+    // rewritten in place when the thread's context changes, so it may be cut short
+    Traceparent::new(unsafe { CStr::from_ptr(tp) }.to_bytes())
+}
+
+/// Whether the calling thread is serving a trace, as the propagator has it.
+pub fn serving() -> bool {
+    crash_traceparent().is_some()
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn crash_traceparent() -> Option<*const c_char> {
+    let f = ld_interpose::cached_global(&CRASH_TRACEPARENT, c"otel_posix_crash_traceparent")?;
+    let f = unsafe { std::mem::transmute::<*mut c_void, CrashTraceparentFn>(f) };
+    let tp = unsafe { f(libc::gettid()) };
+    (!tp.is_null()).then_some(tp)
+}
+
+// the crash table is keyed by kernel thread id, and only kept where there is one
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn crash_traceparent() -> Option<*const c_char> {
+    None
+}
+
+/// The propagator's `OTEL_POSIX_SPAN_KIND_*`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Kind {
+    #[default]
+    Internal = 0,
+    Client = 1,
+    Server = 2,
+    Producer = 3,
+    Consumer = 4,
+}
+
+/// A span that is over, to be recorded under the span its parent `traceparent` names.
+#[derive(Clone, Copy, Debug)]
+pub struct Span<'a> {
+    parent: &'a CStr,
+    name: &'a CStr,
+    kind: Kind,
+    id: Option<&'a CStr>,
+    start: u64,
+    end: u64,
+}
+
+impl<'a> Span<'a> {
+    /// The internal span `name` under `parent`, which ran from `start` to `end`.
+    pub fn new(parent: &'a CStr, name: &'a CStr, start: u64, end: u64) -> Self {
+        Span {
+            parent,
+            name,
+            kind: Kind::Internal,
+            id: None,
+            start,
+            end,
+        }
+    }
+
+    pub fn kind(self, kind: Kind) -> Self {
+        Span { kind, ..self }
+    }
+
+    /// Gives the span the id `id`, 16 hex digits, instead of a new one.
+    pub fn id(self, id: &'a CStr) -> Self {
+        Span {
+            id: Some(id),
+            ..self
+        }
+    }
+
+    /// Records the span, with `attributes`.
+    pub fn record(&self, attributes: &[Attribute]) {
+        let (parent, name, kind) = (self.parent.as_ptr(), self.name.as_ptr(), self.kind as c_int);
+        let (start, end, count) = (self.start, self.end, attributes.len());
+        let attributes = attributes.as_ptr();
+        if let Some(id) = self.id {
+            if let Some(f) =
+                ld_interpose::cached_global(&ADD_SPAN_WITH_ID, c"otel_posix_add_span_with_id")
+            {
+                let f = unsafe { std::mem::transmute::<*mut c_void, AddSpanWithIdFn>(f) };
+                let id = id.as_ptr();
+                unsafe { f(parent, id, name, kind, start, end, attributes, count) };
+            }
+        } else if self.kind != Kind::Internal {
+            if let Some(f) =
+                ld_interpose::cached_global(&ADD_SPAN_KIND, c"otel_posix_add_span_kind")
+            {
+                let f = unsafe { std::mem::transmute::<*mut c_void, AddSpanKindFn>(f) };
+                unsafe { f(parent, name, kind, start, end, attributes, count) };
+            }
+        } else if let Some(f) = ld_interpose::cached_global(&ADD_SPAN, c"otel_posix_add_span") {
+            let f = unsafe { std::mem::transmute::<*mut c_void, AddSpanFn>(f) };
+            unsafe { f(parent, name, start, end, attributes, count) };
+        }
+    }
+}
+
+/// Adds an event at `time` to the calling thread's current span.
+pub fn add_event(name: &CStr, time: u64, attributes: &[Attribute]) {
+    if let Some(f) = ld_interpose::cached_global(&ADD_EVENT, c"otel_posix_add_event") {
+        let f = unsafe { std::mem::transmute::<*mut c_void, AddEventFn>(f) };
+        unsafe { f(name.as_ptr(), time, attributes.as_ptr(), attributes.len()) };
+    }
+}
+
+/// Records `value`, in `unit`, in the histogram `name`.
+pub fn record_histogram(name: &CStr, unit: &CStr, value: f64, attributes: &[Attribute]) {
+    if let Some(f) = ld_interpose::cached_global(&RECORD_HISTOGRAM, c"otel_posix_record_histogram")
+    {
+        let f = unsafe { std::mem::transmute::<*mut c_void, RecordHistogramFn>(f) };
+        let (name, unit, count) = (name.as_ptr(), unit.as_ptr(), attributes.len());
+        unsafe { f(name, unit, value, attributes.as_ptr(), count) };
+    }
+}
+
+/// Emits a record of `body`, written at `time`, with the span `parent` names, or without
+/// a trace. `severity` is an OTel severity number, or 0 for none.
+pub fn emit_log(
+    parent: Option<&CStr>,
+    severity: c_int,
+    body: &CStr,
+    time: u64,
+    attributes: &[Attribute],
+) {
+    if let Some(f) = ld_interpose::cached_global(&EMIT_LOG, c"otel_posix_emit_log") {
+        let f = unsafe { std::mem::transmute::<*mut c_void, EmitLogFn>(f) };
+        let parent = parent.map_or(std::ptr::null(), CStr::as_ptr);
+        let count = attributes.len();
+        unsafe {
+            f(
+                parent,
+                severity,
+                body.as_ptr(),
+                time,
+                attributes.as_ptr(),
+                count,
+            )
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TP: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn traceparents_are_kept_whole() {
+        let tp = Traceparent::new(TP.as_bytes()).unwrap();
+        assert_eq!(tp.as_bytes(), TP.as_bytes());
+        assert_eq!(tp.as_cstr().to_bytes(), TP.as_bytes());
+        // one rewritten as it was read
+        assert_eq!(Traceparent::new(&TP.as_bytes()[..30]), None);
+        let mut cut = TP.as_bytes().to_vec();
+        cut[30] = 0;
+        assert_eq!(Traceparent::new(&cut), None);
+    }
+
+    #[test]
+    fn nothing_is_served_without_the_propagator() {
+        assert_eq!(traceparent(), None);
+        assert!(!serving());
+    }
+}
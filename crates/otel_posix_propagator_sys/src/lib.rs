@@ -0,0 +1,28 @@
+// src/lib.rs
+//
+// The propagator's C API, for the workspace's other preload shims to
+// record through. They are separate libraries with no context, exporter or
+// provider of their own, preloaded alongside the propagator: the trace a
+// thread is serving comes from the propagator's crash table, cheap and
+// safe enough to read on every call, and spans, events, histogram values
+// and log records go through its `otel_posix_*` functions (api.rs). Each
+// is looked up once, in the objects loaded globally; without the
+// propagator preloaded, nothing is recorded and the shims only forward
+// their calls.
+//
+// A shim's recording follows the same few steps whatever it records
+// (record.rs): it marks the thread busy, so what the propagator does while
+// recording isn't recorded in turn, keeps errno for the caller, and stamps
+// what it records with the wall clock, in nanoseconds since the Unix epoch,
+// as the C API takes it.
+
+mod api;
+mod record;
+
+pub use api::{
+    Attribute, Kind, Span, Traceparent, add_event, emit_log, record_histogram, serving, traceparent,
+};
+pub use record::{Busy, Errno, began, enter, now};
+
+/// The length of a version 00 `traceparent`: `"00-" + 32 + "-" + 16 + "-" + 2`.
+pub const TRACEPARENT_LEN: usize = 55;
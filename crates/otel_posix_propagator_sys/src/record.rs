@@ -0,0 +1,110 @@
+// src/record.rs
+//
+// What recording takes besides the C API. Recording may itself make the
+// calls a shim interposes (an exporter writing its file, connecting to
+// its collector, taking a lock), and the ones the propagator makes while
+// it records aren't recorded: the thread is marked busy until it is done.
+// errno is kept for the caller, who may be about to read it.
+
+use libc::c_int;
+use std::cell::Cell;
+use std::time::{Duration, SystemTime};
+
+thread_local! {
+    static BUSY: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the thread busy until dropped.
+pub struct Busy(());
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        BUSY.set(false);
+    }
+}
+
+/// Marks the thread busy, or returns `None` if it already is: the call is being made
+/// while another is recorded.
+pub fn enter() -> Option<Busy> {
+    if BUSY.replace(true) {
+        None
+    } else {
+        Some(Busy(()))
+    }
+}
+
+/// The calling thread's errno, put back as it was when dropped.
+pub struct Errno(c_int);
+
+impl Errno {
+    pub fn keep() -> Self {
+        Errno(unsafe { *errno_location() })
+    }
+
+    /// The errno kept.
+    pub fn get(&self) -> c_int {
+        self.0
+    }
+}
+
+impl Drop for Errno {
+    fn drop(&mut self) {
+        unsafe { *errno_location() = self.0 };
+    }
+}
+
+/// Where the calling thread's errno is kept, which each libc names differently.
+fn errno_location() -> *mut c_int {
+    #[cfg(target_os = "linux")]
+    return unsafe { libc::__errno_location() };
+    #[cfg(target_os = "android")]
+    return unsafe { libc::__errno() };
+    #[cfg(any(
+        target_vendor = "apple",
+        target_os = "freebsd",
+        target_os = "dragonfly"
+    ))]
+    return unsafe { libc::__error() };
+}
+
+/// Now, in nanoseconds since the Unix epoch.
+pub fn now() -> u64 {
+    nanos(SystemTime::now())
+}
+
+/// When a wait that took `waited` and is just over began.
+pub fn began(waited: Duration) -> u64 {
+    nanos(SystemTime::now() - waited)
+}
+
+fn nanos(t: SystemTime) -> u64 {
+    t.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |t| t.as_nanos() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_call_is_recorded_at_a_time() {
+        let busy = enter();
+        assert!(busy.is_some());
+        assert!(enter().is_none());
+        // refusing doesn't end the call in progress
+        assert!(enter().is_none());
+        drop(busy);
+        assert!(enter().is_some());
+    }
+
+    #[test]
+    fn errno_is_put_back() {
+        unsafe { *errno_location() = libc::EINTR };
+        {
+            let errno = Errno::keep();
+            unsafe { *errno_location() = libc::ENOENT };
+            assert_eq!(errno.get(), libc::EINTR);
+        }
+        assert_eq!(unsafe { *errno_location() }, libc::EINTR);
+    }
+}
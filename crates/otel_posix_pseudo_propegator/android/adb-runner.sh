@@ -0,0 +1,19 @@
+#!/bin/sh
+# Cargo runner for Android targets: pushes the test binary, and the cdylib
+# built next to it, to the device or emulator adb talks to and runs it there.
+#
+#   export CARGO_TARGET_X86_64_LINUX_ANDROID_RUNNER=$PWD/android/adb-runner.sh
+#   cargo test --target x86_64-linux-android
+set -eu
+
+exe=$1
+shift
+dir=/data/local/tmp/otel_posix_pseudo_propegator
+
+adb shell mkdir -p "$dir" >/dev/null
+adb push "$exe" "$dir/" >/dev/null
+for so in "$(dirname "$exe")"/*.so; do
+    [ -e "$so" ] && adb push "$so" "$dir/" >/dev/null
+done
+# adb shell passes the remote exit status back since Android 7
+exec adb shell "cd $dir && LD_LIBRARY_PATH=$dir ./$(basename "$exe") $*"
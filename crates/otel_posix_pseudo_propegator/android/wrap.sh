@@ -0,0 +1,7 @@
+#!/system/bin/sh
+# wrap.sh for apps: package it as lib/<abi>/wrap.sh in the APK, next to
+# libotel_posix_pseudo_propegator.so. Android only runs it for debuggable apps
+# (8.1 and later).
+HERE=$(cd "$(dirname "$0")" && pwd)
+export LD_PRELOAD="$HERE/libotel_posix_pseudo_propegator.so${LD_PRELOAD:+:$LD_PRELOAD}"
+exec "$@"
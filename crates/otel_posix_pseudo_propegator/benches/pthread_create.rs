@@ -0,0 +1,122 @@
+// benches/pthread_create.rs
+//
+// What the shim adds to thread creation. Every group compares libc's own
+// pthread_create (looked up past the interposer, so nothing is wrapped)
+// with the interposed one, with and without an active span. The parallel
+// group has several creators spawning at once, the way a thread pool
+// refills itself, which is where the launcher free list earns its keep.
+//
+//     cargo bench --bench pthread_create
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use opentelemetry::trace::{TraceContextExt, Tracer, TracerProvider};
+use opentelemetry::{Context, ContextGuard};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use std::ffi::c_void;
+use std::hint::black_box;
+
+// keeps the crate, and with it the pthread_create interposer, linked in
+use otel_posix_pseudo_propegator as _;
+
+type CreateFn = unsafe extern "C" fn(
+    *mut libc::pthread_t,
+    *const libc::pthread_attr_t,
+    extern "C" fn(*mut c_void) -> *mut c_void,
+    *mut c_void,
+) -> i32;
+
+extern "C" fn noop(arg: *mut c_void) -> *mut c_void {
+    black_box(arg)
+}
+
+/// libc's pthread_create, bypassing the interposer linked into this binary.
+fn libc_pthread_create() -> CreateFn {
+    let sym = unsafe { libc::dlsym(libc::RTLD_NEXT, c"pthread_create".as_ptr()) };
+    assert!(!sym.is_null());
+    unsafe { std::mem::transmute::<*mut c_void, CreateFn>(sym) }
+}
+
+/// Creates `n` threads through `create`, then joins them all.
+fn spawn_batch(create: CreateFn, n: usize) {
+    let mut tids = vec![0 as libc::pthread_t; n];
+    for tid in &mut tids {
+        let rc = unsafe { create(tid, std::ptr::null(), noop, std::ptr::null_mut()) };
+        assert_eq!(rc, 0);
+    }
+    for tid in tids {
+        unsafe { libc::pthread_join(tid, std::ptr::null_mut()) };
+    }
+}
+
+fn attach_span(tracer: &opentelemetry_sdk::trace::SdkTracer) -> ContextGuard {
+    Context::current_with_span(tracer.start("bench")).attach()
+}
+
+fn variants() -> [(&'static str, CreateFn, bool); 3] {
+    [
+        ("libc", libc_pthread_create(), false),
+        ("shim_no_span", libc::pthread_create, false),
+        ("shim_active_span", libc::pthread_create, true),
+    ]
+}
+
+fn bench_latency(c: &mut Criterion) {
+    let tracer = SdkTracerProvider::builder().build().tracer("bench");
+    let mut group = c.benchmark_group("create_join");
+    for (name, create, active) in variants() {
+        group.bench_function(name, |b| {
+            let _span = active.then(|| attach_span(&tracer));
+            b.iter(|| spawn_batch(create, 1));
+        });
+    }
+    group.finish();
+}
+
+fn bench_throughput(c: &mut Criterion) {
+    let tracer = SdkTracerProvider::builder().build().tracer("bench");
+    let mut group = c.benchmark_group("create_batch");
+    for batch in [16, 64] {
+        group.throughput(Throughput::Elements(batch as u64));
+        for (name, create, active) in variants() {
+            group.bench_with_input(BenchmarkId::new(name, batch), &batch, |b, &n| {
+                let _span = active.then(|| attach_span(&tracer));
+                b.iter(|| spawn_batch(create, n));
+            });
+        }
+    }
+    group.finish();
+}
+
+/// Creates and joins `n` threads from each of `creators` threads at once.
+fn spawn_parallel(
+    create: CreateFn,
+    tracer: Option<&opentelemetry_sdk::trace::SdkTracer>,
+    creators: usize,
+    n: usize,
+) {
+    std::thread::scope(|s| {
+        for _ in 0..creators {
+            s.spawn(|| {
+                let _span = tracer.map(attach_span);
+                spawn_batch(create, n);
+            });
+        }
+    });
+}
+
+fn bench_parallel(c: &mut Criterion) {
+    let tracer = SdkTracerProvider::builder().build().tracer("bench");
+    let (creators, batch) = (4, 16);
+    let mut group = c.benchmark_group("create_parallel");
+    group.throughput(Throughput::Elements((creators * batch) as u64));
+    for (name, create, active) in variants() {
+        group.bench_function(name, |b| {
+            let tracer = active.then_some(&tracer);
+            b.iter(|| spawn_parallel(create, tracer, creators, batch));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_latency, bench_throughput, bench_parallel);
+criterion_main!(benches);
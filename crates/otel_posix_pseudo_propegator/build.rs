@@ -1,8 +1,78 @@
 // build.rs
 fn main() {
     // Note: CARGO_CFG_TARGET_OS is set by Cargo for the current compile target
-    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("linux") {
-        // emit a linker-arg only on Linux
+    let linux = std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("linux");
+    if linux && std::env::var_os("CARGO_FEATURE_LINKER_WRAP").is_some() {
+        // route this package's own binaries and tests through __wrap_pthread_create;
+        // downstream binaries pass the same flag when linking us statically
         println!("cargo:rustc-link-arg=-Wl,-wrap,pthread_create");
     }
+
+    sanitize_cfg();
+    hooks_cfg();
+
+    // the fixture tests compile C programs for the same target with the cc crate
+    println!(
+        "cargo:rustc-env=OTEL_POSIX_TEST_TARGET={}",
+        std::env::var("TARGET").unwrap()
+    );
+
+    generate_header();
+}
+
+// cfg(sanitize) is nightly-only, so builds with -Zsanitizer=... get otel_posix_sanitize (and
+// otel_posix_sanitize="address" and so on) for the tests that can't run instrumented
+fn sanitize_cfg() {
+    println!(
+        "cargo:rustc-check-cfg=cfg(otel_posix_sanitize, values(none(), \"address\", \"thread\", \"memory\", \"hwaddress\", \"leak\"))"
+    );
+    let rustflags = std::env::var("CARGO_ENCODED_RUSTFLAGS").unwrap_or_default();
+    // either -Zsanitizer=x or -Z sanitizer=x
+    let flags: Vec<&str> = rustflags.split('\x1f').collect();
+    let sanitizers = flags.iter().enumerate().filter_map(|(i, flag)| {
+        flag.strip_prefix("-Zsanitizer=").or_else(|| {
+            (*flag == "-Z")
+                .then(|| flags.get(i + 1)?.strip_prefix("sanitizer="))
+                .flatten()
+        })
+    });
+    for list in sanitizers {
+        println!("cargo:rustc-cfg=otel_posix_sanitize");
+        for sanitizer in list.split(',') {
+            println!("cargo:rustc-cfg=otel_posix_sanitize=\"{sanitizer}\"");
+        }
+    }
+}
+
+// otel_posix_hooks when anything besides pthread_create is interposed, which is when the
+// lookups in resolve.rs are used; the hook features are too many to list at every use
+fn hooks_cfg() {
+    println!("cargo:rustc-check-cfg=cfg(otel_posix_hooks)");
+    let hooked = std::env::vars().any(|(key, _)| {
+        key.starts_with("CARGO_FEATURE_HOOK_")
+            || key == "CARGO_FEATURE_HTTP_INJECT"
+            || key == "CARGO_FEATURE_MQUEUE_INJECT"
+    });
+    if hooked {
+        println!("cargo:rustc-cfg=otel_posix_hooks");
+    }
+}
+
+// regenerate include/otel_posix_pseudo_propegator.h from the exported C API
+fn generate_header() {
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=src");
+
+    let config = cbindgen::Config::from_file(format!("{crate_dir}/cbindgen.toml"))
+        .expect("failed to read cbindgen.toml");
+    match cbindgen::generate_with_config(&crate_dir, config) {
+        Ok(bindings) => {
+            bindings.write_to_file(format!(
+                "{crate_dir}/include/otel_posix_pseudo_propegator.h"
+            ));
+        }
+        // don't fail the build over the header, the library itself is fine
+        Err(e) => println!("cargo:warning=failed to generate C header: {e}"),
+    }
 }
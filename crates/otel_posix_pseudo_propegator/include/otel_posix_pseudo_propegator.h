@@ -0,0 +1,391 @@
+#ifndef OTEL_POSIX_PSEUDO_PROPEGATOR_H
+#define OTEL_POSIX_PSEUDO_PROPEGATOR_H
+
+/* Generated by cbindgen from src/, do not edit by hand. */
+
+#include <stddef.h>
+#include <stdint.h>
+#include <sys/types.h>
+
+/**
+ * Number of slots in [`otel_posix_crash_traceparents`].
+ */
+#define OTEL_POSIX_CRASH_SLOTS 1024
+
+/**
+ * [`OtelPosixAttribute::kind`] of a string, in `string_value`.
+ */
+#define OTEL_POSIX_VALUE_STRING 0
+
+/**
+ * [`OtelPosixAttribute::kind`] of an integer, in `int_value`.
+ */
+#define OTEL_POSIX_VALUE_INT 1
+
+/**
+ * [`OtelPosixAttribute::kind`] of a floating-point number, in `double_value`.
+ */
+#define OTEL_POSIX_VALUE_DOUBLE 2
+
+/**
+ * [`OtelPosixAttribute::kind`] of a boolean, in `int_value` (non-zero for true).
+ */
+#define OTEL_POSIX_VALUE_BOOL 3
+
+/**
+ * A span kind for [`otel_posix_add_span_kind`]: internal.
+ */
+#define OTEL_POSIX_SPAN_KIND_INTERNAL 0
+
+/**
+ * A span kind for [`otel_posix_add_span_kind`]: the client side of a call.
+ */
+#define OTEL_POSIX_SPAN_KIND_CLIENT 1
+
+/**
+ * A span kind for [`otel_posix_add_span_kind`]: the server side of a call.
+ */
+#define OTEL_POSIX_SPAN_KIND_SERVER 2
+
+/**
+ * A span kind for [`otel_posix_add_span_kind`]: sending a message.
+ */
+#define OTEL_POSIX_SPAN_KIND_PRODUCER 3
+
+/**
+ * A span kind for [`otel_posix_add_span_kind`]: receiving a message.
+ */
+#define OTEL_POSIX_SPAN_KIND_CONSUMER 4
+
+/**
+ * C capture callback: runs in the creating thread and returns the state to carry over,
+ * or NULL for none.
+ */
+typedef void *(*OtelPosixCaptureFn)(void *user);
+
+/**
+ * C restore/release callback, called with the captured state.
+ */
+typedef void (*OtelPosixStateFn)(void *state, void *user);
+
+/**
+ * One attribute of a span recorded with [`otel_posix_add_span`].
+ */
+typedef struct OtelPosixAttribute {
+  /**
+   * The attribute's key, NUL-terminated.
+   */
+  const char *key;
+  /**
+   * Which of the value fields holds the value: one of the `OTEL_POSIX_VALUE_*`.
+   */
+  int kind;
+  /**
+   * A NUL-terminated string, for `OTEL_POSIX_VALUE_STRING`.
+   */
+  const char *string_value;
+  /**
+   * An integer, for `OTEL_POSIX_VALUE_INT` and `OTEL_POSIX_VALUE_BOOL`.
+   */
+  int64_t int_value;
+  /**
+   * A floating-point number, for `OTEL_POSIX_VALUE_DOUBLE`.
+   */
+  double double_value;
+} OtelPosixAttribute;
+
+/**
+ * One thread's entry in [`otel_posix_crash_traceparents`].
+ */
+typedef struct OtelPosixCrashSlot {
+  /**
+   * The thread's tid, or 0 for a free slot.
+   */
+  pid_t tid;
+  /**
+   * The thread's `traceparent`, NUL-terminated; empty while it has none.
+   */
+  char traceparent[56];
+} OtelPosixCrashSlot;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * The trace each live thread is serving, by tid, for core dumps and crash handlers.
+ *
+ * Written by the shim only; read it from a debugger or with
+ * [`otel_posix_crash_traceparent`].
+ */
+extern struct OtelPosixCrashSlot otel_posix_crash_traceparents[OTEL_POSIX_CRASH_SLOTS];
+
+/**
+ * Takes over a process this library was loaded into late: points the bindings every
+ * loaded object made to functions the library interposes at the library's own.
+ *
+ * `otel-preload attach` calls it once it has loaded the library into a running process,
+ * and a host that dlopens the library itself can too; there's no need for it under
+ * `LD_PRELOAD`. Objects loaded afterwards keep libc's bindings until it is called again.
+ * Returns the number of bindings changed, or -1 if the library can't find itself.
+ */
+int otel_posix_attach(void);
+
+/**
+ * The `traceparent` of the trace thread `tid` is serving, as recorded in
+ * [`otel_posix_crash_traceparents`], or NULL if it has none.
+ *
+ * Async-signal-safe: it neither locks nor allocates, so a crash handler can call it for
+ * the crashing thread. The string stays valid until that thread's context next changes.
+ */
+const char *otel_posix_crash_traceparent(pid_t tid);
+
+/**
+ * Sets the deadline of the calling thread and the threads it starts from now on, as an
+ * absolute `CLOCK_MONOTONIC` time in nanoseconds.
+ *
+ * Replaces the deadline set by the previous call on the same thread; 0 clears it. A
+ * deadline the thread was given by its creator still applies, and is kept when it's
+ * the earlier one. Returns the deadline now in effect, or 0 for none.
+ */
+uint64_t otel_posix_set_deadline(uint64_t deadline_ns);
+
+/**
+ * The calling thread's deadline, as an absolute `CLOCK_MONOTONIC` time in nanoseconds,
+ * or 0 if it has none.
+ */
+uint64_t otel_posix_get_deadline(void);
+
+/**
+ * Nanoseconds left until the calling thread's deadline, negative once it has passed,
+ * or `INT64_MAX` if it has none.
+ */
+int64_t otel_posix_deadline_remaining(void);
+
+/**
+ * Writes the current thread's W3C `traceparent` into `buf` as a NUL-terminated string.
+ *
+ * Returns the length of the traceparent (excluding the NUL), or 0 if there is no valid
+ * span context on this thread. Like `snprintf`, nothing is written when `len` is too
+ * small, and the return value tells the caller how many bytes (minus one) are needed.
+ *
+ * # Safety
+ *
+ * `buf` must be null or valid for writes of `len` bytes.
+ */
+int otel_posix_get_traceparent(char *buf, size_t len);
+
+/**
+ * Writes the W3C `traceparent` of the trace OS thread `tid` is serving into `buf`, like
+ * [`otel_posix_get_traceparent`] does for the calling thread.
+ *
+ * Only contexts the shim established are known: a wrapped thread's inherited context,
+ * one seeded with [`otel_posix_set_traceparent`], or a coroutine's after a switch.
+ * Returns 0 for threads without one, including threads that have exited.
+ *
+ * # Safety
+ *
+ * `buf` must be null or valid for writes of `len` bytes.
+ */
+int otel_posix_context_for_tid(pid_t tid, char *buf, size_t len);
+
+/**
+ * The `pthread_create` this library forwards to: libc's, or that of another interposer
+ * preloaded after it, which then passes the call on itself.
+ *
+ * Interposers loaded before this one can call it to reach the rest of the chain without
+ * the context being carried over. Returns NULL if it hasn't been found (yet).
+ */
+void *otel_posix_next_pthread_create(void);
+
+/**
+ * Seeds the current thread's context from a W3C `traceparent` string.
+ *
+ * The seeded context stays attached until the next call on the same thread; passing
+ * NULL detaches it again. Returns 0 on success and -1 if the string is not a valid
+ * traceparent, in which case the current context is left untouched.
+ *
+ * # Safety
+ *
+ * `traceparent` must be null or point to a NUL-terminated string.
+ */
+int otel_posix_set_traceparent(const char *traceparent);
+
+/**
+ * Registers C callbacks run for every thread the shim starts.
+ *
+ * `capture(user)` runs in the creating thread and returns the state to carry over
+ * (NULL for none). In the new thread, `restore(state, user)` runs before the start
+ * routine and `release(state, user)` after it returns; if the thread couldn't be
+ * created, only `release` runs, in the creating thread. `restore` and `release` may
+ * be NULL. Returns 0, or -1 if `capture` is NULL.
+ *
+ * # Safety
+ *
+ * The callbacks must be safe to call from any thread with `user` for the rest of the
+ * process lifetime, and must not unwind.
+ */
+int otel_posix_register_hook(OtelPosixCaptureFn capture, OtelPosixStateFn restore, OtelPosixStateFn release, void *user);
+
+/**
+ * Waits for the exporters the library installs at load time, from a thread of its own,
+ * to be in place, for at most `timeout_ms` milliseconds. A program that starts spans
+ * right away calls it first, so that none is started under a provider about to be
+ * replaced, and dropped when it ends.
+ *
+ * Returns 0 once the install is done, at once if there is none running, or -1 if it is
+ * still running at the timeout.
+ */
+int otel_posix_wait_installed(uint64_t timeout_ms);
+
+/**
+ * Replaces the tracer provider with a new OTLP one, which exports to `endpoint` if it
+ * isn't NULL, and otherwise to wherever the `OTEL_EXPORTER_OTLP_*` variables say now.
+ *
+ * The provider replaced, the one the library installed at load time or by an earlier
+ * call, is shut down: what it still holds is exported first, for at most
+ * `OTEL_POSIX_PROP_EXIT_TIMEOUT`. The new one is flushed at exit in its place. An
+ * `endpoint` is the full URL spans are posted to, as in
+ * `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`. Returns 0, or -1 if the exporter couldn't be
+ * built, in which case the provider in place is kept.
+ *
+ * Not to be called from a library constructor: building the exporter starts threads
+ * and waits for them.
+ *
+ * # Safety
+ *
+ * `endpoint` must be null or point to a NUL-terminated string.
+ */
+int otel_posix_install_provider(const char *endpoint);
+
+/**
+ * Shuts the installed tracer, meter and logger providers down, exporting what they still hold
+ * for at most `OTEL_POSIX_PROP_EXIT_TIMEOUT`, and leaves a no-op tracer provider in
+ * their place, so nothing is recorded from then on. Nothing is flushed at exit
+ * afterwards; [`otel_posix_install_provider`] starts exporting again.
+ *
+ * Returns 0, or -1 if the export didn't finish in time.
+ */
+int otel_posix_shutdown(void);
+
+/**
+ * Records `value` in the histogram named `name`, with the `count` attributes at
+ * `attributes`. The histogram is created on first use, in `unit` (which may be NULL).
+ *
+ * Returns 0, or -1 if `name` is NULL.
+ *
+ * # Safety
+ *
+ * `name` and `unit` must be null or point to NUL-terminated strings, and `attributes`
+ * must be null or point to `count` attributes whose strings are NUL-terminated or null.
+ */
+int otel_posix_record_histogram(const char *name, const char *unit, double value, const struct OtelPosixAttribute *attributes, size_t count);
+
+/**
+ * Records a log record with the message `body`, written at `time`, in nanoseconds since
+ * the Unix epoch, with the `count` attributes at `attributes`. `severity` is one of the
+ * OTel data model's severity numbers, from 1 (TRACE) to 24 (FATAL4), or 0 for none. The
+ * record goes with the span the traceparent `parent` names, or the calling thread's
+ * current span when `parent` is NULL.
+ *
+ * Returns 0, or -1 if `body` is NULL, `parent` isn't a valid traceparent or no logger
+ * provider is installed.
+ *
+ * # Safety
+ *
+ * `parent` and `body` must be null or point to NUL-terminated strings, and `attributes`
+ * must be null or point to `count` attributes whose strings are NUL-terminated or null.
+ */
+int otel_posix_emit_log(const char *parent, int severity_number, const char *body, uint64_t time, const struct OtelPosixAttribute *attributes, size_t count);
+
+/**
+ * Reports who created the calling thread, in attribute-only mode: the creator's OS thread
+ * id goes to `tid` and the creation time, in nanoseconds since the Unix epoch, to
+ * `created_ns` (either may be NULL), and the creating span's W3C `traceparent` into
+ * `buf` like [`otel_posix_get_traceparent`](crate::ffi::otel_posix_get_traceparent).
+ *
+ * Returns the length of the traceparent, or 0 if the thread wasn't created through the
+ * shim in attribute-only mode, in which case nothing is written.
+ *
+ * # Safety
+ *
+ * `tid` and `created_ns` must each be null or valid for writes, and `buf` null or valid
+ * for writes of `len` bytes.
+ */
+int otel_posix_thread_origin(pid_t *tid, uint64_t *created_ns, char *buf, size_t len);
+
+/**
+ * Records a span named `name` that ran from `start` to `end`, in nanoseconds since the
+ * Unix epoch, with the `count` attributes at `attributes`. Its parent is the span the
+ * traceparent `parent` names, or the calling thread's current span when `parent` is NULL.
+ *
+ * Returns 0, or -1 if `name` is NULL or `parent` isn't a valid traceparent.
+ *
+ * # Safety
+ *
+ * `name` must be null or point to a NUL-terminated string, as must `parent`, and
+ * `attributes` must be null or point to `count` attributes whose strings are
+ * NUL-terminated or null.
+ */
+int otel_posix_add_span(const char *parent, const char *name, uint64_t start, uint64_t end, const struct OtelPosixAttribute *attributes, size_t count);
+
+/**
+ * Records a span like [`otel_posix_add_span`], of the kind `kind`: one of the
+ * `OTEL_POSIX_SPAN_KIND_*`.
+ *
+ * Returns 0, or -1 if `name` is NULL or `parent` isn't a valid traceparent.
+ *
+ * # Safety
+ *
+ * As for [`otel_posix_add_span`].
+ */
+int otel_posix_add_span_kind(const char *parent, const char *name, int kind, uint64_t start, uint64_t end, const struct OtelPosixAttribute *attributes, size_t count);
+
+/**
+ * Records a span like [`otel_posix_add_span_kind`] whose id is `span_id`, 16 hex
+ * digits, or a new one when `span_id` is NULL.
+ *
+ * Returns 0, or -1 if `name` is NULL, `parent` isn't a valid traceparent or `span_id`
+ * isn't a valid span id.
+ *
+ * # Safety
+ *
+ * As for [`otel_posix_add_span`], and `span_id` must be null or point to a
+ * NUL-terminated string.
+ */
+int otel_posix_add_span_with_id(const char *parent, const char *span_id, const char *name, int kind, uint64_t start, uint64_t end, const struct OtelPosixAttribute *attributes, size_t count);
+
+/**
+ * Adds an event named `name` at `time`, in nanoseconds since the Unix epoch, with the
+ * `count` attributes at `attributes`, to the calling thread's current span.
+ *
+ * Returns 0, or -1 if `name` is NULL or no span is recording on this thread.
+ *
+ * # Safety
+ *
+ * `name` must be null or point to a NUL-terminated string, and `attributes` must be
+ * null or point to `count` attributes whose strings are NUL-terminated or null.
+ */
+int otel_posix_add_event(const char *name, uint64_t time, const struct OtelPosixAttribute *attributes, size_t count);
+
+/**
+ * Writes a JSON object describing the shim's state into `buf` as a NUL-terminated string:
+ * its version and linkage, whether wrapping is enabled, the mode and hook switches, where
+ * the configuration came from, how `pthread_create` binds (and how many libraries loaded
+ * with RTLD_DEEPBIND or dlmopen were redirected, or couldn't be), the real symbols found
+ * so far, and the interposition counters.
+ *
+ * Returns the length of the JSON (excluding the NUL). Like `snprintf`, nothing is
+ * written when `len` is too small, so callers can pass NULL first to size the buffer.
+ *
+ * # Safety
+ *
+ * `buf` must be null or valid for writes of `len` bytes.
+ */
+int otel_posix_prop_status(char *buf, size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* OTEL_POSIX_PSEUDO_PROPEGATOR_H */
@@ -0,0 +1,272 @@
+// src/attach.rs
+//
+// Late attachment to a process that is already running, for `otel-preload
+// attach` (see src/bin/otel-preload/inject.rs), which dlopens the library into it
+// over ptrace. Loaded that way, the library comes last in symbol lookup
+// order, and everything the process bound before (pthread_create through
+// its PLT, most of all) still points at libc. otel_posix_attach() walks the
+// loaded objects' relocations and points every binding to a function this
+// library exports at its own definition instead, which is what LD_PRELOAD
+// would have done at startup. Bindings in RELRO are made writable for the
+// write and read-only again afterwards.
+//
+// Threads already running stay as they are; threads created from now on
+// are wrapped, and the exec and exit hooks apply, like under LD_PRELOAD.
+
+use libc::{Elf64_Phdr, Elf64_Sym, c_int, c_void, dl_phdr_info, size_t};
+use std::ffi::CStr;
+
+// not in the libc crate
+const RTLD_DL_LINKMAP: c_int = 2;
+const DT_NULL: i64 = 0;
+const DT_PLTRELSZ: i64 = 2;
+const DT_STRTAB: i64 = 5;
+const DT_SYMTAB: i64 = 6;
+const DT_RELA: i64 = 7;
+const DT_RELASZ: i64 = 8;
+const DT_JMPREL: i64 = 23;
+const STT_OBJECT: u8 = 1;
+#[cfg(target_arch = "x86_64")]
+const FUNCTION_RELOCATIONS: [u32; 2] = [6, 7]; // R_X86_64_GLOB_DAT, R_X86_64_JUMP_SLOT
+#[cfg(target_arch = "aarch64")]
+const FUNCTION_RELOCATIONS: [u32; 2] = [1025, 1026]; // R_AARCH64_GLOB_DAT, R_AARCH64_JUMP_SLOT
+
+#[repr(C)]
+struct Dyn {
+    tag: i64,
+    val: u64,
+}
+
+#[repr(C)]
+struct Rela {
+    offset: u64,
+    info: u64,
+    _addend: i64,
+}
+
+/// Where this library is loaded, and a handle to look its own symbols up with.
+struct Own {
+    base: usize,
+    handle: *mut c_void,
+    // whether the handle came from dlopen, and is to be closed again
+    opened: bool,
+}
+
+impl Own {
+    fn find() -> Option<Own> {
+        let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+        // glibc's handles are its link maps
+        let mut map: *mut c_void = std::ptr::null_mut();
+        let found = unsafe {
+            libc::dladdr1(
+                otel_posix_attach as *const c_void,
+                &mut info,
+                (&raw mut map).cast(),
+                RTLD_DL_LINKMAP,
+            )
+        };
+        if found == 0 || map.is_null() {
+            return None;
+        }
+        // but a preloaded library's has no search list to look symbols up in until it's
+        // opened; the executable's always has, and isn't found by its file name
+        let handle = unsafe { libc::dlopen(info.dli_fname, libc::RTLD_LAZY | libc::RTLD_NOLOAD) };
+        Some(Own {
+            base: info.dli_fbase as usize,
+            handle: if handle.is_null() { map } else { handle },
+            opened: !handle.is_null(),
+        })
+    }
+
+    /// This library's own definition of `name`, not one from a library it depends on.
+    fn definition(&self, name: &CStr) -> Option<usize> {
+        let sym = unsafe { libc::dlsym(self.handle, name.as_ptr()) };
+        if sym.is_null() {
+            return None;
+        }
+        let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+        let found = unsafe { libc::dladdr(sym, &mut info) } != 0;
+        (found && info.dli_fbase as usize == self.base).then_some(sym as usize)
+    }
+}
+
+impl Drop for Own {
+    fn drop(&mut self) {
+        if self.opened {
+            unsafe { libc::dlclose(self.handle) };
+        }
+    }
+}
+
+/// A loaded object's view of its dynamic section.
+struct Object<'a> {
+    base: usize,
+    phdrs: &'a [Elf64_Phdr],
+}
+
+impl Object<'_> {
+    /// The address `value` of a dynamic entry stands for: glibc relocates most of them in
+    /// place, other loaders leave them relative to the load base.
+    fn address(&self, value: u64) -> usize {
+        let value = value as usize;
+        if value < self.base {
+            self.base + value
+        } else {
+            value
+        }
+    }
+
+    /// The RELRO range, which is read-only once relocation is done.
+    fn relro(&self) -> Option<std::ops::Range<usize>> {
+        self.phdrs
+            .iter()
+            .find(|p| p.p_type == libc::PT_GNU_RELRO)
+            .map(|p| {
+                let start = self.base + p.p_vaddr as usize;
+                start..start + p.p_memsz as usize
+            })
+    }
+
+    /// Points every function binding for which `own` has a definition, and `replace`
+    /// agrees, at it, and returns how many it changed.
+    fn redirect(&self, own: &Own, replace: &dyn Fn(&CStr) -> bool) -> usize {
+        let Some(dynamic) = self.phdrs.iter().find(|p| p.p_type == libc::PT_DYNAMIC) else {
+            return 0;
+        };
+        let (mut strtab, mut symtab) = (0, 0);
+        let mut tables = [(0, 0); 2];
+        let mut entry = (self.base + dynamic.p_vaddr as usize) as *const Dyn;
+        loop {
+            let Dyn { tag, val } = unsafe { entry.read() };
+            match tag {
+                DT_NULL => break,
+                DT_STRTAB => strtab = self.address(val),
+                DT_SYMTAB => symtab = self.address(val),
+                DT_JMPREL => tables[0].0 = self.address(val),
+                DT_PLTRELSZ => tables[0].1 = val as usize,
+                DT_RELA => tables[1].0 = self.address(val),
+                DT_RELASZ => tables[1].1 = val as usize,
+                _ => {}
+            }
+            entry = unsafe { entry.add(1) };
+        }
+        if strtab == 0 || symtab == 0 {
+            return 0;
+        }
+        let relro = self.relro();
+        let mut changed = 0;
+        for (table, size) in tables.into_iter().filter(|(table, _)| *table != 0) {
+            let relas = unsafe {
+                std::slice::from_raw_parts(table as *const Rela, size / size_of::<Rela>())
+            };
+            for rela in relas {
+                let (index, kind) = ((rela.info >> 32) as usize, rela.info as u32);
+                if index == 0 || !FUNCTION_RELOCATIONS.contains(&kind) {
+                    continue;
+                }
+                let sym = unsafe { &*(symtab as *const Elf64_Sym).add(index) };
+                if sym.st_info & 0xf == STT_OBJECT {
+                    continue;
+                }
+                let name = unsafe { CStr::from_ptr((strtab + sym.st_name as usize) as *const _) };
+                let Some(ours) = own.definition(name) else {
+                    continue;
+                };
+                let slot = (self.base + rela.offset as usize) as *mut usize;
+                if unsafe { slot.read() } == ours || !replace(name) {
+                    continue;
+                }
+                let protected = relro.as_ref().is_some_and(|r| r.contains(&(slot as usize)));
+                if unsafe { write(slot, ours, protected) } {
+                    changed += 1;
+                }
+            }
+        }
+        changed
+    }
+}
+
+/// Writes `value` to `slot`, lifting the protection of its page for the write if it is in
+/// RELRO.
+unsafe fn write(slot: *mut usize, value: usize, protected: bool) -> bool {
+    if !protected {
+        unsafe { slot.write(value) };
+        return true;
+    }
+    let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    let start = (slot as usize & !(page - 1)) as *mut c_void;
+    if unsafe { libc::mprotect(start, page, libc::PROT_READ | libc::PROT_WRITE) } != 0 {
+        return false;
+    }
+    unsafe { slot.write(value) };
+    unsafe { libc::mprotect(start, page, libc::PROT_READ) };
+    true
+}
+
+/// Takes over a process this library was loaded into late: points the bindings every
+/// loaded object made to functions the library interposes at the library's own.
+///
+/// `otel-preload attach` calls it once it has loaded the library into a running process,
+/// and a host that dlopens the library itself can too; there's no need for it under
+/// `LD_PRELOAD`. Objects loaded afterwards keep libc's bindings until it is called again.
+/// Returns the number of bindings changed, or -1 if the library can't find itself.
+#[unsafe(no_mangle)]
+pub extern "C" fn otel_posix_attach() -> c_int {
+    let Some(changed) = redirect(&|_| true, &|_| true) else {
+        return -1;
+    };
+    if changed > 0 {
+        crate::selftest::attached();
+    }
+    changed.try_into().unwrap_or(c_int::MAX)
+}
+
+/// Points the bindings of the loaded objects `select` picks, by load address, at this
+/// library's definitions, for the symbols `replace` agrees to. Returns how many it
+/// changed, or `None` if the library can't find itself.
+pub(crate) fn redirect(
+    select: &dyn Fn(usize) -> bool,
+    replace: &dyn Fn(&CStr) -> bool,
+) -> Option<usize> {
+    struct Sweep<'a> {
+        own: Own,
+        select: &'a dyn Fn(usize) -> bool,
+        replace: &'a dyn Fn(&CStr) -> bool,
+        changed: usize,
+    }
+    unsafe extern "C" fn visit(info: *mut dl_phdr_info, _size: size_t, data: *mut c_void) -> c_int {
+        let info = unsafe { &*info };
+        let sweep = unsafe { &mut *data.cast::<Sweep<'_>>() };
+        let base = info.dlpi_addr as usize;
+        // leave our own bindings (to libc) alone, and the loader's
+        let name = (!info.dlpi_name.is_null()).then(|| unsafe { CStr::from_ptr(info.dlpi_name) });
+        let loader = name.is_some_and(|n| n.to_bytes().windows(3).any(|w| w == b"ld-"));
+        if base == sweep.own.base || loader || info.dlpi_phdr.is_null() || !(sweep.select)(base) {
+            return 0;
+        }
+        let phdrs = unsafe { std::slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum.into()) };
+        sweep.changed += Object { base, phdrs }.redirect(&sweep.own, sweep.replace);
+        0
+    }
+    let mut sweep = Sweep {
+        own: Own::find()?,
+        select,
+        replace,
+        changed: 0,
+    };
+    unsafe { libc::dl_iterate_phdr(Some(visit), (&raw mut sweep).cast()) };
+    Some(sweep.changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_its_interposers_but_not_libc() {
+        let own = Own::find().expect("the test binary carries the library");
+        assert!(own.definition(c"pthread_create").is_some());
+        // libc's, found through the handle's dependencies
+        assert!(own.definition(c"strlen").is_none());
+    }
+}
@@ -0,0 +1,155 @@
+// src/attr.rs
+//
+// What the creator asked for in the pthread_attr_t it passed: the stack
+// size, whether the thread starts detached, and its scheduling, so a
+// thread that ran out of stack or never got the policy it was meant to
+// have shows it in its trace. Read in pthread_create, when the attributes
+// object is still the caller's to read, and recorded on the span the
+// thread gets of its own and on its `thread.join` event. A thread created
+// without one runs with the defaults, and gets nothing.
+
+use libc::{c_int, pthread_attr_t, sched_param};
+use opentelemetry::KeyValue;
+
+unsafe extern "C" {
+    // not exposed by the libc crate on every target
+    fn pthread_attr_getdetachstate(attr: *const pthread_attr_t, state: *mut c_int) -> c_int;
+    fn pthread_attr_getinheritsched(attr: *const pthread_attr_t, inherit: *mut c_int) -> c_int;
+    fn pthread_attr_getschedpolicy(attr: *const pthread_attr_t, policy: *mut c_int) -> c_int;
+    fn pthread_attr_getschedparam(attr: *const pthread_attr_t, param: *mut sched_param) -> c_int;
+}
+
+/// The attributes a thread was created with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Requested {
+    stack_size: usize,
+    detached: bool,
+    // policy and priority, or None when the creator's scheduling is inherited
+    sched: Option<(c_int, c_int)>,
+}
+
+impl Requested {
+    /// Reads `attr`, or `None` for a null one.
+    pub(crate) fn read(attr: *const pthread_attr_t) -> Option<Requested> {
+        if attr.is_null() {
+            return None;
+        }
+        let mut stack_size = 0;
+        unsafe { libc::pthread_attr_getstacksize(attr, &mut stack_size) };
+        let mut inherit = libc::PTHREAD_INHERIT_SCHED;
+        unsafe { pthread_attr_getinheritsched(attr, &mut inherit) };
+        let sched = (inherit == libc::PTHREAD_EXPLICIT_SCHED).then(|| {
+            let mut policy = libc::SCHED_OTHER;
+            let mut param = unsafe { std::mem::zeroed::<sched_param>() };
+            unsafe {
+                pthread_attr_getschedpolicy(attr, &mut policy);
+                pthread_attr_getschedparam(attr, &mut param);
+            }
+            (policy, param.sched_priority)
+        });
+        Some(Requested {
+            stack_size,
+            detached: is_detached(attr),
+            sched,
+        })
+    }
+
+    pub(crate) fn attributes(&self) -> Vec<KeyValue> {
+        let mut attributes = vec![
+            KeyValue::new("otel_posix.thread.stack_size", self.stack_size as i64),
+            KeyValue::new("otel_posix.thread.detached", self.detached),
+        ];
+        match self.sched {
+            Some((policy, priority)) => {
+                attributes.push(KeyValue::new(
+                    "otel_posix.thread.sched_policy",
+                    policy_name(policy),
+                ));
+                attributes.push(KeyValue::new(
+                    "otel_posix.thread.sched_priority",
+                    i64::from(priority),
+                ));
+            }
+            None => attributes.push(KeyValue::new("otel_posix.thread.sched_policy", "inherit")),
+        }
+        attributes
+    }
+}
+
+/// Whether `attr` creates the thread detached; a null one doesn't.
+pub(crate) fn is_detached(attr: *const pthread_attr_t) -> bool {
+    let mut state = libc::PTHREAD_CREATE_JOINABLE;
+    !attr.is_null()
+        && unsafe { pthread_attr_getdetachstate(attr, &mut state) } == 0
+        && state == libc::PTHREAD_CREATE_DETACHED
+}
+
+pub(crate) fn policy_name(policy: c_int) -> String {
+    match policy {
+        libc::SCHED_OTHER => "SCHED_OTHER".into(),
+        libc::SCHED_FIFO => "SCHED_FIFO".into(),
+        libc::SCHED_RR => "SCHED_RR".into(),
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        libc::SCHED_BATCH => "SCHED_BATCH".into(),
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        libc::SCHED_IDLE => "SCHED_IDLE".into(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_what_was_set() {
+        assert_eq!(Requested::read(std::ptr::null()), None);
+        let mut attr = unsafe { std::mem::zeroed::<pthread_attr_t>() };
+        unsafe {
+            libc::pthread_attr_init(&mut attr);
+            libc::pthread_attr_setstacksize(&mut attr, 256 * 1024);
+        }
+        let requested = Requested::read(&attr).unwrap();
+        assert_eq!(
+            requested,
+            Requested {
+                stack_size: 256 * 1024,
+                detached: false,
+                sched: None,
+            }
+        );
+        assert_eq!(
+            requested.attributes()[2],
+            KeyValue::new("otel_posix.thread.sched_policy", "inherit")
+        );
+
+        let param = sched_param { sched_priority: 10 };
+        unsafe {
+            libc::pthread_attr_setdetachstate(&mut attr, libc::PTHREAD_CREATE_DETACHED);
+            libc::pthread_attr_setinheritsched(&mut attr, libc::PTHREAD_EXPLICIT_SCHED);
+            libc::pthread_attr_setschedpolicy(&mut attr, libc::SCHED_RR);
+            libc::pthread_attr_setschedparam(&mut attr, &param);
+        }
+        let requested = Requested::read(&attr).unwrap();
+        assert!(requested.detached);
+        assert_eq!(
+            requested.attributes()[2..],
+            [
+                KeyValue::new("otel_posix.thread.sched_policy", "SCHED_RR"),
+                KeyValue::new("otel_posix.thread.sched_priority", 10),
+            ]
+        );
+        unsafe { libc::pthread_attr_destroy(&mut attr) };
+    }
+
+    #[test]
+    fn detached_attr_is_not_tracked() {
+        let mut attr = unsafe { std::mem::zeroed::<pthread_attr_t>() };
+        unsafe { libc::pthread_attr_init(&mut attr) };
+        assert!(!is_detached(&attr));
+        unsafe { libc::pthread_attr_setdetachstate(&mut attr, libc::PTHREAD_CREATE_DETACHED) };
+        assert!(is_detached(&attr));
+        unsafe { libc::pthread_attr_destroy(&mut attr) };
+        assert!(!is_detached(std::ptr::null()));
+    }
+}
@@ -0,0 +1,171 @@
+// src/audit.rs
+//
+// rtld-audit installation: LD_AUDIT=libotel_posix_pseudo_propegator.so, for
+// environments that strip LD_PRELOAD but still honour LD_AUDIT.
+//
+// The loader opens audit libraries in a link-map namespace of their own, so
+// the copy loaded here can't serve the application directly: its
+// OpenTelemetry state and C API would be invisible to it. Instead, once the
+// application's objects are loaded (la_preinit), it loads a second copy of
+// the library into the base namespace, where everything else works as under
+// LD_PRELOAD. That copy comes after libc in symbol lookup order, so
+// pthread_create bindings are redirected to it from la_symbind. Bindings
+// made before la_preinit (BIND_NOW relocations) go through `create` below,
+// which forwards to the base copy once it is there and straight to libc
+// until then.
+
+use crate::{PthreadCreateFn, StartRoutine};
+use libc::{LM_ID_BASE, Lmid_t, c_char, c_int, c_uint, pthread_attr_t, pthread_t};
+use std::ffi::{CStr, c_void};
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+// from <link.h>
+const LAV_CURRENT: c_uint = 2;
+const LA_FLG_BINDTO: c_uint = 0x01;
+const LA_FLG_BINDFROM: c_uint = 0x02;
+// from <dlfcn.h>, missing from the libc crate
+const RTLD_DL_LINKMAP: c_int = 2;
+
+/// The leading fields of glibc's `struct link_map`.
+#[repr(C)]
+struct LinkMap {
+    l_addr: usize,
+    l_name: *const c_char,
+}
+
+// the pthread_create the application bound to, normally libc's
+static REAL: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+// pthread_create of the copy loaded into the base namespace
+static SHIM: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+// that copy's cookie, to tell the bindings it makes itself
+static SHIM_MAP: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+/// This copy's `link_map`.
+fn own_map() -> Option<&'static LinkMap> {
+    let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+    let mut map = std::ptr::null_mut::<c_void>();
+    let found = unsafe {
+        libc::dladdr1(
+            la_version as *const c_void,
+            &mut info,
+            &mut map,
+            RTLD_DL_LINKMAP,
+        )
+    } != 0;
+    (found && !map.is_null()).then(|| unsafe { &*map.cast::<LinkMap>() })
+}
+
+/// Whether this copy was loaded as an auditor, in a namespace of its own; its load-time
+/// constructor must not start exporting from there.
+pub(crate) fn is_auditor() -> bool {
+    let Some(map) = own_map() else {
+        return false;
+    };
+    let mut lmid: Lmid_t = LM_ID_BASE;
+    let map = map as *const LinkMap as *mut c_void;
+    let found = unsafe { libc::dlinfo(map, libc::RTLD_DI_LMID, (&mut lmid as *mut Lmid_t).cast()) };
+    found == 0 && lmid != LM_ID_BASE
+}
+
+/// Whether `map` is another copy of this library.
+fn is_own_object(map: &LinkMap) -> bool {
+    let Some(own) = own_map() else {
+        return false;
+    };
+    !map.l_name.is_null()
+        && !own.l_name.is_null()
+        && unsafe { CStr::from_ptr(map.l_name) == CStr::from_ptr(own.l_name) }
+}
+
+/// Target of the redirected bindings: the base-namespace copy once loaded, libc before.
+unsafe extern "C" fn create(
+    tid: *mut pthread_t,
+    attr: *const pthread_attr_t,
+    start_routine: StartRoutine,
+    arg: *mut c_void,
+) -> c_int {
+    let shim = SHIM.load(Ordering::Acquire);
+    let target = if shim.is_null() {
+        REAL.load(Ordering::Acquire)
+    } else {
+        shim
+    };
+    if target.is_null() {
+        return libc::EAGAIN;
+    }
+    let target = unsafe { std::mem::transmute::<*mut c_void, PthreadCreateFn>(target) };
+    unsafe { target(tid, attr, start_routine, arg) }
+}
+
+/// rtld-audit entry point: agrees on the interface version.
+#[unsafe(no_mangle)]
+pub extern "C" fn la_version(version: c_uint) -> c_uint {
+    version.min(LAV_CURRENT)
+}
+
+/// rtld-audit entry point: audits bindings from and to every object of the base
+/// namespace.
+///
+/// # Safety
+///
+/// Called by the dynamic loader with a valid `link_map`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn la_objopen(map: *mut c_void, lmid: Lmid_t, cookie: *mut usize) -> c_uint {
+    if lmid != LM_ID_BASE {
+        return 0;
+    }
+    if is_own_object(unsafe { &*map.cast::<LinkMap>() }) {
+        // its own lookups of the real pthread_create must not come back to it
+        SHIM_MAP.store(unsafe { *cookie } as *mut c_void, Ordering::Release);
+    }
+    LA_FLG_BINDTO | LA_FLG_BINDFROM
+}
+
+/// rtld-audit entry point: loads the shim into the base namespace once the application's
+/// own objects are in place, before their constructors run.
+///
+/// # Safety
+///
+/// Called by the dynamic loader.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn la_preinit(_cookie: *mut usize) {
+    let Some(own) = own_map() else {
+        return;
+    };
+    let flags = libc::RTLD_NOW | libc::RTLD_GLOBAL;
+    let handle = unsafe { libc::dlmopen(LM_ID_BASE, own.l_name, flags) };
+    let mut map = std::ptr::null_mut::<LinkMap>();
+    let info = (&mut map as *mut *mut LinkMap).cast();
+    if handle.is_null() || unsafe { libc::dlinfo(handle, libc::RTLD_DI_LINKMAP, info) } != 0 {
+        return;
+    }
+    // both copies are the same file, so its pthread_create sits at the same offset; a
+    // dlsym would be redirected back to `create`
+    let offset = crate::pthread_create as *const () as usize - own.l_addr;
+    let shim = unsafe { (*map).l_addr } + offset;
+    SHIM.store(shim as *mut c_void, Ordering::Release);
+}
+
+/// rtld-audit entry point: sends `pthread_create` bindings through the shim.
+///
+/// # Safety
+///
+/// Called by the dynamic loader with a valid symbol, cookie and name.
+#[cfg(target_pointer_width = "64")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn la_symbind64(
+    sym: *mut libc::Elf64_Sym,
+    _ndx: c_uint,
+    refcook: *mut usize,
+    _defcook: *mut usize,
+    _flags: *mut c_uint,
+    symname: *const c_char,
+) -> usize {
+    let value = unsafe { (*sym).st_value } as usize;
+    let own = unsafe { *refcook } as *mut c_void == SHIM_MAP.load(Ordering::Acquire);
+    if own || unsafe { CStr::from_ptr(symname) } != c"pthread_create" {
+        return value;
+    }
+    REAL.store(value as *mut c_void, Ordering::Release);
+    create as *const () as usize
+}
@@ -0,0 +1,75 @@
+// src/auto_root.rs
+//
+// Auto-root mode: a completely uninstrumented binary never has a span
+// active, so nothing it does is ever traced. With OTEL_POSIX_PROP_AUTO_ROOT
+// set, a thread created without one gets a root span of its own, named
+// after its start routine, that lasts as long as the thread. Like a
+// lifetime-mode span, it ends when the thread exits however it leaves.
+//
+// Read straight from the environment by a load-time constructor: the
+// setting is needed exactly where no span is active, which is before the
+// rest of the configuration is ever read. A library initialized ahead of
+// us can start threads before that constructor has run; the first of them
+// reads it instead.
+
+use crate::config::parse_bool;
+use crate::{entry, lifetime, scope};
+use opentelemetry::trace::{SpanKind, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue};
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+// unset until read, by the constructor or whichever comes first
+static ENABLED: AtomicU8 = AtomicU8::new(UNSET);
+const UNSET: u8 = 0;
+const ON: u8 = 1;
+const OFF: u8 = 2;
+
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+#[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__mod_init_func"))]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(read);
+}
+
+fn read() -> bool {
+    let on = std::env::var("OTEL_POSIX_PROP_AUTO_ROOT").is_ok_and(|v| parse_bool(&v));
+    ENABLED.store(if on { ON } else { OFF }, Ordering::Relaxed);
+    on
+}
+
+/// Whether threads created without an active span get a root span.
+pub(crate) fn enabled() -> bool {
+    match ENABLED.load(Ordering::Relaxed) {
+        ON => true,
+        OFF => false,
+        _ => read(),
+    }
+}
+
+/// Builds the context a thread started in `start_routine` runs under when it was created
+/// without a span: a new root span, ended with the thread.
+pub(crate) fn thread_context(start_routine: *const c_void) -> Context {
+    let name = entry::start_routine_name(start_routine).unwrap_or_else(|| "thread".to_string());
+    let tracer = scope::tracer();
+    let mut attributes = vec![KeyValue::new(
+        "thread.id",
+        i64::from(unsafe { libc::gettid() }),
+    )];
+    attributes.extend(entry::code_attributes(start_routine));
+    let span = tracer
+        .span_builder(name)
+        .with_kind(SpanKind::Internal)
+        .with_attributes(attributes)
+        // an empty parent makes this a root span
+        .start_with_context(&tracer, &Context::new());
+    let cx = Context::new().with_span(span);
+    lifetime::end_with_thread(&cx);
+    cx
+}
@@ -0,0 +1,292 @@
+// src/bin/otel-observe/bpf.rs
+//
+// The BPF program the uprobes run, and the perf event array it writes to.
+// The program is a few instructions, put together here instead of being
+// compiled from C, so the observer needs neither clang nor a BPF library:
+// on each hit it writes the thread's ids, the time, which probe it is and,
+// on pthread_create, the start routine, to the calling CPU's entry in the
+// array. perf.rs maps each entry's ring buffer.
+
+use libc::c_int;
+use std::ffi::CStr;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+// not in the libc crate: from <linux/bpf.h>
+const BPF_MAP_CREATE: c_int = 0;
+const BPF_MAP_UPDATE_ELEM: c_int = 2;
+const BPF_PROG_LOAD: c_int = 5;
+const BPF_MAP_TYPE_PERF_EVENT_ARRAY: u32 = 4;
+const BPF_PROG_TYPE_KPROBE: u32 = 2;
+const BPF_PSEUDO_MAP_FD: u8 = 1;
+// helper function numbers
+const KTIME_GET_NS: i32 = 5;
+const GET_CURRENT_PID_TGID: i32 = 14;
+const PERF_EVENT_OUTPUT: i32 = 25;
+
+// registers: r0 returns, r1 to r5 are arguments, r6 survives calls, r10 is the frame
+const R0: u8 = 0;
+const R1: u8 = 1;
+const R2: u8 = 2;
+const R3: u8 = 3;
+const R4: u8 = 4;
+const R5: u8 = 5;
+const R6: u8 = 6;
+const R10: u8 = 10;
+
+// pthread_create's third argument, the start routine, in the registers a uprobe's
+// program is handed
+#[cfg(target_arch = "x86_64")]
+const START_ROUTINE: i16 = 12 * 8; // struct pt_regs, dx
+#[cfg(target_arch = "aarch64")]
+const START_ROUTINE: i16 = 2 * 8; // struct user_pt_regs, regs[2]
+
+/// What the program writes per hit, in native byte order: the tid and pid (as
+/// bpf_get_current_pid_tgid has them, thread in the low half), the CLOCK_MONOTONIC
+/// time, the probe's kind, and the start routine or 0.
+pub(crate) const OUTPUT: usize = 32;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Insn {
+    code: u8,
+    // the destination register in the low nibble, the source in the high one
+    regs: u8,
+    off: i16,
+    imm: i32,
+}
+
+fn insn(code: u8, dst: u8, src: u8, off: i16, imm: i32) -> Insn {
+    Insn {
+        code,
+        regs: (src << 4) | dst,
+        off,
+        imm,
+    }
+}
+
+fn mov_reg(dst: u8, src: u8) -> Insn {
+    insn(0xbf, dst, src, 0, 0) // BPF_ALU64 | BPF_MOV | BPF_X
+}
+
+fn mov_imm(dst: u8, imm: i32) -> Insn {
+    insn(0xb7, dst, 0, 0, imm) // BPF_ALU64 | BPF_MOV | BPF_K
+}
+
+/// A 32-bit move, which zero-extends `imm` rather than sign-extending it.
+fn mov32_imm(dst: u8, imm: i32) -> Insn {
+    insn(0xb4, dst, 0, 0, imm) // BPF_ALU | BPF_MOV | BPF_K
+}
+
+fn add_imm(dst: u8, imm: i32) -> Insn {
+    insn(0x07, dst, 0, 0, imm) // BPF_ALU64 | BPF_ADD | BPF_K
+}
+
+/// `*(u64 *)(src + off)` into `dst`.
+fn read(dst: u8, src: u8, off: i16) -> Insn {
+    insn(0x79, dst, src, off, 0) // BPF_LDX | BPF_MEM | BPF_DW
+}
+
+/// `src` into the u64 at `off` in the frame.
+fn store(off: i16, src: u8) -> Insn {
+    insn(0x7b, R10, src, off, 0) // BPF_STX | BPF_MEM | BPF_DW
+}
+
+fn store_imm(off: i16, imm: i32) -> Insn {
+    insn(0x7a, R10, 0, off, imm) // BPF_ST | BPF_MEM | BPF_DW
+}
+
+/// The map open as `fd` into `dst`, which takes two instructions.
+fn load_map(dst: u8, fd: c_int) -> [Insn; 2] {
+    [
+        insn(0x18, dst, BPF_PSEUDO_MAP_FD, 0, fd), // BPF_LD | BPF_DW | BPF_IMM
+        insn(0, 0, 0, 0, 0),
+    ]
+}
+
+fn call(helper: i32) -> Insn {
+    insn(0x85, 0, 0, 0, helper) // BPF_JMP | BPF_CALL
+}
+
+fn exit() -> Insn {
+    insn(0x95, 0, 0, 0, 0) // BPF_JMP | BPF_EXIT
+}
+
+/// The program for a probe: it writes OUTPUT bytes to `array`, with `kind` to tell the
+/// probes apart, and the start routine if `start_routine` is set.
+fn program(array: c_int, kind: u64, start_routine: bool) -> Vec<Insn> {
+    let mut insns = vec![
+        mov_reg(R6, R1),
+        call(GET_CURRENT_PID_TGID),
+        store(-32, R0),
+        call(KTIME_GET_NS),
+        store(-24, R0),
+        store_imm(-16, kind as i32),
+    ];
+    if start_routine {
+        insns.extend([read(R1, R6, START_ROUTINE), store(-8, R1)]);
+    } else {
+        insns.push(store_imm(-8, 0));
+    }
+    insns.push(mov_reg(R1, R6));
+    insns.extend(load_map(R2, array));
+    insns.extend([
+        // BPF_F_CURRENT_CPU: the calling CPU's entry
+        mov32_imm(R3, -1),
+        mov_reg(R4, R10),
+        add_imm(R4, -(OUTPUT as i32)),
+        mov_imm(R5, OUTPUT as i32),
+        call(PERF_EVENT_OUTPUT),
+        // and no sample from the uprobe itself
+        mov_imm(R0, 0),
+        exit(),
+    ]);
+    insns
+}
+
+fn bpf<T>(cmd: c_int, attr: &T) -> io::Result<c_int> {
+    let fd = unsafe { libc::syscall(libc::SYS_bpf, cmd, attr as *const T, size_of::<T>()) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(fd as c_int)
+}
+
+#[repr(C)]
+struct MapCreate {
+    map_type: u32,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+    map_flags: u32,
+}
+
+#[repr(C)]
+struct MapUpdate {
+    map_fd: u32,
+    key: u64,
+    value: u64,
+    flags: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ProgLoad {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+    kern_version: u32,
+    prog_flags: u32,
+    prog_name: [u8; 16],
+}
+
+/// A perf event array with an entry for each of `cpus` CPUs.
+pub(crate) fn array(cpus: u32) -> io::Result<OwnedFd> {
+    let attr = MapCreate {
+        map_type: BPF_MAP_TYPE_PERF_EVENT_ARRAY,
+        key_size: 4,
+        value_size: 4,
+        max_entries: cpus,
+        map_flags: 0,
+    };
+    Ok(unsafe { OwnedFd::from_raw_fd(bpf(BPF_MAP_CREATE, &attr)?) })
+}
+
+/// Makes `event` the entry for `cpu` in `array`.
+pub(crate) fn set(array: &OwnedFd, cpu: u32, event: &OwnedFd) -> io::Result<()> {
+    let value = event.as_raw_fd() as u32;
+    let attr = MapUpdate {
+        map_fd: array.as_raw_fd() as u32,
+        key: &cpu as *const u32 as u64,
+        value: &value as *const u32 as u64,
+        flags: 0,
+    };
+    bpf(BPF_MAP_UPDATE_ELEM, &attr).map(drop)
+}
+
+/// Loads the program for a probe writing to `array` (see `program`).
+pub(crate) fn load(array: &OwnedFd, kind: u64, start_routine: bool) -> io::Result<OwnedFd> {
+    let insns = program(array.as_raw_fd(), kind, start_routine);
+    let mut attr = ProgLoad {
+        prog_type: BPF_PROG_TYPE_KPROBE,
+        insn_cnt: insns.len() as u32,
+        insns: insns.as_ptr() as u64,
+        // bpf_perf_event_output is only there for GPL-compatible programs
+        license: c"GPL".as_ptr() as u64,
+        kern_version: version_code(&kernel_release()),
+        ..ProgLoad::default()
+    };
+    let e = match bpf(BPF_PROG_LOAD, &attr) {
+        Ok(fd) => return Ok(unsafe { OwnedFd::from_raw_fd(fd) }),
+        Err(e) => e,
+    };
+    if !matches!(e.raw_os_error(), Some(libc::EACCES | libc::EINVAL)) {
+        return Err(e);
+    }
+    // rejected: again, for the verifier's reasons
+    let mut log = vec![0u8; 64 * 1024];
+    attr.log_level = 1;
+    attr.log_size = log.len() as u32;
+    attr.log_buf = log.as_mut_ptr() as u64;
+    let _ = bpf(BPF_PROG_LOAD, &attr).map(|fd| drop(unsafe { OwnedFd::from_raw_fd(fd) }));
+    let log = CStr::from_bytes_until_nul(&log).map_or_else(|_| "".into(), CStr::to_string_lossy);
+    match log.lines().rfind(|line| !line.trim().is_empty()) {
+        Some(reason) => Err(io::Error::new(e.kind(), format!("{e}: {reason}"))),
+        None => Err(e),
+    }
+}
+
+fn kernel_release() -> String {
+    let mut name: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } != 0 {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(name.release.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// LINUX_VERSION_CODE for `release`, which kprobe programs had to be loaded with
+/// before Linux 5.0.
+fn version_code(release: &str) -> u32 {
+    let mut numbers = release
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|n| n.parse::<u32>().unwrap_or(0));
+    let mut next = || numbers.next().unwrap_or(0);
+    let (major, minor, patch) = (next(), next(), next());
+    (major << 16) | (minor.min(255) << 8) | patch.min(255)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn programs_write_to_the_array_they_were_given() {
+        let create = program(42, 1, true);
+        let pthread_exit = program(42, 2, false);
+        assert_eq!(create.len(), pthread_exit.len() + 1);
+        for insns in [&create, &pthread_exit] {
+            let at = insns.iter().position(|i| i.code == 0x18).unwrap();
+            assert_eq!(insns[at].imm, 42);
+            assert_eq!(insns[at].regs, (BPF_PSEUDO_MAP_FD << 4) | R2);
+            assert_eq!(insns.last(), Some(&exit()));
+        }
+        assert!(create.contains(&read(R1, R6, START_ROUTINE)));
+        assert!(pthread_exit.contains(&store_imm(-8, 0)));
+        assert!(create.contains(&store_imm(-16, 1)));
+    }
+
+    #[test]
+    fn version_codes() {
+        assert_eq!(version_code("4.19.0-17-amd64"), 0x04_13_00);
+        assert_eq!(version_code("6.18.44-fc-v130"), 0x06_12_2c);
+        // the patch level saturates, as in the kernel's own KERNEL_VERSION
+        assert_eq!(version_code("4.9.337"), 0x04_09_ff);
+        assert_eq!(version_code(""), 0);
+    }
+}
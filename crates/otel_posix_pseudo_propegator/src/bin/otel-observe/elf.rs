@@ -0,0 +1,289 @@
+// src/bin/otel-observe/elf.rs
+//
+// Just enough ELF64 for the observer, read from the files themselves since
+// the observed processes aren't ours to dladdr in: the loadable segments,
+// to turn addresses into file offsets and back, and the function symbols,
+// from .symtab where the file still has one as well as .dynsym. Also where
+// a process has which file mapped, from /proc/<pid>/maps.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// not in the libc crate
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_DYNSYM: u32 = 11;
+const STT_FUNC: u8 = 2;
+
+pub(crate) struct Elf {
+    // (file offset, virtual address, size in the file) of each PT_LOAD
+    loads: Vec<(u64, u64, u64)>,
+    // function symbols, by address
+    functions: Vec<(u64, String)>,
+}
+
+/// A little-endian reader over the file, which fails on anything out of bounds.
+struct Bytes<'a>(&'a [u8]);
+
+impl Bytes<'_> {
+    fn get<const N: usize>(&self, at: u64) -> Option<[u8; N]> {
+        let at = usize::try_from(at).ok()?;
+        self.0.get(at..at.checked_add(N)?)?.try_into().ok()
+    }
+
+    fn u8(&self, at: u64) -> Option<u8> {
+        self.get::<1>(at).map(|[b]| b)
+    }
+
+    fn u16(&self, at: u64) -> Option<u16> {
+        self.get(at).map(u16::from_le_bytes)
+    }
+
+    fn u32(&self, at: u64) -> Option<u32> {
+        self.get(at).map(u32::from_le_bytes)
+    }
+
+    fn u64(&self, at: u64) -> Option<u64> {
+        self.get(at).map(u64::from_le_bytes)
+    }
+
+    fn str(&self, at: u64) -> Option<&str> {
+        let rest = self.0.get(usize::try_from(at).ok()?..)?;
+        let end = rest.iter().position(|&b| b == 0)?;
+        std::str::from_utf8(&rest[..end]).ok()
+    }
+}
+
+impl Elf {
+    pub(crate) fn read(path: &Path) -> io::Result<Elf> {
+        let data = std::fs::read(path)?;
+        Elf::parse(&data).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} isn't a little-endian ELF64 file", path.display()),
+            )
+        })
+    }
+
+    fn parse(data: &[u8]) -> Option<Elf> {
+        if data.get(..6)? != b"\x7fELF\x02\x01" {
+            return None;
+        }
+        let b = Bytes(data);
+        let (phoff, phentsize, phnum) = (b.u64(0x20)?, b.u16(0x36)?, b.u16(0x38)?);
+        let (shoff, shentsize, shnum) = (b.u64(0x28)?, b.u16(0x3a)?, b.u16(0x3c)?);
+
+        let mut loads = Vec::new();
+        for i in 0..u64::from(phnum) {
+            let p = phoff + i * u64::from(phentsize);
+            if b.u32(p)? == PT_LOAD {
+                loads.push((b.u64(p + 0x08)?, b.u64(p + 0x10)?, b.u64(p + 0x20)?));
+            }
+        }
+
+        let section = |i: u64| shoff + i * u64::from(shentsize);
+        let mut functions = Vec::new();
+        for i in 0..u64::from(shnum) {
+            let s = section(i);
+            if !matches!(b.u32(s + 0x04)?, SHT_SYMTAB | SHT_DYNSYM) {
+                continue;
+            }
+            let (offset, size, entsize) = (b.u64(s + 0x18)?, b.u64(s + 0x20)?, b.u64(s + 0x38)?);
+            let strtab = b.u64(section(u64::from(b.u32(s + 0x28)?)) + 0x18)?;
+            for sym in (offset..offset + size).step_by(entsize.max(1) as usize) {
+                let (name, info, shndx, value) = (
+                    b.u32(sym)?,
+                    b.u8(sym + 4)?,
+                    b.u16(sym + 6)?,
+                    b.u64(sym + 8)?,
+                );
+                if info & 0xf != STT_FUNC || shndx == 0 || value == 0 {
+                    continue;
+                }
+                if let Some(name) = b.str(strtab + u64::from(name)).filter(|n| !n.is_empty()) {
+                    functions.push((value, name.to_string()));
+                }
+            }
+        }
+        functions.sort();
+        functions.dedup();
+        Some(Elf { loads, functions })
+    }
+
+    /// The address of the function `name`.
+    pub(crate) fn function(&self, name: &str) -> Option<u64> {
+        self.functions
+            .iter()
+            .find(|(_, n)| n == name)
+            .map(|&(addr, _)| addr)
+    }
+
+    /// The name of the function starting exactly at `vaddr`, preferring one that isn't
+    /// an internal alias.
+    pub(crate) fn symbol_at(&self, vaddr: u64) -> Option<&str> {
+        let start = self.functions.partition_point(|&(addr, _)| addr < vaddr);
+        let names = self.functions[start..]
+            .iter()
+            .take_while(|&&(addr, _)| addr == vaddr)
+            .map(|(_, name)| name.as_str());
+        let mut first = None;
+        for name in names {
+            if !name.starts_with('_') {
+                return Some(name);
+            }
+            first = first.or(Some(name));
+        }
+        first
+    }
+
+    /// The file offset of virtual address `vaddr`.
+    pub(crate) fn offset(&self, vaddr: u64) -> Option<u64> {
+        self.loads
+            .iter()
+            .find(|&&(_, start, size)| (start..start + size).contains(&vaddr))
+            .map(|&(offset, start, _)| vaddr - start + offset)
+    }
+
+    /// The virtual address at file offset `offset`.
+    pub(crate) fn vaddr(&self, offset: u64) -> Option<u64> {
+        self.loads
+            .iter()
+            .find(|&&(start, _, size)| (start..start + size).contains(&offset))
+            .map(|&(start, vaddr, _)| offset - start + vaddr)
+    }
+}
+
+/// A file mapped into a process: its address range, and the file offset it starts at.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Mapping {
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+    pub(crate) offset: u64,
+    pub(crate) path: PathBuf,
+}
+
+/// The files mapped in `maps`, the contents of /proc/<pid>/maps.
+pub(crate) fn mappings(maps: &str) -> Vec<Mapping> {
+    maps.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (start, end) = fields.next()?.split_once('-')?;
+            let offset = fields.nth(1)?;
+            let path = fields.nth(2)?;
+            path.starts_with('/').then_some(())?;
+            Some(Mapping {
+                start: u64::from_str_radix(start, 16).ok()?,
+                end: u64::from_str_radix(end, 16).ok()?,
+                offset: u64::from_str_radix(offset, 16).ok()?,
+                path: PathBuf::from(path),
+            })
+        })
+        .collect()
+}
+
+/// A path in process `pid`'s view of the filesystem, which may be a container's.
+pub(crate) fn in_root(pid: u32, path: &Path) -> PathBuf {
+    Path::new(&format!("/proc/{pid}/root")).join(path.strip_prefix("/").unwrap_or(path))
+}
+
+/// Names the functions at addresses in the observed processes, as `code.function` and
+/// `code.namespace` (the object's file name) do in the shim. Each process's mappings are
+/// read while it is alive, as its threads are created, and the files once each.
+#[derive(Default)]
+pub(crate) struct Symbolizer {
+    maps: HashMap<u32, Vec<Mapping>>,
+    files: HashMap<PathBuf, Option<Elf>>,
+}
+
+impl Symbolizer {
+    /// Makes sure `addr` in process `pid` can be named later, after the process is gone.
+    pub(crate) fn note(&mut self, pid: u32, addr: u64) {
+        let known = |maps: &Vec<Mapping>| maps.iter().any(|m| (m.start..m.end).contains(&addr));
+        if !self.maps.get(&pid).is_some_and(known) {
+            // a library loaded since, or the first thread
+            let maps = std::fs::read_to_string(format!("/proc/{pid}/maps")).unwrap_or_default();
+            self.maps.insert(pid, mappings(&maps));
+        }
+        if let Some(m) = self.mapping(pid, addr) {
+            let (path, file) = (m.path.clone(), in_root(pid, &m.path));
+            self.files
+                .entry(path)
+                .or_insert_with(|| Elf::read(&file).ok());
+        }
+    }
+
+    fn mapping(&self, pid: u32, addr: u64) -> Option<&Mapping> {
+        self.maps
+            .get(&pid)?
+            .iter()
+            .find(|m| (m.start..m.end).contains(&addr))
+    }
+
+    /// The function at `addr` in process `pid`, if it has a symbol, and the file name of
+    /// the object it is in.
+    pub(crate) fn name(&self, pid: u32, addr: u64) -> (Option<String>, Option<String>) {
+        let Some(m) = self.mapping(pid, addr) else {
+            return (None, None);
+        };
+        let object = m
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+        let function = self
+            .files
+            .get(&m.path)
+            .and_then(Option::as_ref)
+            .and_then(|elf| elf.symbol_at(elf.vaddr(addr - m.start + m.offset)?))
+            .map(str::to_string);
+        (function, object)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_functions_in_its_own_libc() {
+        let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+        let libc = mappings(&maps)
+            .into_iter()
+            .find(|m| m.path.file_name().is_some_and(|n| n == "libc.so.6"))
+            .expect("linked against glibc");
+        let elf = Elf::read(&libc.path).unwrap();
+        let create = elf.function("pthread_create").unwrap();
+        assert_eq!(elf.symbol_at(create), Some("pthread_create"));
+        let offset = elf.offset(create).unwrap();
+        assert_eq!(elf.vaddr(offset), Some(create));
+
+        // and the running copy agrees
+        let mut symbolizer = Symbolizer::default();
+        let addr = libc::pthread_create as *const () as u64;
+        symbolizer.note(std::process::id(), addr);
+        let (function, object) = symbolizer.name(std::process::id(), addr);
+        assert_eq!(function.as_deref(), Some("pthread_create"));
+        assert_eq!(object.as_deref(), Some("libc.so.6"));
+    }
+
+    #[test]
+    fn parses_file_mappings_only() {
+        let maps = "\
+55d0c0000000-55d0c0001000 r--p 00000000 fd:01 1234 /usr/bin/app
+55d0c0001000-55d0c0002000 r-xp 00001000 fd:01 1234 /usr/bin/app
+7ffd00000000-7ffd00021000 rw-p 00000000 00:00 0 [stack]
+7ffd00030000-7ffd00031000 rw-p 00000000 00:00 0";
+        let maps = mappings(maps);
+        assert_eq!(maps.len(), 2);
+        assert_eq!(
+            maps[1],
+            Mapping {
+                start: 0x55d0c0001000,
+                end: 0x55d0c0002000,
+                offset: 0x1000,
+                path: PathBuf::from("/usr/bin/app"),
+            }
+        );
+        assert!(Elf::parse(b"#!/bin/sh\n").is_none());
+    }
+}
@@ -0,0 +1,298 @@
+// src/bin/otel-observe/lineage.rs
+//
+// Turns the probes' records into thread lineage: which thread created
+// which, and when each started and ended. Every observed process gets a
+// trace, its main thread the root `process` span, and every thread created
+// while it is observed a `thread` span under its creator's (or the root's,
+// for a creator that was already running), much like lifetime mode does
+// from the inside. A clone that comes within a pthread_create call of the
+// thread making it is that call's thread, and takes its start routine.
+//
+// Ids are handed out as threads start, but spans are only built once they
+// end, by main.rs, from what is returned here.
+
+use crate::perf::Record;
+use opentelemetry::trace::{SpanId, TraceId};
+use opentelemetry_sdk::trace::{IdGenerator, RandomIdGenerator};
+use std::collections::HashMap;
+
+/// A thread (or a process, for its main thread) that is done with.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Finished {
+    pub(crate) pid: u32,
+    pub(crate) tid: u32,
+    pub(crate) trace_id: TraceId,
+    pub(crate) span_id: SpanId,
+    // none for the process
+    pub(crate) parent: Option<SpanId>,
+    pub(crate) creator: Option<u32>,
+    pub(crate) start_routine: Option<u64>,
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+    pub(crate) pthread_exit: bool,
+    // still running when observation stopped
+    pub(crate) running: bool,
+}
+
+struct Process {
+    trace_id: TraceId,
+    root: SpanId,
+    start: u64,
+    ended: bool,
+}
+
+struct Thread {
+    pid: u32,
+    span_id: SpanId,
+    parent: SpanId,
+    creator: u32,
+    start_routine: Option<u64>,
+    start: u64,
+    pthread_exit: bool,
+}
+
+#[derive(Default)]
+pub(crate) struct Lineage {
+    ids: RandomIdGenerator,
+    processes: HashMap<u32, Process>,
+    threads: HashMap<u32, Thread>,
+    // pthread_create calls not matched with their clone yet, by calling thread
+    creating: HashMap<u32, u64>,
+    // whether the main thread called pthread_exit, by process
+    main_exited: HashMap<u32, bool>,
+    lost: u64,
+}
+
+impl Lineage {
+    /// Starts observing process `pid`, which started (or was first seen) at `start`.
+    pub(crate) fn follow(&mut self, pid: u32, start: u64) {
+        let process = Process {
+            trace_id: self.ids.new_trace_id(),
+            root: self.ids.new_span_id(),
+            start,
+            ended: false,
+        };
+        self.processes.entry(pid).or_insert(process);
+    }
+
+    /// Whether any observed process is still running.
+    pub(crate) fn running(&self) -> bool {
+        self.processes.values().any(|p| !p.ended)
+    }
+
+    /// Records the kernel dropped.
+    pub(crate) fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    /// Whether process `pid` is observed and still running.
+    pub(crate) fn follows(&self, pid: u32) -> bool {
+        self.processes.get(&pid).is_some_and(|p| !p.ended)
+    }
+
+    /// Takes `record` in, and returns the thread it finished, if any.
+    pub(crate) fn record(&mut self, record: Record) -> Option<Finished> {
+        match record {
+            Record::Create {
+                pid,
+                tid,
+                start_routine,
+                ..
+            } if self.follows(pid) => {
+                self.creating.insert(tid, start_routine);
+                None
+            }
+            Record::Clone {
+                pid,
+                ppid,
+                tid,
+                ptid,
+                time,
+            } if pid == ppid && tid != pid && self.follows(pid) => {
+                let start_routine = self.creating.remove(&ptid);
+                let parent = match self.threads.get(&ptid) {
+                    Some(creator) => creator.span_id,
+                    None => self.processes[&pid].root,
+                };
+                let thread = Thread {
+                    pid,
+                    span_id: self.ids.new_span_id(),
+                    parent,
+                    creator: ptid,
+                    start_routine,
+                    start: time,
+                    pthread_exit: false,
+                };
+                self.threads.insert(tid, thread);
+                None
+            }
+            Record::PthreadExit { pid, tid, .. } => {
+                if let Some(thread) = self.threads.get_mut(&tid) {
+                    thread.pthread_exit = true;
+                } else if tid == pid && self.follows(pid) {
+                    self.main_exited.insert(pid, true);
+                }
+                None
+            }
+            Record::Exit { pid, tid, time } if tid == pid && self.follows(pid) => {
+                let process = self.processes.get_mut(&pid)?;
+                process.ended = true;
+                let pthread_exit = self.main_exited.remove(&pid).unwrap_or(false);
+                Some(finished_process(pid, process, time, pthread_exit, false))
+            }
+            Record::Exit { tid, time, .. } => {
+                self.creating.remove(&tid);
+                let thread = self.threads.remove(&tid)?;
+                let trace_id = self.processes.get(&thread.pid)?.trace_id;
+                Some(finished_thread(tid, thread, trace_id, time, false))
+            }
+            Record::Lost(n) => {
+                self.lost += n;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Ends what is still running at `time`, when observation stops.
+    pub(crate) fn finish(&mut self, time: u64) -> Vec<Finished> {
+        let mut finished = Vec::new();
+        for (tid, thread) in self.threads.drain() {
+            if let Some(process) = self.processes.get(&thread.pid) {
+                finished.push(finished_thread(tid, thread, process.trace_id, time, true));
+            }
+        }
+        for (&pid, process) in self.processes.iter_mut().filter(|(_, p)| !p.ended) {
+            process.ended = true;
+            finished.push(finished_process(pid, process, time, false, true));
+        }
+        finished
+    }
+}
+
+fn finished_process(
+    pid: u32,
+    process: &Process,
+    end: u64,
+    pthread_exit: bool,
+    running: bool,
+) -> Finished {
+    Finished {
+        pid,
+        tid: pid,
+        trace_id: process.trace_id,
+        span_id: process.root,
+        parent: None,
+        creator: None,
+        start_routine: None,
+        start: process.start,
+        end,
+        pthread_exit,
+        running,
+    }
+}
+
+fn finished_thread(
+    tid: u32,
+    thread: Thread,
+    trace_id: TraceId,
+    end: u64,
+    running: bool,
+) -> Finished {
+    Finished {
+        pid: thread.pid,
+        tid,
+        trace_id,
+        span_id: thread.span_id,
+        parent: Some(thread.parent),
+        creator: Some(thread.creator),
+        start_routine: thread.start_routine,
+        start: thread.start,
+        end,
+        pthread_exit: thread.pthread_exit,
+        running,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clone(pid: u32, tid: u32, ptid: u32, time: u64) -> Record {
+        Record::Clone {
+            pid,
+            ppid: pid,
+            tid,
+            ptid,
+            time,
+        }
+    }
+
+    fn exit(pid: u32, tid: u32, time: u64) -> Record {
+        Record::Exit { pid, tid, time }
+    }
+
+    #[test]
+    fn threads_nest_under_their_creators() {
+        let mut lineage = Lineage::default();
+        lineage.follow(100, 0);
+        let create = |tid, start_routine| Record::Create {
+            pid: 100,
+            tid,
+            time: 0,
+            start_routine,
+        };
+        assert_eq!(lineage.record(create(100, 0x1000)), None);
+        assert_eq!(lineage.record(clone(100, 101, 100, 10)), None);
+        assert_eq!(lineage.record(create(101, 0x2000)), None);
+        assert_eq!(lineage.record(clone(100, 102, 101, 20)), None);
+        // another process using the same libc
+        assert_eq!(lineage.record(clone(200, 201, 200, 25)), None);
+        lineage.record(Record::PthreadExit {
+            pid: 100,
+            tid: 102,
+            time: 29,
+        });
+
+        let grandchild = lineage.record(exit(100, 102, 30)).unwrap();
+        let child = lineage.record(exit(100, 101, 40)).unwrap();
+        let process = lineage.record(exit(100, 100, 50)).unwrap();
+        assert!(!lineage.running());
+
+        assert_eq!(process.parent, None);
+        assert_eq!((process.start, process.end), (0, 50));
+        assert_eq!(child.parent, Some(process.span_id));
+        assert_eq!(
+            (child.creator, child.start_routine),
+            (Some(100), Some(0x1000))
+        );
+        assert_eq!(grandchild.parent, Some(child.span_id));
+        assert_eq!((grandchild.start, grandchild.end), (20, 30));
+        assert!(grandchild.pthread_exit && !child.pthread_exit);
+        assert!(
+            [child.trace_id, grandchild.trace_id]
+                .iter()
+                .all(|&t| t == process.trace_id)
+        );
+        assert_eq!(lineage.record(exit(200, 201, 60)), None);
+    }
+
+    #[test]
+    fn raw_clones_and_threads_still_running() {
+        let mut lineage = Lineage::default();
+        lineage.follow(100, 0);
+        // a thread from before observation started creates one without pthread_create
+        lineage.record(clone(100, 102, 101, 10));
+        lineage.record(Record::Lost(3));
+        let mut finished = lineage.finish(90);
+        finished.sort_by_key(|f| f.tid);
+        let [process, thread] = &finished[..] else {
+            panic!("{finished:?}");
+        };
+        assert_eq!(thread.parent, Some(process.span_id));
+        assert_eq!((thread.creator, thread.start_routine), (Some(101), None));
+        assert!(thread.running && process.running);
+        assert_eq!((thread.end, process.end), (90, 90));
+        assert_eq!(lineage.lost(), 3);
+    }
+}
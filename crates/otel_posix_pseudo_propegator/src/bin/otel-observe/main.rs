@@ -0,0 +1,139 @@
+// src/bin/otel-observe/main.rs
+//
+// Observer mode, for where preloading is not allowed: traces which thread
+// created which from the outside, with BPF programs on uprobes on libc's
+// pthread_create and pthread_exit and the kernel's fork and exit records,
+// and exports the lineage as spans from this process, with nothing loaded
+// into the target:
+//
+//     otel-observe [--service-name NAME] [--endpoint URL] --pid PID [--pid PID...]
+//     otel-observe [--service-name NAME] [--endpoint URL] -- mycmd args...
+//
+// Only the lineage can be seen from here: no context is propagated and the
+// target's own spans aren't linked to these (that takes the shim). Needs
+// root, or CAP_BPF and CAP_PERFMON.
+
+// the opentelemetry crates by their plain names, as in lib.rs
+#[cfg(feature = "otel-0_31")]
+extern crate opentelemetry_0_31 as opentelemetry;
+#[cfg(feature = "otel-0_31")]
+extern crate opentelemetry_otlp_0_31 as opentelemetry_otlp;
+#[cfg(feature = "otel-0_31")]
+extern crate opentelemetry_sdk_0_31 as opentelemetry_sdk;
+
+#[cfg(target_os = "linux")]
+mod bpf;
+#[cfg(target_os = "linux")]
+mod elf;
+#[cfg(target_os = "linux")]
+mod lineage;
+#[cfg(target_os = "linux")]
+mod observe;
+#[cfg(target_os = "linux")]
+mod perf;
+
+#[cfg(target_os = "linux")]
+use observe::observe;
+use std::ffi::OsString;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+usage: otel-observe [OPTIONS] --pid <pid> [--pid <pid>...]
+       otel-observe [OPTIONS] -- <command> [args...]
+
+Traces thread creation in the running processes <pid>, or in <command>, with
+eBPF programs on uprobes, and exports it as spans, without loading anything
+into them. Needs root, or CAP_BPF and CAP_PERFMON.
+
+options:
+  --service-name <name>   service.name of the spans (default: the process's
+                          OTEL_SERVICE_NAME, then its comm)
+  --endpoint <url>        OTLP/HTTP endpoint (default: $OTEL_EXPORTER_OTLP_ENDPOINT)
+  -h, --help              print this help";
+
+#[derive(Debug, Default, PartialEq)]
+struct ObserveArgs {
+    service_name: Option<String>,
+    endpoint: Option<String>,
+    pids: Vec<u32>,
+    command: Vec<OsString>,
+}
+
+fn parse_args(args: impl IntoIterator<Item = OsString>) -> Result<ObserveArgs, String> {
+    let mut observe = ObserveArgs::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .map(|v| v.to_string_lossy().into_owned())
+                .ok_or_else(|| format!("{flag} requires a value"))
+        };
+        match arg.to_str() {
+            Some("--") => {
+                observe.command = args.collect();
+                break;
+            }
+            Some("--service-name") => observe.service_name = Some(value("--service-name")?),
+            Some("--endpoint") => observe.endpoint = Some(value("--endpoint")?),
+            Some("--pid") => {
+                let pid = value("--pid")?;
+                observe.pids.push(
+                    pid.parse()
+                        .ok()
+                        .filter(|&pid| pid > 0)
+                        .ok_or_else(|| format!("{pid:?} is not a process id"))?,
+                );
+            }
+            _ => return Err(format!("unexpected argument {arg:?}")),
+        }
+    }
+    match (observe.pids.is_empty(), observe.command.is_empty()) {
+        (true, true) => Err("missing --pid or command after --".into()),
+        (false, false) => Err("--pid and a command can't be combined".into()),
+        _ => Ok(observe),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn observe(_: ObserveArgs) -> ExitCode {
+    eprintln!("otel-observe: only supported on Linux");
+    ExitCode::FAILURE
+}
+
+fn main() -> ExitCode {
+    let args: Vec<OsString> = std::env::args_os().skip(1).collect();
+    if matches!(args.first().and_then(|a| a.to_str()), Some("-h" | "--help")) {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    match parse_args(args) {
+        Ok(args) => observe(args),
+        Err(e) => {
+            eprintln!("otel-observe: {e}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn os(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn parses_pids_or_a_command() {
+        let observe = parse_args(os(&["--pid", "10", "--pid", "11"])).unwrap();
+        assert_eq!(observe.pids, [10, 11]);
+        let observe =
+            parse_args(os(&["--endpoint", "http://c:4318", "--", "app", "--pid"])).unwrap();
+        assert_eq!(observe.endpoint.as_deref(), Some("http://c:4318"));
+        assert_eq!(observe.command, os(&["app", "--pid"]));
+
+        assert!(parse_args(os(&[])).is_err());
+        assert!(parse_args(os(&["--pid", "app"])).is_err());
+        assert!(parse_args(os(&["--pid", "10", "--", "app"])).is_err());
+    }
+}
@@ -0,0 +1,417 @@
+// src/bin/otel-observe/observe.rs
+//
+// The observer itself: sets the probes, follows the processes, and feeds
+// their records, in time order across CPUs, through the lineage to one
+// tracer provider per process, each with that process's resource.
+
+use crate::ObserveArgs;
+use crate::elf;
+use crate::lineage::{Finished, Lineage};
+use crate::perf::{Libc, Probes, Record};
+use opentelemetry::trace::{
+    Span, SpanContext, SpanKind, TraceContextExt, TraceFlags, TraceState, Tracer, TracerProvider,
+};
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use std::collections::{BTreeMap, HashMap};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitCode};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+
+// how long records are held back, for those from other CPUs to catch up
+const REORDER_WINDOW: Duration = Duration::from_millis(50);
+const POLL: Duration = Duration::from_millis(100);
+
+static STOP: AtomicBool = AtomicBool::new(false);
+
+fn monotonic() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// The libc process `pid` has mapped (`self` for this one), in this process's view.
+fn libc_of(pid: &str) -> Option<PathBuf> {
+    let maps = std::fs::read_to_string(format!("/proc/{pid}/maps")).ok()?;
+    let libc = elf::mappings(&maps).into_iter().find(|m| {
+        m.path.file_name().is_some_and(|name| {
+            let name = name.to_string_lossy();
+            name.starts_with("libc.so") || name.starts_with("libc-")
+        })
+    })?;
+    Some(match pid.parse() {
+        Ok(pid) => elf::in_root(pid, &libc.path),
+        Err(_) => libc.path,
+    })
+}
+
+/// The libcs to probe, each file once however many processes use it.
+fn libcs(pids: &[u32]) -> Result<Vec<Libc>, String> {
+    let paths: Vec<PathBuf> = if pids.is_empty() {
+        // a command we start, which will use ours
+        vec![libc_of("self").ok_or("can't find this process's libc")?]
+    } else {
+        pids.iter()
+            .map(|pid| libc_of(&pid.to_string()).ok_or(format!("can't find the libc of {pid}")))
+            .collect::<Result<_, _>>()?
+    };
+    let mut seen = Vec::new();
+    let mut libcs = Vec::new();
+    for path in paths {
+        let meta = std::fs::metadata(&path).map_err(|e| format!("{}: {e}", path.display()))?;
+        if seen.contains(&(meta.dev(), meta.ino())) {
+            continue;
+        }
+        seen.push((meta.dev(), meta.ino()));
+        libcs.push(Libc::find(&path).map_err(|e| e.to_string())?);
+    }
+    Ok(libcs)
+}
+
+/// The value of `key` in process `pid`'s environment, as it was started with.
+fn environ(pid: u32, key: &str) -> Option<String> {
+    let environ = std::fs::read(format!("/proc/{pid}/environ")).ok()?;
+    environ.split(|&b| b == 0).find_map(|entry| {
+        let entry = std::str::from_utf8(entry).ok()?;
+        entry
+            .strip_prefix(key)?
+            .strip_prefix('=')
+            .filter(|v| !v.trim().is_empty())
+            .map(|v| v.trim().to_string())
+    })
+}
+
+/// Process `pid`'s service name and executable, as far as /proc tells, while it's there.
+fn process(pid: u32, service_name: Option<&str>) -> (String, Option<PathBuf>) {
+    let comm = std::fs::read_to_string(format!("/proc/{pid}/comm")).unwrap_or_default();
+    let name = service_name
+        .map(str::to_string)
+        .or_else(|| environ(pid, "OTEL_SERVICE_NAME"))
+        .unwrap_or_else(|| comm.trim().to_string());
+    (name, std::fs::read_link(format!("/proc/{pid}/exe")).ok())
+}
+
+fn resource(pid: u32, service_name: String, exe: Option<&Path>) -> Resource {
+    let mut attributes = vec![KeyValue::new("process.pid", i64::from(pid))];
+    if let Some(exe) = exe {
+        if let Some(file) = exe.file_name() {
+            attributes.push(KeyValue::new(
+                "process.executable.name",
+                file.to_string_lossy().into_owned(),
+            ));
+        }
+        if exe.is_absolute() {
+            attributes.push(KeyValue::new(
+                "process.executable.path",
+                exe.to_string_lossy().into_owned(),
+            ));
+        }
+    }
+    Resource::builder_empty()
+        .with_service_name(service_name)
+        .with_attributes(attributes)
+        .build()
+}
+
+fn provider(resource: Resource, endpoint: Option<&str>) -> Option<SdkTracerProvider> {
+    let mut builder = opentelemetry_otlp::SpanExporter::builder().with_http();
+    if let Some(endpoint) = endpoint {
+        use opentelemetry_otlp::WithExportConfig;
+        // as OTEL_EXPORTER_OTLP_ENDPOINT is taken, the base the signal's path goes on
+        builder = builder.with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')));
+    }
+    match builder.build() {
+        Ok(exporter) => Some(
+            SdkTracerProvider::builder()
+                .with_resource(resource)
+                .with_batch_exporter(exporter)
+                .build(),
+        ),
+        Err(e) => {
+            eprintln!("otel-observe: failed to build OTLP span exporter: {e}");
+            None
+        }
+    }
+}
+
+/// Turns the observer's records into spans, one tracer provider per process.
+struct Exporter {
+    endpoint: Option<String>,
+    // each process's resource until its first span, then its provider (if it could be
+    // built); building one takes longer than short-lived processes live
+    resources: HashMap<u32, Resource>,
+    providers: HashMap<u32, Option<SdkTracerProvider>>,
+    symbols: elf::Symbolizer,
+    // CLOCK_REALTIME - CLOCK_MONOTONIC
+    realtime: u64,
+}
+
+impl Exporter {
+    fn new(args: &ObserveArgs) -> Exporter {
+        let realtime = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        Exporter {
+            endpoint: args.endpoint.clone(),
+            resources: HashMap::new(),
+            providers: HashMap::new(),
+            symbols: elf::Symbolizer::default(),
+            realtime: realtime - monotonic(),
+        }
+    }
+
+    fn follow(&mut self, lineage: &mut Lineage, pid: u32, start: u64, resource: Resource) {
+        self.resources.insert(pid, resource);
+        lineage.follow(pid, start);
+    }
+
+    fn time(&self, monotonic: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_nanos(monotonic + self.realtime)
+    }
+
+    fn export(&mut self, finished: Finished) {
+        let (resources, endpoint) = (&mut self.resources, self.endpoint.as_deref());
+        let provider = self
+            .providers
+            .entry(finished.pid)
+            .or_insert_with(|| provider(resources.remove(&finished.pid)?, endpoint));
+        let Some(provider) = provider else {
+            return;
+        };
+        let tracer = provider.tracer("otel_posix_pseudo_propegator");
+        let mut attributes = vec![KeyValue::new("thread.id", i64::from(finished.tid))];
+        if let Some(creator) = finished.creator {
+            attributes.push(KeyValue::new(
+                "otel_posix.thread.creator.id",
+                i64::from(creator),
+            ));
+        }
+        if let Some(start_routine) = finished.start_routine {
+            let (function, object) = self.symbols.name(finished.pid, start_routine);
+            attributes.extend(function.map(|name| KeyValue::new("code.function", name)));
+            attributes.extend(object.map(|name| KeyValue::new("code.namespace", name)));
+        }
+        if finished.pthread_exit {
+            attributes.push(KeyValue::new("otel_posix.thread.pthread_exit", true));
+        }
+        if finished.running {
+            attributes.push(KeyValue::new("otel_posix.thread.running", true));
+        }
+        let parent = match finished.parent {
+            Some(parent) => Context::new().with_remote_span_context(SpanContext::new(
+                finished.trace_id,
+                parent,
+                TraceFlags::SAMPLED,
+                false,
+                TraceState::default(),
+            )),
+            None => Context::new(),
+        };
+        let name = if finished.parent.is_some() {
+            "thread"
+        } else {
+            "process"
+        };
+        let mut span = tracer
+            .span_builder(name)
+            .with_kind(SpanKind::Internal)
+            .with_trace_id(finished.trace_id)
+            .with_span_id(finished.span_id)
+            .with_start_time(self.time(finished.start))
+            .with_attributes(attributes)
+            .start_with_context(&tracer, &parent);
+        span.end_with_timestamp(self.time(finished.end));
+    }
+
+    fn shutdown(self) {
+        for provider in self.providers.values().flatten() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("otel-observe: failed to export spans: {e}");
+            }
+        }
+    }
+}
+
+/// Holds records back until those from other CPUs can't come before them any more.
+#[derive(Default)]
+struct Reorder {
+    records: BTreeMap<(u64, u64), Record>,
+    seq: u64,
+}
+
+impl Reorder {
+    fn push(&mut self, record: Record) {
+        self.seq += 1;
+        self.records.insert((record.time(), self.seq), record);
+    }
+
+    /// The records from before `until`, in order.
+    fn release(&mut self, until: u64) -> Vec<Record> {
+        let later = self.records.split_off(&(until, 0));
+        std::mem::replace(&mut self.records, later)
+            .into_values()
+            .collect()
+    }
+}
+
+extern "C" fn stop(_: libc::c_int) {
+    STOP.store(true, Ordering::Relaxed);
+}
+
+fn alive(pid: u32) -> bool {
+    let alive = unsafe { libc::kill(pid as libc::pid_t, 0) } == 0;
+    alive || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+pub(crate) fn observe(args: ObserveArgs) -> ExitCode {
+    let libcs = match libcs(&args.pids) {
+        Ok(libcs) => libcs,
+        Err(e) => {
+            eprintln!("otel-observe: {e}");
+            return ExitCode::from(2);
+        }
+    };
+    let probes = match Probes::open(&libcs) {
+        Ok(probes) => probes,
+        Err(e) => {
+            eprintln!("otel-observe: can't set the probes: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    unsafe {
+        libc::signal(libc::SIGINT, stop as *const () as libc::sighandler_t);
+        libc::signal(libc::SIGTERM, stop as *const () as libc::sighandler_t);
+    }
+
+    let mut exporter = Exporter::new(&args);
+    let mut lineage = Lineage::default();
+    let mut child: Option<Child> = None;
+    if args.command.is_empty() {
+        let now = monotonic();
+        for &pid in &args.pids {
+            let (name, exe) = process(pid, args.service_name.as_deref());
+            exporter.follow(&mut lineage, pid, now, resource(pid, name, exe.as_deref()));
+        }
+    } else {
+        let start = monotonic();
+        match Command::new(&args.command[0])
+            .args(&args.command[1..])
+            .spawn()
+        {
+            Ok(spawned) => {
+                // it may not be done exec'ing yet, so from what it was told, as for
+                // `otel-preload run`
+                let exe = Path::new(&args.command[0]);
+                let name = args
+                    .service_name
+                    .clone()
+                    .or_else(|| std::env::var("OTEL_SERVICE_NAME").ok())
+                    .filter(|name| !name.trim().is_empty())
+                    .or_else(|| exe.file_name().map(|n| n.to_string_lossy().into_owned()))
+                    .unwrap_or_default();
+                let resource = resource(spawned.id(), name, Some(exe));
+                exporter.follow(&mut lineage, spawned.id(), start, resource);
+                child = Some(spawned);
+            }
+            Err(e) => {
+                eprintln!("otel-observe: failed to run {:?}: {e}", args.command[0]);
+                return ExitCode::from(127);
+            }
+        }
+    }
+
+    let mut reorder = Reorder::default();
+    let mut status = None;
+    loop {
+        let done = STOP.load(Ordering::Relaxed)
+            || match &mut child {
+                Some(child) => {
+                    status = status.or(child.try_wait().ok().flatten());
+                    status.is_some()
+                }
+                None => !args.pids.iter().any(|&pid| alive(pid)),
+            };
+        // one more read, for what came before the end
+        let timeout = if done { Duration::ZERO } else { POLL };
+        probes.read(timeout, |record| {
+            if let Record::Create {
+                pid, start_routine, ..
+            } = record
+                && lineage.follows(pid)
+            {
+                exporter.symbols.note(pid, start_routine);
+            }
+            reorder.push(record);
+        });
+        let until = if done {
+            u64::MAX
+        } else {
+            monotonic().saturating_sub(REORDER_WINDOW.as_nanos() as u64)
+        };
+        for record in reorder.release(until) {
+            if let Some(finished) = lineage.record(record) {
+                exporter.export(finished);
+            }
+        }
+        if done || !lineage.running() {
+            break;
+        }
+    }
+    for finished in lineage.finish(monotonic()) {
+        exporter.export(finished);
+    }
+    if lineage.lost() > 0 {
+        eprintln!(
+            "otel-observe: the kernel dropped {} records, some threads are missing",
+            lineage.lost()
+        );
+    }
+    exporter.shutdown();
+
+    let Some(mut child) = child else {
+        return ExitCode::SUCCESS;
+    };
+    if status.is_none() && STOP.load(Ordering::Relaxed) {
+        let _ = child.kill();
+    }
+    // its exit record can come before it can be waited for
+    match status.or_else(|| child.wait().ok()) {
+        Some(status) => match (status.code(), status.signal()) {
+            (Some(code), _) => ExitCode::from(code as u8),
+            (None, Some(signal)) => ExitCode::from(128 + signal as u8),
+            (None, None) => ExitCode::FAILURE,
+        },
+        None => ExitCode::FAILURE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn releases_records_in_time_order() {
+        let exit = |tid, time| Record::Exit { pid: 1, tid, time };
+        let mut reorder = Reorder::default();
+        reorder.push(exit(3, 30));
+        reorder.push(exit(1, 10));
+        reorder.push(exit(2, 10));
+        assert_eq!(reorder.release(20), [exit(1, 10), exit(2, 10)]);
+        assert_eq!(reorder.release(u64::MAX), [exit(3, 30)]);
+    }
+
+    #[test]
+    fn probes_its_own_libc() {
+        let path = libc_of("self").expect("linked against libc");
+        assert!(Libc::find(&path).is_ok());
+        assert_eq!(libcs(&[std::process::id()]).unwrap().len(), 1);
+    }
+}
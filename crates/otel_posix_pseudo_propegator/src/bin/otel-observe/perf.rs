@@ -0,0 +1,439 @@
+// src/bin/otel-observe/perf.rs
+//
+// The kernel side of observer mode: a uprobe on each libc's pthread_create
+// and pthread_exit runs a BPF program (bpf.rs), which writes what it saw to
+// the calling CPU's ring buffer, the one that CPU's fork and exit records
+// go to. It takes the uprobe PMU (Linux 4.17 and later), and root or
+// CAP_BPF and CAP_PERFMON.
+//
+// A uprobe is set on a file rather than a process, so every process using
+// that libc hits it; the records are filtered by process in lineage.rs.
+
+use crate::bpf;
+use crate::elf::Elf;
+use libc::{c_int, c_ulong};
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// not in the libc crate: from <linux/perf_event.h>
+const PERF_TYPE_SOFTWARE: u32 = 1;
+const PERF_COUNT_SW_BPF_OUTPUT: u64 = 10;
+const PERF_SAMPLE_RAW: u64 = 1 << 10;
+const ATTR_TASK: u64 = 1 << 13;
+const ATTR_USE_CLOCKID: u64 = 1 << 25;
+const PERF_RECORD_LOST: u32 = 2;
+const PERF_RECORD_EXIT: u32 = 4;
+const PERF_RECORD_FORK: u32 = 7;
+const PERF_RECORD_SAMPLE: u32 = 9;
+const PERF_EVENT_IOC_SET_BPF: c_ulong = 0x4004_2408;
+const PERF_FLAG_FD_CLOEXEC: c_ulong = 8;
+// data_head and data_tail in struct perf_event_mmap_page
+const DATA_HEAD: usize = 1024;
+const DATA_TAIL: usize = 1032;
+// the ring buffer's data pages per CPU, a power of two
+const PAGES: usize = 64;
+
+// struct perf_event_attr, as of PERF_ATTR_SIZE_VER5
+#[repr(C)]
+#[derive(Default)]
+struct Attr {
+    kind: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+    config2: u64,
+    branch_sample_type: u64,
+    sample_regs_user: u64,
+    sample_stack_user: u32,
+    clockid: i32,
+    sample_regs_intr: u64,
+    aux_watermark: u32,
+    sample_max_stack: u16,
+    reserved: u16,
+}
+
+/// What the probes saw. Times are CLOCK_MONOTONIC nanoseconds, ids the kernel's: `pid`
+/// is the thread group (the process), `tid` the thread.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Record {
+    /// A thread called pthread_create with `start_routine`.
+    Create {
+        pid: u32,
+        tid: u32,
+        time: u64,
+        start_routine: u64,
+    },
+    /// A thread called pthread_exit.
+    PthreadExit { pid: u32, tid: u32, time: u64 },
+    /// Thread `ptid` of process `ppid` cloned thread `tid` of process `pid`: a new
+    /// thread when the two processes are the same, a new process otherwise.
+    Clone {
+        pid: u32,
+        ppid: u32,
+        tid: u32,
+        ptid: u32,
+        time: u64,
+    },
+    /// A thread exited.
+    Exit { pid: u32, tid: u32, time: u64 },
+    /// The kernel dropped this many records for want of room.
+    Lost(u64),
+}
+
+impl Record {
+    pub(crate) fn time(&self) -> u64 {
+        match *self {
+            Record::Create { time, .. }
+            | Record::PthreadExit { time, .. }
+            | Record::Clone { time, .. }
+            | Record::Exit { time, .. } => time,
+            Record::Lost(_) => 0,
+        }
+    }
+}
+
+// what the programs write to tell the probes apart
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Probe {
+    Create = 1,
+    PthreadExit = 2,
+}
+
+/// A libc to probe: the file, and the file offsets of its pthread_create and pthread_exit.
+pub(crate) struct Libc {
+    path: PathBuf,
+    create: u64,
+    exit: u64,
+}
+
+impl Libc {
+    pub(crate) fn find(path: &Path) -> io::Result<Libc> {
+        let elf = Elf::read(path)?;
+        let offset = |name: &str| {
+            elf.function(name)
+                .and_then(|vaddr| elf.offset(vaddr))
+                .ok_or_else(|| io::Error::other(format!("{} has no {name}", path.display())))
+        };
+        Ok(Libc {
+            path: path.to_path_buf(),
+            create: offset("pthread_create")?,
+            exit: offset("pthread_exit")?,
+        })
+    }
+}
+
+/// One CPU's ring buffer, mapped from its output event.
+struct Ring {
+    map: *mut u8,
+    page: usize,
+}
+
+impl Ring {
+    fn map(fd: &OwnedFd) -> io::Result<Ring> {
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let map = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                (PAGES + 1) * page,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Ring {
+            map: map.cast(),
+            page,
+        })
+    }
+
+    fn position(&self, at: usize) -> &AtomicU64 {
+        unsafe { &*self.map.add(at).cast::<AtomicU64>() }
+    }
+
+    /// Hands each record written since the last call to `each`, and frees its room.
+    fn drain(&self, mut each: impl FnMut(&[u8])) {
+        let head = self.position(DATA_HEAD).load(Ordering::Acquire);
+        let mut tail = self.position(DATA_TAIL).load(Ordering::Relaxed);
+        let size = (PAGES * self.page) as u64;
+        let data = unsafe { std::slice::from_raw_parts(self.map.add(self.page), size as usize) };
+        // records can wrap around the end
+        let at = |pos: u64| data[(pos % size) as usize];
+        let mut record = Vec::new();
+        while tail < head {
+            let len = u64::from(u16::from_ne_bytes([at(tail + 6), at(tail + 7)]));
+            if len == 0 {
+                break;
+            }
+            record.clear();
+            record.extend((tail..tail + len).map(at));
+            each(&record);
+            tail += len;
+        }
+        self.position(DATA_TAIL).store(tail, Ordering::Release);
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.map.cast(), (PAGES + 1) * self.page) };
+    }
+}
+
+/// The probes, and the output events they write to on every CPU.
+pub(crate) struct Probes {
+    rings: Vec<Ring>,
+    outputs: Vec<OwnedFd>,
+    // the array, the programs and the uprobes running them, open while observing
+    _attached: Vec<OwnedFd>,
+}
+
+/// The PMU the kernel registered uprobes as.
+fn uprobe_pmu() -> io::Result<u32> {
+    std::fs::read_to_string("/sys/bus/event_source/devices/uprobe/type")?
+        .trim()
+        .parse()
+        .map_err(io::Error::other)
+}
+
+fn open(attr: &Attr, cpu: c_int) -> io::Result<OwnedFd> {
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_perf_event_open,
+            attr as *const Attr,
+            -1 as libc::pid_t,
+            cpu,
+            -1 as c_int,
+            PERF_FLAG_FD_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as c_int) })
+}
+
+impl Probes {
+    /// Sets the probes on `libcs`, writing to every online CPU.
+    pub(crate) fn open(libcs: &[Libc]) -> io::Result<Probes> {
+        let pmu = uprobe_pmu().map_err(|e| io::Error::other(format!("no uprobe PMU: {e}")))?;
+        let paths = libcs
+            .iter()
+            .map(|libc| CString::new(libc.path.as_os_str().as_bytes()).map_err(io::Error::other))
+            .collect::<io::Result<Vec<_>>>()?;
+        let cpus = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) }.max(1) as c_int;
+        let array = bpf::array(cpus as u32)?;
+        let mut probes = Probes {
+            rings: Vec::new(),
+            outputs: Vec::new(),
+            _attached: Vec::new(),
+        };
+        let mut online = None;
+        for cpu in 0..cpus {
+            // the CPU's fork and exit records too
+            let attr = Attr {
+                kind: PERF_TYPE_SOFTWARE,
+                size: size_of::<Attr>() as u32,
+                config: PERF_COUNT_SW_BPF_OUTPUT,
+                sample_period: 1,
+                sample_type: PERF_SAMPLE_RAW,
+                flags: ATTR_TASK | ATTR_USE_CLOCKID,
+                wakeup_events: 1,
+                clockid: libc::CLOCK_MONOTONIC,
+                ..Attr::default()
+            };
+            let fd = match open(&attr, cpu) {
+                Ok(fd) => fd,
+                // offline
+                Err(e) if e.raw_os_error() == Some(libc::ENODEV) => continue,
+                Err(e) => return Err(e),
+            };
+            bpf::set(&array, cpu as u32, &fd)?;
+            probes.rings.push(Ring::map(&fd)?);
+            probes.outputs.push(fd);
+            online.get_or_insert(cpu);
+        }
+        let Some(cpu) = online else {
+            return Err(io::Error::other("no CPU to probe on"));
+        };
+        for (probe, start_routine) in [(Probe::Create, true), (Probe::PthreadExit, false)] {
+            let program = bpf::load(&array, probe as u64, start_routine)
+                .map_err(|e| io::Error::new(e.kind(), format!("can't load the program: {e}")))?;
+            for (libc, path) in libcs.iter().zip(&paths) {
+                let attr = Attr {
+                    kind: pmu,
+                    size: size_of::<Attr>() as u32,
+                    config1: path.as_ptr() as u64,
+                    config2: match probe {
+                        Probe::Create => libc.create,
+                        Probe::PthreadExit => libc.exit,
+                    },
+                    ..Attr::default()
+                };
+                // the program runs whichever CPU the probe is hit on
+                let fd = open(&attr, cpu)?;
+                if unsafe {
+                    libc::ioctl(fd.as_raw_fd(), PERF_EVENT_IOC_SET_BPF, program.as_raw_fd())
+                } != 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+                probes._attached.push(fd);
+            }
+            probes._attached.push(program);
+        }
+        probes._attached.push(array);
+        Ok(probes)
+    }
+
+    /// Waits up to `timeout` for records, then hands over every one there is.
+    pub(crate) fn read(&self, timeout: Duration, mut each: impl FnMut(Record)) {
+        let mut fds: Vec<libc::pollfd> = self
+            .outputs
+            .iter()
+            .map(|fd| libc::pollfd {
+                fd: fd.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        let timeout = timeout.as_millis().min(c_int::MAX as u128) as c_int;
+        unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+        for ring in &self.rings {
+            ring.drain(|record| {
+                if let Some(record) = parse(record) {
+                    each(record);
+                }
+            });
+        }
+    }
+}
+
+/// The record in `bytes`, if it's one the observer wants.
+fn parse(bytes: &[u8]) -> Option<Record> {
+    let u32_at = |at: usize| Some(u32::from_ne_bytes(bytes.get(at..at + 4)?.try_into().ok()?));
+    let u64_at = |at: usize| Some(u64::from_ne_bytes(bytes.get(at..at + 8)?.try_into().ok()?));
+    match u32_at(0)? {
+        PERF_RECORD_SAMPLE => {
+            // the raw data's size, then what the program wrote (see bpf::OUTPUT)
+            if (u32_at(8)? as usize) < bpf::OUTPUT {
+                return None;
+            }
+            let (tid, pid, time) = (u32_at(12)?, u32_at(16)?, u64_at(20)?);
+            match u64_at(28)? {
+                kind if kind == Probe::Create as u64 => Some(Record::Create {
+                    pid,
+                    tid,
+                    time,
+                    start_routine: u64_at(36)?,
+                }),
+                kind if kind == Probe::PthreadExit as u64 => {
+                    Some(Record::PthreadExit { pid, tid, time })
+                }
+                _ => None,
+            }
+        }
+        PERF_RECORD_FORK => Some(Record::Clone {
+            pid: u32_at(8)?,
+            ppid: u32_at(12)?,
+            tid: u32_at(16)?,
+            ptid: u32_at(20)?,
+            time: u64_at(24)?,
+        }),
+        PERF_RECORD_EXIT => Some(Record::Exit {
+            pid: u32_at(8)?,
+            tid: u32_at(16)?,
+            time: u64_at(24)?,
+        }),
+        PERF_RECORD_LOST => Some(Record::Lost(u64_at(16)?)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(kind: u32, fields: &[u64]) -> Vec<u8> {
+        let mut bytes = kind.to_ne_bytes().to_vec();
+        bytes.extend_from_slice(&0u16.to_ne_bytes());
+        bytes.extend_from_slice(&((8 + fields.len() * 8) as u16).to_ne_bytes());
+        for field in fields {
+            bytes.extend_from_slice(&field.to_ne_bytes());
+        }
+        bytes
+    }
+
+    /// Two u32s as they sit in one u64 slot.
+    fn pair(a: u32, b: u32) -> u64 {
+        let mut bytes = [0; 8];
+        bytes[..4].copy_from_slice(&a.to_ne_bytes());
+        bytes[4..].copy_from_slice(&b.to_ne_bytes());
+        u64::from_ne_bytes(bytes)
+    }
+
+    /// A sample as the programs write it: the raw data's size, padded so that the
+    /// record stays 8-byte aligned, then the data.
+    fn sample(fields: &[u64]) -> Vec<u8> {
+        let mut bytes = record(PERF_RECORD_SAMPLE, &[]);
+        bytes.extend_from_slice(&((fields.len() * 8 + 4) as u32).to_ne_bytes());
+        for field in fields {
+            bytes.extend_from_slice(&field.to_ne_bytes());
+        }
+        bytes.extend_from_slice(&[0; 4]);
+        let len = bytes.len() as u16;
+        bytes[6..8].copy_from_slice(&len.to_ne_bytes());
+        bytes
+    }
+
+    #[test]
+    fn parses_the_records_it_asked_for() {
+        let create = sample(&[pair(101, 100), 5_000, 1, 0x4011d6]);
+        assert_eq!(
+            parse(&create),
+            Some(Record::Create {
+                pid: 100,
+                tid: 101,
+                time: 5_000,
+                start_routine: 0x4011d6
+            })
+        );
+        let exit = sample(&[pair(102, 100), 6_000, 2, 0]);
+        assert_eq!(
+            parse(&exit),
+            Some(Record::PthreadExit {
+                pid: 100,
+                tid: 102,
+                time: 6_000
+            })
+        );
+        let fork = record(PERF_RECORD_FORK, &[pair(100, 100), pair(102, 101), 5_500]);
+        assert_eq!(
+            parse(&fork),
+            Some(Record::Clone {
+                pid: 100,
+                ppid: 100,
+                tid: 102,
+                ptid: 101,
+                time: 5_500
+            })
+        );
+        // not from either program
+        assert_eq!(parse(&sample(&[pair(101, 100), 5_000, 3, 0])), None);
+        assert_eq!(parse(&sample(&[pair(101, 100), 5_000])), None);
+    }
+}
@@ -0,0 +1,473 @@
+// src/bin/otel-preload/inject.rs
+//
+// `otel-preload attach`: loads the propagator into a process that is
+// already running, for services that can't be restarted under LD_PRELOAD.
+// It stops the process's main thread with ptrace and borrows it to call
+// setenv for the OTEL_* variables, dlopen for the library, and the
+// library's otel_posix_attach (src/attach.rs), then puts the thread back
+// the way it was. The library's constructors install the exporter as it
+// loads; otel_posix_attach points the bindings the process made at startup
+// (pthread_create and the rest) at the library, as LD_PRELOAD would have.
+//
+// The functions are found in the process at their offset in libc, so it
+// has to run the same libc file as this executable: a process in a
+// container with a libc of its own, or a static one, is turned away. The
+// thread is only borrowed while it is blocked in a system call, where it
+// can't be holding one of libc's locks (malloc's, say) that the calls
+// need; one that stays busy is retried for a while, then given up on.
+
+use libc::{c_int, c_long, c_void, pid_t};
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io;
+use std::mem::{size_of, take};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::time::Duration;
+
+#[cfg(target_env = "gnu")]
+type Request = libc::c_uint;
+#[cfg(not(target_env = "gnu"))]
+type Request = c_int;
+
+// how often, 10ms apart, to look for the thread blocked in a system call
+const RETRIES: usize = 200;
+
+fn ptrace(request: Request, pid: pid_t, addr: usize, data: usize) -> io::Result<c_long> {
+    let rc = unsafe { libc::ptrace(request, pid, addr as *mut c_void, data as *mut c_void) };
+    if rc == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(rc)
+    }
+}
+
+/// Reads a register set of the stopped thread into `regs`.
+fn get_regset<T>(pid: pid_t, set: c_int, regs: &mut T) -> io::Result<()> {
+    let mut iov = libc::iovec {
+        iov_base: (regs as *mut T).cast(),
+        iov_len: size_of::<T>(),
+    };
+    ptrace(
+        libc::PTRACE_GETREGSET,
+        pid,
+        set as usize,
+        &raw mut iov as usize,
+    )
+    .map(drop)
+}
+
+/// Writes a register set of the stopped thread from `regs`.
+fn set_regset<T>(pid: pid_t, set: c_int, regs: &T) -> io::Result<()> {
+    let mut iov = libc::iovec {
+        iov_base: (regs as *const T).cast_mut().cast(),
+        iov_len: size_of::<T>(),
+    };
+    ptrace(
+        libc::PTRACE_SETREGSET,
+        pid,
+        set as usize,
+        &raw mut iov as usize,
+    )
+    .map(drop)
+}
+
+#[cfg(target_arch = "x86_64")]
+mod arch {
+    use super::{get_regset, set_regset};
+    use libc::pid_t;
+    use std::io;
+
+    /// The thread's registers, as they were when it was stopped.
+    #[derive(Clone, Copy)]
+    pub(super) struct Saved(libc::user_regs_struct);
+
+    // the return address goes on the stack
+    pub(super) const PUSHES_RETURN: bool = true;
+
+    pub(super) fn save(pid: pid_t) -> io::Result<Saved> {
+        let mut regs = unsafe { std::mem::zeroed() };
+        get_regset(pid, libc::NT_PRSTATUS, &mut regs)?;
+        Ok(Saved(regs))
+    }
+
+    pub(super) fn restore(pid: pid_t, saved: &Saved) -> io::Result<()> {
+        set_regset(pid, libc::NT_PRSTATUS, &saved.0)
+    }
+
+    impl Saved {
+        /// Whether the thread was stopped blocked in a system call.
+        pub(super) fn in_syscall(&self) -> bool {
+            self.0.orig_rax as i64 >= 0
+        }
+
+        pub(super) fn sp(&self) -> u64 {
+            self.0.rsp
+        }
+    }
+
+    /// Sets the thread up to call `func` with `args` on stack `sp`.
+    pub(super) fn call(
+        pid: pid_t,
+        saved: &Saved,
+        func: u64,
+        args: [u64; 3],
+        sp: u64,
+    ) -> io::Result<()> {
+        let mut regs = saved.0;
+        regs.rip = func;
+        [regs.rdi, regs.rsi, regs.rdx] = args;
+        regs.rsp = sp;
+        regs.rax = 0;
+        // or the kernel would take the new registers for an interrupted call to restart
+        regs.orig_rax = u64::MAX;
+        set_regset(pid, libc::NT_PRSTATUS, &regs)
+    }
+
+    /// Where the thread is, and what it returned.
+    pub(super) fn returned(pid: pid_t) -> io::Result<(u64, u64)> {
+        let Saved(regs) = save(pid)?;
+        Ok((regs.rip, regs.rax))
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arch {
+    use super::{get_regset, set_regset};
+    use libc::{c_int, pid_t};
+    use std::io;
+
+    // not in the libc crate
+    const NT_ARM_SYSTEM_CALL: c_int = 0x404;
+
+    /// The thread's registers, and the system call it was in, as they were when it was
+    /// stopped.
+    #[derive(Clone, Copy)]
+    pub(super) struct Saved(libc::user_regs_struct, c_int);
+
+    // the return address goes in the link register
+    pub(super) const PUSHES_RETURN: bool = false;
+
+    pub(super) fn save(pid: pid_t) -> io::Result<Saved> {
+        let mut regs = unsafe { std::mem::zeroed() };
+        get_regset(pid, libc::NT_PRSTATUS, &mut regs)?;
+        let mut syscall: c_int = -1;
+        get_regset(pid, NT_ARM_SYSTEM_CALL, &mut syscall)?;
+        Ok(Saved(regs, syscall))
+    }
+
+    pub(super) fn restore(pid: pid_t, saved: &Saved) -> io::Result<()> {
+        set_regset(pid, NT_ARM_SYSTEM_CALL, &saved.1)?;
+        set_regset(pid, libc::NT_PRSTATUS, &saved.0)
+    }
+
+    impl Saved {
+        /// Whether the thread was stopped blocked in a system call.
+        pub(super) fn in_syscall(&self) -> bool {
+            self.1 >= 0
+        }
+
+        pub(super) fn sp(&self) -> u64 {
+            self.0.sp
+        }
+    }
+
+    /// Sets the thread up to call `func` with `args` on stack `sp`.
+    pub(super) fn call(
+        pid: pid_t,
+        saved: &Saved,
+        func: u64,
+        args: [u64; 3],
+        sp: u64,
+    ) -> io::Result<()> {
+        let mut regs = saved.0;
+        regs.pc = func;
+        regs.regs[..3].copy_from_slice(&args);
+        regs.regs[30] = 0;
+        regs.sp = sp;
+        // or the kernel would take the new registers for an interrupted call to restart
+        set_regset(pid, NT_ARM_SYSTEM_CALL, &(-1 as c_int))?;
+        set_regset(pid, libc::NT_PRSTATUS, &regs)
+    }
+
+    /// Where the thread is, and what it returned.
+    pub(super) fn returned(pid: pid_t) -> io::Result<(u64, u64)> {
+        let Saved(regs, _) = save(pid)?;
+        Ok((regs.pc, regs.regs[0]))
+    }
+}
+
+/// Waits for the thread to stop, and returns the signal it stopped to be handed, or 0 if
+/// it stopped for ptrace itself.
+fn wait_stop(pid: pid_t) -> io::Result<c_int> {
+    let mut status = 0;
+    while unsafe { libc::waitpid(pid, &mut status, libc::__WALL) } == -1 {
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+    if !libc::WIFSTOPPED(status) {
+        return Err(io::Error::other("the process exited"));
+    }
+    // PTRACE_EVENT_STOP, for the interrupt or a group stop
+    if status >> 16 != 0 {
+        return Ok(0);
+    }
+    Ok(libc::WSTOPSIG(status))
+}
+
+/// The main thread of a process, stopped and borrowed; it goes back as it was on drop.
+struct Tracee {
+    pid: pid_t,
+    mem: File,
+    saved: arch::Saved,
+    // the bottom of the scratch space under the thread's stack
+    scratch: u64,
+    // a signal that arrived while the thread was borrowed, handed over on the way out
+    pending: c_int,
+}
+
+impl Tracee {
+    fn seize(pid: pid_t) -> io::Result<Tracee> {
+        ptrace(libc::PTRACE_SEIZE, pid, 0, 0)?;
+        let mut pending = 0;
+        let stopped = (|| {
+            for _ in 0..RETRIES {
+                ptrace(libc::PTRACE_INTERRUPT, pid, 0, 0)?;
+                match wait_stop(pid)? {
+                    0 => {}
+                    signal => pending = signal,
+                }
+                let saved = arch::save(pid)?;
+                if saved.in_syscall() {
+                    let mem = File::options()
+                        .read(true)
+                        .write(true)
+                        .open(format!("/proc/{pid}/mem"))?;
+                    return Ok((saved, mem));
+                }
+                ptrace(libc::PTRACE_CONT, pid, 0, take(&mut pending) as usize)?;
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            Err(io::Error::other(
+                "its main thread never blocked in a system call; try again when it is idle",
+            ))
+        })();
+        match stopped {
+            Ok((saved, mem)) => Ok(Tracee {
+                pid,
+                mem,
+                // clear of the red zone below the stack pointer
+                scratch: saved.sp() - 256,
+                saved,
+                pending,
+            }),
+            Err(e) => {
+                let _ = ptrace(libc::PTRACE_DETACH, pid, 0, pending as usize);
+                Err(e)
+            }
+        }
+    }
+
+    /// Copies `bytes` to the scratch space, and returns their address.
+    fn push(&mut self, bytes: &[u8]) -> io::Result<u64> {
+        self.scratch = (self.scratch - bytes.len() as u64) & !15;
+        self.mem.write_all_at(bytes, self.scratch)?;
+        Ok(self.scratch)
+    }
+
+    /// Copies `s` to the scratch space, and returns its address.
+    fn push_str(&mut self, s: &CStr) -> io::Result<u64> {
+        self.push(s.to_bytes_with_nul())
+    }
+
+    /// Reads the string at `addr`, up to a limit.
+    fn read_str(&self, addr: u64) -> String {
+        let mut buf = [0u8; 512];
+        let n = self.mem.read_at(&mut buf, addr).unwrap_or(0);
+        let end = buf[..n].iter().position(|&b| b == 0).unwrap_or(n);
+        String::from_utf8_lossy(&buf[..end]).into_owned()
+    }
+
+    /// Calls `func` in the process with up to three arguments, and returns what it returned.
+    fn call(&mut self, func: u64, args: &[u64]) -> io::Result<u64> {
+        let mut regs = [0; 3];
+        regs[..args.len()].copy_from_slice(args);
+        let mut sp = (self.scratch - 256) & !15;
+        if arch::PUSHES_RETURN {
+            // a return address of 0 stops the thread with a SIGSEGV as the call returns
+            sp -= 8;
+            self.mem.write_all_at(&0u64.to_ne_bytes(), sp)?;
+        }
+        arch::call(self.pid, &self.saved, func, regs, sp)?;
+        loop {
+            ptrace(libc::PTRACE_CONT, self.pid, 0, 0)?;
+            match wait_stop(self.pid)? {
+                0 => {}
+                libc::SIGSEGV => {
+                    let (pc, value) = arch::returned(self.pid)?;
+                    return if pc == 0 {
+                        Ok(value)
+                    } else {
+                        Err(io::Error::other(format!(
+                            "the call to {func:#x} crashed at {pc:#x}"
+                        )))
+                    };
+                }
+                signal => self.pending = signal,
+            }
+        }
+    }
+}
+
+impl Drop for Tracee {
+    fn drop(&mut self) {
+        let _ = arch::restore(self.pid, &self.saved);
+        let _ = ptrace(libc::PTRACE_DETACH, self.pid, 0, self.pending as usize);
+    }
+}
+
+/// The device and inode of the file mapped at `start` in `maps`.
+fn mapped_file(maps: &str, start: u64) -> Option<(&str, &str)> {
+    maps.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let (range, _, _, dev, inode) = (
+            fields.next()?,
+            fields.next()?,
+            fields.next()?,
+            fields.next()?,
+            fields.next()?,
+        );
+        let from = u64::from_str_radix(range.split_once('-')?.0, 16).ok()?;
+        (from == start).then_some((dev, inode))
+    })
+}
+
+/// Where the file with device and inode `id` starts in `maps`.
+fn file_base(maps: &str, id: (&str, &str)) -> Option<u64> {
+    maps.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        let (range, _, offset, dev, inode) = (
+            fields.next()?,
+            fields.next()?,
+            fields.next()?,
+            fields.next()?,
+            fields.next()?,
+        );
+        let at_start = u64::from_str_radix(offset, 16).ok()? == 0;
+        (at_start && (dev, inode) == id)
+            .then(|| u64::from_str_radix(range.split_once('-')?.0, 16).ok())
+            .flatten()
+    })
+}
+
+/// libc's functions in the target, at the same offsets as in this process.
+struct Libc {
+    handle: *mut c_void,
+    // this process's libc base, and the target's
+    ours: u64,
+    theirs: u64,
+}
+
+impl Libc {
+    fn find(pid: pid_t) -> io::Result<Libc> {
+        let handle =
+            unsafe { libc::dlopen(c"libc.so.6".as_ptr(), libc::RTLD_NOW | libc::RTLD_NOLOAD) };
+        if handle.is_null() {
+            return Err(io::Error::other("this executable has no libc.so.6"));
+        }
+        let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+        let sym = unsafe { libc::dlsym(handle, c"dlopen".as_ptr()) };
+        if sym.is_null() || unsafe { libc::dladdr(sym, &mut info) } == 0 {
+            return Err(io::Error::other("can't find dlopen in libc.so.6"));
+        }
+        let ours = info.dli_fbase as u64;
+        let own_maps = std::fs::read_to_string("/proc/self/maps")?;
+        let id = mapped_file(&own_maps, ours)
+            .ok_or_else(|| io::Error::other("can't find libc.so.6 in /proc/self/maps"))?;
+        let maps = std::fs::read_to_string(format!("/proc/{pid}/maps"))?;
+        let theirs = file_base(&maps, id).ok_or_else(|| {
+            let name = unsafe { CStr::from_ptr(info.dli_fname) }.to_string_lossy();
+            io::Error::other(format!("it doesn't run {name}, which this executable does"))
+        })?;
+        Ok(Libc {
+            handle,
+            ours,
+            theirs,
+        })
+    }
+
+    /// The address of libc's `name` in the target.
+    fn function(&self, name: &CStr) -> io::Result<u64> {
+        let sym = unsafe { libc::dlsym(self.handle, name.as_ptr()) };
+        if sym.is_null() {
+            return Err(io::Error::other(format!("libc has no {name:?}")));
+        }
+        Ok(sym as u64 - self.ours + self.theirs)
+    }
+}
+
+/// Loads `lib` into process `pid` with `vars` set, and has it take over the process's
+/// bindings. `vars` are (name, value, whether to replace a value the process has already).
+/// Returns the number of bindings it took over.
+pub(crate) fn attach(pid: pid_t, lib: &Path, vars: &[(&str, String, bool)]) -> io::Result<i32> {
+    let libc = Libc::find(pid)?;
+    let setenv = libc.function(c"setenv")?;
+    let dlopen = libc.function(c"dlopen")?;
+    let dlsym = libc.function(c"dlsym")?;
+    let dlerror = libc.function(c"dlerror")?;
+    let cstring = |s: &[u8]| CString::new(s).map_err(io::Error::other);
+
+    let mut tracee = Tracee::seize(pid)?;
+    for (name, value, replace) in vars {
+        let name = tracee.push_str(&cstring(name.as_bytes())?)?;
+        let value = tracee.push_str(&cstring(value.as_bytes())?)?;
+        tracee.call(setenv, &[name, value, *replace as u64])?;
+    }
+    let path = tracee.push_str(&cstring(lib.as_os_str().as_bytes())?)?;
+    let flags = (libc::RTLD_NOW | libc::RTLD_GLOBAL) as u64;
+    let handle = tracee.call(dlopen, &[path, flags])?;
+    if handle == 0 {
+        let message = tracee.call(dlerror, &[])?;
+        return Err(io::Error::other(format!(
+            "dlopen failed in the process: {}",
+            tracee.read_str(message)
+        )));
+    }
+    let name = tracee.push_str(c"otel_posix_attach")?;
+    let attach = tracee.call(dlsym, &[handle, name])?;
+    if attach == 0 {
+        return Err(io::Error::other(format!(
+            "{} has no otel_posix_attach; build it with the preload feature",
+            lib.display()
+        )));
+    }
+    match tracee.call(attach, &[])? as i32 {
+        -1 => Err(io::Error::other(
+            "the library couldn't find itself in the process",
+        )),
+        bindings => Ok(bindings),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPS: &str = "\
+55d0c0000000-55d0c0001000 r--p 00000000 fd:01 1234 /usr/bin/app
+7f0000000000-7f0000028000 r--p 00000000 fd:01 5678 /usr/lib/x86_64-linux-gnu/libc.so.6
+7f0000028000-7f000019d000 r-xp 00028000 fd:01 5678 /usr/lib/x86_64-linux-gnu/libc.so.6
+7ffd00000000-7ffd00021000 rw-p 00000000 00:00 0 [stack]";
+
+    #[test]
+    fn finds_libc_by_file() {
+        let id = mapped_file(MAPS, 0x7f0000000000).unwrap();
+        assert_eq!(id, ("fd:01", "5678"));
+        assert_eq!(file_base(MAPS, id), Some(0x7f0000000000));
+        assert_eq!(file_base(MAPS, ("fd:01", "9999")), None);
+        assert_eq!(mapped_file(MAPS, 0x7f0000028001), None);
+    }
+}
@@ -0,0 +1,625 @@
+// src/bin/otel-preload/main.rs
+//
+// Launcher that runs a command with the propagator preloaded:
+//
+//     otel-preload run [--lib PATH] [--service-name NAME] [--endpoint URL] -- mycmd args...
+//
+// It finds the built shared library, prepends it to LD_PRELOAD (keeping any
+// existing entries), fills in the standard OTEL_* variables and execs the
+// target, so nobody has to hand-roll LD_PRELOAD incantations again.
+//
+//     otel-preload attach [--lib PATH] [--service-name NAME] [--endpoint URL] PID
+//
+// loads it into a process that is already running instead (see inject.rs).
+//
+//     otel-preload record [--output FILE] [--lib PATH] [--service-name NAME] -- mycmd args...
+//     otel-preload report [--folded] [FILE]
+//
+// run the command with every span of its process tree written to a local
+// file instead of a collector, and print what was recorded as a tree per
+// trace (see report.rs), to look at propagation without a collector.
+
+#[cfg(all(
+    target_os = "linux",
+    target_env = "gnu",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod inject;
+mod report;
+
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+#[cfg(target_os = "macos")]
+const PRELOAD_VAR: &str = "DYLD_INSERT_LIBRARIES";
+#[cfg(not(target_os = "macos"))]
+const PRELOAD_VAR: &str = "LD_PRELOAD";
+
+#[cfg(target_os = "macos")]
+const LIB_NAME: &str = "libotel_posix_pseudo_propegator.dylib";
+#[cfg(not(target_os = "macos"))]
+const LIB_NAME: &str = "libotel_posix_pseudo_propegator.so";
+
+const USAGE: &str = "\
+usage: otel-preload run [OPTIONS] -- <command> [args...]
+       otel-preload attach [OPTIONS] <pid>
+       otel-preload record [--output <file>] [OPTIONS] -- <command> [args...]
+       otel-preload report [--folded] [<file>]
+
+Runs <command> with the OpenTelemetry pthread propagator preloaded, or loads it
+into the running process <pid> with ptrace. `record` runs <command> with the
+spans of its whole process tree written to <file> instead of a collector, and
+`report` prints what was recorded as a tree per trace, or with --folded as
+folded stacks for flamegraph.pl.
+
+options:
+  --lib <path>            shared library to preload (default: $OTEL_POSIX_PROP_LIB,
+                          then next to this executable, then the system lib dirs)
+  --service-name <name>   OTEL_SERVICE_NAME for the child (default: command name),
+                          or for <pid> unless it has one (default: its comm)
+  --endpoint <url>        OTEL_EXPORTER_OTLP_ENDPOINT for the child or <pid>
+  --output <file>         where `record` writes the spans (default: otel-spans.jsonl,
+                          which `report` reads unless given another)
+  -h, --help              print this help";
+
+const DEFAULT_OUTPUT: &str = "otel-spans.jsonl";
+
+#[derive(Debug, Default, PartialEq)]
+struct RunArgs {
+    lib: Option<PathBuf>,
+    service_name: Option<String>,
+    endpoint: Option<String>,
+    command: Vec<OsString>,
+}
+
+fn parse_run_args(args: impl IntoIterator<Item = OsString>) -> Result<RunArgs, String> {
+    let mut run = RunArgs::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .ok_or_else(|| format!("{flag} requires a value"))
+        };
+        match arg.to_str() {
+            Some("--") => {
+                run.command = args.collect();
+                break;
+            }
+            Some("--lib") => run.lib = Some(value("--lib")?.into()),
+            Some("--service-name") => {
+                run.service_name = Some(value("--service-name")?.to_string_lossy().into_owned())
+            }
+            Some("--endpoint") => {
+                run.endpoint = Some(value("--endpoint")?.to_string_lossy().into_owned())
+            }
+            _ => return Err(format!("unexpected argument {arg:?}")),
+        }
+    }
+    if run.command.is_empty() {
+        return Err("missing command after --".into());
+    }
+    Ok(run)
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct AttachArgs {
+    lib: Option<PathBuf>,
+    service_name: Option<String>,
+    endpoint: Option<String>,
+    pid: i32,
+}
+
+fn parse_attach_args(args: impl IntoIterator<Item = OsString>) -> Result<AttachArgs, String> {
+    let mut attach = AttachArgs::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .ok_or_else(|| format!("{flag} requires a value"))
+        };
+        match arg.to_str() {
+            Some("--lib") => attach.lib = Some(value("--lib")?.into()),
+            Some("--service-name") => {
+                attach.service_name = Some(value("--service-name")?.to_string_lossy().into_owned())
+            }
+            Some("--endpoint") => {
+                attach.endpoint = Some(value("--endpoint")?.to_string_lossy().into_owned())
+            }
+            Some(pid) if attach.pid == 0 => {
+                attach.pid = pid
+                    .parse()
+                    .ok()
+                    .filter(|&pid| pid > 0)
+                    .ok_or_else(|| format!("{pid:?} is not a process id"))?
+            }
+            _ => return Err(format!("unexpected argument {arg:?}")),
+        }
+    }
+    if attach.pid == 0 {
+        return Err("missing process id".into());
+    }
+    Ok(attach)
+}
+
+#[derive(Debug, PartialEq)]
+struct RecordArgs {
+    output: PathBuf,
+    run: RunArgs,
+}
+
+fn parse_record_args(args: impl IntoIterator<Item = OsString>) -> Result<RecordArgs, String> {
+    let mut output = PathBuf::from(DEFAULT_OUTPUT);
+    let mut rest = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--output") => {
+                output = args
+                    .next()
+                    .ok_or_else(|| "--output requires a value".to_string())?
+                    .into()
+            }
+            Some("--endpoint") => return Err("record writes to a file, not to --endpoint".into()),
+            Some("--") => {
+                rest.push(arg);
+                rest.extend(args);
+                break;
+            }
+            _ => rest.push(arg),
+        }
+    }
+    Ok(RecordArgs {
+        output,
+        run: parse_run_args(rest)?,
+    })
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct ReportArgs {
+    folded: bool,
+    file: Option<PathBuf>,
+}
+
+fn parse_report_args(args: impl IntoIterator<Item = OsString>) -> Result<ReportArgs, String> {
+    let mut report = ReportArgs::default();
+    for arg in args {
+        match arg.to_str() {
+            Some("--folded") => report.folded = true,
+            Some(flag) if flag.starts_with('-') => {
+                return Err(format!("unexpected argument {arg:?}"));
+            }
+            _ if report.file.is_none() => report.file = Some(arg.into()),
+            _ => return Err(format!("unexpected argument {arg:?}")),
+        }
+    }
+    Ok(report)
+}
+
+/// Finds the shared library to preload, in order of precedence.
+fn locate_lib(explicit: Option<&Path>) -> Result<PathBuf, String> {
+    if let Some(path) = explicit {
+        return if path.is_file() {
+            Ok(path.to_path_buf())
+        } else {
+            Err(format!("{} does not exist", path.display()))
+        };
+    }
+
+    let mut candidates = Vec::new();
+    if let Some(path) = env::var_os("OTEL_POSIX_PROP_LIB") {
+        candidates.push(PathBuf::from(path));
+    }
+    // cargo puts the cdylib next to the binaries in target/<profile>/
+    if let Some(dir) = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        candidates.push(dir.join(LIB_NAME));
+        candidates.push(dir.join("../lib").join(LIB_NAME));
+    }
+    candidates.push(Path::new("/usr/local/lib").join(LIB_NAME));
+    candidates.push(Path::new("/usr/lib").join(LIB_NAME));
+
+    candidates
+        .into_iter()
+        .find(|path| path.is_file())
+        .map(|path| path.canonicalize().unwrap_or(path))
+        .ok_or_else(|| format!("could not find {LIB_NAME}, pass --lib or set OTEL_POSIX_PROP_LIB"))
+}
+
+/// Prepends `lib` to an existing preload list, dropping any earlier copy of it.
+///
+/// The loader accepts both colons and spaces as separators, so both are split on.
+fn compose_preload(lib: &Path, existing: Option<&OsStr>) -> OsString {
+    let mut out = OsString::from(lib);
+    let existing = existing
+        .map(|e| e.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    for entry in existing.split([':', ' ']).filter(|e| !e.is_empty()) {
+        if Path::new(entry) != lib {
+            out.push(":");
+            out.push(entry);
+        }
+    }
+    out
+}
+
+/// The OTEL_* variables to set for the child; anything already set by the caller wins,
+/// except for values passed explicitly on the command line.
+fn otel_env(
+    run: &RunArgs,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> Vec<(&'static str, OsString)> {
+    let mut vars = Vec::new();
+    let service_name = run.service_name.clone().map(OsString::from).or_else(|| {
+        var("OTEL_SERVICE_NAME").is_none().then(|| {
+            Path::new(&run.command[0])
+                .file_name()
+                .unwrap_or(&run.command[0])
+                .to_os_string()
+        })
+    });
+    if let Some(name) = service_name {
+        vars.push(("OTEL_SERVICE_NAME", name));
+    }
+    if let Some(endpoint) = &run.endpoint {
+        vars.push(("OTEL_EXPORTER_OTLP_ENDPOINT", endpoint.into()));
+    }
+    // the shim's exporter only speaks OTLP over HTTP
+    if var("OTEL_EXPORTER_OTLP_PROTOCOL").is_none() {
+        vars.push(("OTEL_EXPORTER_OTLP_PROTOCOL", "http/protobuf".into()));
+    }
+    if var("OTEL_PROPAGATORS").is_none() {
+        vars.push(("OTEL_PROPAGATORS", "tracecontext,baggage".into()));
+    }
+    vars
+}
+
+/// The OTEL_* variables to set in an attached process, and whether each replaces a value
+/// it has already: the ones given on the command line do, the defaults don't.
+fn attach_env(attach: &AttachArgs, comm: &str) -> Vec<(&'static str, String, bool)> {
+    let mut vars = vec![match &attach.service_name {
+        Some(name) => ("OTEL_SERVICE_NAME", name.clone(), true),
+        None => ("OTEL_SERVICE_NAME", comm.to_string(), false),
+    }];
+    if let Some(endpoint) = &attach.endpoint {
+        vars.push(("OTEL_EXPORTER_OTLP_ENDPOINT", endpoint.clone(), true));
+    }
+    // the shim's exporter only speaks OTLP over HTTP
+    vars.push(("OTEL_EXPORTER_OTLP_PROTOCOL", "http/protobuf".into(), false));
+    vars.push(("OTEL_PROPAGATORS", "tracecontext,baggage".into(), false));
+    vars
+}
+
+/// The variables `record` sets on top of [`otel_env`]'s: the file, and a span for every
+/// thread and child process, unless the caller chose otherwise.
+fn record_env(
+    output: &Path,
+    var: impl Fn(&str) -> Option<OsString>,
+) -> Vec<(&'static str, OsString)> {
+    let mut vars = vec![
+        ("OTEL_POSIX_PROP_TRACES_FILE", output.into()),
+        // the shim's own metrics have nowhere to go
+        ("OTEL_METRICS_EXPORTER", "none".into()),
+    ];
+    for (key, value) in [
+        ("OTEL_POSIX_PROP_MODE", "lifetime"),
+        ("OTEL_POSIX_PROP_AUTO_ROOT", "on"),
+        ("OTEL_POSIX_PROP_CHILD_SPANS", "on"),
+    ] {
+        if var(key).is_none() {
+            vars.push((key, value.into()));
+        }
+    }
+    vars
+}
+
+/// The command to run for `args`, with the library preloaded and the OTEL_* variables set.
+fn command(args: &RunArgs) -> Result<Command, ExitCode> {
+    let lib = match locate_lib(args.lib.as_deref()) {
+        Ok(lib) => lib,
+        Err(e) => {
+            eprintln!("otel-preload: {e}");
+            return Err(ExitCode::from(2));
+        }
+    };
+
+    let mut cmd = Command::new(&args.command[0]);
+    cmd.args(&args.command[1..]);
+    cmd.env(
+        PRELOAD_VAR,
+        compose_preload(&lib, env::var_os(PRELOAD_VAR).as_deref()),
+    );
+    for (key, value) in otel_env(args, |key| env::var_os(key)) {
+        cmd.env(key, value);
+    }
+    Ok(cmd)
+}
+
+/// Why `command` couldn't be started, with the shell's exit code for it: 127 not found,
+/// 126 found but not runnable.
+fn not_started(command: &OsStr, err: std::io::Error) -> ExitCode {
+    eprintln!("otel-preload: failed to run {command:?}: {err}");
+    if err.kind() == std::io::ErrorKind::NotFound {
+        ExitCode::from(127)
+    } else {
+        ExitCode::from(126)
+    }
+}
+
+fn run(args: RunArgs) -> ExitCode {
+    let mut cmd = match command(&args) {
+        Ok(cmd) => cmd,
+        Err(code) => return code,
+    };
+    // exec only returns on failure
+    not_started(&args.command[0], cmd.exec())
+}
+
+fn record(args: RecordArgs) -> ExitCode {
+    // the processes of the tree may each run somewhere else
+    let output = match std::path::absolute(&args.output) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("otel-preload: {}: {e}", args.output.display());
+            return ExitCode::from(2);
+        }
+    };
+    if let Err(e) = std::fs::File::create(&output) {
+        eprintln!("otel-preload: can't write {}: {e}", output.display());
+        return ExitCode::from(2);
+    }
+    let mut cmd = match command(&args.run) {
+        Ok(cmd) => cmd,
+        Err(code) => return code,
+    };
+    for (key, value) in record_env(&output, |key| env::var_os(key)) {
+        cmd.env(key, value);
+    }
+    let status = match cmd.status() {
+        Ok(status) => status,
+        Err(e) => return not_started(&args.run.command[0], e),
+    };
+    let spans = std::fs::read_to_string(&output).map_or(0, |s| s.lines().count());
+    eprintln!(
+        "otel-preload: {spans} spans written to {}, see `otel-preload report {}`",
+        output.display(),
+        args.output.display()
+    );
+    // like the shell: the command's exit code, or 128 and the signal that killed it
+    let code = status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1);
+    ExitCode::from(code as u8)
+}
+
+fn show_report(args: ReportArgs) -> ExitCode {
+    let file = args.file.unwrap_or_else(|| DEFAULT_OUTPUT.into());
+    let input = match std::fs::read_to_string(&file) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("otel-preload: can't read {}: {e}", file.display());
+            return ExitCode::from(2);
+        }
+    };
+    let (spans, skipped) = report::parse(&input);
+    if skipped > 0 {
+        eprintln!(
+            "otel-preload: skipped {skipped} lines of {} that aren't spans",
+            file.display()
+        );
+    }
+    if args.folded {
+        print!("{}", report::folded(&spans));
+    } else {
+        print!("{}", report::tree(&spans));
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(all(
+    target_os = "linux",
+    target_env = "gnu",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+fn attach(args: AttachArgs) -> ExitCode {
+    let lib = match locate_lib(args.lib.as_deref()).and_then(|lib| {
+        // the process resolves it from its own working directory
+        lib.canonicalize()
+            .map_err(|e| format!("{}: {e}", lib.display()))
+    }) {
+        Ok(lib) => lib,
+        Err(e) => {
+            eprintln!("otel-preload: {e}");
+            return ExitCode::from(2);
+        }
+    };
+    let comm = std::fs::read_to_string(format!("/proc/{}/comm", args.pid)).unwrap_or_default();
+    let vars = attach_env(&args, comm.trim());
+    match inject::attach(args.pid, &lib, &vars) {
+        Ok(bindings) => {
+            eprintln!(
+                "otel-preload: attached to {}, {bindings} bindings redirected",
+                args.pid
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("otel-preload: can't attach to {}: {e}", args.pid);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    target_env = "gnu",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+fn attach(_: AttachArgs) -> ExitCode {
+    eprintln!("otel-preload: attach is only supported on Linux with glibc, on x86_64 and aarch64");
+    ExitCode::FAILURE
+}
+
+fn main() -> ExitCode {
+    let mut args = env::args_os().skip(1);
+    match args.next().as_ref().and_then(|a| a.to_str()) {
+        Some("run") => match parse_run_args(args) {
+            Ok(run_args) => run(run_args),
+            Err(e) => {
+                eprintln!("otel-preload: {e}\n\n{USAGE}");
+                ExitCode::from(2)
+            }
+        },
+        Some("attach") => match parse_attach_args(args) {
+            Ok(attach_args) => attach(attach_args),
+            Err(e) => {
+                eprintln!("otel-preload: {e}\n\n{USAGE}");
+                ExitCode::from(2)
+            }
+        },
+        Some("record") => match parse_record_args(args) {
+            Ok(record_args) => record(record_args),
+            Err(e) => {
+                eprintln!("otel-preload: {e}\n\n{USAGE}");
+                ExitCode::from(2)
+            }
+        },
+        Some("report") => match parse_report_args(args) {
+            Ok(report_args) => show_report(report_args),
+            Err(e) => {
+                eprintln!("otel-preload: {e}\n\n{USAGE}");
+                ExitCode::from(2)
+            }
+        },
+        Some("-h" | "--help" | "help") => {
+            println!("{USAGE}");
+            ExitCode::SUCCESS
+        }
+        _ => {
+            eprintln!("{USAGE}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn os(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn parses_options_and_command() {
+        let run = parse_run_args(os(&[
+            "--lib",
+            "/tmp/lib.so",
+            "--endpoint",
+            "http://collector:4318",
+            "--",
+            "mycmd",
+            "--lib",
+        ]))
+        .unwrap();
+        assert_eq!(run.lib.as_deref(), Some(Path::new("/tmp/lib.so")));
+        assert_eq!(run.endpoint.as_deref(), Some("http://collector:4318"));
+        assert_eq!(run.command, os(&["mycmd", "--lib"]));
+    }
+
+    #[test]
+    fn rejects_missing_command_and_values() {
+        assert!(parse_run_args(os(&["--"])).is_err());
+        assert!(parse_run_args(os(&["mycmd"])).is_err());
+        assert!(parse_run_args(os(&["--lib"])).is_err());
+    }
+
+    #[test]
+    fn preload_keeps_existing_entries_once() {
+        let lib = Path::new("/opt/otel/lib.so");
+        assert_eq!(compose_preload(lib, None), "/opt/otel/lib.so");
+        assert_eq!(
+            compose_preload(
+                lib,
+                Some(OsStr::new("libjemalloc.so /opt/otel/lib.so:libfoo.so"))
+            ),
+            "/opt/otel/lib.so:libjemalloc.so:libfoo.so"
+        );
+    }
+
+    #[test]
+    fn env_defaults_do_not_clobber_caller() {
+        let run = parse_run_args(os(&["--", "/usr/bin/mycmd"])).unwrap();
+        let vars = otel_env(&run, |_| None);
+        assert!(vars.contains(&("OTEL_SERVICE_NAME", "mycmd".into())));
+        assert!(vars.contains(&("OTEL_EXPORTER_OTLP_PROTOCOL", "http/protobuf".into())));
+
+        let vars = otel_env(&run, |_| Some("set".into()));
+        assert!(vars.is_empty());
+    }
+
+    #[test]
+    fn parses_record_and_report_options() {
+        let record = parse_record_args(os(&[
+            "--output",
+            "/tmp/spans.jsonl",
+            "--service-name",
+            "app",
+            "--",
+            "make",
+            "--output",
+        ]))
+        .unwrap();
+        assert_eq!(record.output, Path::new("/tmp/spans.jsonl"));
+        assert_eq!(record.run.service_name.as_deref(), Some("app"));
+        assert_eq!(record.run.command, os(&["make", "--output"]));
+        let record = parse_record_args(os(&["--", "make"])).unwrap();
+        assert_eq!(record.output, Path::new(DEFAULT_OUTPUT));
+        assert!(parse_record_args(os(&["--endpoint", "http://c:4318", "--", "make"])).is_err());
+        assert!(parse_record_args(os(&["--output"])).is_err());
+
+        assert_eq!(parse_report_args(os(&[])).unwrap(), ReportArgs::default());
+        let report = parse_report_args(os(&["--folded", "spans.jsonl"])).unwrap();
+        assert!(report.folded);
+        assert_eq!(report.file.as_deref(), Some(Path::new("spans.jsonl")));
+        assert!(parse_report_args(os(&["a", "b"])).is_err());
+        assert!(parse_report_args(os(&["--tree"])).is_err());
+    }
+
+    #[test]
+    fn record_env_keeps_the_callers_mode() {
+        let output = Path::new("/tmp/spans.jsonl");
+        let vars = record_env(output, |_| None);
+        assert!(vars.contains(&("OTEL_POSIX_PROP_TRACES_FILE", "/tmp/spans.jsonl".into())));
+        assert!(vars.contains(&("OTEL_POSIX_PROP_MODE", "lifetime".into())));
+        let vars = record_env(output, |key| {
+            (key == "OTEL_POSIX_PROP_MODE").then(|| "links".into())
+        });
+        assert!(!vars.iter().any(|(key, _)| *key == "OTEL_POSIX_PROP_MODE"));
+        assert!(vars.contains(&("OTEL_POSIX_PROP_AUTO_ROOT", "on".into())));
+    }
+
+    #[test]
+    fn parses_attach_options_and_pid() {
+        let attach =
+            parse_attach_args(os(&["--endpoint", "http://collector:4318", "4242"])).unwrap();
+        assert_eq!(attach.pid, 4242);
+        assert_eq!(attach.endpoint.as_deref(), Some("http://collector:4318"));
+        assert!(parse_attach_args(os(&[])).is_err());
+        assert!(parse_attach_args(os(&["nginx"])).is_err());
+        assert!(parse_attach_args(os(&["1", "2"])).is_err());
+
+        let vars = attach_env(&attach, "nginx");
+        assert!(vars.contains(&("OTEL_SERVICE_NAME", "nginx".into(), false)));
+        assert!(vars.contains(&(
+            "OTEL_EXPORTER_OTLP_ENDPOINT",
+            "http://collector:4318".into(),
+            true
+        )));
+    }
+}
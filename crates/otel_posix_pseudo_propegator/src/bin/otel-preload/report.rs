@@ -0,0 +1,429 @@
+// src/bin/otel-preload/report.rs
+//
+// `otel-preload report`: reads the spans `record` had the shim write (a
+// line of JSON each, see src/file_export.rs) and prints them as a tree per
+// trace, with each span's duration and process:
+//
+//     trace 4bf92f3577b34da6a3ce929d0e0e4736: 3 spans, 2 processes
+//       sh  12.41ms  pid 4242
+//         thread  1.10ms  pid 4242
+//         exec /usr/bin/make  9.02ms  pid 4243
+//
+// or, with --folded, as folded stacks (`sh;thread 1100`, self time in
+// microseconds) for flamegraph.pl and the tools that read its input.
+//
+// The lines are only ever the shim's own, so a small JSON reader does.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+/// A span, as much of it as the report shows.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Span {
+    trace_id: String,
+    span_id: String,
+    parent_span_id: String,
+    name: String,
+    start: u128,
+    end: u128,
+    pid: u64,
+}
+
+impl Span {
+    fn duration(&self) -> u128 {
+        self.end.saturating_sub(self.start)
+    }
+}
+
+/// Reads the spans in `input`, skipping lines that aren't one; returns them and how many
+/// were skipped.
+pub(crate) fn parse(input: &str) -> (Vec<Span>, usize) {
+    let mut spans = Vec::new();
+    let mut skipped = 0;
+    for line in input.lines().filter(|l| !l.trim().is_empty()) {
+        match span(line) {
+            Some(span) => spans.push(span),
+            None => skipped += 1,
+        }
+    }
+    (spans, skipped)
+}
+
+fn span(line: &str) -> Option<Span> {
+    let Value::Object(fields) = Reader::new(line).document()? else {
+        return None;
+    };
+    let field = |key: &str| fields.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+    let text = |key: &str| match field(key) {
+        Some(Value::String(s)) => Some(s.clone()),
+        _ => None,
+    };
+    let number = |key: &str| match field(key) {
+        Some(Value::Number(n)) => n.parse::<u128>().ok(),
+        _ => None,
+    };
+    Some(Span {
+        trace_id: text("trace_id")?,
+        span_id: text("span_id")?,
+        parent_span_id: text("parent_span_id").unwrap_or_default(),
+        name: text("name")?,
+        start: number("start_unix_nano")?,
+        end: number("end_unix_nano")?,
+        pid: number("pid").unwrap_or(0) as u64,
+    })
+}
+
+/// The spans of each trace, as trees: the traces in the order they started, and in each
+/// the spans whose parent wasn't recorded as its roots.
+struct Forest<'a> {
+    traces: Vec<(&'a str, Vec<&'a Span>)>,
+    children: HashMap<(&'a str, &'a str), Vec<&'a Span>>,
+}
+
+impl<'a> Forest<'a> {
+    fn new(spans: &'a [Span]) -> Self {
+        let mut sorted: Vec<&Span> = spans.iter().collect();
+        sorted.sort_by_key(|s| s.start);
+        let known: HashSet<(&str, &str)> = sorted
+            .iter()
+            .map(|s| (s.trace_id.as_str(), s.span_id.as_str()))
+            .collect();
+        let mut traces: Vec<(&str, Vec<&Span>)> = Vec::new();
+        let mut children: HashMap<(&str, &str), Vec<&Span>> = HashMap::new();
+        for span in sorted {
+            let trace = span.trace_id.as_str();
+            let parent = (trace, span.parent_span_id.as_str());
+            if known.contains(&parent) {
+                children.entry(parent).or_default().push(span);
+                continue;
+            }
+            match traces.iter_mut().find(|(id, _)| *id == trace) {
+                Some((_, roots)) => roots.push(span),
+                None => traces.push((trace, vec![span])),
+            }
+        }
+        Forest { traces, children }
+    }
+
+    fn children(&self, span: &'a Span) -> &[&'a Span] {
+        self.children
+            .get(&(span.trace_id.as_str(), span.span_id.as_str()))
+            .map_or(&[], Vec::as_slice)
+    }
+}
+
+/// The spans as a tree per trace.
+pub(crate) fn tree(spans: &[Span]) -> String {
+    let forest = Forest::new(spans);
+    let mut out = String::new();
+    for (trace, roots) in &forest.traces {
+        let in_trace: Vec<&Span> = spans.iter().filter(|s| s.trace_id == *trace).collect();
+        let mut pids: Vec<u64> = in_trace.iter().map(|s| s.pid).collect();
+        pids.sort_unstable();
+        pids.dedup();
+        let _ = writeln!(
+            out,
+            "trace {trace}: {} {}, {} {}",
+            in_trace.len(),
+            plural(in_trace.len(), "span", "spans"),
+            pids.len(),
+            plural(pids.len(), "process", "processes"),
+        );
+        for root in roots {
+            let remote = (!root.parent_span_id.is_empty())
+                .then(|| format!("  (parent {})", root.parent_span_id));
+            branch(&forest, root, 1, remote.as_deref().unwrap_or(""), &mut out);
+        }
+    }
+    out
+}
+
+fn branch<'a>(forest: &Forest<'a>, span: &'a Span, depth: usize, note: &str, out: &mut String) {
+    let _ = writeln!(
+        out,
+        "{:indent$}{}  {}  pid {}{note}",
+        "",
+        span.name,
+        duration(span.duration()),
+        span.pid,
+        indent = depth * 2,
+    );
+    for child in forest.children(span) {
+        branch(forest, child, depth + 1, "", out);
+    }
+}
+
+/// The spans as folded stacks, `root;child;span <self time in µs>`, one per line.
+pub(crate) fn folded(spans: &[Span]) -> String {
+    let forest = Forest::new(spans);
+    let mut out = String::new();
+    for (_, roots) in &forest.traces {
+        for root in roots {
+            fold(&forest, root, &mut Vec::new(), &mut out);
+        }
+    }
+    out
+}
+
+fn fold<'a>(forest: &Forest<'a>, span: &'a Span, stack: &mut Vec<String>, out: &mut String) {
+    // `;` separates frames
+    stack.push(span.name.replace(';', ":"));
+    let children = forest.children(span);
+    let in_children: u128 = children.iter().map(|c| c.duration()).sum();
+    let own = span.duration().saturating_sub(in_children) / 1_000;
+    if own > 0 {
+        let _ = writeln!(out, "{} {own}", stack.join(";"));
+    }
+    for child in children {
+        fold(forest, child, stack, out);
+    }
+    stack.pop();
+}
+
+fn plural<'a>(n: usize, one: &'a str, many: &'a str) -> &'a str {
+    if n == 1 { one } else { many }
+}
+
+/// `ns` for people: 850ns, 12.41µs, 3.20ms, 1.50s.
+fn duration(ns: u128) -> String {
+    match ns {
+        0..1_000 => format!("{ns}ns"),
+        1_000..1_000_000 => format!("{:.2}µs", ns as f64 / 1e3),
+        1_000_000..1_000_000_000 => format!("{:.2}ms", ns as f64 / 1e6),
+        _ => format!("{:.2}s", ns as f64 / 1e9),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    // kept as written: nanosecond timestamps don't fit an f64
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+struct Reader<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a str) -> Self {
+        Reader { rest: input }
+    }
+
+    /// The one value in the input.
+    fn document(mut self) -> Option<Value> {
+        let value = self.value()?;
+        self.skip_space();
+        self.rest.is_empty().then_some(value)
+    }
+
+    fn skip_space(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_space();
+        match self.rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        self.skip_space();
+        match self.rest.chars().next()? {
+            '{' => self.object(),
+            '[' => self.array(),
+            '"' => self.string().map(Value::String),
+            't' if self.eat("true") => Some(Value::Bool(true)),
+            'f' if self.eat("false") => Some(Value::Bool(false)),
+            'n' if self.eat("null") => Some(Value::Null),
+            _ => self.number(),
+        }
+    }
+
+    fn object(&mut self) -> Option<Value> {
+        self.eat("{");
+        let mut fields = Vec::new();
+        if self.eat("}") {
+            return Some(Value::Object(fields));
+        }
+        loop {
+            self.skip_space();
+            let key = self.string()?;
+            if !self.eat(":") {
+                return None;
+            }
+            fields.push((key, self.value()?));
+            if self.eat("}") {
+                return Some(Value::Object(fields));
+            }
+            if !self.eat(",") {
+                return None;
+            }
+        }
+    }
+
+    fn array(&mut self) -> Option<Value> {
+        self.eat("[");
+        let mut items = Vec::new();
+        if self.eat("]") {
+            return Some(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            if self.eat("]") {
+                return Some(Value::Array(items));
+            }
+            if !self.eat(",") {
+                return None;
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let mut chars = self.rest.strip_prefix('"')?.char_indices();
+        let mut out = String::new();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.rest = &self.rest[1 + i + 1..];
+                    return Some(out);
+                }
+                '\\' => match chars.next()?.1 {
+                    'n' => out.push('\n'),
+                    't' => out.push('\t'),
+                    'r' => out.push('\r'),
+                    'b' => out.push('\u{8}'),
+                    'f' => out.push('\u{c}'),
+                    'u' => {
+                        let hex: String = (0..4)
+                            .filter_map(|_| chars.next())
+                            .map(|(_, c)| c)
+                            .collect();
+                        let code = u32::from_str_radix(&hex, 16).ok()?;
+                        out.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    c => out.push(c),
+                },
+                c => out.push(c),
+            }
+        }
+        None
+    }
+
+    fn number(&mut self) -> Option<Value> {
+        let end = self
+            .rest
+            .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+            .unwrap_or(self.rest.len());
+        if end == 0 {
+            return None;
+        }
+        let (number, rest) = self.rest.split_at(end);
+        self.rest = rest;
+        Some(Value::Number(number.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    fn line(span: &str, parent: &str, name: &str, start: u64, end: u64, pid: u32) -> String {
+        format!(
+            "{{\"trace_id\":\"{TRACE}\",\"span_id\":\"{span}\",\"parent_span_id\":\"{parent}\",\
+             \"name\":\"{name}\",\"kind\":\"internal\",\"start_unix_nano\":{start},\
+             \"end_unix_nano\":{end},\"status\":\"unset\",\"service\":\"app\",\"pid\":{pid},\
+             \"attributes\":{{\"thread.id\":{pid},\"code.function\":\"w\\\"x\",\"ok\":true}}}}\n"
+        )
+    }
+
+    fn sample() -> String {
+        [
+            line(
+                "0000000000000002",
+                "0000000000000001",
+                "thread",
+                2_000,
+                1_102_000,
+                10,
+            ),
+            line("0000000000000001", "", "main", 1_000, 12_411_000, 10),
+            line(
+                "0000000000000003",
+                "0000000000000001",
+                "exec make",
+                3_000,
+                9_023_000,
+                11,
+            ),
+            line(
+                "0000000000000009",
+                "00f067aa0ba902b7",
+                "seeded",
+                5_000,
+                5_850,
+                12,
+            ),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn reads_the_shims_lines() {
+        let (spans, skipped) = parse(&format!("{}not json\n{{\"trace_id\":1}}\n", sample()));
+        assert_eq!(skipped, 2);
+        assert_eq!(spans.len(), 4);
+        assert_eq!(spans[1].name, "main");
+        assert_eq!(spans[1].parent_span_id, "");
+        assert_eq!(spans[0].duration(), 1_100_000);
+        assert_eq!(
+            Reader::new(r#"{"a":[1,-2.5e3,null],"b":"é\n"}"#).document(),
+            Some(Value::Object(vec![
+                (
+                    "a".into(),
+                    Value::Array(vec![
+                        Value::Number("1".into()),
+                        Value::Number("-2.5e3".into()),
+                        Value::Null
+                    ])
+                ),
+                ("b".into(), Value::String("é\n".into())),
+            ]))
+        );
+    }
+
+    #[test]
+    fn prints_a_tree_per_trace() {
+        let (spans, _) = parse(&sample());
+        assert_eq!(
+            tree(&spans),
+            format!(
+                "trace {TRACE}: 4 spans, 3 processes\n\
+                 \x20 main  12.41ms  pid 10\n\
+                 \x20   thread  1.10ms  pid 10\n\
+                 \x20   exec make  9.02ms  pid 11\n\
+                 \x20 seeded  850ns  pid 12  (parent 00f067aa0ba902b7)\n"
+            )
+        );
+    }
+
+    #[test]
+    fn folds_stacks_by_self_time() {
+        let (spans, _) = parse(&sample());
+        assert_eq!(
+            folded(&spans),
+            "main 2290\nmain;thread 1100\nmain;exec make 9020\n"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+// src/callback.rs
+//
+// Context for C callback APIs the shim doesn't interpose (libevent, GLib
+// sources, signal-style registries). A callback's user-data pointer is
+// wrapped at registration together with the Context current there, and
+// unwrapped again when the callback runs, with that Context attached for
+// the call. The `#[otel_trampoline]` attribute (the `macros` feature)
+// writes the unwrapping side into the callback itself.
+
+use opentelemetry::{Context, ContextGuard};
+use std::ffi::c_void;
+
+/// What a callback gets instead of its user data: the data, and the Context it was
+/// registered under.
+struct Captured {
+    cx: Context,
+    data: *mut c_void,
+}
+
+/// Wraps `data` with the current `Context`, to register a callback with in its place.
+///
+/// The callback has to unwrap it again, with [`enter`] (or by being an
+/// `#[otel_trampoline]`). Free it with [`release`] once the callback can't run any more,
+/// or let the callback free it on its one call with [`enter_once`].
+///
+/// ```
+/// use otel_posix_pseudo_propegator::callback;
+/// use std::ffi::c_void;
+///
+/// extern "C" fn on_event(data: *mut c_void) {
+///     let (_guard, data) = unsafe { callback::enter(data) };
+///     // runs under the registering Context, with the original `data`
+/// #   assert!(data.is_null());
+/// }
+///
+/// let data = callback::capture(std::ptr::null_mut());
+/// on_event(data);
+/// unsafe { callback::release(data) };
+/// ```
+pub fn capture(data: *mut c_void) -> *mut c_void {
+    Box::into_raw(Box::new(Captured {
+        cx: Context::current(),
+        data,
+    }))
+    .cast()
+}
+
+/// Attaches the `Context` `captured` was made under, and returns the guard keeping it
+/// attached along with the original user data.
+///
+/// # Safety
+///
+/// `captured` must come from [`capture`] and not have been released.
+pub unsafe fn enter(captured: *mut c_void) -> (ContextGuard, *mut c_void) {
+    let captured = unsafe { &*captured.cast::<Captured>() };
+    (captured.cx.clone().attach(), captured.data)
+}
+
+/// Like [`enter`], for a callback that runs once: `captured` is freed.
+///
+/// # Safety
+///
+/// `captured` must come from [`capture`] and not have been released, and isn't valid
+/// afterwards.
+pub unsafe fn enter_once(captured: *mut c_void) -> (ContextGuard, *mut c_void) {
+    let captured = unsafe { Box::from_raw(captured.cast::<Captured>()) };
+    (captured.cx.attach(), captured.data)
+}
+
+/// Frees what [`capture`] returned, and gives back the original user data.
+///
+/// # Safety
+///
+/// `captured` must come from [`capture`] and not have been released, and the callback
+/// must not run with it any more.
+pub unsafe fn release(captured: *mut c_void) -> *mut c_void {
+    unsafe { Box::from_raw(captured.cast::<Captured>()) }.data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{
+        SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState,
+    };
+
+    fn span_id() -> SpanId {
+        Context::map_current(|cx| cx.span().span_context().span_id())
+    }
+
+    #[test]
+    fn captured_context_is_attached_for_the_call() {
+        let sc = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let mut value = 7;
+        let captured = {
+            let _registering = Context::new().with_remote_span_context(sc.clone()).attach();
+            capture((&raw mut value).cast())
+        };
+        assert_eq!(span_id(), SpanId::INVALID);
+        for _ in 0..2 {
+            let (guard, data) = unsafe { enter(captured) };
+            assert_eq!(span_id(), sc.span_id());
+            assert_eq!(unsafe { *data.cast::<i32>() }, 7);
+            drop(guard);
+            assert_eq!(span_id(), SpanId::INVALID);
+        }
+        assert_eq!(unsafe { release(captured) }, (&raw mut value).cast());
+
+        let (_guard, data) = unsafe { enter_once(capture(std::ptr::null_mut())) };
+        assert!(data.is_null());
+    }
+}
@@ -0,0 +1,136 @@
+// src/caller.rs
+//
+// Filtering by calling library. Inside a large third-party process only
+// the threads one's own library starts may be worth tracing, whatever
+// routine they run, so with OTEL_POSIX_PROP_CALLER_ALLOW set the stack is
+// walked up to the first frame outside this library, and the object that
+// frame lives in (found with dladdr) has to match one of the globs.
+//
+// The walk goes through the unwinder the Rust runtime links anyway, and
+// only happens when the allowlist is set. Under linker-wrap the shim is
+// part of the very object calling it, so only preload builds use this.
+
+use crate::filter::{Glob, parse_globs};
+use libc::c_int;
+use std::ffi::{CStr, c_void};
+use std::path::Path;
+
+// opaque, the unwinder's own
+#[repr(C)]
+pub(crate) struct UnwindContext {
+    _private: [u8; 0],
+}
+
+pub(crate) type TraceFn = extern "C" fn(*mut UnwindContext, *mut c_void) -> c_int;
+
+unsafe extern "C" {
+    // libgcc_s' or libunwind's, not exposed by the libc crate
+    pub(crate) fn _Unwind_Backtrace(trace: TraceFn, arg: *mut c_void) -> c_int;
+    pub(crate) fn _Unwind_GetIP(cx: *mut UnwindContext) -> usize;
+}
+
+pub(crate) const URC_NO_REASON: c_int = 0;
+pub(crate) const URC_NORMAL_STOP: c_int = 4;
+
+// frames looked at at most, should the stack never leave this library
+const MAX_FRAMES: usize = 64;
+
+/// Allowlist from `OTEL_POSIX_PROP_CALLER_ALLOW`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CallerFilter {
+    allow: Vec<Glob>,
+}
+
+impl CallerFilter {
+    /// Builds the filter from a comma-separated glob list; `None` if it is empty.
+    pub fn parse(allow: &str) -> Option<Self> {
+        let allow = parse_globs(allow);
+        (!allow.is_empty()).then_some(CallerFilter { allow })
+    }
+
+    /// Whether the object that called into the shim on this stack is allowed.
+    pub fn allows_caller(&self) -> bool {
+        self.allows_object(caller_object().as_deref())
+    }
+
+    // a caller that can't be placed (JIT code, a stack the unwinder can't walk) isn't
+    fn allows_object(&self, name: Option<&str>) -> bool {
+        name.is_some_and(|n| self.allow.iter().any(|p| p.matches(n)))
+    }
+}
+
+struct Walk {
+    own: *mut c_void,
+    frames: usize,
+    found: *mut c_void,
+}
+
+extern "C" fn visit(cx: *mut UnwindContext, arg: *mut c_void) -> c_int {
+    let walk = unsafe { &mut *arg.cast::<Walk>() };
+    walk.frames += 1;
+    // a return address, which for a call at the very end of a function is already
+    // past it
+    let ip = unsafe { _Unwind_GetIP(cx) }.saturating_sub(1);
+    match object_base(ip as *const c_void) {
+        Some(base) if base != walk.own => {
+            walk.found = ip as *mut c_void;
+            URC_NORMAL_STOP
+        }
+        _ if walk.frames >= MAX_FRAMES => URC_NORMAL_STOP,
+        _ => URC_NO_REASON,
+    }
+}
+
+/// The file name of the object the first frame outside this library lives in.
+fn caller_object() -> Option<String> {
+    let mut walk = Walk {
+        own: object_base(caller_object as *const c_void)?,
+        frames: 0,
+        found: std::ptr::null_mut(),
+    };
+    unsafe { _Unwind_Backtrace(visit, (&mut walk as *mut Walk).cast()) };
+    if walk.found.is_null() {
+        return None;
+    }
+    object_name(walk.found)
+}
+
+fn object_base(addr: *const c_void) -> Option<*mut c_void> {
+    let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+    (unsafe { libc::dladdr(addr, &mut info) } != 0).then_some(info.dli_fbase)
+}
+
+fn object_name(addr: *const c_void) -> Option<String> {
+    let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+    if unsafe { libc::dladdr(addr, &mut info) } == 0 || info.dli_fname.is_null() {
+        return None;
+    }
+    let path = unsafe { CStr::from_ptr(info.dli_fname) }.to_string_lossy();
+    Path::new(path.as_ref())
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowlist() {
+        assert_eq!(CallerFilter::parse(" , "), None);
+        let filter = CallerFilter::parse("libmine.so*, libtheirs.so.1").unwrap();
+        assert!(filter.allows_object(Some("libmine.so.3")));
+        assert!(filter.allows_object(Some("libtheirs.so.1")));
+        assert!(!filter.allows_object(Some("libtheirs.so.2")));
+        assert!(!filter.allows_object(None));
+    }
+
+    #[test]
+    fn walk_leaves_the_own_object() {
+        // the test binary is this library as well as its caller, so the walk only
+        // gets out of it into libc's thread start or process entry, or not at all
+        let name = caller_object();
+        let exe = std::env::current_exe().unwrap();
+        assert_ne!(name.as_deref(), exe.file_name().and_then(|n| n.to_str()));
+    }
+}
@@ -0,0 +1,298 @@
+// src/children.rs
+//
+// Spans for child processes. With OTEL_POSIX_PROP_CHILD_SPANS on, a child
+// started with posix_spawn, posix_spawnp or fork while a span is recording
+// gets a `child process` span under it. That span is the context a spawned
+// child's environment carries (exec.rs) and the one a forked child carries
+// on under, so whatever the child traces hangs off it. wait, waitpid, wait4
+// and waitid end it when they reap the child, with the exit code or the
+// signal that ended it; the span's duration is the child's lifetime. A
+// child reaped some other way, or never (SIGCHLD ignored, a parent that
+// exits first), leaves its span unended and unexported.
+//
+// A forked child inherits the parent's copies of its siblings' spans, and
+// forgets them rather than ending them twice.
+
+use crate::config::config;
+use crate::{reentry, scope};
+use libc::{c_int, id_t, idtype_t, pid_t, rusage, siginfo_t};
+use opentelemetry::trace::{Status, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex, MutexGuard, PoisonError};
+
+type ForkFn = unsafe extern "C" fn() -> pid_t;
+type WaitFn = unsafe extern "C" fn(*mut c_int) -> pid_t;
+type WaitpidFn = unsafe extern "C" fn(pid_t, *mut c_int, c_int) -> pid_t;
+type Wait4Fn = unsafe extern "C" fn(pid_t, *mut c_int, c_int, *mut rusage) -> pid_t;
+type WaitidFn = unsafe extern "C" fn(idtype_t, id_t, *mut siginfo_t, c_int) -> c_int;
+
+// children with a span that haven't been reaped yet
+static CHILDREN: LazyLock<Mutex<HashMap<pid_t, Context>>> = LazyLock::new(Default::default);
+
+// set once the first child is tracked
+static TRACKING: AtomicBool = AtomicBool::new(false);
+
+/// How a reaped child ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Ended {
+    Exited(c_int),
+    Signaled { signal: c_int, core_dumped: bool },
+}
+
+impl Ended {
+    /// From a `wait`-style status, or `None` for a child that was only stopped or continued.
+    pub(crate) fn from_status(status: c_int) -> Option<Ended> {
+        if libc::WIFEXITED(status) {
+            Some(Ended::Exited(libc::WEXITSTATUS(status)))
+        } else if libc::WIFSIGNALED(status) {
+            Some(Ended::Signaled {
+                signal: libc::WTERMSIG(status),
+                core_dumped: libc::WCOREDUMP(status),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// From what `waitid` filled in.
+    fn from_siginfo(info: &siginfo_t) -> Option<Ended> {
+        let status = unsafe { info.si_status() };
+        match info.si_code {
+            libc::CLD_EXITED => Some(Ended::Exited(status)),
+            libc::CLD_KILLED => Some(Ended::Signaled {
+                signal: status,
+                core_dumped: false,
+            }),
+            libc::CLD_DUMPED => Some(Ended::Signaled {
+                signal: status,
+                core_dumped: true,
+            }),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn attributes(self) -> Vec<KeyValue> {
+        match self {
+            Ended::Exited(code) => vec![KeyValue::new("process.exit.code", i64::from(code))],
+            Ended::Signaled {
+                signal,
+                core_dumped,
+            } => vec![
+                KeyValue::new("otel_posix.process.signal", i64::from(signal)),
+                KeyValue::new("otel_posix.process.core_dumped", core_dumped),
+            ],
+        }
+    }
+
+    pub(crate) fn status(self) -> Status {
+        match self {
+            Ended::Exited(0) => Status::Unset,
+            Ended::Exited(code) => Status::error(format!("exited with {code}")),
+            Ended::Signaled { signal, .. } => Status::error(format!("killed by signal {signal}")),
+        }
+    }
+}
+
+/// Starts the span of a child about to be started from the current context, running
+/// `executable` if it is known yet, when child spans are on and a span is recording.
+pub(crate) fn begin(executable: Option<&CStr>) -> Option<Context> {
+    let attributes = executable.map(|executable| {
+        KeyValue::new(
+            "process.executable.path",
+            executable.to_string_lossy().into_owned(),
+        )
+    });
+    start("child process", attributes.into_iter().collect())
+}
+
+/// Starts a span named `name` for a child about to be started from the current context,
+/// when child spans are on and a span is recording.
+pub(crate) fn start(name: &'static str, attributes: Vec<KeyValue>) -> Option<Context> {
+    let recording =
+        Context::map_current(|cx| cx.span().is_recording() && !cx.is_telemetry_suppressed());
+    // the configuration is only read once there's a span to put the child under
+    if !recording || !config().child_spans {
+        return None;
+    }
+    let tracer = scope::tracer();
+    let cx = Context::current();
+    let span = tracer
+        .span_builder(name)
+        .with_attributes(attributes)
+        .start_with_context(&tracer, &cx);
+    Some(cx.with_span(span))
+}
+
+/// Remembers the child started as `pid` under `cx` until it is reaped.
+pub(crate) fn started(pid: pid_t, cx: Context) {
+    cx.span()
+        .set_attribute(KeyValue::new("process.pid", i64::from(pid)));
+    TRACKING.store(true, Ordering::Relaxed);
+    // a reused pid replaces whatever was left behind by its previous owner
+    lock_children().insert(pid, cx);
+}
+
+/// Ends the span of a child that couldn't be started, with `errno`.
+pub(crate) fn failed(cx: Context, errno: c_int) {
+    let span = cx.span();
+    span.set_status(Status::error(
+        std::io::Error::from_raw_os_error(errno).to_string(),
+    ));
+    span.end();
+}
+
+pub(crate) fn lock_children() -> MutexGuard<'static, HashMap<pid_t, Context>> {
+    // the map stays consistent even if a holder panicked
+    CHILDREN.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Called in a forked child: its siblings' spans are the parent's to end.
+pub(crate) fn forked() {
+    if TRACKING.load(Ordering::Relaxed) {
+        std::mem::forget(std::mem::take(&mut *lock_children()));
+    }
+}
+
+/// Ends the span of `pid`, if it has one, the way the child `ended`.
+fn reaped(pid: pid_t, ended: impl FnOnce() -> Option<Ended>) {
+    if pid <= 0 || !TRACKING.load(Ordering::Relaxed) {
+        return;
+    }
+    let Some(ended) = ended() else {
+        return;
+    };
+    let Some(_guard) = reentry::enter() else {
+        return;
+    };
+    // ended after the lock is let go, exporting may take others
+    let Some(cx) = lock_children().remove(&pid) else {
+        return;
+    };
+    let errno = unsafe { *libc::__errno_location() };
+    let span = cx.span();
+    span.set_attributes(ended.attributes());
+    span.set_status(ended.status());
+    span.end();
+    unsafe { *libc::__errno_location() = errno };
+}
+
+/// `fork`, with a span for the child, which the child carries on under.
+pub(crate) unsafe fn fork(real: ForkFn) -> pid_t {
+    let child = reentry::enter().and_then(|_guard| begin(None));
+    let pid = unsafe { real() };
+    let Some(cx) = child else {
+        return pid;
+    };
+    let errno = unsafe { *libc::__errno_location() };
+    match pid {
+        0 => {
+            // the parent ends the span; the child only keeps its context, for as long as
+            // it runs, under whatever it attaches later
+            let sc = cx.span().span_context().clone();
+            let parent = Context::current();
+            std::mem::forget(cx);
+            std::mem::forget(parent.with_remote_span_context(sc).attach());
+        }
+        -1 => failed(cx, errno),
+        pid => started(pid, cx),
+    }
+    unsafe { *libc::__errno_location() = errno };
+    pid
+}
+
+/// `wait`, ending the span of the child it reaps.
+pub(crate) unsafe fn wait(real: WaitFn, status: *mut c_int) -> pid_t {
+    let mut own = 0;
+    let status = if status.is_null() {
+        &raw mut own
+    } else {
+        status
+    };
+    let pid = unsafe { real(status) };
+    reaped(pid, || Ended::from_status(unsafe { *status }));
+    pid
+}
+
+/// `waitpid`, ending the span of the child it reaps.
+pub(crate) unsafe fn waitpid(
+    real: WaitpidFn,
+    pid: pid_t,
+    status: *mut c_int,
+    options: c_int,
+) -> pid_t {
+    let mut own = 0;
+    let status = if status.is_null() {
+        &raw mut own
+    } else {
+        status
+    };
+    let reaped_pid = unsafe { real(pid, status, options) };
+    reaped(reaped_pid, || Ended::from_status(unsafe { *status }));
+    reaped_pid
+}
+
+/// `wait4`, ending the span of the child it reaps.
+pub(crate) unsafe fn wait4(
+    real: Wait4Fn,
+    pid: pid_t,
+    status: *mut c_int,
+    options: c_int,
+    usage: *mut rusage,
+) -> pid_t {
+    let mut own = 0;
+    let status = if status.is_null() {
+        &raw mut own
+    } else {
+        status
+    };
+    let reaped_pid = unsafe { real(pid, status, options, usage) };
+    reaped(reaped_pid, || Ended::from_status(unsafe { *status }));
+    reaped_pid
+}
+
+/// `waitid`, ending the span of the child it reaps, unless asked to leave it waitable.
+pub(crate) unsafe fn waitid(
+    real: WaitidFn,
+    idtype: idtype_t,
+    id: id_t,
+    info: *mut siginfo_t,
+    options: c_int,
+) -> c_int {
+    let mut own = unsafe { std::mem::zeroed::<siginfo_t>() };
+    let info = if info.is_null() { &raw mut own } else { info };
+    let rc = unsafe { real(idtype, id, info, options) };
+    if rc == 0 && options & libc::WNOWAIT == 0 {
+        let info = unsafe { &*info };
+        // zero with WNOHANG and nothing to reap
+        reaped(unsafe { info.si_pid() }, || Ended::from_siginfo(info));
+    }
+    rc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statuses_are_told_apart() {
+        // as the kernel encodes them: exit code in the second byte, signal in the first
+        assert_eq!(Ended::from_status(3 << 8), Some(Ended::Exited(3)));
+        assert_eq!(
+            Ended::from_status(libc::SIGKILL | 0x80),
+            Some(Ended::Signaled {
+                signal: libc::SIGKILL,
+                core_dumped: true,
+            })
+        );
+        // stopped
+        assert_eq!(Ended::from_status((libc::SIGSTOP << 8) | 0x7f), None);
+        assert_eq!(Ended::Exited(0).status(), Status::Unset);
+        assert_eq!(
+            Ended::Exited(3).attributes(),
+            [KeyValue::new("process.exit.code", 3)]
+        );
+    }
+}
@@ -0,0 +1,395 @@
+// src/config.rs
+//
+// Runtime configuration for the shim, read from the environment (and the
+// OTEL_POSIX_PROP_CONFIG file, see config_file.rs) the first time it's
+// needed, and again on a reload. A reload swaps in a new snapshot; the one
+// replaced is leaked, since a thread may still be reading it.
+
+use crate::caller::CallerFilter;
+use crate::config_file;
+use crate::entry::EntryFilter;
+use crate::filter::SpanFilter;
+use crate::log::{self, log_warn};
+use crate::propagators::{ExecAllow, Forward, Propagator};
+use crate::ratelimit::parse_rate;
+use crate::scope::Scope;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+static CONFIG: AtomicPtr<Config> = AtomicPtr::new(std::ptr::null_mut());
+
+// held while a configuration is built, so the first is built once and reloads don't interleave
+static BUILDING: Mutex<()> = Mutex::new(());
+
+/// How a wrapped thread relates to the span that created it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mode {
+    /// Attach the creator's context, so work in the thread is parented under it.
+    #[default]
+    Parent,
+    /// Start a fresh root span for the thread that links back to the creator.
+    Links,
+    /// Start a child span for the thread that covers it from `pthread_create` to exit.
+    Lifetime,
+    /// Attach nothing; only record the creator's tid, span and creation time for the
+    /// C API.
+    Attributes,
+}
+
+impl Mode {
+    fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "parent" => Some(Mode::Parent),
+            "links" | "link" => Some(Mode::Links),
+            "lifetime" => Some(Mode::Lifetime),
+            "attributes" | "attribute-only" => Some(Mode::Attributes),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Mode::Parent => "parent",
+            Mode::Links => "links",
+            Mode::Lifetime => "lifetime",
+            Mode::Attributes => "attributes",
+        }
+    }
+}
+
+/// What becomes of the trace context in a process that daemonizes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Daemon {
+    /// Stay in the trace the daemon was started from.
+    #[default]
+    Keep,
+    /// Start a trace of its own, under a `daemon` span linked to the one it left.
+    Root,
+    /// Leave it like any other forked child.
+    Off,
+}
+
+impl Daemon {
+    fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "keep" | "on" | "true" => Some(Daemon::Keep),
+            "root" | "reroot" => Some(Daemon::Root),
+            "off" | "false" | "none" => Some(Daemon::Off),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Config {
+    /// Only wrap threads created while a span matching one of these names is active.
+    /// `OTEL_POSIX_PROP_SPAN_FILTER`, comma-separated globs and `re:` regexes.
+    pub span_filter: Option<SpanFilter>,
+    /// `OTEL_POSIX_PROP_MODE`, `parent`, `links`, `lifetime` or `attributes`.
+    pub mode: Mode,
+    /// Only wrap threads whose start routine is allowed by these lists.
+    /// `OTEL_POSIX_PROP_ENTRY_ALLOW` / `OTEL_POSIX_PROP_ENTRY_DENY`, comma-separated globs.
+    pub entry_filter: Option<EntryFilter>,
+    /// Only wrap threads created from these objects. `OTEL_POSIX_PROP_CALLER_ALLOW`,
+    /// comma-separated globs.
+    #[cfg_attr(not(feature = "preload"), allow(dead_code))]
+    pub caller_filter: Option<CallerFilter>,
+    /// Formats the context is written in for child processes. `OTEL_PROPAGATORS`.
+    #[cfg_attr(not(feature = "hook-exec"), allow(dead_code))]
+    pub propagators: Vec<Propagator>,
+    /// Add a `thread.join` event to the creating span when a wrapped thread is joined.
+    /// `OTEL_POSIX_PROP_JOIN_EVENTS`.
+    #[cfg_attr(not(feature = "hook-join"), allow(dead_code))]
+    pub join_events: bool,
+    /// Give spawned and forked children a `child process` span, ended when they are reaped.
+    /// `OTEL_POSIX_PROP_CHILD_SPANS`.
+    #[cfg_attr(not(feature = "hook-wait"), allow(dead_code))]
+    pub child_spans: bool,
+    /// Add `dlopen` and `dlclose` events to the current span.
+    /// `OTEL_POSIX_PROP_DLOPEN_EVENTS`.
+    #[cfg_attr(
+        not(all(feature = "hook-dlopen", target_os = "linux", target_env = "gnu")),
+        allow(dead_code)
+    )]
+    pub dlopen_events: bool,
+    /// Add a `pthread_once` event to the current span for each init routine run under it.
+    /// `OTEL_POSIX_PROP_ONCE_EVENTS`.
+    #[cfg_attr(not(feature = "hook-once"), allow(dead_code))]
+    pub once_events: bool,
+    /// Run each init routine run under a recording span under a `pthread_once` span of its
+    /// own. `OTEL_POSIX_PROP_ONCE_SPANS`.
+    #[cfg_attr(not(feature = "hook-once"), allow(dead_code))]
+    pub once_spans: bool,
+    /// What exec'd and spawned children get besides the context.
+    /// `OTEL_POSIX_PROP_EXEC_FORWARD`.
+    #[cfg_attr(not(feature = "hook-exec"), allow(dead_code))]
+    pub exec_forward: Forward,
+    /// Only add to the environment of these executables. `OTEL_POSIX_PROP_EXEC_ALLOW`.
+    #[cfg_attr(not(feature = "hook-exec"), allow(dead_code))]
+    pub exec_allow: Option<ExecAllow>,
+    /// Which interposers are active. All off with `OTEL_SDK_DISABLED`.
+    pub hooks: Hooks,
+    /// Only wrap threads created under a sampled span. `OTEL_POSIX_PROP_SAMPLED_ONLY`.
+    pub sampled_only: bool,
+    /// Wrap at most this many threads a second, passing the rest through.
+    /// `OTEL_POSIX_PROP_WRAP_RATE`.
+    pub wrap_rate: Option<u32>,
+    /// What a daemonizing process does with its context. `OTEL_POSIX_PROP_DAEMON`, `keep`,
+    /// `root` or `off`.
+    #[cfg_attr(not(feature = "hook-daemon"), allow(dead_code))]
+    pub daemon: Daemon,
+    /// The instrumentation scope of the shim's own spans and metrics.
+    /// `OTEL_POSIX_PROP_SCOPE_NAME`, `_VERSION` and `_SCHEMA_URL`.
+    pub scope: Scope,
+}
+
+/// Switches for the individual interposers, all on by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Hooks {
+    /// Wrap threads. `OTEL_POSIX_PROP_HOOK_PTHREAD_CREATE`.
+    pub pthread_create: bool,
+    /// Inject context into exec'd and spawned children. `OTEL_POSIX_PROP_HOOK_EXEC`.
+    #[cfg_attr(not(feature = "hook-exec"), allow(dead_code))]
+    pub exec: bool,
+    /// Add `traceparent` to outgoing HTTP requests. `OTEL_POSIX_PROP_HOOK_HTTP`.
+    #[cfg_attr(not(feature = "http-inject"), allow(dead_code))]
+    pub http: bool,
+    /// Carry context across makecontext/swapcontext switches.
+    /// `OTEL_POSIX_PROP_HOOK_UCONTEXT`.
+    #[cfg_attr(not(feature = "hook-ucontext"), allow(dead_code))]
+    pub ucontext: bool,
+    /// Append the trace and span id to syslog messages. `OTEL_POSIX_PROP_HOOK_SYSLOG`.
+    #[cfg_attr(not(feature = "hook-syslog"), allow(dead_code))]
+    pub syslog: bool,
+    /// Carry context in a trailer on POSIX message queue messages.
+    /// `OTEL_POSIX_PROP_HOOK_MQUEUE`.
+    #[cfg_attr(
+        not(all(feature = "mqueue-inject", target_os = "linux")),
+        allow(dead_code)
+    )]
+    pub mqueue: bool,
+}
+
+impl Default for Hooks {
+    fn default() -> Self {
+        Hooks {
+            pthread_create: true,
+            exec: true,
+            http: true,
+            ucontext: true,
+            syslog: true,
+            mqueue: true,
+        }
+    }
+}
+
+impl Hooks {
+    /// Every interposer switched off.
+    const NONE: Hooks = Hooks {
+        pthread_create: false,
+        exec: false,
+        http: false,
+        ucontext: false,
+        syslog: false,
+        mqueue: false,
+    };
+}
+
+impl Config {
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let config = Config {
+            span_filter: var("OTEL_POSIX_PROP_SPAN_FILTER").and_then(|v| SpanFilter::parse(&v)),
+            mode: var("OTEL_POSIX_PROP_MODE")
+                .map(|v| {
+                    Mode::parse(&v).unwrap_or_else(|| {
+                        log_warn!("unknown OTEL_POSIX_PROP_MODE {v:?}, using parent");
+                        Mode::Parent
+                    })
+                })
+                .unwrap_or_default(),
+            entry_filter: EntryFilter::parse(
+                var("OTEL_POSIX_PROP_ENTRY_ALLOW").as_deref(),
+                var("OTEL_POSIX_PROP_ENTRY_DENY").as_deref(),
+            ),
+            caller_filter: var("OTEL_POSIX_PROP_CALLER_ALLOW")
+                .and_then(|v| CallerFilter::parse(&v)),
+            propagators: var("OTEL_PROPAGATORS")
+                .map_or_else(Propagator::defaults, |v| Propagator::parse_list(&v)),
+            join_events: var("OTEL_POSIX_PROP_JOIN_EVENTS").is_some_and(|v| parse_bool(&v)),
+            child_spans: var("OTEL_POSIX_PROP_CHILD_SPANS").is_some_and(|v| parse_bool(&v)),
+            dlopen_events: var("OTEL_POSIX_PROP_DLOPEN_EVENTS").is_some_and(|v| parse_bool(&v)),
+            once_events: var("OTEL_POSIX_PROP_ONCE_EVENTS").is_some_and(|v| parse_bool(&v)),
+            once_spans: var("OTEL_POSIX_PROP_ONCE_SPANS").is_some_and(|v| parse_bool(&v)),
+            exec_forward: var("OTEL_POSIX_PROP_EXEC_FORWARD")
+                .map(|v| Forward::parse(&v))
+                .unwrap_or_default(),
+            exec_allow: var("OTEL_POSIX_PROP_EXEC_ALLOW").and_then(|v| ExecAllow::parse(&v)),
+            hooks: Hooks {
+                pthread_create: var("OTEL_POSIX_PROP_HOOK_PTHREAD_CREATE")
+                    .is_none_or(|v| parse_bool(&v)),
+                exec: var("OTEL_POSIX_PROP_HOOK_EXEC").is_none_or(|v| parse_bool(&v)),
+                http: var("OTEL_POSIX_PROP_HOOK_HTTP").is_none_or(|v| parse_bool(&v)),
+                ucontext: var("OTEL_POSIX_PROP_HOOK_UCONTEXT").is_none_or(|v| parse_bool(&v)),
+                syslog: var("OTEL_POSIX_PROP_HOOK_SYSLOG").is_none_or(|v| parse_bool(&v)),
+                mqueue: var("OTEL_POSIX_PROP_HOOK_MQUEUE").is_none_or(|v| parse_bool(&v)),
+            },
+            sampled_only: var("OTEL_POSIX_PROP_SAMPLED_ONLY").is_some_and(|v| parse_bool(&v)),
+            wrap_rate: var("OTEL_POSIX_PROP_WRAP_RATE")
+                .and_then(|v| parse_rate("OTEL_POSIX_PROP_WRAP_RATE", &v)),
+            daemon: var("OTEL_POSIX_PROP_DAEMON")
+                .map(|v| {
+                    Daemon::parse(&v).unwrap_or_else(|| {
+                        log_warn!("unknown OTEL_POSIX_PROP_DAEMON {v:?}, using keep");
+                        Daemon::Keep
+                    })
+                })
+                .unwrap_or_default(),
+            scope: Scope::from_env(&var),
+        };
+        // the standard switch for turning OpenTelemetry off leaves everything untouched
+        if sdk_disabled(&var) {
+            return Config {
+                join_events: false,
+                child_spans: false,
+                dlopen_events: false,
+                once_events: false,
+                once_spans: false,
+                hooks: Hooks::NONE,
+                daemon: Daemon::Off,
+                ..config
+            };
+        }
+        config
+    }
+}
+
+/// Whether `OTEL_SDK_DISABLED` is set, which the specification only takes as `true`.
+pub(crate) fn sdk_disabled(var: impl Fn(&str) -> Option<String>) -> bool {
+    var("OTEL_SDK_DISABLED").is_some_and(|v| v.trim().eq_ignore_ascii_case("true"))
+}
+
+pub(crate) fn parse_bool(s: &str) -> bool {
+    matches!(
+        s.trim().to_ascii_lowercase().as_str(),
+        "1" | "true" | "yes" | "on"
+    )
+}
+
+/// The process-wide configuration.
+pub(crate) fn config() -> &'static Config {
+    let current = CONFIG.load(Ordering::Acquire);
+    if !current.is_null() {
+        return unsafe { &*current };
+    }
+    let _building = lock_building();
+    let current = CONFIG.load(Ordering::Acquire);
+    if !current.is_null() {
+        return unsafe { &*current };
+    }
+    install(Config::from_env(config_file::var))
+}
+
+fn install(config: Config) -> &'static Config {
+    let config = Box::leak(Box::new(config));
+    CONFIG.store(config, Ordering::Release);
+    config
+}
+
+/// Reads the configuration again, from the environment and the config file, for what
+/// happens from now on: threads already wrapped keep what they were wrapped with. A
+/// config file that fails to read keeps its earlier settings, and the error is returned.
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+pub(crate) fn reload() -> Result<(), String> {
+    let _building = lock_building();
+    let file = config_file::reload();
+    log::reload();
+    install(Config::from_env(config_file::var));
+    file
+}
+
+pub(crate) fn lock_building() -> MutexGuard<'static, ()> {
+    BUILDING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_from_env() {
+        let mode = |v: Option<&str>| Config::from_env(|_| v.map(String::from)).mode;
+        assert_eq!(mode(None), Mode::Parent);
+        assert_eq!(mode(Some("links")), Mode::Links);
+        assert_eq!(mode(Some(" Links ")), Mode::Links);
+        assert_eq!(mode(Some("lifetime")), Mode::Lifetime);
+        assert_eq!(mode(Some("attribute-only")), Mode::Attributes);
+        assert_eq!(mode(Some("bogus")), Mode::Parent);
+    }
+
+    #[test]
+    fn join_events_from_env() {
+        let on = |v: Option<&str>| {
+            Config::from_env(|key| {
+                (key == "OTEL_POSIX_PROP_JOIN_EVENTS")
+                    .then(|| v.map(String::from))
+                    .flatten()
+            })
+            .join_events
+        };
+        assert!(!on(None));
+        assert!(on(Some("true")));
+        assert!(on(Some(" 1 ")));
+        assert!(!on(Some("off")));
+    }
+
+    #[test]
+    fn hooks_default_on() {
+        assert_eq!(Config::from_env(|_| None).hooks, Hooks::default());
+        let hooks = Config::from_env(|key| {
+            (key == "OTEL_POSIX_PROP_HOOK_EXEC").then(|| "false".to_string())
+        })
+        .hooks;
+        assert!(!hooks.exec && hooks.pthread_create && hooks.http);
+    }
+
+    #[test]
+    fn wrap_rate_from_env() {
+        let rate = |v: &str| {
+            Config::from_env(|key| (key == "OTEL_POSIX_PROP_WRAP_RATE").then(|| v.to_string()))
+                .wrap_rate
+        };
+        assert_eq!(Config::from_env(|_| None).wrap_rate, None);
+        assert_eq!(rate("2000"), Some(2000));
+        assert_eq!(rate("-1"), None);
+    }
+
+    #[test]
+    fn daemon_from_env() {
+        let daemon = |v: &str| {
+            Config::from_env(|key| (key == "OTEL_POSIX_PROP_DAEMON").then(|| v.to_string())).daemon
+        };
+        assert_eq!(Config::from_env(|_| None).daemon, Daemon::Keep);
+        assert_eq!(daemon(" Root "), Daemon::Root);
+        assert_eq!(daemon("off"), Daemon::Off);
+        assert_eq!(daemon("bogus"), Daemon::Keep);
+    }
+
+    #[test]
+    fn sdk_disabled_turns_everything_off() {
+        let config = Config::from_env(|key| match key {
+            "OTEL_SDK_DISABLED" => Some("true".to_string()),
+            "OTEL_POSIX_PROP_JOIN_EVENTS" => Some("true".to_string()),
+            "OTEL_POSIX_PROP_CHILD_SPANS" => Some("true".to_string()),
+            "OTEL_POSIX_PROP_ONCE_SPANS" => Some("true".to_string()),
+            _ => None,
+        });
+        assert_eq!(config.hooks, Hooks::NONE);
+        assert!(!config.join_events);
+        assert!(!config.child_spans);
+        assert!(!config.once_spans);
+        assert_eq!(config.daemon, Daemon::Off);
+        let config =
+            Config::from_env(|key| (key == "OTEL_SDK_DISABLED").then(|| "false".to_string()));
+        assert_eq!(config.hooks, Hooks::default());
+    }
+}
@@ -0,0 +1,233 @@
+// src/config_file.rs
+//
+// OTEL_POSIX_PROP_CONFIG=/path/to/config.toml. Every key in the file stands
+// in for one of the environment variables, so the rest of the shim only ever
+// asks for variables by name; a variable that is actually set in the
+// environment wins over the file. It is read again when the configuration
+// is reloaded (see reload.rs).
+//
+//     mode = "links"
+//     propagators = ["tracecontext", "b3"]
+//     log = "off"
+//
+//     [filters]
+//     span = ["http.*", "batch.job"]
+//     entry_deny = ["libjemalloc.so*"]
+//
+//     [hooks]
+//     exec = false
+//     join = true
+//
+// Builds without the config-file feature, minimal ones among them, leave the
+// TOML parser out and take the environment alone.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+/// The settings read from the file, and where from.
+#[derive(Default)]
+struct File {
+    vars: HashMap<&'static str, String>,
+    // only once it has been read successfully
+    path: Option<String>,
+}
+
+static FILE: OnceLock<File> = OnceLock::new();
+
+// what the last reload read, in place of FILE; replaced ones are leaked, since a
+// thread may still be reading one
+static RELOADED: AtomicPtr<File> = AtomicPtr::new(std::ptr::null_mut());
+
+/// File keys and the environment variables they stand in for.
+#[cfg(feature = "config-file")]
+const KEYS: &[(&str, &str)] = &[
+    ("mode", "OTEL_POSIX_PROP_MODE"),
+    ("propagators", "OTEL_PROPAGATORS"),
+    ("log", "OTEL_POSIX_PROP_LOG"),
+    ("redact_ids", "OTEL_POSIX_PROP_REDACT_IDS"),
+    ("daemon", "OTEL_POSIX_PROP_DAEMON"),
+    ("limits.wrap_rate", "OTEL_POSIX_PROP_WRAP_RATE"),
+    ("limits.log_rate", "OTEL_POSIX_PROP_LOG_RATE"),
+    ("filters.span", "OTEL_POSIX_PROP_SPAN_FILTER"),
+    ("filters.entry_allow", "OTEL_POSIX_PROP_ENTRY_ALLOW"),
+    ("filters.entry_deny", "OTEL_POSIX_PROP_ENTRY_DENY"),
+    ("filters.caller_allow", "OTEL_POSIX_PROP_CALLER_ALLOW"),
+    ("filters.sampled_only", "OTEL_POSIX_PROP_SAMPLED_ONLY"),
+    (
+        "hooks.pthread_create",
+        "OTEL_POSIX_PROP_HOOK_PTHREAD_CREATE",
+    ),
+    ("hooks.exec", "OTEL_POSIX_PROP_HOOK_EXEC"),
+    ("hooks.exec_forward", "OTEL_POSIX_PROP_EXEC_FORWARD"),
+    ("hooks.exec_allow", "OTEL_POSIX_PROP_EXEC_ALLOW"),
+    ("hooks.http", "OTEL_POSIX_PROP_HOOK_HTTP"),
+    ("hooks.ucontext", "OTEL_POSIX_PROP_HOOK_UCONTEXT"),
+    ("hooks.syslog", "OTEL_POSIX_PROP_HOOK_SYSLOG"),
+    ("hooks.mqueue", "OTEL_POSIX_PROP_HOOK_MQUEUE"),
+    ("hooks.join", "OTEL_POSIX_PROP_JOIN_EVENTS"),
+    ("hooks.children", "OTEL_POSIX_PROP_CHILD_SPANS"),
+    ("hooks.dlopen", "OTEL_POSIX_PROP_DLOPEN_EVENTS"),
+    ("hooks.once", "OTEL_POSIX_PROP_ONCE_EVENTS"),
+    ("hooks.once_spans", "OTEL_POSIX_PROP_ONCE_SPANS"),
+    ("scope.name", "OTEL_POSIX_PROP_SCOPE_NAME"),
+    ("scope.version", "OTEL_POSIX_PROP_SCOPE_VERSION"),
+    ("scope.schema_url", "OTEL_POSIX_PROP_SCOPE_SCHEMA_URL"),
+];
+
+/// Looks `key` up in the environment, then in the config file.
+pub(crate) fn var(key: &str) -> Option<String> {
+    std::env::var(key)
+        .ok()
+        .or_else(|| file().vars.get(key).cloned())
+}
+
+fn file() -> &'static File {
+    let reloaded = RELOADED.load(Ordering::Acquire);
+    if reloaded.is_null() {
+        FILE.get_or_init(load)
+    } else {
+        unsafe { &*reloaded }
+    }
+}
+
+fn load() -> File {
+    // the log level may itself come from this file, so problems with it are always shown
+    read(std::env::var("OTEL_POSIX_PROP_CONFIG").ok()).unwrap_or_else(|e| {
+        eprintln!("otel_posix_pseudo_propegator: ignoring config file {e}");
+        File::default()
+    })
+}
+
+/// Reads the file at `path`; errors start with the path.
+fn read(path: Option<String>) -> Result<File, String> {
+    let Some(path) = path else {
+        return Ok(File::default());
+    };
+    match std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|s| parse(&s))
+    {
+        Ok(vars) => Ok(File {
+            vars,
+            path: Some(path),
+        }),
+        Err(e) => Err(format!("{path}: {e}")),
+    }
+}
+
+/// Reads the config file again. One that can't be read or parsed now leaves the
+/// settings read from it before in place.
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+pub(crate) fn reload() -> Result<(), String> {
+    let file = read(std::env::var("OTEL_POSIX_PROP_CONFIG").ok())?;
+    RELOADED.store(Box::into_raw(Box::new(file)), Ordering::Release);
+    Ok(())
+}
+
+/// The config file the settings were read from, if there is one and it was usable.
+pub(crate) fn path() -> Option<&'static str> {
+    file().path.as_deref()
+}
+
+/// Parses a config file into the environment variables it sets. Unknown keys are
+/// reported and skipped.
+#[cfg(feature = "config-file")]
+fn parse(s: &str) -> Result<HashMap<&'static str, String>, String> {
+    let table: toml::Table = s.parse().map_err(|e: toml::de::Error| e.to_string())?;
+    let mut vars = HashMap::new();
+    flatten("", &table, &mut vars)?;
+    Ok(vars)
+}
+
+#[cfg(feature = "config-file")]
+fn flatten(
+    prefix: &str,
+    table: &toml::Table,
+    vars: &mut HashMap<&'static str, String>,
+) -> Result<(), String> {
+    for (name, value) in table {
+        let key = format!("{prefix}{name}");
+        if let toml::Value::Table(inner) = value {
+            flatten(&format!("{key}."), inner, vars)?;
+            continue;
+        }
+        let Some(&(_, var)) = KEYS.iter().find(|(k, _)| *k == key) else {
+            eprintln!("otel_posix_pseudo_propegator: unknown config key {key:?}");
+            continue;
+        };
+        vars.insert(var, to_env_value(&key, value)?);
+    }
+    Ok(())
+}
+
+/// Renders a file value the way it would be written in the environment; arrays become
+/// comma-separated lists.
+#[cfg(feature = "config-file")]
+fn to_env_value(key: &str, value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                toml::Value::String(s) => Ok(s.as_str()),
+                _ => Err(format!("{key}: expected a list of strings")),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|items| items.join(",")),
+        _ => Err(format!("{key}: unsupported {} value", value.type_str())),
+    }
+}
+
+#[cfg(not(feature = "config-file"))]
+fn parse(_: &str) -> Result<HashMap<&'static str, String>, String> {
+    Err("built without config-file support, only the environment is read".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "config-file")]
+    fn keys_map_to_env_vars() {
+        let vars = parse(
+            r#"
+            mode = "links"
+            propagators = ["tracecontext", "b3"]
+            bogus = 1
+
+            [filters]
+            span = ["http.*", "batch.job"]
+
+            [hooks]
+            exec = false
+            join = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(vars["OTEL_POSIX_PROP_MODE"], "links");
+        assert_eq!(vars["OTEL_PROPAGATORS"], "tracecontext,b3");
+        assert_eq!(vars["OTEL_POSIX_PROP_SPAN_FILTER"], "http.*,batch.job");
+        assert_eq!(vars["OTEL_POSIX_PROP_HOOK_EXEC"], "false");
+        assert_eq!(vars["OTEL_POSIX_PROP_JOIN_EVENTS"], "true");
+        assert_eq!(vars.len(), 5);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert!(parse("mode = ").is_err());
+        assert!(parse("[filters]\nspan = [1, 2]").is_err());
+    }
+
+    #[test]
+    fn read_errors_name_the_file() {
+        let missing = "/nonexistent/otel-posix.toml".to_string();
+        let e = read(Some(missing)).err().unwrap();
+        assert!(e.starts_with("/nonexistent/otel-posix.toml: "), "{e}");
+        let none = read(None).unwrap();
+        assert!(none.vars.is_empty() && none.path.is_none());
+    }
+}
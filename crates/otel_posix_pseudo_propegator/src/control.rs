@@ -0,0 +1,203 @@
+// src/control.rs
+//
+// A control channel for adjusting a running process without restarting it.
+// With OTEL_POSIX_PROP_CONTROL set, a thread of our own listens on an
+// abstract unix socket and answers one command per line:
+//
+//     $ socat - ABSTRACT-CONNECT:otel-posix.4242
+//     stats
+//     wrapped=120 passed_through=3 wrap_failures=0
+//
+// Abstract sockets have no file permissions, so connections from other
+// users (root aside) are refused. Read straight from the environment by a
+// load-time constructor, like the thread dump.
+
+use crate::log::log_warn;
+use crate::{config, dump, log, metrics, recorder, suppress};
+use std::io::{BufRead, BufReader, Write};
+#[cfg(target_os = "android")]
+use std::os::android::net::SocketAddrExt;
+use std::os::fd::AsRawFd;
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixListener, UnixStream};
+
+const HELP: &str =
+    "commands: status, enable, disable, log <warn|off>, reload, stats, dump, events, help";
+
+#[used]
+#[unsafe(link_section = ".init_array")]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(|| {
+        if let Some(name) = std::env::var("OTEL_POSIX_PROP_CONTROL")
+            .ok()
+            .and_then(|v| socket_name(&v, std::process::id()))
+        {
+            // constructors run in no particular order; `status` reports the binding
+            #[cfg(feature = "preload")]
+            crate::selftest::check();
+            listen(&name);
+        }
+    });
+}
+
+/// The abstract socket name for an `OTEL_POSIX_PROP_CONTROL` value: `on` picks
+/// `otel-posix.<pid>`, anything else is the name itself, with `%p` standing for the pid.
+fn socket_name(value: &str, pid: u32) -> Option<String> {
+    let value = value.trim();
+    let name = match value.to_ascii_lowercase().as_str() {
+        "" | "0" | "false" | "no" | "off" => return None,
+        "1" | "true" | "yes" | "on" => format!("otel-posix.{pid}"),
+        // socat and ss show abstract names with a leading @
+        _ => value
+            .trim_start_matches('@')
+            .replace("%p", &pid.to_string()),
+    };
+    Some(name)
+}
+
+/// Binds `name` and starts the thread answering on it.
+fn listen(name: &str) {
+    let listener = SocketAddr::from_abstract_name(name).and_then(|a| UnixListener::bind_addr(&a));
+    let listener = match listener {
+        Ok(listener) => listener,
+        Err(e) => {
+            log_warn!("failed to bind control socket @{name}: {e}");
+            return;
+        }
+    };
+    // our own plumbing, not a thread of the host's
+    let _suppress = suppress::suppress_wrapping();
+    let spawned = std::thread::Builder::new()
+        .name("otel-posix-ctl".into())
+        .spawn(move || serve(listener));
+    if let Err(e) = spawned {
+        log_warn!("failed to start the control thread: {e}");
+    }
+}
+
+fn serve(listener: UnixListener) {
+    // one client at a time; the commands are all quick
+    for stream in listener.incoming().flatten() {
+        if is_trusted(&stream) {
+            let _ = session(stream);
+        }
+    }
+}
+
+/// Whether the peer runs as our user, or as root.
+fn is_trusted(stream: &UnixStream) -> bool {
+    let mut cred = unsafe { std::mem::zeroed::<libc::ucred>() };
+    let mut len = size_of::<libc::ucred>() as libc::socklen_t;
+    let found = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    } == 0;
+    found && (cred.uid == 0 || cred.uid == unsafe { libc::geteuid() })
+}
+
+fn session(stream: UnixStream) -> std::io::Result<()> {
+    let mut out = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let reply = command(&line?);
+        out.write_all(reply.as_bytes())?;
+        if !reply.ends_with('\n') {
+            out.write_all(b"\n")?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs one command line and returns the reply.
+fn command(line: &str) -> String {
+    let mut words = line.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some("status"), None) => status(),
+        (Some("enable"), None) => {
+            suppress::set_paused(false);
+            "ok".to_string()
+        }
+        (Some("disable"), None) => {
+            suppress::set_paused(true);
+            "ok".to_string()
+        }
+        (Some("log"), Some(level)) if words.next().is_none() => {
+            match level.to_ascii_lowercase().as_str() {
+                "warn" | "on" => log::set_enabled(true),
+                "off" => log::set_enabled(false),
+                _ => return format!("error: unknown log level {level:?}"),
+            }
+            "ok".to_string()
+        }
+        (Some("reload"), None) => match config::reload() {
+            Ok(()) => "ok".to_string(),
+            Err(e) => format!("error: config file {e}, keeping its earlier settings"),
+        },
+        (Some("stats"), None) => {
+            let counts = metrics::counts();
+            format!(
+                "wrapped={} passed_through={} wrap_failures={}",
+                counts.wrapped, counts.passed_through, counts.wrap_failures
+            )
+        }
+        (Some("dump"), None) => dump::snapshot(),
+        (Some("events"), None) => recorder::snapshot(),
+        (Some("help"), None) => HELP.to_string(),
+        (None, _) => String::new(),
+        _ => format!("error: unknown command {:?}; {HELP}", line.trim()),
+    }
+}
+
+fn status() -> String {
+    let wrapping = if suppress::is_paused() {
+        "disabled"
+    } else {
+        "enabled"
+    };
+    let log = if log::enabled() { "warn" } else { "off" };
+    #[cfg(feature = "preload")]
+    let next = crate::resolve::next_object();
+    #[cfg(not(feature = "preload"))]
+    let next: Option<String> = None;
+    let next = next.as_deref().unwrap_or("-");
+    #[cfg(feature = "preload")]
+    let binding = crate::selftest::binding().map_or("-", |b| b.as_str());
+    #[cfg(not(feature = "preload"))]
+    let binding = "linked";
+    format!("wrapping={wrapping} log={log} next={next} binding={binding}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn socket_names() {
+        assert_eq!(socket_name("on", 42).as_deref(), Some("otel-posix.42"));
+        assert_eq!(socket_name("@myd.%p", 42).as_deref(), Some("myd.42"));
+        assert_eq!(socket_name("myd-ctl", 42).as_deref(), Some("myd-ctl"));
+        assert_eq!(socket_name("off", 42), None);
+        assert_eq!(socket_name(" ", 42), None);
+    }
+
+    #[test]
+    fn commands() {
+        assert!(command("stats").starts_with("wrapped="));
+        assert!(command(" dump ").starts_with("otel_posix_pseudo_propegator: thread dump"));
+        assert!(command("events").starts_with("otel_posix_pseudo_propegator: "));
+        assert_eq!(command("help"), HELP);
+        assert_eq!(command("reload"), "ok");
+        assert_eq!(command(""), "");
+        assert!(command("log loud").starts_with("error: "));
+        assert!(command("status extra").starts_with("error: unknown command"));
+        assert!(command("reboot").starts_with("error: unknown command"));
+    }
+}
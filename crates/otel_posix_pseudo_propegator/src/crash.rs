@@ -0,0 +1,433 @@
+// src/crash.rs
+//
+// Fatal signals, made visible in the trace instead of the process just
+// vanishing from it. With OTEL_POSIX_PROP_CRASH_SIGNALS set, a handler for
+// SIGSEGV, SIGABRT and the like reports the crash, then puts back whatever
+// handled the signal before and raises it again, so cores, the app's own
+// handler and the exit status are as they would have been.
+//
+// The handler only does what is async-signal-safe: it takes the crashing
+// thread's traceparent from the crash table (crashdump.rs), walks the
+// stack into a fixed array, and writes one report to stderr with write(2)
+// (the traceparent left out with OTEL_POSIX_PROP_REDACT_IDS).
+// The span in progress can't be ended from there, so when the thread has
+// a context the report also goes down a pipe to a thread of our own,
+// which symbolizes the stack and records a `fatal signal` span under the
+// crashing span, with an `exception` event and an error status, and
+// flushes the exporter. The handler waits for that a few seconds at most.
+// Read straight from the environment by a load-time constructor, like the
+// thread dump.
+
+use crate::crashdump::otel_posix_crash_traceparent;
+use crate::log::{self, log_warn};
+use crate::scope;
+use crate::stack::{stacktrace, walk};
+use crate::w3c::parse_traceparent;
+use libc::{c_int, pid_t};
+use opentelemetry::trace::{Status, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue};
+use std::ffi::{CStr, c_void};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+
+// frames kept from the crashing stack
+const MAX_FRAMES: usize = 48;
+
+// how long the handler waits for the crash span to be recorded and exported
+const WAIT_MS: c_int = 5000;
+
+/// The signals `on` picks, and the names known.
+const DEFAULT_SIGNALS: [c_int; 5] = [
+    libc::SIGSEGV,
+    libc::SIGABRT,
+    libc::SIGBUS,
+    libc::SIGFPE,
+    libc::SIGILL,
+];
+
+const NAMES: [(c_int, &str); 7] = [
+    (libc::SIGSEGV, "SIGSEGV"),
+    (libc::SIGABRT, "SIGABRT"),
+    (libc::SIGBUS, "SIGBUS"),
+    (libc::SIGFPE, "SIGFPE"),
+    (libc::SIGILL, "SIGILL"),
+    (libc::SIGTRAP, "SIGTRAP"),
+    (libc::SIGSYS, "SIGSYS"),
+];
+
+// the dispositions replaced, put back before the signal is raised again; written before
+// the handler is installed
+static mut PREVIOUS: [libc::sigaction; 65] = unsafe { std::mem::zeroed() };
+
+// write end of the pipe to the crash thread, and read end of the one back
+static REPORT: AtomicI32 = AtomicI32::new(-1);
+static RECORDED: AtomicI32 = AtomicI32::new(-1);
+
+// the first crash is reported; threads crashing after it just go down
+static CRASHING: AtomicBool = AtomicBool::new(false);
+
+/// A crash, as the handler hands it to the crash thread: plain data, written to the pipe
+/// in one piece.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Crash {
+    signal: c_int,
+    tid: pid_t,
+    // the faulting address, for the signals that have one
+    addr: Option<usize>,
+    traceparent: [u8; 56],
+    frames: usize,
+    ips: [usize; MAX_FRAMES],
+}
+
+#[used]
+#[unsafe(link_section = ".init_array")]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(|| {
+        if let Ok(value) = std::env::var("OTEL_POSIX_PROP_CRASH_SIGNALS") {
+            let signals = parse_signals(&value);
+            if !signals.is_empty() {
+                install(&signals);
+            }
+        }
+    });
+}
+
+/// Parses a comma-separated list of signal names (`SEGV`, `SIGABRT`) or numbers; `on`
+/// picks SIGSEGV, SIGABRT, SIGBUS, SIGFPE and SIGILL.
+fn parse_signals(s: &str) -> Vec<c_int> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "" | "0" | "false" | "no" | "off" => return Vec::new(),
+        "1" | "true" | "yes" | "on" => return DEFAULT_SIGNALS.to_vec(),
+        _ => {}
+    }
+    let mut signals = Vec::new();
+    for item in s.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+        let upper = item.to_ascii_uppercase();
+        let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+        let signal = NAMES
+            .iter()
+            .find(|(_, n)| n[3..] == *name)
+            .map(|(signal, _)| *signal)
+            .or_else(|| name.parse().ok().filter(|&n| n > 0 && n < 65));
+        match signal {
+            Some(signal) if !signals.contains(&signal) => signals.push(signal),
+            Some(_) => {}
+            None => log_warn!("unknown signal {item:?} in OTEL_POSIX_PROP_CRASH_SIGNALS"),
+        }
+    }
+    signals
+}
+
+fn signal_name(signal: c_int) -> Option<&'static str> {
+    NAMES.iter().find(|(s, _)| *s == signal).map(|(_, n)| *n)
+}
+
+/// Starts the crash thread and installs the handler for each of `signals`.
+fn install(signals: &[c_int]) {
+    // the unwinder sets itself up on first use, which is best not left to a crash
+    let mut ips = [0; MAX_FRAMES];
+    walk(&mut ips);
+    // read now, so that the handler only has to load it
+    log::redact_ids();
+    start_thread();
+    for &signal in signals {
+        let mut action = unsafe { std::mem::zeroed::<libc::sigaction>() };
+        action.sa_sigaction = on_fatal as *const () as usize;
+        // the thread's alternate stack, where it has one, survives a stack overflow
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+        let previous = unsafe { &raw mut PREVIOUS[signal as usize] };
+        if unsafe { libc::sigaction(signal, &action, previous) } != 0 {
+            log_warn!(
+                "failed to install the crash handler for signal {signal}: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+/// The thread that turns a crash into a span; without it, crashes only go to stderr.
+fn start_thread() {
+    let (mut report, mut recorded) = ([0 as c_int; 2], [0 as c_int; 2]);
+    if unsafe { libc::pipe2(report.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        log_warn!(
+            "failed to create the crash pipe: {}",
+            std::io::Error::last_os_error()
+        );
+        return;
+    }
+    if unsafe { libc::pipe2(recorded.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        log_warn!(
+            "failed to create the crash pipe: {}",
+            std::io::Error::last_os_error()
+        );
+        unsafe { libc::close(report[0]) };
+        unsafe { libc::close(report[1]) };
+        return;
+    }
+    // our own plumbing, not a thread of the host's
+    let _suppress = crate::suppress_wrapping();
+    let ([report_read, report_write], [recorded_read, recorded_write]) = (report, recorded);
+    let spawned = std::thread::Builder::new()
+        .name("otel-posix-crash".into())
+        .spawn(move || wait(report_read, recorded_write));
+    if let Err(e) = spawned {
+        log_warn!("failed to start the crash thread: {e}");
+        for fd in [report_read, report_write, recorded_read, recorded_write] {
+            unsafe { libc::close(fd) };
+        }
+        return;
+    }
+    REPORT.store(report_write, Ordering::Release);
+    RECORDED.store(recorded_read, Ordering::Release);
+    unsafe { libc::pthread_atfork(None, None, Some(forked)) };
+}
+
+// the crash thread stays behind in the parent; a child's crashes only go to stderr
+extern "C" fn forked() {
+    REPORT.store(-1, Ordering::Release);
+}
+
+extern "C" fn on_fatal(signal: c_int, info: *mut libc::siginfo_t, _: *mut c_void) {
+    let errno = unsafe { *libc::__errno_location() };
+    if !CRASHING.swap(true, Ordering::AcqRel) {
+        report(signal, info);
+    }
+    // back to what handled it before, which sees it once this handler returns
+    unsafe {
+        libc::sigaction(
+            signal,
+            &raw const PREVIOUS[signal as usize],
+            std::ptr::null_mut(),
+        )
+    };
+    unsafe { *libc::__errno_location() = errno };
+    unsafe { libc::raise(signal) };
+}
+
+/// Everything the handler does about a crash, without allocating or locking.
+fn report(signal: c_int, info: *mut libc::siginfo_t) {
+    let tid = unsafe { libc::gettid() };
+    let mut crash = Crash {
+        signal,
+        tid,
+        addr: None,
+        traceparent: [0; 56],
+        frames: 0,
+        ips: [0; MAX_FRAMES],
+    };
+    if !info.is_null() && matches!(signal, libc::SIGSEGV | libc::SIGBUS) {
+        crash.addr = Some(unsafe { (*info).si_addr() } as usize);
+    }
+    let traceparent = otel_posix_crash_traceparent(tid);
+    if !traceparent.is_null() {
+        let tp = unsafe { CStr::from_ptr(traceparent) }.to_bytes();
+        let len = tp.len().min(crash.traceparent.len() - 1);
+        crash.traceparent[..len].copy_from_slice(&tp[..len]);
+    }
+    crash.frames = walk(&mut crash.ips);
+
+    let mut line = Line::new();
+    line.push(b"otel_posix_pseudo_propegator: fatal signal ");
+    line.push_dec(signal as u64);
+    if let Some(name) = signal_name(signal) {
+        line.push(b" (");
+        line.push(name.as_bytes());
+        line.push(b")");
+    }
+    line.push(b" on tid ");
+    line.push_dec(tid as u64);
+    if let Some(addr) = crash.addr {
+        line.push(b" at ");
+        line.push_hex(addr);
+    }
+    line.push(b", traceparent ");
+    line.push(if traceparent.is_null() {
+        b"-"
+    } else if log::redact_ids() {
+        log::REDACTED.as_bytes()
+    } else {
+        &crash.traceparent[..55]
+    });
+    line.push(b"\n");
+    line.write(libc::STDERR_FILENO);
+    for (i, ip) in crash.ips[..crash.frames].iter().enumerate() {
+        let mut line = Line::new();
+        line.push(b"  #");
+        line.push_dec(i as u64);
+        line.push(b" ");
+        line.push_hex(*ip);
+        line.push(b"\n");
+        line.write(libc::STDERR_FILENO);
+    }
+
+    let fd = REPORT.load(Ordering::Acquire);
+    if traceparent.is_null() || fd < 0 {
+        return;
+    }
+    let bytes =
+        unsafe { std::slice::from_raw_parts((&raw const crash).cast::<u8>(), size_of::<Crash>()) };
+    if unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) } != bytes.len() as isize {
+        return;
+    }
+    let mut done = libc::pollfd {
+        fd: RECORDED.load(Ordering::Acquire),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut done, 1, WAIT_MS) };
+}
+
+/// A line put together on the stack, for writing from a signal handler.
+struct Line {
+    buf: [u8; 256],
+    len: usize,
+}
+
+impl Line {
+    fn new() -> Self {
+        Line {
+            buf: [0; 256],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        let n = bytes.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+        self.len += n;
+    }
+
+    fn push_dec(&mut self, mut n: u64) {
+        let mut digits = [0u8; 20];
+        let mut at = digits.len();
+        loop {
+            at -= 1;
+            digits[at] = b'0' + (n % 10) as u8;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        self.push(&digits[at..]);
+    }
+
+    fn push_hex(&mut self, mut n: usize) {
+        let mut digits = [0u8; 2 + 2 * size_of::<usize>()];
+        let mut at = digits.len();
+        loop {
+            at -= 1;
+            digits[at] = b"0123456789abcdef"[n & 0xf];
+            n >>= 4;
+            if n == 0 {
+                break;
+            }
+        }
+        at -= 2;
+        digits[at..at + 2].copy_from_slice(b"0x");
+        self.push(&digits[at..]);
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    fn write(&self, fd: c_int) {
+        let bytes = self.as_bytes();
+        unsafe { libc::write(fd, bytes.as_ptr().cast(), bytes.len()) };
+    }
+}
+
+fn wait(report: c_int, recorded: c_int) {
+    let mut crash = std::mem::MaybeUninit::<Crash>::uninit();
+    loop {
+        let read = unsafe { libc::read(report, crash.as_mut_ptr().cast(), size_of::<Crash>()) };
+        match read {
+            n if n == size_of::<Crash>() as isize => {
+                let crash = unsafe { crash.assume_init_ref() };
+                let _ = std::panic::catch_unwind(|| record(crash));
+                unsafe { libc::write(recorded, b"r".as_ptr().cast(), 1) };
+            }
+            -1 if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => {}
+            _ => return,
+        }
+    }
+}
+
+/// Records `crash` as a `fatal signal` span under the span the thread was in, and exports
+/// it while the process is still there.
+fn record(crash: &Crash) {
+    let traceparent = CStr::from_bytes_until_nul(&crash.traceparent)
+        .ok()
+        .and_then(|tp| tp.to_str().ok())
+        .and_then(parse_traceparent);
+    let Some(parent) = traceparent else {
+        return;
+    };
+    let name = signal_name(crash.signal).map_or_else(|| crash.signal.to_string(), String::from);
+    let message = format!("fatal signal {} ({name})", crash.signal);
+    let mut event = vec![
+        KeyValue::new("exception.type", name.clone()),
+        KeyValue::new("exception.message", message.clone()),
+        KeyValue::new(
+            "exception.stacktrace",
+            stacktrace(&crash.ips[..crash.frames.min(MAX_FRAMES)]),
+        ),
+    ];
+    if let Some(addr) = crash.addr {
+        event.push(KeyValue::new(
+            "otel_posix.signal.address",
+            format!("{addr:#x}"),
+        ));
+    }
+    let tracer = scope::tracer();
+    let span = tracer
+        .span_builder("fatal signal")
+        .with_attributes([
+            KeyValue::new("thread.id", i64::from(crash.tid)),
+            KeyValue::new("otel_posix.signal.number", i64::from(crash.signal)),
+            KeyValue::new("otel_posix.signal.name", name),
+        ])
+        .start_with_context(&tracer, &Context::new().with_remote_span_context(parent));
+    let cx = Context::new().with_span(span);
+    let span = cx.span();
+    span.add_event("exception", event);
+    span.set_status(Status::error(message));
+    span.end();
+    #[cfg(feature = "otlp")]
+    crate::init::flush_before_exit();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signal_lists() {
+        assert_eq!(parse_signals("on"), DEFAULT_SIGNALS.to_vec());
+        assert_eq!(parse_signals("off"), Vec::<c_int>::new());
+        assert_eq!(
+            parse_signals("SEGV, sigabrt,7,SEGV,bogus"),
+            vec![libc::SIGSEGV, libc::SIGABRT, 7]
+        );
+    }
+
+    #[test]
+    fn lines_without_allocating() {
+        let mut line = Line::new();
+        line.push(b"signal ");
+        line.push_dec(11);
+        line.push(b" at ");
+        line.push_hex(0xdead_beef);
+        line.push(b" ");
+        line.push_dec(0);
+        line.push_hex(0);
+        assert_eq!(line.as_bytes(), b"signal 11 at 0xdeadbeef 00x0");
+        let mut long = Line::new();
+        long.push(&[b'x'; 300]);
+        assert_eq!(long.as_bytes().len(), 256);
+    }
+}
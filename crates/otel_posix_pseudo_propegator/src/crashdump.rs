@@ -0,0 +1,223 @@
+// src/crashdump.rs
+//
+// The trace each thread is serving, kept where it survives into a core
+// dump and a crash handler can read it without calling into the shim:
+// `otel_posix_crash_traceparents`, an exported table of (tid, traceparent)
+// slots. It mirrors the tid registry, so it is written at the same points
+// (a wrapped thread starting, C seeding a context, coroutine switches).
+//
+// A thread claims a slot the first time it has a context and gives it back
+// when it exits. Only the owning thread writes its slot, so nothing is
+// locked; the traceparent's first byte is cleared while it is rewritten,
+// so a reader never takes a half-written one for a whole one. With every
+// slot taken, further threads just aren't recorded.
+
+use crate::w3c::format_traceparent;
+use libc::{c_char, pid_t};
+use opentelemetry::trace::SpanContext;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicI32, AtomicU8, Ordering};
+
+/// Number of slots in [`otel_posix_crash_traceparents`].
+pub const OTEL_POSIX_CRASH_SLOTS: usize = 1024;
+
+/// One thread's entry in [`otel_posix_crash_traceparents`].
+#[repr(C)]
+pub struct OtelPosixCrashSlot {
+    /// The thread's tid, or 0 for a free slot.
+    pub tid: pid_t,
+    /// The thread's `traceparent`, NUL-terminated; empty while it has none.
+    pub traceparent: [c_char; 56],
+}
+
+/// The trace each live thread is serving, by tid, for core dumps and crash handlers.
+///
+/// Written by the shim only; read it from a debugger or with
+/// [`otel_posix_crash_traceparent`].
+#[allow(non_upper_case_globals)]
+#[unsafe(no_mangle)]
+pub static mut otel_posix_crash_traceparents: [OtelPosixCrashSlot; OTEL_POSIX_CRASH_SLOTS] = [const {
+    OtelPosixCrashSlot {
+        tid: 0,
+        traceparent: [0; 56],
+    }
+};
+    OTEL_POSIX_CRASH_SLOTS];
+
+/// The calling thread's slot; dropping it at thread exit frees the slot.
+struct Claimed(Cell<Option<usize>>);
+
+impl Drop for Claimed {
+    fn drop(&mut self) {
+        if let Some(i) = self.0.get() {
+            let (tid, traceparent) = slot(i);
+            traceparent[0].store(0, Ordering::Relaxed);
+            tid.store(0, Ordering::Release);
+        }
+    }
+}
+
+thread_local! {
+    static CLAIMED: Claimed = const { Claimed(Cell::new(None)) };
+}
+
+fn slot(i: usize) -> (&'static AtomicI32, &'static [AtomicU8; 56]) {
+    unsafe {
+        let slot = &raw mut otel_posix_crash_traceparents[i];
+        // the same layout as the plain integers C readers see
+        let tid = AtomicI32::from_ptr(&raw mut (*slot).tid);
+        let traceparent = &*(&raw const (*slot).traceparent).cast::<[AtomicU8; 56]>();
+        (tid, traceparent)
+    }
+}
+
+/// Claims a free slot for `tid`, starting where [`otel_posix_crash_traceparent`] would
+/// look for it first; a slot left behind by an earlier thread with the same tid is
+/// taken over.
+fn claim(tid: pid_t) -> Option<usize> {
+    let start = tid as usize % OTEL_POSIX_CRASH_SLOTS;
+    (0..OTEL_POSIX_CRASH_SLOTS)
+        .map(|n| (start + n) % OTEL_POSIX_CRASH_SLOTS)
+        .find(|&i| {
+            let (slot_tid, _) = slot(i);
+            slot_tid
+                .compare_exchange(0, tid, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+                || slot_tid.load(Ordering::Relaxed) == tid
+        })
+}
+
+/// Records `sc` as what the calling thread is serving, or clears its slot for `None` or an
+/// invalid span context.
+pub(crate) fn set_current(sc: Option<&SpanContext>) {
+    let sc = sc.filter(|sc| sc.is_valid());
+    let _ = CLAIMED.try_with(|claimed| {
+        let i = match claimed.0.get() {
+            Some(i) => i,
+            None if sc.is_none() => return,
+            None => match claim(unsafe { libc::gettid() }) {
+                Some(i) => {
+                    claimed.0.set(Some(i));
+                    i
+                }
+                None => return,
+            },
+        };
+        let (_, traceparent) = slot(i);
+        traceparent[0].store(0, Ordering::Release);
+        let Some(sc) = sc else {
+            return;
+        };
+        let tp = format_traceparent(sc);
+        let bytes = tp.as_bytes();
+        for (dst, &b) in traceparent[1..].iter().zip(&bytes[1..]) {
+            dst.store(b, Ordering::Relaxed);
+        }
+        traceparent[bytes.len()].store(0, Ordering::Relaxed);
+        traceparent[0].store(bytes[0], Ordering::Release);
+    });
+}
+
+/// Empties the table in a forked child, where the only thread has a new tid and the
+/// others are the parent's, so the calling thread claims a slot afresh.
+#[cfg_attr(not(feature = "hook-daemon"), allow(dead_code))]
+pub(crate) fn forked() {
+    for i in 0..OTEL_POSIX_CRASH_SLOTS {
+        let (tid, traceparent) = slot(i);
+        traceparent[0].store(0, Ordering::Relaxed);
+        tid.store(0, Ordering::Release);
+    }
+    let _ = CLAIMED.try_with(|claimed| claimed.0.set(None));
+}
+
+/// The `traceparent` of the trace thread `tid` is serving, as recorded in
+/// [`otel_posix_crash_traceparents`], or NULL if it has none.
+///
+/// Async-signal-safe: it neither locks nor allocates, so a crash handler can call it for
+/// the crashing thread. The string stays valid until that thread's context next changes.
+#[unsafe(no_mangle)]
+pub extern "C" fn otel_posix_crash_traceparent(tid: pid_t) -> *const c_char {
+    if tid == 0 {
+        return std::ptr::null();
+    }
+    let start = tid as usize % OTEL_POSIX_CRASH_SLOTS;
+    (0..OTEL_POSIX_CRASH_SLOTS)
+        .map(|n| slot((start + n) % OTEL_POSIX_CRASH_SLOTS))
+        .find(|(slot_tid, _)| slot_tid.load(Ordering::Acquire) == tid)
+        .filter(|(_, traceparent)| traceparent[0].load(Ordering::Acquire) != 0)
+        .map_or(std::ptr::null(), |(_, traceparent)| {
+            traceparent.as_ptr().cast()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanId, TraceFlags, TraceId, TraceState};
+    use std::ffi::CStr;
+
+    const TP: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn recorded(tid: pid_t) -> Option<String> {
+        let tp = otel_posix_crash_traceparent(tid);
+        (!tp.is_null()).then(|| unsafe { CStr::from_ptr(tp) }.to_str().unwrap().to_string())
+    }
+
+    /// Slots holding `tid`, read as plain memory.
+    fn in_table(tid: pid_t) -> usize {
+        (0..OTEL_POSIX_CRASH_SLOTS)
+            .filter(|&i| {
+                let slot_tid = unsafe {
+                    std::ptr::read_volatile(&raw const otel_posix_crash_traceparents[i].tid)
+                };
+                slot_tid == tid
+            })
+            .count()
+    }
+
+    #[test]
+    fn slots_follow_the_thread() {
+        let sc = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || {
+            set_current(Some(&sc));
+            tx.send(unsafe { libc::gettid() }).unwrap();
+            done_rx.recv().unwrap();
+            set_current(None);
+            tx.send(0).unwrap();
+            done_rx.recv().unwrap();
+        });
+        let tid = rx.recv().unwrap();
+        assert_eq!(recorded(tid).as_deref(), Some(TP));
+        // what a debugger sees
+        assert_eq!(in_table(tid), 1);
+
+        done_tx.send(()).unwrap();
+        rx.recv().unwrap();
+        assert_eq!(recorded(tid), None);
+        done_tx.send(()).unwrap();
+        thread.join().unwrap();
+        assert_eq!(recorded(tid), None);
+        assert_eq!(in_table(tid), 0);
+    }
+
+    #[test]
+    fn threads_without_a_context_take_no_slot() {
+        std::thread::spawn(|| {
+            set_current(Some(&SpanContext::empty_context()));
+            set_current(None);
+            assert_eq!(CLAIMED.with(|claimed| claimed.0.get()), None);
+            assert_eq!(recorded(unsafe { libc::gettid() }), None);
+        })
+        .join()
+        .unwrap();
+        assert_eq!(recorded(0), None);
+    }
+}
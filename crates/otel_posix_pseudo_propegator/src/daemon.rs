@@ -0,0 +1,111 @@
+// src/daemon.rs
+//
+// Daemons. A process that daemonizes carries on in a child (daemon(3)) or
+// a grandchild (fork, setsid, fork again), with only the forking thread.
+// The context attached on that thread comes along, but the exporter's
+// worker thread stays behind in a parent that is about to exit, and the
+// thread's tid changes, so a daemon went on serving a trace it could no
+// longer export to, under a tid nothing knew about.
+//
+// daemon() is interposed, and so is setsid(): a process that made itself a
+// session leader is taken for the middle of a double fork, and the next
+// child it forks for the daemon. In the daemon, the tid registry starts
+// over, the installed tracer provider is started again (see init.rs), and
+// OTEL_POSIX_PROP_DAEMON picks what becomes of the context: `keep` (the
+// default) stays in the trace the daemon was started from, `root` starts a
+// trace of its own under a short `daemon` span linked to the span it left,
+// and `off` leaves the daemon like any other forked child.
+
+use crate::config::{Daemon, config};
+use crate::{reentry, registry, scope};
+use libc::{c_int, pid_t};
+use opentelemetry::trace::{Link, Span, SpanContext, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue};
+use std::sync::atomic::{AtomicI32, Ordering};
+
+type DaemonFn = unsafe extern "C" fn(c_int, c_int) -> c_int;
+type SetsidFn = unsafe extern "C" fn() -> pid_t;
+
+// this process's pid once it has called setsid(); its next child is the daemon
+static SESSION_LEADER: AtomicI32 = AtomicI32::new(0);
+
+// the process last settled as a daemon, so daemon() called by a session leader isn't
+// handled twice
+static DAEMONIZED: AtomicI32 = AtomicI32::new(0);
+
+/// `daemon`, carrying the trace context into the daemon.
+pub(crate) unsafe fn daemon(real: DaemonFn, nochdir: c_int, noclose: c_int) -> c_int {
+    let parent = unsafe { libc::getpid() };
+    let rc = unsafe { real(nochdir, noclose) };
+    // only the daemon returns
+    if rc == 0 {
+        settle(parent);
+    }
+    rc
+}
+
+/// `setsid`, marking the process as the middle of a double fork.
+pub(crate) unsafe fn setsid(real: SetsidFn) -> pid_t {
+    let sid = unsafe { real() };
+    if sid != -1 {
+        SESSION_LEADER.store(unsafe { libc::getpid() }, Ordering::Relaxed);
+    }
+    sid
+}
+
+/// Called in every forked child: the child of a session leader is the daemon, and its own
+/// children are not.
+pub(crate) fn forked() {
+    let leader = SESSION_LEADER.swap(0, Ordering::Relaxed);
+    if leader != 0 {
+        settle(leader);
+    }
+}
+
+/// Sets the daemon up once it is running, `parent` being the process it forked from.
+fn settle(parent: pid_t) {
+    let pid = unsafe { libc::getpid() };
+    if DAEMONIZED.swap(pid, Ordering::Relaxed) == pid {
+        return;
+    }
+    let Some(_guard) = reentry::enter() else {
+        return;
+    };
+    let errno = unsafe { *libc::__errno_location() };
+    let _ = std::panic::catch_unwind(|| {
+        let daemon = config().daemon;
+        if daemon == Daemon::Off {
+            return;
+        }
+        registry::forked();
+        #[cfg(feature = "otlp")]
+        crate::init::reinstall();
+        let sc = Context::map_current(|cx| cx.span().span_context().clone());
+        if daemon == Daemon::Keep || !sc.is_valid() {
+            registry::set_current(Some(&sc));
+            return;
+        }
+        let root = reroot(sc, pid, parent);
+        registry::set_current(Some(&root));
+        // for as long as the daemon runs, under whatever it attaches later
+        std::mem::forget(Context::new().with_remote_span_context(root).attach());
+    });
+    unsafe { *libc::__errno_location() = errno };
+}
+
+/// Records the `daemon` span that starts the daemon's own trace, linked to `left`, and
+/// returns its span context.
+fn reroot(left: SpanContext, pid: pid_t, parent: pid_t) -> SpanContext {
+    let tracer = scope::tracer();
+    let mut span = tracer
+        .span_builder("daemon")
+        .with_links(vec![Link::with_context(left)])
+        .with_attributes([
+            KeyValue::new("process.pid", i64::from(pid)),
+            KeyValue::new("process.parent_pid", i64::from(parent)),
+        ])
+        .start_with_context(&tracer, &Context::new());
+    let root = span.span_context().clone();
+    span.end();
+    root
+}
@@ -0,0 +1,136 @@
+// src/deadline.rs
+//
+// Deadlines for thread-per-request C servers, the way gRPC carries them: the
+// thread handling a request sets the absolute CLOCK_MONOTONIC time by which
+// it has to be answered, with otel_posix_set_deadline, and every thread it
+// starts from there reads it back with otel_posix_get_deadline and gives up
+// in time. The deadline is a value of the OTEL Context, so it goes wherever
+// the context goes (wrapped threads in every mode, coroutine switches,
+// spawn_with_otel); threads started without a span, or in attribute-only
+// mode, get it carried over on its own. A thread can bring the deadline it
+// was given forward, but not push it back.
+
+use opentelemetry::{Context, ContextGuard};
+use std::cell::RefCell;
+
+thread_local! {
+    // the deadline set from C, kept attached until replaced or cleared
+    static SET: RefCell<Held> = const { RefCell::new(Held(None)) };
+}
+
+struct Held(Option<ContextGuard>);
+
+impl Drop for Held {
+    fn drop(&mut self) {
+        // like ffi.rs's seed: opentelemetry's own thread-local may be gone by now
+        std::mem::forget(self.0.take());
+    }
+}
+
+/// An absolute `CLOCK_MONOTONIC` time, in nanoseconds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Deadline(u64);
+
+/// The deadline `cx` carries, if any.
+pub(crate) fn of(cx: &Context) -> Option<Deadline> {
+    cx.get::<Deadline>().copied()
+}
+
+/// The deadline of the current context, if any.
+pub(crate) fn current() -> Option<Deadline> {
+    Context::map_current(of)
+}
+
+/// Attaches `cx`, or an empty context when there's none, with `deadline` added to it.
+/// `None` when there's neither.
+pub(crate) fn attach(cx: Option<Context>, deadline: Option<Deadline>) -> Option<ContextGuard> {
+    match (cx, deadline) {
+        (Some(cx), Some(deadline)) => Some(cx.with_value(deadline).attach()),
+        (Some(cx), None) => Some(cx.attach()),
+        (None, Some(deadline)) => Some(Context::new().with_value(deadline).attach()),
+        (None, None) => None,
+    }
+}
+
+/// Sets the deadline of the calling thread and the threads it starts from now on, as an
+/// absolute `CLOCK_MONOTONIC` time in nanoseconds.
+///
+/// Replaces the deadline set by the previous call on the same thread; 0 clears it. A
+/// deadline the thread was given by its creator still applies, and is kept when it's
+/// the earlier one. Returns the deadline now in effect, or 0 for none.
+#[unsafe(no_mangle)]
+pub extern "C" fn otel_posix_set_deadline(deadline_ns: u64) -> u64 {
+    SET.with(|set| {
+        let mut set = set.borrow_mut();
+        // detach the previous one first, to get at what the thread was given
+        set.0.take();
+        let given = current();
+        let own = (deadline_ns != 0).then_some(Deadline(deadline_ns));
+        match own.filter(|own| given.is_none_or(|given| *own < given)) {
+            Some(own) => {
+                set.0 = Some(Context::current().with_value(own).attach());
+                own.0
+            }
+            None => given.map_or(0, |d| d.0),
+        }
+    })
+}
+
+/// The calling thread's deadline, as an absolute `CLOCK_MONOTONIC` time in nanoseconds,
+/// or 0 if it has none.
+#[unsafe(no_mangle)]
+pub extern "C" fn otel_posix_get_deadline() -> u64 {
+    current().map_or(0, |d| d.0)
+}
+
+/// Nanoseconds left until the calling thread's deadline, negative once it has passed,
+/// or `INT64_MAX` if it has none.
+#[unsafe(no_mangle)]
+pub extern "C" fn otel_posix_deadline_remaining() -> i64 {
+    let Some(Deadline(deadline)) = current() else {
+        return i64::MAX;
+    };
+    let mut now = unsafe { std::mem::zeroed::<libc::timespec>() };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    let now = now.tv_sec as i128 * 1_000_000_000 + now.tv_nsec as i128;
+    (deadline as i128 - now).clamp(i64::MIN.into(), i64::MAX.into()) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ns: u64) -> u64 {
+        otel_posix_set_deadline(ns)
+    }
+
+    #[test]
+    fn set_replaces_and_clears() {
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                assert_eq!(otel_posix_get_deadline(), 0);
+                assert_eq!(otel_posix_deadline_remaining(), i64::MAX);
+                assert_eq!(set(5_000), 5_000);
+                assert_eq!(set(9_000), 9_000);
+                assert_eq!(otel_posix_get_deadline(), 9_000);
+                // long gone
+                assert!(otel_posix_deadline_remaining() < 0);
+                assert_eq!(set(0), 0);
+                assert_eq!(otel_posix_get_deadline(), 0);
+            });
+        });
+    }
+
+    #[test]
+    fn a_given_deadline_is_only_brought_forward() {
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                let _given = attach(None, Some(Deadline(5_000)));
+                assert_eq!(set(9_000), 5_000);
+                assert_eq!(set(2_000), 2_000);
+                assert_eq!(set(0), 5_000);
+                assert_eq!(otel_posix_get_deadline(), 5_000);
+            });
+        });
+    }
+}
@@ -0,0 +1,332 @@
+// src/dlopen.rs
+//
+// Library load and unload events. With OTEL_POSIX_PROP_DLOPEN_EVENTS on,
+// each dlopen and dlclose made while a span is recording adds a `dlopen` or
+// `dlclose` event to it: the name asked for, the file loaded, the handle
+// and how long the call took, so seconds spent loading plugins show up in
+// the trace.
+//
+// glibc resolves a dlopen name relative to the object that called it: its
+// DT_RPATH or DT_RUNPATH, $ORIGIN, and the linker namespace it is in. A
+// call made from here would be resolved relative to this library instead.
+// So `dlopen` is a naked function that keeps the caller's return address:
+// it asks `route` whether to record the call, and either jumps to the real
+// dlopen, as if called directly, or to `record_dlopen`, which makes the
+// call itself. Calls whose result depends on the caller (a name without a
+// slash from an object with a search path of its own, a dynamic string
+// token, a caller in another namespace) are never made from here; they go
+// through unrecorded.
+//
+// RTLD_DEEPBIND calls are made from here whether or not they are recorded,
+// so that the objects they load can be pointed at the shim afterwards (see
+// namespaces.rs).
+
+use crate::config::config;
+use crate::{namespaces, reentry, resolve};
+use libc::{c_char, c_int};
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::{Context, KeyValue};
+use std::ffi::{CStr, c_void};
+use std::sync::atomic::AtomicPtr;
+use std::time::Instant;
+
+type DlopenFn = unsafe extern "C" fn(*const c_char, c_int) -> *mut c_void;
+type DlcloseFn = unsafe extern "C" fn(*mut c_void) -> c_int;
+
+static REAL_DLOPEN: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+// not in the libc crate
+const RTLD_DL_LINKMAP: c_int = 2;
+const DT_NULL: i64 = 0;
+const DT_RPATH: i64 = 15;
+const DT_RUNPATH: i64 = 29;
+
+#[repr(C)]
+struct Dyn {
+    tag: i64,
+    _val: u64,
+}
+
+// the public head of glibc's struct link_map
+#[repr(C)]
+struct LinkMap {
+    _addr: usize,
+    name: *const c_char,
+    ld: *const Dyn,
+}
+
+/// Whether events go on the current span: one is recording, and they are turned on.
+fn recording() -> bool {
+    let recording =
+        Context::map_current(|cx| cx.span().is_recording() && !cx.is_telemetry_suppressed());
+    // the configuration is only read once there's somewhere to put the event
+    recording && config().dlopen_events
+}
+
+/// Whether glibc resolves `file` from this library the same way as from the object
+/// containing `caller`.
+fn same_from_here(file: *const c_char, caller: *const c_void) -> bool {
+    if file.is_null() {
+        // the main program, whoever asks
+        return true;
+    }
+    let name = unsafe { CStr::from_ptr(file) }.to_bytes();
+    if name.contains(&b'$') {
+        return false;
+    }
+    let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+    let mut map: *mut LinkMap = std::ptr::null_mut();
+    let found = unsafe { libc::dladdr1(caller, &mut info, (&raw mut map).cast(), RTLD_DL_LINKMAP) };
+    if found == 0 || map.is_null() {
+        return false;
+    }
+    let mut lmid: libc::c_long = -1;
+    if unsafe { libc::dlinfo(map.cast(), libc::RTLD_DI_LMID, (&raw mut lmid).cast()) } != 0
+        || lmid != libc::LM_ID_BASE
+    {
+        return false;
+    }
+    name.contains(&b'/') || !has_search_path(unsafe { &*map })
+}
+
+/// Whether the object has a DT_RPATH or DT_RUNPATH of its own.
+fn has_search_path(map: &LinkMap) -> bool {
+    let mut entry = map.ld;
+    while !entry.is_null() {
+        match unsafe { (*entry).tag } {
+            DT_NULL => return false,
+            DT_RPATH | DT_RUNPATH => return true,
+            _ => entry = unsafe { entry.add(1) },
+        }
+    }
+    false
+}
+
+/// The file `handle` was loaded from, if it has one.
+fn loaded_path(handle: *mut c_void) -> Option<String> {
+    let mut map: *mut LinkMap = std::ptr::null_mut();
+    if unsafe { libc::dlinfo(handle, libc::RTLD_DI_LINKMAP, (&raw mut map).cast()) } != 0
+        || map.is_null()
+    {
+        return None;
+    }
+    let name = unsafe { (*map).name };
+    // the main program's is empty
+    (!name.is_null())
+        .then(|| {
+            unsafe { CStr::from_ptr(name) }
+                .to_string_lossy()
+                .into_owned()
+        })
+        .filter(|name| !name.is_empty())
+}
+
+// while this thread is resolving the real dlopen
+unsafe extern "C" fn unresolved(_: *const c_char, _: c_int) -> *mut c_void {
+    std::ptr::null_mut()
+}
+
+/// Called from the naked `dlopen` with the caller's arguments and return address: stores
+/// the real function in `real` and returns whether to make the call from here rather
+/// than jump straight to it.
+unsafe extern "C" fn route(
+    file: *const c_char,
+    mode: c_int,
+    caller: *const c_void,
+    real: *mut *const c_void,
+) -> bool {
+    let errno = unsafe { *libc::__errno_location() };
+    let target = resolve::cached_next(&REAL_DLOPEN, c"dlopen");
+    unsafe { *real = target.map_or(unresolved as *const c_void, |sym| sym.cast_const()) };
+    let record = target.is_some()
+        && reentry::enter().is_some_and(|_guard| {
+            if !namespaces::is_deepbind(mode) {
+                return recording() && same_from_here(file, caller);
+            }
+            let here = same_from_here(file, caller);
+            if !here {
+                namespaces::passed_by(file);
+            }
+            here
+        });
+    unsafe { *libc::__errno_location() = errno };
+    record
+}
+
+/// The real dlopen, made from here, with a `dlopen` event on the current span when one
+/// is recording.
+unsafe extern "C" fn record_dlopen(file: *const c_char, mode: c_int) -> *mut c_void {
+    let real = resolve::cached_next(&REAL_DLOPEN, c"dlopen")
+        .map(|sym| unsafe { std::mem::transmute::<*mut c_void, DlopenFn>(sym) });
+    let Some(real) = real else {
+        return std::ptr::null_mut();
+    };
+    // the objects already there, to tell the ones the call adds
+    let before = namespaces::is_deepbind(mode)
+        .then(|| reentry::enter().map(|_guard| namespaces::loaded()))
+        .flatten();
+    let start = Instant::now();
+    let handle = unsafe { real(file, mode) };
+    let duration = start.elapsed().as_secs_f64();
+    if let Some(_guard) = reentry::enter() {
+        let errno = unsafe { *libc::__errno_location() };
+        if let Some(before) = before.filter(|_| !handle.is_null()) {
+            namespaces::deepbound(handle, &before);
+        }
+        if recording() {
+            let mut attributes = Vec::with_capacity(5);
+            if !file.is_null() {
+                let name = unsafe { CStr::from_ptr(file) }.to_string_lossy();
+                attributes.push(KeyValue::new("otel_posix.dl.name", name.into_owned()));
+            }
+            attributes.extend(handle_attributes(handle, duration));
+            Context::map_current(|cx| cx.span().add_event("dlopen", attributes));
+        }
+        unsafe { *libc::__errno_location() = errno };
+    }
+    handle
+}
+
+fn handle_attributes(handle: *mut c_void, duration: f64) -> Vec<KeyValue> {
+    let mut attributes = vec![KeyValue::new("otel_posix.dl.duration", duration)];
+    if handle.is_null() {
+        // the reason stays with dlerror(), for the caller to read
+        attributes.push(KeyValue::new("otel_posix.dl.failed", true));
+        return attributes;
+    }
+    attributes.push(KeyValue::new(
+        "otel_posix.dl.handle",
+        format!("{:#x}", handle as usize),
+    ));
+    if let Some(path) = loaded_path(handle) {
+        attributes.push(KeyValue::new("otel_posix.dl.path", path));
+    }
+    attributes
+}
+
+/// Interposed `dlopen` that adds a `dlopen` event to the current span.
+///
+/// # Safety
+///
+/// Same contract as libc's `dlopen`.
+#[cfg(target_arch = "x86_64")]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dlopen(file: *const c_char, mode: c_int) -> *mut c_void {
+    std::arch::naked_asm!(
+        "push rbp",
+        "mov rbp, rsp",
+        "sub rsp, 32",
+        "mov qword ptr [rsp], rdi",
+        "mov qword ptr [rsp + 8], rsi",
+        "mov rdx, qword ptr [rbp + 8]",
+        "lea rcx, [rsp + 16]",
+        "call {route}",
+        "mov rdi, qword ptr [rsp]",
+        "mov rsi, qword ptr [rsp + 8]",
+        "mov r11, qword ptr [rsp + 16]",
+        "leave",
+        "test al, al",
+        "jnz {record}",
+        "jmp r11",
+        route = sym route,
+        record = sym record_dlopen,
+    )
+}
+
+/// Interposed `dlopen` that adds a `dlopen` event to the current span.
+///
+/// # Safety
+///
+/// Same contract as libc's `dlopen`.
+#[cfg(target_arch = "aarch64")]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dlopen(file: *const c_char, mode: c_int) -> *mut c_void {
+    std::arch::naked_asm!(
+        "stp x29, x30, [sp, #-48]!",
+        "mov x29, sp",
+        "stp x0, x1, [sp, #16]",
+        "mov x2, x30",
+        "add x3, sp, #32",
+        "bl {route}",
+        "mov w9, w0",
+        "ldr x16, [sp, #32]",
+        "ldp x0, x1, [sp, #16]",
+        "ldp x29, x30, [sp], #48",
+        "cbz w9, 2f",
+        "b {record}",
+        "2:",
+        "br x16",
+        route = sym route,
+        record = sym record_dlopen,
+    )
+}
+
+/// `dlclose`, with a `dlclose` event added to the current span.
+pub(crate) unsafe fn dlclose(real: DlcloseFn, handle: *mut c_void) -> c_int {
+    let Some(_guard) = reentry::enter() else {
+        return unsafe { real(handle) };
+    };
+    if handle.is_null() || !recording() {
+        return unsafe { real(handle) };
+    }
+    // read while the object is still mapped
+    let path = loaded_path(handle);
+    let start = Instant::now();
+    let rc = unsafe { real(handle) };
+    let duration = start.elapsed().as_secs_f64();
+    let errno = unsafe { *libc::__errno_location() };
+    let mut attributes = vec![
+        KeyValue::new("otel_posix.dl.duration", duration),
+        KeyValue::new("otel_posix.dl.handle", format!("{:#x}", handle as usize)),
+    ];
+    attributes.extend(path.map(|path| KeyValue::new("otel_posix.dl.path", path)));
+    if rc != 0 {
+        attributes.push(KeyValue::new("otel_posix.dl.failed", true));
+    }
+    Context::map_current(|cx| cx.span().add_event("dlclose", attributes));
+    unsafe { *libc::__errno_location() = errno };
+    rc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caller_relative_names_are_left_alone() {
+        let here = caller_relative_names_are_left_alone as *const c_void;
+        assert!(same_from_here(std::ptr::null(), here));
+        assert!(same_from_here(c"/usr/lib/libfoo.so".as_ptr(), here));
+        assert!(!same_from_here(c"$ORIGIN/libfoo.so".as_ptr(), here));
+        // not in any object: glibc would take it for the main program's
+        let heap = Box::new(0u8);
+        assert!(!same_from_here(
+            c"libfoo.so".as_ptr(),
+            (&raw const *heap).cast()
+        ));
+    }
+
+    #[test]
+    fn search_paths_are_found() {
+        let dynamic = [
+            Dyn { tag: 1, _val: 0 },
+            Dyn {
+                tag: DT_RUNPATH,
+                _val: 0,
+            },
+            Dyn {
+                tag: DT_NULL,
+                _val: 0,
+            },
+        ];
+        let map = |ld: &[Dyn]| LinkMap {
+            _addr: 0,
+            name: std::ptr::null(),
+            ld: ld.as_ptr(),
+        };
+        assert!(has_search_path(&map(&dynamic)));
+        assert!(!has_search_path(&map(&dynamic[2..])));
+    }
+}
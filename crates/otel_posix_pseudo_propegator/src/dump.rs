@@ -0,0 +1,282 @@
+// src/dump.rs
+//
+// On-demand dump of what every thread is serving, for correlating a hung
+// process with its traces without attaching a debugger:
+//
+//     OTEL_POSIX_PROP_DUMP_SIGNAL=USR2 ./daemon &
+//     kill -USR2 $!
+//
+// Almost nothing is async-signal-safe, so the handler only writes a byte to
+// a pipe; a thread of our own waits on the other end and writes the dump
+// from the tid registry (and the span names the SDK processor collected)
+// to stderr or OTEL_POSIX_PROP_DUMP_FILE, followed by the flight
+// recorder's events when it is on. Read straight from the
+// environment by a load-time constructor, like the exporter settings.
+
+use crate::log::{self, log_warn};
+use crate::w3c::format_traceparent;
+use crate::{recorder, registry};
+use libc::{c_int, pid_t};
+use opentelemetry::trace::{SpanContext, SpanId};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{LazyLock, Mutex, MutexGuard, PoisonError};
+
+// write end of the pipe the handler pokes
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+// set once the handler is installed; span names are only collected from then on
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+// names of live spans, for the dump
+static NAMES: LazyLock<Mutex<HashMap<SpanId, String>>> = LazyLock::new(Default::default);
+
+#[used]
+#[unsafe(link_section = ".init_array")]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(|| {
+        let Ok(value) = std::env::var("OTEL_POSIX_PROP_DUMP_SIGNAL") else {
+            return;
+        };
+        match parse_signal(&value) {
+            Some(signal) => install(signal, std::env::var("OTEL_POSIX_PROP_DUMP_FILE").ok()),
+            None => log_warn!("unknown OTEL_POSIX_PROP_DUMP_SIGNAL {value:?}, no dump handler"),
+        }
+    });
+}
+
+/// Parses a signal name (`USR2`, `SIGUSR2`) or number; `on`/`true` pick `SIGUSR2`.
+pub(crate) fn parse_signal(s: &str) -> Option<c_int> {
+    let s = s.trim().to_ascii_uppercase();
+    let name = s.strip_prefix("SIG").unwrap_or(&s);
+    match name {
+        "ON" | "TRUE" | "YES" | "USR2" => Some(libc::SIGUSR2),
+        "USR1" => Some(libc::SIGUSR1),
+        "HUP" => Some(libc::SIGHUP),
+        "QUIT" => Some(libc::SIGQUIT),
+        "WINCH" => Some(libc::SIGWINCH),
+        "PWR" => Some(libc::SIGPWR),
+        _ => name.parse().ok().filter(|&n| n > 0 && n < 65),
+    }
+}
+
+/// Starts the dump thread and installs the handler for `signal`, unless the process
+/// already handles it.
+fn install(signal: c_int, file: Option<String>) {
+    let mut old = unsafe { std::mem::zeroed::<libc::sigaction>() };
+    unsafe { libc::sigaction(signal, std::ptr::null(), &mut old) };
+    if old.sa_sigaction != libc::SIG_DFL && old.sa_sigaction != on_signal as *const () as usize {
+        log_warn!("signal {signal} already has a handler, no dump handler");
+        return;
+    }
+    let mut fds = [0 as c_int; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        log_warn!(
+            "failed to create the dump pipe: {}",
+            std::io::Error::last_os_error()
+        );
+        return;
+    }
+    // our own plumbing, not a thread of the host's
+    let _suppress = crate::suppress_wrapping();
+    let [read, write] = fds;
+    let spawned = std::thread::Builder::new()
+        .name("otel-posix-dump".into())
+        .spawn(move || wait(read, file));
+    if let Err(e) = spawned {
+        log_warn!("failed to start the dump thread: {e}");
+        unsafe { libc::close(read) };
+        unsafe { libc::close(write) };
+        return;
+    }
+    PIPE.store(write, Ordering::Release);
+    ENABLED.store(true, Ordering::Release);
+    unsafe { libc::pthread_atfork(None, None, Some(forked)) };
+
+    let mut action = unsafe { std::mem::zeroed::<libc::sigaction>() };
+    action.sa_sigaction = on_signal as *const () as usize;
+    action.sa_flags = libc::SA_RESTART;
+    unsafe { libc::sigemptyset(&mut action.sa_mask) };
+    unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) };
+}
+
+extern "C" fn on_signal(_: c_int) {
+    // write() is async-signal-safe, but may clobber the interrupted code's errno
+    let errno = unsafe { *libc::__errno_location() };
+    let fd = PIPE.load(Ordering::Acquire);
+    if fd >= 0 {
+        unsafe { libc::write(fd, b"d".as_ptr().cast(), 1) };
+    }
+    unsafe { *libc::__errno_location() = errno };
+}
+
+// the dump thread stays behind in the parent, which shouldn't dump on the child's signal
+extern "C" fn forked() {
+    PIPE.store(-1, Ordering::Release);
+}
+
+fn wait(fd: c_int, file: Option<String>) {
+    let mut byte = 0u8;
+    loop {
+        match unsafe { libc::read(fd, (&mut byte as *mut u8).cast(), 1) } {
+            1 => write_dump(file.as_deref()),
+            -1 if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => {}
+            _ => return,
+        }
+    }
+}
+
+/// The dump of every thread with a context, as of now.
+pub(crate) fn snapshot() -> String {
+    let threads: Vec<(pid_t, SpanContext)> = registry::lock_threads()
+        .iter()
+        .map(|(tid, sc)| (*tid, sc.clone()))
+        .collect();
+    render(
+        unsafe { libc::getpid() },
+        threads,
+        &lock_names(),
+        log::redact_ids(),
+    )
+}
+
+fn write_dump(file: Option<&str>) {
+    let mut dump = snapshot();
+    if recorder::enabled() {
+        dump.push_str(&recorder::snapshot());
+    }
+    let written = match file {
+        Some(path) => std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut f| f.write_all(dump.as_bytes())),
+        None => std::io::stderr().write_all(dump.as_bytes()),
+    };
+    if let Err(e) = written {
+        log_warn!("failed to write the thread dump: {e}");
+    }
+}
+
+/// One header line, then a line per thread: tid, traceparent (unless `redact`ed) and
+/// span name if known.
+fn render(
+    pid: pid_t,
+    mut threads: Vec<(pid_t, SpanContext)>,
+    names: &HashMap<SpanId, String>,
+    redact: bool,
+) -> String {
+    threads.sort_by_key(|(tid, _)| *tid);
+    let mut out = format!(
+        "otel_posix_pseudo_propegator: thread dump, pid {pid}, {} threads with a context\n",
+        threads.len()
+    );
+    for (tid, sc) in threads {
+        if redact {
+            let _ = write!(out, "  tid={tid} traceparent={}", log::REDACTED);
+        } else {
+            let _ = write!(out, "  tid={tid} traceparent={}", format_traceparent(&sc));
+        }
+        if let Some(name) = names.get(&sc.span_id()) {
+            let _ = write!(out, " span={name:?}");
+        }
+        out.push('\n');
+    }
+    out
+}
+
+pub(crate) fn lock_names() -> MutexGuard<'static, HashMap<SpanId, String>> {
+    NAMES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Whether the dump handler is installed, and span names worth collecting.
+#[cfg_attr(not(feature = "sdk"), allow(dead_code))]
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Remembers the name of a live span.
+#[cfg_attr(not(feature = "sdk"), allow(dead_code))]
+pub(crate) fn span_started(span_id: SpanId, name: &str) {
+    lock_names().insert(span_id, name.to_string());
+}
+
+/// Forgets the name of a span that ended.
+#[cfg_attr(not(feature = "sdk"), allow(dead_code))]
+pub(crate) fn span_ended(span_id: SpanId) {
+    lock_names().remove(&span_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{TraceFlags, TraceId, TraceState};
+
+    #[test]
+    fn signal_names() {
+        assert_eq!(parse_signal("USR2"), Some(libc::SIGUSR2));
+        assert_eq!(parse_signal("sigusr1"), Some(libc::SIGUSR1));
+        assert_eq!(parse_signal("on"), Some(libc::SIGUSR2));
+        assert_eq!(parse_signal("3"), Some(libc::SIGQUIT));
+        assert_eq!(parse_signal("SIGNOPE"), None);
+        assert_eq!(parse_signal("0"), None);
+    }
+
+    #[test]
+    fn dump_lists_threads_by_tid() {
+        let sc = |span: &str| {
+            SpanContext::new(
+                TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+                SpanId::from_hex(span).unwrap(),
+                TraceFlags::SAMPLED,
+                true,
+                TraceState::default(),
+            )
+        };
+        let names = HashMap::from([(SpanId::from_hex("00000000000000a2").unwrap(), "job".into())]);
+        let dump = render(
+            7,
+            vec![(12, sc("00000000000000a2")), (11, sc("00000000000000a1"))],
+            &names,
+            false,
+        );
+        assert_eq!(
+            dump,
+            "otel_posix_pseudo_propegator: thread dump, pid 7, 2 threads with a context\n\
+             \x20 tid=11 traceparent=00-4bf92f3577b34da6a3ce929d0e0e4736-00000000000000a1-01\n\
+             \x20 tid=12 traceparent=00-4bf92f3577b34da6a3ce929d0e0e4736-00000000000000a2-01 span=\"job\"\n"
+        );
+        let redacted = render(7, vec![(12, sc("00000000000000a2"))], &names, true);
+        assert_eq!(
+            redacted,
+            "otel_posix_pseudo_propegator: thread dump, pid 7, 1 threads with a context\n\
+             \x20 tid=12 traceparent=redacted span=\"job\"\n"
+        );
+    }
+
+    #[test]
+    fn signal_writes_the_dump() {
+        let path = std::env::temp_dir().join(format!("otel-posix-dump-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        install(libc::SIGUSR2, Some(path.to_string_lossy().into_owned()));
+        unsafe { libc::raise(libc::SIGUSR2) };
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        let dump = loop {
+            let dump = std::fs::read_to_string(&path).unwrap_or_default();
+            if dump.ends_with('\n') || std::time::Instant::now() > deadline {
+                break dump;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        };
+        let _ = std::fs::remove_file(&path);
+        assert!(
+            dump.starts_with("otel_posix_pseudo_propegator: thread dump, pid "),
+            "{dump:?}"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+// src/early.rs
+//
+// Threads created before the exporter is installed. The provider is built
+// on a thread of its own once the load-time constructor has run, and until
+// it is in place every span started goes to the no-op provider, so the
+// threads a host starts from its own constructors, or early in main, leave
+// nothing behind however their context was seeded.
+//
+// While an install is still to come, each thread creation is noted in a
+// fixed buffer instead: when, by which thread, the start routine, and the
+// creator's span if it had one. Once the tracer provider is in place, the
+// notes become one span, "early threads", from the first creation until
+// then, with an event for each thread at the time it was created and a link
+// to each creating span. When the environment asks for no exporter, the
+// buffer is closed by the constructor and nothing more is noted.
+//
+// Noting a creation takes one fetch_add and never allocates or blocks, like
+// the flight recorder's; the first SLOTS are kept and the rest counted.
+
+use crate::{StartRoutine, entry, scope, suppress};
+use opentelemetry::trace::{
+    Event, Link, Span, SpanContext, SpanId, SpanKind, TraceContextExt, TraceFlags, TraceId,
+    TraceState, Tracer,
+};
+use opentelemetry::{Context, KeyValue};
+use std::cell::UnsafeCell;
+use std::ffi::c_void;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+const SLOTS: usize = 64;
+
+// cleared for good by the install, or by the constructor when there's none to wait for
+static OPEN: AtomicBool = AtomicBool::new(true);
+// slots claimed, the ones past SLOTS only counted
+static CLAIMED: AtomicUsize = AtomicUsize::new(0);
+static BUFFER: [Slot; SLOTS] = [const { Slot::new() }; SLOTS];
+
+/// One thread creation.
+#[derive(Clone, Copy)]
+struct Created {
+    at: SystemTime,
+    creator: libc::pid_t,
+    start_routine: usize,
+    span: Option<(TraceId, SpanId, TraceFlags)>,
+}
+
+struct Slot {
+    ready: AtomicBool,
+    created: UnsafeCell<MaybeUninit<Created>>,
+}
+
+// each slot is written once, by the thread that claimed it, and only read once `ready`
+unsafe impl Sync for Slot {}
+
+impl Slot {
+    const fn new() -> Self {
+        Slot {
+            ready: AtomicBool::new(false),
+            created: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+}
+
+/// Wraps `real`, the call that creates a thread starting in `start_routine`, so that a
+/// successful creation is noted while the buffer is open.
+pub(crate) fn recording(
+    start_routine: StartRoutine,
+    real: impl FnOnce(StartRoutine, *mut c_void) -> i32,
+) -> impl FnOnce(StartRoutine, *mut c_void) -> i32 {
+    move |start, arg| {
+        let rc = real(start, arg);
+        if rc == 0 && OPEN.load(Ordering::Relaxed) {
+            record(start_routine);
+        }
+        rc
+    }
+}
+
+fn record(start_routine: StartRoutine) {
+    // the SDK's own threads, and the install's
+    if suppress::is_suppressed() {
+        return;
+    }
+    let Some(span) = Context::map_current(|cx| {
+        if cx.is_telemetry_suppressed() {
+            return None;
+        }
+        let span = cx.span();
+        let sc = span.span_context();
+        Some(
+            sc.is_valid()
+                .then(|| (sc.trace_id(), sc.span_id(), sc.trace_flags())),
+        )
+    }) else {
+        return;
+    };
+    let i = CLAIMED.fetch_add(1, Ordering::Relaxed);
+    let Some(slot) = BUFFER.get(i) else {
+        return;
+    };
+    let created = Created {
+        at: SystemTime::now(),
+        creator: unsafe { libc::gettid() },
+        start_routine: start_routine as usize,
+        span,
+    };
+    unsafe { (*slot.created.get()).write(created) };
+    slot.ready.store(true, Ordering::Release);
+}
+
+/// Stops noting creations, dropping those noted: no provider is coming.
+pub(crate) fn discard() {
+    OPEN.store(false, Ordering::Relaxed);
+}
+
+/// Stops noting creations and reports those noted to the tracer provider just installed.
+pub(crate) fn flush() {
+    if !OPEN.swap(false, Ordering::Relaxed) {
+        return;
+    }
+    let claimed = CLAIMED.load(Ordering::Relaxed);
+    // a creation still being written when the buffer closed is left out
+    let created: Vec<Created> = BUFFER[..claimed.min(SLOTS)]
+        .iter()
+        .filter(|slot| slot.ready.load(Ordering::Acquire))
+        .map(|slot| unsafe { (*slot.created.get()).assume_init() })
+        .collect();
+    if let Some(first) = created.iter().map(|c| c.at).min() {
+        report(first, &created, claimed.saturating_sub(SLOTS));
+    }
+}
+
+fn report(first: SystemTime, created: &[Created], dropped: usize) {
+    let mut links: Vec<Link> = Vec::new();
+    let mut events = Vec::with_capacity(created.len());
+    for c in created {
+        if let Some((trace_id, span_id, flags)) = c.span
+            && !links.iter().any(|l| l.span_context.span_id() == span_id)
+        {
+            let sc = SpanContext::new(trace_id, span_id, flags, true, TraceState::default());
+            links.push(Link::with_context(sc));
+        }
+        let mut attributes = vec![KeyValue::new(
+            "otel_posix.thread.creator.id",
+            i64::from(c.creator),
+        )];
+        attributes.extend(entry::code_attributes(c.start_routine as *const c_void));
+        events.push(Event::new("thread.create", c.at, attributes, 0));
+    }
+    let mut attributes = vec![KeyValue::new(
+        "otel_posix.threads.early",
+        created.len() as i64,
+    )];
+    if dropped > 0 {
+        attributes.push(KeyValue::new(
+            "otel_posix.threads.early.dropped",
+            dropped as i64,
+        ));
+    }
+    let tracer = scope::tracer();
+    let mut span = tracer
+        .span_builder("early threads")
+        .with_kind(SpanKind::Internal)
+        .with_start_time(first)
+        .with_links(links)
+        .with_events(events)
+        .with_attributes(attributes)
+        // an empty parent makes this a root span
+        .start_with_context(&tracer, &Context::new());
+    span.end();
+}
@@ -0,0 +1,194 @@
+// src/entry.rs
+//
+// Filtering by thread entry point. Some pools (jemalloc's background
+// threads, a library's internal workers) are noise however they were
+// created, so the start routine passed to pthread_create is looked up with
+// dladdr and matched against an allowlist and a denylist.
+//
+// dladdr only knows exported symbols. For a start routine that isn't
+// exported (a static function, or anything in a stripped executable) the
+// name used is the file name of the object containing it instead, so
+// `libjemalloc.so*` covers every thread jemalloc starts. The same lookup
+// gives the thread spans and join events their code.function and
+// code.namespace attributes, so traces say what each thread runs.
+//
+// Pools start every thread in the same routine, so decisions are cached by
+// address, for the filter they were made with. The cache is only
+// try-locked: a contended (or, after fork, orphaned) lock just means
+// another dladdr.
+
+use crate::filter::{Glob, parse_globs};
+use opentelemetry::KeyValue;
+use std::collections::HashMap;
+use std::ffi::{CStr, c_void};
+use std::path::Path;
+use std::sync::{LazyLock, Mutex};
+
+/// Start routines whose decision is remembered at most.
+const CACHED: usize = 256;
+
+// the filter decided with (its address; reloaded configurations are never freed), and
+// whether the start routine at an address may be wrapped
+static DECISIONS: LazyLock<Mutex<(usize, HashMap<usize, bool>)>> = LazyLock::new(Default::default);
+
+/// Allow/deny lists from `OTEL_POSIX_PROP_ENTRY_ALLOW` and `OTEL_POSIX_PROP_ENTRY_DENY`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EntryFilter {
+    allow: Vec<Glob>,
+    deny: Vec<Glob>,
+}
+
+impl EntryFilter {
+    /// Builds the filter from comma-separated glob lists; `None` if both are empty.
+    pub fn parse(allow: Option<&str>, deny: Option<&str>) -> Option<Self> {
+        let allow = allow.map(parse_globs).unwrap_or_default();
+        let deny = deny.map(parse_globs).unwrap_or_default();
+        (!allow.is_empty() || !deny.is_empty()).then_some(EntryFilter { allow, deny })
+    }
+
+    /// Whether a thread starting in `start_routine` may be wrapped.
+    pub fn allows(&self, start_routine: *const c_void) -> bool {
+        self.allows_name(start_routine_name(start_routine).as_deref())
+    }
+
+    // a routine whose name can't be found is only excluded by an allowlist
+    fn allows_name(&self, name: Option<&str>) -> bool {
+        let matches =
+            |patterns: &[Glob]| name.is_some_and(|n| patterns.iter().any(|p| p.matches(n)));
+        (self.allow.is_empty() || matches(&self.allow)) && !matches(&self.deny)
+    }
+}
+
+/// [`EntryFilter::allows`], remembered per start routine.
+pub(crate) fn allows_cached(filter: &EntryFilter, start_routine: *const c_void) -> bool {
+    let addr = start_routine as usize;
+    let made_with = filter as *const EntryFilter as usize;
+    let cached = DECISIONS
+        .try_lock()
+        .ok()
+        .filter(|d| d.0 == made_with)
+        .and_then(|d| d.1.get(&addr).copied());
+    if let Some(allowed) = cached {
+        return allowed;
+    }
+    let allowed = filter.allows(start_routine);
+    if let Ok(mut decisions) = DECISIONS.try_lock() {
+        let (filter, decisions) = &mut *decisions;
+        if *filter != made_with {
+            // the configuration was reloaded
+            *filter = made_with;
+            decisions.clear();
+        }
+        if decisions.len() < CACHED {
+            decisions.insert(addr, allowed);
+        }
+    }
+    allowed
+}
+
+/// The exported symbol at `addr`, or the file name of the object containing it.
+pub(crate) fn start_routine_name(addr: *const c_void) -> Option<String> {
+    let (symbol, object) = lookup(addr)?;
+    symbol.or(object)
+}
+
+/// The semantic-conventions `code.function` (the exported symbol at `addr`) and
+/// `code.namespace` (the file name of the object containing it) attributes, as far as
+/// dladdr knows them.
+pub(crate) fn code_attributes(addr: *const c_void) -> Vec<KeyValue> {
+    let Some((symbol, object)) = lookup(addr) else {
+        return Vec::new();
+    };
+    let function = symbol.map(|name| KeyValue::new("code.function", name));
+    let namespace = object.map(|name| KeyValue::new("code.namespace", name));
+    function.into_iter().chain(namespace).collect()
+}
+
+/// The exported symbol starting exactly at `addr`, and the file name of the object
+/// containing it; `None` if `addr` isn't in any loaded object.
+fn lookup(addr: *const c_void) -> Option<(Option<String>, Option<String>)> {
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    if unsafe { libc::dladdr(addr, &mut info) } == 0 {
+        return None;
+    }
+    let cstr = |p: *const libc::c_char| unsafe { CStr::from_ptr(p) }.to_string_lossy();
+    // otherwise dli_sname is just the nearest exported symbol before addr, which is
+    // misleading
+    let symbol = (!info.dli_sname.is_null() && info.dli_saddr.cast_const() == addr)
+        .then(|| cstr(info.dli_sname).into_owned());
+    let object = (!info.dli_fname.is_null()).then(|| {
+        let path = cstr(info.dli_fname);
+        Path::new(path.as_ref())
+            .file_name()
+            .map_or_else(|| path.to_string(), |n| n.to_string_lossy().into_owned())
+    });
+    Some((symbol, object))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern "C" fn local_routine(_: *mut c_void) -> *mut c_void {
+        std::ptr::null_mut()
+    }
+
+    #[test]
+    fn names_exported_symbols_and_objects() {
+        let name = start_routine_name(libc::pthread_self as *const c_void);
+        assert_eq!(name.as_deref(), Some("pthread_self"));
+
+        // the test binary doesn't export its functions, so this falls back to its file name
+        let exe = std::env::current_exe().unwrap();
+        let name = start_routine_name(local_routine as *const c_void);
+        assert_eq!(name.as_deref(), exe.file_name().and_then(|n| n.to_str()));
+    }
+
+    #[test]
+    fn code_attributes_name_function_and_object() {
+        let attributes = code_attributes(libc::pthread_self as *const c_void);
+        assert_eq!(
+            attributes[0],
+            KeyValue::new("code.function", "pthread_self")
+        );
+        assert_eq!(attributes[1].key.as_str(), "code.namespace");
+        assert!(attributes[1].value.as_str().starts_with("libc"));
+
+        // an unexported routine is only known by its object
+        let exe = std::env::current_exe().unwrap();
+        let exe = exe.file_name().unwrap().to_string_lossy().into_owned();
+        assert_eq!(
+            code_attributes(local_routine as *const c_void),
+            [KeyValue::new("code.namespace", exe)]
+        );
+    }
+
+    #[test]
+    fn allow_and_deny() {
+        assert_eq!(EntryFilter::parse(None, Some(" , ")), None);
+
+        let deny = EntryFilter::parse(None, Some("background_thread*,libjemalloc.so*")).unwrap();
+        assert!(!deny.allows_name(Some("libjemalloc.so.2")));
+        assert!(deny.allows_name(Some("worker_main")));
+        assert!(deny.allows_name(None));
+
+        let allow = EntryFilter::parse(Some("worker_*"), Some("worker_noisy")).unwrap();
+        assert!(allow.allows_name(Some("worker_main")));
+        assert!(!allow.allows_name(Some("worker_noisy")));
+        assert!(!allow.allows_name(Some("other")));
+        assert!(!allow.allows_name(None));
+    }
+
+    #[test]
+    fn decisions_are_cached() {
+        let deny = EntryFilter::parse(None, Some("pthread_self")).unwrap();
+        let routine = libc::pthread_self as *const c_void;
+        assert!(!allows_cached(&deny, routine));
+        let cached = |addr| DECISIONS.lock().unwrap().1.get(&addr).copied();
+        assert_eq!(cached(routine as usize), Some(false));
+        // a reloaded filter decides again
+        let allow_all = EntryFilter::parse(None, Some("nothing")).unwrap();
+        assert!(allows_cached(&allow_all, routine));
+        assert_eq!(cached(routine as usize), Some(true));
+    }
+}
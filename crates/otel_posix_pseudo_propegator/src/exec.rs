@@ -0,0 +1,261 @@
+// src/exec.rs
+//
+// Context propagation into child processes. The exec family and
+// posix_spawn are interposed, and while a span is active the child's
+// environment gets the context in the formats selected by OTEL_PROPAGATORS
+// (see propagators.rs), along with the baggage and resource attributes
+// this process was given. Stale values inherited from our own parent are
+// replaced, not duplicated. With OTEL_POSIX_PROP_EXEC_ALLOW set, only the
+// executables it names get anything; the others keep the environment they
+// were given, untouched.
+//
+// execl*() are variadic and can't be interposed from Rust; glibc builds
+// them on an internal execve, so they pass through untouched.
+//
+// With child spans on (children.rs), a spawned child's environment carries
+// the context of its own `child process` span rather than the caller's.
+
+#[cfg(feature = "hook-wait")]
+use crate::children;
+use crate::config::config;
+use crate::propagators::{self, ALL_VARS, RESOURCE_VAR};
+#[cfg(feature = "hook-wait")]
+use crate::reentry;
+use libc::{c_char, c_int, pid_t, posix_spawn_file_actions_t, posix_spawnattr_t};
+use opentelemetry::Context;
+use std::ffi::{CStr, CString};
+
+type ExecveFn =
+    unsafe extern "C" fn(*const c_char, *const *const c_char, *const *const c_char) -> c_int;
+type ExecvFn = unsafe extern "C" fn(*const c_char, *const *const c_char) -> c_int;
+type PosixSpawnFn = unsafe extern "C" fn(
+    *mut pid_t,
+    *const c_char,
+    *const posix_spawn_file_actions_t,
+    *const posix_spawnattr_t,
+    *const *mut c_char,
+    *const *mut c_char,
+) -> c_int;
+
+unsafe extern "C" {
+    static mut environ: *const *const c_char;
+}
+
+/// A copy of an environment block with the current context added. The original
+/// entries are borrowed; only the injected ones are owned here.
+struct Env {
+    _owned: Vec<CString>,
+    ptrs: Vec<*const c_char>,
+}
+
+impl Env {
+    /// Builds the environment of the child running `path` from `envp`, or `None` if
+    /// there's nothing to inject.
+    fn with_context(path: *const c_char, envp: *const *const c_char) -> Option<Env> {
+        let config = config();
+        if !config.hooks.exec || !allowed(path) {
+            return None;
+        }
+        let mut vars = Context::map_current(|cx| {
+            if cx.is_telemetry_suppressed() {
+                return Vec::new();
+            }
+            propagators::env_vars(cx, &config.propagators)
+        });
+        propagators::forward(
+            &mut vars,
+            config.exec_forward,
+            &config.propagators,
+            |key| unsafe { lookup(envp, key) },
+            |key| std::env::var(key).ok(),
+        );
+        if vars.is_empty() {
+            return None;
+        }
+
+        let resource = vars.iter().any(|(k, _)| *k == RESOURCE_VAR);
+        let owned: Vec<CString> = vars
+            .into_iter()
+            .filter_map(|(k, v)| CString::new(format!("{k}={v}")).ok())
+            .collect();
+        let mut ptrs = Vec::new();
+        let mut entry = envp;
+        while !envp.is_null() && !unsafe { *entry }.is_null() {
+            let bytes = unsafe { CStr::from_ptr(*entry) }.to_bytes();
+            let key = bytes.split(|&b| b == b'=').next().unwrap_or_default();
+            let replaced = ALL_VARS.iter().any(|v| v.as_bytes() == key)
+                || (resource && key == RESOURCE_VAR.as_bytes());
+            if !replaced {
+                ptrs.push(unsafe { *entry });
+            }
+            entry = unsafe { entry.add(1) };
+        }
+        ptrs.extend(owned.iter().map(|s| s.as_ptr()));
+        ptrs.push(std::ptr::null());
+        Some(Env {
+            _owned: owned,
+            ptrs,
+        })
+    }
+
+    fn as_ptr(&self) -> *const *const c_char {
+        self.ptrs.as_ptr()
+    }
+}
+
+/// Whether the executable started by `path` is on the allowlist, if there is one.
+fn allowed(path: *const c_char) -> bool {
+    config().exec_allow.as_ref().is_none_or(|allow| {
+        !path.is_null() && allow.allows(&unsafe { CStr::from_ptr(path) }.to_string_lossy())
+    })
+}
+
+/// The value of `key` in the environment block `envp`.
+///
+/// # Safety
+///
+/// `envp` must be null or a NULL-terminated array of C strings.
+unsafe fn lookup(envp: *const *const c_char, key: &str) -> Option<String> {
+    let mut entry = envp;
+    while !envp.is_null() && !unsafe { *entry }.is_null() {
+        let bytes = unsafe { CStr::from_ptr(*entry) }.to_bytes();
+        if let Some(value) = bytes
+            .strip_prefix(key.as_bytes())
+            .and_then(|rest| rest.strip_prefix(b"="))
+        {
+            return Some(String::from_utf8_lossy(value).into_owned());
+        }
+        entry = unsafe { entry.add(1) };
+    }
+    None
+}
+
+/// Runs an exec-style call of `path` that reads `environ` with the context swapped in,
+/// restoring the original once it returns (for an exec, only when it failed).
+pub(crate) fn with_environ<T>(path: *const c_char, exec: impl FnOnce() -> T) -> T {
+    let original = unsafe { environ };
+    let Some(env) = Env::with_context(path, original) else {
+        return exec();
+    };
+    unsafe { environ = env.as_ptr() };
+    let rc = exec();
+    unsafe { environ = original };
+    rc
+}
+
+/// `execve` with the current context added to `envp`.
+pub(crate) unsafe fn execve(
+    real: ExecveFn,
+    path: *const c_char,
+    argv: *const *const c_char,
+    envp: *const *const c_char,
+) -> c_int {
+    match Env::with_context(path, envp) {
+        Some(env) => unsafe { real(path, argv, env.as_ptr()) },
+        None => unsafe { real(path, argv, envp) },
+    }
+}
+
+/// `execv` with the current context added to the inherited environment.
+pub(crate) unsafe fn execv(
+    real: ExecvFn,
+    path: *const c_char,
+    argv: *const *const c_char,
+) -> c_int {
+    with_environ(path, || unsafe { real(path, argv) })
+}
+
+/// `execvp` with the current context added to the inherited environment.
+pub(crate) unsafe fn execvp(
+    real: ExecvFn,
+    file: *const c_char,
+    argv: *const *const c_char,
+) -> c_int {
+    with_environ(file, || unsafe { real(file, argv) })
+}
+
+/// Spawns the child with a span of its own, where there is one to give it (see
+/// children.rs), whose context its environment gets.
+#[cfg(feature = "hook-wait")]
+unsafe fn spawn_with_context(
+    real: PosixSpawnFn,
+    pid: *mut pid_t,
+    path: *const c_char,
+    file_actions: *const posix_spawn_file_actions_t,
+    attrp: *const posix_spawnattr_t,
+    argv: *const *mut c_char,
+    envp: *const *mut c_char,
+) -> c_int {
+    let child =
+        reentry::enter().and_then(|_guard| children::begin(Some(unsafe { CStr::from_ptr(path) })));
+    let Some(cx) = child else {
+        return unsafe { spawn_with_env(real, pid, path, file_actions, attrp, argv, envp) };
+    };
+    // the child's pid is needed to end its span, whether or not the caller wants it
+    let mut own = 0;
+    let pid = if pid.is_null() { &raw mut own } else { pid };
+    let rc = {
+        let _attached = cx.clone().attach();
+        unsafe { spawn_with_env(real, pid, path, file_actions, attrp, argv, envp) }
+    };
+    let errno = unsafe { *libc::__errno_location() };
+    match rc {
+        0 => children::started(unsafe { *pid }, cx),
+        error => children::failed(cx, error),
+    }
+    unsafe { *libc::__errno_location() = errno };
+    rc
+}
+
+#[cfg(not(feature = "hook-wait"))]
+use spawn_with_env as spawn_with_context;
+
+unsafe fn spawn_with_env(
+    real: PosixSpawnFn,
+    pid: *mut pid_t,
+    path: *const c_char,
+    file_actions: *const posix_spawn_file_actions_t,
+    attrp: *const posix_spawnattr_t,
+    argv: *const *mut c_char,
+    envp: *const *mut c_char,
+) -> c_int {
+    match Env::with_context(path, envp as *const *const c_char) {
+        Some(env) => unsafe {
+            real(
+                pid,
+                path,
+                file_actions,
+                attrp,
+                argv,
+                env.as_ptr() as *const *mut c_char,
+            )
+        },
+        None => unsafe { real(pid, path, file_actions, attrp, argv, envp) },
+    }
+}
+
+/// `posix_spawn` with the current context added to `envp`.
+pub(crate) unsafe fn posix_spawn(
+    real: PosixSpawnFn,
+    pid: *mut pid_t,
+    path: *const c_char,
+    file_actions: *const posix_spawn_file_actions_t,
+    attrp: *const posix_spawnattr_t,
+    argv: *const *mut c_char,
+    envp: *const *mut c_char,
+) -> c_int {
+    unsafe { spawn_with_context(real, pid, path, file_actions, attrp, argv, envp) }
+}
+
+/// `posix_spawnp` with the current context added to `envp`.
+pub(crate) unsafe fn posix_spawnp(
+    real: PosixSpawnFn,
+    pid: *mut pid_t,
+    file: *const c_char,
+    file_actions: *const posix_spawn_file_actions_t,
+    attrp: *const posix_spawnattr_t,
+    argv: *const *mut c_char,
+    envp: *const *mut c_char,
+) -> c_int {
+    unsafe { spawn_with_context(real, pid, file, file_actions, attrp, argv, envp) }
+}
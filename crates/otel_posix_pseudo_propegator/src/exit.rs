@@ -0,0 +1,55 @@
+// src/exit.rs
+//
+// Flushing on the way out. init.rs exports what is left from an atexit
+// handler, but _exit, _Exit and quick_exit don't run atexit handlers, and
+// short-lived tools that leave through them used to lose their last batch
+// of spans. So these, and exit itself, are interposed to flush the
+// installed providers first, waiting at most OTEL_POSIX_PROP_EXIT_TIMEOUT
+// (see init.rs) before carrying on with the real call. A process that
+// installed no provider of ours, or a forked child of one, exits straight
+// away.
+
+use crate::{init, reentry};
+use libc::c_int;
+
+type ExitFn = unsafe extern "C" fn(c_int) -> !;
+
+/// Flushes, then calls `real`.
+pub(crate) fn flush_and(real: ExitFn, status: c_int) -> ! {
+    if let Some(_guard) = reentry::enter() {
+        let errno = unsafe { *libc::__errno_location() };
+        let _ = std::panic::catch_unwind(init::flush_before_exit);
+        unsafe { *libc::__errno_location() = errno };
+    }
+    unsafe { real(status) }
+}
+
+/// Ends the process with the raw system call, standing in for the real call inside a
+/// symbol lookup.
+pub(crate) unsafe extern "C" fn exit_group(status: c_int) -> ! {
+    unsafe {
+        libc::syscall(libc::SYS_exit_group, status);
+        std::hint::unreachable_unchecked()
+    }
+}
+
+/// `exit`, flushing the installed providers first.
+pub(crate) unsafe fn exit(real: ExitFn, status: c_int) -> ! {
+    flush_and(real, status)
+}
+
+/// `_exit`, flushing the installed providers first.
+pub(crate) unsafe fn _exit(real: ExitFn, status: c_int) -> ! {
+    flush_and(real, status)
+}
+
+/// `_Exit`, flushing the installed providers first.
+#[allow(non_snake_case)]
+pub(crate) unsafe fn _Exit(real: ExitFn, status: c_int) -> ! {
+    flush_and(real, status)
+}
+
+/// `quick_exit`, flushing the installed providers first.
+pub(crate) unsafe fn quick_exit(real: ExitFn, status: c_int) -> ! {
+    flush_and(real, status)
+}
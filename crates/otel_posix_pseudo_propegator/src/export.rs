@@ -0,0 +1,379 @@
+// src/export.rs
+//
+// The span processor of the provider the shim installs itself. Ended
+// spans go into a bounded lock-free ring, and a dedicated exporter thread
+// of our own (started while wrapping is suppressed, so it carries no
+// context) takes them out in batches and hands them to the exporter. An
+// application thread ending a span, in its own code or in the trampoline,
+// only ever writes a slot: it never takes a lock the exporter holds while
+// it's on the network, and never waits for room. A span that finds the
+// ring full is dropped and counted, and the count is reported with the
+// next export.
+//
+// Any number of threads push; only whoever holds the exporter takes out,
+// the exporter thread on schedule, or a flush at exit. The ring is a
+// bounded queue of sequenced slots, where the sequence number says whose
+// turn a slot is. The sizes and the schedule come from the batch span
+// processor's OTEL_BSP_* variables.
+
+use crate::log::log_warn;
+use opentelemetry::Context;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::trace::{Span, SpanData, SpanExporter, SpanProcessor};
+use std::cell::UnsafeCell;
+use std::future::Future;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Poll, Wake, Waker};
+use std::thread::Thread;
+use std::time::Duration;
+
+const DEFAULT_QUEUE_SIZE: usize = 2048;
+const DEFAULT_BATCH_SIZE: usize = 512;
+const DEFAULT_SCHEDULE_DELAY: Duration = Duration::from_millis(5000);
+
+struct Slot {
+    // the position that may use the slot next: a producer when it equals the position, the
+    // consumer when it is one past
+    seq: AtomicUsize,
+    span: UnsafeCell<MaybeUninit<SpanData>>,
+}
+
+/// A bounded queue of spans that producers never wait on.
+pub(crate) struct Ring {
+    slots: Box<[Slot]>,
+    mask: usize,
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+// a slot's span is only touched by whoever its sequence number hands it to
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    /// A ring of at least `capacity` slots, rounded up to a power of two.
+    pub(crate) fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+        Ring {
+            slots: (0..capacity)
+                .map(|i| Slot {
+                    seq: AtomicUsize::new(i),
+                    span: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            mask: capacity - 1,
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Adds `span`, or drops it and returns false when the ring is full.
+    pub(crate) fn push(&self, span: SpanData) -> bool {
+        let mut pos = self.head.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos & self.mask];
+            let seq = slot.seq.load(Ordering::Acquire);
+            match seq.wrapping_sub(pos) as isize {
+                0 => match self.head.compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe { (*slot.span.get()).write(span) };
+                        slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+                        return true;
+                    }
+                    Err(current) => pos = current,
+                },
+                // the consumer hasn't emptied the slot from the last lap yet
+                diff if diff < 0 => return false,
+                // another producer took this position
+                _ => pos = self.head.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Takes out the oldest span, if it has been written completely.
+    ///
+    /// # Safety
+    ///
+    /// Only one thread may take out at a time.
+    pub(crate) unsafe fn pop(&self) -> Option<SpanData> {
+        let pos = self.tail.load(Ordering::Relaxed);
+        let slot = &self.slots[pos & self.mask];
+        if slot.seq.load(Ordering::Acquire) != pos.wrapping_add(1) {
+            return None;
+        }
+        let span = unsafe { (*slot.span.get()).assume_init_read() };
+        self.tail.store(pos.wrapping_add(1), Ordering::Relaxed);
+        slot.seq
+            .store(pos.wrapping_add(self.mask + 1), Ordering::Release);
+        Some(span)
+    }
+
+    /// Roughly how many spans are waiting.
+    pub(crate) fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Relaxed);
+        head.wrapping_sub(self.tail.load(Ordering::Relaxed))
+            .min(self.mask + 1)
+    }
+}
+
+impl Drop for Ring {
+    fn drop(&mut self) {
+        while unsafe { self.pop() }.is_some() {}
+    }
+}
+
+struct Shared<E> {
+    ring: Ring,
+    // also serializes taking spans out of the ring
+    exporter: Mutex<E>,
+    batch_size: usize,
+    dropped: AtomicU64,
+    shutdown: AtomicBool,
+}
+
+impl<E: SpanExporter> Shared<E> {
+    fn exporter(&self) -> MutexGuard<'_, E> {
+        self.exporter.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Exports everything in the ring, a batch at a time.
+    fn export_all(&self) -> OTelSdkResult {
+        let exporter = self.exporter();
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            log_warn!("{dropped} spans dropped, the export buffer was full");
+        }
+        let mut result = Ok(());
+        loop {
+            let batch: Vec<SpanData> = std::iter::from_fn(|| unsafe { self.ring.pop() })
+                .take(self.batch_size)
+                .collect();
+            if batch.is_empty() {
+                return result;
+            }
+            if let Err(e) = block_on(exporter.export(batch)) {
+                log_warn!("failed to export spans: {e}");
+                result = Err(e);
+            }
+        }
+    }
+}
+
+/// Span processor that buffers ended spans in a [`Ring`] for an exporter thread of its own.
+pub(crate) struct RingSpanProcessor<E> {
+    shared: Arc<Shared<E>>,
+    worker: Option<Thread>,
+}
+
+impl<E> std::fmt::Debug for RingSpanProcessor<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RingSpanProcessor")
+            .field("queued", &self.shared.ring.len())
+            .finish()
+    }
+}
+
+impl<E: SpanExporter + 'static> RingSpanProcessor<E> {
+    /// Starts the exporter thread for `exporter`, sized and scheduled by the `OTEL_BSP_*`
+    /// variables in `var`.
+    pub(crate) fn new(exporter: E, var: impl Fn(&str) -> Option<String>) -> Self {
+        let number = |key| var(key).and_then(|v: String| v.trim().parse::<u64>().ok());
+        let queue_size = number("OTEL_BSP_MAX_QUEUE_SIZE")
+            .filter(|&n| n > 0)
+            .map_or(DEFAULT_QUEUE_SIZE, |n| n as usize);
+        let batch_size = number("OTEL_BSP_MAX_EXPORT_BATCH_SIZE")
+            .filter(|&n| n > 0)
+            .map_or(DEFAULT_BATCH_SIZE, |n| n as usize)
+            .min(queue_size);
+        let delay =
+            number("OTEL_BSP_SCHEDULE_DELAY").map_or(DEFAULT_SCHEDULE_DELAY, Duration::from_millis);
+        let shared = Arc::new(Shared {
+            ring: Ring::new(queue_size),
+            exporter: Mutex::new(exporter),
+            batch_size,
+            dropped: AtomicU64::new(0),
+            shutdown: AtomicBool::new(false),
+        });
+        let worker = {
+            let shared = shared.clone();
+            std::thread::Builder::new()
+                .name("otel-posix-export".into())
+                .spawn(move || run(&shared, delay))
+        };
+        let worker = match worker {
+            Ok(handle) => Some(handle.thread().clone()),
+            Err(e) => {
+                // spans are still exported by flushes
+                log_warn!("failed to start the export thread: {e}");
+                None
+            }
+        };
+        RingSpanProcessor { shared, worker }
+    }
+}
+
+/// The exporter thread: exports every `delay`, or as soon as a batch is waiting.
+fn run<E: SpanExporter>(shared: &Shared<E>, delay: Duration) {
+    // the exporter's HTTP client starts threads of its own
+    let _suppress = crate::suppress_wrapping();
+    while !shared.shutdown.load(Ordering::Acquire) {
+        if shared.ring.len() < shared.batch_size {
+            std::thread::park_timeout(delay);
+        }
+        let _ = shared.export_all();
+    }
+}
+
+impl<E: SpanExporter + 'static> SpanProcessor for RingSpanProcessor<E> {
+    fn on_start(&self, _span: &mut Span, _cx: &Context) {}
+
+    fn on_end(&self, span: SpanData) {
+        if !span.span_context.is_sampled() || self.shared.shutdown.load(Ordering::Relaxed) {
+            return;
+        }
+        if !self.shared.ring.push(span) {
+            self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+        } else if self.shared.ring.len() == self.shared.batch_size
+            && let Some(worker) = &self.worker
+        {
+            worker.unpark();
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.shared.export_all()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        if self.shared.shutdown.swap(true, Ordering::AcqRel) {
+            return Err(OTelSdkError::AlreadyShutdown);
+        }
+        if let Some(worker) = &self.worker {
+            worker.unpark();
+        }
+        let exported = self.shared.export_all();
+        let shutdown = self.shared.exporter().shutdown_with_timeout(timeout);
+        exported.and(shutdown)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.shared.exporter().set_resource(resource);
+    }
+}
+
+/// Runs `future` to completion on the calling thread. The OTLP exporter's blocking client
+/// finishes in its first poll; anything else is polled again whenever it wakes us.
+fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(Thread);
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+    let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    let mut cx = std::task::Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+    use std::sync::mpsc;
+
+    fn span(name: &'static str) -> SpanData {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        drop(provider.tracer("test").start(name));
+        exporter.get_finished_spans().unwrap().remove(0)
+    }
+
+    #[test]
+    fn ring_is_first_in_first_out_and_bounded() {
+        let ring = Ring::new(3);
+        for name in ["a", "b", "c", "d"] {
+            assert!(ring.push(span(name)));
+        }
+        assert_eq!(ring.len(), 4);
+        assert!(!ring.push(span("e")));
+        let names: Vec<_> = std::iter::from_fn(|| unsafe { ring.pop() })
+            .map(|span| span.name)
+            .collect();
+        assert_eq!(names, ["a", "b", "c", "d"]);
+        // wrapped around
+        assert!(ring.push(span("f")));
+        assert_eq!(unsafe { ring.pop() }.unwrap().name, "f");
+        assert!(unsafe { ring.pop() }.is_none());
+    }
+
+    #[test]
+    fn spans_are_exported_by_a_flush() {
+        let exporter = InMemorySpanExporter::default();
+        let processor = RingSpanProcessor::new(exporter.clone(), |key| {
+            (key == "OTEL_BSP_SCHEDULE_DELAY").then(|| "600000".to_string())
+        });
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(processor)
+            .build();
+        let tracer = provider.tracer("test");
+        for _ in 0..3 {
+            drop(tracer.start("work"));
+        }
+        assert!(exporter.get_finished_spans().unwrap().is_empty());
+        provider.force_flush().unwrap();
+        assert_eq!(exporter.get_finished_spans().unwrap().len(), 3);
+    }
+
+    /// Holds every export until told to go on.
+    #[derive(Debug)]
+    struct Stuck(Mutex<mpsc::Receiver<()>>);
+
+    impl SpanExporter for Stuck {
+        async fn export(&self, _batch: Vec<SpanData>) -> OTelSdkResult {
+            let _ = self.0.lock().unwrap().recv();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn ending_spans_never_waits_for_the_exporter() {
+        let (release, held) = mpsc::channel();
+        let processor = RingSpanProcessor::new(Stuck(Mutex::new(held)), |key| match key {
+            "OTEL_BSP_MAX_QUEUE_SIZE" => Some("4".to_string()),
+            "OTEL_BSP_MAX_EXPORT_BATCH_SIZE" => Some("2".to_string()),
+            _ => None,
+        });
+        // the exporter thread takes what is there and gets stuck on it
+        processor.on_end(span("first"));
+        processor.on_end(span("second"));
+        while processor.shared.ring.len() == 2 {
+            std::thread::yield_now();
+        }
+        let left = processor.shared.ring.len() as u64;
+        for _ in 0..10 {
+            processor.on_end(span("more"));
+        }
+        assert_eq!(processor.shared.ring.len(), 4);
+        assert_eq!(
+            processor.shared.dropped.load(Ordering::Relaxed),
+            10 - (4 - left)
+        );
+        drop(release);
+    }
+}
@@ -0,0 +1,195 @@
+// src/ffi.rs
+//
+// C-callable accessors for the current OTEL Context, so hand-written C code
+// can read or seed the context where the pthread_create shim can't see it
+// (custom queues, callbacks, IPC). The header is generated by cbindgen into
+// include/otel_posix_pseudo_propegator.h.
+
+use crate::registry;
+use crate::w3c::{TRACEPARENT_LEN, format_traceparent, parse_traceparent};
+use libc::{c_char, c_int, pid_t, size_t};
+use opentelemetry::trace::{SpanContext, TraceContextExt};
+use opentelemetry::{Context, ContextGuard};
+use std::cell::RefCell;
+use std::ffi::CStr;
+
+thread_local! {
+    // context seeded from C via otel_posix_set_traceparent, kept attached
+    // until replaced or cleared
+    static SEEDED: RefCell<Seed> = const { RefCell::new(Seed(None)) };
+}
+
+struct Seed(Option<ContextGuard>);
+
+impl Drop for Seed {
+    fn drop(&mut self) {
+        // only runs at thread exit, when opentelemetry's own thread-local may already be
+        // destroyed; detaching would touch it and abort, and it's going away regardless
+        std::mem::forget(self.0.take());
+    }
+}
+
+/// Writes the current thread's W3C `traceparent` into `buf` as a NUL-terminated string.
+///
+/// Returns the length of the traceparent (excluding the NUL), or 0 if there is no valid
+/// span context on this thread. Like `snprintf`, nothing is written when `len` is too
+/// small, and the return value tells the caller how many bytes (minus one) are needed.
+///
+/// # Safety
+///
+/// `buf` must be null or valid for writes of `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn otel_posix_get_traceparent(buf: *mut c_char, len: size_t) -> c_int {
+    let sc = Context::map_current(|cx| cx.span().span_context().clone());
+    unsafe { write_traceparent(&sc, buf, len) }
+}
+
+/// Writes the W3C `traceparent` of the trace OS thread `tid` is serving into `buf`, like
+/// [`otel_posix_get_traceparent`] does for the calling thread.
+///
+/// Only contexts the shim established are known: a wrapped thread's inherited context,
+/// one seeded with [`otel_posix_set_traceparent`], or a coroutine's after a switch.
+/// Returns 0 for threads without one, including threads that have exited.
+///
+/// # Safety
+///
+/// `buf` must be null or valid for writes of `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn otel_posix_context_for_tid(
+    tid: pid_t,
+    buf: *mut c_char,
+    len: size_t,
+) -> c_int {
+    let sc = registry::lookup(tid).unwrap_or_else(SpanContext::empty_context);
+    unsafe { write_traceparent(&sc, buf, len) }
+}
+
+/// The `pthread_create` this library forwards to: libc's, or that of another interposer
+/// preloaded after it, which then passes the call on itself.
+///
+/// Interposers loaded before this one can call it to reach the rest of the chain without
+/// the context being carried over. Returns NULL if it hasn't been found (yet).
+#[cfg(feature = "preload")]
+#[unsafe(no_mangle)]
+pub extern "C" fn otel_posix_next_pthread_create() -> *mut std::ffi::c_void {
+    crate::real_pthread_create().map_or(std::ptr::null_mut(), |real| real as *mut _)
+}
+
+/// Formats `sc` into `buf` with `snprintf`-style truncation, returning its length, or 0
+/// for an invalid span context.
+unsafe fn write_traceparent(sc: &SpanContext, buf: *mut c_char, len: size_t) -> c_int {
+    if !sc.is_valid() {
+        return 0;
+    }
+    let tp = format_traceparent(sc);
+    debug_assert_eq!(tp.len(), TRACEPARENT_LEN);
+    if !buf.is_null() && len > tp.len() {
+        unsafe {
+            std::ptr::copy_nonoverlapping(tp.as_ptr(), buf as *mut u8, tp.len());
+            *buf.add(tp.len()) = 0;
+        }
+    }
+    tp.len() as c_int
+}
+
+/// Seeds the current thread's context from a W3C `traceparent` string.
+///
+/// The seeded context stays attached until the next call on the same thread; passing
+/// NULL detaches it again. Returns 0 on success and -1 if the string is not a valid
+/// traceparent, in which case the current context is left untouched.
+///
+/// # Safety
+///
+/// `traceparent` must be null or point to a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn otel_posix_set_traceparent(traceparent: *const c_char) -> c_int {
+    if traceparent.is_null() {
+        seed(None);
+        return 0;
+    }
+    let Some(sc) = unsafe { CStr::from_ptr(traceparent) }
+        .to_str()
+        .ok()
+        .and_then(parse_traceparent)
+    else {
+        return -1;
+    };
+    seed(Some(sc));
+    0
+}
+
+/// Keeps `sc` attached on the calling thread in place of the previous seed, or detaches
+/// the previous seed for `None`.
+pub(crate) fn seed(sc: Option<SpanContext>) {
+    SEEDED.with(|seeded| {
+        let mut seeded = seeded.borrow_mut();
+        // detach the previous seed first so the new one isn't stacked on top of it
+        seeded.0.take();
+        registry::set_current(sc.as_ref());
+        seeded.0 = sc.map(|sc| Context::current().with_remote_span_context(sc).attach());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    const TP: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn get() -> (c_int, String) {
+        let mut buf = [0 as c_char; 64];
+        let n = unsafe { otel_posix_get_traceparent(buf.as_mut_ptr(), buf.len()) };
+        let s = unsafe { CStr::from_ptr(buf.as_ptr()) };
+        (n, s.to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn get_without_context_returns_zero() {
+        assert_eq!(get(), (0, String::new()));
+    }
+
+    #[test]
+    fn set_then_get_round_trips() {
+        let tp = CString::new(TP).unwrap();
+        assert_eq!(unsafe { otel_posix_set_traceparent(tp.as_ptr()) }, 0);
+        assert_eq!(get(), (TRACEPARENT_LEN as c_int, TP.to_string()));
+
+        // an invalid seed leaves the current context alone
+        let bad = CString::new("garbage").unwrap();
+        assert_eq!(unsafe { otel_posix_set_traceparent(bad.as_ptr()) }, -1);
+        assert_eq!(get().1, TP);
+
+        assert_eq!(unsafe { otel_posix_set_traceparent(std::ptr::null()) }, 0);
+        assert_eq!(get().0, 0);
+    }
+
+    #[test]
+    fn seeded_context_is_found_by_tid() {
+        let tp = CString::new(TP).unwrap();
+        let tid = unsafe { libc::gettid() };
+        unsafe { otel_posix_set_traceparent(tp.as_ptr()) };
+        let mut buf = [0 as c_char; 64];
+        let n = unsafe { otel_posix_context_for_tid(tid, buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(n, TRACEPARENT_LEN as c_int);
+        assert_eq!(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str(), Ok(TP));
+
+        unsafe { otel_posix_set_traceparent(std::ptr::null()) };
+        let n = unsafe { otel_posix_context_for_tid(tid, buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(n, 0);
+    }
+
+    #[test]
+    fn short_buffer_reports_required_length() {
+        let tp = CString::new(TP).unwrap();
+        unsafe { otel_posix_set_traceparent(tp.as_ptr()) };
+        let mut buf = [0x7f as c_char; TRACEPARENT_LEN];
+        let n = unsafe { otel_posix_get_traceparent(buf.as_mut_ptr(), buf.len()) };
+        assert_eq!(n, TRACEPARENT_LEN as c_int);
+        assert!(
+            buf.iter().all(|&c| c == 0x7f),
+            "nothing written to a short buffer"
+        );
+        unsafe { otel_posix_set_traceparent(std::ptr::null()) };
+    }
+}
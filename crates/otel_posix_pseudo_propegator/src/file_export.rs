@@ -0,0 +1,209 @@
+// src/file_export.rs
+//
+// A span exporter writing to a local file instead of a collector, for
+// looking at propagation on a developer's machine (`otel-preload record`).
+// With OTEL_POSIX_PROP_TRACES_FILE set, the provider the shim installs
+// appends each ended span to that file as one line of JSON:
+//
+//     {"trace_id":"4bf9...","span_id":"00f0...","parent_span_id":"",
+//      "name":"thread","kind":"internal","start_unix_nano":...,
+//      "end_unix_nano":...,"status":"unset","service":"my_app","pid":4242,
+//      "attributes":{"thread.id":4243,...},
+//      "events":[{"name":"...","time_unix_nano":...,"attributes":{...}}]}
+//
+// Every process of a tree run under the shim inherits the variable and
+// appends to the same file. It's opened with O_APPEND and each batch goes
+// out in one write, so the lines of different processes don't interleave.
+
+use crate::log::log_warn;
+use crate::status::string;
+use opentelemetry::trace::{SpanId, SpanKind, Status};
+use opentelemetry::{Key, KeyValue, Value};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::trace::{SpanData, SpanExporter};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Write as _;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The file named by `OTEL_POSIX_PROP_TRACES_FILE`, if any.
+pub(crate) fn path(var: impl Fn(&str) -> Option<String>) -> Option<String> {
+    var("OTEL_POSIX_PROP_TRACES_FILE").filter(|v| !v.trim().is_empty())
+}
+
+/// Appends spans to a file, a line of JSON each.
+#[derive(Debug)]
+pub(crate) struct FileExporter {
+    file: File,
+    service: String,
+}
+
+impl FileExporter {
+    /// Opens `path` for appending, creating it if needed.
+    pub(crate) fn open(path: &str) -> Option<Self> {
+        match File::options().append(true).create(true).open(path) {
+            Ok(file) => Some(FileExporter {
+                file,
+                service: String::new(),
+            }),
+            Err(e) => {
+                log_warn!("failed to open {path:?} for spans: {e}");
+                None
+            }
+        }
+    }
+}
+
+impl SpanExporter for FileExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        let pid = std::process::id();
+        let mut out = String::new();
+        for span in &batch {
+            line(&mut out, span, &self.service, pid);
+        }
+        // one write for the batch, appended whole
+        (&self.file)
+            .write_all(out.as_bytes())
+            .map_err(|e| OTelSdkError::InternalFailure(e.to_string()))
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.service = resource
+            .get(&Key::from_static_str("service.name"))
+            .map_or_else(String::new, |v| v.as_str().into_owned());
+    }
+}
+
+/// Writes `span` to `out` as a line of JSON.
+fn line(out: &mut String, span: &SpanData, service: &str, pid: u32) {
+    let sc = &span.span_context;
+    let parent = if span.parent_span_id == SpanId::INVALID {
+        String::new()
+    } else {
+        span.parent_span_id.to_string()
+    };
+    let kind = match span.span_kind {
+        SpanKind::Client => "client",
+        SpanKind::Server => "server",
+        SpanKind::Producer => "producer",
+        SpanKind::Consumer => "consumer",
+        SpanKind::Internal => "internal",
+    };
+    let status = match span.status {
+        Status::Unset => "unset",
+        Status::Ok => "ok",
+        Status::Error { .. } => "error",
+    };
+    let _ = write!(
+        out,
+        "{{\"trace_id\":\"{}\",\"span_id\":\"{}\",\"parent_span_id\":\"{parent}\",\"name\":{},\"kind\":\"{kind}\",\"start_unix_nano\":{},\"end_unix_nano\":{},\"status\":\"{status}\",\"service\":{},\"pid\":{pid},\"attributes\":",
+        sc.trace_id(),
+        sc.span_id(),
+        string(&span.name),
+        unix_nanos(span.start_time),
+        unix_nanos(span.end_time),
+        string(service),
+    );
+    attributes(out, &span.attributes);
+    out.push_str(",\"events\":[");
+    for (i, event) in span.events.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(
+            out,
+            "{{\"name\":{},\"time_unix_nano\":{},\"attributes\":",
+            string(&event.name),
+            unix_nanos(event.timestamp),
+        );
+        attributes(out, &event.attributes);
+        out.push('}');
+    }
+    out.push_str("]}\n");
+}
+
+/// Writes `attributes` to `out` as a JSON object.
+fn attributes(out: &mut String, attributes: &[KeyValue]) {
+    out.push('{');
+    for (i, kv) in attributes.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "{}:{}", string(kv.key.as_str()), value(&kv.value));
+    }
+    out.push('}');
+}
+
+/// `v` as a JSON value; arrays go as the string the SDK formats them to.
+fn value(v: &Value) -> String {
+    match v {
+        Value::Bool(b) => b.to_string(),
+        Value::I64(n) => n.to_string(),
+        Value::F64(f) if f.is_finite() => f.to_string(),
+        v => string(&v.as_str()),
+    }
+}
+
+fn unix_nanos(t: SystemTime) -> u128 {
+    t.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{Event, SpanContext, TraceFlags, TraceId, TraceState};
+    use opentelemetry_sdk::trace::{SpanEvents, SpanLinks};
+    use std::borrow::Cow;
+    use std::time::Duration;
+
+    #[test]
+    fn spans_are_written_as_json_lines() {
+        let span = SpanData {
+            span_context: SpanContext::new(
+                TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+                SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+                TraceFlags::SAMPLED,
+                false,
+                TraceState::default(),
+            ),
+            parent_span_id: SpanId::INVALID,
+            #[cfg(feature = "otel-0_31")]
+            parent_span_is_remote: false,
+            span_kind: SpanKind::Internal,
+            name: Cow::Borrowed("say \"hi\""),
+            start_time: UNIX_EPOCH + Duration::from_nanos(1_000),
+            end_time: UNIX_EPOCH + Duration::from_nanos(3_500),
+            attributes: vec![
+                KeyValue::new("thread.id", 42),
+                KeyValue::new("code.function", "worker"),
+                KeyValue::new("ok", true),
+            ],
+            dropped_attributes_count: 0,
+            events: {
+                let mut events = SpanEvents::default();
+                events.events.push(Event::new(
+                    "nanosleep",
+                    UNIX_EPOCH + Duration::from_nanos(2_000),
+                    vec![KeyValue::new("otel_posix.blocking.wait_time", 0.5)],
+                    0,
+                ));
+                events
+            },
+            links: SpanLinks::default(),
+            status: Status::Unset,
+            instrumentation_scope: Default::default(),
+        };
+        let mut out = String::new();
+        line(&mut out, &span, "app", 7);
+        assert_eq!(
+            out,
+            "{\"trace_id\":\"4bf92f3577b34da6a3ce929d0e0e4736\",\"span_id\":\"00f067aa0ba902b7\",\
+             \"parent_span_id\":\"\",\"name\":\"say \\\"hi\\\"\",\"kind\":\"internal\",\
+             \"start_unix_nano\":1000,\"end_unix_nano\":3500,\"status\":\"unset\",\
+             \"service\":\"app\",\"pid\":7,\"attributes\":{\"thread.id\":42,\
+             \"code.function\":\"worker\",\"ok\":true},\"events\":[{\"name\":\"nanosleep\",\
+             \"time_unix_nano\":2000,\"attributes\":{\"otel_posix.blocking.wait_time\":0.5}}]}\n"
+        );
+    }
+}
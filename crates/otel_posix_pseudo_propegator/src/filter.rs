@@ -0,0 +1,272 @@
+// src/filter.rs
+//
+// Span-name filtering, so only threads created under interesting spans get
+// wrapped. The OTEL API can't tell us the name of the current span, so a span
+// processor remembers which live spans matched the configured patterns (or
+// descend from one that did), and the interposer only has to check whether
+// the current span id is in that set.
+//
+// A pattern is a glob, or, with a `re:` prefix, a regular expression that
+// has to match the whole name. Patterns are separated by commas, so neither
+// kind can contain one.
+
+#[cfg(feature = "sdk")]
+use crate::log::log_warn;
+use opentelemetry::trace::SpanId;
+use std::collections::HashSet;
+use std::sync::{LazyLock, Mutex, MutexGuard, PoisonError};
+
+// live spans whose name (or an ancestor's) matched the filter
+static MATCHING: LazyLock<Mutex<HashSet<SpanId>>> = LazyLock::new(Default::default);
+
+/// A shell-style pattern: `*` matches any run of characters, `?` exactly one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Glob(String);
+
+impl Glob {
+    pub fn new(pattern: &str) -> Self {
+        Glob(pattern.to_string())
+    }
+
+    pub fn matches(&self, s: &str) -> bool {
+        let (p, s): (Vec<char>, Vec<char>) = (self.0.chars().collect(), s.chars().collect());
+        let (mut pi, mut si) = (0, 0);
+        // last `*` seen in the pattern, and where in `s` it started matching
+        let mut star: Option<(usize, usize)> = None;
+        while si < s.len() {
+            match p.get(pi) {
+                Some('*') => {
+                    star = Some((pi, si));
+                    pi += 1;
+                }
+                Some(&c) if c == '?' || c == s[si] => {
+                    pi += 1;
+                    si += 1;
+                }
+                // mismatch: let the last `*` swallow one more character
+                _ => match star {
+                    Some((star_pi, star_si)) => {
+                        pi = star_pi + 1;
+                        si = star_si + 1;
+                        star = Some((star_pi, star_si + 1));
+                    }
+                    None => return false,
+                },
+            }
+        }
+        p[pi..].iter().all(|&c| c == '*')
+    }
+}
+
+/// Parses a comma-separated list of globs, skipping empty entries.
+pub(crate) fn parse_globs(s: &str) -> Vec<Glob> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(Glob::new)
+        .collect()
+}
+
+/// One of the span-name patterns: a glob, or a regular expression anchored at both ends.
+#[derive(Debug, Clone)]
+enum NamePattern {
+    Glob(Glob),
+    // only the sdk span processor evaluates names, so only it needs the regex engine
+    #[cfg(feature = "sdk")]
+    Regex(regex::Regex),
+}
+
+impl NamePattern {
+    /// The pattern `s`, or `None` if it is a regular expression that doesn't compile.
+    fn parse(s: &str) -> Option<Self> {
+        let Some(re) = s.strip_prefix("re:") else {
+            return Some(NamePattern::Glob(Glob::new(s)));
+        };
+        #[cfg(feature = "sdk")]
+        return match regex::Regex::new(&format!("^(?:{re})$")) {
+            Ok(re) => Some(NamePattern::Regex(re)),
+            Err(e) => {
+                log_warn!("invalid OTEL_POSIX_PROP_SPAN_FILTER regex {re:?}, skipped: {e}");
+                None
+            }
+        };
+        // nothing matches names without the sdk; the filter only has to be there
+        #[cfg(not(feature = "sdk"))]
+        Some(NamePattern::Glob(Glob::new(re)))
+    }
+
+    #[cfg_attr(not(feature = "sdk"), allow(dead_code))]
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Glob(glob) => glob.matches(name),
+            #[cfg(feature = "sdk")]
+            NamePattern::Regex(re) => re.is_match(name),
+        }
+    }
+
+    /// What the pattern was written as, less any `re:`.
+    fn as_str(&self) -> &str {
+        match self {
+            NamePattern::Glob(glob) => &glob.0,
+            #[cfg(feature = "sdk")]
+            NamePattern::Regex(re) => re.as_str(),
+        }
+    }
+}
+
+impl PartialEq for NamePattern {
+    fn eq(&self, other: &Self) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+            && self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for NamePattern {}
+
+/// The set of span-name patterns from `OTEL_POSIX_PROP_SPAN_FILTER`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SpanFilter {
+    patterns: Vec<NamePattern>,
+}
+
+impl SpanFilter {
+    /// Parses a comma-separated list of globs and `re:` regular expressions; `None` if it
+    /// contains no patterns that can be used.
+    pub fn parse(s: &str) -> Option<Self> {
+        let patterns: Vec<_> = s
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .filter_map(NamePattern::parse)
+            .collect();
+        (!patterns.is_empty()).then_some(SpanFilter { patterns })
+    }
+
+    // only the sdk span processor evaluates names
+    #[cfg_attr(not(feature = "sdk"), allow(dead_code))]
+    pub fn matches(&self, name: &str) -> bool {
+        self.patterns.iter().any(|p| p.matches(name))
+    }
+}
+
+/// Whether the span `span_id` (still live) matched the filter.
+pub(crate) fn is_matching(span_id: SpanId) -> bool {
+    lock_matching().contains(&span_id)
+}
+
+pub(crate) fn lock_matching() -> MutexGuard<'static, HashSet<SpanId>> {
+    // the set stays consistent even if a holder panicked
+    MATCHING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(feature = "sdk")]
+pub use processor::SpanNameFilterProcessor;
+
+#[cfg(feature = "sdk")]
+mod processor {
+    use super::{is_matching, lock_matching};
+    use crate::config::config;
+    use opentelemetry::Context;
+    use opentelemetry::trace::TraceContextExt;
+    use opentelemetry_sdk::error::OTelSdkResult;
+    use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+    use std::time::Duration;
+
+    /// Span processor that feeds `OTEL_POSIX_PROP_SPAN_FILTER` and the span names of the
+    /// signal-triggered thread dump.
+    ///
+    /// The shim adds it to the provider it installs itself; hosts that bring their own
+    /// `SdkTracerProvider` need to add it for span-name filtering to see their spans.
+    #[derive(Debug, Default)]
+    pub struct SpanNameFilterProcessor;
+
+    impl SpanProcessor for SpanNameFilterProcessor {
+        fn on_start(&self, span: &mut Span, cx: &Context) {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            if crate::dump::enabled()
+                && let Some(data) = span.exported_data()
+            {
+                crate::dump::span_started(data.span_context.span_id(), &data.name);
+            }
+            let Some(filter) = &config().span_filter else {
+                return;
+            };
+            // non-recording spans have no data and are never matched
+            let Some(data) = span.exported_data() else {
+                return;
+            };
+            let parent_matches =
+                cx.has_active_span() && is_matching(cx.span().span_context().span_id());
+            if parent_matches || filter.matches(&data.name) {
+                lock_matching().insert(data.span_context.span_id());
+            }
+        }
+
+        fn on_end(&self, span: SpanData) {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            if crate::dump::enabled() {
+                crate::dump::span_ended(span.span_context.span_id());
+            }
+            if config().span_filter.is_some() {
+                lock_matching().remove(&span.span_context.span_id());
+            }
+        }
+
+        fn force_flush(&self) -> OTelSdkResult {
+            Ok(())
+        }
+
+        fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matching() {
+        let g = Glob::new;
+        assert!(g("http.*").matches("http.request"));
+        assert!(g("http.*").matches("http."));
+        assert!(!g("http.*").matches("grpc.request"));
+        assert!(g("*query*").matches("db.query.select"));
+        assert!(g("job-?").matches("job-7"));
+        assert!(!g("job-?").matches("job-77"));
+        assert!(g("a*b*c").matches("aXXbYYbc"));
+        assert!(!g("a*b*c").matches("aXXbYYb"));
+        assert!(g("exact").matches("exact"));
+        assert!(!g("exact").matches("exactly"));
+        assert!(g("*").matches(""));
+    }
+
+    #[test]
+    fn filter_parse() {
+        assert_eq!(SpanFilter::parse(""), None);
+        assert_eq!(SpanFilter::parse(" , "), None);
+        let f = SpanFilter::parse("http.*, db.query ").unwrap();
+        assert!(f.matches("http.get"));
+        assert!(f.matches("db.query"));
+        assert!(!f.matches("db.query.slow"));
+    }
+
+    #[cfg(feature = "sdk")]
+    #[test]
+    fn regex_patterns() {
+        let f = SpanFilter::parse("re:(GET|POST) /api/.+, batch.*").unwrap();
+        assert!(f.matches("GET /api/users"));
+        assert!(f.matches("batch.nightly"));
+        // the whole name
+        assert!(!f.matches("GET /api/"));
+        assert!(!f.matches("xGET /api/users"));
+        // one that doesn't compile is left out
+        assert_eq!(SpanFilter::parse("re:(unclosed"), None);
+        assert_eq!(
+            SpanFilter::parse("re:[, job"),
+            SpanFilter::parse("job"),
+            "skipped, the rest kept"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+// src/fork.rs
+//
+// Fork safety. Only the forking thread survives into the child, so a lock
+// some other thread held at that moment stays held forever and the next
+// wrapped pthread_create (or log line) in the child deadlocks. The prepare
+// handler finishes any lazy initialization and takes every lock the shim
+// uses, so the fork happens at a point where none of them is busy; both
+// parent and child release them again afterwards. The child then drops its
+// copies of the parent's child and popen spans (children.rs, popen.rs) and
+// checks whether it is a daemon (daemon.rs).
+//
+// The handlers are installed when the library is loaded, so even a fork
+// that comes before any wrapped pthread_create is covered.
+
+#[cfg(feature = "hook-join")]
+use crate::join;
+use crate::{filter, poll, registry};
+use opentelemetry::trace::{SpanContext, SpanId};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io::StderrLock;
+use std::sync::Arc;
+use std::sync::{MutexGuard, Once};
+
+static REGISTER: Once = Once::new();
+
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+#[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__mod_init_func"))]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(register);
+}
+
+// taken in prepare, in the same order as everywhere else (a configuration being built,
+// stderr, the filter set, the tid registry, the dump's span names, the joinable
+// threads, the poll totals, the unreaped children, the unclosed popen streams, then the
+// installed providers)
+struct Held {
+    _config: MutexGuard<'static, ()>,
+    _stderr: StderrLock<'static>,
+    _matching: MutexGuard<'static, HashSet<SpanId>>,
+    _tids: MutexGuard<'static, HashMap<libc::pid_t, SpanContext>>,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    _names: MutexGuard<'static, HashMap<SpanId, String>>,
+    #[cfg(feature = "hook-join")]
+    _threads: MutexGuard<'static, HashMap<libc::pthread_t, Arc<join::Thread>>>,
+    _loops: MutexGuard<'static, HashMap<String, Arc<poll::Totals>>>,
+    #[cfg(feature = "hook-wait")]
+    _children: MutexGuard<'static, HashMap<libc::pid_t, opentelemetry::Context>>,
+    #[cfg(feature = "hook-popen")]
+    _streams: MutexGuard<'static, HashMap<usize, opentelemetry::Context>>,
+    #[cfg(feature = "otlp")]
+    _providers: MutexGuard<'static, ()>,
+}
+
+thread_local! {
+    // fork handlers all run on the forking thread, so the guards can live here
+    static HELD: RefCell<Option<Held>> = const { RefCell::new(None) };
+}
+
+/// Installs the atfork handlers, once per process.
+pub(crate) fn register() {
+    REGISTER.call_once(|| unsafe {
+        libc::pthread_atfork(Some(prepare), Some(release), Some(child));
+    });
+}
+
+extern "C" fn prepare() {
+    let _ = std::panic::catch_unwind(|| {
+        // a OnceLock still being initialized by another thread would never finish in the child
+        crate::config::config();
+        #[cfg(feature = "preload")]
+        crate::real_pthread_create();
+
+        let held = Held {
+            _config: crate::config::lock_building(),
+            _stderr: std::io::stderr().lock(),
+            _matching: filter::lock_matching(),
+            _tids: registry::lock_threads(),
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            _names: crate::dump::lock_names(),
+            #[cfg(feature = "hook-join")]
+            _threads: join::lock_threads(),
+            _loops: poll::lock_loops(),
+            #[cfg(feature = "hook-wait")]
+            _children: crate::children::lock_children(),
+            #[cfg(feature = "hook-popen")]
+            _streams: crate::popen::lock_streams(),
+            #[cfg(feature = "otlp")]
+            _providers: crate::init::lock_swapping(),
+        };
+        HELD.with(|h| *h.borrow_mut() = Some(held));
+    });
+}
+
+extern "C" fn release() {
+    let _ = std::panic::catch_unwind(|| HELD.with(|h| h.borrow_mut().take()));
+}
+
+extern "C" fn child() {
+    release();
+    #[cfg(feature = "hook-wait")]
+    crate::children::forked();
+    #[cfg(feature = "hook-popen")]
+    crate::popen::forked();
+    #[cfg(feature = "hook-daemon")]
+    crate::daemon::forked();
+}
@@ -0,0 +1,73 @@
+// src/glib.rs
+//
+// GLib threads. g_thread_new starts every thread in GLib's own proxy
+// routine, so the pthread_create underneath only ever sees that proxy:
+// entry-point filters and auto-root span names can't tell GLib's threads
+// apart, and where libglib binds pthread_create to itself we never see the
+// call at all. g_thread_new and g_thread_try_new are interposed too, and
+// wrap the caller's function before GLib gets to it; the pthread_create
+// they make is then a nested call and goes straight through.
+//
+// Joined with g_thread_join, which is GLib's own business, so join events
+// aren't tracked for these threads.
+
+use crate::{StartRoutine, reentry};
+use std::ffi::{c_char, c_void};
+
+// opaque GThread* and GError** as far as we're concerned
+type GThreadNewFn = unsafe extern "C" fn(*const c_char, StartRoutine, *mut c_void) -> *mut c_void;
+type GThreadTryNewFn =
+    unsafe extern "C" fn(*const c_char, StartRoutine, *mut c_void, *mut *mut c_void) -> *mut c_void;
+
+/// Runs `new` with the routine and argument to start the thread with, wrapped if the
+/// current context calls for it, and returns the `GThread` it made.
+unsafe fn create(
+    func: StartRoutine,
+    data: *mut c_void,
+    new: impl FnOnce(StartRoutine, *mut c_void) -> *mut c_void,
+) -> *mut c_void {
+    // the pthread_create GLib makes comes back to us as a nested call, which can only be
+    // forwarded once the real one is known
+    let _ = crate::real_pthread_create();
+    let mut thread = std::ptr::null_mut();
+    unsafe {
+        crate::create_wrapped(
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            func,
+            data,
+            |f, d| {
+                thread = new(f, d);
+                if thread.is_null() { libc::EAGAIN } else { 0 }
+            },
+        );
+    }
+    thread
+}
+
+/// `g_thread_new`, carrying the caller's OTEL `Context` into the new thread.
+pub(crate) unsafe fn g_thread_new(
+    real: GThreadNewFn,
+    name: *const c_char,
+    func: StartRoutine,
+    data: *mut c_void,
+) -> *mut c_void {
+    let Some(_guard) = reentry::enter() else {
+        return unsafe { real(name, func, data) };
+    };
+    unsafe { create(func, data, |f, d| real(name, f, d)) }
+}
+
+/// `g_thread_try_new`, carrying the caller's OTEL `Context` into the new thread.
+pub(crate) unsafe fn g_thread_try_new(
+    real: GThreadTryNewFn,
+    name: *const c_char,
+    func: StartRoutine,
+    data: *mut c_void,
+    error: *mut *mut c_void,
+) -> *mut c_void {
+    let Some(_guard) = reentry::enter() else {
+        return unsafe { real(name, func, data, error) };
+    };
+    unsafe { create(func, data, |f, d| real(name, f, d, error)) }
+}
@@ -0,0 +1,199 @@
+// src/hooks.rs
+//
+// Embedder hooks run around thread creation, so other ambient state (a
+// logging MDC, request-scoped thread-locals, ...) can ride along in the same
+// trampoline as the OTEL context. A hook captures in the creating thread and
+// restores in the new one before its start routine runs.
+//
+// Hooks are registered rarely and never removed, so the registered set is an
+// immutable, leaked snapshot behind an atomic pointer: pthread_create reads
+// it without taking a lock.
+
+use libc::c_int;
+use std::any::Any;
+use std::ffi::c_void;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// Restores captured state in the new thread. Whatever it returns is dropped once the
+/// thread's start routine has returned, so it can be a guard that undoes the restore.
+pub type RestoreFn = Box<dyn FnOnce() -> Box<dyn Any> + Send>;
+
+type CaptureHook = Box<dyn Fn() -> Option<RestoreFn> + Send + Sync>;
+
+/// C capture callback: runs in the creating thread and returns the state to carry over,
+/// or NULL for none.
+pub type OtelPosixCaptureFn = Option<unsafe extern "C" fn(user: *mut c_void) -> *mut c_void>;
+
+/// C restore/release callback, called with the captured state.
+pub type OtelPosixStateFn = Option<unsafe extern "C" fn(state: *mut c_void, user: *mut c_void)>;
+
+struct CHook {
+    capture: unsafe extern "C" fn(*mut c_void) -> *mut c_void,
+    restore: OtelPosixStateFn,
+    release: OtelPosixStateFn,
+    user: *mut c_void,
+}
+
+// `user` is only ever handed back to the embedder's callbacks, which promised to
+// accept it from any thread
+unsafe impl Send for CHook {}
+unsafe impl Sync for CHook {}
+
+enum Hook {
+    Rust(CaptureHook),
+    C(CHook),
+}
+
+static HOOKS: AtomicPtr<Vec<&'static Hook>> = AtomicPtr::new(std::ptr::null_mut());
+// serializes registrations; never taken by pthread_create
+static REGISTER: Mutex<()> = Mutex::new(());
+
+fn register(hook: Hook) {
+    let _lock = REGISTER.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut hooks = registered().to_vec();
+    hooks.push(Box::leak(Box::new(hook)));
+    // the previous snapshot may still be read by a concurrent pthread_create, so it is
+    // leaked rather than freed
+    HOOKS.store(Box::leak(Box::new(hooks)), Ordering::Release);
+}
+
+fn registered() -> &'static [&'static Hook] {
+    let hooks = HOOKS.load(Ordering::Acquire);
+    if hooks.is_null() {
+        &[]
+    } else {
+        unsafe { &*hooks }
+    }
+}
+
+/// Whether any hook is registered, so threads get trampolined even without a span.
+pub(crate) fn any() -> bool {
+    !HOOKS.load(Ordering::Relaxed).is_null()
+}
+
+/// Registers a hook run for every thread the shim starts.
+///
+/// `capture` runs in the creating thread, inside `pthread_create`; the [`RestoreFn`] it
+/// returns runs in the new thread, after the OTEL context is attached and before the
+/// start routine. Hooks can't be unregistered, and panics in them are swallowed.
+///
+/// ```no_run
+/// use std::cell::Cell;
+///
+/// thread_local! {
+///     static REQUEST_ID: Cell<u64> = const { Cell::new(0) };
+/// }
+///
+/// otel_posix_pseudo_propegator::register_thread_hook(|| {
+///     let id = REQUEST_ID.get();
+///     Some(Box::new(move || {
+///         REQUEST_ID.set(id);
+///         Box::new(())
+///     }))
+/// });
+/// ```
+pub fn register_thread_hook(capture: impl Fn() -> Option<RestoreFn> + Send + Sync + 'static) {
+    register(Hook::Rust(Box::new(capture)));
+}
+
+/// Registers C callbacks run for every thread the shim starts.
+///
+/// `capture(user)` runs in the creating thread and returns the state to carry over
+/// (NULL for none). In the new thread, `restore(state, user)` runs before the start
+/// routine and `release(state, user)` after it returns; if the thread couldn't be
+/// created, only `release` runs, in the creating thread. `restore` and `release` may
+/// be NULL. Returns 0, or -1 if `capture` is NULL.
+///
+/// # Safety
+///
+/// The callbacks must be safe to call from any thread with `user` for the rest of the
+/// process lifetime, and must not unwind.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn otel_posix_register_hook(
+    capture: OtelPosixCaptureFn,
+    restore: OtelPosixStateFn,
+    release: OtelPosixStateFn,
+    user: *mut c_void,
+) -> c_int {
+    let Some(capture) = capture else {
+        return -1;
+    };
+    register(Hook::C(CHook {
+        capture,
+        restore,
+        release,
+        user,
+    }));
+    0
+}
+
+/// State captured by one C hook; released when dropped.
+pub(crate) struct CState {
+    hook: &'static CHook,
+    state: *mut c_void,
+}
+
+impl Drop for CState {
+    fn drop(&mut self) {
+        if let Some(release) = self.hook.release {
+            unsafe { release(self.state, self.hook.user) };
+        }
+    }
+}
+
+/// What one hook captured in the creating thread.
+pub(crate) enum Captured {
+    Rust(RestoreFn),
+    C(CState),
+}
+
+/// What one hook restored in the new thread, undone when dropped.
+pub(crate) enum Restored {
+    Rust(#[allow(dead_code)] Box<dyn Any>),
+    C(#[allow(dead_code)] CState),
+}
+
+/// Runs every registered capture hook in the creating thread.
+pub(crate) fn capture() -> Vec<Captured> {
+    registered()
+        .iter()
+        .filter_map(|hook| match hook {
+            Hook::Rust(capture) => catch_unwind(AssertUnwindSafe(capture))
+                .ok()
+                .flatten()
+                .map(Captured::Rust),
+            Hook::C(hook) => {
+                let state = unsafe { (hook.capture)(hook.user) };
+                (!state.is_null()).then_some(Captured::C(CState { hook, state }))
+            }
+        })
+        .collect()
+}
+
+/// Restores everything `captured` in the new thread. The result is dropped (releasing
+/// in reverse order) after the start routine returns.
+pub(crate) fn restore(captured: Vec<Captured>) -> Vec<Restored> {
+    captured
+        .into_iter()
+        .filter_map(|c| match c {
+            Captured::Rust(restore) => catch_unwind(AssertUnwindSafe(restore))
+                .ok()
+                .map(Restored::Rust),
+            Captured::C(cstate) => {
+                if let Some(restore) = cstate.hook.restore {
+                    unsafe { restore(cstate.state, cstate.hook.user) };
+                }
+                Some(Restored::C(cstate))
+            }
+        })
+        .collect()
+}
+
+/// Drops restored state in reverse order of restoring.
+pub(crate) fn release(mut restored: Vec<Restored>) {
+    while let Some(r) = restored.pop() {
+        drop(r);
+    }
+}
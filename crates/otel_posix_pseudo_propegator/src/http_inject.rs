@@ -0,0 +1,247 @@
+// src/http_inject.rs
+//
+// Experimental: traceparent injection on outbound plaintext HTTP/1.x.
+// `send` and `write` are interposed; when a buffer holding a complete
+// request head is written to a socket while a span is active, a
+// `traceparent` header is added after the request line, so legacy C
+// services propagate across the wire without code changes.
+//
+// Only heads written in a single call are recognized (writev/sendmsg and
+// TLS are out of reach), and a head that already carries traceparent is
+// left alone. The byte count returned to the caller is always in terms of
+// the caller's own buffer.
+
+use crate::config::config;
+use crate::reentry;
+use crate::w3c::format_traceparent;
+use libc::{c_int, c_void, size_t, ssize_t};
+use opentelemetry::Context;
+use opentelemetry::trace::TraceContextExt;
+use std::time::{Duration, Instant};
+
+type SendFn = unsafe extern "C" fn(c_int, *const c_void, size_t, c_int) -> ssize_t;
+type WriteFn = unsafe extern "C" fn(c_int, *const c_void, size_t) -> ssize_t;
+
+const METHODS: &[&[u8]] = &[
+    b"GET ",
+    b"POST ",
+    b"PUT ",
+    b"DELETE ",
+    b"HEAD ",
+    b"OPTIONS ",
+    b"PATCH ",
+    b"CONNECT ",
+    b"TRACE ",
+];
+
+// how long a non-blocking socket that took part of the header gets to take the rest
+const FINISH_WITHIN: Duration = Duration::from_secs(5);
+
+// without libc's versions while this thread is inside a symbol lookup, e.g. an
+// allocator logging from under dlsym, the raw syscalls stand in
+pub(crate) unsafe extern "C" fn sys_send(
+    fd: c_int,
+    buf: *const c_void,
+    len: size_t,
+    flags: c_int,
+) -> ssize_t {
+    let no_addr = std::ptr::null::<libc::sockaddr>();
+    unsafe { libc::syscall(libc::SYS_sendto, fd, buf, len, flags, no_addr, 0) as ssize_t }
+}
+
+pub(crate) unsafe extern "C" fn sys_write(fd: c_int, buf: *const c_void, count: size_t) -> ssize_t {
+    unsafe { libc::syscall(libc::SYS_write, fd, buf, count) as ssize_t }
+}
+
+/// Where the header goes if `buf` is a complete HTTP/1.x request head without a
+/// traceparent: just past the request line.
+fn header_offset(buf: &[u8]) -> Option<usize> {
+    // cheap checks first, this runs on every write
+    if !METHODS.iter().any(|m| buf.starts_with(m)) {
+        return None;
+    }
+    let line_end = buf.windows(2).position(|w| w == b"\r\n")?;
+    let request_line = &buf[..line_end];
+    if !(request_line.ends_with(b" HTTP/1.1") || request_line.ends_with(b" HTTP/1.0")) {
+        return None;
+    }
+    let head_end = buf.windows(4).position(|w| w == b"\r\n\r\n")?;
+    let has_traceparent = buf[line_end + 2..head_end + 2]
+        .split(|&b| b == b'\n')
+        .any(|line| line.len() >= 12 && line[..12].eq_ignore_ascii_case(b"traceparent:"));
+    (!has_traceparent).then_some(line_end + 2)
+}
+
+/// The header to inject for the current context, if there's a span worth propagating.
+fn traceparent_header() -> Option<Vec<u8>> {
+    if !config().hooks.http {
+        return None;
+    }
+    Context::map_current(|cx| {
+        let sc = cx.span().span_context().clone();
+        (sc.is_valid() && !cx.is_telemetry_suppressed())
+            .then(|| format!("traceparent: {}\r\n", format_traceparent(&sc)).into_bytes())
+    })
+}
+
+fn is_socket(fd: c_int) -> bool {
+    let mut st: libc::stat = unsafe { std::mem::zeroed() };
+    unsafe { libc::fstat(fd, &mut st) == 0 && (st.st_mode & libc::S_IFMT) == libc::S_IFSOCK }
+}
+
+/// Writes `buf` with `header` inserted at `at` through `raw`, and maps the result back to
+/// bytes of `buf` consumed. A write that stops inside the header is finished within
+/// `finish_within`, or given up on.
+fn write_injected(
+    fd: c_int,
+    buf: &[u8],
+    at: usize,
+    header: &[u8],
+    finish_within: Duration,
+    mut raw: impl FnMut(&[u8]) -> ssize_t,
+) -> ssize_t {
+    let mut out = Vec::with_capacity(buf.len() + header.len());
+    out.extend_from_slice(&buf[..at]);
+    out.extend_from_slice(header);
+    out.extend_from_slice(&buf[at..]);
+
+    let n = raw(&out);
+    if n < 0 || (n as usize) <= at {
+        return n;
+    }
+    let n = n as usize;
+    if n >= at + header.len() {
+        return (n - header.len()) as ssize_t;
+    }
+    // stopped inside our header: finish it, or the stream is corrupt
+    let deadline = Instant::now() + finish_within;
+    let mut sent = n;
+    while sent < at + header.len() {
+        let n = raw(&out[sent..at + header.len()]);
+        if n > 0 {
+            sent += n as usize;
+            continue;
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        match std::io::Error::last_os_error().kind() {
+            std::io::ErrorKind::WouldBlock if n < 0 && !left.is_zero() => {
+                // non-blocking socket: wait until it can take the rest
+                let mut pfd = libc::pollfd {
+                    fd,
+                    events: libc::POLLOUT,
+                    revents: 0,
+                };
+                let timeout = left.as_millis().clamp(1, 1000) as c_int;
+                unsafe { libc::poll(&mut pfd, 1, timeout) };
+            }
+            std::io::ErrorKind::Interrupted if n < 0 && !left.is_zero() => {}
+            // the connection is broken, or has stalled past the deadline, either way; report
+            // what the caller got out
+            _ => break,
+        }
+    }
+    at as ssize_t
+}
+
+/// Decides whether to inject into this write and does it; `None` means pass through.
+fn maybe_inject(
+    fd: c_int,
+    buf: *const c_void,
+    len: size_t,
+    raw: impl FnMut(&[u8]) -> ssize_t,
+) -> Option<ssize_t> {
+    if buf.is_null() || len < 16 {
+        return None;
+    }
+    let bytes = unsafe { std::slice::from_raw_parts(buf as *const u8, len) };
+    let at = header_offset(bytes)?;
+    let header = traceparent_header()?;
+    if !is_socket(fd) {
+        return None;
+    }
+    Some(write_injected(fd, bytes, at, &header, FINISH_WITHIN, raw))
+}
+
+/// `send`, with `traceparent` injected into outbound HTTP/1.x request heads.
+pub(crate) unsafe fn send(
+    real: SendFn,
+    fd: c_int,
+    buf: *const c_void,
+    len: size_t,
+    flags: c_int,
+) -> ssize_t {
+    if let Some(_guard) = reentry::enter() {
+        let raw = |b: &[u8]| unsafe { real(fd, b.as_ptr() as *const c_void, b.len(), flags) };
+        if let Some(n) = maybe_inject(fd, buf, len, raw) {
+            return n;
+        }
+    }
+    unsafe { real(fd, buf, len, flags) }
+}
+
+/// `write`, with `traceparent` injected into HTTP/1.x request heads written to sockets.
+pub(crate) unsafe fn write(real: WriteFn, fd: c_int, buf: *const c_void, count: size_t) -> ssize_t {
+    if let Some(_guard) = reentry::enter() {
+        let raw = |b: &[u8]| unsafe { real(fd, b.as_ptr() as *const c_void, b.len()) };
+        if let Some(n) = maybe_inject(fd, buf, count, raw) {
+            return n;
+        }
+    }
+    unsafe { real(fd, buf, count) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEAD: &[u8] = b"GET /x HTTP/1.1\r\nHost: a\r\n\r\nbody";
+    const TP: &[u8] = b"traceparent: 00-x\r\n";
+
+    #[test]
+    fn finds_request_heads() {
+        assert_eq!(header_offset(HEAD), Some(17));
+        assert_eq!(header_offset(b"GET /x HTTP/1.1\r\nHost: a\r\n"), None);
+        assert_eq!(header_offset(b"HTTP/1.1 200 OK\r\n\r\n"), None);
+        assert_eq!(header_offset(b"GET /x HTTP/2\r\n\r\n"), None);
+        assert_eq!(
+            header_offset(b"GET / HTTP/1.1\r\nTraceParent: 00-x\r\n\r\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn returns_counts_in_caller_bytes() {
+        let mut wire = Vec::new();
+        let n = write_injected(-1, HEAD, 17, TP, FINISH_WITHIN, |b| {
+            wire.extend_from_slice(b);
+            b.len() as ssize_t
+        });
+        assert_eq!(n as usize, HEAD.len());
+        assert_eq!(
+            wire,
+            b"GET /x HTTP/1.1\r\ntraceparent: 00-x\r\nHost: a\r\n\r\nbody"
+        );
+
+        // a short write that ends inside the header still delivers all of it
+        let mut wire = Vec::new();
+        let n = write_injected(-1, HEAD, 17, TP, FINISH_WITHIN, |b| {
+            let take = b.len().min(20);
+            wire.extend_from_slice(&b[..take]);
+            take as ssize_t
+        });
+        assert_eq!(n, 17);
+        assert_eq!(wire, b"GET /x HTTP/1.1\r\ntraceparent: 00-x\r\n");
+
+        // a socket that never takes the rest is given up on at the deadline
+        let mut first = true;
+        let n = write_injected(-1, HEAD, 17, TP, Duration::from_millis(50), |b| {
+            if std::mem::take(&mut first) {
+                return 20;
+            }
+            unsafe { *libc::__errno_location() = libc::EAGAIN };
+            assert!(b.len() < TP.len());
+            -1
+        });
+        assert_eq!(n, 17);
+    }
+}
@@ -0,0 +1,647 @@
+// src/init.rs
+//
+// Load-time constructor. The shim only propagates context, so an
+// uninstrumented host has no tracer provider and nothing is ever exported.
+// When OTEL_EXPORTER_OTLP_ENDPOINT is set we install an OTLP provider
+// ourselves, exporting from a thread of its own (export.rs), plus a meter
+// provider for the shim's own metrics, describing the process with the
+// resource from resource.rs, and flush them again at exit. Asked for with
+// OTEL_LOGS_EXPORTER=otlp, a logger provider goes with them, for the records
+// other shims emit (logs.rs). A daemon gets a tracer provider of its own (see
+// daemon.rs). A flush at exit waits at most
+// OTEL_POSIX_PROP_EXIT_TIMEOUT, so a collector that doesn't answer can't
+// hold the process up; exit.rs flushes on the exits that skip atexit.
+//
+// Building the exporter spawns and waits on helper threads, which would
+// deadlock against the loader lock held while constructors run, so the
+// install itself happens on a short-lived thread of its own. The threads
+// created until it's done are reported once it is (early.rs), and
+// otel_posix_wait_installed() lets a program that records spans of its own
+// from the start wait for it.
+//
+// The embedding application, or a plugin loaded later, can replace the
+// tracer provider with otel_posix_install_provider() (exporting somewhere
+// else, or once it has set the OTEL_EXPORTER_OTLP_* variables itself) and
+// take everything down with otel_posix_shutdown(). The provider replaced is
+// shut down, which exports what it still holds, within the exit timeout.
+
+use crate::export::RingSpanProcessor;
+use crate::file_export::{self, FileExporter};
+use crate::filter::SpanNameFilterProcessor;
+use crate::log::log_warn;
+use crate::resource;
+use libc::{c_char, c_int};
+use opentelemetry::global;
+use opentelemetry::trace::noop::NoopTracerProvider;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::logs::SdkLoggerProvider;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::trace::{SdkTracerProvider, SpanExporter};
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicPtr, AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard, Once, PoisonError};
+use std::time::Duration;
+
+// the providers installed by the constructor, kept so they can be flushed at exit; a
+// daemon swaps in its own, and the ones it replaces are leaked with their workers gone
+static PROVIDERS: AtomicPtr<Providers> = AtomicPtr::new(std::ptr::null_mut());
+// held while PROVIDERS and the global tracer provider are swapped, so they agree
+static SWAPPING: Mutex<()> = Mutex::new(());
+static ATEXIT: Once = Once::new();
+// set while the install the constructor started is running; INSTALLED is notified when
+// it's done
+static INSTALLING: Mutex<bool> = Mutex::new(false);
+static INSTALLED: Condvar = Condvar::new();
+
+struct Providers {
+    traces: Option<SdkTracerProvider>,
+    metrics: Option<SdkMeterProvider>,
+    logs: Option<SdkLoggerProvider>,
+}
+
+// pid that installed PROVIDERS; forked children inherit the atexit handler but
+// not the batch worker thread, so shutting down there would wait forever
+static INSTALL_PID: AtomicI32 = AtomicI32::new(0);
+
+// how long a flush at exit may hold the process up, in milliseconds; 0 waits for as long
+// as the exporters take, and leaves the exits that skip atexit alone
+const DEFAULT_EXIT_TIMEOUT_MS: u64 = 2000;
+static EXIT_TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_EXIT_TIMEOUT_MS);
+
+// set by the first flush before an exit; the exit paths after it don't wait again
+static FLUSHED: AtomicBool = AtomicBool::new(false);
+// that flush ran out of time: the collector isn't answering, so the atexit handler doesn't
+// wait for it a second time
+static GAVE_UP: AtomicBool = AtomicBool::new(false);
+
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+#[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__mod_init_func"))]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(|| {
+        // the main thread's context outlives what is left of its exit (reentry.rs)
+        crate::reentry::watch();
+        // the copy loaded as an auditor only redirects bindings, the one it loads into
+        // the application's namespace does the exporting
+        #[cfg(all(feature = "audit", target_os = "linux", target_env = "gnu"))]
+        if crate::audit::is_auditor() {
+            crate::early::discard();
+            return;
+        }
+        let var = |key: &str| std::env::var(key).ok();
+        if !should_install(Signal::Traces, var) {
+            crate::early::discard();
+        }
+        if [Signal::Traces, Signal::Metrics, Signal::Logs]
+            .into_iter()
+            .any(|signal| should_install(signal, var))
+        {
+            // not one of the threads to report once it's done
+            let _suppress = crate::suppress_wrapping();
+            set_installing(true);
+            let spawned = std::thread::Builder::new()
+                .name("otel-posix-init".into())
+                .spawn(|| {
+                    // done, even if the install panics
+                    let _done = Installed;
+                    install();
+                });
+            if let Err(e) = spawned {
+                set_installing(false);
+                log_warn!("failed to start init thread: {e}");
+            }
+        }
+    });
+}
+
+#[derive(Clone, Copy)]
+enum Signal {
+    Traces,
+    Metrics,
+    Logs,
+}
+
+/// Whether the environment asks for an exporter for `signal`: an OTLP endpoint is
+/// configured, and neither that signal's exporter nor the SDK as a whole has been switched
+/// off. Logs are only exported when `OTEL_LOGS_EXPORTER=otlp` asks for them.
+fn should_install(signal: Signal, var: impl Fn(&str) -> Option<String>) -> bool {
+    let (exporter_var, endpoint_var) = match signal {
+        Signal::Traces => ("OTEL_TRACES_EXPORTER", "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"),
+        Signal::Metrics => (
+            "OTEL_METRICS_EXPORTER",
+            "OTEL_EXPORTER_OTLP_METRICS_ENDPOINT",
+        ),
+        Signal::Logs => ("OTEL_LOGS_EXPORTER", "OTEL_EXPORTER_OTLP_LOGS_ENDPOINT"),
+    };
+    // the shim has no logs of its own, so a logger provider would be a batch worker idling
+    // in every process; only the other shims' records need one
+    if matches!(signal, Signal::Logs) && var(exporter_var).is_none_or(|v| v.trim() != "otlp") {
+        return false;
+    }
+    let set = |key| var(key).is_some_and(|v: String| !v.trim().is_empty());
+    let disabled =
+        var(exporter_var).is_some_and(|v| v.trim() == "none") || crate::config::sdk_disabled(&var);
+    // spans can go to a file instead, metrics only to a collector
+    let file = matches!(signal, Signal::Traces) && file_export::path(&var).is_some();
+    !disabled && (set("OTEL_EXPORTER_OTLP_ENDPOINT") || set(endpoint_var) || file)
+}
+
+fn install() {
+    // the exporters' HTTP clients start threads of their own
+    let _suppress = crate::suppress_wrapping();
+    let var = |key: &str| std::env::var(key).ok();
+    INSTALL_PID.store(unsafe { libc::getpid() }, Ordering::Relaxed);
+    if let Some(ms) = var("OTEL_POSIX_PROP_EXIT_TIMEOUT").and_then(|v| parse_exit_timeout(&v)) {
+        EXIT_TIMEOUT_MS.store(ms, Ordering::Relaxed);
+    }
+    let providers = Providers {
+        traces: should_install(Signal::Traces, var)
+            .then(|| build_traces(None))
+            .flatten(),
+        metrics: should_install(Signal::Metrics, var)
+            .then(install_metrics)
+            .flatten(),
+        logs: should_install(Signal::Logs, var).then(build_logs).flatten(),
+    };
+    let traces = providers.traces.is_some();
+    swap(providers);
+    if traces {
+        crate::early::flush();
+    } else {
+        crate::early::discard();
+    }
+    ATEXIT.call_once(|| unsafe {
+        libc::atexit(flush_at_exit);
+    });
+}
+
+fn set_installing(running: bool) {
+    *INSTALLING.lock().unwrap_or_else(PoisonError::into_inner) = running;
+    if !running {
+        INSTALLED.notify_all();
+    }
+}
+
+/// Marks the constructor's install done when dropped.
+struct Installed;
+
+impl Drop for Installed {
+    fn drop(&mut self) {
+        set_installing(false);
+    }
+}
+
+/// Waits for the exporters the library installs at load time, from a thread of its own,
+/// to be in place, for at most `timeout_ms` milliseconds. A program that starts spans
+/// right away calls it first, so that none is started under a provider about to be
+/// replaced, and dropped when it ends.
+///
+/// Returns 0 once the install is done, at once if there is none running, or -1 if it is
+/// still running at the timeout.
+#[unsafe(no_mangle)]
+pub extern "C" fn otel_posix_wait_installed(timeout_ms: u64) -> c_int {
+    let installing = INSTALLING.lock().unwrap_or_else(PoisonError::into_inner);
+    let timeout = Duration::from_millis(timeout_ms);
+    let (installing, _) = INSTALLED
+        .wait_timeout_while(installing, timeout, |running| *running)
+        .unwrap_or_else(PoisonError::into_inner);
+    if *installing { -1 } else { 0 }
+}
+
+/// Makes `providers` the installed ones, and its tracer provider the global one; a
+/// no-op one takes the place of an installed one it has none to replace with. Returns
+/// those replaced, leaked: another thread may still be reading them.
+fn swap(providers: Providers) -> Option<&'static Providers> {
+    let _swapping = lock_swapping();
+    let old = unsafe { PROVIDERS.load(Ordering::Acquire).as_ref() };
+    match &providers.traces {
+        Some(provider) => {
+            global::set_tracer_provider(provider.clone());
+        }
+        None if old.is_some_and(|old| old.traces.is_some()) => {
+            global::set_tracer_provider(NoopTracerProvider::new());
+        }
+        None => {}
+    }
+    crate::logs::install(providers.logs.clone());
+    PROVIDERS.store(Box::leak(Box::new(providers)), Ordering::Release);
+    old
+}
+
+pub(crate) fn lock_swapping() -> MutexGuard<'static, ()> {
+    SWAPPING.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn providers() -> Option<&'static Providers> {
+    unsafe { PROVIDERS.load(Ordering::Acquire).as_ref() }
+}
+
+/// Installs a new tracer provider in a daemon, whose copy of the installed one lost its
+/// worker thread in the fork, and makes the daemon the process that flushes it. The
+/// shim's metrics stay with the meter provider left behind, so a daemon doesn't export
+/// them, and log records are dropped there. Does nothing where no tracer provider was installed, or in the process that
+/// installed it.
+#[cfg_attr(not(feature = "hook-daemon"), allow(dead_code))]
+pub(crate) fn reinstall() {
+    let pid = unsafe { libc::getpid() };
+    if providers().is_none_or(|p| p.traces.is_none())
+        || INSTALL_PID.swap(pid, Ordering::Relaxed) == pid
+    {
+        return;
+    }
+    // the exporter's HTTP client starts threads of its own
+    let _suppress = crate::suppress_wrapping();
+    FLUSHED.store(false, Ordering::Relaxed);
+    GAVE_UP.store(false, Ordering::Relaxed);
+    let providers = Providers {
+        traces: build_traces(None),
+        metrics: None,
+        logs: None,
+    };
+    swap(providers);
+}
+
+/// Replaces the tracer provider with a new OTLP one, which exports to `endpoint` if it
+/// isn't NULL, and otherwise to wherever the `OTEL_EXPORTER_OTLP_*` variables say now.
+///
+/// The provider replaced, the one the library installed at load time or by an earlier
+/// call, is shut down: what it still holds is exported first, for at most
+/// `OTEL_POSIX_PROP_EXIT_TIMEOUT`. The new one is flushed at exit in its place. An
+/// `endpoint` is the full URL spans are posted to, as in
+/// `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`. Returns 0, or -1 if the exporter couldn't be
+/// built, in which case the provider in place is kept.
+///
+/// Not to be called from a library constructor: building the exporter starts threads
+/// and waits for them.
+///
+/// # Safety
+///
+/// `endpoint` must be null or point to a NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn otel_posix_install_provider(endpoint: *const c_char) -> c_int {
+    let endpoint = (!endpoint.is_null()).then(|| {
+        unsafe { CStr::from_ptr(endpoint) }
+            .to_string_lossy()
+            .into_owned()
+    });
+    let replaced = std::panic::catch_unwind(|| {
+        // the exporter's HTTP client starts threads of its own
+        let _suppress = crate::suppress_wrapping();
+        let traces = build_traces(endpoint)?;
+        if let Some(ms) = std::env::var("OTEL_POSIX_PROP_EXIT_TIMEOUT")
+            .ok()
+            .and_then(|v| parse_exit_timeout(&v))
+        {
+            EXIT_TIMEOUT_MS.store(ms, Ordering::Relaxed);
+        }
+        INSTALL_PID.store(unsafe { libc::getpid() }, Ordering::Relaxed);
+        FLUSHED.store(false, Ordering::Relaxed);
+        GAVE_UP.store(false, Ordering::Relaxed);
+        let metrics = providers().and_then(|p| p.metrics.clone());
+        let logs = providers().and_then(|p| p.logs.clone());
+        let old = swap(Providers {
+            traces: Some(traces),
+            metrics,
+            logs,
+        });
+        crate::early::flush();
+        ATEXIT.call_once(|| unsafe {
+            libc::atexit(flush_at_exit);
+        });
+        Some(old.and_then(|old| old.traces.clone()))
+    });
+    let Ok(Some(replaced)) = replaced else {
+        return -1;
+    };
+    if let Some(provider) = replaced {
+        bounded(move || {
+            let _ = provider.shutdown();
+        });
+    }
+    0
+}
+
+/// Shuts the installed tracer, meter and logger providers down, exporting what they still hold
+/// for at most `OTEL_POSIX_PROP_EXIT_TIMEOUT`, and leaves a no-op tracer provider in
+/// their place, so nothing is recorded from then on. Nothing is flushed at exit
+/// afterwards; [`otel_posix_install_provider`] starts exporting again.
+///
+/// Returns 0, or -1 if the export didn't finish in time.
+#[unsafe(no_mangle)]
+pub extern "C" fn otel_posix_shutdown() -> c_int {
+    let finished = std::panic::catch_unwind(|| {
+        let Some(old) = swap(Providers {
+            traces: None,
+            metrics: None,
+            logs: None,
+        }) else {
+            return true;
+        };
+        let (traces, metrics, logs) = (old.traces.clone(), old.metrics.clone(), old.logs.clone());
+        if traces.is_none() && metrics.is_none() && logs.is_none() {
+            return true;
+        }
+        bounded(move || {
+            if let Some(provider) = traces {
+                let _ = provider.shutdown();
+            }
+            if let Some(provider) = metrics {
+                let _ = provider.shutdown();
+            }
+            if let Some(provider) = logs {
+                let _ = provider.shutdown();
+            }
+        })
+    });
+    if finished.unwrap_or(false) { 0 } else { -1 }
+}
+
+/// A tracer provider exporting over OTLP to `endpoint`, or where the environment says:
+/// the file named by `OTEL_POSIX_PROP_TRACES_FILE`, or a collector.
+fn build_traces(endpoint: Option<String>) -> Option<SdkTracerProvider> {
+    if endpoint.is_none()
+        && let Some(path) = file_export::path(|key| std::env::var(key).ok())
+    {
+        return FileExporter::open(&path).map(provider);
+    }
+    // headers and timeout, and the endpoint unless given, are all read from the standard
+    // OTEL_EXPORTER_OTLP_* vars
+    let mut builder = opentelemetry_otlp::SpanExporter::builder().with_http();
+    if let Some(endpoint) = endpoint {
+        builder = builder.with_endpoint(endpoint);
+    }
+    let exporter = match builder.build() {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            log_warn!("failed to build OTLP span exporter: {e}");
+            return None;
+        }
+    };
+
+    Some(provider(exporter))
+}
+
+fn provider(exporter: impl SpanExporter + 'static) -> SdkTracerProvider {
+    SdkTracerProvider::builder()
+        .with_resource(resource::resource())
+        .with_span_processor(SpanNameFilterProcessor)
+        .with_span_processor(RingSpanProcessor::new(exporter, |key| {
+            std::env::var(key).ok()
+        }))
+        .build()
+}
+
+fn install_metrics() -> Option<SdkMeterProvider> {
+    let exporter = match opentelemetry_otlp::MetricExporter::builder()
+        .with_http()
+        .build()
+    {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            log_warn!("failed to build OTLP metric exporter: {e}");
+            return None;
+        }
+    };
+
+    let provider = SdkMeterProvider::builder()
+        .with_resource(resource::resource())
+        .with_periodic_exporter(exporter)
+        .build();
+    global::set_meter_provider(provider.clone());
+    crate::metrics::register_metrics(&crate::scope::meter());
+    Some(provider)
+}
+
+fn build_logs() -> Option<SdkLoggerProvider> {
+    let exporter = match opentelemetry_otlp::LogExporter::builder()
+        .with_http()
+        .build()
+    {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            log_warn!("failed to build OTLP log exporter: {e}");
+            return None;
+        }
+    };
+
+    Some(
+        SdkLoggerProvider::builder()
+            .with_resource(resource::resource())
+            .with_batch_exporter(exporter)
+            .build(),
+    )
+}
+
+/// Parses `OTEL_POSIX_PROP_EXIT_TIMEOUT`, in milliseconds; `off` is 0.
+fn parse_exit_timeout(s: &str) -> Option<u64> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("off") {
+        return Some(0);
+    }
+    let ms = s.parse().ok();
+    if ms.is_none() {
+        log_warn!("invalid OTEL_POSIX_PROP_EXIT_TIMEOUT {s:?}, using {DEFAULT_EXIT_TIMEOUT_MS}");
+    }
+    ms
+}
+
+extern "C" fn flush_at_exit() {
+    let _ = std::panic::catch_unwind(|| {
+        if INSTALL_PID.load(Ordering::Relaxed) != unsafe { libc::getpid() }
+            || GAVE_UP.load(Ordering::Relaxed)
+        {
+            return;
+        }
+        let Some(providers) = providers() else {
+            return;
+        };
+        bounded(|| {
+            if let Some(provider) = &providers.traces {
+                // shutdown flushes the batch processor and stops its worker thread
+                let _ = provider.shutdown();
+            }
+            if let Some(provider) = &providers.metrics {
+                // collects and exports one last time
+                let _ = provider.shutdown();
+            }
+            if let Some(provider) = &providers.logs {
+                let _ = provider.shutdown();
+            }
+        });
+    });
+}
+
+/// Exports what the installed providers hold, for an exit that won't run the atexit
+/// handler (or runs it only after others that may take a while). Only the first call in
+/// the installing process does anything.
+#[cfg_attr(not(feature = "preload"), allow(dead_code))]
+pub(crate) fn flush_before_exit() {
+    let Some(providers) = providers() else {
+        return;
+    };
+    if EXIT_TIMEOUT_MS.load(Ordering::Relaxed) == 0
+        || INSTALL_PID.load(Ordering::Relaxed) != unsafe { libc::getpid() }
+        || (providers.traces.is_none() && providers.metrics.is_none() && providers.logs.is_none())
+        || FLUSHED.swap(true, Ordering::Relaxed)
+    {
+        return;
+    }
+    let finished = bounded(|| {
+        if let Some(provider) = &providers.traces {
+            let _ = provider.force_flush();
+        }
+        if let Some(provider) = &providers.metrics {
+            let _ = provider.force_flush();
+        }
+        if let Some(provider) = &providers.logs {
+            let _ = provider.force_flush();
+        }
+    });
+    GAVE_UP.store(!finished, Ordering::Relaxed);
+}
+
+/// Runs `flush` on a thread of its own and waits for it at most the exit timeout, then
+/// lets the exit go ahead whether it finished or not. Returns whether it finished.
+fn bounded(flush: impl FnOnce() + Send + 'static) -> bool {
+    let ms = EXIT_TIMEOUT_MS.load(Ordering::Relaxed);
+    if ms == 0 {
+        flush();
+        return true;
+    }
+    // our own plumbing, not a thread of the host's
+    let _suppress = crate::suppress_wrapping();
+    let (done, finished) = std::sync::mpsc::channel();
+    let spawned = std::thread::Builder::new()
+        .name("otel-posix-flush".into())
+        .spawn(move || {
+            flush();
+            let _ = done.send(());
+        });
+    if let Err(e) = spawned {
+        log_warn!("failed to start the exit flush thread: {e}");
+        return false;
+    }
+    let done = finished.recv_timeout(Duration::from_millis(ms)).is_ok();
+    if !done {
+        log_warn!("telemetry still not exported after {ms}ms at exit, exiting anyway");
+    }
+    done
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Installed, Signal, otel_posix_wait_installed, parse_exit_timeout, set_installing,
+        should_install,
+    };
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: Vec<(String, String)> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| vars.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone())
+    }
+
+    #[test]
+    fn installs_only_with_an_endpoint() {
+        let traces = |vars| should_install(Signal::Traces, env(vars));
+        assert!(!traces(&[]));
+        assert!(!traces(&[("OTEL_EXPORTER_OTLP_ENDPOINT", " ")]));
+        assert!(traces(&[(
+            "OTEL_EXPORTER_OTLP_ENDPOINT",
+            "http://localhost:4318"
+        )]));
+        assert!(traces(&[(
+            "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+            "http://localhost:4318/v1/traces"
+        )]));
+    }
+
+    #[test]
+    fn a_traces_file_installs_traces_only() {
+        let vars = [("OTEL_POSIX_PROP_TRACES_FILE", "/tmp/spans.jsonl")];
+        assert!(should_install(Signal::Traces, env(&vars)));
+        assert!(!should_install(Signal::Metrics, env(&vars)));
+        let vars = [
+            ("OTEL_POSIX_PROP_TRACES_FILE", "/tmp/spans.jsonl"),
+            ("OTEL_TRACES_EXPORTER", "none"),
+        ];
+        assert!(!should_install(Signal::Traces, env(&vars)));
+    }
+
+    #[test]
+    fn signal_specific_settings() {
+        let vars = [
+            (
+                "OTEL_EXPORTER_OTLP_METRICS_ENDPOINT",
+                "http://localhost:4318/v1/metrics",
+            ),
+            ("OTEL_TRACES_EXPORTER", "none"),
+        ];
+        assert!(should_install(Signal::Metrics, env(&vars)));
+        assert!(!should_install(Signal::Traces, env(&vars)));
+    }
+
+    #[test]
+    fn logs_only_when_asked_for() {
+        let endpoint = ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://localhost:4318");
+        assert!(!should_install(Signal::Logs, env(&[endpoint])));
+        let vars = [endpoint, ("OTEL_LOGS_EXPORTER", "otlp")];
+        assert!(should_install(Signal::Logs, env(&vars)));
+        // not to a traces file
+        let vars = [
+            ("OTEL_POSIX_PROP_TRACES_FILE", "/tmp/spans.jsonl"),
+            ("OTEL_LOGS_EXPORTER", "otlp"),
+        ];
+        assert!(!should_install(Signal::Logs, env(&vars)));
+    }
+
+    #[test]
+    fn exporter_none_wins() {
+        let vars = [
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://localhost:4318"),
+            ("OTEL_TRACES_EXPORTER", "none"),
+            ("OTEL_METRICS_EXPORTER", "none"),
+        ];
+        assert!(!should_install(Signal::Traces, env(&vars)));
+        assert!(!should_install(Signal::Metrics, env(&vars)));
+    }
+
+    #[test]
+    fn sdk_disabled_wins() {
+        let vars = [
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://localhost:4318"),
+            ("OTEL_SDK_DISABLED", "TRUE"),
+        ];
+        assert!(!should_install(Signal::Traces, env(&vars)));
+        assert!(!should_install(Signal::Metrics, env(&vars)));
+    }
+
+    #[test]
+    fn exit_timeouts() {
+        assert_eq!(parse_exit_timeout(" 500 "), Some(500));
+        assert_eq!(parse_exit_timeout("OFF"), Some(0));
+        assert_eq!(parse_exit_timeout("0"), Some(0));
+        assert_eq!(parse_exit_timeout("2s"), None);
+    }
+
+    #[test]
+    fn waiting_ends_with_the_install() {
+        assert_eq!(otel_posix_wait_installed(0), 0);
+        set_installing(true);
+        assert_eq!(otel_posix_wait_installed(10), -1);
+        let install = std::thread::spawn(|| {
+            let _done = Installed;
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        });
+        assert_eq!(otel_posix_wait_installed(60_000), 0);
+        install.join().unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+// src/instruments.rs
+//
+// C-callable metric recording, the counterpart of spans.rs for the
+// workspace's other preload shims: they can't reach the meter provider this
+// library installs either. A histogram is made the first time its name is
+// recorded to, under the shim's meter, and kept by name; the unit given
+// then is the one it has.
+
+use crate::spans::{OtelPosixAttribute, key_values};
+use crate::{reentry, scope};
+use libc::{c_char, c_int, size_t};
+use opentelemetry::metrics::Histogram;
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::sync::{Mutex, PoisonError};
+
+static HISTOGRAMS: Mutex<Option<HashMap<String, Histogram<f64>>>> = Mutex::new(None);
+
+/// Records `value` in the histogram named `name`, with the `count` attributes at
+/// `attributes`. The histogram is created on first use, in `unit` (which may be NULL).
+///
+/// Returns 0, or -1 if `name` is NULL.
+///
+/// # Safety
+///
+/// `name` and `unit` must be null or point to NUL-terminated strings, and `attributes`
+/// must be null or point to `count` attributes whose strings are NUL-terminated or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn otel_posix_record_histogram(
+    name: *const c_char,
+    unit: *const c_char,
+    value: f64,
+    attributes: *const OtelPosixAttribute,
+    count: size_t,
+) -> c_int {
+    let Some(_guard) = reentry::enter() else {
+        return -1;
+    };
+    if name.is_null() {
+        return -1;
+    }
+    let name = unsafe { CStr::from_ptr(name) }.to_string_lossy();
+    let attributes = unsafe { key_values(attributes, count) };
+    let mut histograms = HISTOGRAMS.lock().unwrap_or_else(PoisonError::into_inner);
+    let histograms = histograms.get_or_insert_with(HashMap::new);
+    if let Some(histogram) = histograms.get(name.as_ref()) {
+        histogram.record(value, &attributes);
+        return 0;
+    }
+    let meter = scope::meter();
+    let mut builder = meter.f64_histogram(name.clone().into_owned());
+    if !unit.is_null() {
+        builder = builder.with_unit(
+            unsafe { CStr::from_ptr(unit) }
+                .to_string_lossy()
+                .into_owned(),
+        );
+    }
+    let histogram = builder.build();
+    histogram.record(value, &attributes);
+    histograms.insert(name.into_owned(), histogram);
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histograms_need_a_name() {
+        let record = |name: *const c_char| unsafe {
+            otel_posix_record_histogram(name, c"s".as_ptr(), 0.5, std::ptr::null(), 0)
+        };
+        assert_eq!(record(c"test.wait_time".as_ptr()), 0);
+        // kept, whatever the provider does with it
+        assert_eq!(record(c"test.wait_time".as_ptr()), 0);
+        assert!(
+            HISTOGRAMS
+                .lock()
+                .unwrap()
+                .as_ref()
+                .is_some_and(|h| h.contains_key("test.wait_time"))
+        );
+        assert_eq!(record(std::ptr::null()), -1);
+    }
+}
@@ -0,0 +1,371 @@
+// src/interpose.rs
+//
+// The table of functions the preload build interposes besides
+// pthread_create. Each entry gives the C signature and what the call
+// returns when the real function can't be found (from inside a symbol
+// lookup, see resolve.rs); `interpose!`, from the ld_interpose crate,
+// writes the exported symbol, the cache of the real one and the lookup
+// (resolve.rs's, which records what it found for the status report), and
+// hands the real function and the caller's arguments to the handler of the
+// same name in the module named for the group. Adding one is a table entry
+// and a safe-to-call handler, with no unsafe boilerplate of its own.
+//
+// The groups are picked with cargo features, all of them by default
+// (`default-hooks`); a build without one doesn't export its functions at
+// all, where the configuration's switches only make them pass calls
+// straight through.
+//
+// Not in the table, since they are looked up differently or can't be
+// written as plain Rust functions: pthread_create (lib.rs) and thrd_create
+// (thrd.rs), which resolve on the first non-nested call only, the naked
+// dlopen (dlopen.rs), dlmopen (namespaces.rs), syslog and __syslog_chk
+// (syslog.rs), and pthread_once (once.rs), which its init routine may
+// unwind through.
+
+use libc::c_int;
+
+/// Fails a call made from inside a symbol lookup, for functions that report errors
+/// through `errno`.
+#[cfg_attr(
+    not(any(
+        feature = "hook-exec",
+        feature = "hook-daemon",
+        feature = "hook-poll",
+        feature = "hook-ucontext",
+        feature = "hook-wait",
+        feature = "hook-popen",
+        feature = "hook-signal",
+        feature = "mqueue-inject"
+    )),
+    allow(dead_code)
+)]
+fn unavailable() -> c_int {
+    unsafe { *libc::__errno_location() = libc::EAGAIN };
+    -1
+}
+
+ld_interpose::interpose! {
+    lookup = crate::resolve::cached_next;
+
+    #[cfg(feature = "hook-exec")]
+    exec {
+        /// Interposed `execve` that adds the current context to `envp`.
+        fn execve(
+            path: *const libc::c_char,
+            argv: *const *const libc::c_char,
+            envp: *const *const libc::c_char,
+        ) -> c_int, else unsafe { libc::syscall(libc::SYS_execve, path, argv, envp) as c_int };
+
+        /// Interposed `execv` that adds the current context to the inherited environment.
+        fn execv(path: *const libc::c_char, argv: *const *const libc::c_char) -> c_int,
+            else unavailable();
+
+        /// Interposed `execvp` that adds the current context to the inherited environment.
+        fn execvp(file: *const libc::c_char, argv: *const *const libc::c_char) -> c_int,
+            else unavailable();
+
+        /// Interposed `posix_spawn` that adds the current context to `envp`.
+        fn posix_spawn(
+            pid: *mut libc::pid_t,
+            path: *const libc::c_char,
+            file_actions: *const libc::posix_spawn_file_actions_t,
+            attrp: *const libc::posix_spawnattr_t,
+            argv: *const *mut libc::c_char,
+            envp: *const *mut libc::c_char,
+        ) -> c_int, else libc::EAGAIN;
+
+        /// Interposed `posix_spawnp` that adds the current context to `envp`.
+        fn posix_spawnp(
+            pid: *mut libc::pid_t,
+            file: *const libc::c_char,
+            file_actions: *const libc::posix_spawn_file_actions_t,
+            attrp: *const libc::posix_spawnattr_t,
+            argv: *const *mut libc::c_char,
+            envp: *const *mut libc::c_char,
+        ) -> c_int, else libc::EAGAIN;
+    }
+
+    #[cfg(feature = "hook-join")]
+    join {
+        /// Interposed `pthread_join` that records a `thread.join` event for wrapped threads.
+        fn pthread_join(tid: libc::pthread_t, retval: *mut *mut libc::c_void) -> c_int,
+            else libc::EAGAIN;
+
+        /// Interposed `pthread_timedjoin_np` that records a `thread.join` event for wrapped
+        /// threads once the join succeeds.
+        #[cfg(not(target_os = "android"))]
+        fn pthread_timedjoin_np(
+            tid: libc::pthread_t,
+            retval: *mut *mut libc::c_void,
+            abstime: *const libc::timespec,
+        ) -> c_int, else libc::EAGAIN;
+
+        /// Interposed `pthread_detach` that stops tracking the thread for join events.
+        fn pthread_detach(tid: libc::pthread_t) -> c_int, else libc::EAGAIN;
+    }
+
+    #[cfg(feature = "hook-daemon")]
+    daemon {
+        /// Interposed `daemon` that carries the trace context into the daemon.
+        fn daemon(nochdir: c_int, noclose: c_int) -> c_int, else unavailable();
+
+        /// Interposed `setsid` that marks the process as the middle of a double fork.
+        fn setsid() -> libc::pid_t, else unavailable();
+    }
+
+    #[cfg(all(feature = "hook-exit", feature = "otlp"))]
+    exit {
+        /// Interposed `exit` that flushes the installed providers first.
+        fn exit(status: c_int) -> !, else crate::exit::flush_and(crate::exit::exit_group, status);
+
+        /// Interposed `_exit` that flushes the installed providers first.
+        fn _exit(status: c_int) -> !, else crate::exit::flush_and(crate::exit::exit_group, status);
+
+        /// Interposed `_Exit` that flushes the installed providers first.
+        #[allow(non_snake_case)]
+        fn _Exit(status: c_int) -> !, else crate::exit::flush_and(crate::exit::exit_group, status);
+
+        /// Interposed `quick_exit` that flushes the installed providers first.
+        fn quick_exit(status: c_int) -> !,
+            else crate::exit::flush_and(crate::exit::exit_group, status);
+    }
+
+    #[cfg(all(
+        feature = "hook-dlopen",
+        target_os = "linux",
+        target_env = "gnu",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    dlopen {
+        /// Interposed `dlclose` that adds a `dlclose` event to the current span.
+        fn dlclose(handle: *mut libc::c_void) -> c_int, else -1;
+    }
+
+    #[cfg(feature = "hook-glib")]
+    glib {
+        /// Interposed `g_thread_new` that carries the caller's OTEL `Context` into the new
+        /// thread.
+        fn g_thread_new(
+            name: *const libc::c_char,
+            func: crate::StartRoutine,
+            data: *mut libc::c_void,
+        // GLib itself aborts when it can't start a thread; that's not ours to do
+        ) -> *mut libc::c_void, else std::ptr::null_mut();
+
+        /// Interposed `g_thread_try_new` that carries the caller's OTEL `Context` into the
+        /// new thread.
+        fn g_thread_try_new(
+            name: *const libc::c_char,
+            func: crate::StartRoutine,
+            data: *mut libc::c_void,
+            error: *mut *mut libc::c_void,
+        ) -> *mut libc::c_void, else std::ptr::null_mut();
+    }
+
+    #[cfg(feature = "hook-uv")]
+    uv {
+        /// Interposed `uv_thread_create` that carries the caller's OTEL `Context` into the new
+        /// thread.
+        fn uv_thread_create(
+            tid: *mut libc::pthread_t,
+            entry: crate::uv::UvThreadCb,
+            arg: *mut libc::c_void,
+        // UV_EAGAIN
+        ) -> c_int, else -libc::EAGAIN;
+
+        /// Interposed `uv_thread_create_ex` that carries the caller's OTEL `Context` into the
+        /// new thread.
+        fn uv_thread_create_ex(
+            tid: *mut libc::pthread_t,
+            params: *const libc::c_void,
+            entry: crate::uv::UvThreadCb,
+            arg: *mut libc::c_void,
+        ) -> c_int, else -libc::EAGAIN;
+    }
+
+    #[cfg(all(
+        feature = "hook-ucontext",
+        target_os = "linux",
+        target_env = "gnu",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    ucontext {
+        /// Interposed `makecontext` that lets the new coroutine start under the caller's
+        /// context. Supports up to six arguments for `func`.
+        #[allow(clippy::too_many_arguments)]
+        fn makecontext(
+            ucp: *mut libc::ucontext_t,
+            func: extern "C" fn(),
+            argc: c_int,
+            a0: libc::c_long,
+            a1: libc::c_long,
+            a2: libc::c_long,
+            a3: libc::c_long,
+            a4: libc::c_long,
+            a5: libc::c_long,
+        ), else ();
+
+        /// Interposed `swapcontext` that saves the current context for `oucp` and resumes
+        /// `ucp` under its own.
+        fn swapcontext(oucp: *mut libc::ucontext_t, ucp: *const libc::ucontext_t) -> c_int,
+            else unavailable();
+
+        /// Interposed `setcontext` that resumes `ucp` under its own context.
+        fn setcontext(ucp: *const libc::ucontext_t) -> c_int, else unavailable();
+    }
+
+    #[cfg(all(
+        feature = "hook-syslog",
+        any(target_os = "linux", target_os = "android"),
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    syslog {
+        /// Interposed `vsyslog` that appends the current trace and span id to the message.
+        /// The message is dropped without the real one.
+        fn vsyslog(priority: c_int, format: *const libc::c_char, ap: crate::syslog::VaList),
+            else ();
+
+        /// Interposed `__vsyslog_chk`, the `_FORTIFY_SOURCE` variant of `vsyslog`.
+        #[cfg(target_env = "gnu")]
+        fn __vsyslog_chk(
+            priority: c_int,
+            flag: c_int,
+            format: *const libc::c_char,
+            ap: crate::syslog::VaList,
+        ), else ();
+    }
+
+    #[cfg(feature = "hook-poll")]
+    poll {
+        /// Interposed `poll` that counts the time in it as the thread waiting.
+        fn poll(fds: *mut libc::pollfd, nfds: libc::nfds_t, timeout: c_int) -> c_int,
+            else unavailable();
+
+        /// Interposed `epoll_wait` that counts the time in it as the thread waiting.
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        fn epoll_wait(
+            epfd: c_int,
+            events: *mut libc::epoll_event,
+            maxevents: c_int,
+            timeout: c_int,
+        ) -> c_int, else unavailable();
+
+        /// Interposed `epoll_pwait` that counts the time in it as the thread waiting.
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        fn epoll_pwait(
+            epfd: c_int,
+            events: *mut libc::epoll_event,
+            maxevents: c_int,
+            timeout: c_int,
+            sigmask: *const libc::sigset_t,
+        ) -> c_int, else unavailable();
+    }
+
+    #[cfg(feature = "hook-wait")]
+    children {
+        /// Interposed `fork` that gives the child a `child process` span.
+        fn fork() -> libc::pid_t, else unavailable();
+
+        /// Interposed `wait` that ends the span of the child it reaps.
+        fn wait(status: *mut c_int) -> libc::pid_t, else unavailable();
+
+        /// Interposed `waitpid` that ends the span of the child it reaps.
+        fn waitpid(pid: libc::pid_t, status: *mut c_int, options: c_int) -> libc::pid_t,
+            else unavailable();
+
+        /// Interposed `wait4` that ends the span of the child it reaps.
+        fn wait4(
+            pid: libc::pid_t,
+            status: *mut c_int,
+            options: c_int,
+            usage: *mut libc::rusage,
+        ) -> libc::pid_t, else unavailable();
+
+        /// Interposed `waitid` that ends the span of the child it reaps.
+        fn waitid(
+            idtype: libc::idtype_t,
+            id: libc::id_t,
+            info: *mut libc::siginfo_t,
+            options: c_int,
+        ) -> c_int, else unavailable();
+    }
+
+    #[cfg(feature = "hook-popen")]
+    popen {
+        /// Interposed `popen` that adds the current context to the command's environment
+        /// and gives it a `popen` span.
+        fn popen(command: *const libc::c_char, mode: *const libc::c_char) -> *mut libc::FILE,
+            else { unavailable(); std::ptr::null_mut() };
+
+        /// Interposed `pclose` that ends the span of the command it waits for.
+        fn pclose(stream: *mut libc::FILE) -> c_int, else unavailable();
+    }
+
+    #[cfg(feature = "hook-signal")]
+    signal {
+        /// Interposed `sigaction` that runs the handler behind a dispatcher, so that
+        /// `pthread_create` knows when it is called from one.
+        fn sigaction(
+            signum: c_int,
+            act: *const libc::sigaction,
+            oldact: *mut libc::sigaction,
+        ) -> c_int, else unavailable();
+
+        /// Interposed `signal` that installs the handler through the interposed `sigaction`.
+        fn signal(signum: c_int, handler: libc::sighandler_t) -> libc::sighandler_t,
+            else libc::SIG_ERR;
+    }
+
+    #[cfg(feature = "http-inject")]
+    http_inject {
+        /// Interposed `send` that injects `traceparent` into outbound HTTP/1.x request heads.
+        /// Without libc's, the raw system call stands in.
+        fn send(fd: c_int, buf: *const libc::c_void, len: libc::size_t, flags: c_int)
+            -> libc::ssize_t,
+            else unsafe { crate::http_inject::send(crate::http_inject::sys_send, fd, buf, len, flags) };
+
+        /// Interposed `write` that injects `traceparent` into HTTP/1.x request heads written
+        /// to sockets.
+        fn write(fd: c_int, buf: *const libc::c_void, count: libc::size_t) -> libc::ssize_t,
+            else unsafe { crate::http_inject::write(crate::http_inject::sys_write, fd, buf, count) };
+    }
+
+    #[cfg(all(feature = "mqueue-inject", target_os = "linux"))]
+    mqueue {
+        /// Interposed `mq_send` that appends the current span's traceparent to the message.
+        fn mq_send(
+            mqdes: libc::mqd_t,
+            msg: *const libc::c_char,
+            len: libc::size_t,
+            prio: libc::c_uint,
+        ) -> c_int, else unavailable();
+
+        /// Interposed `mq_timedsend` that appends the current span's traceparent to the
+        /// message.
+        fn mq_timedsend(
+            mqdes: libc::mqd_t,
+            msg: *const libc::c_char,
+            len: libc::size_t,
+            prio: libc::c_uint,
+            timeout: *const libc::timespec,
+        ) -> c_int, else unavailable();
+
+        /// Interposed `mq_receive` that strips a traceparent trailer and seeds its context.
+        fn mq_receive(
+            mqdes: libc::mqd_t,
+            msg: *mut libc::c_char,
+            len: libc::size_t,
+            prio: *mut libc::c_uint,
+        ) -> libc::ssize_t, else unavailable() as libc::ssize_t;
+
+        /// Interposed `mq_timedreceive` that strips a traceparent trailer and seeds its
+        /// context.
+        fn mq_timedreceive(
+            mqdes: libc::mqd_t,
+            msg: *mut libc::c_char,
+            len: libc::size_t,
+            prio: *mut libc::c_uint,
+            timeout: *const libc::timespec,
+        ) -> libc::ssize_t, else unavailable() as libc::ssize_t;
+    }
+}
@@ -0,0 +1,181 @@
+// src/join.rs
+//
+// Completion events for joined threads. With OTEL_POSIX_PROP_JOIN_EVENTS on,
+// every wrapped thread is remembered by its pthread_t; the trampoline stamps
+// when the start routine began and returned, and pthread_join /
+// pthread_timedjoin_np (which bionic doesn't have) add a `thread.join` event
+// to the creating span with how long the thread ran and how long the joiner
+// waited for it. Threads created or later marked detached are forgotten,
+// nobody will join them.
+
+use crate::config::config;
+use crate::{attr, entry, reentry};
+use libc::{c_int, pthread_attr_t, pthread_t, timespec};
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::{Context, KeyValue};
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::Instant;
+
+type JoinFn = unsafe extern "C" fn(pthread_t, *mut *mut c_void) -> c_int;
+#[cfg(not(target_os = "android"))]
+type TimedJoinFn = unsafe extern "C" fn(pthread_t, *mut *mut c_void, *const timespec) -> c_int;
+type DetachFn = unsafe extern "C" fn(pthread_t) -> c_int;
+
+// wrapped, joinable threads that haven't been joined yet
+static THREADS: LazyLock<Mutex<HashMap<pthread_t, Arc<Thread>>>> = LazyLock::new(Default::default);
+
+// set once the first thread is tracked
+static TRACKING: AtomicBool = AtomicBool::new(false);
+
+/// What the trampoline learns about a wrapped thread, for the event at join.
+#[derive(Debug)]
+pub(crate) struct Thread {
+    cx: Context,
+    // the start routine, named in the event
+    start_routine: usize,
+    tid: AtomicI32,
+    started: OnceLock<Instant>,
+    ended: OnceLock<Instant>,
+    exit_value: AtomicBool,
+    // what it was created with
+    requested: Option<attr::Requested>,
+}
+
+impl Thread {
+    /// Called by the trampoline before the real start routine.
+    pub fn started(&self) {
+        self.tid.store(unsafe { libc::gettid() }, Ordering::Relaxed);
+        let _ = self.started.set(Instant::now());
+    }
+
+    /// Called by the trampoline once the real start routine returned `ret`. Threads
+    /// leaving through `pthread_exit` never get here.
+    pub fn finished(&self, ret: *mut c_void) {
+        self.exit_value.store(!ret.is_null(), Ordering::Relaxed);
+        let _ = self.ended.set(Instant::now());
+    }
+
+    fn event_attributes(&self, wait: f64, retval: Option<*mut c_void>) -> Vec<KeyValue> {
+        let mut attributes = vec![KeyValue::new("otel_posix.join.wait", wait)];
+        let tid = self.tid.load(Ordering::Relaxed);
+        if tid != 0 {
+            attributes.push(KeyValue::new("thread.id", i64::from(tid)));
+        }
+        attributes.extend(entry::code_attributes(self.start_routine as *const c_void));
+        attributes.extend(self.requested.iter().flat_map(attr::Requested::attributes));
+        if let (Some(started), Some(ended)) = (self.started.get(), self.ended.get()) {
+            attributes.push(KeyValue::new(
+                "otel_posix.thread.run_duration",
+                ended.duration_since(*started).as_secs_f64(),
+            ));
+        }
+        // the joiner's copy also covers pthread_exit; otherwise fall back to what the
+        // trampoline saw returned
+        let exit_value = match retval {
+            Some(value) => Some(!value.is_null()),
+            None => self
+                .ended
+                .get()
+                .map(|_| self.exit_value.load(Ordering::Relaxed)),
+        };
+        if let Some(present) = exit_value {
+            attributes.push(KeyValue::new(
+                "otel_posix.thread.exit_value.present",
+                present,
+            ));
+        }
+        attributes
+    }
+}
+
+/// Starts tracking a thread about to be created under `cx` to run `start_routine`, if join
+/// events are on and `attr` doesn't create it detached.
+pub(crate) fn begin(
+    cx: &Context,
+    attr: *const pthread_attr_t,
+    start_routine: crate::StartRoutine,
+) -> Option<Arc<Thread>> {
+    if !config().join_events || attr::is_detached(attr) {
+        return None;
+    }
+    Some(Arc::new(Thread {
+        cx: cx.clone(),
+        start_routine: start_routine as usize,
+        tid: AtomicI32::new(0),
+        started: OnceLock::new(),
+        ended: OnceLock::new(),
+        exit_value: AtomicBool::new(false),
+        requested: attr::Requested::read(attr),
+    }))
+}
+
+/// Remembers a successfully created thread until it is joined or detached.
+pub(crate) fn track(tid: pthread_t, thread: Arc<Thread>) {
+    // a reused pthread_t replaces whatever was left behind by its previous owner
+    TRACKING.store(true, Ordering::Relaxed);
+    lock_threads().insert(tid, thread);
+}
+
+pub(crate) fn lock_threads() -> MutexGuard<'static, HashMap<pthread_t, Arc<Thread>>> {
+    // the map stays consistent even if a holder panicked
+    THREADS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Records the join of `tid` on its creating span.
+fn joined(tid: pthread_t, wait_start: Instant, retval: *mut *mut c_void) {
+    let Some(thread) = lock_threads().remove(&tid) else {
+        return;
+    };
+    if Context::map_current(|cx| cx.is_telemetry_suppressed()) {
+        return;
+    }
+    let retval = (!retval.is_null()).then(|| unsafe { *retval });
+    let attributes = thread.event_attributes(wait_start.elapsed().as_secs_f64(), retval);
+    thread.cx.span().add_event("thread.join", attributes);
+}
+
+/// `pthread_join`, with a `thread.join` event for wrapped threads.
+pub(crate) unsafe fn pthread_join(real: JoinFn, tid: pthread_t, retval: *mut *mut c_void) -> c_int {
+    let Some(_guard) = reentry::enter() else {
+        return unsafe { real(tid, retval) };
+    };
+    let start = config().join_events.then(Instant::now);
+    let rc = unsafe { real(tid, retval) };
+    if let (0, Some(start)) = (rc, start) {
+        joined(tid, start, retval);
+    }
+    rc
+}
+
+/// `pthread_timedjoin_np`, with a `thread.join` event for wrapped threads once the join
+/// succeeds.
+#[cfg(not(target_os = "android"))]
+pub(crate) unsafe fn pthread_timedjoin_np(
+    real: TimedJoinFn,
+    tid: pthread_t,
+    retval: *mut *mut c_void,
+    abstime: *const timespec,
+) -> c_int {
+    let Some(_guard) = reentry::enter() else {
+        return unsafe { real(tid, retval, abstime) };
+    };
+    let start = config().join_events.then(Instant::now);
+    let rc = unsafe { real(tid, retval, abstime) };
+    if let (0, Some(start)) = (rc, start) {
+        joined(tid, start, retval);
+    }
+    rc
+}
+
+/// `pthread_detach`, which also stops tracking the thread for join events.
+pub(crate) unsafe fn pthread_detach(real: DetachFn, tid: pthread_t) -> c_int {
+    let rc = unsafe { real(tid) };
+    // checked without reading the config, which detaching threads mustn't initialize
+    if rc == 0 && TRACKING.load(Ordering::Relaxed) {
+        lock_threads().remove(&tid);
+    }
+    rc
+}
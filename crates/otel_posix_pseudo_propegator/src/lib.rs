@@ -1,59 +1,725 @@
 // Cargo.toml:
-// [lib] crate-type = ["cdylib"]
+// [lib] crate-type = ["cdylib", "rlib"]
 
 use libc::{pthread_attr_t, pthread_t};
-use opentelemetry::{Context, trace::TraceContextExt};
+use opentelemetry::{Context, ContextGuard, trace::TraceContextExt};
 use std::ffi::c_void;
+#[cfg(feature = "preload")]
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::time::{Instant, SystemTime};
+
+#[cfg(all(feature = "preload", feature = "linker-wrap"))]
+compile_error!("features `preload` and `linker-wrap` are mutually exclusive");
+#[cfg(not(any(feature = "preload", feature = "linker-wrap")))]
+compile_error!("enable one of the `preload` or `linker-wrap` features");
+#[cfg(all(feature = "otel-0_30", feature = "otel-0_31"))]
+compile_error!("features `otel-0_30` and `otel-0_31` are mutually exclusive");
+#[cfg(not(any(feature = "otel-0_30", feature = "otel-0_31")))]
+compile_error!("enable one of the `otel-0_30` or `otel-0_31` features");
+#[cfg(all(feature = "minimal", feature = "otlp"))]
+compile_error!("features `minimal` and `otlp` are mutually exclusive");
+#[cfg(all(feature = "sdk", not(any(feature = "sdk-0_30", feature = "sdk-0_31"))))]
+compile_error!("enable `sdk` and `otlp` through `sdk-0_30`/`otlp-0_30` or `sdk-0_31`/`otlp-0_31`");
+
+// the rest of the crate names the opentelemetry crates plainly, whichever release was picked
+#[cfg(feature = "otel-0_31")]
+extern crate opentelemetry_0_31 as opentelemetry;
+#[cfg(feature = "otel-0_31")]
+extern crate opentelemetry_otlp_0_31 as opentelemetry_otlp;
+#[cfg(feature = "otel-0_31")]
+extern crate opentelemetry_sdk_0_31 as opentelemetry_sdk;
+
+#[cfg(all(
+    feature = "preload",
+    target_os = "linux",
+    target_env = "gnu",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod attach;
+mod attr;
+#[cfg(all(feature = "audit", target_os = "linux", target_env = "gnu"))]
+mod audit;
+mod auto_root;
+pub mod callback;
+// only preload builds can tell the shim apart from its caller
+#[cfg_attr(not(feature = "preload"), allow(dead_code))]
+mod caller;
+#[cfg(feature = "hook-wait")]
+mod children;
+mod config;
+mod config_file;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod control;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod crash;
+mod crashdump;
+#[cfg(feature = "hook-daemon")]
+mod daemon;
+mod deadline;
+#[cfg(all(
+    feature = "hook-dlopen",
+    target_os = "linux",
+    target_env = "gnu",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod dlopen;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod dump;
+#[cfg(feature = "otlp")]
+mod early;
+mod entry;
+#[cfg(feature = "hook-exec")]
+mod exec;
+#[cfg(all(feature = "hook-exit", feature = "otlp"))]
+mod exit;
+#[cfg(feature = "otlp")]
+mod export;
+mod ffi;
+#[cfg(feature = "otlp")]
+mod file_export;
+mod filter;
+mod fork;
+#[cfg(feature = "hook-glib")]
+mod glib;
+mod hooks;
+#[cfg(feature = "http-inject")]
+mod http_inject;
+#[cfg(feature = "otlp")]
+mod init;
+mod instruments;
+#[cfg(feature = "preload")]
+mod interpose;
+#[cfg(feature = "hook-join")]
+mod join;
+mod lifetime;
+mod links;
+mod log;
+#[cfg(feature = "sdk")]
+mod logs;
+pub mod metrics;
+#[cfg(all(feature = "mqueue-inject", target_os = "linux"))]
+mod mqueue;
+#[cfg(all(
+    feature = "hook-dlopen",
+    target_os = "linux",
+    target_env = "gnu",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod namespaces;
+#[cfg(feature = "hook-once")]
+mod once;
+mod origin;
+mod panics;
+mod poll;
+mod pool;
+#[cfg(feature = "hook-popen")]
+mod popen;
+// only the exec/spawn interposers write child environments so far
+#[cfg_attr(not(feature = "hook-exec"), allow(dead_code))]
+mod propagators;
+mod ratelimit;
+mod recorder;
+mod reentry;
+mod registry;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod reload;
+#[cfg(feature = "preload")]
+mod resolve;
+#[cfg(feature = "otlp")]
+mod resource;
+mod rusage;
+mod sanitizer;
+mod sched;
+mod scope;
+#[cfg(feature = "preload")]
+mod selftest;
+#[cfg(feature = "hook-signal")]
+mod signal;
+mod spans;
+mod spawn;
+#[cfg(any(feature = "creation-stack", target_os = "linux", target_os = "android"))]
+mod stack;
+mod status;
+mod suppress;
+#[cfg(all(
+    feature = "hook-syslog",
+    any(target_os = "linux", target_os = "android"),
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod syslog;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+#[cfg(all(feature = "hook-thrd", any(target_os = "linux", target_os = "android")))]
+mod thrd;
+#[cfg(feature = "tracing")]
+mod tracing_span;
+#[cfg(all(
+    feature = "hook-ucontext",
+    target_os = "linux",
+    target_env = "gnu",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod ucontext;
+mod unwind;
+#[cfg(feature = "hook-uv")]
+mod uv;
+pub mod w3c;
+
+#[cfg(feature = "sdk")]
+pub use filter::SpanNameFilterProcessor;
+pub use hooks::{RestoreFn, register_thread_hook};
+pub use metrics::register_metrics;
+#[cfg(feature = "macros")]
+pub use otel_posix_pseudo_propegator_macros::otel_trampoline;
+pub use spawn::{PropagatingBuilder, spawn_with_otel};
+pub use suppress::{SuppressGuard, suppress_wrapping};
+
+type StartRoutine = extern "C" fn(*mut c_void) -> *mut c_void;
+
+#[cfg(feature = "preload")]
+type PthreadCreateFn =
+    unsafe extern "C" fn(*mut pthread_t, *const pthread_attr_t, StartRoutine, *mut c_void) -> i32;
+
+// the next pthread_create in the lookup chain (normally libc's); a plain atomic rather
+// than a OnceLock, which would deadlock if resolving it re-entered us
+#[cfg(feature = "preload")]
+static REAL_PTHREAD_CREATE: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+/// The real `pthread_create` if it has been resolved already.
+#[cfg(feature = "preload")]
+fn cached_real_pthread_create() -> Option<PthreadCreateFn> {
+    let sym = REAL_PTHREAD_CREATE.load(Ordering::Acquire);
+    (!sym.is_null()).then(|| unsafe { std::mem::transmute::<*mut c_void, PthreadCreateFn>(sym) })
+}
+
+/// The real `pthread_create`, resolved on first use. `None` if it can't be found yet;
+/// a failed lookup isn't cached, so the next call tries again.
+#[cfg(feature = "preload")]
+fn real_pthread_create() -> Option<PthreadCreateFn> {
+    if let Some(real) = cached_real_pthread_create() {
+        return Some(real);
+    }
+    // racing resolvers all find the same symbol
+    let real = resolve::pthread_create()?;
+    REAL_PTHREAD_CREATE.store(real as *mut c_void, Ordering::Release);
+    Some(real)
+}
+
+// launchers in flight, recycled rather than allocated per thread
+static LAUNCHES: pool::Pool<Launch> = pool::Pool::new();
+
+// wrapped creations, at most OTEL_POSIX_PROP_WRAP_RATE a second
+static WRAP_LIMIT: ratelimit::TokenBucket = ratelimit::TokenBucket::new();
 
 // A little launcher holding the real fn + its arg + the OTEL Context
 struct Launch {
-    real_fn: extern "C" fn(*mut c_void) -> *mut c_void,
+    real_fn: StartRoutine,
     real_arg: *mut c_void,
-    ctx: Context,
+    // None when the thread is only trampolined for the embedder hooks
+    ctx: Option<Context>,
+    // state captured by the embedder hooks
+    hooks: Vec<hooks::Captured>,
+    // the creator's `tracing` span
+    #[cfg(feature = "tracing")]
+    tracing: Option<tracing::Span>,
+    // when pthread_create was called, for the lifetime span
+    created: Option<SystemTime>,
+    // the creator, in attribute-only mode, where nothing is attached
+    origin: Option<origin::Origin>,
+    // the creator's deadline, when no context of its goes along
+    deadline: Option<deadline::Deadline>,
+    // created without any span; the thread gets a root span of its own
+    auto_root: bool,
+    // tracked for the event at pthread_join
+    #[cfg(feature = "hook-join")]
+    join: Option<std::sync::Arc<join::Thread>>,
+    // the attributes it was created with, for a span of its own
+    requested: Option<attr::Requested>,
+    // where the thread was created, for a span of its own
+    #[cfg(feature = "creation-stack")]
+    stack: Option<stack::Creation>,
+}
+
+/// Everything the trampoline set up around the start routine, undone once it returns.
+struct Running {
+    // the thread's root span in links mode, ended with the thread
+    root: Option<Context>,
+    // the thread's span was handed to lifetime.rs, to be ended with the thread
+    parked: bool,
+    guard: Option<ContextGuard>,
+    #[cfg(feature = "tracing")]
+    entered: Option<tracing::span::EnteredSpan>,
+    restored: Vec<hooks::Restored>,
+    #[cfg(feature = "hook-join")]
+    join: Option<std::sync::Arc<join::Thread>>,
+}
+
+impl Running {
+    fn enter(launch: Launch, creator: Option<&Context>, start: Option<Instant>) -> Self {
+        // activate the captured Context, or a root span linked to it
+        let mode = config::config().mode;
+        let cx = creator.map(|ctx| match mode {
+            config::Mode::Parent => ctx.clone(),
+            config::Mode::Links => links::thread_root_context(ctx, launch.real_fn as *const c_void),
+            config::Mode::Lifetime => {
+                let created = launch.created.unwrap_or_else(SystemTime::now);
+                lifetime::thread_context(ctx, created, launch.real_fn as *const c_void)
+            }
+            // the context stays behind in the creator, see origin.rs
+            config::Mode::Attributes => unreachable!("no context is carried over"),
+        });
+        if let Some(origin) = launch.origin {
+            origin::set(origin);
+        }
+        let parked = match cx {
+            Some(_) => mode == config::Mode::Lifetime,
+            None => launch.auto_root,
+        };
+        let cx = cx.or_else(|| {
+            launch
+                .auto_root
+                .then(|| auto_root::thread_context(launch.real_fn as *const c_void))
+        });
+        if let Some(cx) = &cx {
+            registry::set_current(Some(cx.span().span_context()));
+        }
+
+        if recorder::enabled() {
+            recorder::record(
+                recorder::Action::Started,
+                cx.as_ref().map(|cx| cx.span().span_context().span_id()),
+            );
+        }
+        // only a links-mode root span is needed again; the rest is attached as it is
+        let root = cx
+            .as_ref()
+            .filter(|_| !parked && mode == config::Mode::Links)
+            .cloned();
+        if let (Some(cx), Some(requested)) = (&cx, &launch.requested)
+            && (parked || root.is_some())
+        {
+            cx.span().set_attributes(requested.attributes());
+        }
+        // symbolized here rather than in the creator, which is waiting on pthread_create
+        #[cfg(feature = "creation-stack")]
+        if let (Some(cx), Some(stack)) = (&cx, &launch.stack)
+            && (parked || root.is_some())
+        {
+            cx.span().set_attribute(stack.attribute());
+        }
+        let guard = deadline::attach(cx, launch.deadline);
+        #[cfg(feature = "tracing")]
+        let entered = launch.tracing.map(tracing::Span::entered);
+        // the hooks see the attached context
+        let restored = hooks::restore(launch.hooks);
+        if let Some(start) = start {
+            metrics::record_overhead(metrics::Phase::Start, start.elapsed());
+        }
+        #[cfg(feature = "hook-join")]
+        if let Some(thread) = &launch.join {
+            thread.started();
+        }
+        Running {
+            root,
+            parked,
+            guard,
+            #[cfg(feature = "tracing")]
+            entered,
+            restored,
+            #[cfg(feature = "hook-join")]
+            join: launch.join,
+        }
+    }
+
+    fn finish(
+        self,
+        #[cfg_attr(not(feature = "hook-join"), allow(unused_variables))] ret: *mut c_void,
+    ) {
+        #[cfg(feature = "hook-join")]
+        if let Some(thread) = &self.join {
+            thread.finished(ret);
+        }
+        recorder::record(recorder::Action::Finished, None);
+        hooks::release(self.restored);
+        if self.guard.is_some() {
+            registry::set_current(None);
+        }
+        // the thread's own span covers exactly the thread's lifetime
+        if let Some(root) = self.root {
+            root.span().end();
+        } else if self.parked {
+            lifetime::thread_returned();
+        }
+        #[cfg(feature = "tracing")]
+        drop(self.entered);
+        drop(self.guard);
+    }
 }
 
 extern "C" fn trampoline(v: *mut c_void) -> *mut c_void {
+    let start = metrics::sample().then(Instant::now);
     // recover the Launch struct
-    let launch: Box<Launch> = unsafe { Box::from_raw(v as *mut Launch) };
-    // activate the captured Context
-    let _guard = launch.ctx.attach();
-    // call the original thread entry point
-    (launch.real_fn)(launch.real_arg)
+    let launch = unsafe { LAUNCHES.take(v.cast::<Launch>()) };
+    let (real_fn, real_arg) = (launch.real_fn, launch.real_arg);
+    let (creator, running) = enter(launch, start);
+    // call the original thread entry point, from this frame: the call can't unwind, so a
+    // forced unwind (pthread_exit, cancellation) passes through it
+    let ret = real_fn(real_arg);
+    leave(creator, running, ret);
+    ret
+}
+
+/// Sets up what `launch` carries in the new thread, before its start routine runs.
+fn enter(mut launch: Launch, start: Option<Instant>) -> (Option<Context>, Option<Running>) {
+    let creator = launch.ctx.take();
+    // a panic in our own code must not unwind into C: it is reported on the creator's
+    // span, and the start routine runs all the same, just without what we set up
+    let running = unwind::guarded(creator.as_ref(), || {
+        Running::enter(launch, creator.as_ref(), start)
+    });
+    (creator, running)
+}
+
+/// Finishes what [`enter`] set up, once the start routine has returned `ret`.
+fn leave(creator: Option<Context>, running: Option<Running>, ret: *mut c_void) {
+    if let Some(running) = running {
+        unwind::guarded(creator.as_ref(), || running.finish(ret));
+    }
+}
+
+/// The launch for `real_fn(real_arg)` in a thread created from a signal handler, by
+/// `creator` at `created` under the span `sc`, made here as `create_wrapped` would have in
+/// the creator (see signal.rs); `None` if it is passed through.
+#[cfg(feature = "hook-signal")]
+fn deferred_launch(
+    real_fn: StartRoutine,
+    real_arg: *mut c_void,
+    sc: opentelemetry::trace::SpanContext,
+    creator: libc::pid_t,
+    created: SystemTime,
+) -> Option<Launch> {
+    let config = config::config();
+    let wrap = config.hooks.pthread_create
+        && (!config.sampled_only || sc.is_sampled())
+        && (config.span_filter.is_none() || filter::is_matching(sc.span_id()));
+    if !wrap {
+        metrics::thread_passed_through();
+        return None;
+    }
+    let cx = Context::new().with_remote_span_context(sc);
+    let (ctx, origin) = match config.mode {
+        config::Mode::Attributes => (None, Some(origin::Origin::deferred(creator, &cx, created))),
+        _ => (Some(cx), None),
+    };
+    metrics::thread_wrapped();
+    Some(Launch {
+        real_fn,
+        real_arg,
+        ctx,
+        hooks: Vec::new(),
+        #[cfg(feature = "tracing")]
+        tracing: None,
+        created: Some(created),
+        origin,
+        deadline: None,
+        auto_root: false,
+        #[cfg(feature = "hook-join")]
+        join: None,
+        requested: None,
+        #[cfg(feature = "creation-stack")]
+        stack: None,
+    })
 }
 
+/// Interposed `pthread_create` that carries the caller's OTEL `Context` into the new thread.
+///
+/// Exported in `preload` mode; forwards to the next `pthread_create` in the lookup chain.
+///
+/// # Safety
+///
+/// Same contract as libc's `pthread_create`: `tid` must be valid for writes, `attr` must be
+/// null or point to an initialized attribute object, and `arg` must be valid for
+/// `start_routine`.
+#[cfg(feature = "preload")]
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn pthread_create(
     tid: *mut pthread_t,
     attr: *const pthread_attr_t,
-    start_routine: extern "C" fn(*mut c_void) -> *mut c_void,
+    start_routine: StartRoutine,
     arg: *mut c_void,
 ) -> i32 {
-    // 1. capture the current OTEL Context
-    let cx = Context::current();
+    let guard = (!resolve::in_progress()).then(reentry::enter).flatten();
+    let Some(_guard) = guard else {
+        // nested call, e.g. from a malloc interposer while we were resolving or wrapping
+        recorder::record(recorder::Action::Nested, None);
+        return match cached_real_pthread_create() {
+            Some(real) => unsafe { real(tid, attr, start_routine, arg) },
+            None => libc::EAGAIN,
+        };
+    };
+    let Some(real) = real_pthread_create() else {
+        // failing one thread creation beats taking down a host we were merely preloaded
+        // into
+        resolve::report_missing(c"pthread_create");
+        return libc::EAGAIN;
+    };
+    unsafe {
+        create_wrapped(tid, attr, start_routine, arg, |start, arg| {
+            real(tid, attr, start, arg)
+        })
+    }
+}
 
-    // if no context, just call the original pthread_create
-    // This is a fast path to avoid unnecessary overhead when no context is active.
-    if !cx.has_active_span() {
-        // if no context, just call the original pthread_create
+/// `--wrap=pthread_create` target that carries the caller's OTEL `Context` into the new
+/// thread.
+///
+/// Exported in `linker-wrap` mode; forwards to `__real_pthread_create`.
+///
+/// # Safety
+///
+/// Same contract as libc's `pthread_create`.
+#[cfg(feature = "linker-wrap")]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn __wrap_pthread_create(
+    tid: *mut pthread_t,
+    attr: *const pthread_attr_t,
+    start_routine: StartRoutine,
+    arg: *mut c_void,
+) -> i32 {
+    let Some(_guard) = reentry::enter() else {
+        // nested call, e.g. from a malloc interposer while we were wrapping
         return unsafe { __real_pthread_create(tid, attr, start_routine, arg) };
+    };
+    unsafe {
+        create_wrapped(tid, attr, start_routine, arg, |start, arg| {
+            __real_pthread_create(tid, attr, start, arg)
+        })
     }
+}
+
+/// Starts a thread running `start_routine(arg)`, with the creator's context if there is
+/// one to carry over. `real` does the actual creation, given the routine and argument to
+/// start the thread with. `tid` is where the new thread's `pthread_t` ends up, or null
+/// when the thread isn't joined through `pthread_join`.
+unsafe fn create_wrapped(
+    #[cfg_attr(not(feature = "hook-join"), allow(unused_variables))] tid: *mut pthread_t,
+    attr: *const pthread_attr_t,
+    start_routine: StartRoutine,
+    arg: *mut c_void,
+    real: impl FnOnce(StartRoutine, *mut c_void) -> i32,
+) -> i32 {
+    // nothing below is safe to do in a signal handler
+    #[cfg(feature = "hook-signal")]
+    if signal::in_handler() {
+        return signal::create(start_routine, arg, real);
+    }
+    let start = metrics::sample().then(Instant::now);
+    // noted for the exporter until it's installed
+    #[cfg(feature = "otlp")]
+    let real = early::recording(start_routine, real);
 
-    // 2. box up the real fn, its arg, and our Context
-    let launch = Box::new(Launch {
+    // if no context, just call the original pthread_create
+    // This is a fast path to avoid unnecessary overhead when no context is active: it
+    // only peeks at the current Context, without cloning or allocating anything.
+    // With a span filter configured, only spans that matched it count as active, and
+    // with OTEL_POSIX_PROP_SAMPLED_ONLY only sampled ones.
+    // Threads started by the OTEL SDK itself run with telemetry suppressed.
+    // Registered embedder hooks, a deadline, or a current `tracing` span, want the
+    // thread even without an OTEL span, and so does auto-root mode when no span is
+    // active at all.
+    // The entry-point lists are checked last, dladdr being the costliest step, and
+    // the rate limit after them, so that filtered threads don't use up its tokens.
+    let (eligible, traced, auto_root) = if suppress::is_suppressed() {
+        (false, false, false)
+    } else {
+        Context::map_current(|cx| {
+            let traced = cx.has_active_span()
+                && (!config::config().sampled_only || cx.span().span_context().is_sampled())
+                && (config::config().span_filter.is_none()
+                    || filter::is_matching(cx.span().span_context().span_id()));
+            let auto_root = !cx.has_active_span() && auto_root::enabled();
+            let eligible = !cx.is_telemetry_suppressed()
+                && (traced
+                    || auto_root
+                    || hooks::any()
+                    || deadline::of(cx).is_some()
+                    || tracing_active());
+            (eligible, traced, auto_root)
+        })
+    };
+    let wrap = eligible
+        && config::config().hooks.pthread_create
+        && !sanitizer::blocks_wrapping()
+        && config::config()
+            .entry_filter
+            .as_ref()
+            .is_none_or(|f| entry::allows_cached(f, start_routine as *const c_void))
+        && caller_allowed();
+    let limited = wrap
+        && config::config()
+            .wrap_rate
+            .is_some_and(|rate| !WRAP_LIMIT.try_take(rate));
+    if !wrap || limited {
+        metrics::thread_passed_through();
+        if limited {
+            metrics::rate_limited();
+        }
+        if recorder::enabled() {
+            let reason = if limited {
+                recorder::Action::RateLimited
+            } else {
+                pass_reason(eligible, start_routine)
+            };
+            recorder::record(reason, current_span_id());
+        }
+        return real(start_routine, arg);
+    }
+
+    // 1. capture the current OTEL Context, and whatever the hooks want to carry over
+    let mut cx = traced.then(Context::current);
+    #[cfg(feature = "hook-join")]
+    let join = cx
+        .as_ref()
+        .filter(|_| !tid.is_null())
+        .and_then(|cx| join::begin(cx, attr, start_routine));
+    let lifetime = traced && config::config().mode == config::Mode::Lifetime;
+    // the thread gets a span of its own, which can say how and where it was created
+    let own_span = auto_root
+        || (traced
+            && matches!(
+                config::config().mode,
+                config::Mode::Links | config::Mode::Lifetime
+            ));
+    // in attribute-only mode only a note of the creator goes along
+    let origin = if config::config().mode == config::Mode::Attributes {
+        cx.take().map(|cx| origin::Origin::capture(&cx))
+    } else {
+        None
+    };
+    // a context that goes along carries its deadline already
+    let deadline = cx.is_none().then(deadline::current).flatten();
+
+    // 2. put the real fn, its arg, and our Context into a launcher
+    let launch = LAUNCHES.put(Launch {
         real_fn: start_routine,
         real_arg: arg,
         ctx: cx,
+        hooks: hooks::capture(),
+        #[cfg(feature = "tracing")]
+        tracing: tracing_span::capture(),
+        created: lifetime.then(SystemTime::now),
+        origin,
+        deadline,
+        auto_root,
+        #[cfg(feature = "hook-join")]
+        join: join.clone(),
+        requested: own_span.then(|| attr::Requested::read(attr)).flatten(),
+        #[cfg(feature = "creation-stack")]
+        stack: own_span.then(stack::Creation::capture),
     });
 
-    // 3. invoke it with our trampoline + boxed launcher
-    unsafe { __real_pthread_create(tid, attr, trampoline, Box::into_raw(launch) as *mut c_void) }
+    // 3. invoke it with our trampoline + the launcher
+    if let Some(start) = start {
+        metrics::record_overhead(metrics::Phase::Create, start.elapsed());
+    }
+    let rc = real(trampoline, launch as *mut c_void);
+    if rc != 0 {
+        // the thread never started, so the launcher is still ours to free
+        drop(unsafe { LAUNCHES.take(launch) });
+        metrics::wrap_failed();
+        if recorder::enabled() {
+            recorder::record(recorder::Action::Failed, current_span_id());
+        }
+    } else if !traced && !auto_root {
+        metrics::thread_passed_through();
+        recorder::record(recorder::Action::Hooked, None);
+    } else {
+        metrics::thread_wrapped();
+        if recorder::enabled() {
+            recorder::record(recorder::Action::Wrapped, current_span_id());
+        }
+        #[cfg(feature = "hook-join")]
+        if let Some(thread) = join {
+            join::track(unsafe { *tid }, thread);
+        }
+    }
+    rc
+}
+
+/// Whether the object calling into the shim may have its threads wrapped.
+fn caller_allowed() -> bool {
+    #[cfg(feature = "preload")]
+    let allowed = config::config()
+        .caller_filter
+        .as_ref()
+        .is_none_or(caller::CallerFilter::allows_caller);
+    #[cfg(not(feature = "preload"))]
+    let allowed = true;
+    allowed
+}
+
+/// Why `create_wrapped` passed a thread starting in `start_routine` through, worked out
+/// again for the flight recorder so the fast path doesn't have to keep track. `eligible`
+/// is what it decided before looking at the hook switch and the filters.
+fn pass_reason(eligible: bool, start_routine: StartRoutine) -> recorder::Action {
+    if eligible {
+        let entry_allowed = config::config()
+            .entry_filter
+            .as_ref()
+            .is_none_or(|f| entry::allows_cached(f, start_routine as *const c_void));
+        return if !config::config().hooks.pthread_create {
+            recorder::Action::Disabled
+        } else if sanitizer::blocks_wrapping() {
+            recorder::Action::Sanitizer
+        } else if !entry_allowed {
+            recorder::Action::EntryFiltered
+        } else {
+            recorder::Action::CallerFiltered
+        };
+    }
+    if suppress::is_suppressed() {
+        return recorder::Action::Suppressed;
+    }
+    Context::map_current(|cx| {
+        if cx.is_telemetry_suppressed() {
+            recorder::Action::Suppressed
+        } else if cx.has_active_span()
+            && config::config().sampled_only
+            && !cx.span().span_context().is_sampled()
+        {
+            recorder::Action::Unsampled
+        } else if cx.has_active_span() {
+            recorder::Action::SpanFiltered
+        } else {
+            recorder::Action::NoSpan
+        }
+    })
+}
+
+/// The id of the span active on this thread, if any.
+fn current_span_id() -> Option<opentelemetry::trace::SpanId> {
+    Context::map_current(|cx| {
+        cx.has_active_span()
+            .then(|| cx.span().span_context().span_id())
+    })
+}
+
+// a `tracing` span is worth carrying over on its own, too
+#[cfg(feature = "tracing")]
+use tracing_span::active as tracing_active;
+
+#[cfg(not(feature = "tracing"))]
+fn tracing_active() -> bool {
+    false
 }
 
+// resolved by the linker to the original pthread_create under --wrap
+#[cfg(feature = "linker-wrap")]
 unsafe extern "C" {
     fn __real_pthread_create(
         tid: *mut pthread_t,
         attr: *const pthread_attr_t,
-        start_routine: extern "C" fn(*mut c_void) -> *mut c_void,
+        start_routine: StartRoutine,
         arg: *mut c_void,
     ) -> i32;
 }
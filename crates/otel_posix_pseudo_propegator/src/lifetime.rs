@@ -0,0 +1,96 @@
+// src/lifetime.rs
+//
+// Lifetime mode: every wrapped thread gets a `thread` span, parented under
+// its creator, that starts at the pthread_create call and ends when the
+// thread exits. The trampoline ends it when the start routine returns; a
+// thread leaving through pthread_exit (or cancellation) skips that, so the
+// span is also parked in a thread-local whose destructor ends it then.
+// Either way, the thread's resource usage goes on the span as it ends (see
+// rusage.rs), along with its scheduling (sched.rs), and so does its time in
+// poll and epoll_wait if that is being measured (poll.rs).
+
+use crate::rusage::{self, Usage};
+use crate::{entry, scope};
+use crate::{poll, sched};
+use opentelemetry::trace::{SpanKind, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue};
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::panic::AssertUnwindSafe;
+use std::time::SystemTime;
+
+/// Ends the span of the context it holds when dropped, with the thread's usage since
+/// `started`.
+struct ThreadSpan {
+    cx: Context,
+    started: Option<Usage>,
+}
+
+impl Drop for ThreadSpan {
+    fn drop(&mut self) {
+        // may run as a TLS destructor, where the SDK's own thread-locals can already be
+        // gone; a panic there would abort the process
+        let ThreadSpan { cx, started } = self;
+        let _ = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            let span = cx.span();
+            if let Some(usage) = rusage::thread() {
+                span.set_attributes(rusage::attributes(*started, usage));
+            }
+            span.set_attributes(sched::thread_attributes());
+            span.set_attributes(poll::thread_attributes());
+            span.end();
+        }));
+    }
+}
+
+thread_local! {
+    static THREAD_SPAN: RefCell<Option<ThreadSpan>> = const { RefCell::new(None) };
+}
+
+/// Builds the context a thread starting in `start_routine` runs under in lifetime mode:
+/// the creator's context with a new child span that started at `created`. The span ends at
+/// [`thread_returned`] or, failing that, when the thread's locals are destroyed.
+pub(crate) fn thread_context(
+    creator: &Context,
+    created: SystemTime,
+    start_routine: *const c_void,
+) -> Context {
+    let tracer = scope::tracer();
+    let mut attributes = vec![KeyValue::new(
+        "thread.id",
+        i64::from(unsafe { libc::gettid() }),
+    )];
+    attributes.extend(entry::code_attributes(start_routine));
+    let span = tracer
+        .span_builder("thread")
+        .with_kind(SpanKind::Internal)
+        .with_start_time(created)
+        .with_attributes(attributes)
+        .start_with_context(&tracer, creator);
+    let cx = creator.with_span(span);
+    end_with_thread(&cx);
+    cx
+}
+
+/// Has the span of `cx` ended at [`thread_returned`] or, failing that, when the calling
+/// thread's locals are destroyed.
+pub(crate) fn end_with_thread(cx: &Context) {
+    // thread-locals are destroyed in reverse order of first use, so touching the current
+    // context first keeps it alive for ending the span
+    Context::map_current(|_| ());
+    crate::reentry::watch();
+    let span = ThreadSpan {
+        cx: cx.clone(),
+        started: rusage::thread(),
+    };
+    THREAD_SPAN.with(|s| *s.borrow_mut() = Some(span));
+}
+
+/// Ends the thread's span once its start routine has returned normally.
+pub(crate) fn thread_returned() {
+    let span = THREAD_SPAN
+        .try_with(|s| s.borrow_mut().take())
+        .ok()
+        .flatten();
+    drop(span);
+}
@@ -0,0 +1,27 @@
+// src/links.rs
+//
+// Span-links mode: instead of re-parenting a wrapped thread under its
+// creator, give it a root span of its own with a Link back to the creating
+// span. Long-lived workers then show up as their own traces rather than as
+// one enormous parent tree.
+
+use crate::{entry, scope};
+use opentelemetry::Context;
+use opentelemetry::trace::{Link, SpanKind, TraceContextExt, Tracer};
+use std::ffi::c_void;
+
+/// Builds the context a thread starting in `start_routine` runs under in links mode: the
+/// creator's context (keeping its baggage and other values) with its span replaced by a
+/// new root span.
+pub(crate) fn thread_root_context(creator: &Context, start_routine: *const c_void) -> Context {
+    let tracer = scope::tracer();
+    let link = Link::with_context(creator.span().span_context().clone());
+    let span = tracer
+        .span_builder("thread")
+        .with_kind(SpanKind::Internal)
+        .with_links(vec![link])
+        .with_attributes(entry::code_attributes(start_routine))
+        // an empty parent makes this a root span
+        .start_with_context(&tracer, &Context::new());
+    creator.with_span(span)
+}
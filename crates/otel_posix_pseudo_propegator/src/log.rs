@@ -0,0 +1,140 @@
+// src/log.rs
+//
+// The shim's own diagnostics. They go to stderr of whatever process we are
+// preloaded into, so operators can turn them off with
+// OTEL_POSIX_PROP_LOG=off, and a warning repeated for every thread of a
+// storm is held to OTEL_POSIX_PROP_LOG_RATE a second.
+//
+// OTEL_POSIX_PROP_REDACT_IDS=true keeps trace and span ids out of
+// everything the shim writes for people to read: the fatal-signal line,
+// the thread dump and the flight recorder's events, on stderr, in files and
+// over the control socket alike. The crash table (crashdump.rs) is left
+// as it is, being there for a debugger rather than written anywhere.
+
+use crate::config::parse_bool;
+use crate::config_file;
+use crate::ratelimit::{TokenBucket, parse_rate};
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+
+// OTEL_POSIX_PROP_LOG, once read
+static ENABLED: AtomicU8 = AtomicU8::new(UNSET);
+
+// warnings a second when OTEL_POSIX_PROP_LOG_RATE isn't set
+const DEFAULT_RATE: u32 = 10;
+// OTEL_POSIX_PROP_LOG_RATE, once read
+const UNREAD: u64 = u64::MAX;
+static RATE: AtomicU64 = AtomicU64::new(UNREAD);
+static LIMIT: TokenBucket = TokenBucket::new();
+// warnings held back since the last one printed
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+
+// set at runtime through the control channel, overriding OTEL_POSIX_PROP_LOG
+const UNSET: u8 = 0;
+const ON: u8 = 1;
+const OFF: u8 = 2;
+static OVERRIDE: AtomicU8 = AtomicU8::new(UNSET);
+
+// OTEL_POSIX_PROP_REDACT_IDS, once read
+static REDACT: AtomicU8 = AtomicU8::new(UNSET);
+
+/// What stands in for a redacted id.
+pub(crate) const REDACTED: &str = "redacted";
+
+/// Whether warnings are printed. Read separately from the main config, which reports
+/// its own parse problems through [`log_warn!`].
+pub(crate) fn enabled() -> bool {
+    match OVERRIDE.load(Ordering::Relaxed) {
+        ON => true,
+        OFF => false,
+        _ => match ENABLED.load(Ordering::Relaxed) {
+            ON => true,
+            OFF => false,
+            _ => {
+                let on = config_file::var("OTEL_POSIX_PROP_LOG")
+                    .is_none_or(|v| !v.trim().eq_ignore_ascii_case("off"));
+                ENABLED.store(if on { ON } else { OFF }, Ordering::Relaxed);
+                on
+            }
+        },
+    }
+}
+
+/// Whether a warning may be printed now under the rate limit. The first one printed
+/// after some were held back says how many.
+#[cfg_attr(feature = "minimal", allow(dead_code))]
+pub(crate) fn admit() -> bool {
+    let rate = match RATE.load(Ordering::Relaxed) {
+        UNREAD => {
+            let rate = config_file::var("OTEL_POSIX_PROP_LOG_RATE")
+                .and_then(|v| parse_rate("OTEL_POSIX_PROP_LOG_RATE", &v))
+                .unwrap_or(DEFAULT_RATE);
+            RATE.store(rate.into(), Ordering::Relaxed);
+            rate
+        }
+        rate => rate as u32,
+    };
+    if !LIMIT.try_take(rate) {
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+        return false;
+    }
+    let dropped = DROPPED.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        eprintln!("otel_posix_pseudo_propegator: {dropped} warnings dropped by the rate limit");
+    }
+    true
+}
+
+/// Turns warnings on or off from now on, whatever `OTEL_POSIX_PROP_LOG` says.
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+pub(crate) fn set_enabled(on: bool) {
+    OVERRIDE.store(if on { ON } else { OFF }, Ordering::Relaxed);
+}
+
+/// Whether trace and span ids are left out of what the shim writes. Once read, only an
+/// atomic load, which the crash handler can afford.
+pub(crate) fn redact_ids() -> bool {
+    match REDACT.load(Ordering::Relaxed) {
+        ON => true,
+        OFF => false,
+        _ => read_redact_ids(),
+    }
+}
+
+fn read_redact_ids() -> bool {
+    let on = config_file::var("OTEL_POSIX_PROP_REDACT_IDS").is_some_and(|v| parse_bool(&v));
+    REDACT.store(if on { ON } else { OFF }, Ordering::Relaxed);
+    on
+}
+
+/// Forgets `OTEL_POSIX_PROP_LOG` and `OTEL_POSIX_PROP_LOG_RATE`, to be read again on
+/// the next warning. An override from the control channel stays.
+/// `OTEL_POSIX_PROP_REDACT_IDS` is read again right away, not in a signal handler.
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+pub(crate) fn reload() {
+    ENABLED.store(UNSET, Ordering::Relaxed);
+    RATE.store(UNREAD, Ordering::Relaxed);
+    read_redact_ids();
+}
+
+/// Prints a warning prefixed with the crate name, unless logging is off or over its rate.
+#[cfg(not(feature = "minimal"))]
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled() && $crate::log::admit() {
+            eprintln!("otel_posix_pseudo_propegator: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+/// Minimal builds print nothing, and leave the formatting out; the arguments are still
+/// type-checked.
+#[cfg(feature = "minimal")]
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use log_warn;
@@ -0,0 +1,176 @@
+// src/logs.rs
+//
+// C-callable log records, for the workspace's log bridge (otel_log_bridge),
+// which forwards what legacy programs write to stderr. Like the span
+// functions in spans.rs, it has no SDK of its own to record with.
+//
+// The API has no global logger provider, so the shim keeps the one the
+// records go to here: the OTLP one the constructor installs when
+// OTEL_LOGS_EXPORTER=otlp asks for it (init.rs), or the in-memory one of
+// test_support. The shim logs nothing itself, so by default there is none
+// and records are dropped.
+//
+// A record is put with the span its traceparent names, or with the calling
+// thread's current span, so a backend can show it in its trace.
+
+use crate::reentry;
+use crate::spans::{OtelPosixAttribute, key_values};
+use crate::w3c::parse_traceparent;
+use libc::{c_char, c_int, size_t};
+use opentelemetry::Value;
+use opentelemetry::logs::{AnyValue, LogRecord, Logger, Severity};
+use opentelemetry_sdk::logs::{SdkLogger, SdkLoggerProvider};
+use std::ffi::CStr;
+use std::sync::{PoisonError, RwLock};
+use std::time::{Duration, SystemTime};
+
+// the provider the records go to, and its logger for the shim's scope
+static LOGGER: RwLock<Option<(SdkLoggerProvider, SdkLogger)>> = RwLock::new(None);
+
+/// Makes `provider` the one records go to, or drops them from now on if it is `None`.
+/// Returns the provider it replaces.
+#[cfg(any(feature = "otlp", feature = "test-support"))]
+pub(crate) fn install(provider: Option<SdkLoggerProvider>) -> Option<SdkLoggerProvider> {
+    let logger = provider.map(|p| {
+        let logger = crate::scope::logger(&p);
+        (p, logger)
+    });
+    let mut installed = LOGGER.write().unwrap_or_else(PoisonError::into_inner);
+    std::mem::replace(&mut *installed, logger).map(|(p, _)| p)
+}
+
+// the OTel data model's severity numbers, from 1
+const SEVERITIES: [Severity; 24] = [
+    Severity::Trace,
+    Severity::Trace2,
+    Severity::Trace3,
+    Severity::Trace4,
+    Severity::Debug,
+    Severity::Debug2,
+    Severity::Debug3,
+    Severity::Debug4,
+    Severity::Info,
+    Severity::Info2,
+    Severity::Info3,
+    Severity::Info4,
+    Severity::Warn,
+    Severity::Warn2,
+    Severity::Warn3,
+    Severity::Warn4,
+    Severity::Error,
+    Severity::Error2,
+    Severity::Error3,
+    Severity::Error4,
+    Severity::Fatal,
+    Severity::Fatal2,
+    Severity::Fatal3,
+    Severity::Fatal4,
+];
+
+/// The severity numbered `number`; 0, unspecified, and numbers out of range have none.
+fn severity(number: c_int) -> Option<Severity> {
+    SEVERITIES
+        .get(usize::try_from(number).ok()?.checked_sub(1)?)
+        .copied()
+}
+
+fn any_value(value: Value) -> AnyValue {
+    match value {
+        Value::I64(i) => AnyValue::Int(i),
+        Value::F64(f) => AnyValue::Double(f),
+        Value::Bool(b) => AnyValue::Boolean(b),
+        Value::String(s) => AnyValue::String(s),
+        other => AnyValue::from(other.as_str().into_owned()),
+    }
+}
+
+/// Records a log record with the message `body`, written at `time`, in nanoseconds since
+/// the Unix epoch, with the `count` attributes at `attributes`. `severity` is one of the
+/// OTel data model's severity numbers, from 1 (TRACE) to 24 (FATAL4), or 0 for none. The
+/// record goes with the span the traceparent `parent` names, or the calling thread's
+/// current span when `parent` is NULL.
+///
+/// Returns 0, or -1 if `body` is NULL, `parent` isn't a valid traceparent or no logger
+/// provider is installed.
+///
+/// # Safety
+///
+/// `parent` and `body` must be null or point to NUL-terminated strings, and `attributes`
+/// must be null or point to `count` attributes whose strings are NUL-terminated or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn otel_posix_emit_log(
+    parent: *const c_char,
+    severity_number: c_int,
+    body: *const c_char,
+    time: u64,
+    attributes: *const OtelPosixAttribute,
+    count: size_t,
+) -> c_int {
+    let Some(_guard) = reentry::enter() else {
+        return -1;
+    };
+    if body.is_null() {
+        return -1;
+    }
+    let parent = if parent.is_null() {
+        // the SDK takes the current span's
+        None
+    } else {
+        let parent = unsafe { CStr::from_ptr(parent) }.to_str().ok();
+        match parent.and_then(parse_traceparent) {
+            Some(sc) => Some(sc),
+            None => return -1,
+        }
+    };
+    // held while the record is emitted, which only queues it
+    let installed = LOGGER.read().unwrap_or_else(PoisonError::into_inner);
+    let Some((_, logger)) = installed.as_ref() else {
+        return -1;
+    };
+    let mut record = logger.create_log_record();
+    record.set_timestamp(SystemTime::UNIX_EPOCH + Duration::from_nanos(time));
+    if let Some(severity) = severity(severity_number) {
+        record.set_severity_number(severity);
+        record.set_severity_text(severity.name());
+    }
+    record.set_body(AnyValue::from(
+        unsafe { CStr::from_ptr(body) }
+            .to_string_lossy()
+            .into_owned(),
+    ));
+    if let Some(sc) = parent {
+        record.set_trace_context(sc.trace_id(), sc.span_id(), Some(sc.trace_flags()));
+    }
+    record.add_attributes(
+        unsafe { key_values(attributes, count) }
+            .into_iter()
+            .map(|kv| (kv.key, any_value(kv.value))),
+    );
+    logger.emit(record);
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn severities_are_numbered_from_one() {
+        assert_eq!(severity(0), None);
+        assert_eq!(severity(1), Some(Severity::Trace));
+        assert_eq!(severity(9), Some(Severity::Info));
+        assert_eq!(severity(17), Some(Severity::Error));
+        assert_eq!(severity(24), Some(Severity::Fatal4));
+        assert_eq!(severity(25), None);
+        assert_eq!(severity(-1), None);
+    }
+
+    #[test]
+    fn a_record_needs_a_body_and_a_valid_parent() {
+        let emit = |parent: *const c_char, body: *const c_char| unsafe {
+            otel_posix_emit_log(parent, 9, body, 1, std::ptr::null(), 0)
+        };
+        assert_eq!(emit(std::ptr::null(), std::ptr::null()), -1);
+        assert_eq!(emit(c"not a traceparent".as_ptr(), c"line".as_ptr()), -1);
+    }
+}
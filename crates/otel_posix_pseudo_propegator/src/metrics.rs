@@ -0,0 +1,202 @@
+// src/metrics.rs
+//
+// Counters and an overhead histogram describing what the interposer is doing
+// in this process. Counts live in atomics so the pthread_create hot path never
+// touches the metrics SDK; they are reported through observable counters once
+// a meter has been registered (by the load-time constructor or by the host).
+// The per-thread poll and epoll_wait times (poll.rs) are reported the same way,
+// and so is whether the preloaded pthread_create is the one bound (selftest.rs).
+// The overhead histogram is sampled, at most OVERHEAD_SAMPLES a second, so a
+// thread storm doesn't turn into as many histogram updates.
+
+use crate::ratelimit::TokenBucket;
+use opentelemetry::KeyValue;
+#[cfg(feature = "preload")]
+use opentelemetry::metrics::ObservableGauge;
+use opentelemetry::metrics::{Histogram, Meter, ObservableCounter};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+static WRAPPED: AtomicU64 = AtomicU64::new(0);
+static PASSED_THROUGH: AtomicU64 = AtomicU64::new(0);
+static WRAP_FAILURES: AtomicU64 = AtomicU64::new(0);
+static RATE_LIMITED: AtomicU64 = AtomicU64::new(0);
+
+const OVERHEAD_SAMPLES: u32 = 1000;
+static SAMPLES: TokenBucket = TokenBucket::new();
+
+static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+
+struct Instruments {
+    overhead: Histogram<f64>,
+    // kept alive for the lifetime of the process so their callbacks stay registered
+    _counters: [ObservableCounter<u64>; 4],
+    _poll: [ObservableCounter<f64>; 2],
+    #[cfg(feature = "preload")]
+    _interposition: ObservableGauge<u64>,
+}
+
+/// Where in the wrapping the overhead was measured.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Phase {
+    /// Capturing the context and boxing the launcher, in the creating thread.
+    Create,
+    /// Attaching the context in the new thread, before the real entry point runs.
+    Start,
+}
+
+impl Phase {
+    fn as_str(self) -> &'static str {
+        match self {
+            Phase::Create => "create",
+            Phase::Start => "start",
+        }
+    }
+}
+
+/// Snapshot of the interposition counters.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counts {
+    /// Threads started through the trampoline with a captured context.
+    pub wrapped: u64,
+    /// Threads handed straight to the real `pthread_create` (no active span, or filtered out).
+    pub passed_through: u64,
+    /// Wrapped creations where the real `pthread_create` failed.
+    pub wrap_failures: u64,
+    /// Threads passed through for being over `OTEL_POSIX_PROP_WRAP_RATE`, also counted in
+    /// `passed_through`.
+    pub rate_limited: u64,
+}
+
+/// Returns the interposition counters accumulated so far in this process.
+pub fn counts() -> Counts {
+    Counts {
+        wrapped: WRAPPED.load(Ordering::Relaxed),
+        passed_through: PASSED_THROUGH.load(Ordering::Relaxed),
+        wrap_failures: WRAP_FAILURES.load(Ordering::Relaxed),
+        rate_limited: RATE_LIMITED.load(Ordering::Relaxed),
+    }
+}
+
+/// Publishes the interposition metrics through `meter`.
+///
+/// The shim does this itself when it installs its own exporter; hosts with their own
+/// meter provider can call it once at startup. Later calls are ignored.
+pub fn register_metrics(meter: &Meter) {
+    INSTRUMENTS.get_or_init(|| {
+        let counter =
+            |name: &'static str, description: &'static str, source: &'static AtomicU64| {
+                meter
+                    .u64_observable_counter(name)
+                    .with_description(description)
+                    .with_unit("{thread}")
+                    .with_callback(move |observer| {
+                        observer.observe(source.load(Ordering::Relaxed), &[])
+                    })
+                    .build()
+            };
+        // seconds by thread name, from poll.rs
+        let poll_time = |name: &'static str, description: &'static str, busy: bool| {
+            meter
+                .f64_observable_counter(name)
+                .with_description(description)
+                .with_unit("s")
+                .with_callback(move |observer| {
+                    crate::poll::totals(|thread, wait, busy_time| {
+                        let value = if busy { busy_time } else { wait };
+                        observer.observe(value, &[KeyValue::new("thread.name", thread.to_string())])
+                    })
+                })
+                .build()
+        };
+        Instruments {
+            overhead: meter
+                .f64_histogram("otel_posix.trampoline.overhead")
+                .with_description("Time spent in the interposer on top of the real thread creation")
+                .with_unit("s")
+                .with_boundaries(vec![
+                    1e-7, 2.5e-7, 5e-7, 1e-6, 2.5e-6, 5e-6, 1e-5, 1e-4, 1e-3,
+                ])
+                .build(),
+            _counters: [
+                counter(
+                    "otel_posix.threads.wrapped",
+                    "Threads started with the creator's context attached",
+                    &WRAPPED,
+                ),
+                counter(
+                    "otel_posix.threads.passed_through",
+                    "Threads created without an active span and left untouched",
+                    &PASSED_THROUGH,
+                ),
+                counter(
+                    "otel_posix.wrap.failures",
+                    "Wrapped thread creations rejected by the real pthread_create",
+                    &WRAP_FAILURES,
+                ),
+                counter(
+                    "otel_posix.threads.rate_limited",
+                    "Threads passed through for being over the wrapping rate limit",
+                    &RATE_LIMITED,
+                ),
+            ],
+            _poll: [
+                poll_time(
+                    "otel_posix.thread.poll.wait_time",
+                    "Time threads spent blocked in epoll_wait or poll",
+                    false,
+                ),
+                poll_time(
+                    "otel_posix.thread.poll.busy_time",
+                    "Time threads spent between returning from epoll_wait or poll and calling it again",
+                    true,
+                ),
+            ],
+            #[cfg(feature = "preload")]
+            _interposition: meter
+                .u64_observable_gauge("otel_posix.interposition.effective")
+                .with_description("Whether the process's pthread_create binds to the shim's")
+                .with_callback(|observer| {
+                    if let Some(binding) = crate::selftest::binding() {
+                        observer.observe(
+                            binding.is_effective().into(),
+                            &[KeyValue::new("otel_posix.binding", binding.as_str())],
+                        )
+                    }
+                })
+                .build(),
+        }
+    });
+}
+
+/// Whether to measure the overhead of this creation, so callers can skip reading the
+/// clock: only when it is being recorded, and the sample rate allows.
+pub(crate) fn sample() -> bool {
+    INSTRUMENTS.get().is_some() && SAMPLES.try_take(OVERHEAD_SAMPLES)
+}
+
+pub(crate) fn thread_wrapped() {
+    WRAPPED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn thread_passed_through() {
+    PASSED_THROUGH.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn rate_limited() {
+    RATE_LIMITED.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn wrap_failed() {
+    WRAP_FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+pub(crate) fn record_overhead(phase: Phase, elapsed: Duration) {
+    if let Some(instruments) = INSTRUMENTS.get() {
+        instruments.overhead.record(
+            elapsed.as_secs_f64(),
+            &[KeyValue::new("otel_posix.phase", phase.as_str())],
+        );
+    }
+}
@@ -0,0 +1,211 @@
+// src/mqueue.rs
+//
+// Opt-in: traceparent trailers on POSIX message queues. Two preloaded
+// processes talking over an mqueue have no headers to carry a context in,
+// so with the `mqueue-inject` feature mq_send and mq_timedsend append the
+// sending span's traceparent after the message, and mq_receive and
+// mq_timedreceive strip it off again and seed it on the receiving thread,
+// the way otel_posix_set_traceparent does, until the next message.
+//
+// Both ends have to be built with it: a receiver without the shim would see
+// the trailer as part of the message. A message only gets one when the
+// queue's mq_msgsize leaves room for it, so a send never fails for it, and
+// only a trailer that parses back into a traceparent is stripped.
+
+use crate::config::config;
+use crate::w3c::{TRACEPARENT_LEN, format_traceparent, parse_traceparent};
+use crate::{ffi, reentry};
+use libc::{c_char, c_int, c_uint, mqd_t, size_t, ssize_t, timespec};
+use opentelemetry::Context;
+use opentelemetry::trace::{SpanContext, TraceContextExt};
+use std::cell::Cell;
+
+type SendFn = unsafe extern "C" fn(mqd_t, *const c_char, size_t, c_uint) -> c_int;
+type TimedSendFn =
+    unsafe extern "C" fn(mqd_t, *const c_char, size_t, c_uint, *const timespec) -> c_int;
+type ReceiveFn = unsafe extern "C" fn(mqd_t, *mut c_char, size_t, *mut c_uint) -> ssize_t;
+type TimedReceiveFn =
+    unsafe extern "C" fn(mqd_t, *mut c_char, size_t, *mut c_uint, *const timespec) -> ssize_t;
+
+// what a trailer starts with, ahead of the traceparent itself
+const MARKER: &[u8] = b"\0traceparent:";
+const TRAILER_LEN: usize = MARKER.len() + TRACEPARENT_LEN;
+
+thread_local! {
+    // whether this thread's seeded context came from a message, to be dropped when the
+    // next message has none
+    static FROM_QUEUE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// `msg` with the trailer for `sc` appended, if `mq_msgsize` leaves room for it.
+fn with_trailer(msg: &[u8], sc: &SpanContext, msgsize: usize) -> Option<Vec<u8>> {
+    if msg.len() + TRAILER_LEN > msgsize {
+        return None;
+    }
+    let mut out = Vec::with_capacity(msg.len() + TRAILER_LEN);
+    out.extend_from_slice(msg);
+    out.extend_from_slice(MARKER);
+    out.extend_from_slice(format_traceparent(sc).as_bytes());
+    Some(out)
+}
+
+/// The length of the message in `received` without its trailer, and the context the
+/// trailer carried, if it ends in one.
+fn strip_trailer(received: &[u8]) -> Option<(usize, SpanContext)> {
+    let len = received.len().checked_sub(TRAILER_LEN)?;
+    let (marker, traceparent) = received[len..].split_at(MARKER.len());
+    if marker != MARKER {
+        return None;
+    }
+    let sc = parse_traceparent(std::str::from_utf8(traceparent).ok()?)?;
+    Some((len, sc))
+}
+
+/// The context to send along, if there's a span worth propagating.
+fn outgoing() -> Option<SpanContext> {
+    if !config().hooks.mqueue {
+        return None;
+    }
+    Context::map_current(|cx| {
+        let sc = cx.span().span_context().clone();
+        (sc.is_valid() && !cx.is_telemetry_suppressed()).then_some(sc)
+    })
+}
+
+fn msgsize(mqdes: mqd_t) -> Option<usize> {
+    let mut attr: libc::mq_attr = unsafe { std::mem::zeroed() };
+    (unsafe { libc::mq_getattr(mqdes, &mut attr) } == 0).then_some(attr.mq_msgsize as usize)
+}
+
+/// Sends through `raw`, with a trailer if there's a context to carry and room for it.
+fn send_with(
+    mqdes: mqd_t,
+    msg: *const c_char,
+    len: size_t,
+    raw: impl Fn(*const c_char, size_t) -> c_int,
+) -> c_int {
+    let Some(_guard) = reentry::enter() else {
+        return raw(msg, len);
+    };
+    let trailed = (|| {
+        if msg.is_null() {
+            return None;
+        }
+        let sc = outgoing()?;
+        let bytes = unsafe { std::slice::from_raw_parts(msg.cast::<u8>(), len) };
+        with_trailer(bytes, &sc, msgsize(mqdes)?)
+    })();
+    match trailed {
+        Some(out) => raw(out.as_ptr().cast(), out.len()),
+        None => raw(msg, len),
+    }
+}
+
+/// Receives through `raw`, then strips a trailer off the message and seeds its context.
+fn receive_with(msg: *mut c_char, raw: impl FnOnce() -> ssize_t) -> ssize_t {
+    let n = raw();
+    if n <= 0 || msg.is_null() {
+        return n;
+    }
+    let Some(_guard) = reentry::enter() else {
+        return n;
+    };
+    if !config().hooks.mqueue {
+        return n;
+    }
+    let errno = unsafe { *libc::__errno_location() };
+    let received = unsafe { std::slice::from_raw_parts(msg.cast::<u8>(), n as usize) };
+    let n = match strip_trailer(received) {
+        Some((len, sc)) => {
+            ffi::seed(Some(sc));
+            FROM_QUEUE.set(true);
+            len as ssize_t
+        }
+        None => {
+            if FROM_QUEUE.replace(false) {
+                ffi::seed(None);
+            }
+            n
+        }
+    };
+    unsafe { *libc::__errno_location() = errno };
+    n
+}
+
+/// `mq_send`, with the current span's traceparent appended to the message.
+pub(crate) unsafe fn mq_send(
+    real: SendFn,
+    mqdes: mqd_t,
+    msg: *const c_char,
+    len: size_t,
+    prio: c_uint,
+) -> c_int {
+    send_with(mqdes, msg, len, |msg, len| unsafe {
+        real(mqdes, msg, len, prio)
+    })
+}
+
+/// `mq_timedsend`, with the current span's traceparent appended to the message.
+pub(crate) unsafe fn mq_timedsend(
+    real: TimedSendFn,
+    mqdes: mqd_t,
+    msg: *const c_char,
+    len: size_t,
+    prio: c_uint,
+    timeout: *const timespec,
+) -> c_int {
+    send_with(mqdes, msg, len, |msg, len| unsafe {
+        real(mqdes, msg, len, prio, timeout)
+    })
+}
+
+/// `mq_receive`, stripping a traceparent trailer and seeding its context.
+pub(crate) unsafe fn mq_receive(
+    real: ReceiveFn,
+    mqdes: mqd_t,
+    msg: *mut c_char,
+    len: size_t,
+    prio: *mut c_uint,
+) -> ssize_t {
+    receive_with(msg, || unsafe { real(mqdes, msg, len, prio) })
+}
+
+/// `mq_timedreceive`, stripping a traceparent trailer and seeding its context.
+pub(crate) unsafe fn mq_timedreceive(
+    real: TimedReceiveFn,
+    mqdes: mqd_t,
+    msg: *mut c_char,
+    len: size_t,
+    prio: *mut c_uint,
+    timeout: *const timespec,
+) -> ssize_t {
+    receive_with(msg, || unsafe { real(mqdes, msg, len, prio, timeout) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TP: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn trailer_round_trips() {
+        let sc = parse_traceparent(TP).unwrap();
+        let sent = with_trailer(b"job 42", &sc, 128).unwrap();
+        assert_eq!(sent.len(), 6 + TRAILER_LEN);
+        let (len, received) = strip_trailer(&sent).unwrap();
+        assert_eq!(&sent[..len], b"job 42");
+        assert_eq!(received, sc);
+    }
+
+    #[test]
+    fn only_where_it_fits_and_parses() {
+        let sc = parse_traceparent(TP).unwrap();
+        assert!(with_trailer(&[0; 60], &sc, 128).is_some());
+        assert!(with_trailer(&[0; 61], &sc, 128).is_none());
+        assert!(strip_trailer(b"job 42").is_none());
+        let mut forged = with_trailer(b"", &sc, 128).unwrap();
+        forged[MARKER.len() + 3] = b'x';
+        assert!(strip_trailer(&forged).is_none());
+    }
+}
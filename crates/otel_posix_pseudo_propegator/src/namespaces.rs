@@ -0,0 +1,206 @@
+// src/namespaces.rs
+//
+// Libraries that don't look pthread_create up in the global scope, where
+// the preloaded one is found first. One opened with RTLD_DEEPBIND binds to
+// its own dependencies ahead of it, libc's among them; one opened with
+// dlmopen into a namespace of its own gets a second copy of libc, and of
+// everything else it needs, with no shim in front of it. The threads either
+// starts would go unwrapped, without a word.
+//
+// The naked dlopen (dlopen.rs) makes RTLD_DEEPBIND calls itself, where the
+// name resolves the same from here, and then points the bindings the new
+// objects made to libc at this library's own functions, as
+// otel_posix_attach() does at attachment. Bindings their own dependencies
+// satisfy are left alone, since that's what the flag was asked for, and
+// threads their constructors start during the call aren't seen.
+//
+// A DEEPBIND call that has to be made by its caller, and a dlmopen into any
+// namespace but the base one, is warned about once: a namespace has its own
+// libc, and the threads it starts are that libc's to create, not this
+// library's (whose context isn't visible from there anyway). Both kinds are
+// counted in the status report.
+
+use crate::log::log_warn;
+use crate::{reentry, resolve};
+use libc::{Lmid_t, c_char, c_int, c_void, dl_phdr_info, size_t};
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+// not in the libc crate
+const RTLD_DEEPBIND: c_int = 0x8;
+
+static REAL_DLMOPEN: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+// loads whose bindings were redirected, and loads left unwrapped
+static DEEPBOUND: AtomicUsize = AtomicUsize::new(0);
+static ISOLATED: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether a dlopen `mode` asks for the object's own dependencies to come first.
+pub(crate) fn is_deepbind(mode: c_int) -> bool {
+    mode & RTLD_DEEPBIND != 0
+}
+
+/// The load addresses of the objects loaded now, in every namespace.
+pub(crate) fn loaded() -> Vec<usize> {
+    unsafe extern "C" fn visit(info: *mut dl_phdr_info, _size: size_t, data: *mut c_void) -> c_int {
+        let bases = unsafe { &mut *data.cast::<Vec<usize>>() };
+        bases.push(unsafe { (*info).dlpi_addr } as usize);
+        0
+    }
+    let mut bases = Vec::new();
+    unsafe { libc::dl_iterate_phdr(Some(visit), (&raw mut bases).cast()) };
+    bases
+}
+
+/// Redirects the bindings of the objects an RTLD_DEEPBIND dlopen of `handle` added to
+/// those `before`, where the object's own scope finds libc's function.
+pub(crate) fn deepbound(handle: *mut c_void, before: &[usize]) {
+    let replace = |name: &CStr| {
+        let found = unsafe { libc::dlsym(handle, name.as_ptr()) };
+        !found.is_null() && resolve::is_libc(found)
+    };
+    let added = |base: usize| !before.contains(&base);
+    if crate::attach::redirect(&added, &replace).is_some_and(|changed| changed > 0) {
+        DEEPBOUND.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts an RTLD_DEEPBIND dlopen of `file` that has to be made by its caller, and warns
+/// about the first.
+pub(crate) fn passed_by(file: *const c_char) {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    ISOLATED.fetch_add(1, Ordering::Relaxed);
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        let name = unsafe { CStr::from_ptr(file) }.to_string_lossy();
+        log_warn!(
+            "{name} was opened with RTLD_DEEPBIND under a name only its caller can resolve; \
+             threads it creates won't be wrapped. Open it by path, or call \
+             otel_posix_attach() after dlopen"
+        );
+    }
+}
+
+/// Counts a dlmopen of `file` into `lmid`, and warns about the first into a namespace
+/// other than the base one.
+fn isolated(lmid: Lmid_t, file: *const c_char) {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    if lmid == libc::LM_ID_BASE {
+        return;
+    }
+    ISOLATED.fetch_add(1, Ordering::Relaxed);
+    if !WARNED.swap(true, Ordering::Relaxed) {
+        let name = if file.is_null() {
+            "the main program".into()
+        } else {
+            unsafe { CStr::from_ptr(file) }.to_string_lossy()
+        };
+        log_warn!(
+            "{name} was opened with dlmopen into a namespace of its own, with a libc of its \
+             own; threads it creates won't be wrapped, and the context of this namespace \
+             isn't visible there"
+        );
+    }
+}
+
+/// RTLD_DEEPBIND loads redirected to this library, and loads whose threads it can't wrap.
+pub(crate) fn counts() -> (usize, usize) {
+    (
+        DEEPBOUND.load(Ordering::Relaxed),
+        ISOLATED.load(Ordering::Relaxed),
+    )
+}
+
+// while this thread is resolving the real dlmopen
+unsafe extern "C" fn unresolved(_: Lmid_t, _: *const c_char, _: c_int) -> *mut c_void {
+    std::ptr::null_mut()
+}
+
+/// Called from the naked `dlmopen` with the caller's arguments: stores the real function
+/// in `real`, after counting a load into another namespace.
+unsafe extern "C" fn route(lmid: Lmid_t, file: *const c_char, real: *mut *const c_void) {
+    let errno = unsafe { *libc::__errno_location() };
+    let target = resolve::cached_next(&REAL_DLMOPEN, c"dlmopen");
+    unsafe { *real = target.map_or(unresolved as *const c_void, |sym| sym.cast_const()) };
+    if let Some(_guard) = reentry::enter() {
+        isolated(lmid, file);
+    }
+    unsafe { *libc::__errno_location() = errno };
+}
+
+/// Interposed `dlmopen` that reports loads into namespaces this library isn't in. Naked,
+/// like `dlopen`, so that names resolve relative to the caller.
+///
+/// # Safety
+///
+/// Same contract as libc's `dlmopen`.
+#[cfg(target_arch = "x86_64")]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dlmopen(lmid: Lmid_t, file: *const c_char, mode: c_int) -> *mut c_void {
+    std::arch::naked_asm!(
+        "push rbp",
+        "mov rbp, rsp",
+        "sub rsp, 32",
+        "mov qword ptr [rsp], rdi",
+        "mov qword ptr [rsp + 8], rsi",
+        "mov qword ptr [rsp + 16], rdx",
+        "lea rdx, [rsp + 24]",
+        "call {route}",
+        "mov rdi, qword ptr [rsp]",
+        "mov rsi, qword ptr [rsp + 8]",
+        "mov rdx, qword ptr [rsp + 16]",
+        "mov r11, qword ptr [rsp + 24]",
+        "leave",
+        "jmp r11",
+        route = sym route,
+    )
+}
+
+/// Interposed `dlmopen` that reports loads into namespaces this library isn't in. Naked,
+/// like `dlopen`, so that names resolve relative to the caller.
+///
+/// # Safety
+///
+/// Same contract as libc's `dlmopen`.
+#[cfg(target_arch = "aarch64")]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn dlmopen(lmid: Lmid_t, file: *const c_char, mode: c_int) -> *mut c_void {
+    std::arch::naked_asm!(
+        "stp x29, x30, [sp, #-48]!",
+        "mov x29, sp",
+        "stp x0, x1, [sp, #16]",
+        "str x2, [sp, #32]",
+        "add x2, sp, #40",
+        "bl {route}",
+        "ldr x16, [sp, #40]",
+        "ldp x0, x1, [sp, #16]",
+        "ldr x2, [sp, #32]",
+        "ldp x29, x30, [sp], #48",
+        "br x16",
+        route = sym route,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_other_namespaces_are_isolated() {
+        let (_, before) = counts();
+        isolated(libc::LM_ID_BASE, c"libm.so.6".as_ptr());
+        assert_eq!(counts().1, before);
+        isolated(libc::LM_ID_NEWLM, c"libm.so.6".as_ptr());
+        assert!(counts().1 > before);
+        assert!(is_deepbind(libc::RTLD_NOW | RTLD_DEEPBIND));
+        assert!(!is_deepbind(libc::RTLD_NOW | libc::RTLD_GLOBAL));
+    }
+
+    #[test]
+    fn loaded_objects_include_this_one() {
+        let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+        let found = unsafe { libc::dladdr(loaded as *const c_void, &mut info) };
+        assert_ne!(found, 0);
+        assert!(loaded().contains(&(info.dli_fbase as usize)));
+    }
+}
@@ -0,0 +1,191 @@
+// src/once.rs
+//
+// One-time initialization. A library's lazy setup (reading its config,
+// building a table, opening a pool) runs in whichever request happens to
+// need it first, and that request is then slow for a reason its trace
+// doesn't show. With OTEL_POSIX_PROP_ONCE_EVENTS on, an init routine that
+// pthread_once runs while a span is recording adds a `pthread_once` event
+// to that span when it is done, naming the routine as thread spans name
+// their start routines (entry.rs), with how long it took. With
+// OTEL_POSIX_PROP_ONCE_SPANS on, the routine also runs under a
+// `pthread_once` span of its own, so what it does (threads it starts,
+// children it spawns) lands beneath it.
+//
+// An init routine takes no argument, so the one to run is left in a
+// thread-local and pthread_once is handed `run` instead, which takes it
+// back out. pthread_once calls it on the calling thread, or not at all
+// when the control is done already or another thread is running it; those
+// calls cost a context lookup.
+//
+// glibc lets an init routine leave by unwinding (a C++ exception, or the
+// thread being cancelled in it), and runs it again on the next call. So
+// pthread_once is written with the C-unwind ABI, outside interpose.rs's
+// table, and the event (marked unwound) and the span are still recorded on
+// the way out.
+//
+// The real pthread_once is looked up by a load-time constructor, so that
+// a call from inside a later symbol lookup (a malloc replacement setting
+// itself up) has it. One made before then fails with EAGAIN.
+
+use crate::config::config;
+use crate::{entry, reentry, resolve, scope};
+use libc::{c_int, pthread_once_t};
+use opentelemetry::trace::{Status, TraceContextExt, Tracer};
+use opentelemetry::{Context, ContextGuard, KeyValue};
+use std::cell::Cell;
+use std::ffi::c_void;
+use std::sync::atomic::AtomicPtr;
+use std::time::Instant;
+
+type InitFn = unsafe extern "C-unwind" fn();
+type PthreadOnceFn = unsafe extern "C-unwind" fn(*mut pthread_once_t, Option<InitFn>) -> c_int;
+
+static REAL_PTHREAD_ONCE: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+thread_local! {
+    // the init routine this thread's pthread_once is about to run, for `run` to take
+    static PENDING: Cell<Option<InitFn>> = const { Cell::new(None) };
+}
+
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+#[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__mod_init_func"))]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(real);
+}
+
+/// The real `pthread_once`, looked up on first use.
+fn real() -> Option<PthreadOnceFn> {
+    resolve::cached_next(&REAL_PTHREAD_ONCE, c"pthread_once")
+        .map(|sym| unsafe { std::mem::transmute::<*mut c_void, PthreadOnceFn>(sym) })
+}
+
+/// Whether an init routine run from the current context is recorded: a span is
+/// recording, and events or spans are on.
+fn recording() -> bool {
+    let recording =
+        Context::map_current(|cx| cx.span().is_recording() && !cx.is_telemetry_suppressed());
+    // the configuration is only read once there's somewhere to record the run
+    recording && {
+        let config = config();
+        config.once_events || config.once_spans
+    }
+}
+
+/// Interposed `pthread_once` that records the init routine's run on the current span.
+///
+/// # Safety
+///
+/// Same contract as libc's `pthread_once`: `control` must point to a `pthread_once_t`
+/// initialized with `PTHREAD_ONCE_INIT`, always used with the same `init`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C-unwind" fn pthread_once(
+    control: *mut pthread_once_t,
+    init: Option<InitFn>,
+) -> c_int {
+    let Some(real) = real() else {
+        return libc::EAGAIN;
+    };
+    let recorded = init.is_some()
+        && !resolve::in_progress()
+        && reentry::enter().is_some_and(|_guard| recording());
+    if !recorded {
+        return unsafe { real(control, init) };
+    }
+    // put back however pthread_once is left
+    let _pending = Pending(PENDING.replace(init));
+    unsafe { real(control, Some(run)) }
+}
+
+/// The init routine an outer `pthread_once` left pending, when this one was called from
+/// an init routine, to put back.
+struct Pending(Option<InitFn>);
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        PENDING.set(self.0);
+    }
+}
+
+/// What `pthread_once` runs in place of the caller's init routine: the pending one,
+/// recorded.
+extern "C-unwind" fn run() {
+    let Some(init) = PENDING.take() else {
+        return;
+    };
+    let mut run = reentry::enter().map(|_guard| Run::start(init));
+    unsafe { init() };
+    if let Some(run) = &mut run {
+        run.returned = true;
+    }
+}
+
+/// An init routine running, recorded when it returns or unwinds.
+struct Run {
+    caller: Context,
+    code: Vec<KeyValue>,
+    events: bool,
+    span: Option<Context>,
+    started: Instant,
+    returned: bool,
+    _attached: Option<ContextGuard>,
+}
+
+impl Run {
+    fn start(init: InitFn) -> Self {
+        let config = config();
+        let caller = Context::current();
+        let code = entry::code_attributes(init as *const c_void);
+        let span = config.once_spans.then(|| {
+            let tracer = scope::tracer();
+            let span = tracer
+                .span_builder("pthread_once")
+                .with_attributes(code.clone())
+                .start_with_context(&tracer, &caller);
+            caller.with_span(span)
+        });
+        Run {
+            _attached: span.clone().map(Context::attach),
+            caller,
+            code,
+            events: config.once_events,
+            span,
+            started: Instant::now(),
+            returned: false,
+        }
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let Some(_guard) = reentry::enter() else {
+            return;
+        };
+        let errno = unsafe { *libc::__errno_location() };
+        if self.events {
+            let mut attributes = std::mem::take(&mut self.code);
+            attributes.push(KeyValue::new(
+                "otel_posix.once.duration",
+                self.started.elapsed().as_secs_f64(),
+            ));
+            if !self.returned {
+                attributes.push(KeyValue::new("otel_posix.once.unwound", true));
+            }
+            self.caller.span().add_event("pthread_once", attributes);
+        }
+        if let Some(cx) = &self.span {
+            let span = cx.span();
+            if !self.returned {
+                span.set_status(Status::error("unwound"));
+            }
+            span.end();
+        }
+        unsafe { *libc::__errno_location() = errno };
+    }
+}
@@ -0,0 +1,154 @@
+// src/origin.rs
+//
+// Attribute-only mode (OTEL_POSIX_PROP_MODE=attributes): for services where
+// changing the trace topology is too big a step, a wrapped thread doesn't
+// get its creator's context attached at all. It only learns who created it
+// (the creator's tid and span, and when) as thread-local data, which C code
+// reads with otel_posix_thread_origin and can log or attach as it sees fit.
+
+use crate::w3c::{TRACEPARENT_LEN, format_traceparent};
+use libc::{c_char, c_int, pid_t, size_t};
+use opentelemetry::Context;
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+use std::cell::Cell;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+thread_local! {
+    // plain data, so the thread-local needs no destructor
+    static ORIGIN: Cell<Option<Origin>> = const { Cell::new(None) };
+}
+
+/// Where a thread came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Origin {
+    tid: pid_t,
+    trace_id: TraceId,
+    span_id: SpanId,
+    flags: TraceFlags,
+    created: SystemTime,
+}
+
+impl Origin {
+    /// The calling thread, about to create another one under `cx`.
+    pub(crate) fn capture(cx: &Context) -> Self {
+        let span = cx.span();
+        let sc = span.span_context();
+        Origin {
+            tid: unsafe { libc::gettid() },
+            trace_id: sc.trace_id(),
+            span_id: sc.span_id(),
+            flags: sc.trace_flags(),
+            created: SystemTime::now(),
+        }
+    }
+
+    /// Thread `tid`, having created another one under `cx` at `created`.
+    #[cfg(feature = "hook-signal")]
+    pub(crate) fn deferred(tid: pid_t, cx: &Context, created: SystemTime) -> Self {
+        Origin {
+            tid,
+            created,
+            ..Origin::capture(cx)
+        }
+    }
+
+    fn span_context(&self) -> SpanContext {
+        SpanContext::new(
+            self.trace_id,
+            self.span_id,
+            self.flags,
+            true,
+            TraceState::default(),
+        )
+    }
+}
+
+/// Records where the calling thread came from.
+pub(crate) fn set(origin: Origin) {
+    ORIGIN.set(Some(origin));
+}
+
+/// Reports who created the calling thread, in attribute-only mode: the creator's OS thread
+/// id goes to `tid` and the creation time, in nanoseconds since the Unix epoch, to
+/// `created_ns` (either may be NULL), and the creating span's W3C `traceparent` into
+/// `buf` like [`otel_posix_get_traceparent`](crate::ffi::otel_posix_get_traceparent).
+///
+/// Returns the length of the traceparent, or 0 if the thread wasn't created through the
+/// shim in attribute-only mode, in which case nothing is written.
+///
+/// # Safety
+///
+/// `tid` and `created_ns` must each be null or valid for writes, and `buf` null or valid
+/// for writes of `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn otel_posix_thread_origin(
+    tid: *mut pid_t,
+    created_ns: *mut u64,
+    buf: *mut c_char,
+    len: size_t,
+) -> c_int {
+    let Some(origin) = ORIGIN.get() else {
+        return 0;
+    };
+    if !tid.is_null() {
+        unsafe { *tid = origin.tid };
+    }
+    if !created_ns.is_null() {
+        let nanos = origin
+            .created
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        unsafe { *created_ns = nanos };
+    }
+    let tp = format_traceparent(&origin.span_context());
+    debug_assert_eq!(tp.len(), TRACEPARENT_LEN);
+    if !buf.is_null() && len > tp.len() {
+        unsafe {
+            std::ptr::copy_nonoverlapping(tp.as_ptr(), buf as *mut u8, tp.len());
+            *buf.add(tp.len()) = 0;
+        }
+    }
+    tp.len() as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    const TP: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn origin_is_per_thread() {
+        let sc = crate::w3c::parse_traceparent(TP).unwrap();
+        let origin = Origin::capture(&Context::new().with_remote_span_context(sc));
+        let creator = unsafe { libc::gettid() };
+
+        let (n, tid, created, tp) = std::thread::spawn(move || {
+            set(origin);
+            let (mut tid, mut created, mut buf) = (0, 0, [0 as c_char; 64]);
+            let n = unsafe {
+                otel_posix_thread_origin(&mut tid, &mut created, buf.as_mut_ptr(), buf.len())
+            };
+            let tp = unsafe { CStr::from_ptr(buf.as_ptr()) }
+                .to_string_lossy()
+                .into_owned();
+            (n, tid, created, tp)
+        })
+        .join()
+        .unwrap();
+        assert_eq!(
+            (n, tid, tp.as_str()),
+            (TRACEPARENT_LEN as c_int, creator, TP)
+        );
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        assert!(created > 0 && u128::from(created) <= now.as_nanos());
+
+        // this thread was never given one
+        let mut tid = -1;
+        let n = unsafe {
+            otel_posix_thread_origin(&mut tid, std::ptr::null_mut(), std::ptr::null_mut(), 0)
+        };
+        assert_eq!((n, tid), (0, -1));
+    }
+}
@@ -0,0 +1,243 @@
+// src/panics.rs
+//
+// Panics, and C++ exceptions nothing caught, recorded in the trace of the
+// thread they happened on. A load-time constructor installs a panic hook
+// ahead of the one already set, which still runs after it, so the message
+// is printed as before. The hook adds an `exception` event with the
+// message, the location and the thread's name to the thread's current
+// span, and sets its status to an error. A thread serving a context it
+// has no recording span for (one seeded from C, or taken from the
+// environment) gets a short `panic` span under it instead.
+//
+// The hook belongs to the standard library this crate is built with: the
+// shim's own panics reach it, and so do a Rust host's that links the crate.
+// A Rust executable with a standard library of its own keeps its own hook.
+//
+// Where a C++ runtime is loaded, std::set_terminate gets a handler that
+// records the type of the exception nothing caught the same way, as a
+// `terminate` span under a foreign context, and exports it, before it calls
+// the handler it replaced. OTEL_POSIX_PROP_PANIC_HOOK=off leaves both alone.
+
+use crate::scope;
+use crate::unwind::{guarding, panic_message};
+use libc::{c_char, c_int, c_void};
+use opentelemetry::trace::{Span, Status, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue};
+use std::ffi::CStr;
+use std::panic::{Location, PanicHookInfo};
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+// the terminate handler ours replaced, called once the exception is recorded
+static PREVIOUS_TERMINATE: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+type TerminateHandler = extern "C" fn();
+type SetTerminateFn = unsafe extern "C" fn(Option<TerminateHandler>) -> Option<TerminateHandler>;
+
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+#[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__mod_init_func"))]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(|| {
+        // the copy loaded as an auditor runs no application code
+        #[cfg(all(feature = "audit", target_os = "linux", target_env = "gnu"))]
+        if crate::audit::is_auditor() {
+            return;
+        }
+        let off = std::env::var("OTEL_POSIX_PROP_PANIC_HOOK").is_ok_and(|v| {
+            matches!(
+                v.trim().to_ascii_lowercase().as_str(),
+                "0" | "false" | "no" | "off"
+            )
+        });
+        if !off {
+            install_hook();
+            install_terminate();
+        }
+    });
+}
+
+fn install_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // the trampoline's own panics are recorded on the creating span by unwind.rs
+        if !guarding() {
+            on_panic(info);
+        }
+        previous(info);
+    }));
+}
+
+fn on_panic(info: &PanicHookInfo<'_>) {
+    let message = panic_message(info.payload());
+    let mut attributes = vec![
+        KeyValue::new("exception.type", "panic"),
+        KeyValue::new("exception.message", message.clone()),
+        KeyValue::new("thread.name", crate::poll::thread_name()),
+    ];
+    attributes.extend(info.location().map(location).into_iter().flatten());
+    record("panic", attributes, message);
+}
+
+fn location(location: &Location<'_>) -> [KeyValue; 3] {
+    [
+        KeyValue::new("code.filepath", location.file().to_string()),
+        KeyValue::new("code.lineno", i64::from(location.line())),
+        KeyValue::new("code.column", i64::from(location.column())),
+    ]
+}
+
+/// Adds the `exception` event to the calling thread's span, or to a span named `name`
+/// started under its context when the thread has no recording span of its own.
+fn record(name: &'static str, attributes: Vec<KeyValue>, message: String) {
+    let cx = Context::current();
+    let span = cx.span();
+    if span.is_recording() {
+        span.add_event("exception", attributes);
+        span.set_status(Status::error(message));
+    } else if span.span_context().is_valid() {
+        let tracer = scope::tracer();
+        let mut span = tracer.start_with_context(name, &cx);
+        span.add_event("exception", attributes);
+        span.set_status(Status::error(message));
+        span.end();
+    }
+}
+
+/// Points `std::set_terminate` at [`on_terminate`], if a C++ runtime is loaded.
+fn install_terminate() {
+    let sym = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"_ZSt13set_terminatePFvvE".as_ptr()) };
+    if sym.is_null() {
+        return;
+    }
+    let set_terminate = unsafe { std::mem::transmute::<*mut c_void, SetTerminateFn>(sym) };
+    let previous = unsafe { set_terminate(Some(on_terminate)) };
+    PREVIOUS_TERMINATE.store(
+        previous.map_or(std::ptr::null_mut(), |f| f as *mut c_void),
+        Ordering::Release,
+    );
+}
+
+extern "C" fn on_terminate() {
+    // never unwind into the C++ runtime
+    let _ = std::panic::catch_unwind(|| {
+        let thrown = thrown_type();
+        let message = match &thrown {
+            Some(name) => format!("terminate called after throwing an instance of '{name}'"),
+            None => "terminate called without an active exception".to_string(),
+        };
+        let attributes = vec![
+            KeyValue::new(
+                "exception.type",
+                thrown.unwrap_or_else(|| "std::terminate".to_string()),
+            ),
+            KeyValue::new("exception.message", message.clone()),
+            KeyValue::new("thread.name", crate::poll::thread_name()),
+        ];
+        record("terminate", attributes, message);
+        #[cfg(feature = "otlp")]
+        crate::init::flush_before_exit();
+    });
+    let previous = PREVIOUS_TERMINATE.load(Ordering::Acquire);
+    if !previous.is_null() {
+        let previous = unsafe { std::mem::transmute::<*mut c_void, TerminateHandler>(previous) };
+        previous();
+    }
+    std::process::abort();
+}
+
+/// The demangled type of the exception being handled, from the C++ runtime.
+fn thrown_type() -> Option<String> {
+    // the start of std::type_info: its vtable, then the mangled name
+    #[repr(C)]
+    struct TypeInfo {
+        _vtable: *const c_void,
+        name: *const c_char,
+    }
+    type CurrentTypeFn = unsafe extern "C" fn() -> *const TypeInfo;
+    type DemangleFn = unsafe extern "C" fn(
+        mangled: *const c_char,
+        buf: *mut c_char,
+        len: *mut libc::size_t,
+        status: *mut c_int,
+    ) -> *mut c_char;
+
+    let current =
+        unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"__cxa_current_exception_type".as_ptr()) };
+    if current.is_null() {
+        return None;
+    }
+    let current = unsafe { std::mem::transmute::<*mut c_void, CurrentTypeFn>(current) };
+    let info = unsafe { current().as_ref() }?;
+    if info.name.is_null() {
+        return None;
+    }
+    // a leading '*' marks a type local to its object
+    let mangled = unsafe { CStr::from_ptr(info.name) };
+    let mangled = mangled
+        .to_bytes_with_nul()
+        .strip_prefix(b"*")
+        .map_or(mangled, |rest| {
+            CStr::from_bytes_with_nul(rest).unwrap_or(mangled)
+        });
+    let demangle = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"__cxa_demangle".as_ptr()) };
+    if !demangle.is_null() {
+        let demangle = unsafe { std::mem::transmute::<*mut c_void, DemangleFn>(demangle) };
+        let mut status = 0;
+        let name = unsafe {
+            demangle(
+                mangled.as_ptr(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                &mut status,
+            )
+        };
+        if !name.is_null() {
+            let demangled = unsafe { CStr::from_ptr(name) }
+                .to_string_lossy()
+                .into_owned();
+            unsafe { libc::free(name.cast()) };
+            return Some(demangled);
+        }
+    }
+    Some(mangled.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+
+    #[test]
+    fn panic_is_recorded_on_the_current_span() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let cx = Context::new().with_span(provider.tracer("test").start("work"));
+        {
+            let _guard = cx.clone().attach();
+            let _ = std::panic::catch_unwind(|| panic!("boom"));
+        }
+        cx.span().end();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert_eq!(spans[0].status, Status::error("boom"));
+        let event = &spans[0].events[0];
+        assert_eq!(event.name, "exception");
+        for expected in [
+            KeyValue::new("exception.type", "panic"),
+            KeyValue::new("exception.message", "boom"),
+            KeyValue::new("code.filepath", file!()),
+            KeyValue::new("thread.name", crate::poll::thread_name()),
+        ] {
+            assert!(event.attributes.contains(&expected), "{expected:?}");
+        }
+    }
+}
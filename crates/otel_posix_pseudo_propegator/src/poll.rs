@@ -0,0 +1,295 @@
+// src/poll.rs
+//
+// Event-loop utilization. With OTEL_POSIX_PROP_POLL_METRICS on, epoll_wait,
+// epoll_pwait and poll are interposed and time each thread spends blocked
+// in them (waiting) and between returning from one and calling the next
+// (busy), so unmodified C services report how loaded their loops are. The
+// totals are kept by thread name, read by the observable counters in
+// metrics.rs, and a thread's own totals go on its lifetime span when it
+// ends, next to rusage.rs's.
+//
+// Counting only takes a clock read and two atomic adds; a thread takes a
+// lock once, the first time it polls, to find its name's totals.
+//
+// Read straight from the environment by a load-time constructor, like
+// auto_root.rs: std's runtime polls its standard fds before `main`, and
+// reading the rest of the configuration there would fix it before a host
+// (or a test) had set it. A poll from a library initialized ahead of us
+// reads it itself.
+
+use crate::config::{self, parse_bool};
+use crate::reentry;
+use opentelemetry::KeyValue;
+use std::cell::{Cell, OnceCell};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU8, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Nanoseconds spent waiting and busy, by the threads sharing a name.
+#[derive(Default)]
+pub(crate) struct Totals {
+    wait: AtomicU64,
+    busy: AtomicU64,
+}
+
+// unset until read, by the constructor or whichever comes first
+static ENABLED: AtomicU8 = AtomicU8::new(UNSET);
+const UNSET: u8 = 0;
+const ON: u8 = 1;
+const OFF: u8 = 2;
+
+#[cfg(feature = "hook-poll")]
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+#[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__mod_init_func"))]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+#[cfg_attr(not(feature = "hook-poll"), allow(dead_code))]
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(read);
+}
+
+#[cfg_attr(not(feature = "hook-poll"), allow(dead_code))]
+fn read() -> bool {
+    let var = |key: &str| std::env::var(key).ok();
+    let on = var("OTEL_POSIX_PROP_POLL_METRICS").is_some_and(|v| parse_bool(&v))
+        && !config::sdk_disabled(var);
+    ENABLED.store(if on { ON } else { OFF }, Ordering::Relaxed);
+    on
+}
+
+/// Whether `OTEL_POSIX_PROP_POLL_METRICS` turned the timing on.
+#[cfg_attr(not(feature = "hook-poll"), allow(dead_code))]
+pub(crate) fn enabled() -> bool {
+    match ENABLED.load(Ordering::Relaxed) {
+        ON => true,
+        OFF => false,
+        _ => read(),
+    }
+}
+
+static LOOPS: LazyLock<Mutex<HashMap<String, Arc<Totals>>>> = LazyLock::new(Default::default);
+
+/// The calling thread's loop: its name's totals, its own, and when it last stopped
+/// waiting.
+struct Local {
+    named: OnceCell<Arc<Totals>>,
+    wait: Cell<Duration>,
+    busy: Cell<Duration>,
+    returned: Cell<Option<Instant>>,
+}
+
+thread_local! {
+    static LOCAL: Local = const {
+        Local {
+            named: OnceCell::new(),
+            wait: Cell::new(Duration::ZERO),
+            busy: Cell::new(Duration::ZERO),
+            returned: Cell::new(None),
+        }
+    };
+}
+
+pub(crate) fn lock_loops() -> MutexGuard<'static, HashMap<String, Arc<Totals>>> {
+    LOOPS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The calling thread's name, as `ps` and the thread dump show it.
+pub(crate) fn thread_name() -> String {
+    #[cfg(target_os = "linux")]
+    {
+        let mut buf = [0 as libc::c_char; 16];
+        let rc = unsafe { libc::pthread_getname_np(libc::pthread_self(), buf.as_mut_ptr(), 16) };
+        if rc == 0 {
+            return unsafe { std::ffi::CStr::from_ptr(buf.as_ptr()) }
+                .to_string_lossy()
+                .into_owned();
+        }
+    }
+    std::thread::current()
+        .name()
+        .unwrap_or("unnamed")
+        .to_string()
+}
+
+/// Runs `wait`, a call that blocks for events, counting the time in it as waiting and the
+/// time since the last one returned as busy.
+#[cfg_attr(not(feature = "hook-poll"), allow(dead_code))]
+pub(crate) fn timed<R>(wait: impl FnOnce() -> R) -> R {
+    let called = Instant::now();
+    let result = wait();
+    let returned = Instant::now();
+    // nor are calls made from inside the shim, or from a signal handler while this thread
+    // is in here
+    let Some(_guard) = reentry::enter() else {
+        return result;
+    };
+    let errno = unsafe { *libc::__errno_location() };
+    let _ = LOCAL.try_with(|local| {
+        let waited = returned - called;
+        let busy = local
+            .returned
+            .replace(Some(returned))
+            .map_or(Duration::ZERO, |last| {
+                called.saturating_duration_since(last)
+            });
+        local.wait.set(local.wait.get() + waited);
+        local.busy.set(local.busy.get() + busy);
+        let named = local
+            .named
+            .get_or_init(|| lock_loops().entry(thread_name()).or_default().clone());
+        named
+            .wait
+            .fetch_add(waited.as_nanos() as u64, Ordering::Relaxed);
+        named
+            .busy
+            .fetch_add(busy.as_nanos() as u64, Ordering::Relaxed);
+    });
+    unsafe { *libc::__errno_location() = errno };
+    result
+}
+
+/// Calls `observe` with each thread name's seconds waiting and busy so far.
+pub(crate) fn totals(mut observe: impl FnMut(&str, f64, f64)) {
+    for (name, totals) in lock_loops().iter() {
+        let secs = |ns: &AtomicU64| Duration::from_nanos(ns.load(Ordering::Relaxed)).as_secs_f64();
+        observe(name, secs(&totals.wait), secs(&totals.busy));
+    }
+}
+
+/// Attributes for the calling thread's span, if it has polled.
+pub(crate) fn thread_attributes() -> Vec<KeyValue> {
+    LOCAL
+        .try_with(|local| {
+            if local.returned.get().is_none() {
+                return Vec::new();
+            }
+            vec![
+                KeyValue::new(
+                    "otel_posix.thread.poll.wait",
+                    local.wait.get().as_secs_f64(),
+                ),
+                KeyValue::new(
+                    "otel_posix.thread.poll.busy",
+                    local.busy.get().as_secs_f64(),
+                ),
+            ]
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(feature = "hook-poll")]
+type PollFn = unsafe extern "C" fn(*mut libc::pollfd, libc::nfds_t, libc::c_int) -> libc::c_int;
+#[cfg(all(feature = "hook-poll", any(target_os = "linux", target_os = "android")))]
+type EpollWaitFn = unsafe extern "C" fn(
+    libc::c_int,
+    *mut libc::epoll_event,
+    libc::c_int,
+    libc::c_int,
+) -> libc::c_int;
+#[cfg(all(feature = "hook-poll", any(target_os = "linux", target_os = "android")))]
+type EpollPwaitFn = unsafe extern "C" fn(
+    libc::c_int,
+    *mut libc::epoll_event,
+    libc::c_int,
+    libc::c_int,
+    *const libc::sigset_t,
+) -> libc::c_int;
+
+/// `poll`, timed if the metrics are on.
+#[cfg(feature = "hook-poll")]
+pub(crate) unsafe fn poll(
+    real: PollFn,
+    fds: *mut libc::pollfd,
+    nfds: libc::nfds_t,
+    timeout: libc::c_int,
+) -> libc::c_int {
+    if enabled() {
+        timed(|| unsafe { real(fds, nfds, timeout) })
+    } else {
+        unsafe { real(fds, nfds, timeout) }
+    }
+}
+
+/// `epoll_wait`, timed if the metrics are on.
+#[cfg(all(feature = "hook-poll", any(target_os = "linux", target_os = "android")))]
+pub(crate) unsafe fn epoll_wait(
+    real: EpollWaitFn,
+    epfd: libc::c_int,
+    events: *mut libc::epoll_event,
+    maxevents: libc::c_int,
+    timeout: libc::c_int,
+) -> libc::c_int {
+    if enabled() {
+        timed(|| unsafe { real(epfd, events, maxevents, timeout) })
+    } else {
+        unsafe { real(epfd, events, maxevents, timeout) }
+    }
+}
+
+/// `epoll_pwait`, timed if the metrics are on.
+#[cfg(all(feature = "hook-poll", any(target_os = "linux", target_os = "android")))]
+pub(crate) unsafe fn epoll_pwait(
+    real: EpollPwaitFn,
+    epfd: libc::c_int,
+    events: *mut libc::epoll_event,
+    maxevents: libc::c_int,
+    timeout: libc::c_int,
+    sigmask: *const libc::sigset_t,
+) -> libc::c_int {
+    if enabled() {
+        timed(|| unsafe { real(epfd, events, maxevents, timeout, sigmask) })
+    } else {
+        unsafe { real(epfd, events, maxevents, timeout, sigmask) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waiting_and_busy_time_add_up() {
+        std::thread::Builder::new()
+            .name("poll-test".into())
+            .spawn(|| {
+                assert!(thread_attributes().is_empty());
+                let wait = Duration::from_millis(20);
+                timed(|| std::thread::sleep(wait));
+                // the loop's work between two waits
+                std::thread::sleep(wait);
+                timed(|| std::thread::sleep(wait));
+
+                let attributes = thread_attributes();
+                let get = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|kv| kv.key.as_str() == key)
+                        .and_then(|kv| match kv.value {
+                            opentelemetry::Value::F64(v) => Some(v),
+                            _ => None,
+                        })
+                        .unwrap()
+                };
+                assert!(get("otel_posix.thread.poll.wait") >= 0.04);
+                assert!(get("otel_posix.thread.poll.busy") >= 0.02);
+
+                let mut seen = None;
+                totals(|name, wait, busy| {
+                    if name == "poll-test" {
+                        seen = Some((wait, busy));
+                    }
+                });
+                let (wait, busy) = seen.unwrap();
+                assert!(wait >= 0.04 && busy >= 0.02);
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+// src/pool.rs
+//
+// Recycled allocations for the launchers handed to new threads. A pool
+// that spawns threads all day would otherwise malloc one in the creating
+// thread and free it in the new one for every spawn. Freed slots are kept
+// on a short free list and reused; the list is only ever try-locked, so a
+// contended (or, after fork, orphaned) lock just means a plain allocation.
+
+use std::mem::MaybeUninit;
+use std::sync::Mutex;
+
+/// Free slots kept around at most.
+const CAPACITY: usize = 64;
+
+pub(crate) struct Pool<T> {
+    free: Mutex<Vec<Box<MaybeUninit<T>>>>,
+}
+
+// the free list only holds empty slots; values cross threads the way the raw pointers
+// handed out for them do
+unsafe impl<T> Sync for Pool<T> {}
+
+impl<T> Pool<T> {
+    pub const fn new() -> Self {
+        Pool {
+            free: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Moves `value` into a recycled (or new) slot and leaks it as a raw pointer, to be
+    /// given back with [`Pool::take`].
+    pub fn put(&self, value: T) -> *mut T {
+        let slot = self
+            .free
+            .try_lock()
+            .ok()
+            .and_then(|mut free| free.pop())
+            .unwrap_or_else(Box::new_uninit);
+        Box::into_raw(Box::write(slot, value))
+    }
+
+    /// Moves the value out of a slot from [`Pool::put`] and recycles the slot.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `put` on this pool and not have been taken before.
+    pub unsafe fn take(&self, ptr: *mut T) -> T {
+        let slot = unsafe { Box::from_raw(ptr.cast::<MaybeUninit<T>>()) };
+        let value = unsafe { slot.assume_init_read() };
+        if let Ok(mut free) = self.free.try_lock()
+            && free.len() < CAPACITY
+        {
+            free.push(slot);
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_are_reused() {
+        let pool = Pool::new();
+        let first = pool.put(String::from("a"));
+        assert_eq!(unsafe { pool.take(first) }, "a");
+        let second = pool.put(String::from("b"));
+        assert_eq!(second, first);
+        assert_eq!(unsafe { pool.take(second) }, "b");
+    }
+
+    #[test]
+    fn free_list_is_bounded() {
+        let pool = Pool::new();
+        let ptrs: Vec<_> = (0..CAPACITY + 8).map(|i| pool.put(i)).collect();
+        for ptr in ptrs {
+            unsafe { pool.take(ptr) };
+        }
+        assert_eq!(pool.free.lock().unwrap().len(), CAPACITY);
+    }
+}
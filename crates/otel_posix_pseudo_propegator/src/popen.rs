@@ -0,0 +1,114 @@
+// src/popen.rs
+//
+// popen and pclose. glibc starts a popen'd command with its own internal
+// spawn and reaps it with its own internal waitpid, so neither goes through
+// the interposed posix_spawn or wait (exec.rs, children.rs): the command
+// would get no context, and its span no end.
+//
+// While a span is active, popen runs with the context swapped into
+// `environ`, which the internal spawn hands to /bin/sh, the way execv and
+// execvp are covered. Another thread's setenv during the call lands in the
+// swapped-in block and is lost when the original is put back. The command
+// is started by /bin/sh, so that's the name OTEL_POSIX_PROP_EXEC_ALLOW
+// has to allow.
+//
+// With child spans on, the command gets a `popen` span under the caller's,
+// which its environment carries, with `process.command_line`. pclose ends
+// it with the exit code or the signal, as wait does for a `child process`
+// span; a stream never pclosed leaves its span unexported.
+
+use crate::children::{self, Ended};
+use crate::{exec, reentry};
+use libc::{FILE, c_char, c_int};
+use opentelemetry::trace::{Status, TraceContextExt};
+use opentelemetry::{Context, KeyValue};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex, MutexGuard, PoisonError};
+
+type PopenFn = unsafe extern "C" fn(*const c_char, *const c_char) -> *mut FILE;
+type PcloseFn = unsafe extern "C" fn(*mut FILE) -> c_int;
+
+// popen'd streams with a span that haven't been pclosed yet, by address
+static STREAMS: LazyLock<Mutex<HashMap<usize, Context>>> = LazyLock::new(Default::default);
+
+// set once the first stream is tracked
+static TRACKING: AtomicBool = AtomicBool::new(false);
+
+pub(crate) fn lock_streams() -> MutexGuard<'static, HashMap<usize, Context>> {
+    // the map stays consistent even if a holder panicked
+    STREAMS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Called in a forked child: the parent's streams are the parent's to close.
+pub(crate) fn forked() {
+    if TRACKING.load(Ordering::Relaxed) {
+        std::mem::forget(std::mem::take(&mut *lock_streams()));
+    }
+}
+
+/// `popen`, with the context in the command's environment and a span of its own.
+pub(crate) unsafe fn popen(
+    real: PopenFn,
+    command: *const c_char,
+    mode: *const c_char,
+) -> *mut FILE {
+    let span = reentry::enter().and_then(|_guard| {
+        let command = (!command.is_null()).then(|| {
+            unsafe { CStr::from_ptr(command) }
+                .to_string_lossy()
+                .into_owned()
+        })?;
+        children::start(
+            "popen",
+            vec![KeyValue::new("process.command_line", command)],
+        )
+    });
+    let stream = {
+        let _attached = span.clone().map(Context::attach);
+        exec::with_environ(c"/bin/sh".as_ptr(), || unsafe { real(command, mode) })
+    };
+    let Some(cx) = span else {
+        return stream;
+    };
+    let errno = unsafe { *libc::__errno_location() };
+    if stream.is_null() {
+        children::failed(cx, errno);
+    } else {
+        TRACKING.store(true, Ordering::Relaxed);
+        // a reused address replaces whatever its previous stream left behind
+        lock_streams().insert(stream as usize, cx);
+    }
+    unsafe { *libc::__errno_location() = errno };
+    stream
+}
+
+/// `pclose`, ending the span of the command it waited for.
+pub(crate) unsafe fn pclose(real: PcloseFn, stream: *mut FILE) -> c_int {
+    let status = unsafe { real(stream) };
+    if !TRACKING.load(Ordering::Relaxed) {
+        return status;
+    }
+    let Some(_guard) = reentry::enter() else {
+        return status;
+    };
+    // ended after the lock is let go, exporting may take others
+    let Some(cx) = lock_streams().remove(&(stream as usize)) else {
+        return status;
+    };
+    let errno = unsafe { *libc::__errno_location() };
+    let span = cx.span();
+    match (status, Ended::from_status(status)) {
+        (-1, _) | (_, None) => span.set_status(Status::error(
+            std::io::Error::from_raw_os_error(errno).to_string(),
+        )),
+        (_, Some(ended)) => {
+            span.set_attributes(ended.attributes());
+            span.set_status(ended.status());
+        }
+    }
+    span.end();
+    unsafe { *libc::__errno_location() = errno };
+    status
+}
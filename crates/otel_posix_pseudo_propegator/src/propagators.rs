@@ -0,0 +1,362 @@
+// src/propagators.rs
+//
+// Context encodings for environment-variable carriers. Headers map to
+// variables by upper-casing and replacing '-' with '_' (TRACEPARENT,
+// X_B3_TRACEID, UBER_TRACE_ID, ...), and which formats are written is
+// selected with the standard OTEL_PROPAGATORS list. Besides the context,
+// OTEL_POSIX_PROP_EXEC_FORWARD passes on the BAGGAGE and
+// OTEL_RESOURCE_ATTRIBUTES this process was given, for children started
+// with an environment of their own. OTEL_POSIX_PROP_EXEC_ALLOW limits all
+// of it to the executables it names.
+
+use crate::filter::Glob;
+use crate::log::log_warn;
+use crate::w3c;
+use opentelemetry::Context;
+use opentelemetry::trace::TraceContextExt;
+use otel_env_context::BAGGAGE_VAR;
+
+/// One entry of `OTEL_PROPAGATORS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Propagator {
+    /// W3C `traceparent` / `tracestate`.
+    TraceContext,
+    /// W3C `baggage`.
+    Baggage,
+    /// Zipkin B3 single header, `b3`.
+    B3,
+    /// Zipkin B3 multi header, `X-B3-TraceId` etc.
+    B3Multi,
+    /// `uber-trace-id`.
+    Jaeger,
+}
+
+impl Propagator {
+    /// Parses `OTEL_PROPAGATORS`; unknown entries are reported and skipped.
+    pub fn parse_list(s: &str) -> Vec<Propagator> {
+        let mut out = Vec::new();
+        for name in s.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let p = match name.to_ascii_lowercase().as_str() {
+                "tracecontext" => Propagator::TraceContext,
+                "baggage" => Propagator::Baggage,
+                "b3" => Propagator::B3,
+                "b3multi" => Propagator::B3Multi,
+                "jaeger" => Propagator::Jaeger,
+                "none" => return Vec::new(),
+                _ => {
+                    log_warn!("unsupported propagator {name:?}");
+                    continue;
+                }
+            };
+            if !out.contains(&p) {
+                out.push(p);
+            }
+        }
+        out
+    }
+
+    /// The spec default when `OTEL_PROPAGATORS` is unset.
+    pub fn defaults() -> Vec<Propagator> {
+        vec![Propagator::TraceContext, Propagator::Baggage]
+    }
+}
+
+/// What a child gets besides the span context. `OTEL_POSIX_PROP_EXEC_FORWARD`, a list of
+/// `baggage` and `resource`, or `none`; both by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Forward {
+    /// The `BAGGAGE` this process has, under the current context's own baggage.
+    pub baggage: bool,
+    /// The `OTEL_RESOURCE_ATTRIBUTES` this process has, minus what identifies it.
+    pub resource: bool,
+}
+
+impl Default for Forward {
+    fn default() -> Self {
+        Forward {
+            baggage: true,
+            resource: true,
+        }
+    }
+}
+
+impl Forward {
+    /// Parses `OTEL_POSIX_PROP_EXEC_FORWARD`; unknown entries are reported and skipped.
+    pub fn parse(s: &str) -> Forward {
+        let mut out = Forward {
+            baggage: false,
+            resource: false,
+        };
+        for name in s.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            match name.to_ascii_lowercase().as_str() {
+                "baggage" => out.baggage = true,
+                "resource" => out.resource = true,
+                "none" => {}
+                _ => log_warn!("unknown OTEL_POSIX_PROP_EXEC_FORWARD entry {name:?}"),
+            }
+        }
+        out
+    }
+}
+
+/// The executables whose environment gets anything added. `OTEL_POSIX_PROP_EXEC_ALLOW`,
+/// comma-separated globs; a pattern with a `/` is matched against the path the
+/// executable was started by, the others against its file name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ExecAllow {
+    paths: Vec<Glob>,
+    names: Vec<Glob>,
+}
+
+impl ExecAllow {
+    /// Builds the allowlist from a comma-separated glob list; `None` if it is empty.
+    pub fn parse(allow: &str) -> Option<Self> {
+        let (paths, names): (Vec<&str>, Vec<&str>) = allow
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .partition(|p| p.contains('/'));
+        let globs = |patterns: Vec<&str>| patterns.into_iter().map(Glob::new).collect();
+        (!paths.is_empty() || !names.is_empty()).then(|| ExecAllow {
+            paths: globs(paths),
+            names: globs(names),
+        })
+    }
+
+    /// Whether the executable started by `path` (as given to exec or spawn) is allowed.
+    pub fn allows(&self, path: &str) -> bool {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        self.paths.iter().any(|p| p.matches(path)) || self.names.iter().any(|p| p.matches(name))
+    }
+}
+
+/// The resource attributes variable the SDKs read.
+pub(crate) const RESOURCE_VAR: &str = "OTEL_RESOURCE_ATTRIBUTES";
+
+// what identifies this process rather than the deployment it is part of
+const OWN_RESOURCE_KEYS: &[&str] = &["service.name", "service.instance.id"];
+
+/// Environment variables carrying `cx` in each of `propagators`.
+pub(crate) fn env_vars(cx: &Context, propagators: &[Propagator]) -> Vec<(&'static str, String)> {
+    let span = cx.span();
+    let sc = span.span_context();
+    let sampled = sc.is_sampled();
+    let mut vars = Vec::new();
+    for p in propagators {
+        match p {
+            Propagator::Baggage => vars.extend(otel_env_context::baggage_to_env(cx)),
+            // everything else describes the span
+            _ if !sc.is_valid() => {}
+            Propagator::TraceContext => vars.extend(w3c::to_env(cx)),
+            Propagator::B3 => vars.push((
+                "B3",
+                format!("{}-{}-{}", sc.trace_id(), sc.span_id(), u8::from(sampled)),
+            )),
+            Propagator::B3Multi => {
+                vars.push(("X_B3_TRACEID", sc.trace_id().to_string()));
+                vars.push(("X_B3_SPANID", sc.span_id().to_string()));
+                vars.push(("X_B3_SAMPLED", u8::from(sampled).to_string()));
+            }
+            // trace:span:parent (deprecated, always 0):flags
+            Propagator::Jaeger => vars.push((
+                "UBER_TRACE_ID",
+                format!("{}:{}:0:{}", sc.trace_id(), sc.span_id(), u8::from(sampled)),
+            )),
+        }
+    }
+    vars
+}
+
+/// Adds what `forward` passes on to `vars`, the context's own variables for a child.
+/// `child` looks a variable up in the environment the child is given, `own` in this
+/// process's. Entries already there win over forwarded ones with the same key.
+pub(crate) fn forward(
+    vars: &mut Vec<(&'static str, String)>,
+    forward: Forward,
+    propagators: &[Propagator],
+    child: impl Fn(&str) -> Option<String>,
+    own: impl Fn(&str) -> Option<String>,
+) {
+    if forward.baggage
+        && propagators.contains(&Propagator::Baggage)
+        && let Some(inherited) = child(BAGGAGE_VAR).or_else(|| own(BAGGAGE_VAR))
+    {
+        match vars.iter_mut().find(|(k, _)| *k == BAGGAGE_VAR) {
+            Some((_, baggage)) => *baggage = merge(baggage, &inherited, &[]),
+            None => vars.push((BAGGAGE_VAR, inherited)),
+        }
+    }
+    if forward.resource
+        && let Some(attributes) = own(RESOURCE_VAR)
+    {
+        let given = child(RESOURCE_VAR).unwrap_or_default();
+        let merged = merge(&given, &attributes, OWN_RESOURCE_KEYS);
+        // the child environment already says as much
+        if merged != given {
+            vars.push((RESOURCE_VAR, merged));
+        }
+    }
+}
+
+/// The `key=value` entries of `primary`, then those of `fallback` for other keys, leaving
+/// out the keys in `skip`.
+fn merge(primary: &str, fallback: &str, skip: &[&str]) -> String {
+    let key = |entry: &str| {
+        entry
+            .split('=')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+    let entries = |list: &str| {
+        list.split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>()
+    };
+    let mut merged = entries(primary);
+    let mut keys: Vec<String> = merged.iter().map(|e| key(e)).collect();
+    keys.extend(skip.iter().map(|k| k.to_string()));
+    for entry in entries(fallback) {
+        if !keys.contains(&key(&entry)) {
+            keys.push(key(&entry));
+            merged.push(entry);
+        }
+    }
+    merged.join(",")
+}
+
+/// Every variable any propagator may set, so stale values from our own parent can be
+/// replaced rather than duplicated.
+pub(crate) const ALL_VARS: &[&str] = &[
+    w3c::TRACEPARENT_VAR,
+    w3c::TRACESTATE_VAR,
+    BAGGAGE_VAR,
+    "B3",
+    "X_B3_TRACEID",
+    "X_B3_SPANID",
+    "X_B3_SAMPLED",
+    "UBER_TRACE_ID",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::KeyValue;
+    use opentelemetry::baggage::BaggageExt;
+    use opentelemetry::trace::{SpanContext, SpanId, TraceFlags, TraceId, TraceState};
+
+    fn cx() -> Context {
+        let sc = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            false,
+            TraceState::default(),
+        );
+        Context::new()
+            .with_remote_span_context(sc)
+            .with_baggage([KeyValue::new("tenant", "a b")])
+    }
+
+    #[test]
+    fn parses_propagator_list() {
+        use Propagator::*;
+        assert_eq!(
+            Propagator::parse_list("tracecontext, B3multi,jaeger,xray,b3multi"),
+            [TraceContext, B3Multi, Jaeger]
+        );
+        assert_eq!(Propagator::parse_list("b3,none"), []);
+    }
+
+    #[test]
+    fn encodes_each_format() {
+        use Propagator::*;
+        let vars = env_vars(&cx(), &[TraceContext, Baggage, B3, B3Multi, Jaeger]);
+        assert_eq!(
+            vars,
+            [
+                (
+                    "TRACEPARENT",
+                    "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".into()
+                ),
+                ("BAGGAGE", "tenant=a%20b".into()),
+                (
+                    "B3",
+                    "4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-1".into()
+                ),
+                ("X_B3_TRACEID", "4bf92f3577b34da6a3ce929d0e0e4736".into()),
+                ("X_B3_SPANID", "00f067aa0ba902b7".into()),
+                ("X_B3_SAMPLED", "1".into()),
+                (
+                    "UBER_TRACE_ID",
+                    "4bf92f3577b34da6a3ce929d0e0e4736:00f067aa0ba902b7:0:1".into()
+                ),
+            ]
+        );
+        assert!(vars.iter().all(|(k, _)| ALL_VARS.contains(k)));
+        assert_eq!(env_vars(&Context::new(), &[TraceContext, B3]), []);
+    }
+
+    #[test]
+    fn forwards_baggage_and_deployment_attributes() {
+        let own = |key: &str| match key {
+            "BAGGAGE" => Some("tenant=b,region=eu".to_string()),
+            "OTEL_RESOURCE_ATTRIBUTES" => {
+                Some("service.name=parent,deployment.environment=prod".to_string())
+            }
+            _ => None,
+        };
+        // a child started with an environment of its own
+        let mut vars = env_vars(&cx(), &Propagator::defaults());
+        forward(
+            &mut vars,
+            Forward::default(),
+            &Propagator::defaults(),
+            |key| (key == "OTEL_RESOURCE_ATTRIBUTES").then(|| "service.name=child".to_string()),
+            own,
+        );
+        assert_eq!(
+            vars[1..],
+            [
+                ("BAGGAGE", "tenant=a%20b,region=eu".into()),
+                (
+                    "OTEL_RESOURCE_ATTRIBUTES",
+                    "service.name=child,deployment.environment=prod".into()
+                ),
+            ]
+        );
+
+        // nothing to add to an environment that has it all already
+        let mut vars = Vec::new();
+        forward(
+            &mut vars,
+            Forward::default(),
+            &[Propagator::TraceContext],
+            own,
+            own,
+        );
+        assert_eq!(vars, []);
+        forward(
+            &mut vars,
+            Forward::parse("baggage, bogus"),
+            &Propagator::defaults(),
+            |_| None,
+            own,
+        );
+        assert_eq!(vars, [("BAGGAGE", "tenant=b,region=eu".into())]);
+    }
+
+    #[test]
+    fn exec_allowlist_matches_names_and_paths() {
+        assert_eq!(ExecAllow::parse(" , "), None);
+        let allow = ExecAllow::parse("worker-*, /opt/app/bin/*").unwrap();
+        assert!(allow.allows("worker-7"));
+        assert!(allow.allows("/usr/libexec/worker-7"));
+        assert!(allow.allows("/opt/app/bin/report"));
+        assert!(!allow.allows("report"));
+        assert!(!allow.allows("/usr/bin/curl"));
+    }
+}
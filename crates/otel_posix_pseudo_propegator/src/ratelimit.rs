@@ -0,0 +1,126 @@
+// src/ratelimit.rs
+//
+// Token buckets for thread storms. A process creating tens of thousands of
+// short-lived threads a second pays the wrapping cost on every one of them,
+// and a misconfiguration warned about per thread floods its stderr. Buckets
+// cap wrapping (OTEL_POSIX_PROP_WRAP_RATE, unlimited by default), the shim's
+// warnings (OTEL_POSIX_PROP_LOG_RATE, 10 a second by default) and overhead
+// samples for the histogram, each refilling at its rate with a second's
+// worth of burst.
+//
+// A bucket is kept as the time it would next be full again (the GCRA form
+// of a token bucket), so taking a token is one compare-and-swap on one
+// atomic and never blocks or allocates.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A token bucket refilling `rate` tokens a second, holding up to `rate` of them.
+pub(crate) struct TokenBucket {
+    // monotonic nanoseconds at which the bucket would be full; anything up to a second
+    // past now means tokens are left
+    full_at: AtomicU64,
+}
+
+impl TokenBucket {
+    pub(crate) const fn new() -> Self {
+        TokenBucket {
+            full_at: AtomicU64::new(0),
+        }
+    }
+
+    /// Takes a token if one is left at the bucket's `rate` per second.
+    pub(crate) fn try_take(&self, rate: u32) -> bool {
+        self.try_take_at(now(), rate)
+    }
+
+    fn try_take_at(&self, now: u64, rate: u32) -> bool {
+        const SECOND: u64 = 1_000_000_000;
+        let interval = SECOND / u64::from(rate.max(1));
+        let mut full_at = self.full_at.load(Ordering::Relaxed);
+        loop {
+            let next = full_at.max(now) + interval;
+            if next > now + SECOND {
+                return false;
+            }
+            match self.full_at.compare_exchange_weak(
+                full_at,
+                next,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(current) => full_at = current,
+            }
+        }
+    }
+}
+
+/// Parses a per-second rate from the environment; `None`, with a warning, unless it is a
+/// positive whole number.
+pub(crate) fn parse_rate(var: &str, value: &str) -> Option<u32> {
+    match value.trim().parse() {
+        Ok(rate) if rate > 0 => Some(rate),
+        _ => {
+            // straight to stderr: the log limit itself is parsed with this
+            eprintln!("otel_posix_pseudo_propegator: ignoring {var} {value:?}, not a rate");
+            None
+        }
+    }
+}
+
+fn now() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000_000;
+
+    #[test]
+    fn burst_then_refill() {
+        let bucket = TokenBucket::new();
+        let t0 = 5 * SECOND;
+        // a full bucket's worth at once, then nothing
+        assert_eq!((0..10).filter(|_| bucket.try_take_at(t0, 4)).count(), 4);
+        // a token comes back every quarter second
+        assert!(bucket.try_take_at(t0 + SECOND / 4, 4));
+        assert!(!bucket.try_take_at(t0 + SECOND / 4, 4));
+        // after an idle spell the bucket is full again, but no fuller
+        assert_eq!(
+            (0..10)
+                .filter(|_| bucket.try_take_at(t0 + 10 * SECOND, 4))
+                .count(),
+            4
+        );
+    }
+
+    #[test]
+    fn concurrent_takers_share_the_burst() {
+        let bucket = TokenBucket::new();
+        let t0 = SECOND;
+        let taken: usize = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    let bucket = &bucket;
+                    s.spawn(move || (0..100).filter(|_| bucket.try_take_at(t0, 50)).count())
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        assert_eq!(taken, 50);
+    }
+
+    #[test]
+    fn rates() {
+        assert_eq!(parse_rate("X", " 500 "), Some(500));
+        assert_eq!(parse_rate("X", "0"), None);
+        assert_eq!(parse_rate("X", "fast"), None);
+    }
+}
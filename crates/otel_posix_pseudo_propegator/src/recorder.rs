@@ -0,0 +1,398 @@
+// src/recorder.rs
+//
+// A flight recorder of the last few hundred things the interposer did, for
+// answering "why didn't this thread get a context?" after the fact: each
+// thread creation is recorded with what was decided and why, and each
+// wrapped thread again once its context is attached. The events go out with
+// the thread dump and through the control socket's `events` command.
+//
+// The ring is fixed-size and lock-free: writers claim a slot with one
+// fetch_add and publish it seqlock-style, so recording never allocates or
+// blocks, even on the pthread_create path. Off unless
+// OTEL_POSIX_PROP_RECORDER is set, read straight from the environment by a
+// load-time constructor like auto-root mode, or by the first thread
+// creation when that comes before it.
+
+use crate::config::parse_bool;
+use crate::log;
+use opentelemetry::trace::SpanId;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicI32, AtomicU8, AtomicU64, Ordering, fence};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SLOTS: usize = 256;
+
+// unset until read, by the constructor or whichever comes first
+static ENABLED: AtomicU8 = AtomicU8::new(UNSET);
+const UNSET: u8 = 0;
+const ON: u8 = 1;
+const OFF: u8 = 2;
+
+static RING: Ring<SLOTS> = Ring::new();
+
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+#[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__mod_init_func"))]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(read);
+}
+
+fn read() -> bool {
+    let on = std::env::var("OTEL_POSIX_PROP_RECORDER").is_ok_and(|v| parse_bool(&v));
+    ENABLED.store(if on { ON } else { OFF }, Ordering::Relaxed);
+    on
+}
+
+/// What the interposer did.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum Action {
+    /// The thread was created with the creator's context.
+    Wrapped = 1,
+    /// The thread was trampolined only for the embedder hooks or a `tracing` span.
+    Hooked,
+    /// The real create call failed.
+    Failed,
+    /// Passed through: no span was active.
+    NoSpan,
+    /// Passed through: the active span didn't match the span filter.
+    SpanFiltered,
+    /// Passed through: the active span isn't sampled.
+    Unsampled,
+    /// Passed through: wrapping was suppressed or paused, or telemetry suppressed.
+    Suppressed,
+    /// Passed through: the pthread_create hook is turned off.
+    Disabled,
+    /// Passed through: a sanitizer runtime's pthread_create runs before ours.
+    Sanitizer,
+    /// Passed through: the entry-point lists exclude the start routine.
+    EntryFiltered,
+    /// Passed through: the calling library isn't on the allowlist.
+    CallerFiltered,
+    /// Passed through: over the wrapping rate limit.
+    RateLimited,
+    /// Passed through: called re-entrantly, while resolving or wrapping.
+    Nested,
+    /// A wrapped thread attached its context.
+    Started,
+    /// A wrapped thread's start routine returned.
+    Finished,
+}
+
+impl Action {
+    const ALL: [Action; 15] = [
+        Action::Wrapped,
+        Action::Hooked,
+        Action::Failed,
+        Action::NoSpan,
+        Action::SpanFiltered,
+        Action::Unsampled,
+        Action::Suppressed,
+        Action::Disabled,
+        Action::Sanitizer,
+        Action::EntryFiltered,
+        Action::CallerFiltered,
+        Action::RateLimited,
+        Action::Nested,
+        Action::Started,
+        Action::Finished,
+    ];
+
+    fn from_u8(n: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| *a as u8 == n)
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Action::Wrapped => "wrapped",
+            Action::Hooked => "hooked",
+            Action::Failed => "failed",
+            Action::NoSpan => "passed:no-span",
+            Action::SpanFiltered => "passed:span-filtered",
+            Action::Unsampled => "passed:unsampled",
+            Action::Suppressed => "passed:suppressed",
+            Action::Disabled => "passed:disabled",
+            Action::Sanitizer => "passed:sanitizer",
+            Action::EntryFiltered => "passed:entry-filtered",
+            Action::CallerFiltered => "passed:caller-filtered",
+            Action::RateLimited => "passed:rate-limited",
+            Action::Nested => "passed:nested",
+            Action::Started => "started",
+            Action::Finished => "finished",
+        }
+    }
+}
+
+/// One recorded event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Event {
+    // position in the whole sequence of events, from 0
+    seq: u64,
+    nanos: u64,
+    tid: i32,
+    action: Action,
+    span: Option<SpanId>,
+}
+
+struct Slot {
+    // 0 when never written, odd while being written, 2 * (seq + 1) once published
+    state: AtomicU64,
+    nanos: AtomicU64,
+    tid: AtomicI32,
+    action: AtomicU8,
+    span: AtomicU64,
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Slot {
+            state: AtomicU64::new(0),
+            nanos: AtomicU64::new(0),
+            tid: AtomicI32::new(0),
+            action: AtomicU8::new(0),
+            span: AtomicU64::new(0),
+        }
+    }
+}
+
+struct Ring<const N: usize> {
+    next: AtomicU64,
+    slots: [Slot; N],
+}
+
+impl<const N: usize> Ring<N> {
+    const fn new() -> Self {
+        Ring {
+            next: AtomicU64::new(0),
+            slots: [const { Slot::new() }; N],
+        }
+    }
+
+    fn push(&self, nanos: u64, tid: i32, action: Action, span: Option<SpanId>) {
+        let seq = self.next.fetch_add(1, Ordering::Relaxed);
+        let slot = &self.slots[(seq % N as u64) as usize];
+        let mut state = slot.state.load(Ordering::Relaxed);
+        loop {
+            // a writer that lapped the ring is still in this slot, or a newer event already
+            // is; losing this one beats waiting
+            if state % 2 == 1 || state > 2 * seq {
+                return;
+            }
+            match slot.state.compare_exchange_weak(
+                state,
+                2 * seq + 1,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(current) => state = current,
+            }
+        }
+        fence(Ordering::Release);
+        slot.nanos.store(nanos, Ordering::Relaxed);
+        slot.tid.store(tid, Ordering::Relaxed);
+        slot.action.store(action as u8, Ordering::Relaxed);
+        let span = span.map_or(0, |id| u64::from_be_bytes(id.to_bytes()));
+        slot.span.store(span, Ordering::Relaxed);
+        slot.state.store(2 * seq + 2, Ordering::Release);
+    }
+
+    /// The events still in the ring, oldest first. A slot being rewritten while it is read
+    /// is left out rather than reported torn.
+    fn events(&self) -> Vec<Event> {
+        let mut events: Vec<Event> = self
+            .slots
+            .iter()
+            .filter_map(|slot| {
+                let state = slot.state.load(Ordering::Acquire);
+                if state == 0 || state % 2 == 1 {
+                    return None;
+                }
+                let nanos = slot.nanos.load(Ordering::Relaxed);
+                let tid = slot.tid.load(Ordering::Relaxed);
+                let action = slot.action.load(Ordering::Relaxed);
+                let span = slot.span.load(Ordering::Relaxed);
+                fence(Ordering::Acquire);
+                if slot.state.load(Ordering::Relaxed) != state {
+                    return None;
+                }
+                Some(Event {
+                    seq: state / 2 - 1,
+                    nanos,
+                    tid,
+                    action: Action::from_u8(action)?,
+                    span: (span != 0).then(|| SpanId::from_bytes(span.to_be_bytes())),
+                })
+            })
+            .collect();
+        events.sort_by_key(|e| e.seq);
+        events
+    }
+
+    fn recorded(&self) -> u64 {
+        self.next.load(Ordering::Relaxed)
+    }
+}
+
+/// Whether events are being recorded.
+pub(crate) fn enabled() -> bool {
+    match ENABLED.load(Ordering::Relaxed) {
+        ON => true,
+        OFF => false,
+        _ => read(),
+    }
+}
+
+/// Records `action` on the calling thread, about `span` if there is one.
+pub(crate) fn record(action: Action, span: Option<SpanId>) {
+    if !enabled() {
+        return;
+    }
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    RING.push(nanos, unsafe { libc::gettid() }, action, span);
+}
+
+/// The recorded events as of now, for the thread dump and the control socket.
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+pub(crate) fn snapshot() -> String {
+    if !enabled() {
+        return "otel_posix_pseudo_propegator: no events recorded, OTEL_POSIX_PROP_RECORDER is off\n"
+            .to_string();
+    }
+    render(
+        unsafe { libc::getpid() },
+        RING.recorded(),
+        &RING.events(),
+        log::redact_ids(),
+    )
+}
+
+/// One header line, then a line per event: time, tid, action and span id if any, unless
+/// `redact`ed.
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+fn render(pid: libc::pid_t, recorded: u64, events: &[Event], redact: bool) -> String {
+    let mut out = format!(
+        "otel_posix_pseudo_propegator: recent events, pid {pid}, last {} of {recorded}\n",
+        events.len()
+    );
+    for event in events {
+        let _ = write!(
+            out,
+            "  {}.{:06} tid={} {}",
+            event.nanos / 1_000_000_000,
+            event.nanos % 1_000_000_000 / 1_000,
+            event.tid,
+            event.action.as_str()
+        );
+        match event.span {
+            Some(_) if redact => {
+                let _ = write!(out, " span={}", log::REDACTED);
+            }
+            Some(span) => {
+                let _ = write!(out, " span={span}");
+            }
+            None => {}
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_keeps_the_latest_events_in_order() {
+        let ring = Ring::<4>::new();
+        assert!(ring.events().is_empty());
+        let span = SpanId::from_hex("00f067aa0ba902b7").unwrap();
+        for i in 0..6 {
+            ring.push(i, 10 + i as i32, Action::Wrapped, (i == 5).then_some(span));
+        }
+        let events = ring.events();
+        assert_eq!(ring.recorded(), 6);
+        assert_eq!(
+            events.iter().map(|e| e.seq).collect::<Vec<_>>(),
+            [2, 3, 4, 5]
+        );
+        assert_eq!(
+            events[3],
+            Event {
+                seq: 5,
+                nanos: 5,
+                tid: 15,
+                action: Action::Wrapped,
+                span: Some(span),
+            }
+        );
+        assert_eq!(events[0].span, None);
+    }
+
+    #[test]
+    fn concurrent_writers_are_never_torn() {
+        let ring = Ring::<8>::new();
+        std::thread::scope(|s| {
+            for t in 1..=4 {
+                let ring = &ring;
+                s.spawn(move || {
+                    for _ in 0..1000 {
+                        // every field derived from the writer, so a torn slot would show
+                        ring.push(t as u64, t, Action::Started, Some(SpanId::from(t as u64)));
+                    }
+                });
+            }
+            for _ in 0..100 {
+                for e in ring.events() {
+                    assert_eq!(e.nanos, e.tid as u64);
+                    assert_eq!(e.span, Some(SpanId::from(e.nanos)));
+                }
+            }
+        });
+        assert_eq!(ring.recorded(), 4000);
+        assert_eq!(ring.events().len(), 8);
+    }
+
+    #[test]
+    fn render_lists_events() {
+        let events = [
+            Event {
+                seq: 0,
+                nanos: 1_700_000_000_123_456_789,
+                tid: 11,
+                action: Action::NoSpan,
+                span: None,
+            },
+            Event {
+                seq: 1,
+                nanos: 1_700_000_000_200_000_000,
+                tid: 11,
+                action: Action::Wrapped,
+                span: Some(SpanId::from_hex("00f067aa0ba902b7").unwrap()),
+            },
+        ];
+        assert_eq!(
+            render(7, 9, &events, false),
+            "otel_posix_pseudo_propegator: recent events, pid 7, last 2 of 9\n\
+             \x20 1700000000.123456 tid=11 passed:no-span\n\
+             \x20 1700000000.200000 tid=11 wrapped span=00f067aa0ba902b7\n"
+        );
+        assert!(render(7, 9, &events, true).ends_with(" wrapped span=redacted\n"));
+    }
+
+    #[test]
+    fn actions_round_trip() {
+        for action in Action::ALL {
+            assert_eq!(Action::from_u8(action as u8), Some(action));
+        }
+        assert_eq!(Action::from_u8(0), None);
+    }
+}
@@ -0,0 +1,101 @@
+// src/reentry.rs
+//
+// Re-entrancy guard for the interposer. Malloc replacements (jemalloc,
+// tcmalloc) start background threads from inside malloc, and the first
+// wrapped pthread_create on a thread may itself allocate (thread-local
+// setup, dlsym). Without a guard that loops straight back into us, so a
+// nested call on the same thread skips all of the shim's logic.
+//
+// The same goes for a thread whose locals are being destroyed, the main
+// thread's at exit included: the atexit handlers, library destructors and
+// pthread key destructors (OpenSSL's) that run after them still call
+// pthread_once and the rest, and the current context is gone by then, so
+// looking it up would panic. A local of ours first touched just after the
+// context, by the constructor for the main thread and on a thread's first
+// entry otherwise (watch), is destroyed just before it, and marks the
+// thread as exiting.
+
+use opentelemetry::Context;
+use std::cell::Cell;
+
+thread_local! {
+    // const-initialized with no destructor: touching it never allocates
+    static ACTIVE: Cell<bool> = const { Cell::new(false) };
+    static WATCHED: Cell<bool> = const { Cell::new(false) };
+    static EXITING: Cell<bool> = const { Cell::new(false) };
+    static WATCH: Watch = const { Watch };
+}
+
+/// Marks the thread as exiting when its locals are destroyed.
+struct Watch;
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        EXITING.set(true);
+    }
+}
+
+/// Marks this thread as inside the interposer until dropped.
+pub(crate) struct Guard(());
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        ACTIVE.set(false);
+    }
+}
+
+/// Enters the interposer, or `None` if this thread is already inside it or exiting.
+pub(crate) fn enter() -> Option<Guard> {
+    if EXITING.get() || ACTIVE.replace(true) {
+        return None;
+    }
+    let guard = Guard(());
+    watch();
+    Some(guard)
+}
+
+/// Has the calling thread marked as exiting once its locals are being destroyed, before
+/// its current context is.
+pub(crate) fn watch() {
+    if !WATCHED.replace(true) {
+        Context::map_current(|_| ());
+        let _ = WATCH.try_with(|_| ());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_enter_is_refused() {
+        let outer = enter().expect("first enter");
+        assert!(enter().is_none());
+        drop(outer);
+        assert!(enter().is_some());
+    }
+
+    #[test]
+    fn exiting_threads_are_refused() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        static REFUSED: AtomicBool = AtomicBool::new(false);
+        // first touched before the watch, so destroyed after it
+        struct Late;
+        impl Drop for Late {
+            fn drop(&mut self) {
+                REFUSED.store(enter().is_none(), Ordering::Relaxed);
+            }
+        }
+        thread_local! {
+            static LATE: Late = const { Late };
+        }
+        std::thread::spawn(|| {
+            LATE.with(|_| ());
+            assert!(enter().is_some());
+        })
+        .join()
+        .unwrap();
+        assert!(REFUSED.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,100 @@
+// src/registry.rs
+//
+// Which trace each OS thread is serving, by tid, so diagnostic tools and C
+// code can ask about a thread other than their own
+// (otel_posix_context_for_tid). An entry is written wherever the shim
+// establishes a thread's context: when a wrapped thread starts, when C
+// seeds one, and at coroutine switches. Spans the thread starts on its own
+// afterwards aren't seen. The entry goes away when the thread exits.
+
+use crate::crashdump;
+use libc::pid_t;
+use opentelemetry::trace::SpanContext;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, MutexGuard, PoisonError};
+
+static THREADS: LazyLock<Mutex<HashMap<pid_t, SpanContext>>> = LazyLock::new(Default::default);
+
+/// Whether the thread has an entry; dropping it at thread exit removes the entry.
+struct Entry(Cell<bool>);
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        if self.0.get() {
+            lock_threads().remove(&unsafe { libc::gettid() });
+        }
+    }
+}
+
+thread_local! {
+    static ENTRY: Entry = const { Entry(Cell::new(false)) };
+}
+
+pub(crate) fn lock_threads() -> MutexGuard<'static, HashMap<pid_t, SpanContext>> {
+    THREADS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Records `sc` as what the calling thread is serving, or forgets the thread for `None`
+/// or an invalid span context.
+pub(crate) fn set_current(sc: Option<&SpanContext>) {
+    crashdump::set_current(sc);
+    let tid = unsafe { libc::gettid() };
+    match sc.filter(|sc| sc.is_valid()) {
+        Some(sc) => {
+            lock_threads().insert(tid, sc.clone());
+            let _ = ENTRY.try_with(|entry| entry.0.set(true));
+        }
+        None => {
+            if ENTRY.try_with(|entry| entry.0.replace(false)) == Ok(true) {
+                lock_threads().remove(&tid);
+            }
+        }
+    }
+}
+
+/// Forgets every thread in a forked child, where the only thread left has a new tid.
+#[cfg_attr(not(feature = "hook-daemon"), allow(dead_code))]
+pub(crate) fn forked() {
+    crashdump::forked();
+    lock_threads().clear();
+    let _ = ENTRY.try_with(|entry| entry.0.set(false));
+}
+
+/// What thread `tid` is serving, if the shim set its context.
+pub(crate) fn lookup(tid: pid_t) -> Option<SpanContext> {
+    lock_threads().get(&tid).cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanId, TraceFlags, TraceId, TraceState};
+
+    #[test]
+    fn entries_follow_the_thread() {
+        let sc = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let (tx, rx) = std::sync::mpsc::channel();
+        let (done_tx, done_rx) = std::sync::mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || {
+            set_current(Some(&sc));
+            tx.send(unsafe { libc::gettid() }).unwrap();
+            done_rx.recv().unwrap();
+        });
+        let tid = rx.recv().unwrap();
+        assert!(lookup(tid).is_some());
+        done_tx.send(()).unwrap();
+        thread.join().unwrap();
+        // removed by the thread's exit
+        assert_eq!(lookup(tid), None);
+
+        set_current(Some(&SpanContext::empty_context()));
+        assert_eq!(lookup(unsafe { libc::gettid() }), None);
+    }
+}
@@ -0,0 +1,150 @@
+// src/reload.rs
+//
+// Configuration reload, so filters, modes and the log level of a
+// long-running daemon can be changed without restarting it: edit the
+// OTEL_POSIX_PROP_CONFIG file, then
+//
+//     OTEL_POSIX_PROP_RELOAD_SIGNAL=HUP ./daemon &
+//     kill -HUP $!
+//
+// or send the control socket `reload`. As with the thread dump, the
+// handler only writes a byte to a pipe, and a thread of our own waiting on
+// the other end does the reading. Settings taken by load-time constructors
+// (the exporter, the dump and reload signals, the control socket, the
+// flight recorder) stay as they were. Read straight from the environment
+// by a load-time constructor, like the thread dump.
+
+use crate::config;
+use crate::dump;
+use crate::log::log_warn;
+use libc::c_int;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+// write end of the pipe the handler pokes
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+#[used]
+#[unsafe(link_section = ".init_array")]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(|| {
+        let Ok(value) = std::env::var("OTEL_POSIX_PROP_RELOAD_SIGNAL") else {
+            return;
+        };
+        match parse_signal(&value) {
+            Some(signal) => install(signal),
+            None => {
+                log_warn!("unknown OTEL_POSIX_PROP_RELOAD_SIGNAL {value:?}, no reload handler")
+            }
+        }
+    });
+}
+
+/// Parses a signal name or number like the dump's; `on`/`true` pick `SIGHUP`.
+fn parse_signal(s: &str) -> Option<c_int> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "on" | "true" | "yes" => Some(libc::SIGHUP),
+        _ => dump::parse_signal(s),
+    }
+}
+
+/// Starts the reload thread and installs the handler for `signal`, unless the process
+/// already handles it.
+fn install(signal: c_int) {
+    let mut old = unsafe { std::mem::zeroed::<libc::sigaction>() };
+    unsafe { libc::sigaction(signal, std::ptr::null(), &mut old) };
+    if old.sa_sigaction != libc::SIG_DFL && old.sa_sigaction != on_signal as *const () as usize {
+        log_warn!("signal {signal} already has a handler, no reload handler");
+        return;
+    }
+    let mut fds = [0 as c_int; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        log_warn!(
+            "failed to create the reload pipe: {}",
+            std::io::Error::last_os_error()
+        );
+        return;
+    }
+    // our own plumbing, not a thread of the host's
+    let _suppress = crate::suppress_wrapping();
+    let [read, write] = fds;
+    let spawned = std::thread::Builder::new()
+        .name("otel-posix-reload".into())
+        .spawn(move || wait(read));
+    if let Err(e) = spawned {
+        log_warn!("failed to start the reload thread: {e}");
+        unsafe { libc::close(read) };
+        unsafe { libc::close(write) };
+        return;
+    }
+    PIPE.store(write, Ordering::Release);
+    unsafe { libc::pthread_atfork(None, None, Some(forked)) };
+
+    let mut action = unsafe { std::mem::zeroed::<libc::sigaction>() };
+    action.sa_sigaction = on_signal as *const () as usize;
+    action.sa_flags = libc::SA_RESTART;
+    unsafe { libc::sigemptyset(&mut action.sa_mask) };
+    unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) };
+}
+
+extern "C" fn on_signal(_: c_int) {
+    // write() is async-signal-safe, but may clobber the interrupted code's errno
+    let errno = unsafe { *libc::__errno_location() };
+    let fd = PIPE.load(Ordering::Acquire);
+    if fd >= 0 {
+        unsafe { libc::write(fd, b"r".as_ptr().cast(), 1) };
+    }
+    unsafe { *libc::__errno_location() = errno };
+}
+
+// the reload thread stays behind in the parent, which shouldn't reload on the child's signal
+extern "C" fn forked() {
+    PIPE.store(-1, Ordering::Release);
+}
+
+fn wait(fd: c_int) {
+    let mut byte = 0u8;
+    loop {
+        match unsafe { libc::read(fd, (&mut byte as *mut u8).cast(), 1) } {
+            1 => {
+                if let Err(e) = config::reload() {
+                    log_warn!("failed to reload config file {e}, keeping its earlier settings");
+                }
+            }
+            -1 if std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted => {}
+            _ => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn signal_names() {
+        assert_eq!(parse_signal("on"), Some(libc::SIGHUP));
+        assert_eq!(parse_signal("SIGHUP"), Some(libc::SIGHUP));
+        assert_eq!(parse_signal("usr1"), Some(libc::SIGUSR1));
+        assert_eq!(parse_signal("off"), None);
+    }
+
+    #[test]
+    fn signal_reloads_the_configuration() {
+        install(libc::SIGHUP);
+        unsafe { std::env::set_var("OTEL_POSIX_PROP_DLOPEN_EVENTS", "true") };
+        unsafe { libc::raise(libc::SIGHUP) };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !config::config().dlopen_events && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(config::config().dlopen_events);
+
+        unsafe { std::env::remove_var("OTEL_POSIX_PROP_DLOPEN_EVENTS") };
+        config::reload().unwrap();
+        assert!(!config::config().dlopen_events);
+    }
+}
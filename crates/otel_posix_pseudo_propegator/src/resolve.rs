@@ -0,0 +1,246 @@
+// src/resolve.rs
+//
+// Finding the real pthread_create behind ours. On glibc the symbol is
+// versioned: before 2.34 it lived in libpthread (GLIBC_2.2.5 on x86_64,
+// GLIBC_2.0/2.1 on i386, ...), since 2.34 libc carries it as GLIBC_2.34 with
+// the old versions kept as compat aliases. Plain dlsym picks the default
+// version of the *next* object, which isn't always the one applications
+// bound to, so try the versions explicitly first and only then fall back.
+//
+// dlsym itself may allocate (glibc callocs its error state, musl and others
+// can malloc), and a malloc replacement that starts threads or logs from
+// inside the allocator lands right back in one of our interposers while the
+// lookup is still running. Every lookup therefore sets a per-thread flag
+// (ld_interpose's, shared with the functions it writes for interpose.rs);
+// an interposer called while it is set gets no symbol and falls back to a
+// raw syscall or an error instead of recursing into dlsym.
+
+use crate::PthreadCreateFn;
+use crate::log::log_warn;
+pub(crate) use ld_interpose::in_progress;
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+use ld_interpose::next_versioned;
+use ld_interpose::{next as next_default, resolving};
+use std::ffi::{CStr, c_void};
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+// the symbols cached_next has resolved, for the status report; fixed-size so that
+// recording one never allocates in the middle of an interposed call
+const RESOLVED_MAX: usize = 32;
+static RESOLVED_LEN: AtomicUsize = AtomicUsize::new(0);
+static RESOLVED: [(AtomicPtr<libc::c_char>, AtomicPtr<c_void>); RESOLVED_MAX] = [const {
+    (
+        AtomicPtr::new(std::ptr::null_mut()),
+        AtomicPtr::new(std::ptr::null_mut()),
+    )
+}; RESOLVED_MAX];
+
+/// glibc versions of `pthread_create`, newest first.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+pub(crate) const GLIBC_VERSIONS: &[&CStr] = &[
+    c"GLIBC_2.34",
+    #[cfg(target_arch = "x86_64")]
+    c"GLIBC_2.2.5",
+    #[cfg(target_arch = "x86")]
+    c"GLIBC_2.1",
+    #[cfg(target_arch = "x86")]
+    c"GLIBC_2.0",
+    #[cfg(target_arch = "aarch64")]
+    c"GLIBC_2.17",
+    #[cfg(any(target_arch = "powerpc64", target_arch = "s390x"))]
+    c"GLIBC_2.3",
+    #[cfg(target_arch = "riscv64")]
+    c"GLIBC_2.27",
+    #[cfg(target_arch = "arm")]
+    c"GLIBC_2.4",
+];
+
+/// [`next_default`] cached in `cache`, or `None` when called from inside another lookup
+/// on this thread or when the symbol can't be found (reported, and retried next time).
+#[cfg_attr(not(otel_posix_hooks), allow(dead_code))]
+pub(crate) fn cached_next(cache: &AtomicPtr<c_void>, symbol: &'static CStr) -> Option<*mut c_void> {
+    let mut sym = cache.load(Ordering::Acquire);
+    if sym.is_null() {
+        sym = resolving(|| next_default(symbol))?;
+        if sym.is_null() {
+            report_missing(symbol);
+            return None;
+        }
+        cache.store(sym, Ordering::Release);
+        remember(symbol, sym);
+    }
+    Some(sym)
+}
+
+#[cfg_attr(not(otel_posix_hooks), allow(dead_code))]
+fn remember(symbol: &'static CStr, sym: *mut c_void) {
+    let i = RESOLVED_LEN.fetch_add(1, Ordering::Relaxed);
+    if let Some((name, addr)) = RESOLVED.get(i) {
+        name.store(symbol.as_ptr().cast_mut(), Ordering::Relaxed);
+        addr.store(sym, Ordering::Release);
+    }
+}
+
+/// The symbols resolved through [`cached_next`] so far, with the object each was found
+/// in.
+pub(crate) fn resolved() -> Vec<(&'static str, Option<String>)> {
+    let len = RESOLVED_LEN.load(Ordering::Relaxed).min(RESOLVED_MAX);
+    let mut found: Vec<(&'static str, Option<String>)> = Vec::new();
+    for (name, addr) in &RESOLVED[..len] {
+        let addr = addr.load(Ordering::Acquire);
+        if addr.is_null() {
+            // claimed, but not written yet
+            continue;
+        }
+        // the names are the interposers' c"..." literals
+        let name = unsafe { CStr::from_ptr(name.load(Ordering::Relaxed)) };
+        let Ok(name) = name.to_str() else { continue };
+        // racing resolvers can both record the same symbol
+        if !found.iter().any(|(n, _)| *n == name) {
+            found.push((name, object_name(addr)));
+        }
+    }
+    found
+}
+
+/// Warns, once per process, that the real `symbol` couldn't be found and the interposer
+/// is failing the call instead.
+pub(crate) fn report_missing(symbol: &CStr) {
+    static REPORTED: AtomicBool = AtomicBool::new(false);
+    if !REPORTED.swap(true, Ordering::Relaxed) {
+        log_warn!("failed to find the original {symbol:?}, failing calls to it until it resolves");
+    }
+}
+
+/// Resolves the real `pthread_create`, trying the known glibc versions before plain dlsym.
+/// `None` if it can't be found, or when called from inside another lookup.
+pub(crate) fn pthread_create() -> Option<PthreadCreateFn> {
+    resolving(find_pthread_create).flatten()
+}
+
+fn find_pthread_create() -> Option<PthreadCreateFn> {
+    // a sanitizer runtime in the same object as us, which the checks below would take for
+    // ourselves
+    if let Some(interceptor) = crate::sanitizer::interceptor() {
+        return Some(unsafe { std::mem::transmute::<*mut c_void, PthreadCreateFn>(interceptor) });
+    }
+    let next = next_default(c"pthread_create");
+    // another interposer preloaded after us (jemalloc, a sanitizer runtime, a security
+    // shim) gets the call and passes it on itself; only libc's own is looked up by version
+    let sym = if !next.is_null() && !is_libc(next) {
+        next
+    } else {
+        next_libc_versioned().unwrap_or(next)
+    };
+    // the copy the LD_AUDIT entry points load comes after libc, with nothing next to it;
+    // the global lookup finds libc's there
+    #[cfg(feature = "audit")]
+    let sym = if sym.is_null() {
+        unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"pthread_create".as_ptr()) }
+    } else {
+        sym
+    };
+    // never hand back ourselves, that would recurse forever
+    (!sym.is_null() && !is_ours(sym))
+        .then(|| unsafe { std::mem::transmute::<*mut c_void, PthreadCreateFn>(sym) })
+}
+
+/// The versioned `pthread_create` of glibc, if that's where the lookup leads.
+fn next_libc_versioned() -> Option<*mut c_void> {
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    let versioned = GLIBC_VERSIONS
+        .iter()
+        .map(|version| next_versioned(c"pthread_create", version))
+        .find(|sym| !sym.is_null());
+    #[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+    let versioned = None;
+    versioned
+}
+
+/// The file name of the object `sym` lives in.
+pub(crate) fn object_name(sym: *const c_void) -> Option<String> {
+    let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+    if unsafe { libc::dladdr(sym, &mut info) } == 0 || info.dli_fname.is_null() {
+        return None;
+    }
+    let path = unsafe { CStr::from_ptr(info.dli_fname) }.to_string_lossy();
+    Some(path.rsplit('/').next().unwrap_or_default().to_string())
+}
+
+/// Whether `sym` is the C library's own, rather than another interposer's.
+pub(crate) fn is_libc(sym: *mut c_void) -> bool {
+    // glibc (libpthread before 2.34), bionic, musl (whose libc is its loader)
+    const LIBC: &[&str] = &["libc.so", "libc-", "libpthread", "ld-musl", "libc.musl"];
+    object_name(sym).is_none_or(|name| LIBC.iter().any(|prefix| name.starts_with(prefix)))
+}
+
+/// The object the interposer forwards `pthread_create` to: libc, or another interposer
+/// stacked after this one.
+pub(crate) fn next_object() -> Option<String> {
+    let real = crate::real_pthread_create()?;
+    object_name(real as *const c_void)
+}
+
+/// Whether `sym` lives in this library. Compared by object rather than against
+/// `crate::pthread_create`, whose address goes through the GOT and is libc's own when we
+/// are loaded after libc.
+pub(crate) fn is_ours(sym: *mut c_void) -> bool {
+    let object = |addr: *const c_void| {
+        let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+        (unsafe { libc::dladdr(addr, &mut info) } != 0).then_some(info.dli_fbase)
+    };
+    let own = object(find_pthread_create as *const c_void);
+    own.is_some() && object(sym) == own
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_past_our_own_symbol() {
+        let real = pthread_create().expect("no pthread_create found");
+        assert_ne!(real as *mut c_void, crate::pthread_create as *mut c_void);
+    }
+
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    #[test]
+    fn versions_agree_with_default() {
+        // every version glibc still carries is an alias of the same implementation
+        // on modern glibc, and at least one of them has to be there
+        let default = next_default(c"pthread_create");
+        let found: Vec<_> = GLIBC_VERSIONS
+            .iter()
+            .map(|v| next_versioned(c"pthread_create", v))
+            .filter(|sym| !sym.is_null())
+            .collect();
+        assert!(!found.is_empty(), "no versioned pthread_create resolved");
+        assert!(found.iter().all(|&sym| sym == default));
+        assert!(next_versioned(c"pthread_create", c"GLIBC_0.0").is_null());
+    }
+
+    // an instrumented build forwards to the runtime's interceptor
+    #[cfg(not(otel_posix_sanitize))]
+    #[test]
+    fn forwards_to_libc_without_other_interposers() {
+        let real = pthread_create().expect("no pthread_create found");
+        assert!(is_libc(real as *mut c_void));
+        assert!(!is_libc(find_pthread_create as *mut c_void));
+    }
+
+    #[test]
+    fn missing_symbols_are_not_fatal() {
+        static CACHE: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+        assert_eq!(cached_next(&CACHE, c"otel_posix_no_such_symbol"), None);
+        assert!(CACHE.load(Ordering::Relaxed).is_null());
+    }
+
+    #[test]
+    fn nested_lookups_are_refused() {
+        static CACHE: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+        let nested = resolving(|| (in_progress(), cached_next(&CACHE, c"getpid")));
+        assert_eq!(nested, Some((true, None)));
+        assert!(!in_progress());
+        assert!(cached_next(&CACHE, c"getpid").is_some());
+    }
+}
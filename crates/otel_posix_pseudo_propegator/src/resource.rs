@@ -0,0 +1,297 @@
+// src/resource.rs
+//
+// Resource attributes for the provider the shim installs itself, so spans
+// from a preloaded binary say where they came from: the process (named
+// after argv[0] unless OTEL_SERVICE_NAME says otherwise, where the SDK
+// would call it unknown_service) and its host. Inside a container the
+// container id is read from the cgroup paths (or, with a private cgroup
+// namespace, from the mounts the runtime sets up); inside a Kubernetes pod
+// the pod comes from the downward-API variables, falling back to what every
+// pod has anyway: its hostname and service account namespace.
+//
+// Whatever OTEL_SERVICE_NAME and OTEL_RESOURCE_ATTRIBUTES say wins over
+// anything detected here.
+
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::resource::{
+    EnvResourceDetector, ResourceDetector, TelemetryResourceDetector,
+};
+use std::path::Path;
+
+// the downward-API variable names in common use, by attribute
+const K8S_VARS: &[(&str, &[&str])] = &[
+    ("k8s.pod.name", &["K8S_POD_NAME", "POD_NAME"]),
+    ("k8s.pod.uid", &["K8S_POD_UID", "POD_UID"]),
+    (
+        "k8s.namespace.name",
+        &["K8S_NAMESPACE_NAME", "K8S_POD_NAMESPACE", "POD_NAMESPACE"],
+    ),
+    ("k8s.node.name", &["K8S_NODE_NAME", "NODE_NAME"]),
+    (
+        "k8s.container.name",
+        &["K8S_CONTAINER_NAME", "CONTAINER_NAME"],
+    ),
+];
+
+const SERVICE_ACCOUNT_NAMESPACE: &str = "/var/run/secrets/kubernetes.io/serviceaccount/namespace";
+
+/// The resource for the auto-installed providers.
+pub(crate) fn resource() -> Resource {
+    let builder = Resource::builder_empty()
+        .with_detectors(&[Box::new(ProcessDetector), Box::new(ContainerDetector)])
+        // the user's own settings last, so they win
+        .with_detectors(&[
+            Box::new(TelemetryResourceDetector),
+            Box::new(EnvResourceDetector::new()),
+        ]);
+    match std::env::var("OTEL_SERVICE_NAME") {
+        Ok(name) if !name.trim().is_empty() => builder.with_service_name(name.trim().to_string()),
+        _ => builder,
+    }
+    .build()
+}
+
+#[derive(Debug)]
+struct ProcessDetector;
+
+impl ResourceDetector for ProcessDetector {
+    fn detect(&self) -> Resource {
+        let argv0 = std::env::args_os().next();
+        let exe = std::env::current_exe().ok();
+        Resource::builder_empty()
+            .with_attributes(process_attributes(
+                argv0.as_deref().map(Path::new),
+                exe.as_deref(),
+                std::process::id(),
+                host_name(),
+            ))
+            .build()
+    }
+}
+
+/// `service.name`, `process.*`, `host.name` and `os.type` for the process started as
+/// `argv0` from the executable `exe`.
+fn process_attributes(
+    argv0: Option<&Path>,
+    exe: Option<&Path>,
+    pid: u32,
+    host: Option<String>,
+) -> Vec<KeyValue> {
+    let file_name = |path: &Path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .filter(|name| !name.is_empty())
+    };
+    let exe_name = exe.and_then(file_name);
+    // argv[0] is how the process is known (busybox applets, login shells' "-bash")
+    let service = argv0
+        .and_then(file_name)
+        .map(|name| name.trim_start_matches('-').to_string())
+        .filter(|name| !name.is_empty())
+        .or_else(|| exe_name.clone());
+
+    let mut attributes = vec![KeyValue::new("process.pid", i64::from(pid))];
+    if let Some(service) = service {
+        attributes.push(KeyValue::new("service.name", service));
+    }
+    if let Some(exe) = exe {
+        attributes.push(KeyValue::new(
+            "process.executable.path",
+            exe.to_string_lossy().into_owned(),
+        ));
+    }
+    if let Some(exe_name) = exe_name {
+        attributes.push(KeyValue::new("process.executable.name", exe_name));
+    }
+    if let Some(host) = host {
+        attributes.push(KeyValue::new("host.name", host));
+    }
+    attributes.push(KeyValue::new("os.type", os_type()));
+    attributes
+}
+
+fn host_name() -> Option<String> {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) } != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    (len > 0).then(|| String::from_utf8_lossy(&buf[..len]).into_owned())
+}
+
+/// The semantic-conventions name for the OS we were built for.
+fn os_type() -> &'static str {
+    match std::env::consts::OS {
+        "macos" | "ios" => "darwin",
+        "illumos" => "solaris",
+        os => os,
+    }
+}
+
+#[derive(Debug)]
+struct ContainerDetector;
+
+impl ResourceDetector for ContainerDetector {
+    fn detect(&self) -> Resource {
+        let var = |key: &str| std::env::var(key).ok();
+        let read = |path: &str| std::fs::read_to_string(path).ok();
+        Resource::builder_empty()
+            .with_attributes(container_attributes(var, read))
+            .build()
+    }
+}
+
+/// `container.id` and the `k8s.*` attributes that can be found through `var` (the
+/// environment) and `read` (files).
+fn container_attributes(
+    var: impl Fn(&str) -> Option<String>,
+    read: impl Fn(&str) -> Option<String>,
+) -> Vec<KeyValue> {
+    let mut attributes = Vec::new();
+    let id = read("/proc/self/cgroup")
+        .and_then(|cgroup| id_from_cgroup(&cgroup))
+        .or_else(|| read("/proc/self/mountinfo").and_then(|m| id_from_mountinfo(&m)));
+    if let Some(id) = id {
+        attributes.push(KeyValue::new("container.id", id));
+    }
+
+    let in_pod = var("KUBERNETES_SERVICE_HOST").is_some();
+    for (key, names) in K8S_VARS {
+        let value = names
+            .iter()
+            .find_map(|name| var(name).filter(|v| !v.trim().is_empty()))
+            .or_else(|| match *key {
+                // a pod's hostname is its name unless the spec sets another
+                "k8s.pod.name" if in_pod => var("HOSTNAME"),
+                "k8s.namespace.name" if in_pod => read(SERVICE_ACCOUNT_NAMESPACE),
+                _ => None,
+            });
+        if let Some(value) = value {
+            attributes.push(KeyValue::new(*key, value.trim().to_string()));
+        }
+    }
+    attributes
+}
+
+/// The container id in a `/proc/self/cgroup` listing: the last 64-hex-digit name in a
+/// path, as docker, containerd and CRI-O all use (`.../docker-<id>.scope`,
+/// `/kubepods/.../<id>`).
+fn id_from_cgroup(cgroup: &str) -> Option<String> {
+    cgroup
+        .lines()
+        .filter_map(|line| line.splitn(3, ':').nth(2))
+        .flat_map(|path| path.split('/').rev())
+        .find_map(container_id)
+}
+
+/// The container id from the runtime's bind mounts (`/etc/hostname` and friends come
+/// from `.../containers/<id>/...`), for a private cgroup namespace where the cgroup path
+/// is just `/`.
+fn id_from_mountinfo(mountinfo: &str) -> Option<String> {
+    mountinfo
+        .lines()
+        .filter(|line| line.contains("/containers/") || line.contains("/sandboxes/"))
+        .filter_map(|line| line.split_whitespace().nth(3))
+        .flat_map(|root| root.split('/'))
+        .find_map(container_id)
+}
+
+/// A 64-hex-digit id in one path segment, with its runtime's prefix and suffix removed.
+fn container_id(segment: &str) -> Option<String> {
+    let segment = segment.strip_suffix(".scope").unwrap_or(segment);
+    let id = segment.rsplit(['-', ':']).next()?;
+    (id.len() == 64 && id.bytes().all(|b| b.is_ascii_hexdigit())).then(|| id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "2f6e1ad8d0f8e5d9c1b2a3f4e5d6c7b8a9f0e1d2c3b4a5f6e7d8c9b0a1f2e3d4";
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: Vec<(String, String)> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| vars.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone())
+    }
+
+    #[test]
+    fn process_names() {
+        let attributes = process_attributes(
+            Some(Path::new("-bash")),
+            Some(Path::new("/usr/bin/bash")),
+            42,
+            Some("build-box".to_string()),
+        );
+        assert_eq!(
+            attributes,
+            [
+                KeyValue::new("process.pid", 42),
+                KeyValue::new("service.name", "bash"),
+                KeyValue::new("process.executable.path", "/usr/bin/bash"),
+                KeyValue::new("process.executable.name", "bash"),
+                KeyValue::new("host.name", "build-box"),
+                KeyValue::new("os.type", os_type()),
+            ]
+        );
+
+        // a busybox applet is named for the applet, not the binary
+        let attributes = process_attributes(
+            Some(Path::new("/sbin/udhcpc")),
+            Some(Path::new("/bin/busybox")),
+            1,
+            None,
+        );
+        assert!(attributes.contains(&KeyValue::new("service.name", "udhcpc")));
+        assert!(attributes.contains(&KeyValue::new("process.executable.name", "busybox")));
+
+        // without argv, the executable's name
+        let attributes = process_attributes(None, Some(Path::new("/opt/app/worker")), 1, None);
+        assert!(attributes.contains(&KeyValue::new("service.name", "worker")));
+    }
+
+    #[test]
+    fn container_ids() {
+        let v1 = format!("12:memory:/kubepods/burstable/pod1234/{ID}\n0::/\n");
+        assert_eq!(id_from_cgroup(&v1).as_deref(), Some(ID));
+        let v2 = format!("0::/system.slice/docker-{ID}.scope\n");
+        assert_eq!(id_from_cgroup(&v2).as_deref(), Some(ID));
+        let crio = format!("0::/kubepods.slice/kubepods-pod1.slice/crio-{ID}.scope\n");
+        assert_eq!(id_from_cgroup(&crio).as_deref(), Some(ID));
+        assert_eq!(id_from_cgroup("0::/user.slice/session-2.scope\n"), None);
+
+        let mountinfo = format!(
+            "1 0 8:1 / / rw - ext4 /dev/sda1 rw\n\
+             2 1 8:1 /var/lib/docker/containers/{ID}/hostname /etc/hostname rw - ext4 /dev/sda1 rw\n"
+        );
+        assert_eq!(id_from_mountinfo(&mountinfo).as_deref(), Some(ID));
+    }
+
+    #[test]
+    fn pod_attributes() {
+        let read = |path: &str| (path == SERVICE_ACCOUNT_NAMESPACE).then(|| "shop\n".to_string());
+        let vars = env(&[
+            ("KUBERNETES_SERVICE_HOST", "10.0.0.1"),
+            ("HOSTNAME", "checkout-7d9f"),
+            ("K8S_NODE_NAME", "node-3"),
+        ]);
+        assert_eq!(
+            container_attributes(vars, read),
+            [
+                KeyValue::new("k8s.pod.name", "checkout-7d9f"),
+                KeyValue::new("k8s.namespace.name", "shop"),
+                KeyValue::new("k8s.node.name", "node-3"),
+            ]
+        );
+
+        // outside a pod, HOSTNAME is just the host's name
+        let vars = env(&[("HOSTNAME", "build-box"), ("POD_NAMESPACE", "ci")]);
+        assert_eq!(
+            container_attributes(vars, |_| None),
+            [KeyValue::new("k8s.namespace.name", "ci")]
+        );
+    }
+}
@@ -0,0 +1,124 @@
+// src/rusage.rs
+//
+// What a thread cost, put on the span that lasts as long as it does (a
+// lifetime-mode `thread` span, or an auto root span) when it ends, which
+// turns those traces into a lightweight per-thread profile. CPU time and
+// context switches come from getrusage(RUSAGE_THREAD) and cover the whole
+// thread. The kernel only keeps a peak RSS for the process, so that one is
+// read when the thread starts too, and given as how far the peak rose
+// while it ran.
+
+use opentelemetry::KeyValue;
+use std::time::Duration;
+
+/// Resource usage of the calling thread, as of when it was read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Usage {
+    user: Duration,
+    system: Duration,
+    // the process's peak, in kilobytes
+    max_rss: i64,
+    voluntary: i64,
+    involuntary: i64,
+}
+
+/// The calling thread's usage, where the kernel keeps it per thread.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn thread() -> Option<Usage> {
+    let mut ru = unsafe { std::mem::zeroed::<libc::rusage>() };
+    if unsafe { libc::getrusage(libc::RUSAGE_THREAD, &mut ru) } != 0 {
+        return None;
+    }
+    let time = |tv: libc::timeval| {
+        Duration::new(tv.tv_sec as u64, 0) + Duration::from_micros(tv.tv_usec as u64)
+    };
+    Some(Usage {
+        user: time(ru.ru_utime),
+        system: time(ru.ru_stime),
+        max_rss: ru.ru_maxrss as i64,
+        voluntary: ru.ru_nvcsw as i64,
+        involuntary: ru.ru_nivcsw as i64,
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn thread() -> Option<Usage> {
+    None
+}
+
+/// Attributes for a thread that used `end` by the time it ended, having started when the
+/// process's usage was `start`.
+pub(crate) fn attributes(start: Option<Usage>, end: Usage) -> Vec<KeyValue> {
+    let mut attributes = vec![
+        KeyValue::new("otel_posix.thread.cpu.user", end.user.as_secs_f64()),
+        KeyValue::new("otel_posix.thread.cpu.system", end.system.as_secs_f64()),
+        KeyValue::new(
+            "otel_posix.thread.context_switches.voluntary",
+            end.voluntary,
+        ),
+        KeyValue::new(
+            "otel_posix.thread.context_switches.involuntary",
+            end.involuntary,
+        ),
+    ];
+    if let Some(start) = start {
+        attributes.push(KeyValue::new(
+            "otel_posix.thread.max_rss.delta",
+            (end.max_rss - start.max_rss).max(0) * 1024,
+        ));
+    }
+    attributes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes_from_usage() {
+        let start = Usage {
+            user: Duration::ZERO,
+            system: Duration::ZERO,
+            max_rss: 1000,
+            voluntary: 0,
+            involuntary: 0,
+        };
+        let end = Usage {
+            user: Duration::from_millis(1500),
+            system: Duration::from_micros(250),
+            max_rss: 1200,
+            voluntary: 7,
+            involuntary: 2,
+        };
+        assert_eq!(
+            attributes(Some(start), end),
+            vec![
+                KeyValue::new("otel_posix.thread.cpu.user", 1.5),
+                KeyValue::new("otel_posix.thread.cpu.system", 0.00025),
+                KeyValue::new("otel_posix.thread.context_switches.voluntary", 7),
+                KeyValue::new("otel_posix.thread.context_switches.involuntary", 2),
+                KeyValue::new("otel_posix.thread.max_rss.delta", 200 * 1024),
+            ]
+        );
+        assert_eq!(attributes(None, end).len(), 4);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn usage_is_per_thread() {
+        let cpu = |spin: Duration| {
+            std::thread::spawn(move || {
+                let start = std::time::Instant::now();
+                while start.elapsed() < spin {}
+                let usage = thread().unwrap();
+                assert!(usage.max_rss > 0);
+                usage.user + usage.system
+            })
+            .join()
+            .unwrap()
+        };
+        let busy = cpu(Duration::from_millis(50));
+        assert!(busy >= Duration::from_millis(20), "{busy:?}");
+        assert!(cpu(Duration::ZERO) < busy);
+    }
+}
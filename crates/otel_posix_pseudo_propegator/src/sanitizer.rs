@@ -0,0 +1,297 @@
+// src/sanitizer.rs
+//
+// Living alongside a sanitizer runtime. ASan, TSan and MSan interpose
+// pthread_create too, and wrap the start routine in their own entry point
+// that sets up the runtime's per-thread state before anything else runs
+// on the new thread. Two things follow:
+//
+// - The order of the interposers matters. When ours is found first (the
+//   shim preloaded ahead of the runtime) it forwards to the runtime's, and
+//   our trampoline runs inside the runtime's entry point. When the
+//   runtime's is found first (LD_PRELOAD=libtsan.so:shim, or a runtime
+//   linked statically into the executable), our trampoline would run on a
+//   thread TSan or MSan haven't set up yet, which crashes them; threads are
+//   passed through instead, with a warning. ASan copes either way, and
+//   insists on coming first itself.
+// - TSan can't see the synchronization in uninstrumented code like ours, so
+//   a launcher slot recycled from one thread to another, or a map behind a
+//   std Mutex, looks like a race. The shim provides TSan's default
+//   suppressions with a called_from_lib entry for itself, which has TSan
+//   ignore what the interceptors see from inside it, and LSan's with one for
+//   the memory it keeps for the process lifetime. An executable that defines
+//   its own takes precedence.
+//
+// The runtime is looked for once, by a load-time constructor: everything
+// loaded with the program, a sanitizer runtime included, is there by then.
+// A library initialized ahead of us can create threads before that
+// constructor has run, so the first of them looks for it instead.
+
+#[cfg(feature = "preload")]
+use crate::log::log_warn;
+#[cfg(feature = "preload")]
+use libc::c_char;
+#[cfg(feature = "preload")]
+use std::cell::UnsafeCell;
+use std::ffi::{CStr, c_void};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// A sanitizer runtime loaded into the process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum Sanitizer {
+    Address = 1,
+    Thread,
+    Memory,
+    HwAddress,
+    Leak,
+}
+
+impl Sanitizer {
+    /// Each runtime, by a symbol only it exports.
+    const ALL: [(Sanitizer, &'static CStr); 5] = [
+        (Sanitizer::Thread, c"__tsan_init"),
+        (Sanitizer::Memory, c"__msan_init"),
+        (Sanitizer::HwAddress, c"__hwasan_init"),
+        (Sanitizer::Address, c"__asan_init"),
+        // standalone only; ASan carries LSan inside
+        (Sanitizer::Leak, c"__lsan_do_leak_check"),
+    ];
+
+    fn from_u8(n: u8) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .map(|(s, _)| s)
+            .find(|s| *s as u8 == n)
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Thread => "thread",
+            Sanitizer::Memory => "memory",
+            Sanitizer::HwAddress => "hwaddress",
+            Sanitizer::Leak => "leak",
+        }
+    }
+
+    /// Whether the runtime needs its entry point to run first on a new thread.
+    #[cfg_attr(not(feature = "preload"), allow(dead_code))]
+    fn owns_thread_start(self) -> bool {
+        matches!(self, Sanitizer::Thread | Sanitizer::Memory)
+    }
+}
+
+static DETECTED: AtomicU8 = AtomicU8::new(0);
+// set once DETECTED (and IN_FRONT) hold what was found
+static LOOKED: AtomicBool = AtomicBool::new(false);
+// the runtime's pthread_create is called before ours
+#[cfg(feature = "preload")]
+static IN_FRONT: AtomicBool = AtomicBool::new(false);
+
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+#[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__mod_init_func"))]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(detect);
+}
+
+/// Looks for the runtime, unless that's been done already. Racing callers find the same.
+fn look() {
+    if !LOOKED.load(Ordering::Acquire) {
+        detect();
+    }
+}
+
+fn detect() {
+    find();
+    LOOKED.store(true, Ordering::Release);
+}
+
+fn find() {
+    let Some((sanitizer, marker)) = Sanitizer::ALL
+        .into_iter()
+        .map(|(s, symbol)| (s, global(symbol)))
+        .find(|(_, sym)| !sym.is_null())
+    else {
+        return;
+    };
+    #[cfg(feature = "preload")]
+    {
+        let first = global(c"pthread_create");
+        let in_front = !first.is_null()
+            && !crate::resolve::is_ours(first)
+            && object_base(first) == object_base(marker);
+        IN_FRONT.store(in_front, Ordering::Relaxed);
+    }
+    #[cfg(not(feature = "preload"))]
+    let _ = marker;
+    DETECTED.store(sanitizer as u8, Ordering::Release);
+}
+
+/// The sanitizer runtime in the process, if any.
+pub(crate) fn detected() -> Option<Sanitizer> {
+    look();
+    Sanitizer::from_u8(DETECTED.load(Ordering::Acquire))
+}
+
+/// Whether threads have to be passed through because the runtime's own thread setup
+/// can't run ahead of our trampoline. Warns the first time it says so.
+#[cfg(feature = "preload")]
+pub(crate) fn blocks_wrapping() -> bool {
+    static WARNED: AtomicBool = AtomicBool::new(false);
+    let Some(sanitizer) = detected().filter(|s| s.owns_thread_start()) else {
+        return false;
+    };
+    let blocks = IN_FRONT.load(Ordering::Relaxed);
+    // not from the constructor: the runtime may not be ready to print anything then
+    if blocks && !WARNED.swap(true, Ordering::Relaxed) {
+        log_warn!(
+            "the {} sanitizer's pthread_create runs before ours, passing threads through; \
+             preload the shim ahead of its runtime to propagate context",
+            sanitizer.as_str()
+        );
+    }
+    blocks
+}
+
+/// Under linker-wrap, `__real_pthread_create` is the runtime's, always called after ours.
+#[cfg(not(feature = "preload"))]
+pub(crate) fn blocks_wrapping() -> bool {
+    false
+}
+
+/// The runtime's `pthread_create` interceptor, for a shim built instrumented itself
+/// (`-Zsanitizer`). Linked into the same executable as the runtime, the shim's own
+/// `pthread_create` takes the place of the runtime's and `RTLD_NEXT` goes straight to libc,
+/// so the runtime would never see a thread start; the interceptor is called instead,
+/// unless the runtime's is already called before ours.
+#[cfg(all(feature = "preload", otel_posix_sanitize))]
+pub(crate) fn interceptor() -> Option<*mut c_void> {
+    unsafe extern "C" {
+        // every sanitizer runtime's, whose symbols the executable doesn't export for dlsym
+        fn __interceptor_pthread_create(
+            tid: *mut libc::pthread_t,
+            attr: *const libc::pthread_attr_t,
+            start_routine: crate::StartRoutine,
+            arg: *mut c_void,
+        ) -> libc::c_int;
+    }
+    look();
+    (!IN_FRONT.load(Ordering::Relaxed)).then_some(__interceptor_pthread_create as *mut c_void)
+}
+
+/// Only instrumented builds link against the runtime.
+#[cfg(all(feature = "preload", not(otel_posix_sanitize)))]
+pub(crate) fn interceptor() -> Option<*mut c_void> {
+    None
+}
+
+// filled once each, by the runtime's initialization, which runs before any other thread
+#[cfg(feature = "preload")]
+struct Suppressions(UnsafeCell<[u8; 256]>);
+
+#[cfg(feature = "preload")]
+unsafe impl Sync for Suppressions {}
+
+#[cfg(feature = "preload")]
+impl Suppressions {
+    const fn new() -> Self {
+        Suppressions(UnsafeCell::new([0; 256]))
+    }
+
+    /// One `kind:<this library>` line, written into the buffer.
+    fn fill(&self, kind: &[u8]) -> *const c_char {
+        let buf = unsafe { &mut *self.0.get() };
+        let name = own_file_name();
+        let line: [&[u8]; 4] = [kind, b":", name.to_bytes(), b"\n"];
+        if line.iter().map(|part| part.len()).sum::<usize>() < buf.len() {
+            let mut at = 0;
+            for part in line {
+                buf[at..at + part.len()].copy_from_slice(part);
+                at += part.len();
+            }
+            buf[at] = 0;
+        }
+        buf.as_ptr().cast()
+    }
+}
+
+/// TSan's hook for built-in suppressions: ignore the interceptors called from this
+/// library, whatever its file is called.
+#[cfg(feature = "preload")]
+#[unsafe(no_mangle)]
+pub extern "C" fn __tsan_default_suppressions() -> *const c_char {
+    static TSAN: Suppressions = Suppressions::new();
+    TSAN.fill(b"called_from_lib")
+}
+
+/// LSan's: the hook registry's snapshots are leaked on purpose, since a concurrent
+/// `pthread_create` may still be reading the one replaced.
+#[cfg(feature = "preload")]
+#[unsafe(no_mangle)]
+pub extern "C" fn __lsan_default_suppressions() -> *const c_char {
+    static LSAN: Suppressions = Suppressions::new();
+    LSAN.fill(b"leak")
+}
+
+/// The file name of this library, without allocating.
+#[cfg(feature = "preload")]
+fn own_file_name() -> &'static CStr {
+    let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+    let addr = own_file_name as *const c_void;
+    if unsafe { libc::dladdr(addr, &mut info) } == 0 || info.dli_fname.is_null() {
+        return c"";
+    }
+    // the loader's copy of the path, which lives as long as the library
+    let path = unsafe { CStr::from_ptr(info.dli_fname) }.to_bytes_with_nul();
+    let start = path.iter().rposition(|&b| b == b'/').map_or(0, |i| i + 1);
+    CStr::from_bytes_with_nul(&path[start..]).unwrap_or(c"")
+}
+
+fn global(symbol: &CStr) -> *mut c_void {
+    unsafe { libc::dlsym(libc::RTLD_DEFAULT, symbol.as_ptr()) }
+}
+
+#[cfg(feature = "preload")]
+fn object_base(addr: *const c_void) -> Option<*mut c_void> {
+    let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+    (unsafe { libc::dladdr(addr, &mut info) } != 0).then_some(info.dli_fbase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitizers_round_trip() {
+        for (sanitizer, _) in Sanitizer::ALL {
+            assert_eq!(Sanitizer::from_u8(sanitizer as u8), Some(sanitizer));
+        }
+        assert_eq!(Sanitizer::from_u8(0), None);
+    }
+
+    #[cfg(not(otel_posix_sanitize))]
+    #[test]
+    fn nothing_to_detect_in_a_plain_build() {
+        assert_eq!(detected(), None);
+        assert!(!blocks_wrapping());
+    }
+
+    #[cfg(feature = "preload")]
+    #[test]
+    fn suppressions_name_this_library() {
+        let exe = std::env::current_exe().unwrap();
+        // linked into the test binary here
+        let name = exe.file_name().unwrap().to_str().unwrap();
+        let tsan = unsafe { CStr::from_ptr(__tsan_default_suppressions()) };
+        assert_eq!(tsan.to_str().unwrap(), format!("called_from_lib:{name}\n"));
+        let lsan = unsafe { CStr::from_ptr(__lsan_default_suppressions()) };
+        assert_eq!(lsan.to_str().unwrap(), format!("leak:{name}\n"));
+    }
+}
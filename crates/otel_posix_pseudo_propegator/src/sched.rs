@@ -0,0 +1,131 @@
+// src/sched.rs
+//
+// How a thread was actually scheduled, put on the span that lasts as long
+// as it does when it ends, next to its resource usage (rusage.rs). What the
+// creator asked for in its pthread_attr_t is recorded as well (attr.rs),
+// but a thread's scheduling is often set from inside it
+// (pthread_setschedparam, sched_setaffinity, nice) or inherited from a
+// creator that had its own changed, and a thread pinned to the wrong CPUs
+// or left at the wrong priority is a common reason it ran late. Read once
+// at the end rather than by interposing the setters, so whatever changed
+// it, the span has what the thread ended up with.
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::attr::policy_name;
+use opentelemetry::KeyValue;
+
+/// The calling thread's scheduling policy, its priority (real-time policies) or nice
+/// value (the others), and the CPUs it may run on.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn thread_attributes() -> Vec<KeyValue> {
+    let mut attributes = Vec::new();
+    let mut policy = 0;
+    let mut param = unsafe { std::mem::zeroed::<libc::sched_param>() };
+    if unsafe { libc::pthread_getschedparam(libc::pthread_self(), &mut policy, &mut param) } == 0 {
+        attributes.push(KeyValue::new(
+            "otel_posix.thread.sched.policy",
+            policy_name(policy),
+        ));
+        if matches!(policy, libc::SCHED_FIFO | libc::SCHED_RR) {
+            attributes.push(KeyValue::new(
+                "otel_posix.thread.sched.priority",
+                i64::from(param.sched_priority),
+            ));
+        } else {
+            // per thread on Linux, by tid; -1 is a valid value, so errno tells a failure
+            unsafe { *libc::__errno_location() = 0 };
+            let tid = unsafe { libc::gettid() } as libc::id_t;
+            let nice = unsafe { libc::getpriority(libc::PRIO_PROCESS, tid) };
+            if unsafe { *libc::__errno_location() } == 0 {
+                attributes.push(KeyValue::new(
+                    "otel_posix.thread.sched.nice",
+                    i64::from(nice),
+                ));
+            }
+        }
+    }
+    let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+    if unsafe { libc::sched_getaffinity(0, size_of::<libc::cpu_set_t>(), &mut set) } == 0 {
+        let cpus: Vec<usize> = (0..libc::CPU_SETSIZE as usize)
+            .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+            .collect();
+        attributes.push(KeyValue::new(
+            "otel_posix.thread.cpu.affinity",
+            cpu_list(&cpus),
+        ));
+    }
+    attributes
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn thread_attributes() -> Vec<KeyValue> {
+    Vec::new()
+}
+
+/// `cpus`, in ascending order, as the kernel's cpulist format: `0-3,6`.
+fn cpu_list(cpus: &[usize]) -> String {
+    let mut list = String::new();
+    let mut i = 0;
+    while i < cpus.len() {
+        let first = cpus[i];
+        while i + 1 < cpus.len() && cpus[i + 1] == cpus[i] + 1 {
+            i += 1;
+        }
+        if !list.is_empty() {
+            list.push(',');
+        }
+        list.push_str(&first.to_string());
+        if cpus[i] != first {
+            list.push('-');
+            list.push_str(&cpus[i].to_string());
+        }
+        i += 1;
+    }
+    list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cpus_are_listed_as_ranges() {
+        assert_eq!(cpu_list(&[]), "");
+        assert_eq!(cpu_list(&[2]), "2");
+        assert_eq!(cpu_list(&[0, 1, 2, 3, 6]), "0-3,6");
+        assert_eq!(cpu_list(&[0, 2, 3, 5, 6, 7]), "0,2-3,5-7");
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn changes_made_in_the_thread_are_seen() {
+        let (cpu, nice, attributes) = std::thread::spawn(|| unsafe {
+            // pinned to one of the CPUs it was allowed, and made nicer
+            let mut set = std::mem::zeroed::<libc::cpu_set_t>();
+            libc::sched_getaffinity(0, size_of::<libc::cpu_set_t>(), &mut set);
+            let cpu = (0..libc::CPU_SETSIZE as usize)
+                .find(|&cpu| libc::CPU_ISSET(cpu, &set))
+                .unwrap();
+            libc::CPU_ZERO(&mut set);
+            libc::CPU_SET(cpu, &mut set);
+            assert_eq!(
+                libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set),
+                0
+            );
+            let tid = libc::gettid() as libc::id_t;
+            let nice = libc::getpriority(libc::PRIO_PROCESS, tid);
+            libc::setpriority(libc::PRIO_PROCESS, tid, (nice + 1).min(19));
+            (cpu, (nice + 1).min(19), thread_attributes())
+        })
+        .join()
+        .unwrap();
+        assert_eq!(
+            attributes,
+            [
+                KeyValue::new("otel_posix.thread.sched.policy", "SCHED_OTHER"),
+                KeyValue::new("otel_posix.thread.sched.nice", i64::from(nice)),
+                KeyValue::new("otel_posix.thread.cpu.affinity", cpu.to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+// src/scope.rs
+//
+// The instrumentation scope the shim's own telemetry (thread spans, auto
+// root spans, crash spans, its metrics) is reported under. It defaults to
+// the crate's name and version; OTEL_POSIX_PROP_SCOPE_NAME, _VERSION and
+// _SCHEMA_URL override it, so backends can tell one deployment of the shim
+// from another.
+
+use crate::config::config;
+use opentelemetry::InstrumentationScope;
+use opentelemetry::global::{self, BoxedTracer};
+use opentelemetry::metrics::Meter;
+
+const DEFAULT_NAME: &str = env!("CARGO_PKG_NAME");
+const DEFAULT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Name, version and schema URL of the scope.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Scope {
+    pub name: String,
+    pub version: Option<String>,
+    pub schema_url: Option<String>,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Scope {
+            name: DEFAULT_NAME.to_string(),
+            version: Some(DEFAULT_VERSION.to_string()),
+            schema_url: None,
+        }
+    }
+}
+
+impl Scope {
+    /// Reads the scope from `var`. An empty name keeps the default; an empty version drops
+    /// it.
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let set = |key| var(key).map(|v: String| v.trim().to_string());
+        Scope {
+            name: set("OTEL_POSIX_PROP_SCOPE_NAME")
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| DEFAULT_NAME.to_string()),
+            version: set("OTEL_POSIX_PROP_SCOPE_VERSION")
+                .or_else(|| Some(DEFAULT_VERSION.to_string()))
+                .filter(|v| !v.is_empty()),
+            schema_url: set("OTEL_POSIX_PROP_SCOPE_SCHEMA_URL").filter(|v| !v.is_empty()),
+        }
+    }
+
+    fn instrumentation_scope(&self) -> InstrumentationScope {
+        let mut builder = InstrumentationScope::builder(self.name.clone());
+        if let Some(version) = &self.version {
+            builder = builder.with_version(version.clone());
+        }
+        if let Some(schema_url) = &self.schema_url {
+            builder = builder.with_schema_url(schema_url.clone());
+        }
+        builder.build()
+    }
+}
+
+/// The tracer for the shim's own spans, from the global provider.
+pub(crate) fn tracer() -> BoxedTracer {
+    global::tracer_with_scope(config().scope.instrumentation_scope())
+}
+
+/// The meter for the shim's own metrics, from the global provider.
+pub(crate) fn meter() -> Meter {
+    global::meter_with_scope(config().scope.instrumentation_scope())
+}
+
+/// The logger for the records shims emit through the shim, from `provider`: the API has
+/// no global one.
+#[cfg(all(feature = "sdk", any(feature = "otlp", feature = "test-support")))]
+pub(crate) fn logger(
+    provider: &opentelemetry_sdk::logs::SdkLoggerProvider,
+) -> opentelemetry_sdk::logs::SdkLogger {
+    use opentelemetry::logs::LoggerProvider;
+    provider.logger_with_scope(config().scope.instrumentation_scope())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scope(vars: &[(&str, &str)]) -> Scope {
+        Scope::from_env(|key| {
+            vars.iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        })
+    }
+
+    #[test]
+    fn defaults_to_the_crate() {
+        assert_eq!(scope(&[]), Scope::default());
+        assert_eq!(scope(&[]).name, "otel_posix_pseudo_propegator");
+        assert_eq!(
+            scope(&[("OTEL_POSIX_PROP_SCOPE_NAME", " ")]).name,
+            DEFAULT_NAME
+        );
+    }
+
+    #[test]
+    fn overridden_from_env() {
+        let custom = scope(&[
+            ("OTEL_POSIX_PROP_SCOPE_NAME", "acme.shim"),
+            ("OTEL_POSIX_PROP_SCOPE_VERSION", "2024.1"),
+            (
+                "OTEL_POSIX_PROP_SCOPE_SCHEMA_URL",
+                "https://opentelemetry.io/schemas/1.26.0",
+            ),
+        ]);
+        let built = custom.instrumentation_scope();
+        assert_eq!(built.name(), "acme.shim");
+        assert_eq!(built.version(), Some("2024.1"));
+        assert_eq!(
+            built.schema_url(),
+            Some("https://opentelemetry.io/schemas/1.26.0")
+        );
+        let unversioned = scope(&[("OTEL_POSIX_PROP_SCOPE_VERSION", "")]);
+        assert_eq!(unversioned.instrumentation_scope().version(), None);
+    }
+}
@@ -0,0 +1,226 @@
+// src/selftest.rs
+//
+// Whether the preloaded pthread_create is the one the process calls. The
+// shim only sees threads whose creation binds to its symbol; when it doesn't
+// (an executable with its C library linked in statically, or the shim
+// loaded after libc, with dlopen rather than LD_PRELOAD) it would otherwise
+// do nothing at all, without a word. A load-time constructor looks
+// pthread_create up the way the program's own references resolve, from the
+// global scope, and compares what it finds with ours. A defeated
+// interposition is warned about once (not while the process is traced:
+// `otel-preload attach` dlopens the library over ptrace, and
+// otel_posix_attach() then takes the bindings over itself), and reported by
+// otel_posix_prop_status(), the control socket and the
+// `otel_posix.interposition.effective` gauge.
+//
+// Libraries opened later with RTLD_DEEPBIND bind to their own dependencies
+// first, and can't be seen from here; namespaces.rs deals with them as they
+// are opened.
+
+use crate::log::log_warn;
+use crate::resolve;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Once, OnceLock};
+
+/// How the process's `pthread_create` binds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum Binding {
+    /// The global lookup finds ours.
+    Bound = 1,
+    /// The shim is linked into the executable, whose own calls reach it at link time.
+    Linked,
+    /// Another interposer is found first, and reaches ours if it passes calls on with
+    /// `RTLD_NEXT`.
+    Behind,
+    /// The C library's own is found first: the shim was loaded after it.
+    Shadowed,
+    /// The executable defines its own, or has none to look up: a static C library.
+    Static,
+    /// Loaded late, and the bindings made before redirected by `otel_posix_attach`.
+    Attached,
+}
+
+impl Binding {
+    const ALL: [Binding; 6] = [
+        Binding::Bound,
+        Binding::Linked,
+        Binding::Behind,
+        Binding::Shadowed,
+        Binding::Static,
+        Binding::Attached,
+    ];
+
+    fn from_u8(n: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|b| *b as u8 == n)
+    }
+
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Binding::Bound => "bound",
+            Binding::Linked => "linked",
+            Binding::Behind => "behind",
+            Binding::Shadowed => "shadowed",
+            Binding::Static => "static",
+            Binding::Attached => "attached",
+        }
+    }
+
+    /// Whether threads the program creates go through the shim.
+    pub(crate) fn is_effective(self) -> bool {
+        !matches!(self, Binding::Shadowed | Binding::Static)
+    }
+}
+
+static BINDING: AtomicU8 = AtomicU8::new(0);
+// the object the global lookup found, when it isn't ours
+static FOUND_IN: OnceLock<String> = OnceLock::new();
+
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+#[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__mod_init_func"))]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(check);
+}
+
+/// Runs the check, once; other constructors that report the binding call it first.
+pub(crate) fn check() {
+    static CHECKED: Once = Once::new();
+    CHECKED.call_once(run);
+}
+
+fn run() {
+    // the copy loaded as an auditor has its bindings redirected rather than looked up
+    #[cfg(all(feature = "audit", target_os = "linux", target_env = "gnu"))]
+    if crate::audit::is_auditor() {
+        return;
+    }
+    let first = unsafe { libc::dlsym(libc::RTLD_DEFAULT, c"pthread_create".as_ptr()) };
+    let binding = classify(first, program_base());
+    if !matches!(binding, Binding::Bound | Binding::Linked) {
+        let _ = FOUND_IN.set(resolve::object_name(first).unwrap_or_default());
+    }
+    BINDING.store(binding as u8, Ordering::Release);
+    if traced() {
+        return;
+    }
+    let object = found_in().unwrap_or("-");
+    match binding {
+        Binding::Shadowed => log_warn!(
+            "pthread_create binds to {object}, loaded before this library; no thread \
+             will be wrapped. Load it with LD_PRELOAD, or call otel_posix_attach() after dlopen"
+        ),
+        Binding::Static => log_warn!(
+            "the executable has its own pthread_create (a static C library); no thread \
+             will be wrapped"
+        ),
+        _ => {}
+    }
+}
+
+/// What the global lookup's `first` says about the binding, given the executable's base.
+fn classify(first: *mut c_void, program: Option<*mut c_void>) -> Binding {
+    let object = |addr: *const c_void| {
+        let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+        (unsafe { libc::dladdr(addr, &mut info) } != 0).then_some(info.dli_fbase)
+    };
+    let in_program = |addr: *const c_void| program.is_some() && object(addr) == program;
+    if in_program(classify as *const c_void) {
+        Binding::Linked
+    } else if first.is_null() || in_program(first) {
+        Binding::Static
+    } else if resolve::is_ours(first) {
+        Binding::Bound
+    } else if resolve::is_libc(first) {
+        Binding::Shadowed
+    } else {
+        Binding::Behind
+    }
+}
+
+/// The load address of the executable, from its program headers.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn program_base() -> Option<*mut c_void> {
+    let phdr = unsafe { libc::getauxval(libc::AT_PHDR) } as *const c_void;
+    let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+    (!phdr.is_null() && unsafe { libc::dladdr(phdr, &mut info) } != 0).then_some(info.dli_fbase)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn program_base() -> Option<*mut c_void> {
+    None
+}
+
+/// Whether a tracer (a debugger, or `otel-preload attach`) is attached to the process.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn traced() -> bool {
+    std::fs::read_to_string("/proc/self/status").is_ok_and(|status| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix("TracerPid:"))
+            .is_some_and(|pid| pid.trim() != "0")
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn traced() -> bool {
+    false
+}
+
+/// Records that `otel_posix_attach` took the process's bindings over.
+#[cfg(all(
+    target_os = "linux",
+    target_env = "gnu",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub(crate) fn attached() {
+    BINDING.store(Binding::Attached as u8, Ordering::Release);
+}
+
+/// The binding found at load time, or `None` before the check has run.
+pub(crate) fn binding() -> Option<Binding> {
+    Binding::from_u8(BINDING.load(Ordering::Acquire))
+}
+
+/// The object whose `pthread_create` is bound instead of ours.
+pub(crate) fn found_in() -> Option<&'static str> {
+    FOUND_IN.get().map(String::as_str).filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_round_trip() {
+        for binding in Binding::ALL {
+            assert_eq!(Binding::from_u8(binding as u8), Some(binding));
+        }
+        assert_eq!(Binding::from_u8(0), None);
+    }
+
+    #[test]
+    fn linked_into_the_test_binary() {
+        assert_eq!(binding(), Some(Binding::Linked));
+        assert!(Binding::Linked.is_effective());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[test]
+    fn classifies_what_the_lookup_finds() {
+        let libc_symbol = libc::pthread_self as *mut c_void;
+        // as if the shim lived in some other object than the executable
+        let elsewhere = Some(std::ptr::dangling_mut::<c_void>());
+        assert_eq!(classify(libc_symbol, elsewhere), Binding::Shadowed);
+        assert_eq!(classify(std::ptr::null_mut(), elsewhere), Binding::Static);
+        assert_eq!(classify(libc_symbol, program_base()), Binding::Linked);
+        assert!(!Binding::Shadowed.is_effective());
+    }
+}
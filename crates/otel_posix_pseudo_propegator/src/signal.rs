@@ -0,0 +1,282 @@
+// src/signal.rs
+//
+// pthread_create called from a signal handler. Some hosts start a thread
+// from a handler (to get off the signal stack, or to report something).
+// The wrapper's usual work allocates, takes locks and formats ids, and a
+// signal interrupting the same thread while it holds one of those (malloc's
+// arena, the registry) would deadlock there, where the host alone wouldn't.
+//
+// So sigaction and signal are interposed, and every handler installed
+// through them runs behind `dispatch`, which notes the handler's frame in a
+// thread-local. pthread_create in a handler then takes a path that only
+// reads the creating thread's traceparent from the crash table (see
+// crashdump.rs), copies it into a preallocated slot and hands that to the
+// real call. The rest is deferred to the new thread, outside any handler,
+// which parses it and is set up as any other wrapped thread, under the
+// creator's span as a remote parent. Baggage, embedder hooks and the
+// caller and entry filters don't come along. With every slot in use, or no
+// traceparent, the thread is passed through.
+//
+// A handler left through siglongjmp never clears its note, so a frame
+// further up the stack than the handler's, or far from it, isn't taken to
+// be in it. Handlers installed before the shim loaded, or with sigset and
+// the other old interfaces, aren't seen.
+
+use crate::crashdump::otel_posix_crash_traceparent;
+use crate::w3c::{TRACEPARENT_LEN, parse_traceparent};
+use crate::{StartRoutine, deferred_launch, enter, leave};
+use libc::{c_int, sighandler_t, siginfo_t};
+use std::cell::{Cell, UnsafeCell};
+use std::ffi::{CStr, c_void};
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+type SigactionFn =
+    unsafe extern "C" fn(c_int, *const libc::sigaction, *mut libc::sigaction) -> c_int;
+type SignalFn = unsafe extern "C" fn(c_int, sighandler_t) -> sighandler_t;
+type Handler = unsafe extern "C" fn(c_int, *mut siginfo_t, *mut c_void);
+
+const SIGNALS: usize = 65;
+
+// threads started from handlers and not yet running
+const SLOTS: usize = 64;
+
+// how far below the dispatcher's frame a handler's calls are taken to reach
+const HANDLER_STACK: usize = 256 * 1024;
+
+// the handler installed for each signal, and whether it takes a siginfo_t
+static HANDLERS: [AtomicUsize; SIGNALS] = [const { AtomicUsize::new(0) }; SIGNALS];
+static SIGINFO: [AtomicBool; SIGNALS] = [const { AtomicBool::new(false) }; SIGNALS];
+
+static DEFERRED: [Slot; SLOTS] = [const { Slot::new() }; SLOTS];
+
+thread_local! {
+    // the outermost running handler's dispatcher frame, or 0; plain data, so the
+    // thread-local needs no destructor
+    static ENTRY: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A thread created from a handler, for it to finish setting itself up.
+struct Deferred {
+    start_routine: StartRoutine,
+    arg: *mut c_void,
+    traceparent: [u8; TRACEPARENT_LEN],
+    creator: libc::pid_t,
+    created: SystemTime,
+}
+
+struct Slot {
+    busy: AtomicBool,
+    deferred: UnsafeCell<MaybeUninit<Deferred>>,
+}
+
+// a slot is written by the thread that claimed it, then read once by the thread it starts
+unsafe impl Sync for Slot {}
+
+impl Slot {
+    const fn new() -> Self {
+        Slot {
+            busy: AtomicBool::new(false),
+            deferred: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+}
+
+/// Roughly where the calling function's frame is.
+#[inline(always)]
+fn frame() -> usize {
+    let here = 0u8;
+    std::hint::black_box(&here) as *const u8 as usize
+}
+
+/// Whether `here` is in a handler dispatched at `entry`: below it on the same stack.
+fn within(entry: usize, here: usize) -> bool {
+    here <= entry && entry - here < HANDLER_STACK
+}
+
+/// Whether the calling thread is running a signal handler.
+pub(crate) fn in_handler() -> bool {
+    let entry = ENTRY.get();
+    if entry == 0 {
+        return false;
+    }
+    if within(entry, frame()) {
+        return true;
+    }
+    // left through siglongjmp
+    ENTRY.set(0);
+    false
+}
+
+extern "C" fn dispatch(signal: c_int, info: *mut siginfo_t, context: *mut c_void) {
+    let outer = ENTRY.get();
+    let here = frame();
+    let nested = outer != 0 && within(outer, here);
+    if !nested {
+        ENTRY.set(here);
+    }
+    let handler = HANDLERS[signal as usize].load(Ordering::Acquire);
+    // SIG_DFL or SIG_IGN, if it was replaced as the signal came in
+    if handler > libc::SIG_IGN {
+        // a handler without a siginfo_t ignores the extra arguments, as the kernel's call
+        // to it would
+        let handler = unsafe { std::mem::transmute::<usize, Handler>(handler) };
+        unsafe { handler(signal, info, context) };
+    }
+    if !nested {
+        ENTRY.set(outer);
+    }
+}
+
+fn index(signal: c_int) -> Option<usize> {
+    usize::try_from(signal)
+        .ok()
+        .filter(|&i| i > 0 && i < SIGNALS)
+}
+
+/// `sigaction`, installing `dispatch` in front of the handler and reporting the handler
+/// rather than `dispatch` back.
+pub(crate) unsafe fn sigaction(
+    real: SigactionFn,
+    signal: c_int,
+    act: *const libc::sigaction,
+    old: *mut libc::sigaction,
+) -> c_int {
+    let Some(i) = index(signal) else {
+        return unsafe { real(signal, act, old) };
+    };
+    let previous = (
+        HANDLERS[i].load(Ordering::Acquire),
+        SIGINFO[i].load(Ordering::Relaxed),
+    );
+    let rc = match unsafe { act.as_ref() } {
+        Some(act) if act.sa_sigaction > libc::SIG_IGN => {
+            let mut wrapped = *act;
+            wrapped.sa_sigaction = dispatch as *const () as sighandler_t;
+            wrapped.sa_flags |= libc::SA_SIGINFO;
+            // in place before the kernel can deliver to it
+            SIGINFO[i].store(act.sa_flags & libc::SA_SIGINFO != 0, Ordering::Relaxed);
+            HANDLERS[i].store(act.sa_sigaction, Ordering::Release);
+            let rc = unsafe { real(signal, &wrapped, old) };
+            if rc != 0 {
+                SIGINFO[i].store(previous.1, Ordering::Relaxed);
+                HANDLERS[i].store(previous.0, Ordering::Release);
+            }
+            rc
+        }
+        _ => unsafe { real(signal, act, old) },
+    };
+    if let Some(old) = unsafe { old.as_mut() }
+        && rc == 0
+        && old.sa_sigaction == dispatch as *const () as sighandler_t
+    {
+        old.sa_sigaction = previous.0;
+        if !previous.1 {
+            old.sa_flags &= !libc::SA_SIGINFO;
+        }
+    }
+    rc
+}
+
+/// `signal`, with glibc's semantics, through the interposed `sigaction`.
+pub(crate) unsafe fn signal(real: SignalFn, signal: c_int, handler: sighandler_t) -> sighandler_t {
+    if index(signal).is_none() || handler <= libc::SIG_IGN {
+        return unsafe { real(signal, handler) };
+    }
+    let mut act = unsafe { std::mem::zeroed::<libc::sigaction>() };
+    act.sa_sigaction = handler;
+    act.sa_flags = libc::SA_RESTART;
+    let mut old = unsafe { std::mem::zeroed::<libc::sigaction>() };
+    unsafe {
+        libc::sigemptyset(&mut act.sa_mask);
+        libc::sigaddset(&mut act.sa_mask, signal);
+        if crate::interpose::sigaction(signal, &act, &mut old) != 0 {
+            return libc::SIG_ERR;
+        }
+    }
+    old.sa_sigaction
+}
+
+/// Creates a thread from a signal handler, with `real` as `create_wrapped` would, without
+/// allocating, locking or formatting anything.
+pub(crate) fn create(
+    start_routine: StartRoutine,
+    arg: *mut c_void,
+    real: impl FnOnce(StartRoutine, *mut c_void) -> i32,
+) -> i32 {
+    let creator = unsafe { libc::gettid() };
+    let tp = otel_posix_crash_traceparent(creator);
+    let slot = (!tp.is_null())
+        .then(|| {
+            DEFERRED.iter().find(|slot| {
+                slot.busy
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            })
+        })
+        .flatten();
+    let Some(slot) = slot else {
+        crate::metrics::thread_passed_through();
+        return real(start_routine, arg);
+    };
+    let mut traceparent = [0; TRACEPARENT_LEN];
+    let bytes = unsafe { CStr::from_ptr(tp) }.to_bytes();
+    let n = bytes.len().min(TRACEPARENT_LEN);
+    traceparent[..n].copy_from_slice(&bytes[..n]);
+    let deferred = Deferred {
+        start_routine,
+        arg,
+        traceparent,
+        creator,
+        created: SystemTime::now(),
+    };
+    unsafe { (*slot.deferred.get()).write(deferred) };
+    let rc = real(start_deferred, (slot as *const Slot).cast_mut().cast());
+    if rc != 0 {
+        slot.busy.store(false, Ordering::Release);
+    }
+    rc
+}
+
+extern "C" fn start_deferred(v: *mut c_void) -> *mut c_void {
+    let slot = unsafe { &*v.cast::<Slot>() };
+    let deferred = unsafe { (*slot.deferred.get()).assume_init_read() };
+    slot.busy.store(false, Ordering::Release);
+    let sc = std::str::from_utf8(&deferred.traceparent)
+        .ok()
+        .and_then(parse_traceparent);
+    let launch = sc.and_then(|sc| {
+        deferred_launch(
+            deferred.start_routine,
+            deferred.arg,
+            sc,
+            deferred.creator,
+            deferred.created,
+        )
+    });
+    let Some(launch) = launch else {
+        return (deferred.start_routine)(deferred.arg);
+    };
+    let (creator, running) = enter(launch, None);
+    // called from this frame, as in the trampoline
+    let ret = (deferred.start_routine)(deferred.arg);
+    leave(creator, running, ret);
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_below_the_handler_are_in_it() {
+        let entry = 0x7fff_0000_0000;
+        assert!(within(entry, entry - 512));
+        // the interrupted code, or where a siglongjmp went
+        assert!(!within(entry, entry + 64));
+        // another stack
+        assert!(!within(entry, entry - 64 * 1024 * 1024));
+        assert!(!in_handler());
+    }
+}
@@ -0,0 +1,386 @@
+// src/spans.rs
+//
+// C-callable span recording, for hand-written C code and for the
+// workspace's other preload shims (the malloc profiler and the like). Those
+// are separate libraries with their own copy of opentelemetry, so they
+// can't see the context this one keeps or export through its provider;
+// they look these functions up with dlsym and record through them instead,
+// and do nothing when the propagator isn't loaded.
+//
+// A span is recorded once it is over, under a parent given as a
+// traceparent: one read from the crash table (crashdump.rs), which is safe
+// to read where the current context isn't, from inside an allocator say.
+// Attributes come in as an array of tagged values, since C has nothing
+// better to pass a list of mixed types in. otel_posix_add_span_kind also
+// takes the span's kind, for shims recording the client or server side of
+// a call; otel_posix_add_span's spans are internal. A shim that has to
+// hand the span's own traceparent on before the call it records is over
+// (the curl tracer, putting it in a request header) picks the span's id
+// itself, and records it with otel_posix_add_span_with_id.
+//
+// An event can only go on a span that is still open, so
+// otel_posix_add_event adds it to the calling thread's current span; unlike
+// the crash table, that can't be read from inside an allocator.
+
+use crate::w3c::parse_traceparent;
+use crate::{reentry, scope};
+use libc::{c_char, c_int, size_t};
+use opentelemetry::trace::{Span, SpanId, SpanKind, TraceContextExt, Tracer};
+use opentelemetry::{Context, KeyValue, Value};
+use std::ffi::CStr;
+use std::time::{Duration, SystemTime};
+
+/// [`OtelPosixAttribute::kind`] of a string, in `string_value`.
+pub const OTEL_POSIX_VALUE_STRING: c_int = 0;
+/// [`OtelPosixAttribute::kind`] of an integer, in `int_value`.
+pub const OTEL_POSIX_VALUE_INT: c_int = 1;
+/// [`OtelPosixAttribute::kind`] of a floating-point number, in `double_value`.
+pub const OTEL_POSIX_VALUE_DOUBLE: c_int = 2;
+/// [`OtelPosixAttribute::kind`] of a boolean, in `int_value` (non-zero for true).
+pub const OTEL_POSIX_VALUE_BOOL: c_int = 3;
+
+/// A span kind for [`otel_posix_add_span_kind`]: internal.
+pub const OTEL_POSIX_SPAN_KIND_INTERNAL: c_int = 0;
+/// A span kind for [`otel_posix_add_span_kind`]: the client side of a call.
+pub const OTEL_POSIX_SPAN_KIND_CLIENT: c_int = 1;
+/// A span kind for [`otel_posix_add_span_kind`]: the server side of a call.
+pub const OTEL_POSIX_SPAN_KIND_SERVER: c_int = 2;
+/// A span kind for [`otel_posix_add_span_kind`]: sending a message.
+pub const OTEL_POSIX_SPAN_KIND_PRODUCER: c_int = 3;
+/// A span kind for [`otel_posix_add_span_kind`]: receiving a message.
+pub const OTEL_POSIX_SPAN_KIND_CONSUMER: c_int = 4;
+
+/// One attribute of a span recorded with [`otel_posix_add_span`].
+#[repr(C)]
+pub struct OtelPosixAttribute {
+    /// The attribute's key, NUL-terminated.
+    pub key: *const c_char,
+    /// Which of the value fields holds the value: one of the `OTEL_POSIX_VALUE_*`.
+    pub kind: c_int,
+    /// A NUL-terminated string, for `OTEL_POSIX_VALUE_STRING`.
+    pub string_value: *const c_char,
+    /// An integer, for `OTEL_POSIX_VALUE_INT` and `OTEL_POSIX_VALUE_BOOL`.
+    pub int_value: i64,
+    /// A floating-point number, for `OTEL_POSIX_VALUE_DOUBLE`.
+    pub double_value: f64,
+}
+
+/// `count` attributes at `attributes` as key-values; those without a key or with an
+/// unknown kind are skipped.
+pub(crate) unsafe fn key_values(
+    attributes: *const OtelPosixAttribute,
+    count: size_t,
+) -> Vec<KeyValue> {
+    if attributes.is_null() {
+        return Vec::new();
+    }
+    let string = |s: *const c_char| unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned();
+    unsafe { std::slice::from_raw_parts(attributes, count) }
+        .iter()
+        .filter(|a| !a.key.is_null())
+        .filter_map(|a| {
+            let value = match a.kind {
+                OTEL_POSIX_VALUE_STRING if !a.string_value.is_null() => {
+                    Value::from(string(a.string_value))
+                }
+                OTEL_POSIX_VALUE_INT => Value::I64(a.int_value),
+                OTEL_POSIX_VALUE_DOUBLE => Value::F64(a.double_value),
+                OTEL_POSIX_VALUE_BOOL => Value::Bool(a.int_value != 0),
+                _ => return None,
+            };
+            Some(KeyValue::new(string(a.key), value))
+        })
+        .collect()
+}
+
+/// The `OTEL_POSIX_SPAN_KIND_*` `kind`; one this version doesn't know is internal.
+fn span_kind(kind: c_int) -> SpanKind {
+    match kind {
+        OTEL_POSIX_SPAN_KIND_CLIENT => SpanKind::Client,
+        OTEL_POSIX_SPAN_KIND_SERVER => SpanKind::Server,
+        OTEL_POSIX_SPAN_KIND_PRODUCER => SpanKind::Producer,
+        OTEL_POSIX_SPAN_KIND_CONSUMER => SpanKind::Consumer,
+        _ => SpanKind::Internal,
+    }
+}
+
+/// Records a span named `name` that ran from `start` to `end`, in nanoseconds since the
+/// Unix epoch, with the `count` attributes at `attributes`. Its parent is the span the
+/// traceparent `parent` names, or the calling thread's current span when `parent` is NULL.
+///
+/// Returns 0, or -1 if `name` is NULL or `parent` isn't a valid traceparent.
+///
+/// # Safety
+///
+/// `name` must be null or point to a NUL-terminated string, as must `parent`, and
+/// `attributes` must be null or point to `count` attributes whose strings are
+/// NUL-terminated or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn otel_posix_add_span(
+    parent: *const c_char,
+    name: *const c_char,
+    start: u64,
+    end: u64,
+    attributes: *const OtelPosixAttribute,
+    count: size_t,
+) -> c_int {
+    unsafe {
+        otel_posix_add_span_kind(
+            parent,
+            name,
+            OTEL_POSIX_SPAN_KIND_INTERNAL,
+            start,
+            end,
+            attributes,
+            count,
+        )
+    }
+}
+
+/// Records a span like [`otel_posix_add_span`], of the kind `kind`: one of the
+/// `OTEL_POSIX_SPAN_KIND_*`.
+///
+/// Returns 0, or -1 if `name` is NULL or `parent` isn't a valid traceparent.
+///
+/// # Safety
+///
+/// As for [`otel_posix_add_span`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn otel_posix_add_span_kind(
+    parent: *const c_char,
+    name: *const c_char,
+    kind: c_int,
+    start: u64,
+    end: u64,
+    attributes: *const OtelPosixAttribute,
+    count: size_t,
+) -> c_int {
+    unsafe {
+        otel_posix_add_span_with_id(
+            parent,
+            std::ptr::null(),
+            name,
+            kind,
+            start,
+            end,
+            attributes,
+            count,
+        )
+    }
+}
+
+/// Records a span like [`otel_posix_add_span_kind`] whose id is `span_id`, 16 hex
+/// digits, or a new one when `span_id` is NULL.
+///
+/// Returns 0, or -1 if `name` is NULL, `parent` isn't a valid traceparent or `span_id`
+/// isn't a valid span id.
+///
+/// # Safety
+///
+/// As for [`otel_posix_add_span`], and `span_id` must be null or point to a
+/// NUL-terminated string.
+#[allow(clippy::too_many_arguments)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn otel_posix_add_span_with_id(
+    parent: *const c_char,
+    span_id: *const c_char,
+    name: *const c_char,
+    kind: c_int,
+    start: u64,
+    end: u64,
+    attributes: *const OtelPosixAttribute,
+    count: size_t,
+) -> c_int {
+    let Some(_guard) = reentry::enter() else {
+        return -1;
+    };
+    if name.is_null() {
+        return -1;
+    }
+    let span_id = if span_id.is_null() {
+        None
+    } else {
+        let span_id = unsafe { CStr::from_ptr(span_id) }.to_str().ok();
+        match span_id.and_then(parse_span_id) {
+            Some(id) => Some(id),
+            None => return -1,
+        }
+    };
+    let cx = if parent.is_null() {
+        Context::current()
+    } else {
+        let parent = unsafe { CStr::from_ptr(parent) }.to_str().ok();
+        match parent.and_then(parse_traceparent) {
+            Some(sc) => Context::new().with_remote_span_context(sc),
+            None => return -1,
+        }
+    };
+    let at = |nanos| SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos);
+    let tracer = scope::tracer();
+    let mut builder = tracer
+        .span_builder(
+            unsafe { CStr::from_ptr(name) }
+                .to_string_lossy()
+                .into_owned(),
+        )
+        .with_kind(span_kind(kind))
+        .with_start_time(at(start))
+        .with_attributes(unsafe { key_values(attributes, count) });
+    builder.span_id = span_id;
+    let mut span = builder.start_with_context(&tracer, &cx);
+    span.end_with_timestamp(at(end.max(start)));
+    0
+}
+
+/// The span id `hex` names: 16 lowercase hex digits, not all zero.
+fn parse_span_id(hex: &str) -> Option<SpanId> {
+    let valid = hex.len() == 16 && hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+    let id = SpanId::from_hex(hex).ok().filter(|_| valid)?;
+    (id != SpanId::INVALID).then_some(id)
+}
+
+/// Adds an event named `name` at `time`, in nanoseconds since the Unix epoch, with the
+/// `count` attributes at `attributes`, to the calling thread's current span.
+///
+/// Returns 0, or -1 if `name` is NULL or no span is recording on this thread.
+///
+/// # Safety
+///
+/// `name` must be null or point to a NUL-terminated string, and `attributes` must be
+/// null or point to `count` attributes whose strings are NUL-terminated or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn otel_posix_add_event(
+    name: *const c_char,
+    time: u64,
+    attributes: *const OtelPosixAttribute,
+    count: size_t,
+) -> c_int {
+    let Some(_guard) = reentry::enter() else {
+        return -1;
+    };
+    if name.is_null() {
+        return -1;
+    }
+    let added = Context::map_current(|cx| {
+        let span = cx.span();
+        span.is_recording().then(|| {
+            span.add_event_with_timestamp(
+                unsafe { CStr::from_ptr(name) }
+                    .to_string_lossy()
+                    .into_owned(),
+                SystemTime::UNIX_EPOCH + Duration::from_nanos(time),
+                unsafe { key_values(attributes, count) },
+            )
+        })
+    });
+    if added.is_some() { 0 } else { -1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes_are_converted_by_kind() {
+        let attribute = |key: &CStr, kind, string: *const c_char, int, double| OtelPosixAttribute {
+            key: key.as_ptr(),
+            kind,
+            string_value: string,
+            int_value: int,
+            double_value: double,
+        };
+        let null = std::ptr::null();
+        let attributes = [
+            attribute(c"path", OTEL_POSIX_VALUE_STRING, c"/tmp".as_ptr(), 0, 0.0),
+            attribute(c"bytes", OTEL_POSIX_VALUE_INT, null, 42, 0.0),
+            attribute(c"ratio", OTEL_POSIX_VALUE_DOUBLE, null, 0, 0.5),
+            attribute(c"hit", OTEL_POSIX_VALUE_BOOL, null, 1, 0.0),
+            // no string, and a kind this version doesn't know
+            attribute(c"empty", OTEL_POSIX_VALUE_STRING, null, 0, 0.0),
+            attribute(c"future", 99, null, 0, 0.0),
+        ];
+        let converted = unsafe { key_values(attributes.as_ptr(), attributes.len()) };
+        assert_eq!(
+            converted,
+            [
+                KeyValue::new("path", "/tmp"),
+                KeyValue::new("bytes", 42),
+                KeyValue::new("ratio", 0.5),
+                KeyValue::new("hit", true),
+            ]
+        );
+        assert!(unsafe { key_values(null.cast(), 3) }.is_empty());
+    }
+
+    #[test]
+    fn a_parent_has_to_be_a_traceparent() {
+        let add = |parent: &CStr| unsafe {
+            otel_posix_add_span(parent.as_ptr(), c"span".as_ptr(), 1, 2, std::ptr::null(), 0)
+        };
+        assert_eq!(
+            add(c"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"),
+            0
+        );
+        assert_eq!(add(c"not a traceparent"), -1);
+        assert_eq!(
+            unsafe {
+                otel_posix_add_span(
+                    std::ptr::null(),
+                    std::ptr::null(),
+                    1,
+                    2,
+                    std::ptr::null(),
+                    0,
+                )
+            },
+            -1
+        );
+    }
+
+    #[test]
+    fn kinds_are_mapped() {
+        assert_eq!(span_kind(OTEL_POSIX_SPAN_KIND_CLIENT), SpanKind::Client);
+        assert_eq!(span_kind(OTEL_POSIX_SPAN_KIND_SERVER), SpanKind::Server);
+        assert_eq!(span_kind(OTEL_POSIX_SPAN_KIND_CONSUMER), SpanKind::Consumer);
+        assert_eq!(span_kind(OTEL_POSIX_SPAN_KIND_INTERNAL), SpanKind::Internal);
+        assert_eq!(span_kind(99), SpanKind::Internal);
+    }
+
+    #[test]
+    fn span_ids_are_checked() {
+        assert_eq!(
+            parse_span_id("00f067aa0ba902b7"),
+            Some(SpanId::from(0x00f0_67aa_0ba9_02b7))
+        );
+        assert_eq!(parse_span_id("0000000000000000"), None);
+        assert_eq!(parse_span_id("00F067AA0BA902B7"), None);
+        assert_eq!(parse_span_id("00f067aa0ba902b"), None);
+        let add = |span_id: &CStr| unsafe {
+            otel_posix_add_span_with_id(
+                c"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".as_ptr(),
+                span_id.as_ptr(),
+                c"span".as_ptr(),
+                OTEL_POSIX_SPAN_KIND_CLIENT,
+                1,
+                2,
+                std::ptr::null(),
+                0,
+            )
+        };
+        assert_eq!(add(c"b7ad6b7169203331"), 0);
+        assert_eq!(add(c"not a span id"), -1);
+    }
+
+    #[test]
+    fn events_need_a_recording_span() {
+        // no span on this thread
+        assert_eq!(
+            unsafe { otel_posix_add_event(c"event".as_ptr(), 1, std::ptr::null(), 0) },
+            -1
+        );
+        assert_eq!(
+            unsafe { otel_posix_add_event(std::ptr::null(), 1, std::ptr::null(), 0) },
+            -1
+        );
+    }
+}
@@ -0,0 +1,88 @@
+// src/spawn.rs
+//
+// Explicit propagation for pure-Rust programs that don't want to rely on
+// the preload shim intercepting pthread_create.
+
+use opentelemetry::Context;
+use std::io;
+use std::thread::{self, JoinHandle};
+
+/// A `std::thread::Builder` that carries the spawning thread's OTEL `Context`
+/// into the new thread.
+///
+/// ```no_run
+/// use otel_posix_pseudo_propegator::PropagatingBuilder;
+///
+/// let handle = PropagatingBuilder::new()
+///     .name("worker".into())
+///     .spawn(|| 42)
+///     .unwrap();
+/// assert_eq!(handle.join().unwrap(), 42);
+/// ```
+#[derive(Debug)]
+pub struct PropagatingBuilder {
+    inner: thread::Builder,
+}
+
+impl PropagatingBuilder {
+    pub fn new() -> Self {
+        PropagatingBuilder {
+            inner: thread::Builder::new(),
+        }
+    }
+
+    /// Names the thread-to-be, see `std::thread::Builder::name`.
+    pub fn name(self, name: String) -> Self {
+        PropagatingBuilder {
+            inner: self.inner.name(name),
+        }
+    }
+
+    /// Sets the stack size for the new thread, see `std::thread::Builder::stack_size`.
+    pub fn stack_size(self, size: usize) -> Self {
+        PropagatingBuilder {
+            inner: self.inner.stack_size(size),
+        }
+    }
+
+    /// Spawns a new thread with the current `Context` attached for the lifetime of `f`.
+    pub fn spawn<F, T>(self, f: F) -> io::Result<JoinHandle<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        // capture on the parent, attach on the child
+        let cx = Context::current();
+        self.inner.spawn(move || {
+            let _guard = cx.attach();
+            f()
+        })
+    }
+}
+
+impl Default for PropagatingBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<thread::Builder> for PropagatingBuilder {
+    fn from(inner: thread::Builder) -> Self {
+        PropagatingBuilder { inner }
+    }
+}
+
+/// Drop-in replacement for `std::thread::spawn` that propagates the current `Context`.
+///
+/// # Panics
+///
+/// Panics if the OS fails to create a thread, like `std::thread::spawn`.
+pub fn spawn_with_otel<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    PropagatingBuilder::new()
+        .spawn(f)
+        .expect("failed to spawn thread")
+}
@@ -0,0 +1,130 @@
+// src/stack.rs
+//
+// Stacks as the shim reports them: walked with the unwinder caller.rs
+// declares, into an array, which is async-signal-safe, and written out
+// later one frame a line, the way `backtrace_symbols` has them, from the
+// first frame outside this library. The crash handler reports the
+// crashing stack this way, and with the `creation-stack` feature the span
+// a thread gets of its own says where it was created: the stack is walked
+// in pthread_create, and only symbolized in the new thread.
+
+use crate::caller::{
+    _Unwind_Backtrace, _Unwind_GetIP, URC_NO_REASON, URC_NORMAL_STOP, UnwindContext,
+};
+use libc::c_int;
+use std::ffi::{CStr, c_void};
+use std::fmt::Write as _;
+
+// frames walked at creation, this library's own included
+#[cfg(feature = "creation-stack")]
+const CREATION_FRAMES: usize = 32;
+
+struct Walk<'a> {
+    ips: &'a mut [usize],
+    frames: usize,
+}
+
+extern "C" fn visit(cx: *mut UnwindContext, arg: *mut c_void) -> c_int {
+    let walk = unsafe { &mut *arg.cast::<Walk>() };
+    walk.ips[walk.frames] = unsafe { _Unwind_GetIP(cx) };
+    walk.frames += 1;
+    if walk.frames == walk.ips.len() {
+        URC_NORMAL_STOP
+    } else {
+        URC_NO_REASON
+    }
+}
+
+/// Fills `ips` with the return addresses on this stack, the caller's own first, and
+/// returns how many there are.
+pub(crate) fn walk(ips: &mut [usize]) -> usize {
+    if ips.is_empty() {
+        return 0;
+    }
+    let mut walk = Walk { ips, frames: 0 };
+    unsafe { _Unwind_Backtrace(visit, (&raw mut walk).cast()) };
+    walk.frames
+}
+
+/// One frame a line, the way `backtrace_symbols` has them, from the first frame outside
+/// this library (the shim and the unwinder).
+pub(crate) fn stacktrace(ips: &[usize]) -> String {
+    let own = object_base(stacktrace as *const c_void);
+    let mut out = String::new();
+    let frames = ips
+        .iter()
+        .skip_while(|&&ip| object_base(ip as *const c_void) == own)
+        // the unwinder can end on a null one
+        .take_while(|&&ip| ip != 0);
+    for (i, &ip) in frames.enumerate() {
+        let _ = write!(out, "#{i} ");
+        let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+        // a return address, which for a call at the very end of a function is already
+        // past it
+        let found = unsafe { libc::dladdr((ip.saturating_sub(1)) as *const c_void, &mut info) };
+        if found != 0 && !info.dli_fname.is_null() {
+            let _ = write!(
+                out,
+                "{}",
+                unsafe { CStr::from_ptr(info.dli_fname) }.to_string_lossy()
+            );
+            if !info.dli_sname.is_null() {
+                let _ = write!(
+                    out,
+                    "({}+{:#x})",
+                    unsafe { CStr::from_ptr(info.dli_sname) }.to_string_lossy(),
+                    ip - info.dli_saddr as usize
+                );
+            } else {
+                let _ = write!(out, "(+{:#x})", ip - info.dli_fbase as usize);
+            }
+            out.push(' ');
+        }
+        let _ = writeln!(out, "[{ip:#x}]");
+    }
+    out
+}
+
+fn object_base(addr: *const c_void) -> Option<*mut c_void> {
+    let mut info = unsafe { std::mem::zeroed::<libc::Dl_info>() };
+    (unsafe { libc::dladdr(addr, &mut info) } != 0).then_some(info.dli_fbase)
+}
+
+/// The stack a thread was created from, as walked in pthread_create.
+#[cfg(feature = "creation-stack")]
+pub(crate) struct Creation {
+    ips: [usize; CREATION_FRAMES],
+    frames: usize,
+}
+
+#[cfg(feature = "creation-stack")]
+impl Creation {
+    pub(crate) fn capture() -> Creation {
+        let mut ips = [0; CREATION_FRAMES];
+        let frames = walk(&mut ips);
+        Creation { ips, frames }
+    }
+
+    /// The `code.stacktrace` attribute for the thread's span.
+    pub(crate) fn attribute(&self) -> opentelemetry::KeyValue {
+        opentelemetry::KeyValue::new("code.stacktrace", stacktrace(&self.ips[..self.frames]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stacks_start_outside_the_shim() {
+        let mut ips = [0; 48];
+        let frames = walk(&mut ips);
+        assert!(frames > 1);
+        let trace = stacktrace(&ips[..frames]);
+        // the test binary is this library too, so only the runtime's frames are left
+        let first = trace.lines().next().unwrap_or_default();
+        assert!(first.starts_with("#0 "), "{trace}");
+        assert!(!trace.contains(&format!("{:#x}]", ips[0])), "{trace}");
+        assert_eq!(walk(&mut ips[..2]), 2);
+    }
+}
@@ -0,0 +1,235 @@
+// src/status.rs
+//
+// otel_posix_prop_status(): a JSON report of what the shim is doing in this
+// process, for deployment tooling that wants to check it's loaded and
+// working inside a target (through dlsym, or gdb's `call`) without parsing
+// logs:
+//
+//     {"version":"0.1.0","linkage":"preload","wrapping":"enabled",
+//      "mode":"parent",...,"counts":{"wrapped":12,...}}
+
+use crate::{auto_root, config, config_file, log, metrics, recorder, sanitizer, suppress};
+use libc::{c_char, c_int, size_t};
+use std::fmt::Write as _;
+
+/// Writes a JSON object describing the shim's state into `buf` as a NUL-terminated string:
+/// its version and linkage, whether wrapping is enabled, the mode and hook switches, where
+/// the configuration came from, how `pthread_create` binds (and how many libraries loaded
+/// with RTLD_DEEPBIND or dlmopen were redirected, or couldn't be), the real symbols found
+/// so far, and the interposition counters.
+///
+/// Returns the length of the JSON (excluding the NUL). Like `snprintf`, nothing is
+/// written when `len` is too small, so callers can pass NULL first to size the buffer.
+///
+/// # Safety
+///
+/// `buf` must be null or valid for writes of `len` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn otel_posix_prop_status(buf: *mut c_char, len: size_t) -> c_int {
+    let json = status();
+    if !buf.is_null() && len > json.len() {
+        unsafe {
+            std::ptr::copy_nonoverlapping(json.as_ptr(), buf as *mut u8, json.len());
+            *buf.add(json.len()) = 0;
+        }
+    }
+    json.len() as c_int
+}
+
+/// The report, as of now.
+fn status() -> String {
+    let config = config::config();
+    let hooks = config.hooks;
+    let counts = metrics::counts();
+    #[cfg(feature = "preload")]
+    let (next, symbols) = (crate::resolve::next_object(), crate::resolve::resolved());
+    #[cfg(not(feature = "preload"))]
+    let (next, symbols): (Option<String>, Vec<(&str, Option<String>)>) = (None, Vec::new());
+    #[cfg(feature = "preload")]
+    let binding = (
+        crate::selftest::binding().map(|b| b.as_str()),
+        crate::selftest::found_in(),
+    );
+    // wrapped at link time
+    #[cfg(not(feature = "preload"))]
+    let binding = (Some("linked"), None::<&str>);
+    #[cfg(all(
+        feature = "hook-dlopen",
+        target_os = "linux",
+        target_env = "gnu",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    let (deepbind, isolated) = crate::namespaces::counts();
+    #[cfg(not(all(
+        feature = "hook-dlopen",
+        target_os = "linux",
+        target_env = "gnu",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    )))]
+    let (deepbind, isolated) = (0, 0);
+
+    let mut out = String::from("{");
+    let _ = write!(
+        out,
+        "\"version\":{},\"linkage\":{},\"opentelemetry\":{}",
+        string(env!("CARGO_PKG_VERSION")),
+        string(if cfg!(feature = "preload") {
+            "preload"
+        } else {
+            "linker-wrap"
+        }),
+        string(if cfg!(feature = "otel-0_31") {
+            "0.31"
+        } else {
+            "0.30"
+        }),
+    );
+    let _ = write!(
+        out,
+        ",\"sanitizer\":{}",
+        sanitizer::detected().map_or("null".to_string(), |s| string(s.as_str()))
+    );
+    let _ = write!(
+        out,
+        ",\"wrapping\":{},\"mode\":{},\"auto_root\":{},\"recorder\":{},\"redact_ids\":{},\"exec_allowlist\":{}",
+        string(if suppress::is_paused() {
+            "disabled"
+        } else {
+            "enabled"
+        }),
+        string(config.mode.as_str()),
+        auto_root::enabled(),
+        recorder::enabled(),
+        log::redact_ids(),
+        config.exec_allow.is_some(),
+    );
+    let _ = write!(
+        out,
+        ",\"hooks\":{{\"pthread_create\":{},\"exec\":{},\"http\":{},\"ucontext\":{},\"syslog\":{},\"join\":{},\"children\":{},\"dlopen\":{},\"once\":{},\"once_spans\":{}}}",
+        hooks.pthread_create,
+        hooks.exec,
+        hooks.http,
+        hooks.ucontext,
+        hooks.syslog,
+        config.join_events,
+        config.child_spans,
+        config.dlopen_events,
+        config.once_events,
+        config.once_spans,
+    );
+    let _ = write!(
+        out,
+        ",\"config\":{{\"source\":{},\"file\":{}}}",
+        string(if config_file::path().is_some() {
+            "file"
+        } else {
+            "env"
+        }),
+        config_file::path().map_or("null".to_string(), string),
+    );
+    let _ = write!(
+        out,
+        ",\"binding\":{{\"state\":{},\"object\":{},\"deepbind\":{},\"isolated\":{}}}",
+        binding.0.map_or("null".to_string(), string),
+        binding.1.map_or("null".to_string(), string),
+        deepbind,
+        isolated,
+    );
+    let _ = write!(
+        out,
+        ",\"next_pthread_create\":{}",
+        next.as_deref().map_or("null".to_string(), string)
+    );
+    out.push_str(",\"symbols\":{");
+    for (i, (name, object)) in symbols.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(
+            out,
+            "{}:{}",
+            string(name),
+            object.as_deref().map_or("null".to_string(), string)
+        );
+    }
+    let _ = write!(
+        out,
+        "}},\"counts\":{{\"wrapped\":{},\"passed_through\":{},\"wrap_failures\":{},\"rate_limited\":{}}}}}",
+        counts.wrapped, counts.passed_through, counts.wrap_failures, counts.rate_limited
+    );
+    out
+}
+
+/// `s` as a JSON string literal.
+pub(crate) fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", u32::from(c));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn json_strings_are_escaped() {
+        assert_eq!(string("libc.so.6"), "\"libc.so.6\"");
+        assert_eq!(string("a\"b\\c\nd\x01"), "\"a\\\"b\\\\c\\nd\\u0001\"");
+    }
+
+    #[test]
+    fn status_reports_state_and_counts() {
+        let n = unsafe { otel_posix_prop_status(std::ptr::null_mut(), 0) };
+        let mut buf = vec![0 as c_char; n as usize + 64];
+        let written = unsafe { otel_posix_prop_status(buf.as_mut_ptr(), buf.len()) };
+        let json = unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str().unwrap();
+        assert_eq!(written as usize, json.len());
+        assert!(
+            json.starts_with(&format!(
+                "{{\"version\":\"{}\",\"linkage\":",
+                env!("CARGO_PKG_VERSION")
+            )),
+            "{json}"
+        );
+        assert!(
+            json.contains(",\"redact_ids\":false,\"exec_allowlist\":false,"),
+            "{json}"
+        );
+        assert!(
+            json.contains(",\"hooks\":{\"pthread_create\":true,"),
+            "{json}"
+        );
+        assert!(
+            json.contains(",\"config\":{\"source\":\"env\",\"file\":null}"),
+            "{json}"
+        );
+        #[cfg(feature = "preload")]
+        assert!(
+            json.contains(",\"binding\":{\"state\":\"linked\",\"object\":null,\"deepbind\":"),
+            "{json}"
+        );
+        assert!(json.contains(",\"counts\":{\"wrapped\":"), "{json}");
+        assert!(json.ends_with("}}"), "{json}");
+    }
+
+    #[test]
+    fn short_buffer_is_left_alone() {
+        let mut buf = [0x7f as c_char; 8];
+        let n = unsafe { otel_posix_prop_status(buf.as_mut_ptr(), buf.len()) };
+        assert!(n as usize > buf.len());
+        assert!(buf.iter().all(|&c| c == 0x7f));
+    }
+}
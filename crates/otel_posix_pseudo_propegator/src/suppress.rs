@@ -0,0 +1,80 @@
+// src/suppress.rs
+//
+// Keeping the shim away from threads that shouldn't carry a context. The
+// SDK's own workers (batch span processor, periodic metric reader) and the
+// threads the exporters start while exporting are telemetry plumbing, and
+// parenting them under whatever span happened to be active is meaningless
+// at best and feeds spans back into the exporter at worst.
+//
+// Two things make pthread_create pass straight through: the OTEL
+// "telemetry suppressed" flag on the current context, which the SDK sets
+// around everything it does internally, and our own thread-local guard for
+// spawn sites that don't set it. Operators can also pause wrapping for the
+// whole process through the control channel.
+
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static DEPTH: Cell<u32> = const { Cell::new(0) };
+}
+
+/// While alive, threads created on this thread are not wrapped.
+///
+/// Returned by [`suppress_wrapping`]; guards nest.
+#[derive(Debug)]
+#[must_use = "wrapping is only suppressed while the guard is alive"]
+pub struct SuppressGuard {
+    // the count is per thread, so the guard has to stay on it
+    _not_send: PhantomData<*const ()>,
+}
+
+/// Stops the shim from wrapping threads created on the current thread until the returned
+/// guard is dropped.
+pub fn suppress_wrapping() -> SuppressGuard {
+    DEPTH.set(DEPTH.get() + 1);
+    SuppressGuard {
+        _not_send: PhantomData,
+    }
+}
+
+impl Drop for SuppressGuard {
+    fn drop(&mut self) {
+        DEPTH.set(DEPTH.get() - 1);
+    }
+}
+
+/// Whether a [`SuppressGuard`] is alive on this thread, or wrapping is paused.
+pub(crate) fn is_suppressed() -> bool {
+    DEPTH.get() > 0 || is_paused()
+}
+
+/// Whether wrapping is paused for the whole process.
+pub(crate) fn is_paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+/// Pauses or resumes wrapping for the whole process.
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+pub(crate) fn set_paused(paused: bool) {
+    PAUSED.store(paused, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guards_nest() {
+        assert!(!is_suppressed());
+        let outer = suppress_wrapping();
+        let inner = suppress_wrapping();
+        drop(inner);
+        assert!(is_suppressed());
+        drop(outer);
+        assert!(!is_suppressed());
+    }
+}
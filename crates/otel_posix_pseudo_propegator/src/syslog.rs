@@ -0,0 +1,357 @@
+// src/syslog.rs
+//
+// Log/trace correlation for daemons that log through syslog: while a span
+// is active, `trace_id=... span_id=...` is appended to every message, so a
+// log line leads straight to its trace.
+//
+// The fields are added to the format string, which leaves the arguments
+// alone. vsyslog takes them as a va_list, which on the supported ABIs is
+// passed as a pointer and can simply be handed on. syslog itself is
+// variadic, which Rust can't define, so it is a naked function: it saves
+// the argument registers, asks `prepare` for the format to use and the
+// real function, restores the arguments with the new format in place and
+// jumps to the real syslog, which then returns straight to the caller.
+// That format lives in a per-thread buffer until the next message.
+//
+// glibc's _FORTIFY_SOURCE builds call __syslog_chk and __vsyslog_chk
+// instead, which take an extra flag before the format.
+
+use crate::config::config;
+use crate::{reentry, resolve};
+use libc::{c_char, c_int};
+use opentelemetry::Context;
+use opentelemetry::trace::{SpanContext, TraceContextExt};
+use std::cell::RefCell;
+use std::ffi::{CStr, c_void};
+use std::sync::atomic::AtomicPtr;
+
+// a va_list argument, which both supported ABIs pass by reference
+pub(crate) type VaList = *mut c_void;
+
+type VsyslogFn = unsafe extern "C" fn(c_int, *const c_char, VaList);
+#[cfg(target_env = "gnu")]
+type VsyslogChkFn = unsafe extern "C" fn(c_int, c_int, *const c_char, VaList);
+
+static REAL_SYSLOG: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+#[cfg(target_env = "gnu")]
+static REAL_SYSLOG_CHK: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+thread_local! {
+    // the rewritten format of this thread's last syslog call
+    static FORMAT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The span to correlate with, if there is one worth naming.
+fn current_span() -> Option<SpanContext> {
+    let sc = Context::map_current(|cx| {
+        (cx.has_active_span() && !cx.is_telemetry_suppressed())
+            .then(|| cx.span().span_context().clone())
+    })?;
+    // the configuration is only read once there's something to add
+    (sc.is_valid() && config().hooks.syslog).then_some(sc)
+}
+
+/// `fmt` with the trace fields appended, before any trailing newlines, NUL-terminated.
+fn annotate(fmt: &CStr, sc: &SpanContext, out: &mut Vec<u8>) {
+    let fmt = fmt.to_bytes();
+    let end = fmt.len() - fmt.iter().rev().take_while(|&&b| b == b'\n').count();
+    out.clear();
+    out.extend_from_slice(&fmt[..end]);
+    let fields = format!(" trace_id={} span_id={}", sc.trace_id(), sc.span_id());
+    out.extend_from_slice(fields.as_bytes());
+    out.extend_from_slice(&fmt[end..]);
+    out.push(0);
+}
+
+/// The format to log `fmt` with: annotated, or `fmt` itself without a span (or a buffer).
+fn rewrite(fmt: *const c_char, buf: &mut Vec<u8>) -> *const c_char {
+    if fmt.is_null() {
+        return fmt;
+    }
+    let Some(_guard) = reentry::enter() else {
+        return fmt;
+    };
+    let Some(sc) = current_span() else {
+        return fmt;
+    };
+    annotate(unsafe { CStr::from_ptr(fmt) }, &sc, buf);
+    buf.as_ptr().cast()
+}
+
+// the real function can't be looked up from inside a lookup; the message is dropped then
+unsafe extern "C" fn dropped() {}
+
+/// Called from the naked `syslog`s with the caller's format: returns the format to log
+/// with and stores the real function to jump to in `real`.
+unsafe extern "C" fn prepare(
+    fmt: *const c_char,
+    chk: usize,
+    real: *mut *const c_void,
+) -> *const c_char {
+    // %m in the format still has to see the caller's errno
+    let errno = unsafe { *libc::__errno_location() };
+    #[cfg(target_env = "gnu")]
+    let cache = if chk != 0 {
+        (&REAL_SYSLOG_CHK, c"__syslog_chk")
+    } else {
+        (&REAL_SYSLOG, c"syslog")
+    };
+    #[cfg(not(target_env = "gnu"))]
+    let cache = {
+        let _ = chk;
+        (&REAL_SYSLOG, c"syslog")
+    };
+    let target = resolve::cached_next(cache.0, cache.1);
+    unsafe { *real = target.map_or(dropped as *const c_void, |sym| sym.cast_const()) };
+    let fmt = FORMAT
+        .try_with(|buf| rewrite(fmt, &mut buf.borrow_mut()))
+        .unwrap_or(fmt);
+    unsafe { *libc::__errno_location() = errno };
+    fmt
+}
+
+/// Interposed `syslog` that appends the current trace and span id to the message.
+///
+/// # Safety
+///
+/// Same contract as libc's `syslog`.
+#[cfg(target_arch = "x86_64")]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn syslog(priority: c_int, format: *const c_char) {
+    std::arch::naked_asm!(
+        "push rbp",
+        "mov rbp, rsp",
+        "sub rsp, 192",
+        "mov qword ptr [rsp], rdi",
+        "mov qword ptr [rsp + 8], rsi",
+        "mov qword ptr [rsp + 16], rdx",
+        "mov qword ptr [rsp + 24], rcx",
+        "mov qword ptr [rsp + 32], r8",
+        "mov qword ptr [rsp + 40], r9",
+        // al: vector registers used by the variadic arguments
+        "mov qword ptr [rsp + 48], rax",
+        "movdqu xmmword ptr [rsp + 64], xmm0",
+        "movdqu xmmword ptr [rsp + 80], xmm1",
+        "movdqu xmmword ptr [rsp + 96], xmm2",
+        "movdqu xmmword ptr [rsp + 112], xmm3",
+        "movdqu xmmword ptr [rsp + 128], xmm4",
+        "movdqu xmmword ptr [rsp + 144], xmm5",
+        "movdqu xmmword ptr [rsp + 160], xmm6",
+        "movdqu xmmword ptr [rsp + 176], xmm7",
+        "mov rdi, rsi",
+        "xor esi, esi",
+        "lea rdx, [rsp + 56]",
+        "call {prepare}",
+        "mov r10, rax",
+        "mov rdi, qword ptr [rsp]",
+        "mov rdx, qword ptr [rsp + 16]",
+        "mov rcx, qword ptr [rsp + 24]",
+        "mov r8, qword ptr [rsp + 32]",
+        "mov r9, qword ptr [rsp + 40]",
+        "mov rax, qword ptr [rsp + 48]",
+        "mov r11, qword ptr [rsp + 56]",
+        "movdqu xmm0, xmmword ptr [rsp + 64]",
+        "movdqu xmm1, xmmword ptr [rsp + 80]",
+        "movdqu xmm2, xmmword ptr [rsp + 96]",
+        "movdqu xmm3, xmmword ptr [rsp + 112]",
+        "movdqu xmm4, xmmword ptr [rsp + 128]",
+        "movdqu xmm5, xmmword ptr [rsp + 144]",
+        "movdqu xmm6, xmmword ptr [rsp + 160]",
+        "movdqu xmm7, xmmword ptr [rsp + 176]",
+        "mov rsi, r10",
+        "leave",
+        "jmp r11",
+        prepare = sym prepare,
+    )
+}
+
+/// Interposed `syslog` that appends the current trace and span id to the message.
+///
+/// # Safety
+///
+/// Same contract as libc's `syslog`.
+#[cfg(target_arch = "aarch64")]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn syslog(priority: c_int, format: *const c_char) {
+    std::arch::naked_asm!(
+        "stp x29, x30, [sp, #-224]!",
+        "mov x29, sp",
+        "stp x0, x1, [sp, #16]",
+        "stp x2, x3, [sp, #32]",
+        "stp x4, x5, [sp, #48]",
+        "stp x6, x7, [sp, #64]",
+        "stp q0, q1, [sp, #80]",
+        "stp q2, q3, [sp, #112]",
+        "stp q4, q5, [sp, #144]",
+        "stp q6, q7, [sp, #176]",
+        "mov x0, x1",
+        "mov x1, #0",
+        "add x2, sp, #208",
+        "bl {prepare}",
+        "mov x9, x0",
+        "ldr x16, [sp, #208]",
+        "ldp x0, x1, [sp, #16]",
+        "ldp x2, x3, [sp, #32]",
+        "ldp x4, x5, [sp, #48]",
+        "ldp x6, x7, [sp, #64]",
+        "ldp q0, q1, [sp, #80]",
+        "ldp q2, q3, [sp, #112]",
+        "ldp q4, q5, [sp, #144]",
+        "ldp q6, q7, [sp, #176]",
+        "mov x1, x9",
+        "ldp x29, x30, [sp], #224",
+        "br x16",
+        prepare = sym prepare,
+    )
+}
+
+/// Interposed `__syslog_chk`, the `_FORTIFY_SOURCE` variant of [`syslog`].
+///
+/// # Safety
+///
+/// Same contract as glibc's `__syslog_chk`.
+#[cfg(all(target_env = "gnu", target_arch = "x86_64"))]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn __syslog_chk(priority: c_int, flag: c_int, format: *const c_char) {
+    std::arch::naked_asm!(
+        "push rbp",
+        "mov rbp, rsp",
+        "sub rsp, 192",
+        "mov qword ptr [rsp], rdi",
+        "mov qword ptr [rsp + 8], rsi",
+        "mov qword ptr [rsp + 16], rdx",
+        "mov qword ptr [rsp + 24], rcx",
+        "mov qword ptr [rsp + 32], r8",
+        "mov qword ptr [rsp + 40], r9",
+        "mov qword ptr [rsp + 48], rax",
+        "movdqu xmmword ptr [rsp + 64], xmm0",
+        "movdqu xmmword ptr [rsp + 80], xmm1",
+        "movdqu xmmword ptr [rsp + 96], xmm2",
+        "movdqu xmmword ptr [rsp + 112], xmm3",
+        "movdqu xmmword ptr [rsp + 128], xmm4",
+        "movdqu xmmword ptr [rsp + 144], xmm5",
+        "movdqu xmmword ptr [rsp + 160], xmm6",
+        "movdqu xmmword ptr [rsp + 176], xmm7",
+        "mov rdi, rdx",
+        "mov esi, 1",
+        "lea rdx, [rsp + 56]",
+        "call {prepare}",
+        "mov r10, rax",
+        "mov rdi, qword ptr [rsp]",
+        "mov rsi, qword ptr [rsp + 8]",
+        "mov rcx, qword ptr [rsp + 24]",
+        "mov r8, qword ptr [rsp + 32]",
+        "mov r9, qword ptr [rsp + 40]",
+        "mov rax, qword ptr [rsp + 48]",
+        "mov r11, qword ptr [rsp + 56]",
+        "movdqu xmm0, xmmword ptr [rsp + 64]",
+        "movdqu xmm1, xmmword ptr [rsp + 80]",
+        "movdqu xmm2, xmmword ptr [rsp + 96]",
+        "movdqu xmm3, xmmword ptr [rsp + 112]",
+        "movdqu xmm4, xmmword ptr [rsp + 128]",
+        "movdqu xmm5, xmmword ptr [rsp + 144]",
+        "movdqu xmm6, xmmword ptr [rsp + 160]",
+        "movdqu xmm7, xmmword ptr [rsp + 176]",
+        "mov rdx, r10",
+        "leave",
+        "jmp r11",
+        prepare = sym prepare,
+    )
+}
+
+/// Interposed `__syslog_chk`, the `_FORTIFY_SOURCE` variant of [`syslog`].
+///
+/// # Safety
+///
+/// Same contract as glibc's `__syslog_chk`.
+#[cfg(all(target_env = "gnu", target_arch = "aarch64"))]
+#[unsafe(naked)]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn __syslog_chk(priority: c_int, flag: c_int, format: *const c_char) {
+    std::arch::naked_asm!(
+        "stp x29, x30, [sp, #-224]!",
+        "mov x29, sp",
+        "stp x0, x1, [sp, #16]",
+        "stp x2, x3, [sp, #32]",
+        "stp x4, x5, [sp, #48]",
+        "stp x6, x7, [sp, #64]",
+        "stp q0, q1, [sp, #80]",
+        "stp q2, q3, [sp, #112]",
+        "stp q4, q5, [sp, #144]",
+        "stp q6, q7, [sp, #176]",
+        "mov x0, x2",
+        "mov x1, #1",
+        "add x2, sp, #208",
+        "bl {prepare}",
+        "mov x9, x0",
+        "ldr x16, [sp, #208]",
+        "ldp x0, x1, [sp, #16]",
+        "ldp x2, x3, [sp, #32]",
+        "ldp x4, x5, [sp, #48]",
+        "ldp x6, x7, [sp, #64]",
+        "ldp q0, q1, [sp, #80]",
+        "ldp q2, q3, [sp, #112]",
+        "ldp q4, q5, [sp, #144]",
+        "ldp q6, q7, [sp, #176]",
+        "mov x2, x9",
+        "ldp x29, x30, [sp], #224",
+        "br x16",
+        prepare = sym prepare,
+    )
+}
+
+/// `vsyslog`, with the current trace and span id appended to the message.
+pub(crate) unsafe fn vsyslog(real: VsyslogFn, priority: c_int, format: *const c_char, ap: VaList) {
+    let errno = unsafe { *libc::__errno_location() };
+    let mut buf = Vec::new();
+    let format = rewrite(format, &mut buf);
+    unsafe { *libc::__errno_location() = errno };
+    unsafe { real(priority, format, ap) }
+}
+
+/// `__vsyslog_chk`, the `_FORTIFY_SOURCE` variant of [`vsyslog`].
+#[cfg(target_env = "gnu")]
+pub(crate) unsafe fn __vsyslog_chk(
+    real: VsyslogChkFn,
+    priority: c_int,
+    flag: c_int,
+    format: *const c_char,
+    ap: VaList,
+) {
+    let errno = unsafe { *libc::__errno_location() };
+    let mut buf = Vec::new();
+    let format = rewrite(format, &mut buf);
+    unsafe { *libc::__errno_location() = errno };
+    unsafe { real(priority, flag, format, ap) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanId, TraceFlags, TraceId, TraceState};
+
+    #[test]
+    fn fields_go_before_trailing_newlines() {
+        let sc = SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        );
+        let mut out = Vec::new();
+        annotate(c"job %d done: %m\n", &sc, &mut out);
+        assert_eq!(
+            out,
+            b"job %d done: %m trace_id=4bf92f3577b34da6a3ce929d0e0e4736 span_id=00f067aa0ba902b7\n\0"
+        );
+        annotate(c"", &sc, &mut out);
+        assert_eq!(
+            out,
+            b" trace_id=4bf92f3577b34da6a3ce929d0e0e4736 span_id=00f067aa0ba902b7\0"
+        );
+    }
+}
@@ -0,0 +1,120 @@
+// src/test_support.rs
+//
+// Helpers for tests that check propagation through the spans a program
+// actually exports, rather than by comparing ids read inside each thread:
+// an in-memory exporter behind the global provider, and assertions on how
+// the spans it caught relate to each other. Log records emitted through the
+// shim can be caught the same way. Built with the `test-support` feature,
+// for dev-dependencies.
+
+use crate::filter::SpanNameFilterProcessor;
+use opentelemetry::global;
+use opentelemetry_sdk::logs::{InMemoryLogExporter, SdkLoggerProvider};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use std::sync::OnceLock;
+
+static EXPORTER: OnceLock<InMemorySpanExporter> = OnceLock::new();
+static LOG_EXPORTER: OnceLock<InMemoryLogExporter> = OnceLock::new();
+
+/// Installs a global tracer provider that keeps every finished span in memory, and
+/// returns its exporter. Later calls return the same exporter, so tests running in
+/// parallel share it; give their spans distinct names.
+pub fn install() -> InMemorySpanExporter {
+    EXPORTER
+        .get_or_init(|| {
+            let exporter = InMemorySpanExporter::default();
+            // a span processor of the SDK's own that starts threads would be wrapped
+            let _suppress = crate::suppress_wrapping();
+            let provider = SdkTracerProvider::builder()
+                .with_span_processor(SpanNameFilterProcessor)
+                .with_simple_exporter(exporter.clone())
+                .build();
+            global::set_tracer_provider(provider);
+            exporter
+        })
+        .clone()
+}
+
+/// Installs a logger provider that keeps every record emitted through the shim in memory,
+/// and returns its exporter. As with [`install`], later calls return the same exporter.
+pub fn install_logs() -> InMemoryLogExporter {
+    LOG_EXPORTER
+        .get_or_init(|| {
+            let exporter = InMemoryLogExporter::default();
+            let provider = SdkLoggerProvider::builder()
+                .with_simple_exporter(exporter.clone())
+                .build();
+            crate::logs::install(Some(provider));
+            exporter
+        })
+        .clone()
+}
+
+/// The one finished span named `name`.
+///
+/// # Panics
+///
+/// If no span, or more than one, of that name has ended.
+pub fn finished_span(exporter: &InMemorySpanExporter, name: &str) -> SpanData {
+    let mut spans: Vec<SpanData> = exporter
+        .get_finished_spans()
+        .expect("in-memory exporter failed")
+        .into_iter()
+        .filter(|s| s.name == name)
+        .collect();
+    match spans.len() {
+        1 => spans.remove(0),
+        0 => panic!("no finished span named {name:?}"),
+        n => panic!("{n} finished spans named {name:?}"),
+    }
+}
+
+/// Asserts that `child` belongs to the same trace as `parent`.
+#[track_caller]
+pub fn assert_same_trace(parent: &SpanData, child: &SpanData) {
+    assert_eq!(
+        child.span_context.trace_id(),
+        parent.span_context.trace_id(),
+        "span {:?} is not in the trace of {:?}",
+        child.name,
+        parent.name
+    );
+}
+
+/// Asserts that `child` was started as a direct child of `parent`.
+#[track_caller]
+pub fn assert_child_of(parent: &SpanData, child: &SpanData) {
+    assert_same_trace(parent, child);
+    assert_eq!(
+        child.parent_span_id,
+        parent.span_context.span_id(),
+        "span {:?} is not a child of {:?}",
+        child.name,
+        parent.name
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{Tracer, TracerProvider};
+
+    #[test]
+    fn parentage() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let tracer = provider.tracer("test");
+        tracer.in_span("outer", |_| tracer.in_span("inner", |_| {}));
+        tracer.in_span("unrelated", |_| {});
+
+        let outer = finished_span(&exporter, "outer");
+        assert_child_of(&outer, &finished_span(&exporter, "inner"));
+        let unrelated = finished_span(&exporter, "unrelated");
+        assert_ne!(
+            unrelated.span_context.trace_id(),
+            outer.span_context.trace_id()
+        );
+    }
+}
@@ -0,0 +1,72 @@
+// src/thrd.rs
+//
+// C11 threads. glibc's and musl's thrd_create call their pthread_create
+// internally, without going through the symbol we interpose, so threads
+// started from <threads.h> would never be wrapped. thrd_create is
+// interposed as well and sent through the same wrapping as pthread_create.
+//
+// A C11 start routine returns an int where a pthread one returns a pointer.
+// Both come back in the same register, and the libcs' own thrd_create
+// already passes one off as the other, so the trampoline does the same.
+
+use crate::{StartRoutine, reentry, resolve};
+use libc::c_int;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+// thrd_t is a pthread_t in both glibc and musl
+type Thrd = libc::pthread_t;
+type ThrdStart = extern "C" fn(*mut c_void) -> c_int;
+type ThrdCreateFn = unsafe extern "C" fn(*mut Thrd, ThrdStart, *mut c_void) -> c_int;
+
+// <threads.h>'s code for a thread that couldn't be started for lack of resources, which
+// is what the libcs map EAGAIN to
+const THRD_NOMEM: c_int = 3;
+
+static REAL_THRD_CREATE: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+/// The real `thrd_create` if it has been resolved already.
+fn cached_real() -> Option<ThrdCreateFn> {
+    let sym = REAL_THRD_CREATE.load(Ordering::Acquire);
+    (!sym.is_null()).then(|| unsafe { std::mem::transmute::<*mut c_void, ThrdCreateFn>(sym) })
+}
+
+/// Interposed `thrd_create` that carries the caller's OTEL `Context` into the new thread.
+///
+/// # Safety
+///
+/// Same contract as libc's `thrd_create`: `thr` must be valid for writes and `arg` valid
+/// for `func`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn thrd_create(thr: *mut Thrd, func: ThrdStart, arg: *mut c_void) -> c_int {
+    let guard = (!resolve::in_progress()).then(reentry::enter).flatten();
+    let Some(_guard) = guard else {
+        // nested call, e.g. from a malloc interposer while we were resolving or wrapping
+        return match cached_real() {
+            Some(real) => unsafe { real(thr, func, arg) },
+            None => THRD_NOMEM,
+        };
+    };
+    let Some(real) = resolve::cached_next(&REAL_THRD_CREATE, c"thrd_create") else {
+        // failing one thread creation beats taking down the host
+        return THRD_NOMEM;
+    };
+    let real = unsafe { std::mem::transmute::<*mut c_void, ThrdCreateFn>(real) };
+    let start = unsafe { std::mem::transmute::<ThrdStart, StartRoutine>(func) };
+    // thrd_join doesn't go through pthread_join, so there is no joining to track
+    unsafe {
+        crate::create_wrapped(
+            std::ptr::null_mut(),
+            std::ptr::null(),
+            start,
+            arg,
+            |start, arg| {
+                real(
+                    thr,
+                    std::mem::transmute::<StartRoutine, ThrdStart>(start),
+                    arg,
+                )
+            },
+        )
+    }
+}
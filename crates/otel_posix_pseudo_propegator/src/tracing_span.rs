@@ -0,0 +1,19 @@
+// src/tracing_span.rs
+//
+// Applications instrumented with the `tracing` crate keep their current span
+// in tracing's own dispatcher rather than in the OTEL Context, so with the
+// `tracing` feature the creator's span is captured alongside the context and
+// entered in the new thread.
+
+use tracing::Span;
+
+/// Whether the creating thread is inside a `tracing` span. Doesn't clone the span.
+pub(crate) fn active() -> bool {
+    tracing::dispatcher::get_default(|dispatch| dispatch.current_span().is_known())
+}
+
+/// The creating thread's current `tracing` span, if it has one.
+pub(crate) fn capture() -> Option<Span> {
+    let span = Span::current();
+    (!span.is_none()).then_some(span)
+}
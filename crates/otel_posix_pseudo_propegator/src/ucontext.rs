@@ -0,0 +1,120 @@
+// src/ucontext.rs
+//
+// Context propagation across ucontext switches, for coroutine libraries
+// built on makecontext/swapcontext. The OTEL context is thread-local, so a
+// coroutine switch would otherwise leave the next coroutine running under
+// whatever the previous one had attached.
+//
+// Every ucontext_t gets the context it should resume under: makecontext
+// records the creator's, swapcontext the suspended one's. Switching to a
+// ucontext attaches its saved context with a guard parked in a
+// thread-local, replaced at the next switch. A coroutine that returns into
+// its uc_link goes straight through libc and keeps its context until the
+// next switch.
+//
+// makecontext is variadic, which Rust can only call, not define. The
+// interposer takes a fixed number of extra integer arguments instead and
+// passes all of them on; on the supported ABIs the first ones travel in the
+// same registers either way, so this covers argc up to MAX_ARGS.
+
+use crate::config::config;
+use crate::log::log_warn;
+use crate::registry;
+use libc::{c_int, c_long, ucontext_t};
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::{Context, ContextGuard};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex, MutexGuard, PoisonError};
+
+/// Extra `makecontext` arguments passed on to the real one.
+pub(crate) const MAX_ARGS: c_int = 6;
+
+type MakecontextFn = unsafe extern "C" fn(*mut ucontext_t, extern "C" fn(), c_int, ...);
+type SwapcontextFn = unsafe extern "C" fn(*mut ucontext_t, *const ucontext_t) -> c_int;
+type SetcontextFn = unsafe extern "C" fn(*const ucontext_t) -> c_int;
+
+// the context each known ucontext_t resumes under, by address
+static SAVED: LazyLock<Mutex<HashMap<usize, Context>>> = LazyLock::new(Default::default);
+
+// set once a context has been saved; until then switches pass straight through
+static TRACKING: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    // the context attached by the last switch on this thread
+    static SWITCHED: RefCell<Option<ContextGuard>> = const { RefCell::new(None) };
+}
+
+fn lock_saved() -> MutexGuard<'static, HashMap<usize, Context>> {
+    SAVED.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Whether switches on this thread need handling: something was saved already, or a span
+/// is active that the next coroutine should see.
+fn tracking() -> bool {
+    TRACKING.load(Ordering::Relaxed) || Context::map_current(|cx| cx.has_active_span())
+}
+
+/// Saves the current context as the one `ucp` resumes under.
+fn save(ucp: *const ucontext_t) {
+    TRACKING.store(true, Ordering::Relaxed);
+    lock_saved().insert(ucp as usize, Context::current());
+}
+
+/// Attaches the context `ucp` resumes under, in place of the previous switch's.
+fn resume(ucp: *const ucontext_t) {
+    let cx = lock_saved().remove(&(ucp as usize));
+    let sc = cx.as_ref().map(|cx| cx.span().span_context().clone());
+    registry::set_current(sc.as_ref());
+    let _ = SWITCHED.try_with(|switched| {
+        // the previous guard goes first, so dropping it doesn't undo the new one
+        drop(switched.borrow_mut().take());
+        *switched.borrow_mut() = cx.map(Context::attach);
+    });
+}
+
+/// `makecontext`, letting the new coroutine start under the caller's context. Passes up
+/// to [`MAX_ARGS`] arguments for `func` on.
+#[allow(clippy::too_many_arguments)]
+pub(crate) unsafe fn makecontext(
+    real: MakecontextFn,
+    ucp: *mut ucontext_t,
+    func: extern "C" fn(),
+    argc: c_int,
+    a0: c_long,
+    a1: c_long,
+    a2: c_long,
+    a3: c_long,
+    a4: c_long,
+    a5: c_long,
+) {
+    if argc > MAX_ARGS {
+        log_warn!("makecontext with {argc} arguments, only the first {MAX_ARGS} are passed on");
+    }
+    if tracking() && config().hooks.ucontext {
+        save(ucp);
+    }
+    unsafe { real(ucp, func, argc, a0, a1, a2, a3, a4, a5) }
+}
+
+/// `swapcontext`, saving the current context for `oucp` and resuming `ucp` under its own.
+pub(crate) unsafe fn swapcontext(
+    real: SwapcontextFn,
+    oucp: *mut ucontext_t,
+    ucp: *const ucontext_t,
+) -> c_int {
+    if tracking() && config().hooks.ucontext {
+        save(oucp);
+        resume(ucp);
+    }
+    unsafe { real(oucp, ucp) }
+}
+
+/// `setcontext`, resuming `ucp` under its own context.
+pub(crate) unsafe fn setcontext(real: SetcontextFn, ucp: *const ucontext_t) -> c_int {
+    if TRACKING.load(Ordering::Relaxed) && config().hooks.ucontext {
+        resume(ucp);
+    }
+    unsafe { real(ucp) }
+}
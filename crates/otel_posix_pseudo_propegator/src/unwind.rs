@@ -0,0 +1,99 @@
+// src/unwind.rs
+//
+// The trampoline's own setup and teardown run between C frames, where a
+// panic must not unwind. They are run through `guarded`, which catches the
+// panic, reports it on the creating span and lets the thread carry on
+// without whatever was being set up. The process-wide panic hook
+// (panics.rs) leaves those panics to it.
+
+use crate::log::log_warn;
+use opentelemetry::trace::{Status, TraceContextExt};
+use opentelemetry::{Context, KeyValue};
+use std::any::Any;
+use std::cell::Cell;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+thread_local! {
+    // set while `guarded` runs something on this thread
+    static GUARDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f`, or returns `None` if it panicked, after recording the panic on the span of
+/// `cx`.
+pub(crate) fn guarded<T>(cx: Option<&Context>, f: impl FnOnce() -> T) -> Option<T> {
+    let outer = GUARDING.replace(true);
+    let caught = catch_unwind(AssertUnwindSafe(f));
+    GUARDING.set(outer);
+    let payload = match caught {
+        Ok(value) => return Some(value),
+        Err(payload) => payload,
+    };
+    let message = panic_message(&*payload);
+    log_warn!("panic in the thread trampoline: {message}");
+    if let Some(cx) = cx {
+        // the SDK may well be what panicked
+        let _ = catch_unwind(AssertUnwindSafe(|| record(cx, &message)));
+    }
+    // a payload's destructor can panic too
+    let _ = catch_unwind(AssertUnwindSafe(|| drop(payload)));
+    None
+}
+
+fn record(cx: &Context, message: &str) {
+    let span = cx.span();
+    span.add_event(
+        "exception",
+        vec![
+            KeyValue::new("exception.type", "panic"),
+            KeyValue::new("exception.message", message.to_string()),
+        ],
+    );
+    span.set_status(Status::error(message.to_string()));
+}
+
+/// Whether a panic on this thread would be caught by [`guarded`].
+pub(crate) fn guarding() -> bool {
+    GUARDING.get()
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+
+    #[test]
+    fn panic_is_recorded_on_the_span() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let cx = Context::new().with_span(provider.tracer("test").start("creator"));
+
+        assert_eq!(guarded(Some(&cx), || 7), Some(7));
+        assert_eq!(guarded(Some(&cx), || -> u8 { panic!("boom") }), None);
+        assert_eq!(guarded(None, || -> u8 { panic!("no span") }), None);
+        cx.span().end();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].status, Status::error("boom"));
+        let event = &spans[0].events[0];
+        assert_eq!(event.name, "exception");
+        assert!(
+            event
+                .attributes
+                .contains(&KeyValue::new("exception.message", "boom"))
+        );
+    }
+}
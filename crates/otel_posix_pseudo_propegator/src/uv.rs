@@ -0,0 +1,74 @@
+// src/uv.rs
+//
+// libuv threads. uv_thread_create(_ex) ends up in pthread_create, but
+// depending on the libuv version the start routine we see there is either
+// the caller's entry or libuv's own proxy, and a libuv built with
+// -Bsymbolic or linked statically into a shared library binds
+// pthread_create past us. Both are interposed, so threads started by
+// native addons and libuv-based services are wrapped with their real entry
+// point; the pthread_create libuv makes is then a nested call and goes
+// straight through.
+//
+// Only a shared libuv can be interposed: an executable that links libuv in
+// (node, for one) is searched before any preloaded library.
+//
+// uv_thread_cb returns nothing where a pthread start routine returns a
+// pointer; libuv itself passes one off as the other.
+
+use crate::{StartRoutine, reentry};
+use libc::{c_int, pthread_t};
+use std::ffi::c_void;
+
+// uv_thread_t is a pthread_t on unix
+pub(crate) type UvThreadCb = extern "C" fn(*mut c_void);
+type UvThreadCreateFn = unsafe extern "C" fn(*mut pthread_t, UvThreadCb, *mut c_void) -> c_int;
+// the options are passed along untouched
+type UvThreadCreateExFn =
+    unsafe extern "C" fn(*mut pthread_t, *const c_void, UvThreadCb, *mut c_void) -> c_int;
+
+/// Runs `new` with the callback and argument to start the thread with, wrapped if the
+/// current context calls for it.
+unsafe fn create(
+    tid: *mut pthread_t,
+    entry: UvThreadCb,
+    arg: *mut c_void,
+    new: impl FnOnce(UvThreadCb, *mut c_void) -> c_int,
+) -> c_int {
+    // the pthread_create libuv makes comes back to us as a nested call, which can only be
+    // forwarded once the real one is known
+    let _ = crate::real_pthread_create();
+    let entry = unsafe { std::mem::transmute::<UvThreadCb, StartRoutine>(entry) };
+    // uv_thread_join is a plain pthread_join, so these threads can be tracked for it
+    unsafe {
+        crate::create_wrapped(tid, std::ptr::null(), entry, arg, |start, arg| {
+            new(std::mem::transmute::<StartRoutine, UvThreadCb>(start), arg)
+        })
+    }
+}
+
+/// `uv_thread_create`, carrying the caller's OTEL `Context` into the new thread.
+pub(crate) unsafe fn uv_thread_create(
+    real: UvThreadCreateFn,
+    tid: *mut pthread_t,
+    entry: UvThreadCb,
+    arg: *mut c_void,
+) -> c_int {
+    let Some(_guard) = reentry::enter() else {
+        return unsafe { real(tid, entry, arg) };
+    };
+    unsafe { create(tid, entry, arg, |entry, arg| real(tid, entry, arg)) }
+}
+
+/// `uv_thread_create_ex`, carrying the caller's OTEL `Context` into the new thread.
+pub(crate) unsafe fn uv_thread_create_ex(
+    real: UvThreadCreateExFn,
+    tid: *mut pthread_t,
+    params: *const c_void,
+    entry: UvThreadCb,
+    arg: *mut c_void,
+) -> c_int {
+    let Some(_guard) = reentry::enter() else {
+        return unsafe { real(tid, params, entry, arg) };
+    };
+    unsafe { create(tid, entry, arg, |entry, arg| real(tid, params, entry, arg)) }
+}
@@ -0,0 +1,43 @@
+// src/w3c.rs
+//
+// W3C trace-context serialization: a span context to and from `traceparent`
+// and `tracestate` strings, and the TRACEPARENT/TRACESTATE environment
+// variables those map to for child processes. Used by the C API and the
+// exec/spawn and HTTP injectors, and public for hosts that carry context
+// over their own channels. The functions are otel_env_context's, under the
+// names this module has always had; applications that only read what the
+// shim injected can depend on that crate alone.
+
+pub use otel_env_context::{
+    TRACEPARENT_LEN, TRACEPARENT_VAR, TRACESTATE_VAR, extract, format_traceparent, inject,
+    parse_traceparent, span_context_from_env as from_env, trace_context_to_env as to_env,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::Context;
+    use opentelemetry::trace::TraceContextExt;
+
+    const TP: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn env_round_trip() {
+        let sc = extract(TP, Some("vendor=abc,other=1")).unwrap();
+        let vars = to_env(&Context::new().with_remote_span_context(sc.clone()));
+        assert_eq!(
+            vars,
+            [
+                (TRACEPARENT_VAR, TP.to_string()),
+                (TRACESTATE_VAR, "vendor=abc,other=1".to_string())
+            ]
+        );
+        let back = from_env(|key| vars.iter().find(|(k, _)| *k == key).map(|(_, v)| v.clone()));
+        assert_eq!(back, Some(sc));
+
+        // a broken tracestate doesn't cost the traceparent
+        let sc = extract(TP, Some("not a tracestate")).unwrap();
+        assert_eq!(sc.trace_state().header(), "");
+        assert!(to_env(&Context::new()).is_empty());
+    }
+}
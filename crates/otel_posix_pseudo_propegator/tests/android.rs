@@ -0,0 +1,36 @@
+#![cfg(all(target_os = "android", feature = "preload"))]
+// Runs on a device or emulator through android/adb-runner.sh; the C fixtures
+// need a compiler on the target, so this covers bionic with plain threads.
+
+use opentelemetry::Context;
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+
+// nothing else references the crate, and it has to be linked for its `pthread_create` to win
+extern crate otel_posix_pseudo_propegator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote_context() -> Context {
+        Context::new().with_remote_span_context(SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        ))
+    }
+
+    #[test]
+    fn bionic_pthread_create_carries_the_context() {
+        let cx = remote_context();
+        let expected = cx.span().span_context().clone();
+        let _guard = cx.attach();
+        // std::thread goes through bionic's pthread_create, found with RTLD_NEXT
+        let seen = std::thread::spawn(|| Context::current().span().span_context().clone())
+            .join()
+            .unwrap();
+        assert_eq!(seen, expected);
+    }
+}
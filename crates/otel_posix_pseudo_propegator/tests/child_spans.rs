@@ -0,0 +1,131 @@
+#![cfg(all(feature = "sdk", feature = "hook-wait", feature = "hook-exec"))]
+
+use opentelemetry::trace::{Status, TraceContextExt, Tracer};
+use opentelemetry::{Value, global};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use std::process::Command;
+use std::sync::OnceLock;
+
+// nothing else references the crate, and it has to be linked for its `waitpid` to win
+extern crate otel_posix_pseudo_propegator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exporter() -> &'static InMemorySpanExporter {
+        static EXPORTER: OnceLock<InMemorySpanExporter> = OnceLock::new();
+        EXPORTER.get_or_init(|| {
+            // the config is read once, on the first child started under a span
+            unsafe { std::env::set_var("OTEL_POSIX_PROP_CHILD_SPANS", "true") };
+            let exporter = InMemorySpanExporter::default();
+            let provider = SdkTracerProvider::builder()
+                .with_simple_exporter(exporter.clone())
+                .build();
+            global::set_tracer_provider(provider);
+            exporter
+        })
+    }
+
+    fn attribute(span: &SpanData, key: &str) -> Option<Value> {
+        span.attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| kv.value.clone())
+    }
+
+    /// The `child process` span of `pid`, once it has been exported.
+    fn child(pid: i64) -> SpanData {
+        exporter()
+            .get_finished_spans()
+            .unwrap()
+            .into_iter()
+            .find(|s| {
+                s.name == "child process" && attribute(s, "process.pid") == Some(Value::I64(pid))
+            })
+            .unwrap_or_else(|| panic!("no child process span for {pid}"))
+    }
+
+    #[test]
+    fn spawned_child_runs_under_its_span() {
+        exporter();
+        let (parent, pid, out) = global::tracer("test").in_span("parent", |cx| {
+            let child = Command::new("sh")
+                .args(["-c", "echo \"$TRACEPARENT\"; exit 3"])
+                .stdout(std::process::Stdio::piped())
+                .spawn()
+                .unwrap();
+            let pid = child.id();
+            // reaped with waitpid
+            let out = child.wait_with_output().unwrap();
+            assert_eq!(out.status.code(), Some(3));
+            (cx.span().span_context().clone(), pid, out.stdout)
+        });
+
+        let span = child(pid.into());
+        assert_eq!(span.parent_span_id, parent.span_id());
+        assert_eq!(
+            String::from_utf8(out).unwrap().trim(),
+            format!(
+                "00-{}-{}-01",
+                span.span_context.trace_id(),
+                span.span_context.span_id()
+            )
+        );
+        assert_eq!(
+            attribute(&span, "process.executable.path"),
+            Some(Value::from("sh"))
+        );
+        assert_eq!(attribute(&span, "process.exit.code"), Some(Value::I64(3)));
+        assert_eq!(span.status, Status::error("exited with 3"));
+    }
+
+    #[test]
+    fn forked_child_killed_by_a_signal() {
+        exporter();
+        let pid = global::tracer("test").in_span("parent", |_| {
+            let pid = unsafe { libc::fork() };
+            if pid == 0 {
+                unsafe {
+                    libc::raise(libc::SIGTERM);
+                    libc::_exit(0);
+                }
+            }
+            assert!(pid > 0);
+            let mut info = unsafe { std::mem::zeroed::<libc::siginfo_t>() };
+            let rc =
+                unsafe { libc::waitid(libc::P_PID, pid as libc::id_t, &mut info, libc::WEXITED) };
+            assert_eq!(rc, 0);
+            pid
+        });
+
+        let span = child(pid.into());
+        assert_eq!(attribute(&span, "process.exit.code"), None);
+        assert_eq!(
+            attribute(&span, "otel_posix.process.signal"),
+            Some(Value::I64(libc::SIGTERM.into()))
+        );
+        assert_eq!(
+            attribute(&span, "otel_posix.process.core_dumped"),
+            Some(Value::Bool(false))
+        );
+        assert_eq!(
+            span.status,
+            Status::error(format!("killed by signal {}", libc::SIGTERM))
+        );
+    }
+
+    #[test]
+    fn nothing_without_a_span() {
+        exporter();
+        let status = Command::new("true").status().unwrap();
+        assert!(status.success());
+        assert!(
+            exporter()
+                .get_finished_spans()
+                .unwrap()
+                .iter()
+                .all(|s| attribute(s, "process.executable.path") != Some(Value::from("true")))
+        );
+    }
+}
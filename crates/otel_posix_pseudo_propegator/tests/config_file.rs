@@ -0,0 +1,29 @@
+use opentelemetry::Context;
+use std::thread;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanId, TraceContextExt, Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use otel_posix_pseudo_propegator::metrics::counts;
+
+    #[test]
+    fn test_hooks_can_be_turned_off_from_the_file() {
+        // the config is read once, on the first wrapped pthread_create
+        let path = std::env::temp_dir().join(format!("otel-posix-{}.toml", std::process::id()));
+        std::fs::write(&path, "[hooks]\npthread_create = false\n").unwrap();
+        unsafe { std::env::set_var("OTEL_POSIX_PROP_CONFIG", &path) };
+
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        tracer.in_span("unhooked", |_| {
+            let passed_through = counts().passed_through;
+            let seen = thread::spawn(|| Context::current().span().span_context().span_id())
+                .join()
+                .unwrap();
+            assert_eq!(seen, SpanId::INVALID);
+            assert!(counts().passed_through > passed_through);
+        });
+        std::fs::remove_file(path).unwrap();
+    }
+}
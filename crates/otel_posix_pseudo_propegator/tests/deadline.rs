@@ -0,0 +1,77 @@
+use std::thread;
+
+// only the C API is used, and the crate has to be linked for its `pthread_create`
+extern crate otel_posix_pseudo_propegator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe extern "C" {
+        // the C API, as declared in the generated header
+        fn otel_posix_set_deadline(deadline_ns: u64) -> u64;
+        fn otel_posix_get_deadline() -> u64;
+        fn otel_posix_deadline_remaining() -> i64;
+    }
+
+    fn now_ns() -> u64 {
+        let mut now = unsafe { std::mem::zeroed::<libc::timespec>() };
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+        now.tv_sec as u64 * 1_000_000_000 + now.tv_nsec as u64
+    }
+
+    #[test]
+    fn test_deadline_reaches_grandchildren_without_a_span() {
+        let deadline = now_ns() + 60_000_000_000;
+        thread::spawn(move || {
+            assert_eq!(unsafe { otel_posix_set_deadline(deadline) }, deadline);
+            let seen = thread::spawn(|| {
+                let own = unsafe { otel_posix_get_deadline() };
+                let remaining = unsafe { otel_posix_deadline_remaining() };
+                let grandchild = thread::spawn(|| unsafe { otel_posix_get_deadline() });
+                (own, remaining, grandchild.join().unwrap())
+            })
+            .join()
+            .unwrap();
+            assert_eq!(seen.0, deadline);
+            assert!(seen.1 > 0 && seen.1 <= 60_000_000_000);
+            assert_eq!(seen.2, deadline);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_child_cannot_push_its_deadline_back() {
+        let deadline = now_ns() + 60_000_000_000;
+        thread::spawn(move || {
+            unsafe { otel_posix_set_deadline(deadline) };
+            thread::spawn(move || {
+                assert_eq!(unsafe { otel_posix_set_deadline(deadline + 1) }, deadline);
+                assert_eq!(
+                    unsafe { otel_posix_set_deadline(deadline - 1) },
+                    deadline - 1
+                );
+                // the creator's is unchanged, and the child's own goes with it
+                thread::spawn(move || {
+                    assert_eq!(unsafe { otel_posix_get_deadline() }, deadline - 1)
+                })
+                .join()
+                .unwrap();
+            })
+            .join()
+            .unwrap();
+            assert_eq!(unsafe { otel_posix_get_deadline() }, deadline);
+        })
+        .join()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_no_deadline_by_default() {
+        let seen = thread::spawn(|| unsafe { otel_posix_get_deadline() })
+            .join()
+            .unwrap();
+        assert_eq!(seen, 0);
+    }
+}
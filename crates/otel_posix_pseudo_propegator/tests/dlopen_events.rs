@@ -0,0 +1,149 @@
+#![cfg(all(
+    feature = "sdk",
+    feature = "hook-dlopen",
+    target_os = "linux",
+    target_env = "gnu",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+
+use opentelemetry::trace::Tracer;
+use opentelemetry::{Value, global};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use std::ffi::{CStr, c_void};
+use std::sync::OnceLock;
+
+// nothing else references the crate, and it has to be linked for its `dlopen` to win
+extern crate otel_posix_pseudo_propegator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exporter() -> &'static InMemorySpanExporter {
+        static EXPORTER: OnceLock<InMemorySpanExporter> = OnceLock::new();
+        EXPORTER.get_or_init(|| {
+            // the config is read once, on the first dlopen under a span
+            unsafe { std::env::set_var("OTEL_POSIX_PROP_DLOPEN_EVENTS", "true") };
+            let exporter = InMemorySpanExporter::default();
+            let provider = SdkTracerProvider::builder()
+                .with_simple_exporter(exporter.clone())
+                .build();
+            global::set_tracer_provider(provider);
+            exporter
+        })
+    }
+
+    fn finished(name: &str) -> SpanData {
+        exporter()
+            .get_finished_spans()
+            .unwrap()
+            .into_iter()
+            .find(|s| s.name == name)
+            .unwrap_or_else(|| panic!("span {name} was not exported"))
+    }
+
+    /// The attributes of each `event` on `span`, in order.
+    fn events(span: &SpanData, event: &str) -> Vec<Vec<(String, Value)>> {
+        span.events
+            .iter()
+            .filter(|e| e.name == event)
+            .map(|e| {
+                e.attributes
+                    .iter()
+                    .map(|kv| (kv.key.to_string(), kv.value.clone()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn get<'a>(attributes: &'a [(String, Value)], key: &str) -> Option<&'a Value> {
+        attributes.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    #[test]
+    fn load_and_unload_are_recorded() {
+        exporter();
+        let handle = global::tracer("test").in_span("load", |_| {
+            let handle = unsafe { libc::dlopen(c"libm.so.6".as_ptr(), libc::RTLD_NOW) };
+            assert!(!handle.is_null());
+            assert_eq!(unsafe { libc::dlclose(handle) }, 0);
+            handle
+        });
+
+        let span = finished("load");
+        let [opened] = &events(&span, "dlopen")[..] else {
+            panic!("not one dlopen event: {:?}", span.events);
+        };
+        let handle = Value::from(format!("{:#x}", handle as usize));
+        assert_eq!(
+            get(opened, "otel_posix.dl.name"),
+            Some(&Value::from("libm.so.6"))
+        );
+        assert_eq!(get(opened, "otel_posix.dl.handle"), Some(&handle));
+        let Some(Value::String(path)) = get(opened, "otel_posix.dl.path") else {
+            panic!("no path in {opened:?}");
+        };
+        assert!(path.as_str().ends_with("/libm.so.6"), "{path}");
+        assert!(matches!(
+            get(opened, "otel_posix.dl.duration"),
+            Some(Value::F64(_))
+        ));
+
+        let [closed] = &events(&span, "dlclose")[..] else {
+            panic!("not one dlclose event: {:?}", span.events);
+        };
+        assert_eq!(get(closed, "otel_posix.dl.handle"), Some(&handle));
+        assert_eq!(
+            get(closed, "otel_posix.dl.path"),
+            get(opened, "otel_posix.dl.path")
+        );
+        assert_eq!(get(closed, "otel_posix.dl.failed"), None);
+    }
+
+    #[test]
+    fn failures_leave_dlerror_to_the_caller() {
+        exporter();
+        global::tracer("test").in_span("missing", |_| {
+            let handle =
+                unsafe { libc::dlopen(c"/nonexistent/libotel-none.so".as_ptr(), libc::RTLD_NOW) };
+            assert!(handle.is_null());
+            let error = unsafe { libc::dlerror() };
+            assert!(!error.is_null());
+            let error = unsafe { CStr::from_ptr(error) }.to_string_lossy();
+            assert!(error.contains("libotel-none.so"), "{error}");
+        });
+
+        let span = finished("missing");
+        let [failed] = &events(&span, "dlopen")[..] else {
+            panic!("not one dlopen event: {:?}", span.events);
+        };
+        assert_eq!(
+            get(failed, "otel_posix.dl.failed"),
+            Some(&Value::Bool(true))
+        );
+        assert_eq!(get(failed, "otel_posix.dl.handle"), None);
+    }
+
+    #[test]
+    fn caller_relative_names_pass_through() {
+        exporter();
+        global::tracer("test").in_span("origin", |_| {
+            // expanded against this executable's directory, so made by it, not the shim
+            let handle =
+                unsafe { libc::dlopen(c"$ORIGIN/libotel-none.so".as_ptr(), libc::RTLD_NOW) };
+            assert!(handle.is_null());
+        });
+        assert!(events(&finished("origin"), "dlopen").is_empty());
+    }
+
+    #[test]
+    fn nothing_is_recorded_without_a_span() {
+        exporter();
+        let handle = unsafe { libc::dlopen(std::ptr::null(), libc::RTLD_NOW) };
+        assert!(!handle.is_null());
+        // jumped straight to the real dlopen, which handed back a working handle
+        let getpid = unsafe { libc::dlsym(handle, c"getpid".as_ptr()) };
+        assert_ne!(getpid, std::ptr::null_mut::<c_void>());
+        assert_eq!(unsafe { libc::dlclose(handle) }, 0);
+    }
+}
@@ -0,0 +1,29 @@
+use opentelemetry::Context;
+use std::thread;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanId, TraceContextExt, Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use otel_posix_pseudo_propegator::metrics::counts;
+
+    #[test]
+    fn test_denied_entry_point_is_not_wrapped() {
+        // std's thread entry point is private to this (unexported) test binary, so
+        // it is known by the binary's file name
+        let exe = std::env::current_exe().unwrap();
+        let name = exe.file_name().unwrap().to_str().unwrap();
+        unsafe { std::env::set_var("OTEL_POSIX_PROP_ENTRY_DENY", format!("nothing,{name}")) };
+
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        tracer.in_span("denied", |_| {
+            let passed_through = counts().passed_through;
+            let seen = thread::spawn(|| Context::current().span().span_context().span_id())
+                .join()
+                .unwrap();
+            assert_eq!(seen, SpanId::INVALID);
+            assert!(counts().passed_through > passed_through);
+        });
+    }
+}
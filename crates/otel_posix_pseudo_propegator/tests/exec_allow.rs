@@ -0,0 +1,61 @@
+#![cfg(feature = "hook-exec")]
+
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::sync::Once;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{TraceContextExt, Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use otel_posix_pseudo_propegator::metrics::counts;
+
+    fn output(cmd: &mut Command) -> String {
+        let out = cmd.output().unwrap();
+        String::from_utf8(out.stdout).unwrap().trim().to_string()
+    }
+
+    fn configure() {
+        static CONFIGURE: Once = Once::new();
+        CONFIGURE.call_once(|| {
+            // read once, on first use
+            unsafe { std::env::set_var("OTEL_POSIX_PROP_EXEC_ALLOW", "printenv, /nonexistent/*") };
+            // keeps the crate linked so its exec/spawn symbols interpose
+            let _ = counts();
+        });
+    }
+
+    #[test]
+    fn test_only_allowed_executables_get_the_context() {
+        configure();
+
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        tracer.in_span("spawn", |cx| {
+            let sc = cx.span().span_context().clone();
+            let expected = format!("00-{}-{}-01", sc.trace_id(), sc.span_id());
+
+            // posix_spawnp, by file name
+            assert_eq!(
+                output(Command::new("printenv").arg("TRACEPARENT")),
+                expected
+            );
+            // execvp, after a fork
+            let mut forked = Command::new("printenv");
+            unsafe { forked.arg("TRACEPARENT").pre_exec(|| Ok(())) };
+            assert_eq!(output(&mut forked), expected);
+
+            // anything else is left with the environment it was given
+            let echo = "echo \"$TRACEPARENT\"";
+            assert_eq!(output(Command::new("sh").args(["-c", echo])), "");
+            assert_eq!(
+                output(
+                    Command::new("sh")
+                        .args(["-c", echo])
+                        .env("TRACEPARENT", "00-stale")
+                ),
+                "00-stale"
+            );
+        });
+    }
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "hook-exec")]
+
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::sync::Once;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{TraceContextExt, Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use otel_posix_pseudo_propegator::metrics::counts;
+
+    fn child_env(cmd: &mut Command) -> String {
+        let out = cmd
+            .args(["-c", "echo \"$TRACEPARENT|$X_B3_TRACEID|$X_B3_SPANID|$B3\""])
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        String::from_utf8(out.stdout).unwrap().trim().to_string()
+    }
+
+    fn configure() {
+        static CONFIGURE: Once = Once::new();
+        CONFIGURE.call_once(|| {
+            // read once, on first use
+            unsafe { std::env::set_var("OTEL_PROPAGATORS", "tracecontext,b3multi") };
+            // keeps the crate linked so its exec/spawn symbols interpose
+            let _ = counts();
+        });
+    }
+
+    #[test]
+    fn test_children_get_the_context_in_each_format() {
+        configure();
+
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        tracer.in_span("spawn", |cx| {
+            let sc = cx.span().span_context().clone();
+            let expected = format!(
+                "00-{}-{}-01|{}|{}|",
+                sc.trace_id(),
+                sc.span_id(),
+                sc.trace_id(),
+                sc.span_id()
+            );
+
+            // posix_spawnp
+            assert_eq!(child_env(&mut Command::new("sh")), expected);
+            // a pre_exec hook makes std fork and execvp instead
+            let mut forked = Command::new("sh");
+            unsafe { forked.pre_exec(|| Ok(())) };
+            assert_eq!(child_env(&mut forked), expected);
+            // a stale value from our own environment is replaced
+            assert_eq!(
+                child_env(Command::new("sh").env("TRACEPARENT", "00-stale")),
+                expected
+            );
+        });
+
+        // nothing active, nothing added
+        assert_eq!(child_env(&mut Command::new("sh")), "|||");
+    }
+
+    #[test]
+    fn test_resource_attributes_reach_children_with_their_own_environment() {
+        configure();
+        unsafe {
+            std::env::set_var(
+                "OTEL_RESOURCE_ATTRIBUTES",
+                "service.name=parent,deployment.environment=test",
+            )
+        };
+        let out = Command::new("/bin/sh")
+            .env_clear()
+            .env("OTEL_RESOURCE_ATTRIBUTES", "service.name=child")
+            .args(["-c", "echo \"$OTEL_RESOURCE_ATTRIBUTES\""])
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out.stdout).unwrap().trim(),
+            "service.name=child,deployment.environment=test"
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::ffi::c_void;
+
+// counts Rust heap allocations made on the current thread
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use otel_posix_pseudo_propegator::metrics::counts;
+
+    extern "C" fn noop(_: *mut c_void) -> *mut c_void {
+        std::ptr::null_mut()
+    }
+
+    fn create_and_join() {
+        let mut tid: libc::pthread_t = 0;
+        let rc =
+            unsafe { libc::pthread_create(&mut tid, std::ptr::null(), noop, std::ptr::null_mut()) };
+        assert_eq!(rc, 0);
+        assert_eq!(unsafe { libc::pthread_join(tid, std::ptr::null_mut()) }, 0);
+    }
+
+    #[test]
+    fn test_no_active_span_does_not_allocate() {
+        // the first call resolves the real pthread_create and sets up thread-locals
+        create_and_join();
+
+        let passed_through = counts().passed_through;
+        let before = ALLOCATIONS.get();
+        for _ in 0..10 {
+            create_and_join();
+        }
+        assert_eq!(ALLOCATIONS.get(), before);
+        assert_eq!(counts().passed_through, passed_through + 10);
+    }
+}
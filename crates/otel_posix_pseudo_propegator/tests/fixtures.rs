@@ -0,0 +1,1161 @@
+// an instrumented cdylib can't be preloaded into the uninstrumented fixtures
+#![cfg(all(target_os = "linux", feature = "preload", not(otel_posix_sanitize)))]
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn manifest_dir() -> &'static Path {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+    }
+
+    /// The cdylib from this build, for the profile the tests were built in. Cargo builds
+    /// it for integration tests but only refreshes the copy in deps/ (next to the test
+    /// binary); the one in the profile directory is only there after a `cargo build`.
+    fn cdylib() -> PathBuf {
+        let exe = std::env::current_exe().unwrap();
+        let deps = exe.parent().unwrap();
+        [deps, deps.parent().unwrap()]
+            .iter()
+            .map(|dir| dir.join("libotel_posix_pseudo_propegator.so"))
+            .find(|lib| lib.exists())
+            .unwrap_or_else(|| panic!("no cdylib next to {}", exe.display()))
+    }
+
+    /// Compiles tests/fixtures/<name>.c into an executable.
+    fn compile_c(name: &str) -> PathBuf {
+        compile(&format!("{name}.c"), false)
+    }
+
+    /// Compiles tests/fixtures/<name>.cpp into an executable.
+    fn compile_cpp(name: &str) -> PathBuf {
+        compile(&format!("{name}.cpp"), true)
+    }
+
+    /// Compiles tests/fixtures/<name>.c into a shared library.
+    fn compile_shared(name: &str) -> PathBuf {
+        shared(name, false)
+    }
+
+    /// Compiles tests/fixtures/<name>.cpp into a shared library.
+    fn compile_shared_cpp(name: &str) -> PathBuf {
+        shared(name, true)
+    }
+
+    fn shared(name: &str, cpp: bool) -> PathBuf {
+        let source = format!("{name}.{}", if cpp { "cpp" } else { "c" });
+        let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("lib{name}.so"));
+        build(&source, cpp, &out, &["-shared", "-fPIC"]);
+        out
+    }
+
+    fn compile(source: &str, cpp: bool) -> PathBuf {
+        compile_with(source, cpp, &[])
+    }
+
+    fn compile_with(source: &str, cpp: bool, flags: &[&str]) -> PathBuf {
+        let name = Path::new(source).file_stem().unwrap();
+        let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+        build(source, cpp, &out, flags);
+        out
+    }
+
+    /// Compiles tests/fixtures/<name>.c into an executable instrumented with `sanitizer`.
+    fn compile_sanitized(name: &str, sanitizer: &str) -> PathBuf {
+        let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}-{sanitizer}"));
+        build(
+            &format!("{name}.c"),
+            false,
+            &out,
+            &[&format!("-fsanitize={sanitizer}"), "-g"],
+        );
+        out
+    }
+
+    /// Whether the compiler can find the shared library `file` to link against.
+    #[cfg(feature = "hook-glib")]
+    fn has_library(file: &str) -> bool {
+        find_library(file).is_some()
+    }
+
+    /// Where the compiler finds the shared library `file`.
+    fn find_library(file: &str) -> Option<PathBuf> {
+        let out = compiler(false)
+            .to_command()
+            .arg(format!("-print-file-name={file}"))
+            .output()
+            .unwrap();
+        // the name comes back as it was when it isn't found
+        let path = String::from_utf8_lossy(&out.stdout).trim().to_string();
+        (path != file).then(|| PathBuf::from(path))
+    }
+
+    /// The C (or C++) compiler for the target the tests were built for.
+    fn compiler(cpp: bool) -> cc::Tool {
+        cc::Build::new()
+            .cargo_metadata(false)
+            .cpp(cpp)
+            .opt_level(0)
+            .target(env!("OTEL_POSIX_TEST_TARGET"))
+            .host(env!("OTEL_POSIX_TEST_TARGET"))
+            .get_compiler()
+    }
+
+    fn build(source: &str, cpp: bool, out: &Path, flags: &[&str]) {
+        let status = compiler(cpp)
+            .to_command()
+            .arg("-I")
+            .arg(manifest_dir().join("include"))
+            .arg(manifest_dir().join("tests/fixtures").join(source))
+            .args(flags)
+            .args(["-pthread", "-ldl", "-o"])
+            .arg(out)
+            .status()
+            .unwrap();
+        assert!(status.success(), "failed to compile fixture {source}");
+    }
+
+    /// Runs `exe` under the preload and returns its "<label> <traceparent>" lines.
+    fn run_preloaded(exe: &Path) -> Vec<(String, String)> {
+        run_with(exe, "LD_PRELOAD")
+    }
+
+    /// Runs `exe` with the cdylib installed through the loader variable `var`.
+    fn run_with(exe: &Path, var: &str) -> Vec<(String, String)> {
+        run_env(exe, var, cdylib().as_os_str())
+    }
+
+    /// Runs `exe` with the loader variable `var` set to `value`.
+    fn run_env(exe: &Path, var: &str, value: &std::ffi::OsStr) -> Vec<(String, String)> {
+        let mut command = Command::new(exe);
+        command.env(var, value);
+        run(command)
+    }
+
+    /// Runs `command`, which must succeed, and returns its "<label> <traceparent>" lines.
+    fn run(mut command: Command) -> Vec<(String, String)> {
+        let out = command
+            .env_remove("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "{} failed: {}",
+            command.get_program().display(),
+            String::from_utf8_lossy(&out.stderr)
+        );
+        let mut lines: Vec<(String, String)> = String::from_utf8(out.stdout)
+            .unwrap()
+            .lines()
+            .filter_map(|l| l.split_once(' '))
+            .map(|(label, tp)| (label.to_string(), tp.to_string()))
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    fn seen(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(l, tp)| (l.to_string(), tp.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_readme_example() {
+        let out = Command::new(compile_c("example"))
+            .env("LD_PRELOAD", cdylib())
+            .env_remove("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        assert_eq!(out.stdout, b"Hello from worker thread!\n");
+        // the loader would have complained about a preload it couldn't find
+        assert_eq!(String::from_utf8_lossy(&out.stderr), "");
+    }
+
+    #[test]
+    fn test_raw_pthread_create() {
+        let lines = run_preloaded(&compile_c("raw_pthread"));
+        assert_eq!(
+            lines,
+            seen(&[
+                ("main", TRACEPARENT),
+                ("unseeded", "-"),
+                ("worker-a", TRACEPARENT),
+                ("worker-b", TRACEPARENT),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_sdk_disabled_passes_through() {
+        let mut command = Command::new(compile_c("raw_pthread"));
+        command
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_SDK_DISABLED", "true");
+        assert_eq!(
+            run(command),
+            seen(&[
+                ("main", TRACEPARENT),
+                ("unseeded", "-"),
+                ("worker-a", "-"),
+                ("worker-b", "-"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_wrap_rate_limit() {
+        let mut command = Command::new(compile_c("raw_pthread"));
+        command
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_WRAP_RATE", "1");
+        // one wrapped thread a second: the second worker comes right after the first
+        assert_eq!(
+            run(command),
+            seen(&[
+                ("main", TRACEPARENT),
+                ("unseeded", "-"),
+                ("worker-a", TRACEPARENT),
+                ("worker-b", "-"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_sanitizer_runtimes() {
+        // gcc's shared runtimes; clang links its own into the executable, where it always
+        // comes first
+        for (sanitizer, runtime) in [("address", "libasan.so"), ("thread", "libtsan.so")] {
+            let Some(runtime) = find_library(runtime) else {
+                eprintln!("skipped {sanitizer}: no {runtime}");
+                continue;
+            };
+            let exe = compile_sanitized("raw_pthread", sanitizer);
+            // ASan refuses to run unless it comes first
+            let orders: &[bool] = if sanitizer == "address" {
+                &[false]
+            } else {
+                &[false, true]
+            };
+            for &shim_first in orders {
+                let (first, second) = if shim_first {
+                    (cdylib(), runtime.clone())
+                } else {
+                    (runtime.clone(), cdylib())
+                };
+                let preload = format!("{}:{}", first.display(), second.display());
+                // TSan running ahead of the trampoline can't be wrapped around, and
+                // passes threads through; any report fails the run
+                let worker = if sanitizer == "thread" && !shim_first {
+                    "-"
+                } else {
+                    TRACEPARENT
+                };
+                assert_eq!(
+                    run_env(&exe, "LD_PRELOAD", preload.as_ref()),
+                    seen(&[
+                        ("main", TRACEPARENT),
+                        ("unseeded", "-"),
+                        ("worker-a", worker),
+                        ("worker-b", worker),
+                    ]),
+                    "{preload}"
+                );
+            }
+        }
+    }
+
+    #[cfg(all(feature = "audit", target_env = "gnu"))]
+    #[test]
+    fn test_raw_pthread_create_under_ld_audit() {
+        let lines = run_with(&compile_c("raw_pthread"), "LD_AUDIT");
+        assert_eq!(
+            lines,
+            seen(&[
+                ("main", TRACEPARENT),
+                ("unseeded", "-"),
+                ("worker-a", TRACEPARENT),
+                ("worker-b", TRACEPARENT),
+            ])
+        );
+    }
+
+    /// Connects to the control socket `name`, waiting for the process to bind it.
+    fn connect_control(name: &str) -> UnixStream {
+        let addr = SocketAddr::from_abstract_name(name).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            match UnixStream::connect_addr(&addr) {
+                Ok(stream) => return stream,
+                Err(e) if Instant::now() > deadline => panic!("no control socket @{name}: {e}"),
+                Err(_) => std::thread::sleep(Duration::from_millis(10)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_control_socket_pauses_wrapping() {
+        let name = format!("otel-posix-test.{}", std::process::id());
+        let mut child = Command::new(compile_c("controlled"))
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_CONTROL", &name)
+            .env_remove("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let control = connect_control(&name);
+        let mut replies = BufReader::new(control.try_clone().unwrap());
+
+        let mut run = |cmd: &str| {
+            writeln!(&control, "{cmd}").unwrap();
+            let mut reply = String::new();
+            replies.read_line(&mut reply).unwrap();
+            reply.trim_end().to_string()
+        };
+        let mut spawn = |label: &str| {
+            writeln!(stdin, "spawn {label}").unwrap();
+            let mut line = String::new();
+            stdout.read_line(&mut line).unwrap();
+            line.trim_end().to_string()
+        };
+
+        assert_eq!(
+            run("status"),
+            "wrapping=enabled log=warn next=libc.so.6 binding=bound"
+        );
+        assert_eq!(spawn("before"), format!("before {TRACEPARENT}"));
+        assert_eq!(run("disable"), "ok");
+        assert_eq!(spawn("paused"), "paused -");
+        assert_eq!(run("enable"), "ok");
+        assert_eq!(spawn("resumed"), format!("resumed {TRACEPARENT}"));
+        // the control thread itself was passed through, too
+        assert_eq!(run("stats"), "wrapped=2 passed_through=2 wrap_failures=0");
+
+        drop(stdin);
+        assert!(child.wait().unwrap().success());
+    }
+
+    #[test]
+    fn test_stacked_with_another_interposer() {
+        let exe = compile_c("raw_pthread");
+        let shim = compile_shared("stacked_shim");
+        for shim_first in [false, true] {
+            let (first, second) = if shim_first {
+                (&shim, &cdylib())
+            } else {
+                (&cdylib(), &shim)
+            };
+            let preload = format!("{}:{}", first.display(), second.display());
+            let mut lines = run_env(&exe, "LD_PRELOAD", preload.as_ref());
+            // the other interposer saw every creation, whichever side of us it was on
+            let shim_calls = lines.iter().position(|(label, _)| label == "shim-calls");
+            let (_, calls) = lines.remove(shim_calls.expect("the shim didn't report"));
+            assert!(calls.parse::<u32>().unwrap() >= 3, "{calls}");
+            assert_eq!(
+                lines,
+                seen(&[
+                    ("main", TRACEPARENT),
+                    ("unseeded", "-"),
+                    ("worker-a", TRACEPARENT),
+                    ("worker-b", TRACEPARENT),
+                ]),
+                "{preload}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_auto_root_spans_for_untraced_threads() {
+        let out = Command::new(compile_c("auto_root"))
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_AUTO_ROOT", "on")
+            // a real provider, exporting nowhere
+            .env("OTEL_EXPORTER_OTLP_ENDPOINT", "http://127.0.0.1:9")
+            .env("OTEL_METRICS_EXPORTER", "none")
+            .output()
+            .unwrap();
+        assert!(out.status.success());
+        let stdout = String::from_utf8(out.stdout).unwrap();
+        let traceparents: Vec<&str> = stdout
+            .lines()
+            .filter_map(|l| l.split_once(' '))
+            .filter(|(label, _)| *label != "main")
+            .map(|(_, tp)| tp)
+            .collect();
+        let [first, second] = traceparents[..] else {
+            panic!("{stdout}");
+        };
+        // each thread is the root of a trace of its own, which its creator isn't part of
+        assert!(first.len() == 55 && second.len() == 55, "{stdout}");
+        assert_ne!(first[3..35], second[3..35]);
+        assert!(stdout.contains("main -\n"), "{stdout}");
+    }
+
+    /// Answers OTLP/HTTP exports on a local port with 200 and reports each request's path
+    /// and body.
+    fn collector() -> (u16, std::sync::mpsc::Receiver<(String, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Some(export) = answer(stream) {
+                    let _ = tx.send(export);
+                }
+            }
+        });
+        (port, rx)
+    }
+
+    fn answer(stream: TcpStream) -> Option<(String, Vec<u8>)> {
+        let mut reader = BufReader::new(stream.try_clone().ok()?);
+        let mut request = String::new();
+        reader.read_line(&mut request).ok()?;
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).ok()?;
+            if header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':')
+                && name.eq_ignore_ascii_case("content-length")
+            {
+                length = value.trim().parse().ok()?;
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).ok()?;
+        (&stream)
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+            .ok()?;
+        let path = request.split_whitespace().nth(1)?;
+        Some((path.to_string(), body))
+    }
+
+    #[test]
+    #[cfg(all(feature = "hook-exit", feature = "otlp"))]
+    fn test_spans_flushed_on_exits_that_skip_atexit() {
+        let exe = compile_c("exit_flush");
+        for how in ["_exit", "_Exit", "quick_exit", "exit"] {
+            let (port, exports) = collector();
+            let out = Command::new(&exe)
+                .arg(how)
+                .env("LD_PRELOAD", cdylib())
+                .env("OTEL_POSIX_PROP_AUTO_ROOT", "on")
+                .env(
+                    "OTEL_EXPORTER_OTLP_ENDPOINT",
+                    format!("http://127.0.0.1:{port}"),
+                )
+                .env("OTEL_METRICS_EXPORTER", "none")
+                // nothing goes out on the batch processor's own schedule
+                .env("OTEL_BSP_SCHEDULE_DELAY", "600000")
+                .output()
+                .unwrap();
+            let stdout = String::from_utf8_lossy(&out.stdout);
+            assert!(out.status.success(), "{how}: {stdout}");
+            assert!(!stdout.contains("worker -"), "{how}: {stdout}");
+            let path = exports
+                .recv_timeout(Duration::from_secs(5))
+                .map(|(path, _)| path);
+            assert_eq!(path.as_deref(), Ok("/v1/traces"), "{how}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "otlp")]
+    fn test_uncaught_exception_is_exported() {
+        let (port, exports) = collector();
+        let out = Command::new(compile_cpp("terminate"))
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_AUTO_ROOT", "on")
+            .env(
+                "OTEL_EXPORTER_OTLP_ENDPOINT",
+                format!("http://127.0.0.1:{port}"),
+            )
+            .env("OTEL_METRICS_EXPORTER", "none")
+            .env("OTEL_BSP_SCHEDULE_DELAY", "600000")
+            .output()
+            .unwrap();
+        // the runtime's own handler still reports it and aborts
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(!out.status.success());
+        assert!(stderr.contains("out of widgets"), "{stderr}");
+        let (path, body) = exports.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(path, "/v1/traces");
+        let body = String::from_utf8_lossy(&body);
+        for expected in ["terminate", "exception", "std::runtime_error"] {
+            assert!(body.contains(expected), "no {expected} in {body}");
+        }
+    }
+
+    /// Whether the OTLP `body` carries the trace id of `traceparent`.
+    #[cfg(feature = "otlp")]
+    fn exports_trace(body: &[u8], traceparent: &str) -> bool {
+        let hex = traceparent.split('-').nth(1).unwrap();
+        let id: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        body.windows(id.len()).any(|w| w == id)
+    }
+
+    #[test]
+    #[cfg(feature = "otlp")]
+    fn test_provider_replaced_and_shut_down() {
+        let (loaded_port, loaded) = collector();
+        let (installed_port, installed) = collector();
+        let out = Command::new(compile_c("swap_provider"))
+            .arg(format!("http://127.0.0.1:{installed_port}/v1/traces"))
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_AUTO_ROOT", "on")
+            .env(
+                "OTEL_EXPORTER_OTLP_ENDPOINT",
+                format!("http://127.0.0.1:{loaded_port}"),
+            )
+            .env("OTEL_METRICS_EXPORTER", "none")
+            .env("OTEL_BSP_SCHEDULE_DELAY", "600000")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert!(out.status.success(), "{stdout}");
+        let lines: Vec<(&str, &str)> = stdout
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .collect();
+        let [
+            ("loaded", first),
+            ("install", "0"),
+            ("installed", second),
+            ("shutdown", "0"),
+            ("shut-down", "-"),
+        ] = lines[..]
+        else {
+            panic!("{stdout}");
+        };
+
+        // each provider exported its own span when it was shut down
+        let (path, body) = loaded.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(path, "/v1/traces");
+        assert!(exports_trace(&body, first), "{first}");
+        let (path, body) = installed.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(path, "/v1/traces");
+        assert!(exports_trace(&body, second), "{second}");
+        // and nothing is left for the exit
+        assert!(installed.recv_timeout(Duration::from_millis(200)).is_err());
+        assert!(loaded.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "otlp")]
+    fn test_threads_created_before_the_exporter_are_reported() {
+        let lib = compile_shared_cpp("early_lib");
+        let dir = lib.parent().unwrap().display();
+        let exe = compile_with(
+            "early.c",
+            false,
+            &[
+                &format!("-L{dir}"),
+                "-learly_lib",
+                &format!("-Wl,-rpath,{dir}"),
+            ],
+        );
+        let (port, exports) = collector();
+        let out = Command::new(&exe)
+            .arg("wait")
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_AUTO_ROOT", "on")
+            .env(
+                "OTEL_EXPORTER_OTLP_ENDPOINT",
+                format!("http://127.0.0.1:{port}"),
+            )
+            .env("OTEL_METRICS_EXPORTER", "none")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert!(out.status.success(), "{stdout}");
+        let (path, body) = exports.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(path, "/v1/traces");
+        let text = String::from_utf8_lossy(&body);
+        assert!(text.contains("early threads"), "{text:?}");
+        assert!(text.contains("thread.create"), "{text:?}");
+        // linked to the span the library's constructor seeded, which nothing else exports
+        assert!(exports_trace(&body, TRACEPARENT), "{text:?}");
+    }
+
+    #[cfg(feature = "otlp")]
+    #[test]
+    fn test_record_and_report() {
+        let file = Path::new(env!("CARGO_TARGET_TMPDIR")).join("record.jsonl");
+        let out = Command::new(env!("CARGO_BIN_EXE_otel-preload"))
+            .arg("record")
+            .arg("--output")
+            .arg(&file)
+            .arg("--lib")
+            .arg(cdylib())
+            .arg("--")
+            .arg(compile_c("auto_root"))
+            .env_remove("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(out.status.success(), "{stderr}");
+        let recorded = std::fs::read_to_string(&file).unwrap();
+        // at least the two workers the provider was in place for
+        assert!(recorded.lines().count() >= 2, "{recorded}");
+        assert!(recorded.lines().all(|l| l.starts_with("{\"trace_id\":")));
+
+        let out = Command::new(env!("CARGO_BIN_EXE_otel-preload"))
+            .arg("report")
+            .arg(&file)
+            .output()
+            .unwrap();
+        let report = String::from_utf8(out.stdout).unwrap();
+        assert!(out.status.success(), "{report}");
+        assert!(report.starts_with("trace "), "{report}");
+        // a span for each worker, named after the thread
+        assert!(report.contains("\n  auto_root  "), "{report}");
+    }
+
+    #[cfg(feature = "creation-stack")]
+    #[test]
+    fn test_creation_stack_attribute() {
+        let (port, exports) = collector();
+        let out = Command::new(compile_with("creation_stack.c", false, &["-rdynamic"]))
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_AUTO_ROOT", "on")
+            .env(
+                "OTEL_EXPORTER_OTLP_ENDPOINT",
+                format!("http://127.0.0.1:{port}"),
+            )
+            .env("OTEL_METRICS_EXPORTER", "none")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert!(out.status.success(), "{stdout}");
+        assert!(!stdout.contains("worker -"), "{stdout}");
+        let (path, body) = exports.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(path, "/v1/traces");
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("code.stacktrace"), "{body:?}");
+        // the first frame is the fixture's, not the shim's
+        let first = body
+            .split("#0 ")
+            .nth(1)
+            .and_then(|rest| rest.lines().next());
+        assert!(
+            first.is_some_and(|frame| frame.contains("(start_worker+0x")),
+            "{body:?}"
+        );
+    }
+
+    #[test]
+    fn test_fatal_signal_reported_in_the_trace() {
+        use std::os::unix::process::{CommandExt, ExitStatusExt};
+        let (port, exports) = collector();
+        let mut command = Command::new(compile_c("fatal_signal"));
+        command
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_CRASH_SIGNALS", "on")
+            .env("OTEL_POSIX_PROP_AUTO_ROOT", "on")
+            .env(
+                "OTEL_EXPORTER_OTLP_ENDPOINT",
+                format!("http://127.0.0.1:{port}"),
+            )
+            .env("OTEL_METRICS_EXPORTER", "none");
+        // no core dump
+        unsafe {
+            command.pre_exec(|| {
+                let none = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                libc::setrlimit(libc::RLIMIT_CORE, &none);
+                Ok(())
+            })
+        };
+        let out = command.output().unwrap();
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        // raised again once reported, so it still dies of it
+        assert_eq!(out.status.signal(), Some(libc::SIGSEGV), "{stderr}");
+        assert!(
+            String::from_utf8_lossy(&out.stdout).contains(&format!("worker {TRACEPARENT}")),
+            "{}",
+            String::from_utf8_lossy(&out.stdout)
+        );
+        let report = stderr
+            .lines()
+            .find(|l| l.contains("fatal signal"))
+            .unwrap_or_else(|| panic!("no crash report in {stderr}"));
+        assert!(
+            report.contains("fatal signal 11 (SIGSEGV) on tid ")
+                && report.contains(" at 0x0, ")
+                && report.ends_with(&format!("traceparent {TRACEPARENT}")),
+            "{report}"
+        );
+        assert!(stderr.contains("  #0 0x"), "{stderr}");
+        let path = exports
+            .recv_timeout(Duration::from_secs(5))
+            .map(|(path, _)| path);
+        assert_eq!(path.as_deref(), Ok("/v1/traces"));
+    }
+
+    /// Runs the daemonize fixture in `how` with OTEL_POSIX_PROP_DAEMON set to `daemon`,
+    /// exporting to `port`, and returns the traceparent seen in the daemon.
+    #[cfg(feature = "hook-daemon")]
+    fn daemonize(how: &str, daemon: &str, port: u16) -> String {
+        let out = Command::new(compile_c("daemonize"))
+            .arg(how)
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_DAEMON", daemon)
+            .env("OTEL_POSIX_PROP_AUTO_ROOT", "on")
+            .env(
+                "OTEL_EXPORTER_OTLP_ENDPOINT",
+                format!("http://127.0.0.1:{port}"),
+            )
+            .env("OTEL_METRICS_EXPORTER", "none")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert!(out.status.success(), "{how}: {stdout}");
+        stdout
+            .lines()
+            .find_map(|l| l.strip_prefix("daemon "))
+            .unwrap_or_else(|| panic!("{how}: no daemon line in {stdout}"))
+            .to_string()
+    }
+
+    #[test]
+    #[cfg(feature = "hook-daemon")]
+    fn test_daemon_keeps_its_trace() {
+        for how in ["daemon", "double"] {
+            let (port, _exports) = collector();
+            assert_eq!(daemonize(how, "keep", port), TRACEPARENT, "{how}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "hook-daemon")]
+    fn test_daemon_rerooted() {
+        for how in ["daemon", "double"] {
+            let (port, exports) = collector();
+            let seen = daemonize(how, "root", port);
+            assert!(
+                seen.len() == 55 && seen[3..35] != TRACEPARENT[3..35],
+                "{how}: {seen}"
+            );
+            // the daemon span, exported by the daemon itself
+            let mut exports =
+                std::iter::from_fn(|| exports.recv_timeout(Duration::from_secs(5)).ok());
+            assert!(
+                exports.any(|(_, body)| body.windows(18).any(|w| w == b"process.parent_pid")),
+                "{how}"
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "hook-poll")]
+    fn test_poll_time_exported() {
+        let (port, exports) = collector();
+        let out = Command::new(compile_c("poll_loop"))
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_POLL_METRICS", "on")
+            .env("OTEL_POSIX_PROP_AUTO_ROOT", "on")
+            .env(
+                "OTEL_EXPORTER_OTLP_ENDPOINT",
+                format!("http://127.0.0.1:{port}"),
+            )
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        assert!(out.status.success(), "{stdout}");
+        assert!(!stdout.contains("probe -"), "{stdout}");
+        let contains = |body: &[u8], s: &str| body.windows(s.len()).any(|w| w == s.as_bytes());
+        let (mut metrics, mut span) = (false, false);
+        while let Ok((path, body)) = exports.recv_timeout(Duration::from_secs(5)) {
+            // the counters by thread name, and the event loop's own totals on its root span
+            metrics |= path == "/v1/metrics"
+                && contains(&body, "otel_posix.thread.poll.busy_time")
+                && contains(&body, "event-loop");
+            span |= path == "/v1/traces" && contains(&body, "otel_posix.thread.poll.wait");
+            if metrics && span {
+                break;
+            }
+        }
+        assert!(metrics && span, "metrics {metrics}, span {span}");
+    }
+
+    #[test]
+    fn test_attach_to_running_process() {
+        let (port, exports) = collector();
+        let mut target = Command::new(compile_c("attach_target"))
+            .env_remove("LD_PRELOAD")
+            .env_remove("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = BufReader::new(target.stdout.take().unwrap());
+        let mut ready = String::new();
+        stdout.read_line(&mut ready).unwrap();
+        let pid = ready.trim().strip_prefix("ready ").expect("no ready line");
+
+        let out = Command::new(env!("CARGO_BIN_EXE_otel-preload"))
+            .arg("attach")
+            .arg("--lib")
+            .arg(cdylib())
+            .args(["--endpoint", &format!("http://127.0.0.1:{port}"), pid])
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(out.status.success(), "{stderr}");
+
+        let mut rest = String::new();
+        stdout.read_to_string(&mut rest).unwrap();
+        assert!(target.wait().unwrap().success());
+        assert_eq!(rest, format!("worker {TRACEPARENT}\n"));
+        // the auto root spans and the shim's metrics, through the exporters it installed
+        // as it loaded
+        let mut paths: Vec<String> = (0..2)
+            .map(|_| exports.recv_timeout(Duration::from_secs(10)).unwrap().0)
+            .collect();
+        paths.sort();
+        assert_eq!(paths, ["/v1/metrics", "/v1/traces"]);
+    }
+
+    #[cfg(feature = "otlp")]
+    #[test]
+    fn test_observer_mode() {
+        let (port, exports) = collector();
+        let out = Command::new(env!("CARGO_BIN_EXE_otel-observe"))
+            .args(["--endpoint", &format!("http://127.0.0.1:{port}"), "--"])
+            .arg(compile_c("observed"))
+            .env_remove("LD_PRELOAD")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        if stderr.contains("can't set the probes") {
+            // not root, or no uprobes
+            eprintln!("skipping: {stderr}");
+            return;
+        }
+        assert!(out.status.success(), "{stderr}");
+
+        let (path, body) = exports.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(path, "/v1/traces");
+        let body = String::from_utf8_lossy(&body);
+        for expected in [
+            "process",
+            "thread",
+            "observed",
+            "outer_worker",
+            "inner_worker",
+            "otel_posix.thread.pthread_exit",
+        ] {
+            assert!(body.contains(expected), "no {expected} in {body:?}");
+        }
+    }
+
+    #[test]
+    fn test_status_report() {
+        let lines = run_preloaded(&compile_c("status"));
+        let status = lines
+            .iter()
+            .find(|(label, _)| label == "status")
+            .map(|(_, json)| json.as_str())
+            .expect("no status line");
+        assert!(status.contains("\"linkage\":\"preload\""), "{status}");
+        assert!(status.contains("\"wrapping\":\"enabled\""), "{status}");
+        assert!(
+            status.contains("\"next_pthread_create\":\"libc.so"),
+            "{status}"
+        );
+        assert!(
+            status.contains(
+                "\"binding\":{\"state\":\"bound\",\"object\":null,\"deepbind\":0,\"isolated\":0}"
+            ),
+            "{status}"
+        );
+        assert!(status.contains("\"counts\":{\"wrapped\":1,"), "{status}");
+    }
+
+    #[test]
+    fn test_late_load_is_reported() {
+        let out = Command::new(compile_c("status"))
+            .arg(cdylib())
+            .env_remove("LD_PRELOAD")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&out.stdout);
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(out.status.success(), "{stderr}");
+        // libc's pthread_create was bound before the shim was loaded
+        assert!(
+            stdout.contains("\"binding\":{\"state\":\"shadowed\",\"object\":\"libc.so"),
+            "{stdout}"
+        );
+        assert!(stdout.contains("\"counts\":{\"wrapped\":0,"), "{stdout}");
+        assert!(stderr.contains("no thread will be wrapped"), "{stderr}");
+    }
+
+    #[test]
+    fn test_attribute_only_mode() {
+        let mut command = Command::new(compile_c("origin"));
+        command
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_MODE", "attributes");
+        assert_eq!(
+            run(command),
+            seen(&[
+                ("created", "yes"),
+                ("creator", "yes"),
+                ("origin", TRACEPARENT),
+                ("worker", "-"),
+            ])
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "hook-thrd")]
+    fn test_c11_thrd_create() {
+        let lines = run_preloaded(&compile_c("c11_threads"));
+        assert_eq!(
+            lines,
+            seen(&[("result", "42"), ("thrd", TRACEPARENT), ("unseeded", "-")])
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "hook-glib")]
+    fn test_glib_threads() {
+        if !has_library("libglib-2.0.so.0") {
+            eprintln!("skipped: no libglib-2.0.so.0");
+            return;
+        }
+        let exe = compile_with("glib_threads.c", false, &["-l:libglib-2.0.so.0"]);
+        // only threads starting in the fixture's own code are wrapped, not GLib's proxy
+        let mut command = Command::new(&exe);
+        command
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_ENTRY_ALLOW", "glib_threads");
+        assert_eq!(
+            run(command),
+            seen(&[
+                ("g-thread-new", TRACEPARENT),
+                ("g-thread-try-new", TRACEPARENT),
+                ("result", "g-thread-try-new"),
+                ("unseeded", "-"),
+            ])
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "hook-uv")]
+    fn test_uv_threads() {
+        let stub = compile_shared("uv_stub");
+        let dir = stub.parent().unwrap().display();
+        let exe = compile_with(
+            "uv_threads.c",
+            false,
+            &[
+                &format!("-L{dir}"),
+                "-luv_stub",
+                &format!("-Wl,-rpath,{dir}"),
+            ],
+        );
+        // only threads starting in the fixture's own code are wrapped, not the proxy
+        let mut command = Command::new(&exe);
+        command
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_ENTRY_ALLOW", "uv_threads");
+        assert_eq!(
+            run(command),
+            seen(&[
+                ("unseeded", "-"),
+                ("uv-thread", TRACEPARENT),
+                ("uv-thread-ex", TRACEPARENT),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_caller_allowlist() {
+        let lib = compile_shared("caller_lib");
+        let dir = lib.parent().unwrap().display();
+        let exe = compile_with(
+            "caller.c",
+            false,
+            &[
+                &format!("-L{dir}"),
+                "-lcaller_lib",
+                &format!("-Wl,-rpath,{dir}"),
+            ],
+        );
+        // the same start routine either way; only who called pthread_create differs
+        let mut command = Command::new(&exe);
+        command
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_CALLER_ALLOW", "libcaller_lib.so*");
+        assert_eq!(
+            run(command),
+            seen(&[("direct", "-"), ("library", TRACEPARENT)])
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "hook-dlopen")]
+    fn test_dlopen_keeps_the_callers_runpath() {
+        let lib = compile_shared("caller_lib");
+        let dir = lib.parent().unwrap().display();
+        let exe = compile_with(
+            "runpath_dlopen.c",
+            false,
+            &[&format!("-Wl,-rpath,{dir}"), "-Wl,--enable-new-dtags"],
+        );
+        let mut command = Command::new(&exe);
+        command
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_DLOPEN_EVENTS", "true");
+        assert_eq!(run(command), seen(&[("plugin", TRACEPARENT)]));
+    }
+
+    #[test]
+    #[cfg(all(
+        feature = "hook-dlopen",
+        target_env = "gnu",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    fn test_deepbind_and_dlmopen_libraries() {
+        let lib = compile_shared("caller_lib");
+        let exe = compile_c("isolated_dlopen");
+        // bound to libc ahead of the shim, until the bindings are redirected
+        let mut command = Command::new(&exe);
+        command
+            .arg(&lib)
+            .arg("deepbind")
+            .env("LD_PRELOAD", cdylib());
+        assert_eq!(run(command), seen(&[("deepbind", TRACEPARENT)]));
+
+        // a namespace with a libc of its own is out of reach, and said to be
+        let out = Command::new(&exe)
+            .arg(&lib)
+            .arg("dlmopen")
+            .env("LD_PRELOAD", cdylib())
+            .env_remove("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(out.status.success(), "{stderr}");
+        assert_eq!(String::from_utf8_lossy(&out.stdout), "dlmopen -\n");
+        assert!(
+            stderr.contains("opened with dlmopen into a namespace of its own"),
+            "{stderr}"
+        );
+    }
+
+    #[test]
+    fn test_detached_threads() {
+        let lines = run_preloaded(&compile_c("detached"));
+        assert_eq!(
+            lines,
+            seen(&[("detached-1", TRACEPARENT), ("detached-2", TRACEPARENT)])
+        );
+    }
+
+    #[test]
+    fn test_crash_handler_finds_the_trace() {
+        let lines = run_preloaded(&compile_c("crash_handler"));
+        assert_eq!(
+            lines,
+            seen(&[("handler", TRACEPARENT), ("table", TRACEPARENT)])
+        );
+    }
+
+    #[test]
+    fn test_thread_created_from_constructor() {
+        let lines = run_preloaded(&compile_c("ctor_thread"));
+        assert_eq!(
+            lines,
+            seen(&[("ctor-worker", TRACEPARENT), ("main", TRACEPARENT)])
+        );
+    }
+
+    #[cfg(feature = "hook-signal")]
+    #[test]
+    fn test_threads_created_from_signal_handlers() {
+        let lines = run_preloaded(&compile_c("signal_thread"));
+        assert_eq!(
+            lines,
+            seen(&[
+                ("main", TRACEPARENT),
+                ("sigaction-worker", TRACEPARENT),
+                ("signal-worker", TRACEPARENT),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_threads_from_a_library_constructor_before_ours() {
+        let lib = compile_shared_cpp("early_lib");
+        let dir = lib.parent().unwrap().display();
+        let exe = compile_with(
+            "early.c",
+            false,
+            &[
+                &format!("-L{dir}"),
+                "-learly_lib",
+                &format!("-Wl,-rpath,{dir}"),
+            ],
+        );
+        // the recorder's switch is read by the first of them, not by its constructor
+        let mut command = Command::new(&exe);
+        command
+            .env("LD_PRELOAD", cdylib())
+            .env("OTEL_POSIX_PROP_RECORDER", "on");
+        assert_eq!(
+            run(command),
+            seen(&[
+                ("ctor-grandchild", TRACEPARENT),
+                ("ctor-thread", TRACEPARENT),
+                ("later", TRACEPARENT),
+                ("main", TRACEPARENT),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_cpp_std_thread_async_and_pool() {
+        let lines = run_preloaded(&compile_cpp("cxx_threads"));
+        let labels: Vec<&str> = lines.iter().map(|(label, _)| label.as_str()).collect();
+        let mut expected = vec![
+            "async",
+            "async-throw",
+            "caught-from-async",
+            "caught-in-thread",
+            "main",
+            "pthread-exit",
+            "std-thread",
+        ];
+        let tasks: Vec<String> = (0..6).map(|i| format!("pool-task-{i}")).collect();
+        expected.extend(tasks.iter().map(String::as_str));
+        expected.sort();
+        assert_eq!(labels, expected);
+        // every thread, including the pool's long-lived workers, saw the seeded context
+        assert!(lines.iter().all(|(_, tp)| tp == TRACEPARENT), "{lines:?}");
+    }
+}
@@ -0,0 +1,47 @@
+/* Started without the shim, for `otel-preload attach`: prints "ready <pid>", waits
+ * for the shim to show up, then seeds TRACEPARENT and reports from a thread, which
+ * only carries it if the pthread_create bound at startup now goes through the shim.
+ * The shim installs its exporter from a thread of its own, so before exiting it also
+ * waits for an auto root span to be a real one, as in auto_root.c. */
+#include <pthread.h>
+#include <sys/prctl.h>
+#include <unistd.h>
+
+#include "fixture.h"
+
+static void *worker(void *arg) {
+    report((const char *)arg);
+    return NULL;
+}
+
+static void *probe(void *arg) {
+    __typeof__(otel_posix_get_traceparent) *get =
+        (__typeof__(otel_posix_get_traceparent) *)shim_fn("otel_posix_get_traceparent");
+    char buf[64];
+    *(int *)arg = get(buf, sizeof buf) > 0;
+    return NULL;
+}
+
+int main(void) {
+    /* under Yama's ptrace_scope=1, only ancestors could attach otherwise */
+    prctl(PR_SET_PTRACER, PR_SET_PTRACER_ANY);
+    /* read by the shim once it is loaded */
+    setenv("OTEL_POSIX_PROP_AUTO_ROOT", "on", 1);
+    printf("ready %d\n", (int)getpid());
+    fflush(stdout);
+    /* mostly asleep, where the injector can borrow the thread */
+    for (int i = 0; i < 1000 && !dlsym(RTLD_DEFAULT, "otel_posix_set_traceparent"); i++)
+        usleep(10000);
+    pthread_t t;
+    int installed = 0;
+    for (int i = 0; i < 500 && !installed; i++) {
+        pthread_create(&t, NULL, probe, &installed);
+        pthread_join(t, NULL);
+        if (!installed)
+            usleep(10000);
+    }
+    seed();
+    pthread_create(&t, NULL, worker, "worker");
+    pthread_join(t, NULL);
+    return 0;
+}
@@ -0,0 +1,25 @@
+/* Threads started with C11's thrd_create, which doesn't go through pthread_create. */
+#include <threads.h>
+
+#include "fixture.h"
+
+static int worker(void *arg) {
+    report((const char *)arg);
+    return 42;
+}
+
+int main(void) {
+    thrd_t before, a;
+    int result = 0;
+    /* nothing attached yet: must pass through */
+    thrd_create(&before, worker, "unseeded");
+    thrd_join(before, NULL);
+
+    seed();
+    if (thrd_create(&a, worker, "thrd") != thrd_success)
+        return 1;
+    thrd_join(a, &result);
+    /* the start routine's int still reaches thrd_join */
+    printf("result %d\n", result);
+    return 0;
+}
@@ -0,0 +1,22 @@
+/* Threads started by the executable itself and by the library in caller_lib.c. */
+#include <pthread.h>
+
+#include "fixture.h"
+
+int caller_lib_spawn(pthread_t *tid, void *(*start)(void *), void *arg);
+
+static void *worker(void *arg) {
+    report((const char *)arg);
+    return NULL;
+}
+
+int main(void) {
+    pthread_t t;
+    seed();
+    pthread_create(&t, NULL, worker, "direct");
+    pthread_join(t, NULL);
+    if (caller_lib_spawn(&t, worker, "library") != 0)
+        return 1;
+    pthread_join(t, NULL);
+    return 0;
+}
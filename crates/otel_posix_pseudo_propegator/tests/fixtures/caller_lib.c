@@ -0,0 +1,6 @@
+/* A library of one's own, starting threads on behalf of the process it's loaded into. */
+#include <pthread.h>
+
+int caller_lib_spawn(pthread_t *tid, void *(*start)(void *), void *arg) {
+    return pthread_create(tid, NULL, start, arg);
+}
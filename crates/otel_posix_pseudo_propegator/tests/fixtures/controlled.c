@@ -0,0 +1,26 @@
+/* Creates a thread per "spawn <label>" line on stdin, under the seeded
+ * context, until stdin is closed; the test pauses and resumes wrapping
+ * through the control socket in between. */
+#include <pthread.h>
+#include <string.h>
+
+#include "fixture.h"
+
+static void *worker(void *arg) {
+    report((const char *)arg);
+    return NULL;
+}
+
+int main(void) {
+    char line[128];
+    seed();
+    while (fgets(line, sizeof line, stdin)) {
+        line[strcspn(line, "\n")] = '\0';
+        if (strncmp(line, "spawn ", 6) != 0)
+            continue;
+        pthread_t t;
+        pthread_create(&t, NULL, worker, line + 6);
+        pthread_join(t, NULL);
+    }
+    return 0;
+}
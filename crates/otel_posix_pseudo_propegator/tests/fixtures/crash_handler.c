@@ -0,0 +1,51 @@
+/* A crash handler reading the trace of the thread that crashed, like breakpad or
+ * crashpad would: SIGSEGV on a wrapped worker, reported from the handler with
+ * write(2) only, then the process exits. */
+#include <pthread.h>
+#include <signal.h>
+#include <string.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#include "fixture.h"
+
+static __typeof__(otel_posix_crash_traceparent) *crash_traceparent;
+static OtelPosixCrashSlot *slots;
+
+static void write_line(const char *label, const char *tp) {
+    write(STDOUT_FILENO, label, strlen(label));
+    write(STDOUT_FILENO, " ", 1);
+    write(STDOUT_FILENO, tp && *tp ? tp : "-", tp && *tp ? strlen(tp) : 1);
+    write(STDOUT_FILENO, "\n", 1);
+}
+
+static void on_crash(int sig) {
+    (void)sig;
+    pid_t tid = (pid_t)syscall(SYS_gettid);
+    write_line("handler", crash_traceparent(tid));
+    /* the table itself, as a core dump would have it */
+    for (size_t i = 0; i < OTEL_POSIX_CRASH_SLOTS; i++) {
+        if (slots[i].tid == tid) {
+            write_line("table", slots[i].traceparent);
+        }
+    }
+    _exit(0);
+}
+
+static void *worker(void *arg) {
+    (void)arg;
+    raise(SIGSEGV);
+    return NULL;
+}
+
+int main(void) {
+    crash_traceparent = shim_fn("otel_posix_crash_traceparent");
+    slots = shim_fn("otel_posix_crash_traceparents");
+    signal(SIGSEGV, on_crash);
+
+    pthread_t t;
+    seed();
+    pthread_create(&t, NULL, worker, NULL);
+    pthread_join(t, NULL);
+    return 1;
+}
@@ -0,0 +1,34 @@
+/* Threads created from start_worker without any context, run with
+ * OTEL_POSIX_PROP_AUTO_ROOT and built with -rdynamic, so that the creation stack
+ * on their root spans can name it. */
+#include <pthread.h>
+#include <unistd.h>
+
+#include "fixture.h"
+
+static void *worker(void *arg) {
+    __typeof__(otel_posix_get_traceparent) *get =
+        (__typeof__(otel_posix_get_traceparent) *)shim_fn("otel_posix_get_traceparent");
+    char *out = (char *)arg;
+    if (get(out, 64) <= 0)
+        out[0] = '\0';
+    return NULL;
+}
+
+void start_worker(char *out) {
+    pthread_t t;
+    pthread_create(&t, NULL, worker, out);
+    pthread_join(t, NULL);
+}
+
+int main(void) {
+    char seen[64] = "";
+    /* until the provider the shim installs from a thread of its own is there */
+    for (int i = 0; i < 500 && !seen[0]; i++) {
+        start_worker(seen);
+        if (!seen[0])
+            usleep(10000);
+    }
+    printf("worker %s\n", seen[0] ? seen : "-");
+    return 0;
+}
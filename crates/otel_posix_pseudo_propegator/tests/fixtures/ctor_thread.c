@@ -0,0 +1,21 @@
+/* A thread created from a static constructor, before main runs. */
+#include <pthread.h>
+
+#include "fixture.h"
+
+static void *worker(void *arg) {
+    report((const char *)arg);
+    return NULL;
+}
+
+__attribute__((constructor)) static void start_early(void) {
+    pthread_t t;
+    seed();
+    pthread_create(&t, NULL, worker, "ctor-worker");
+    pthread_join(t, NULL);
+}
+
+int main(void) {
+    report("main");
+    return 0;
+}
@@ -0,0 +1,110 @@
+// C++ threading under the shim: libstdc++'s std::thread / std::async entry
+// thunks, a small pool, and exceptions thrown on wrapped threads.
+#include <pthread.h>
+
+#include <condition_variable>
+#include <functional>
+#include <future>
+#include <mutex>
+#include <queue>
+#include <stdexcept>
+#include <string>
+#include <thread>
+#include <vector>
+
+#include "fixture.h"
+
+class Pool {
+  public:
+    explicit Pool(int n) {
+        for (int i = 0; i < n; i++) {
+            workers.emplace_back([this] { run(); });
+        }
+    }
+
+    ~Pool() {
+        {
+            std::lock_guard<std::mutex> lock(mu);
+            stopping = true;
+        }
+        cv.notify_all();
+        for (auto &w : workers) {
+            w.join();
+        }
+    }
+
+    void submit(std::function<void()> task) {
+        {
+            std::lock_guard<std::mutex> lock(mu);
+            tasks.push(std::move(task));
+        }
+        cv.notify_one();
+    }
+
+  private:
+    void run() {
+        for (;;) {
+            std::function<void()> task;
+            {
+                std::unique_lock<std::mutex> lock(mu);
+                cv.wait(lock, [this] { return stopping || !tasks.empty(); });
+                if (tasks.empty()) {
+                    return;
+                }
+                task = std::move(tasks.front());
+                tasks.pop();
+            }
+            task();
+        }
+    }
+
+    std::mutex mu;
+    std::condition_variable cv;
+    std::queue<std::function<void()>> tasks;
+    std::vector<std::thread> workers;
+    bool stopping = false;
+};
+
+int main() {
+    seed();
+
+    std::thread([] { report("std-thread"); }).join();
+
+    std::async(std::launch::async, [] { report("async"); }).get();
+
+    {
+        Pool pool(3);
+        for (int i = 0; i < 6; i++) {
+            pool.submit([i] { report(("pool-task-" + std::to_string(i)).c_str()); });
+        }
+    }
+
+    // thrown and caught on the wrapped thread
+    std::thread([] {
+        try {
+            throw std::runtime_error("inside");
+        } catch (const std::exception &) {
+            report("caught-in-thread");
+        }
+    }).join();
+
+    // carried out of the thread by the future and rethrown here
+    auto failing = std::async(std::launch::async, [] {
+        report("async-throw");
+        throw std::runtime_error("to caller");
+    });
+    try {
+        failing.get();
+    } catch (const std::runtime_error &) {
+        report("caught-from-async");
+    }
+
+    // pthread_exit unwinds the thread's stack with a forced unwind
+    std::thread([] {
+        report("pthread-exit");
+        pthread_exit(nullptr);
+    }).join();
+
+    report("main");
+    return 0;
+}
@@ -0,0 +1,56 @@
+/* Daemonizes under TRACEPARENT, with daemon(3) or by forking, calling setsid and forking
+ * again as argv[1] says, and reports the context a thread started in the daemon gets.
+ * Run with OTEL_POSIX_PROP_AUTO_ROOT and an exporter. */
+#include <pthread.h>
+#include <string.h>
+#include <sys/wait.h>
+#include <unistd.h>
+
+#include "fixture.h"
+
+static void *worker(void *arg) {
+    __typeof__(otel_posix_get_traceparent) *get =
+        (__typeof__(otel_posix_get_traceparent) *)shim_fn("otel_posix_get_traceparent");
+    char *out = (char *)arg;
+    if (get(out, 64) <= 0)
+        out[0] = '\0';
+    return NULL;
+}
+
+static void spawn(char *out) {
+    pthread_t t;
+    pthread_create(&t, NULL, worker, out);
+    pthread_join(t, NULL);
+}
+
+int main(int argc, char **argv) {
+    char traceparent[64] = "";
+    /* wait for the provider, as in auto_root.c, so the daemon has one to start again */
+    for (int i = 0; i < 500 && !traceparent[0]; i++) {
+        spawn(traceparent);
+        if (!traceparent[0])
+            usleep(10000);
+    }
+    seed();
+
+    /* stdout stays open, so the test reads until the daemon is done */
+    if (argc > 1 && strcmp(argv[1], "double") == 0) {
+        pid_t child = fork();
+        if (child > 0) {
+            waitpid(child, NULL, 0);
+            _exit(0);
+        }
+        setsid();
+        if (fork() > 0)
+            _exit(0);
+    } else if (daemon(1, 1) != 0) {
+        perror("daemon");
+        return 1;
+    }
+
+    char seen[64] = "";
+    spawn(seen);
+    printf("daemon %s\n", seen[0] ? seen : "-");
+    fflush(stdout);
+    return 0;
+}
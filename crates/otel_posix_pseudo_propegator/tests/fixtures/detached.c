@@ -0,0 +1,28 @@
+/* Detached threads, which nobody joins: the launcher must be freed by the thread. */
+#include <pthread.h>
+#include <semaphore.h>
+
+#include "fixture.h"
+
+static sem_t done;
+
+static void *worker(void *arg) {
+    report((const char *)arg);
+    sem_post(&done);
+    return NULL;
+}
+
+int main(void) {
+    pthread_attr_t attr;
+    pthread_t t;
+    sem_init(&done, 0, 0);
+    pthread_attr_init(&attr);
+    pthread_attr_setdetachstate(&attr, PTHREAD_CREATE_DETACHED);
+
+    seed();
+    pthread_create(&t, &attr, worker, "detached-1");
+    pthread_create(&t, &attr, worker, "detached-2");
+    sem_wait(&done);
+    sem_wait(&done);
+    return 0;
+}
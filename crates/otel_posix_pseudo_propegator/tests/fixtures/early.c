@@ -0,0 +1,38 @@
+/* Linked against early_lib.cpp, whose constructor starts threads before the shim's run. */
+#include <pthread.h>
+#include <string.h>
+#include <unistd.h>
+
+#include "fixture.h"
+
+void early_lib_spawn(void);
+
+static void *worker(void *arg) {
+    __typeof__(otel_posix_get_traceparent) *get =
+        (__typeof__(otel_posix_get_traceparent) *)shim_fn("otel_posix_get_traceparent");
+    char *out = (char *)arg;
+    if (get(out, 64) <= 0)
+        out[0] = '\0';
+    return NULL;
+}
+
+int main(int argc, char **argv) {
+    report("main");
+    early_lib_spawn();
+    /* "wait", with an exporter and OTEL_POSIX_PROP_AUTO_ROOT: stay until the provider is
+     * installed, as in auto_root.c, for the early threads to be reported */
+    if (argc > 1 && strcmp(argv[1], "wait") == 0) {
+        __typeof__(otel_posix_set_traceparent) *set =
+            (__typeof__(otel_posix_set_traceparent) *)shim_fn("otel_posix_set_traceparent");
+        char root[64] = "";
+        set(NULL);
+        for (int i = 0; i < 500 && !root[0]; i++) {
+            pthread_t t;
+            pthread_create(&t, NULL, worker, root);
+            pthread_join(t, NULL);
+            if (!root[0])
+                usleep(10000);
+        }
+    }
+    return 0;
+}
@@ -0,0 +1,28 @@
+// A library whose global constructor starts threads. ld.so initializes the
+// executable's libraries before the preloaded shim, so these are created
+// before any of the shim's own constructors have run.
+#include <thread>
+
+#include "fixture.h"
+
+namespace {
+
+struct Early {
+    Early() {
+        seed();
+        std::thread([] {
+            report("ctor-thread");
+            // and one more level down, still before the shim's constructors
+            std::thread([] { report("ctor-grandchild"); }).join();
+        }).join();
+    }
+};
+
+Early early;
+
+} // namespace
+
+// a thread of the same library, started once main runs
+extern "C" void early_lib_spawn(void) {
+    std::thread([] { report("later"); }).join();
+}
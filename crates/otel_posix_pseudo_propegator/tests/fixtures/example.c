@@ -0,0 +1,17 @@
+/* The example from the README, run by tests/fixtures.rs to check it works as shown. */
+#include <pthread.h>
+#include <stdio.h>
+
+void* worker(void* arg) {
+    (void)arg;
+    // This function runs with the parent thread's trace context attached
+    printf("Hello from worker thread!\n");
+    return NULL;
+}
+
+int main() {
+    pthread_t tid;
+    pthread_create(&tid, NULL, worker, NULL);
+    pthread_join(tid, NULL);
+    return 0;
+}
@@ -0,0 +1,40 @@
+/* Ends a span, then leaves through the exit named in argv[1] before the batch
+ * processor's next scheduled export. Run with OTEL_POSIX_PROP_AUTO_ROOT and an
+ * exporter. */
+#include <pthread.h>
+#include <string.h>
+#include <unistd.h>
+
+#include "fixture.h"
+
+static void *worker(void *arg) {
+    __typeof__(otel_posix_get_traceparent) *get =
+        (__typeof__(otel_posix_get_traceparent) *)shim_fn("otel_posix_get_traceparent");
+    char *out = (char *)arg;
+    if (get(out, 64) <= 0)
+        out[0] = '\0';
+    return NULL;
+}
+
+int main(int argc, char **argv) {
+    char traceparent[64] = "";
+    /* wait for the provider, as in auto_root.c; the thread's root span ends as it exits */
+    for (int i = 0; i < 500 && !traceparent[0]; i++) {
+        pthread_t t;
+        pthread_create(&t, NULL, worker, traceparent);
+        pthread_join(t, NULL);
+        if (!traceparent[0])
+            usleep(10000);
+    }
+    printf("worker %s\n", traceparent[0] ? traceparent : "-");
+    fflush(stdout);
+
+    const char *how = argc > 1 ? argv[1] : "";
+    if (strcmp(how, "_exit") == 0)
+        _exit(0);
+    if (strcmp(how, "_Exit") == 0)
+        _Exit(0);
+    if (strcmp(how, "quick_exit") == 0)
+        quick_exit(0);
+    exit(0);
+}
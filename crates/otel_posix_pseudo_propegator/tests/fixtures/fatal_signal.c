@@ -0,0 +1,40 @@
+/* A wrapped worker that crashes with SIGSEGV, under OTEL_POSIX_PROP_CRASH_SIGNALS and
+ * an exporter. */
+#include <pthread.h>
+#include <unistd.h>
+
+#include "fixture.h"
+
+static void *probe(void *arg) {
+    __typeof__(otel_posix_get_traceparent) *get =
+        (__typeof__(otel_posix_get_traceparent) *)shim_fn("otel_posix_get_traceparent");
+    char *out = (char *)arg;
+    if (get(out, 64) <= 0)
+        out[0] = '\0';
+    return NULL;
+}
+
+static void *crash(void *arg) {
+    (void)arg;
+    report("worker");
+    *(volatile int *)0 = 1;
+    return NULL;
+}
+
+int main(void) {
+    /* wait for the provider, as in auto_root.c, so the crash has somewhere to go */
+    char probed[64] = "";
+    for (int i = 0; i < 500 && !probed[0]; i++) {
+        pthread_t t;
+        pthread_create(&t, NULL, probe, probed);
+        pthread_join(t, NULL);
+        if (!probed[0])
+            usleep(10000);
+    }
+
+    pthread_t t;
+    seed();
+    pthread_create(&t, NULL, crash, NULL);
+    pthread_join(t, NULL);
+    return 1;
+}
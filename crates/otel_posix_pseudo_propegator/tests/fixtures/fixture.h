@@ -0,0 +1,49 @@
+/* Shared helpers for the C and C++ fixtures run under LD_PRELOAD by tests/fixtures.rs.
+ *
+ * The shim's C API is looked up with dlsym so the fixtures don't link
+ * against it: it's only there when preloaded, like in production. */
+#ifndef FIXTURE_H
+#define FIXTURE_H
+
+#ifndef _GNU_SOURCE
+#define _GNU_SOURCE
+#endif
+#include <dlfcn.h>
+#include <stdio.h>
+#include <stdlib.h>
+/* the header has to sit alongside the unwinder's, which declares the same functions */
+#include <unwind.h>
+
+#include "otel_posix_pseudo_propegator.h"
+
+#define TRACEPARENT "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+
+static void *shim_fn(const char *name) {
+    void *f = dlsym(RTLD_DEFAULT, name);
+    if (!f) {
+        fprintf(stderr, "%s not found, is the shim preloaded?\n", name);
+        exit(3);
+    }
+    return f;
+}
+
+/* attaches TRACEPARENT to the calling thread */
+static inline void seed(void) {
+    __typeof__(otel_posix_set_traceparent) *set =
+        (__typeof__(otel_posix_set_traceparent) *)shim_fn("otel_posix_set_traceparent");
+    if (set(TRACEPARENT) != 0) {
+        fprintf(stderr, "failed to seed traceparent\n");
+        exit(3);
+    }
+}
+
+/* prints "<label> <traceparent or ->" for the calling thread */
+static inline void report(const char *label) {
+    __typeof__(otel_posix_get_traceparent) *get =
+        (__typeof__(otel_posix_get_traceparent) *)shim_fn("otel_posix_get_traceparent");
+    char buf[64];
+    printf("%s %s\n", label, get(buf, sizeof buf) > 0 ? buf : "-");
+    fflush(stdout);
+}
+
+#endif
@@ -0,0 +1,34 @@
+/* A library loaded away from the global scope, where the preloaded pthread_create is
+ * found: "deepbind" opens it with RTLD_DEEPBIND, "dlmopen" into a namespace of its own. */
+/* first, for the _GNU_SOURCE dlmopen needs */
+#include "fixture.h"
+
+#include <pthread.h>
+#include <string.h>
+
+static void *worker(void *arg) {
+    report((const char *)arg);
+    return NULL;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 3) {
+        fprintf(stderr, "usage: %s <library> deepbind|dlmopen\n", argv[0]);
+        return 2;
+    }
+    seed();
+    void *lib = strcmp(argv[2], "dlmopen") == 0
+                    ? dlmopen(LM_ID_NEWLM, argv[1], RTLD_NOW)
+                    : dlopen(argv[1], RTLD_NOW | RTLD_DEEPBIND);
+    if (!lib) {
+        fprintf(stderr, "%s\n", dlerror());
+        return 1;
+    }
+    int (*spawn)(pthread_t *, void *(*)(void *), void *) =
+        (int (*)(pthread_t *, void *(*)(void *), void *))dlsym(lib, "caller_lib_spawn");
+    pthread_t t;
+    spawn(&t, worker, argv[2]);
+    pthread_join(t, NULL);
+    dlclose(lib);
+    return 0;
+}
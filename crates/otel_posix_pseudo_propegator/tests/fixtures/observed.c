@@ -0,0 +1,26 @@
+/* Run under otel-observe, without the shim: main creates outer_worker, which creates
+ * inner_worker, which leaves with pthread_exit. Only the lineage can be seen from the
+ * outside, so there's nothing to report. */
+#include <pthread.h>
+#include <unistd.h>
+
+void *inner_worker(void *arg) {
+    (void)arg;
+    usleep(20000);
+    pthread_exit(NULL);
+}
+
+void *outer_worker(void *arg) {
+    (void)arg;
+    pthread_t t;
+    pthread_create(&t, NULL, inner_worker, NULL);
+    pthread_join(t, NULL);
+    return NULL;
+}
+
+int main(void) {
+    pthread_t t;
+    pthread_create(&t, NULL, outer_worker, NULL);
+    pthread_join(t, NULL);
+    return 0;
+}
@@ -0,0 +1,33 @@
+/* Attribute-only mode: the thread gets no context, only a note of its creator. */
+#include <pthread.h>
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#include "fixture.h"
+
+static pid_t creator;
+
+static void *worker(void *arg) {
+    __typeof__(otel_posix_thread_origin) *origin =
+        (__typeof__(otel_posix_thread_origin) *)shim_fn("otel_posix_thread_origin");
+    char buf[64];
+    pid_t tid = 0;
+    uint64_t created = 0;
+    report((const char *)arg);
+    if (origin(&tid, &created, buf, sizeof buf) > 0)
+        printf("origin %s\ncreator %s\ncreated %s\n", buf, tid == creator ? "yes" : "no",
+               created > 0 ? "yes" : "no");
+    else
+        printf("origin -\n");
+    fflush(stdout);
+    return NULL;
+}
+
+int main(void) {
+    pthread_t t;
+    creator = (pid_t)syscall(SYS_gettid);
+    seed();
+    pthread_create(&t, NULL, worker, "worker");
+    pthread_join(t, NULL);
+    return 0;
+}
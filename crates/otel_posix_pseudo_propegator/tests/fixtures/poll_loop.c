@@ -0,0 +1,55 @@
+/* Runs a small event loop on a thread named "event-loop": it waits in poll and
+ * epoll_wait, and does some work between the waits. Run with
+ * OTEL_POSIX_PROP_POLL_METRICS, OTEL_POSIX_PROP_AUTO_ROOT and an exporter. */
+#ifndef _GNU_SOURCE
+#define _GNU_SOURCE
+#endif
+#include <poll.h>
+#include <pthread.h>
+#include <sys/epoll.h>
+#include <unistd.h>
+
+#include "fixture.h"
+
+static void *probe(void *arg) {
+    __typeof__(otel_posix_get_traceparent) *get =
+        (__typeof__(otel_posix_get_traceparent) *)shim_fn("otel_posix_get_traceparent");
+    char *out = (char *)arg;
+    if (get(out, 64) <= 0)
+        out[0] = '\0';
+    return NULL;
+}
+
+static void *event_loop(void *arg) {
+    (void)arg;
+    pthread_setname_np(pthread_self(), "event-loop");
+    int epfd = epoll_create1(0);
+    struct epoll_event event;
+    for (int i = 0; i < 3; i++) {
+        poll(NULL, 0, 10);
+        usleep(5000);
+        epoll_wait(epfd, &event, 1, 10);
+        usleep(5000);
+    }
+    close(epfd);
+    return NULL;
+}
+
+int main(void) {
+    char traceparent[64] = "";
+    /* wait for the provider, as in auto_root.c, so the metrics are registered */
+    for (int i = 0; i < 500 && !traceparent[0]; i++) {
+        pthread_t t;
+        pthread_create(&t, NULL, probe, traceparent);
+        pthread_join(t, NULL);
+        if (!traceparent[0])
+            usleep(10000);
+    }
+    printf("probe %s\n", traceparent[0] ? traceparent : "-");
+
+    pthread_t t;
+    pthread_create(&t, NULL, event_loop, NULL);
+    pthread_join(t, NULL);
+    /* exit flushes both providers */
+    return 0;
+}
@@ -0,0 +1,24 @@
+/* Joinable threads created with a raw pthread_create. */
+#include <pthread.h>
+
+#include "fixture.h"
+
+static void *worker(void *arg) {
+    report((const char *)arg);
+    return NULL;
+}
+
+int main(void) {
+    pthread_t before, a, b;
+    /* nothing attached yet: must pass through */
+    pthread_create(&before, NULL, worker, "unseeded");
+    pthread_join(before, NULL);
+
+    seed();
+    pthread_create(&a, NULL, worker, "worker-a");
+    pthread_create(&b, NULL, worker, "worker-b");
+    pthread_join(a, NULL);
+    pthread_join(b, NULL);
+    report("main");
+    return 0;
+}
@@ -0,0 +1,26 @@
+/* A plugin found through the executable's own RUNPATH, which dlopen only searches
+ * for calls made from the executable itself. */
+#include <pthread.h>
+
+#include "fixture.h"
+
+static void *worker(void *arg) {
+    report((const char *)arg);
+    return NULL;
+}
+
+int main(void) {
+    seed();
+    void *lib = dlopen("libcaller_lib.so", RTLD_NOW);
+    if (!lib) {
+        fprintf(stderr, "%s\n", dlerror());
+        return 1;
+    }
+    int (*spawn)(pthread_t *, void *(*)(void *), void *) =
+        (int (*)(pthread_t *, void *(*)(void *), void *))dlsym(lib, "caller_lib_spawn");
+    pthread_t t;
+    spawn(&t, worker, "plugin");
+    pthread_join(t, NULL);
+    dlclose(lib);
+    return 0;
+}
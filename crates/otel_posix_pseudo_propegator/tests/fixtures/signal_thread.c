@@ -0,0 +1,54 @@
+/* Threads created from signal handlers, installed with sigaction and with signal. */
+#include <pthread.h>
+#include <signal.h>
+#include <string.h>
+
+#include "fixture.h"
+
+static pthread_t threads[2];
+static volatile sig_atomic_t created;
+
+static void *worker(void *arg) {
+    report((const char *)arg);
+    return NULL;
+}
+
+static void on_usr1(int sig) {
+    (void)sig;
+    if (pthread_create(&threads[created], NULL, worker, "sigaction-worker") == 0)
+        created++;
+}
+
+static void on_usr2(int sig) {
+    (void)sig;
+    if (pthread_create(&threads[created], NULL, worker, "signal-worker") == 0)
+        created++;
+}
+
+int main(void) {
+    struct sigaction act, old;
+    memset(&act, 0, sizeof act);
+    act.sa_handler = on_usr1;
+    sigemptyset(&act.sa_mask);
+    sigaction(SIGUSR1, &act, NULL);
+    signal(SIGUSR2, on_usr2);
+
+    /* the handler installed is the one reported back */
+    sigaction(SIGUSR1, NULL, &old);
+    if (old.sa_handler != on_usr1 || (old.sa_flags & SA_SIGINFO)) {
+        fprintf(stderr, "sigaction reported another handler\n");
+        return 1;
+    }
+    if (signal(SIGUSR2, on_usr2) != on_usr2) {
+        fprintf(stderr, "signal reported another handler\n");
+        return 1;
+    }
+
+    seed();
+    raise(SIGUSR1);
+    raise(SIGUSR2);
+    for (int i = 0; i < created; i++)
+        pthread_join(threads[i], NULL);
+    report("main");
+    return 0;
+}
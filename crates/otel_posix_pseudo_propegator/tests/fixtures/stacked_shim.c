@@ -0,0 +1,26 @@
+/* Another LD_PRELOAD interposer of pthread_create, the way allocators and
+ * security shims wrap it: counts the calls and passes them on with
+ * RTLD_NEXT. Reports the count at exit as "shim-calls <n>". */
+#ifndef _GNU_SOURCE
+#define _GNU_SOURCE
+#endif
+#include <dlfcn.h>
+#include <pthread.h>
+#include <stdio.h>
+
+typedef int (*create_fn)(pthread_t *, const pthread_attr_t *, void *(*)(void *), void *);
+
+static int calls;
+
+int pthread_create(pthread_t *t, const pthread_attr_t *attr, void *(*start)(void *), void *arg) {
+    static create_fn next;
+    if (!next)
+        next = (create_fn)dlsym(RTLD_NEXT, "pthread_create");
+    __atomic_fetch_add(&calls, 1, __ATOMIC_RELAXED);
+    return next(t, attr, start, arg);
+}
+
+__attribute__((destructor)) static void report_calls(void) {
+    printf("shim-calls %d\n", calls);
+    fflush(stdout);
+}
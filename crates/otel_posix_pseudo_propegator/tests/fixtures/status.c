@@ -0,0 +1,31 @@
+/* Asks the preloaded shim for its status report, as deployment tooling would. Given a
+ * path, loads the shim itself with dlopen instead, too late to interpose anything. */
+#include <pthread.h>
+
+#include "fixture.h"
+
+static void *worker(void *arg) {
+    report((const char *)arg);
+    return NULL;
+}
+
+int main(int argc, char **argv) {
+    pthread_t t;
+    if (argc > 1 && !dlopen(argv[1], RTLD_NOW | RTLD_GLOBAL)) {
+        fprintf(stderr, "%s\n", dlerror());
+        return 1;
+    }
+    seed();
+    pthread_create(&t, NULL, worker, "worker");
+    pthread_join(t, NULL);
+
+    __typeof__(otel_posix_prop_status) *status =
+        (__typeof__(otel_posix_prop_status) *)shim_fn("otel_posix_prop_status");
+    int len = status(NULL, 0);
+    char *buf = malloc(len + 1);
+    if (!buf || status(buf, len + 1) != len)
+        return 1;
+    printf("status %s\n", buf);
+    free(buf);
+    return 0;
+}
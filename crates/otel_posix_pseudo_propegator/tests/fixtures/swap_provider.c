@@ -0,0 +1,48 @@
+/* Replaces the tracer provider installed at load time with one exporting to
+ * argv[1], then shuts it down, and reports the root span of a thread started
+ * under each. Run with OTEL_POSIX_PROP_AUTO_ROOT and an exporter. */
+#include <pthread.h>
+#include <unistd.h>
+
+#include "fixture.h"
+
+static void *worker(void *arg) {
+    __typeof__(otel_posix_get_traceparent) *get =
+        (__typeof__(otel_posix_get_traceparent) *)shim_fn("otel_posix_get_traceparent");
+    char *out = (char *)arg;
+    if (get(out, 64) <= 0)
+        out[0] = '\0';
+    return NULL;
+}
+
+/* the thread's root span ends as it exits */
+static void run(const char *label, char *traceparent) {
+    pthread_t t;
+    traceparent[0] = '\0';
+    pthread_create(&t, NULL, worker, traceparent);
+    pthread_join(t, NULL);
+    printf("%s %s\n", label, traceparent[0] ? traceparent : "-");
+}
+
+int main(int argc, char **argv) {
+    __typeof__(otel_posix_install_provider) *install =
+        (__typeof__(otel_posix_install_provider) *)shim_fn("otel_posix_install_provider");
+    __typeof__(otel_posix_shutdown) *shutdown =
+        (__typeof__(otel_posix_shutdown) *)shim_fn("otel_posix_shutdown");
+    char traceparent[64] = "";
+    /* wait for the provider, as in auto_root.c */
+    for (int i = 0; i < 500 && !traceparent[0]; i++) {
+        pthread_t t;
+        pthread_create(&t, NULL, worker, traceparent);
+        pthread_join(t, NULL);
+        if (!traceparent[0])
+            usleep(10000);
+    }
+    printf("loaded %s\n", traceparent[0] ? traceparent : "-");
+
+    printf("install %d\n", install(argc > 1 ? argv[1] : NULL));
+    run("installed", traceparent);
+    printf("shutdown %d\n", shutdown());
+    run("shut-down", traceparent);
+    return 0;
+}
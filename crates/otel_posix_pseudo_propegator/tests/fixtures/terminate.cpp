@@ -0,0 +1,31 @@
+// Throws an exception nothing catches, under the seeded context, once the
+// provider is installed. Run with OTEL_POSIX_PROP_AUTO_ROOT and an exporter.
+#include <pthread.h>
+#include <unistd.h>
+
+#include <stdexcept>
+
+#include "fixture.h"
+
+static void *worker(void *arg) {
+    __typeof__(otel_posix_get_traceparent) *get =
+        (__typeof__(otel_posix_get_traceparent) *)shim_fn("otel_posix_get_traceparent");
+    char *out = static_cast<char *>(arg);
+    if (get(out, 64) <= 0)
+        out[0] = '\0';
+    return nullptr;
+}
+
+int main() {
+    char traceparent[64] = "";
+    // wait for the provider, as in exit_flush.c
+    for (int i = 0; i < 500 && !traceparent[0]; i++) {
+        pthread_t t;
+        pthread_create(&t, nullptr, worker, traceparent);
+        pthread_join(t, nullptr);
+        if (!traceparent[0])
+            usleep(10000);
+    }
+    seed();
+    throw std::runtime_error("out of widgets");
+}
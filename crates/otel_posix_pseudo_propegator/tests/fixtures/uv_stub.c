@@ -0,0 +1,47 @@
+/* A stand-in for a shared libuv's thread functions, starting threads through a proxy
+ * routine of its own the way libuv before 1.23 did. */
+#include <pthread.h>
+#include <stdlib.h>
+
+typedef pthread_t uv_thread_t;
+typedef struct {
+    unsigned int flags;
+    size_t stack_size;
+} uv_thread_options_t;
+
+struct thread_ctx {
+    void (*entry)(void *arg);
+    void *arg;
+};
+
+static void *thread_start(void *p) {
+    struct thread_ctx ctx = *(struct thread_ctx *)p;
+    free(p);
+    ctx.entry(ctx.arg);
+    return NULL;
+}
+
+int uv_thread_create_ex(uv_thread_t *tid, const uv_thread_options_t *params,
+                        void (*entry)(void *arg), void *arg) {
+    (void)params;
+    struct thread_ctx *ctx = malloc(sizeof *ctx);
+    if (!ctx)
+        return -12;
+    ctx->entry = entry;
+    ctx->arg = arg;
+    int err = pthread_create(tid, NULL, thread_start, ctx);
+    if (err) {
+        free(ctx);
+        return -err;
+    }
+    return 0;
+}
+
+int uv_thread_create(uv_thread_t *tid, void (*entry)(void *arg), void *arg) {
+    uv_thread_options_t params = {0, 0};
+    return uv_thread_create_ex(tid, &params, entry, arg);
+}
+
+int uv_thread_join(uv_thread_t *tid) {
+    return -pthread_join(*tid, NULL);
+}
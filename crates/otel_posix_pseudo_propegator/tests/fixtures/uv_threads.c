@@ -0,0 +1,37 @@
+/* Threads started with libuv's uv_thread_create and uv_thread_create_ex, linked against
+ * the stand-in in uv_stub.c. */
+#include <pthread.h>
+
+#include "fixture.h"
+
+typedef pthread_t uv_thread_t;
+typedef struct {
+    unsigned int flags;
+    size_t stack_size;
+} uv_thread_options_t;
+
+int uv_thread_create(uv_thread_t *tid, void (*entry)(void *arg), void *arg);
+int uv_thread_create_ex(uv_thread_t *tid, const uv_thread_options_t *params,
+                        void (*entry)(void *arg), void *arg);
+int uv_thread_join(uv_thread_t *tid);
+
+static void worker(void *arg) {
+    report((const char *)arg);
+}
+
+int main(void) {
+    uv_thread_t t;
+    /* nothing attached yet: must pass through */
+    uv_thread_create(&t, worker, "unseeded");
+    uv_thread_join(&t);
+
+    seed();
+    if (uv_thread_create(&t, worker, "uv-thread") != 0)
+        return 1;
+    uv_thread_join(&t);
+    uv_thread_options_t options = {1, 1 << 20};
+    if (uv_thread_create_ex(&t, &options, worker, "uv-thread-ex") != 0)
+        return 1;
+    uv_thread_join(&t);
+    return 0;
+}
@@ -0,0 +1,64 @@
+#![cfg(feature = "sdk")]
+
+use opentelemetry::Context;
+use otel_posix_pseudo_propegator::SpanNameFilterProcessor;
+use std::thread;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{TraceContextExt, Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn test_fork_while_other_threads_use_the_shim() {
+        // with a filter configured every span start/end and wrapped create takes the filter lock
+        unsafe { std::env::set_var("OTEL_POSIX_PROP_SPAN_FILTER", "*") };
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(SpanNameFilterProcessor)
+            .build();
+        let tracer = provider.tracer("test");
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let busy: Vec<_> = (0..4)
+            .map(|_| {
+                let (stop, tracer) = (stop.clone(), tracer.clone());
+                thread::spawn(move || {
+                    while !stop.load(Ordering::Relaxed) {
+                        tracer.in_span("busy", |_| {
+                            thread::spawn(|| eprint!("")).join().unwrap();
+                        });
+                    }
+                })
+            })
+            .collect();
+
+        for _ in 0..50 {
+            let pid = unsafe { libc::fork() };
+            if pid == 0 {
+                // a deadlock in the child gets killed by the alarm instead of hanging the test
+                unsafe { libc::alarm(5) };
+                let ok = tracer.in_span("child", |cx| {
+                    let id = cx.span().span_context().span_id();
+                    let seen = thread::spawn(|| Context::current().span().span_context().span_id())
+                        .join()
+                        .unwrap();
+                    eprint!("");
+                    seen == id
+                });
+                unsafe { libc::_exit(if ok { 0 } else { 1 }) };
+            }
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+            assert!(
+                libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0,
+                "child failed with status {status:#x}"
+            );
+        }
+
+        stop.store(true, Ordering::Relaxed);
+        busy.into_iter().for_each(|t| t.join().unwrap());
+    }
+}
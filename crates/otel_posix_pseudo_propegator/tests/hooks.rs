@@ -0,0 +1,76 @@
+use std::cell::Cell;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+thread_local! {
+    static REQUEST_ID: Cell<u64> = const { Cell::new(0) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use otel_posix_pseudo_propegator::register_thread_hook;
+
+    type StateFn = unsafe extern "C" fn(*mut c_void, *mut c_void);
+
+    unsafe extern "C" {
+        // the C API, as declared in the generated header
+        fn otel_posix_register_hook(
+            capture: Option<unsafe extern "C" fn(*mut c_void) -> *mut c_void>,
+            restore: Option<StateFn>,
+            release: Option<StateFn>,
+            user: *mut c_void,
+        ) -> libc::c_int;
+    }
+
+    #[test]
+    fn test_rust_hook_carries_thread_local_without_a_span() {
+        register_thread_hook(|| {
+            let id = REQUEST_ID.get();
+            Some(Box::new(move || {
+                REQUEST_ID.set(id);
+                Box::new(())
+            }))
+        });
+
+        REQUEST_ID.set(42);
+        let seen = thread::spawn(|| REQUEST_ID.get()).join().unwrap();
+        assert_eq!(seen, 42);
+    }
+
+    static RESTORED: AtomicUsize = AtomicUsize::new(0);
+    static RELEASED: AtomicUsize = AtomicUsize::new(0);
+
+    unsafe extern "C" fn capture(user: *mut c_void) -> *mut c_void {
+        Box::into_raw(Box::new(unsafe { *(user as *const usize) })) as *mut c_void
+    }
+
+    unsafe extern "C" fn restore(state: *mut c_void, _user: *mut c_void) {
+        RESTORED.store(unsafe { *(state as *const usize) }, Ordering::SeqCst);
+    }
+
+    unsafe extern "C" fn release(state: *mut c_void, _user: *mut c_void) {
+        drop(unsafe { Box::from_raw(state as *mut usize) });
+        RELEASED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_c_hook_restores_and_releases() {
+        static VALUE: usize = 7;
+        let user = &VALUE as *const usize as *mut c_void;
+        let rc =
+            unsafe { otel_posix_register_hook(Some(capture), Some(restore), Some(release), user) };
+        assert_eq!(rc, 0);
+        assert_eq!(
+            unsafe { otel_posix_register_hook(None, None, None, user) },
+            -1
+        );
+
+        let released = RELEASED.load(Ordering::SeqCst);
+        thread::spawn(|| assert_eq!(RESTORED.load(Ordering::SeqCst), 7))
+            .join()
+            .unwrap();
+        assert!(RELEASED.load(Ordering::SeqCst) > released);
+    }
+}
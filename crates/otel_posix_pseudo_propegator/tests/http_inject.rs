@@ -0,0 +1,58 @@
+#![cfg(feature = "http-inject")]
+
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
+use std::os::fd::AsRawFd;
+
+// nothing else references the crate, and it has to be linked for its `send` to win
+extern crate otel_posix_pseudo_propegator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{TraceContextExt, Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    /// Sends `data` with a raw libc::send on a fresh connection and returns what arrived.
+    fn send_and_receive(data: &[u8]) -> (isize, Vec<u8>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+
+        let n = unsafe { libc::send(client.as_raw_fd(), data.as_ptr().cast(), data.len(), 0) };
+        drop(client);
+        let mut received = Vec::new();
+        server.read_to_end(&mut received).unwrap();
+        (n, received)
+    }
+
+    #[test]
+    fn test_request_head_gets_traceparent() {
+        let request = b"GET /orders HTTP/1.1\r\nHost: example\r\n\r\n";
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        tracer.in_span("http.client", |cx| {
+            let sc = cx.span().span_context().clone();
+            let (n, received) = send_and_receive(request);
+            assert_eq!(n as usize, request.len());
+
+            let expected = format!(
+                "GET /orders HTTP/1.1\r\ntraceparent: 00-{}-{}-01\r\nHost: example\r\n\r\n",
+                sc.trace_id(),
+                sc.span_id()
+            );
+            assert_eq!(String::from_utf8(received).unwrap(), expected);
+        });
+    }
+
+    #[test]
+    fn test_other_traffic_is_untouched() {
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        tracer.in_span("not-http", |_| {
+            let data = b"HELO mail.example\r\n\r\n";
+            assert_eq!(send_and_receive(data).1, data);
+        });
+        // no active span
+        let request = b"GET / HTTP/1.1\r\n\r\n";
+        assert_eq!(send_and_receive(request).1, request);
+    }
+}
@@ -0,0 +1,143 @@
+#![cfg(all(feature = "sdk", feature = "hook-join"))]
+
+use opentelemetry::trace::{TraceContextExt, Tracer};
+use opentelemetry::{Context, Value, global};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use std::ffi::c_void;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+// nothing else references the crate, and it has to be linked for its `pthread_join` to win
+extern crate otel_posix_pseudo_propegator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exporter() -> &'static InMemorySpanExporter {
+        static EXPORTER: OnceLock<InMemorySpanExporter> = OnceLock::new();
+        EXPORTER.get_or_init(|| {
+            // the config is read once, on the first wrapped pthread_create
+            unsafe { std::env::set_var("OTEL_POSIX_PROP_JOIN_EVENTS", "true") };
+            let exporter = InMemorySpanExporter::default();
+            let provider = SdkTracerProvider::builder()
+                .with_simple_exporter(exporter.clone())
+                .build();
+            global::set_tracer_provider(provider);
+            exporter
+        })
+    }
+
+    fn finished(name: &str) -> SpanData {
+        exporter()
+            .get_finished_spans()
+            .unwrap()
+            .into_iter()
+            .find(|s| s.name == name)
+            .unwrap_or_else(|| panic!("span {name} was not exported"))
+    }
+
+    fn attribute(span: &SpanData, key: &str) -> Option<Value> {
+        let event = span
+            .events
+            .iter()
+            .find(|e| e.name == "thread.join")
+            .expect("no thread.join event");
+        event
+            .attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| kv.value.clone())
+    }
+
+    #[test]
+    fn join_records_run_duration() {
+        exporter();
+        let tracer = global::tracer("test");
+        tracer.in_span("std-join", |_| {
+            thread::spawn(|| thread::sleep(Duration::from_millis(30)))
+                .join()
+                .unwrap();
+        });
+
+        let span = finished("std-join");
+        let Some(Value::F64(run)) = attribute(&span, "otel_posix.thread.run_duration") else {
+            panic!("no run duration");
+        };
+        assert!(run >= 0.03, "ran for {run}s");
+        assert!(matches!(
+            attribute(&span, "otel_posix.join.wait"),
+            Some(Value::F64(_))
+        ));
+        assert!(matches!(attribute(&span, "thread.id"), Some(Value::I64(_))));
+        // std's thread main returns nothing through the pthread exit value
+        assert_eq!(
+            attribute(&span, "otel_posix.thread.exit_value.present"),
+            Some(Value::Bool(false))
+        );
+    }
+
+    unsafe extern "C" {
+        // not exposed by the libc crate
+        fn pthread_timedjoin_np(
+            tid: libc::pthread_t,
+            retval: *mut *mut c_void,
+            abstime: *const libc::timespec,
+        ) -> libc::c_int;
+    }
+
+    extern "C" fn returns_value(arg: *mut c_void) -> *mut c_void {
+        arg
+    }
+
+    #[test]
+    fn timed_join_reports_exit_value() {
+        exporter();
+        let tracer = global::tracer("test");
+        let _guard = Context::current_with_span(tracer.start("timed-join")).attach();
+        let mut tid: libc::pthread_t = 0;
+        let rc = unsafe {
+            libc::pthread_create(
+                &mut tid,
+                std::ptr::null(),
+                returns_value,
+                std::ptr::dangling_mut(),
+            )
+        };
+        assert_eq!(rc, 0);
+        let mut deadline = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(libc::CLOCK_REALTIME, &mut deadline) };
+        deadline.tv_sec += 5;
+        let mut retval = std::ptr::null_mut();
+        let rc = unsafe { pthread_timedjoin_np(tid, &mut retval, &deadline) };
+        assert_eq!(rc, 0);
+        Context::current().span().end();
+
+        let span = finished("timed-join");
+        assert_eq!(
+            attribute(&span, "otel_posix.thread.exit_value.present"),
+            Some(Value::Bool(true))
+        );
+    }
+
+    #[test]
+    fn detached_threads_get_no_event() {
+        exporter();
+        let tracer = global::tracer("test");
+        tracer.in_span("detached", |cx| {
+            let (tx, rx) = std::sync::mpsc::channel();
+            // dropping the handle detaches the thread
+            drop(thread::spawn(move || tx.send(()).unwrap()));
+            rx.recv().unwrap();
+            // the detached thread may still hold the context, so don't wait for it to drop
+            cx.span().end();
+        });
+
+        let span = finished("detached");
+        assert!(span.events.iter().all(|e| e.name != "thread.join"));
+    }
+}
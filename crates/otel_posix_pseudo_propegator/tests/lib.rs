@@ -1,59 +1,85 @@
-// in src/lib.rs (or wherever your spawn_with_otel lives)
-use opentelemetry::Context;
+use opentelemetry::global;
+use opentelemetry::trace::Tracer;
+use otel_posix_pseudo_propegator::test_support::{self, assert_child_of, finished_span};
+use otel_posix_pseudo_propegator::{PropagatingBuilder, spawn_with_otel};
 use std::thread::{self};
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use opentelemetry::{
-        global,
-        trace::{TraceContextExt, Tracer},
-    };
-    use std::{env, sync::mpsc::channel};
+    use opentelemetry::trace::SpanId;
+
+    /// Starts and ends a span named `name` in the calling thread, under whatever context
+    /// it has.
+    fn child_span(name: &'static str) {
+        global::tracer("test").in_span(name, |_| {});
+    }
 
     #[test]
     fn test_spawn_with_otel_propagates_context() {
-        // 0. Set LD_PRELOAD to the path of the otel_posix_pseudo_propegator library
-        // This is typically done outside of the test, in the environment setup.
-        unsafe {
-            env::set_var(
-                "LD_PRELOAD",
-                format!(
-                    "{}:{}",
-                    env!("CARGO_MANIFEST_DIR"),
-                    std::env::var("LD_PRELOAD").unwrap_or_default()
-                ),
-            );
-        }
-
-        // 1. Install a simple in-memory tracer provider
-        let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder().build();
-        global::set_tracer_provider(provider);
-        let tracer = global::tracer("test");
-
-        // 2. Start a span in the parent and attach it
-        let span = tracer.start("parent-span");
-        let cx = Context::current_with_span(span);
-        // 4. Assert they match
-        let parent_span_id = cx.span().span_context().span_id();
-        let _parent_guard = cx.attach();
-
-        // 3. Spawn a thread that reads the current span ID
-        let (tx, rx) = channel();
-        let handle = thread::spawn(move || {
-            // In the child thread, `Context::current()` should give us the same span
-            let binding = Context::current();
-            let child_span = binding.span();
-            tx.send(child_span.span_context().span_id()).unwrap();
+        let exporter = test_support::install();
+
+        // a span started in the child thread must be parented under the creator's
+        global::tracer("test").in_span("spawn-parent", |_| {
+            spawn_with_otel(|| child_span("spawn-child"))
+                .join()
+                .unwrap();
+        });
+
+        assert_child_of(
+            &finished_span(&exporter, "spawn-parent"),
+            &finished_span(&exporter, "spawn-child"),
+        );
+    }
+
+    #[test]
+    fn test_propagating_builder_keeps_name_and_context() {
+        let exporter = test_support::install();
+
+        let name = global::tracer("test").in_span("builder-parent", |_| {
+            PropagatingBuilder::new()
+                .name("otel-worker".into())
+                .spawn(|| {
+                    child_span("builder-child");
+                    thread::current().name().map(String::from)
+                })
+                .unwrap()
+                .join()
+                .unwrap()
+        });
+
+        assert_eq!(name.as_deref(), Some("otel-worker"));
+        assert_child_of(
+            &finished_span(&exporter, "builder-parent"),
+            &finished_span(&exporter, "builder-child"),
+        );
+    }
+
+    #[test]
+    fn test_thread_spawn_is_interposed_when_linked() {
+        // linking the rlib pulls in the pthread_create interposer, so even a
+        // plain std::thread::spawn should inherit the context
+        let exporter = test_support::install();
+
+        global::tracer("test").in_span("linked-parent", |_| {
+            thread::spawn(|| child_span("linked-child")).join().unwrap();
         });
 
-        handle.join().unwrap();
-        let child_span_id = rx.recv().unwrap();
+        assert_child_of(
+            &finished_span(&exporter, "linked-parent"),
+            &finished_span(&exporter, "linked-child"),
+        );
+    }
+
+    #[test]
+    fn test_no_active_span_passes_through() {
+        let exporter = test_support::install();
+
+        spawn_with_otel(|| child_span("orphan")).join().unwrap();
 
-        // 4. Assert they match
         assert_eq!(
-            child_span_id, parent_span_id,
-            "OTEL Context was not propagated into the child thread"
+            finished_span(&exporter, "orphan").parent_span_id,
+            SpanId::INVALID
         );
     }
 }
@@ -0,0 +1,144 @@
+#![cfg(feature = "sdk")]
+
+use opentelemetry::trace::{TraceContextExt, Tracer};
+use opentelemetry::{Context, global};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use otel_posix_pseudo_propegator::metrics::counts;
+use std::ffi::c_void;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::SpanId;
+
+    fn exporter() -> &'static InMemorySpanExporter {
+        static EXPORTER: OnceLock<InMemorySpanExporter> = OnceLock::new();
+        EXPORTER.get_or_init(|| {
+            // the config is read once, on the first wrapped pthread_create
+            unsafe { std::env::set_var("OTEL_POSIX_PROP_MODE", "lifetime") };
+            let exporter = InMemorySpanExporter::default();
+            let provider = SdkTracerProvider::builder()
+                .with_simple_exporter(exporter.clone())
+                .build();
+            global::set_tracer_provider(provider);
+            exporter
+        })
+    }
+
+    fn thread_span(span_id: SpanId) -> SpanData {
+        exporter()
+            .get_finished_spans()
+            .unwrap()
+            .into_iter()
+            .find(|s| s.span_context.span_id() == span_id)
+            .expect("thread span was not ended")
+    }
+
+    #[test]
+    fn test_span_covers_create_to_exit() {
+        exporter();
+        let tracer = global::tracer("test");
+        let wrapped = counts().wrapped;
+        let before = SystemTime::now();
+        let (parent, child) = tracer.in_span("spawner", |cx| {
+            let child = thread::spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                Context::current().span().span_context().span_id()
+            })
+            .join()
+            .unwrap();
+            (cx.span().span_context().span_id(), child)
+        });
+        let after = SystemTime::now();
+
+        assert_eq!(counts().wrapped, wrapped + 1);
+        let span = thread_span(child);
+        assert_eq!(span.name, "thread");
+        assert_eq!(
+            span.instrumentation_scope.name(),
+            "otel_posix_pseudo_propegator"
+        );
+        assert_eq!(
+            span.instrumentation_scope.version(),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(span.parent_span_id, parent);
+        assert!(before <= span.start_time && span.end_time <= after);
+        let ran = span.end_time.duration_since(span.start_time).unwrap();
+        assert!(ran >= Duration::from_millis(20), "span lasted {ran:?}");
+        for key in [
+            "otel_posix.thread.cpu.user",
+            "otel_posix.thread.cpu.system",
+            "otel_posix.thread.context_switches.voluntary",
+            "otel_posix.thread.context_switches.involuntary",
+            "otel_posix.thread.max_rss.delta",
+        ] {
+            assert!(
+                span.attributes.iter().any(|kv| kv.key.as_str() == key),
+                "no {key} in {:?}",
+                span.attributes
+            );
+        }
+    }
+
+    #[test]
+    fn test_span_has_the_requested_attributes() {
+        exporter();
+        let tracer = global::tracer("test");
+        let child = tracer.in_span("spawner", |_| {
+            thread::Builder::new()
+                .stack_size(256 * 1024)
+                .spawn(|| Context::current().span().span_context().span_id())
+                .unwrap()
+                .join()
+                .unwrap()
+        });
+        let span = thread_span(child);
+        for kv in [
+            opentelemetry::KeyValue::new("otel_posix.thread.stack_size", 256 * 1024),
+            opentelemetry::KeyValue::new("otel_posix.thread.detached", false),
+            opentelemetry::KeyValue::new("otel_posix.thread.sched_policy", "inherit"),
+        ] {
+            assert!(span.attributes.contains(&kv), "{:?}", span.attributes);
+        }
+    }
+
+    // pthread_exit force-unwinds through this frame, which a plain "C" fn would abort on
+    extern "C-unwind" fn exits_early(arg: *mut c_void) -> *mut c_void {
+        let out = unsafe { &mut *(arg as *mut SpanId) };
+        *out = Context::current().span().span_context().span_id();
+        unsafe { libc::pthread_exit(std::ptr::null_mut()) }
+    }
+
+    #[test]
+    fn test_span_ends_on_pthread_exit() {
+        exporter();
+        let tracer = global::tracer("test");
+        let child = tracer.in_span("exiter", |_| {
+            let mut seen = SpanId::INVALID;
+            let mut tid: libc::pthread_t = 0;
+            let arg = &mut seen as *mut SpanId as *mut c_void;
+            let start: extern "C" fn(*mut c_void) -> *mut c_void =
+                unsafe { std::mem::transmute(exits_early as extern "C-unwind" fn(_) -> _) };
+            let rc = unsafe { libc::pthread_create(&mut tid, std::ptr::null(), start, arg) };
+            assert_eq!(rc, 0);
+            assert_eq!(unsafe { libc::pthread_join(tid, std::ptr::null_mut()) }, 0);
+            seen
+        });
+        assert_ne!(child, SpanId::INVALID);
+        let span = thread_span(child);
+        assert_eq!(span.name, "thread");
+        // the test binary doesn't export exits_early, so only its object is known
+        let exe = std::env::current_exe().unwrap();
+        let exe = exe.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(
+            span.attributes
+                .contains(&opentelemetry::KeyValue::new("code.namespace", exe)),
+            "{:?}",
+            span.attributes
+        );
+    }
+}
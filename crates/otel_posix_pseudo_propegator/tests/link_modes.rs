@@ -0,0 +1,66 @@
+use opentelemetry::Context;
+use opentelemetry::trace::{SpanId, TraceContextExt, Tracer, TracerProvider};
+use std::ffi::c_void;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use otel_posix_pseudo_propegator::metrics::counts;
+
+    type CreateFn = unsafe extern "C" fn(
+        *mut libc::pthread_t,
+        *const libc::pthread_attr_t,
+        extern "C" fn(*mut c_void) -> *mut c_void,
+        *mut c_void,
+    ) -> i32;
+
+    extern "C" fn record_span_id(arg: *mut c_void) -> *mut c_void {
+        let out = unsafe { &mut *(arg as *mut SpanId) };
+        *out = Context::current().span().span_context().span_id();
+        std::ptr::null_mut()
+    }
+
+    /// Creates a raw pthread through `create` under an active span and returns
+    /// (parent span id, span id seen by the thread).
+    fn span_ids_through(create: CreateFn) -> (SpanId, SpanId) {
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        tracer.in_span("link-mode", |cx| {
+            let mut seen = SpanId::INVALID;
+            let mut tid: libc::pthread_t = 0;
+            let arg = &mut seen as *mut SpanId as *mut c_void;
+            assert_eq!(
+                unsafe { create(&mut tid, std::ptr::null(), record_span_id, arg) },
+                0
+            );
+            assert_eq!(unsafe { libc::pthread_join(tid, std::ptr::null_mut()) }, 0);
+            (cx.span().span_context().span_id(), seen)
+        })
+    }
+
+    #[cfg(feature = "preload")]
+    #[test]
+    fn test_preload_symbol_wraps_threads() {
+        let before = counts().wrapped;
+        let (parent, seen) = span_ids_through(otel_posix_pseudo_propegator::pthread_create);
+        assert_eq!(seen, parent);
+        assert!(counts().wrapped > before);
+    }
+
+    #[cfg(feature = "linker-wrap")]
+    #[test]
+    fn test_wrap_symbol_wraps_threads() {
+        let before = counts().wrapped;
+        let (parent, seen) = span_ids_through(otel_posix_pseudo_propegator::__wrap_pthread_create);
+        assert_eq!(seen, parent);
+        assert!(counts().wrapped > before);
+    }
+
+    #[test]
+    fn test_libc_pthread_create_is_routed_through_the_shim() {
+        // preload: our definition wins at static link time; linker-wrap: --wrap
+        // redirects the reference to __wrap_pthread_create
+        let (parent, seen) = span_ids_through(libc::pthread_create);
+        assert_eq!(seen, parent);
+    }
+}
@@ -0,0 +1,52 @@
+#![cfg(feature = "sdk")]
+
+use opentelemetry::Context;
+use opentelemetry::trace::{SpanContext, TraceContextExt, Tracer};
+use otel_posix_pseudo_propegator::metrics::counts;
+use std::thread;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::global;
+    use opentelemetry::trace::SpanId;
+    use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider};
+
+    #[test]
+    fn test_thread_gets_linked_root_span() {
+        // the config is read once, on the first wrapped pthread_create
+        unsafe { std::env::set_var("OTEL_POSIX_PROP_MODE", "links") };
+
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        global::set_tracer_provider(provider.clone());
+        let tracer = global::tracer("test");
+
+        let wrapped = counts().wrapped;
+        let (parent, child) = tracer.in_span("parent", |cx| {
+            let parent = cx.span().span_context().clone();
+            let child: SpanContext =
+                thread::spawn(|| Context::current().span().span_context().clone())
+                    .join()
+                    .unwrap();
+            (parent, child)
+        });
+
+        assert_eq!(counts().wrapped, wrapped + 1);
+        // the thread runs under a new trace rather than the creator's
+        assert!(child.is_valid());
+        assert_ne!(child.trace_id(), parent.trace_id());
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let thread_span = spans
+            .iter()
+            .find(|s| s.span_context.span_id() == child.span_id())
+            .expect("thread span was not ended");
+        assert_eq!(thread_span.name, "thread");
+        assert_eq!(thread_span.parent_span_id, SpanId::INVALID);
+        let links: Vec<_> = thread_span.links.iter().map(|l| &l.span_context).collect();
+        assert_eq!(links, [&parent]);
+    }
+}
@@ -0,0 +1,56 @@
+use opentelemetry::Context;
+use otel_posix_pseudo_propegator::{metrics, register_metrics};
+use std::thread;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry::trace::{TraceContextExt, Tracer, TracerProvider};
+    use opentelemetry_sdk::metrics::{InMemoryMetricExporter, SdkMeterProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    #[test]
+    fn test_interposition_metrics_are_exported() {
+        let exporter = InMemoryMetricExporter::default();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_periodic_exporter(exporter.clone())
+            .build();
+        register_metrics(&meter_provider.meter("test"));
+
+        let before = metrics::counts();
+
+        // one thread without a span, one under a span
+        thread::spawn(|| {}).join().unwrap();
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        let cx = Context::current_with_span(tracer.start("metrics-parent"));
+        let guard = cx.attach();
+        thread::spawn(|| {}).join().unwrap();
+        drop(guard);
+
+        let after = metrics::counts();
+        assert!(after.passed_through > before.passed_through);
+        assert!(after.wrapped > before.wrapped);
+
+        meter_provider.force_flush().unwrap();
+        let names: Vec<String> = exporter
+            .get_finished_metrics()
+            .unwrap()
+            .iter()
+            .flat_map(|rm| rm.scope_metrics())
+            .flat_map(|sm| sm.metrics())
+            .map(|m| m.name().to_string())
+            .collect();
+        for expected in [
+            "otel_posix.threads.wrapped",
+            "otel_posix.threads.passed_through",
+            "otel_posix.wrap.failures",
+            "otel_posix.trampoline.overhead",
+        ] {
+            assert!(
+                names.iter().any(|n| n == expected),
+                "{expected} missing from {names:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,85 @@
+#![cfg(all(feature = "mqueue-inject", target_os = "linux"))]
+
+use opentelemetry::Context;
+use std::ffi::CString;
+use std::thread;
+
+// nothing else references the crate, and it has to be linked for its `mq_send` to win
+extern crate otel_posix_pseudo_propegator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{TraceContextExt, TraceId, Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    /// Opens a fresh queue for messages of up to `msgsize` bytes.
+    fn queue(name: &str, msgsize: i64) -> libc::mqd_t {
+        let name = CString::new(format!("/otel-posix-{name}-{}", std::process::id())).unwrap();
+        let mut attr: libc::mq_attr = unsafe { std::mem::zeroed() };
+        attr.mq_maxmsg = 4;
+        attr.mq_msgsize = msgsize;
+        let mqd = unsafe {
+            libc::mq_open(
+                name.as_ptr(),
+                libc::O_CREAT | libc::O_EXCL | libc::O_RDWR,
+                0o600,
+                &attr,
+            )
+        };
+        assert_ne!(mqd, -1, "{}", std::io::Error::last_os_error());
+        unsafe { libc::mq_unlink(name.as_ptr()) };
+        mqd
+    }
+
+    /// Receives a message on a thread of its own, and returns it with the trace id the
+    /// thread is under afterwards.
+    fn receive(mqd: libc::mqd_t, msgsize: usize) -> (Vec<u8>, TraceId) {
+        thread::spawn(move || {
+            let mut buf = vec![0u8; msgsize];
+            let n = unsafe {
+                libc::mq_receive(
+                    mqd,
+                    buf.as_mut_ptr().cast(),
+                    buf.len(),
+                    std::ptr::null_mut(),
+                )
+            };
+            assert!(n >= 0, "{}", std::io::Error::last_os_error());
+            buf.truncate(n as usize);
+            let trace_id = Context::map_current(|cx| cx.span().span_context().trace_id());
+            (buf, trace_id)
+        })
+        .join()
+        .unwrap()
+    }
+
+    fn send(mqd: libc::mqd_t, msg: &[u8]) {
+        let rc = unsafe { libc::mq_send(mqd, msg.as_ptr().cast(), msg.len(), 0) };
+        assert_eq!(rc, 0, "{}", std::io::Error::last_os_error());
+    }
+
+    #[test]
+    fn test_context_travels_with_the_message() {
+        let mqd = queue("carry", 128);
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        let sent = tracer.in_span("mq.send", |cx| {
+            send(mqd, b"job 42");
+            cx.span().span_context().trace_id()
+        });
+        assert_eq!(receive(mqd, 128), (b"job 42".to_vec(), sent));
+        unsafe { libc::mq_close(mqd) };
+    }
+
+    #[test]
+    fn test_messages_without_room_or_span_are_untouched() {
+        let mqd = queue("untouched", 64);
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        // no room left for the trailer in a 64-byte queue
+        tracer.in_span("mq.send", |_| send(mqd, b"job 42"));
+        assert_eq!(receive(mqd, 64), (b"job 42".to_vec(), TraceId::INVALID));
+        send(mqd, b"no span");
+        assert_eq!(receive(mqd, 64), (b"no span".to_vec(), TraceId::INVALID));
+        unsafe { libc::mq_close(mqd) };
+    }
+}
@@ -0,0 +1,162 @@
+#![cfg(all(feature = "sdk", feature = "hook-once"))]
+
+use opentelemetry::trace::{SpanId, Status, TraceContextExt, Tracer};
+use opentelemetry::{Value, global};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use std::panic::AssertUnwindSafe;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+// nothing else references the crate, and it has to be linked for its `pthread_once` to win
+extern crate otel_posix_pseudo_propegator;
+
+// the libc crate declares it with the C ABI, which a panic can't leave through
+unsafe extern "C-unwind" {
+    fn pthread_once(
+        control: *mut libc::pthread_once_t,
+        init: unsafe extern "C-unwind" fn(),
+    ) -> libc::c_int;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exporter() -> &'static InMemorySpanExporter {
+        static EXPORTER: OnceLock<InMemorySpanExporter> = OnceLock::new();
+        EXPORTER.get_or_init(|| {
+            // the config is read once, on the first pthread_once under a span
+            unsafe {
+                std::env::set_var("OTEL_POSIX_PROP_ONCE_EVENTS", "true");
+                std::env::set_var("OTEL_POSIX_PROP_ONCE_SPANS", "true");
+            }
+            let exporter = InMemorySpanExporter::default();
+            let provider = SdkTracerProvider::builder()
+                .with_simple_exporter(exporter.clone())
+                .build();
+            global::set_tracer_provider(provider);
+            exporter
+        })
+    }
+
+    fn finished() -> Vec<SpanData> {
+        exporter().get_finished_spans().unwrap()
+    }
+
+    /// The duration of each `pthread_once` event on `span`, and whether it unwound.
+    fn runs(span: &SpanData) -> Vec<(f64, bool)> {
+        span.events
+            .iter()
+            .filter(|e| e.name == "pthread_once")
+            .map(|e| {
+                let get = |key: &str| {
+                    e.attributes
+                        .iter()
+                        .find(|kv| kv.key.as_str() == key)
+                        .map(|kv| kv.value.clone())
+                };
+                let duration = match get("otel_posix.once.duration") {
+                    Some(Value::F64(duration)) => duration,
+                    other => panic!("no duration: {other:?}"),
+                };
+                (duration, get("otel_posix.once.unwound").is_some())
+            })
+            .collect()
+    }
+
+    fn once(control: &mut libc::pthread_once_t, init: unsafe extern "C-unwind" fn()) {
+        assert_eq!(unsafe { pthread_once(control, init) }, 0);
+    }
+
+    static mut OUTER: libc::pthread_once_t = libc::PTHREAD_ONCE_INIT;
+    static mut INNER: libc::pthread_once_t = libc::PTHREAD_ONCE_INIT;
+
+    extern "C-unwind" fn init_outer() {
+        std::thread::sleep(Duration::from_millis(20));
+        once(unsafe { &mut *std::ptr::addr_of_mut!(INNER) }, init_inner);
+    }
+
+    extern "C-unwind" fn init_inner() {}
+
+    #[test]
+    fn first_run_is_recorded_under_the_caller() {
+        exporter();
+        let outer = unsafe { &mut *std::ptr::addr_of_mut!(OUTER) };
+        global::tracer("test").in_span("first", |_| {
+            once(outer, init_outer);
+            // done already
+            once(outer, init_outer);
+        });
+        global::tracer("test").in_span("second", |_| once(outer, init_outer));
+
+        let spans = finished();
+        let find = |name: &str| spans.iter().find(|s| s.name == name).unwrap();
+        let first = find("first");
+        let outer = runs(first);
+        assert_eq!(outer.len(), 1);
+        assert!(outer[0].0 >= 0.02 && !outer[0].1);
+        assert!(runs(find("second")).is_empty());
+
+        // the nested routine ran under the outer one's span
+        let span = spans
+            .iter()
+            .find(|s| s.name == "pthread_once" && s.parent_span_id == first.span_context.span_id())
+            .unwrap();
+        assert_eq!(runs(span).len(), 1);
+        assert_eq!(span.status, Status::Unset);
+        assert!(
+            span.attributes
+                .iter()
+                .any(|kv| kv.key.as_str() == "code.namespace")
+        );
+    }
+
+    static mut THROWS: libc::pthread_once_t = libc::PTHREAD_ONCE_INIT;
+    static mut THROWN: bool = false;
+
+    extern "C-unwind" fn init_throws() {
+        if !unsafe { std::mem::replace(&mut *std::ptr::addr_of_mut!(THROWN), true) } {
+            panic!("init failed");
+        }
+    }
+
+    #[test]
+    fn unwinding_runs_again() {
+        exporter();
+        let control = unsafe { &mut *std::ptr::addr_of_mut!(THROWS) };
+        let cx = global::tracer("test").in_span("unwinds", |cx| {
+            let unwound = std::panic::catch_unwind(AssertUnwindSafe(|| once(control, init_throws)));
+            assert!(unwound.is_err());
+            // glibc resets the control, so it runs again
+            once(control, init_throws);
+            cx.span().span_context().clone()
+        });
+
+        let spans = finished();
+        let span = spans
+            .iter()
+            .find(|s| s.span_context.span_id() == cx.span_id())
+            .unwrap();
+        let unwound: Vec<bool> = runs(span).into_iter().map(|(_, unwound)| unwound).collect();
+        assert_eq!(unwound, [true, false]);
+        let statuses: Vec<&Status> = spans
+            .iter()
+            .filter(|s| s.name == "pthread_once" && s.parent_span_id == cx.span_id())
+            .map(|s| &s.status)
+            .collect();
+        assert_eq!(statuses, [&Status::error("unwound"), &Status::Unset]);
+    }
+
+    #[test]
+    fn nothing_without_a_span() {
+        exporter();
+        static mut CONTROL: libc::pthread_once_t = libc::PTHREAD_ONCE_INIT;
+        extern "C-unwind" fn init() {}
+        once(unsafe { &mut *std::ptr::addr_of_mut!(CONTROL) }, init);
+        assert!(
+            finished()
+                .iter()
+                .all(|s| s.name != "pthread_once" || s.parent_span_id != SpanId::INVALID)
+        );
+    }
+}
@@ -0,0 +1,59 @@
+// the instrumented cdylib can't be preloaded into the programs run here
+#![cfg(not(otel_posix_sanitize))]
+
+use std::process::Command;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn otel_preload() -> Command {
+        Command::new(env!("CARGO_BIN_EXE_otel-preload"))
+    }
+
+    #[test]
+    fn test_run_prepends_library_to_existing_preload() {
+        let out = otel_preload()
+            .args([
+                "run",
+                "--",
+                "sh",
+                "-c",
+                "echo \"$LD_PRELOAD|$OTEL_SERVICE_NAME\"",
+            ])
+            .env("LD_PRELOAD", "libc.so.6")
+            .env_remove("OTEL_SERVICE_NAME")
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+
+        let stdout = String::from_utf8(out.stdout).unwrap();
+        let (preload, service) = stdout.trim().split_once('|').unwrap();
+        let entries: Vec<&str> = preload.split(':').collect();
+        assert!(
+            entries[0].ends_with("/libotel_posix_pseudo_propegator.so"),
+            "{preload}"
+        );
+        assert_eq!(&entries[1..], ["libc.so.6"]);
+        assert_eq!(service, "sh");
+    }
+
+    #[test]
+    fn test_missing_command_exits_127() {
+        let status = otel_preload()
+            .args(["run", "--", "/nonexistent/otel-preload-target"])
+            .status()
+            .unwrap();
+        assert_eq!(status.code(), Some(127));
+    }
+
+    #[test]
+    fn test_usage_errors_exit_2() {
+        let status = otel_preload().args(["run", "mycmd"]).status().unwrap();
+        assert_eq!(status.code(), Some(2));
+    }
+}
@@ -0,0 +1,100 @@
+#![cfg(all(feature = "sdk", feature = "hook-popen"))]
+
+use opentelemetry::trace::{Status, TraceContextExt, Tracer};
+use opentelemetry::{Value, global};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use std::ffi::CString;
+use std::io::Read;
+use std::os::fd::FromRawFd;
+use std::sync::OnceLock;
+
+// nothing else references the crate, and it has to be linked for its `popen` to win
+extern crate otel_posix_pseudo_propegator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exporter() -> &'static InMemorySpanExporter {
+        static EXPORTER: OnceLock<InMemorySpanExporter> = OnceLock::new();
+        EXPORTER.get_or_init(|| {
+            // the config is read once, on the first child started under a span
+            unsafe { std::env::set_var("OTEL_POSIX_PROP_CHILD_SPANS", "true") };
+            let exporter = InMemorySpanExporter::default();
+            let provider = SdkTracerProvider::builder()
+                .with_simple_exporter(exporter.clone())
+                .build();
+            global::set_tracer_provider(provider);
+            exporter
+        })
+    }
+
+    fn attribute(span: &SpanData, key: &str) -> Option<Value> {
+        span.attributes
+            .iter()
+            .find(|kv| kv.key.as_str() == key)
+            .map(|kv| kv.value.clone())
+    }
+
+    /// Runs `command` with popen and returns what it printed and what pclose returned.
+    fn popen(command: &str) -> (String, i32) {
+        let command = CString::new(command).unwrap();
+        let stream = unsafe { libc::popen(command.as_ptr(), c"r".as_ptr()) };
+        assert!(!stream.is_null());
+        // read through a dup, the stream itself is pclose's
+        let fd = unsafe { libc::dup(libc::fileno(stream)) };
+        let mut out = String::new();
+        unsafe { std::fs::File::from_raw_fd(fd) }
+            .read_to_string(&mut out)
+            .unwrap();
+        let status = unsafe { libc::pclose(stream) };
+        (out, status)
+    }
+
+    #[test]
+    fn popen_runs_under_its_span() {
+        exporter();
+        let command = "echo \"$TRACEPARENT\"; exit 3";
+        let (parent, (out, status)) = global::tracer("test").in_span("parent", |cx| {
+            (cx.span().span_context().clone(), popen(command))
+        });
+        assert_eq!(status, 3 << 8);
+
+        let span = exporter()
+            .get_finished_spans()
+            .unwrap()
+            .into_iter()
+            .find(|s| s.name == "popen")
+            .expect("no popen span");
+        assert_eq!(span.parent_span_id, parent.span_id());
+        assert_eq!(
+            out.trim(),
+            format!(
+                "00-{}-{}-01",
+                span.span_context.trace_id(),
+                span.span_context.span_id()
+            )
+        );
+        assert_eq!(
+            attribute(&span, "process.command_line"),
+            Some(Value::from(command))
+        );
+        assert_eq!(attribute(&span, "process.exit.code"), Some(Value::I64(3)));
+        assert_eq!(span.status, Status::error("exited with 3"));
+    }
+
+    #[test]
+    fn nothing_without_a_span() {
+        exporter();
+        let command = "echo \"${TRACEPARENT:-none}\"";
+        let (out, status) = popen(command);
+        assert_eq!((out.trim(), status), ("none", 0));
+        assert!(
+            exporter()
+                .get_finished_spans()
+                .unwrap()
+                .iter()
+                .all(|s| attribute(s, "process.command_line") != Some(Value::from(command)))
+        );
+    }
+}
@@ -0,0 +1,45 @@
+#![cfg(feature = "sdk")]
+
+use opentelemetry::Context;
+use otel_posix_pseudo_propegator::SpanNameFilterProcessor;
+use std::thread;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanId, TraceContextExt, Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    fn span_id_in_thread() -> SpanId {
+        thread::spawn(|| Context::current().span().span_context().span_id())
+            .join()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_only_matching_spans_are_propagated() {
+        // the config is read once, on the first wrapped pthread_create
+        unsafe { std::env::set_var("OTEL_POSIX_PROP_SPAN_FILTER", "http.*") };
+
+        let provider = SdkTracerProvider::builder()
+            .with_span_processor(SpanNameFilterProcessor)
+            .build();
+        let tracer = provider.tracer("test");
+
+        // a matching span is propagated
+        tracer.in_span("http.request", |cx| {
+            let id = cx.span().span_context().span_id();
+            assert_eq!(span_id_in_thread(), id);
+
+            // and so are its children, whatever their name
+            tracer.in_span("db.query", |cx| {
+                assert_eq!(span_id_in_thread(), cx.span().span_context().span_id());
+            });
+        });
+
+        // a non-matching span is not
+        tracer.in_span("background.tick", |_| {
+            assert_eq!(span_id_in_thread(), SpanId::INVALID);
+        });
+    }
+}
@@ -0,0 +1,110 @@
+// the staticlib linked into a C program with -Wl,--wrap, instead of preloaded
+#![cfg(all(target_os = "linux", feature = "linker-wrap", not(otel_posix_sanitize)))]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn manifest_dir() -> &'static Path {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+    }
+
+    /// The staticlib from this build, found like the cdylib in tests/fixtures.rs.
+    fn staticlib() -> PathBuf {
+        let exe = std::env::current_exe().unwrap();
+        let deps = exe.parent().unwrap();
+        [deps, deps.parent().unwrap()]
+            .iter()
+            .map(|dir| dir.join("libotel_posix_pseudo_propegator.a"))
+            .find(|lib| lib.exists())
+            .unwrap_or_else(|| panic!("no staticlib next to {}", exe.display()))
+    }
+
+    /// Links tests/fixtures/<name>.c against the staticlib, with `flags`.
+    fn link_static(name: &str, flags: &[&str]) -> PathBuf {
+        let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}-static"));
+        let status = cc::Build::new()
+            .cargo_metadata(false)
+            .opt_level(0)
+            .target(env!("OTEL_POSIX_TEST_TARGET"))
+            .host(env!("OTEL_POSIX_TEST_TARGET"))
+            .get_compiler()
+            .to_command()
+            .arg("-I")
+            .arg(manifest_dir().join("include"))
+            .arg(
+                manifest_dir()
+                    .join("tests/fixtures")
+                    .join(format!("{name}.c")),
+            )
+            .arg(staticlib())
+            .args(flags)
+            // the fixtures look the C API up with dlsym
+            .arg("-Wl,--export-dynamic")
+            // what rustc reports with --print native-static-libs
+            .args([
+                "-lgcc_s",
+                "-lutil",
+                "-lrt",
+                "-lpthread",
+                "-lm",
+                "-ldl",
+                "-lc",
+            ])
+            .arg("-o")
+            .arg(&out)
+            .status()
+            .unwrap();
+        assert!(status.success(), "failed to link fixture {name}");
+        out
+    }
+
+    /// Runs `exe` and returns its "<label> <traceparent>" lines, sorted.
+    fn run(exe: &Path) -> Vec<(String, String)> {
+        let out = Command::new(exe)
+            .env_remove("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .env_remove("LD_PRELOAD")
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "{} failed: {}",
+            exe.display(),
+            String::from_utf8_lossy(&out.stderr)
+        );
+        let mut lines: Vec<(String, String)> = String::from_utf8(out.stdout)
+            .unwrap()
+            .lines()
+            .filter_map(|l| l.split_once(' '))
+            .map(|(label, tp)| (label.to_string(), tp.to_string()))
+            .collect();
+        lines.sort();
+        lines
+    }
+
+    fn seen(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(l, tp)| (l.to_string(), tp.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_wrapped_static_link() {
+        let exe = link_static("raw_pthread", &["-Wl,--wrap=pthread_create"]);
+        assert_eq!(
+            run(&exe),
+            seen(&[
+                ("main", TRACEPARENT),
+                ("unseeded", "-"),
+                ("worker-a", TRACEPARENT),
+                ("worker-b", TRACEPARENT),
+            ])
+        );
+    }
+}
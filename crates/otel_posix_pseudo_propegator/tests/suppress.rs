@@ -0,0 +1,39 @@
+use opentelemetry::Context;
+use otel_posix_pseudo_propegator::suppress_wrapping;
+use std::thread;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{SpanId, TraceContextExt, Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    fn span_id_in_thread() -> SpanId {
+        thread::spawn(|| Context::current().span().span_context().span_id())
+            .join()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_suppress_guard_passes_threads_through() {
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        tracer.in_span("suppressed", |cx| {
+            let id = cx.span().span_context().span_id();
+            {
+                let _guard = suppress_wrapping();
+                assert_eq!(span_id_in_thread(), SpanId::INVALID);
+            }
+            assert_eq!(span_id_in_thread(), id);
+        });
+    }
+
+    #[test]
+    fn test_telemetry_suppressed_context_passes_threads_through() {
+        // what the SDK does around its own exports and worker loops
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        tracer.in_span("sdk-internal", |_| {
+            let _suppressed = Context::enter_telemetry_suppressed_scope();
+            assert_eq!(span_id_in_thread(), SpanId::INVALID);
+        });
+    }
+}
@@ -0,0 +1,92 @@
+#![cfg(all(
+    feature = "hook-syslog",
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+
+use std::io::{Read, Seek};
+use std::os::fd::AsRawFd;
+
+// nothing else references the crate, and it has to be linked for its `syslog` to win
+extern crate otel_posix_pseudo_propegator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{TraceContextExt, Tracer, TracerProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    #[cfg(target_env = "gnu")]
+    unsafe extern "C" {
+        fn __syslog_chk(priority: libc::c_int, flag: libc::c_int, format: *const libc::c_char, ...);
+    }
+
+    /// Runs `f` with stderr, where LOG_PERROR copies every message, going to a file, and
+    /// returns what was written.
+    fn stderr_of(f: impl FnOnce()) -> String {
+        let mut file = tempfile();
+        let saved = unsafe { libc::dup(2) };
+        unsafe { libc::dup2(file.as_raw_fd(), 2) };
+        f();
+        unsafe { libc::dup2(saved, 2) };
+        unsafe { libc::close(saved) };
+        let mut out = String::new();
+        file.rewind().unwrap();
+        file.read_to_string(&mut out).unwrap();
+        out
+    }
+
+    fn tempfile() -> std::fs::File {
+        let path = std::env::temp_dir().join(format!("otel-posix-syslog-{}", std::process::id()));
+        let file = std::fs::File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_messages_carry_the_trace() {
+        unsafe { libc::openlog(c"otel-test".as_ptr(), libc::LOG_PERROR, libc::LOG_USER) };
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+
+        let untraced = stderr_of(|| unsafe {
+            libc::syslog(libc::LOG_INFO, c"idle %d".as_ptr(), 7);
+        });
+        assert!(untraced.contains("idle 7\n"), "{untraced:?}");
+        assert!(!untraced.contains("trace_id="), "{untraced:?}");
+
+        tracer.in_span("job", |cx| {
+            let sc = cx.span().span_context().clone();
+            let fields = format!("trace_id={} span_id={}", sc.trace_id(), sc.span_id());
+
+            // integer, string and floating-point arguments all still reach the real syslog
+            let out = stderr_of(|| unsafe {
+                libc::syslog(
+                    libc::LOG_INFO,
+                    c"job %d %s %.1f\n".as_ptr(),
+                    42,
+                    c"done".as_ptr(),
+                    2.5f64,
+                );
+            });
+            assert!(
+                out.contains(&format!("job 42 done 2.5 {fields}\n")),
+                "{out:?}"
+            );
+
+            #[cfg(target_env = "gnu")]
+            {
+                let out = stderr_of(|| unsafe {
+                    __syslog_chk(libc::LOG_INFO, 1, c"fortified %d".as_ptr(), 9);
+                });
+                assert!(out.contains(&format!("fortified 9 {fields}")), "{out:?}");
+            }
+        });
+        unsafe { libc::closelog() };
+    }
+}
@@ -0,0 +1,75 @@
+#![cfg(feature = "macros")]
+
+use opentelemetry::Context;
+use opentelemetry::trace::{SpanId, TraceContextExt, Tracer, TracerProvider};
+use otel_posix_pseudo_propegator::{callback, otel_trampoline};
+use std::ffi::c_void;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+
+    fn span_id() -> SpanId {
+        Context::map_current(|cx| cx.span().span_context().span_id())
+    }
+
+    /// What the callbacks saw: the span they ran under, and their user data.
+    struct Seen {
+        span: SpanId,
+        value: i32,
+    }
+
+    // libevent's shape: the user data last
+    #[otel_trampoline]
+    extern "C" fn on_event(_fd: i32, _events: i16, seen: *mut Seen) {
+        let seen = unsafe { &mut *seen };
+        seen.span = span_id();
+        seen.value += 1;
+    }
+
+    // a GLib-style one-shot source, with the user data named
+    #[otel_trampoline(once, data = user)]
+    unsafe extern "C" fn on_idle(user: *mut c_void, _unused: *mut c_void) -> i32 {
+        let seen = unsafe { &mut *user.cast::<Seen>() };
+        seen.span = span_id();
+        0
+    }
+
+    #[test]
+    fn callbacks_run_under_the_registering_span() {
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        let mut seen = Seen {
+            span: SpanId::INVALID,
+            value: 0,
+        };
+        // kept like a C event loop keeps them, and run later, outside any span
+        let (registered, event, data) = tracer.in_span("register", |cx| {
+            let data = callback::capture((&raw mut seen).cast());
+            let event: extern "C" fn(i32, i16, *mut Seen) = on_event;
+            (cx.span().span_context().span_id(), event, data)
+        });
+        for _ in 0..2 {
+            event(3, 1, data.cast());
+            assert_eq!(span_id(), SpanId::INVALID);
+        }
+        unsafe { callback::release(data) };
+        assert_eq!(seen.span, registered);
+        assert_eq!(seen.value, 2);
+    }
+
+    #[test]
+    fn once_callbacks_free_their_capture() {
+        let tracer = SdkTracerProvider::builder().build().tracer("test");
+        let mut seen = Seen {
+            span: SpanId::INVALID,
+            value: 0,
+        };
+        let (registered, data) = tracer.in_span("idle", |cx| {
+            let data = callback::capture((&raw mut seen).cast());
+            (cx.span().span_context().span_id(), data)
+        });
+        assert_eq!(unsafe { on_idle(data, std::ptr::null_mut()) }, 0);
+        assert_eq!(seen.span, registered);
+    }
+}
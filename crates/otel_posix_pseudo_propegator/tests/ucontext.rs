@@ -0,0 +1,110 @@
+#![cfg(all(
+    target_os = "linux",
+    target_env = "gnu",
+    any(target_arch = "x86_64", target_arch = "aarch64"),
+    feature = "hook-ucontext"
+))]
+
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+use opentelemetry::{Context, ContextGuard};
+use std::cell::RefCell;
+use std::sync::Mutex;
+
+// nothing else references the crate, and it has to be linked for its `swapcontext` to win
+extern crate otel_posix_pseudo_propegator;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STACK_SIZE: usize = 256 * 1024;
+
+    // the scheduler's and the coroutine's ucontexts
+    struct Switch {
+        main: libc::ucontext_t,
+        coroutine: libc::ucontext_t,
+    }
+
+    static mut SWITCH: Option<Box<Switch>> = None;
+    static SEEN: Mutex<Vec<(&str, SpanId)>> = Mutex::new(Vec::new());
+
+    thread_local! {
+        static INNER: RefCell<Option<ContextGuard>> = const { RefCell::new(None) };
+    }
+
+    fn span_context(span_id: &str) -> SpanContext {
+        SpanContext::new(
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap(),
+            SpanId::from_hex(span_id).unwrap(),
+            TraceFlags::SAMPLED,
+            true,
+            TraceState::default(),
+        )
+    }
+
+    fn current_span_id() -> SpanId {
+        Context::map_current(|cx| cx.span().span_context().span_id())
+    }
+
+    fn record(label: &'static str) {
+        SEEN.lock().unwrap().push((label, current_span_id()));
+    }
+
+    fn switch() -> &'static mut Switch {
+        #[allow(static_mut_refs)]
+        unsafe {
+            SWITCH.as_mut().unwrap()
+        }
+    }
+
+    extern "C" fn coroutine() {
+        record("coroutine-start");
+        // attached inside the coroutine, must survive the switch away and back
+        let inner = Context::current().with_remote_span_context(span_context("00000000000000c2"));
+        INNER.with(|g| *g.borrow_mut() = Some(inner.attach()));
+        let s = switch();
+        unsafe { libc::swapcontext(&mut s.coroutine, &s.main) };
+        record("coroutine-resumed");
+        unsafe { libc::swapcontext(&mut s.coroutine, &s.main) };
+    }
+
+    #[test]
+    fn context_follows_coroutine_switches() {
+        let mut stack = vec![0u8; STACK_SIZE];
+        unsafe {
+            SWITCH = Some(Box::new(Switch {
+                main: std::mem::zeroed(),
+                coroutine: std::mem::zeroed(),
+            }));
+        }
+        let s = switch();
+        unsafe { libc::getcontext(&mut s.coroutine) };
+        s.coroutine.uc_stack.ss_sp = stack.as_mut_ptr().cast();
+        s.coroutine.uc_stack.ss_size = stack.len();
+
+        // created under the request's span, then scheduled from a context without one
+        let creator = Context::new().with_remote_span_context(span_context("00000000000000c1"));
+        {
+            let _creating = creator.attach();
+            unsafe { libc::makecontext(&mut s.coroutine, coroutine, 0) };
+        }
+        let main_span = current_span_id();
+
+        unsafe { libc::swapcontext(&mut s.main, &s.coroutine) };
+        record("main-after-first-switch");
+        unsafe { libc::swapcontext(&mut s.main, &s.coroutine) };
+        record("main-after-second-switch");
+
+        let c1 = SpanId::from_hex("00000000000000c1").unwrap();
+        let c2 = SpanId::from_hex("00000000000000c2").unwrap();
+        assert_eq!(
+            *SEEN.lock().unwrap(),
+            [
+                ("coroutine-start", c1),
+                ("main-after-first-switch", main_span),
+                ("coroutine-resumed", c2),
+                ("main-after-second-switch", main_span),
+            ]
+        );
+    }
+}
@@ -0,0 +1,203 @@
+// src/lib.rs
+//
+// #[otel_trampoline], re-exported by otel_posix_pseudo_propegator with its
+// `macros` feature. It turns an extern "C" callback into one that unwraps
+// its user data with otel_posix_pseudo_propegator::callback, so the body
+// runs under the Context the callback was registered in.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{FnArg, Ident, ItemFn, Pat, Type};
+
+/// Makes an `extern "C"` callback run under the `Context` it was registered in.
+///
+/// Register the callback with its user data wrapped by `callback::capture`; the callback
+/// gets the original data back, and its body runs with the registering `Context`
+/// attached. The user data is the last pointer parameter, or the one named with
+/// `data = name`. Free the wrapped data with `callback::release` once the callback won't
+/// run again, or mark a callback that runs exactly once with `once`, which frees it on
+/// the call.
+///
+/// ```ignore
+/// use otel_posix_pseudo_propegator::{callback, otel_trampoline};
+///
+/// #[otel_trampoline]
+/// extern "C" fn on_read(fd: c_int, events: c_short, arg: *mut c_void) {
+///     // under the Context current at event_new
+/// }
+///
+/// let ev = event_new(base, fd, EV_READ | EV_PERSIST, on_read, callback::capture(arg));
+/// ```
+#[proc_macro_attribute]
+pub fn otel_trampoline(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut args = Args::default();
+    let parser = syn::meta::parser(|meta| args.parse(meta));
+    syn::parse_macro_input!(attr with parser);
+    let item = syn::parse_macro_input!(item as ItemFn);
+    expand(&args, item)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+#[derive(Default)]
+struct Args {
+    // the callback runs once, and frees the captured data
+    once: bool,
+    // the user-data parameter, if not the last pointer
+    data: Option<Ident>,
+}
+
+impl Args {
+    fn parse(&mut self, meta: syn::meta::ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("once") {
+            self.once = true;
+            Ok(())
+        } else if meta.path.is_ident("data") {
+            self.data = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("expected `once` or `data = <parameter>`"))
+        }
+    }
+}
+
+fn expand(args: &Args, mut item: ItemFn) -> syn::Result<TokenStream2> {
+    let abi = item.sig.abi.as_ref().and_then(|abi| abi.name.as_ref());
+    if !abi.is_some_and(|name| matches!(name.value().as_str(), "C" | "C-unwind" | "system")) {
+        return Err(syn::Error::new(
+            item.sig.fn_token.span(),
+            "#[otel_trampoline] needs an `extern \"C\"` function",
+        ));
+    }
+    let (ident, mutability, ty) = data_param(args, &mut item)?;
+    let enter = if args.once {
+        quote!(enter_once)
+    } else {
+        quote!(enter)
+    };
+    // out of reach of the body
+    let guard = Ident::new("_guard", Span::mixed_site());
+    let data = Ident::new("data", Span::mixed_site());
+    let body = &item.block;
+    item.block = syn::parse_quote!({
+        let (#guard, #data) = unsafe {
+            ::otel_posix_pseudo_propegator::callback::#enter(
+                #ident as *mut ::std::ffi::c_void,
+            )
+        };
+        let #mutability #ident = #data as #ty;
+        #body
+    });
+    Ok(quote!(#item))
+}
+
+/// The user-data parameter's name, whether it is `mut`, and its type. A `mut` moves
+/// from the parameter to the unwrapped data.
+fn data_param(args: &Args, item: &mut ItemFn) -> syn::Result<(Ident, TokenStream2, Type)> {
+    let span = item.sig.inputs.span();
+    let mut params = item.sig.inputs.iter_mut().filter_map(|input| match input {
+        FnArg::Typed(typed) => Some(typed),
+        FnArg::Receiver(_) => None,
+    });
+    let param = match &args.data {
+        Some(name) => params
+            .rfind(|typed| matches!(&*typed.pat, Pat::Ident(p) if p.ident == *name))
+            .ok_or_else(|| syn::Error::new(name.span(), "no parameter with this name"))?,
+        None => params
+            .rfind(|typed| matches!(*typed.ty, Type::Ptr(_)))
+            .ok_or_else(|| {
+                syn::Error::new(
+                    span,
+                    "no pointer parameter for the user data; name it with `data = <parameter>`",
+                )
+            })?,
+    };
+    let ty = (*param.ty).clone();
+    let Pat::Ident(pat) = &mut *param.pat else {
+        return Err(syn::Error::new(
+            param.pat.span(),
+            "the user data parameter needs a name",
+        ));
+    };
+    let mutability = pat
+        .mutability
+        .take()
+        .map(|m| quote!(#m))
+        .unwrap_or_default();
+    Ok((pat.ident.clone(), mutability, ty))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expanded(args: Args, item: TokenStream2) -> Result<String, String> {
+        let item = syn::parse2(item).unwrap();
+        expand(&args, item)
+            .map(|tokens| tokens.to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn unwraps_the_last_pointer() {
+        let out = expanded(
+            Args::default(),
+            quote!(
+                extern "C" fn on_read(fd: i32, events: i16, mut arg: *mut Loop) {}
+            ),
+        )
+        .unwrap();
+        assert!(out.contains("callback :: enter (arg as * mut"), "{out}");
+        assert!(out.contains("let mut arg = data as * mut Loop"), "{out}");
+        assert!(out.contains("events : i16 , arg : * mut Loop)"), "{out}");
+    }
+
+    #[test]
+    fn named_data_and_once() {
+        let args = Args {
+            once: true,
+            data: Some(Ident::new("user", Span::call_site())),
+        };
+        let out = expanded(
+            args,
+            quote!(
+                unsafe extern "C" fn idle(user: *mut u8, other: *const u8) -> i32 {
+                    0
+                }
+            ),
+        )
+        .unwrap();
+        assert!(out.contains("callback :: enter_once (user as"), "{out}");
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_wrap() {
+        let rust = expanded(
+            Args::default(),
+            quote!(
+                fn f(p: *mut u8) {}
+            ),
+        );
+        assert!(rust.unwrap_err().contains("extern \"C\""));
+        let no_pointer = expanded(
+            Args::default(),
+            quote!(
+                extern "C" fn f(x: i32) {}
+            ),
+        );
+        assert!(no_pointer.unwrap_err().contains("no pointer parameter"));
+        let args = Args {
+            data: Some(Ident::new("missing", Span::call_site())),
+            ..Args::default()
+        };
+        let missing = expanded(
+            args,
+            quote!(
+                extern "C" fn f(p: *mut u8) {}
+            ),
+        );
+        assert!(missing.unwrap_err().contains("no parameter"));
+    }
+}
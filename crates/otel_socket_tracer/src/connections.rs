@@ -0,0 +1,107 @@
+// src/connections.rs
+//
+// The connections being recorded, by descriptor. As with the fd tracer's
+// paths, a bit per descriptor says whether there is one, so the closes of
+// everything else (files, pipes, untraced sockets) pass through without
+// taking the lock. Descriptors from 65536 up are never recorded.
+//
+// A connection ends when it has been shut down both ways, in one shutdown
+// or two, or when it is closed. One closed some other way (dup2 over it,
+// close_range) is dropped unrecorded when its number is handed out again.
+
+use crate::record::Connection;
+use libc::c_int;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+const MAX_FD: usize = 1 << 16;
+
+// SHUT_RD and SHUT_WR as bits
+const READ: u8 = 1;
+const WRITE: u8 = 2;
+
+static TRACKED: [AtomicU64; MAX_FD / 64] = [const { AtomicU64::new(0) }; MAX_FD / 64];
+static OPEN: Mutex<BTreeMap<c_int, (Connection, u8)>> = Mutex::new(BTreeMap::new());
+
+fn lock() -> MutexGuard<'static, BTreeMap<c_int, (Connection, u8)>> {
+    OPEN.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The word and bit saying whether `fd` is tracked, if it can be.
+fn bit(fd: c_int) -> Option<(&'static AtomicU64, u64)> {
+    let fd = usize::try_from(fd).ok().filter(|&fd| fd < MAX_FD)?;
+    Some((&TRACKED[fd / 64], 1 << (fd % 64)))
+}
+
+fn tracked(fd: c_int) -> bool {
+    bit(fd).is_some_and(|(word, bit)| word.load(Ordering::Acquire) & bit != 0)
+}
+
+/// Remembers `connection` as open on `fd`, returning the one `fd` had, if any.
+pub(crate) fn open(fd: c_int, connection: Connection) -> Option<Connection> {
+    let (word, bit) = bit(fd)?;
+    let replaced = lock().insert(fd, (connection, 0));
+    word.fetch_or(bit, Ordering::Release);
+    replaced.map(|(connection, _)| connection)
+}
+
+/// Forgets `fd`, returning its connection if it had one.
+pub(crate) fn forget(fd: c_int) -> Option<Connection> {
+    let (word, bit) = bit(fd)?;
+    if word.fetch_and(!bit, Ordering::AcqRel) & bit == 0 {
+        return None;
+    }
+    lock().remove(&fd).map(|(connection, _)| connection)
+}
+
+/// Notes that `fd` was shut down as `how` says, returning its connection, forgotten, if it
+/// is now shut down both ways.
+pub(crate) fn shut(fd: c_int, how: c_int) -> Option<Connection> {
+    if !tracked(fd) {
+        return None;
+    }
+    let how = match how {
+        libc::SHUT_RD => READ,
+        libc::SHUT_WR => WRITE,
+        libc::SHUT_RDWR => READ | WRITE,
+        _ => return None,
+    };
+    {
+        let mut open = lock();
+        let (_, shut) = open.get_mut(&fd)?;
+        *shut |= how;
+        if *shut != READ | WRITE {
+            return None;
+        }
+    }
+    forget(fd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::record::Side;
+    use otel_posix_propagator_sys::Traceparent;
+
+    #[test]
+    fn connections_end_when_shut_both_ways() {
+        let connection = || {
+            let peer = "127.0.0.1:80".parse().unwrap();
+            let tp = b"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+            Connection::new(Side::Client, 9000, peer, Traceparent::new(tp).unwrap(), 1)
+        };
+        assert!(open(9000, connection()).is_none());
+        assert!(shut(9000, libc::SHUT_WR).is_none());
+        assert!(shut(9000, libc::SHUT_RD).is_some());
+        assert!(!tracked(9000));
+        assert!(forget(9000).is_none());
+
+        assert!(open(9000, connection()).is_none());
+        assert!(open(9000, connection()).is_some());
+        assert!(shut(9000, libc::SHUT_RDWR).is_some());
+        assert!(shut(9001, libc::SHUT_RDWR).is_none());
+        assert!(open(MAX_FD as c_int, connection()).is_none());
+        assert!(!tracked(MAX_FD as c_int));
+    }
+}
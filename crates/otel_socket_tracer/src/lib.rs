@@ -0,0 +1,44 @@
+// src/lib.rs
+//
+// An LD_PRELOAD tracer that gives unmodified processes' network
+// connections a place in their traces, enough to see which services talk
+// to which. connect, accept and accept4 are interposed to note each TCP or
+// UDP connection a thread serving a trace makes or takes (socket.rs), and
+// shutdown and close to see it end; the connection is then recorded as a
+// client span for a connect and a server span for an accept, with the
+// peer's address and port and how long it lasted (record.rs).
+//
+// Only IPv4 and IPv6 sockets are recorded; Unix-domain ones, and the
+// connections of threads serving no trace, pass straight through. A
+// connection closed some other way than close or shutdown (dup2 over it,
+// close_range) is never recorded (connections.rs).
+
+mod connections;
+mod record;
+mod socket;
+
+use libc::{c_int, sockaddr, socklen_t};
+
+ld_interpose::interpose! {
+    socket {
+        /// Interposed `connect` that notes a connection made under a trace.
+        fn connect(fd: c_int, addr: *const sockaddr, len: socklen_t) -> c_int,
+            else unsafe { libc::syscall(libc::SYS_connect, fd, addr, len) as c_int };
+
+        /// Interposed `accept` that notes a connection taken under a trace.
+        fn accept(fd: c_int, addr: *mut sockaddr, len: *mut socklen_t) -> c_int,
+            else unsafe { libc::syscall(libc::SYS_accept4, fd, addr, len, 0) as c_int };
+
+        /// Interposed `accept4` that notes a connection taken under a trace.
+        fn accept4(fd: c_int, addr: *mut sockaddr, len: *mut socklen_t, flags: c_int) -> c_int,
+            else unsafe { libc::syscall(libc::SYS_accept4, fd, addr, len, flags) as c_int };
+
+        /// Interposed `shutdown` that records a connection shut down both ways.
+        fn shutdown(fd: c_int, how: c_int) -> c_int,
+            else unsafe { libc::syscall(libc::SYS_shutdown, fd, how) as c_int };
+
+        /// Interposed `close` that records a connection still open.
+        fn close(fd: c_int) -> c_int,
+            else unsafe { libc::syscall(libc::SYS_close, fd) as c_int };
+    }
+}
@@ -0,0 +1,135 @@
+// src/record.rs
+//
+// A connection, from when it was made or taken to when it ended, and its
+// recording: a span named for the call that started it, "connect" as the
+// client side or "accept" as the server side, under the span of the trace
+// the thread that started it was serving. It ends with the shutdown or
+// close that finished it, on whichever thread that was. A connect that
+// failed is recorded at once, with the error.
+
+use libc::c_int;
+use otel_posix_propagator_sys::{Attribute, Errno, Kind, Span, Traceparent, now};
+use std::ffi::{CStr, CString};
+use std::net::SocketAddr;
+
+fn seconds(nanos: u64) -> f64 {
+    nanos as f64 / 1e9
+}
+
+/// Which end of a connection this process is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Side {
+    Client,
+    Server,
+}
+
+/// A connection being recorded.
+#[derive(Debug)]
+pub(crate) struct Connection {
+    side: Side,
+    fd: c_int,
+    peer: SocketAddr,
+    local_port: Option<u16>,
+    transport: Option<&'static CStr>,
+    traceparent: Traceparent,
+    start: u64,
+    // how long a connect call took
+    connecting: Option<u64>,
+}
+
+impl Connection {
+    /// A connection on `fd` to or from `peer`, started at `start` on a thread serving
+    /// `traceparent`.
+    pub(crate) fn new(
+        side: Side,
+        fd: c_int,
+        peer: SocketAddr,
+        traceparent: Traceparent,
+        start: u64,
+    ) -> Self {
+        Connection {
+            side,
+            fd,
+            peer,
+            local_port: None,
+            transport: None,
+            traceparent,
+            start,
+            connecting: None,
+        }
+    }
+
+    /// The port this end is bound to, and the transport, `tcp` or `udp`.
+    pub(crate) fn local(mut self, port: Option<u16>, transport: Option<&'static CStr>) -> Self {
+        self.local_port = port;
+        self.transport = transport;
+        self
+    }
+
+    /// Notes that the connect call took until `at`.
+    pub(crate) fn connected(mut self, at: u64) -> Self {
+        self.connecting = Some(at.saturating_sub(self.start));
+        self
+    }
+
+    /// Records the connection as ended now by the call `by`.
+    pub(crate) fn end(self, by: &'static CStr) {
+        let end = now();
+        let duration = Attribute::double(
+            c"otel_posix.socket.duration",
+            seconds(end.saturating_sub(self.start)),
+        );
+        let by = Attribute::string(c"otel_posix.socket.closed_by", by);
+        self.record(end, vec![duration, by]);
+    }
+
+    /// Records a connect that failed with `errno`, described by `reason`.
+    pub(crate) fn failed(self, errno: c_int, reason: &CStr) {
+        let end = now();
+        let attributes = vec![
+            Attribute::int(c"otel_posix.socket.errno", errno.into()),
+            Attribute::string(c"error.type", reason),
+        ];
+        self.record(end, attributes);
+    }
+
+    fn record(&self, end: u64, attributes: Vec<Attribute>) {
+        let _errno = Errno::keep();
+        let address = CString::new(self.peer.ip().to_string()).expect("an address has no NUL");
+        // borrowing `address` as well
+        let mut attributes: Vec<Attribute> = attributes;
+        attributes.extend([
+            Attribute::string(c"network.peer.address", &address),
+            Attribute::int(c"network.peer.port", self.peer.port().into()),
+            Attribute::string(
+                c"network.type",
+                if self.peer.is_ipv4() {
+                    c"ipv4"
+                } else {
+                    c"ipv6"
+                },
+            ),
+            Attribute::int(c"otel_posix.socket.fd", self.fd.into()),
+        ]);
+        attributes.extend(
+            self.local_port
+                .map(|port| Attribute::int(c"network.local.port", port.into())),
+        );
+        attributes.extend(
+            self.transport
+                .map(|transport| Attribute::string(c"network.transport", transport)),
+        );
+        attributes.extend(
+            self.connecting.map(|nanos| {
+                Attribute::double(c"otel_posix.socket.connect_duration", seconds(nanos))
+            }),
+        );
+        let (name, kind) = match self.side {
+            Side::Client => (c"connect", Kind::Client),
+            Side::Server => (c"accept", Kind::Server),
+        };
+        Span::new(self.traceparent.as_cstr(), name, self.start, end)
+            .kind(kind)
+            .record(&attributes);
+    }
+}
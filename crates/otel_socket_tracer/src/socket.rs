@@ -0,0 +1,227 @@
+// src/socket.rs
+//
+// The interposed socket functions. connect and the accepts forward to the
+// real ones and, when the thread is serving a trace and the socket is an
+// IPv4 or IPv6 one, note the connection (connections.rs); shutdown and
+// close forward and end it (record.rs). Unix-domain and other sockets pass
+// through.
+//
+// A non-blocking connect returns EINPROGRESS; the connection is taken to
+// be on its way, and its connect_duration is only the call's. The peer of
+// an accepted connection is asked for with getpeername, since the caller
+// may not have passed anywhere to put it.
+
+use crate::connections;
+use crate::record::{Connection, Side};
+use libc::{c_int, sockaddr, sockaddr_in, sockaddr_in6, sockaddr_storage, socklen_t};
+use otel_posix_propagator_sys as propagator;
+use std::ffi::CStr;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+
+pub(crate) type ConnectFn = unsafe extern "C" fn(c_int, *const sockaddr, socklen_t) -> c_int;
+pub(crate) type AcceptFn = unsafe extern "C" fn(c_int, *mut sockaddr, *mut socklen_t) -> c_int;
+pub(crate) type Accept4Fn =
+    unsafe extern "C" fn(c_int, *mut sockaddr, *mut socklen_t, c_int) -> c_int;
+pub(crate) type ShutdownFn = unsafe extern "C" fn(c_int, c_int) -> c_int;
+pub(crate) type CloseFn = unsafe extern "C" fn(c_int) -> c_int;
+
+type NameFn = unsafe extern "C" fn(c_int, *mut sockaddr, *mut socklen_t) -> c_int;
+
+fn errno() -> c_int {
+    unsafe { *libc::__errno_location() }
+}
+
+/// The IPv4 or IPv6 address at `addr`, `len` bytes long.
+unsafe fn address(addr: *const sockaddr, len: socklen_t) -> Option<SocketAddr> {
+    let len = len as usize;
+    if addr.is_null() || len < size_of::<libc::sa_family_t>() {
+        return None;
+    }
+    match c_int::from(unsafe { (*addr).sa_family }) {
+        libc::AF_INET if len >= size_of::<sockaddr_in>() => {
+            let sin = unsafe { &*addr.cast::<sockaddr_in>() };
+            let ip = Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr));
+            Some(SocketAddr::from((ip, u16::from_be(sin.sin_port))))
+        }
+        libc::AF_INET6 if len >= size_of::<sockaddr_in6>() => {
+            let sin6 = unsafe { &*addr.cast::<sockaddr_in6>() };
+            let ip = Ipv6Addr::from(sin6.sin6_addr.s6_addr);
+            let port = u16::from_be(sin6.sin6_port);
+            Some(SocketAddrV6::new(ip, port, sin6.sin6_flowinfo, sin6.sin6_scope_id).into())
+        }
+        _ => None,
+    }
+}
+
+/// The address `name` (getsockname or getpeername) gives for `fd`.
+unsafe fn name(name: NameFn, fd: c_int) -> Option<SocketAddr> {
+    let mut storage: sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut len = size_of::<sockaddr_storage>() as socklen_t;
+    let addr = (&raw mut storage).cast::<sockaddr>();
+    if unsafe { name(fd, addr, &mut len) } != 0 {
+        return None;
+    }
+    unsafe { address(addr, len) }
+}
+
+/// The transport of the socket `fd`: `tcp` for a stream, `udp` for datagrams.
+fn transport(fd: c_int) -> Option<&'static CStr> {
+    let mut kind: c_int = 0;
+    let mut len = size_of::<c_int>() as socklen_t;
+    let got = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_TYPE,
+            (&raw mut kind).cast(),
+            &mut len,
+        )
+    };
+    match (got, kind) {
+        (0, libc::SOCK_STREAM) => Some(c"tcp"),
+        (0, libc::SOCK_DGRAM) => Some(c"udp"),
+        _ => None,
+    }
+}
+
+/// `connection` with this end's port and transport, read from `fd`.
+fn local(connection: Connection, fd: c_int) -> Connection {
+    let port = unsafe { name(libc::getsockname, fd) }.map(|addr| addr.port());
+    connection.local(port, transport(fd))
+}
+
+pub(crate) unsafe fn connect(
+    real: ConnectFn,
+    fd: c_int,
+    addr: *const sockaddr,
+    len: socklen_t,
+) -> c_int {
+    let Some(_busy) = propagator::enter() else {
+        return unsafe { real(fd, addr, len) };
+    };
+    let noted =
+        unsafe { address(addr, len) }.and_then(|peer| Some((peer, propagator::traceparent()?)));
+    let start = propagator::now();
+    let result = unsafe { real(fd, addr, len) };
+    let Some((peer, traceparent)) = noted else {
+        return result;
+    };
+    let errno = errno();
+    let connection =
+        Connection::new(Side::Client, fd, peer, traceparent, start).connected(propagator::now());
+    let connection = local(connection, fd);
+    if result == 0 || errno == libc::EINPROGRESS {
+        // connected again, as a datagram socket may be
+        if let Some(previous) = connections::open(fd, connection) {
+            previous.end(c"connect");
+        }
+    } else {
+        connection.failed(errno, unsafe { CStr::from_ptr(libc::strerror(errno)) });
+    }
+    unsafe { *libc::__errno_location() = errno };
+    result
+}
+
+/// Notes the connection `accept` returned, if it is one to record.
+fn accepted(accept: impl FnOnce() -> c_int) -> c_int {
+    let Some(_busy) = propagator::enter() else {
+        return accept();
+    };
+    let result = accept();
+    if result < 0 {
+        return result;
+    }
+    let errno = errno();
+    let noted = unsafe { name(libc::getpeername, result) }
+        .and_then(|peer| Some((peer, propagator::traceparent()?)));
+    if let Some((peer, traceparent)) = noted {
+        let connection =
+            Connection::new(Side::Server, result, peer, traceparent, propagator::now());
+        // one the number had was closed some other way, and goes unrecorded
+        let _ = connections::open(result, local(connection, result));
+    }
+    unsafe { *libc::__errno_location() = errno };
+    result
+}
+
+pub(crate) unsafe fn accept(
+    real: AcceptFn,
+    fd: c_int,
+    addr: *mut sockaddr,
+    len: *mut socklen_t,
+) -> c_int {
+    accepted(|| unsafe { real(fd, addr, len) })
+}
+
+pub(crate) unsafe fn accept4(
+    real: Accept4Fn,
+    fd: c_int,
+    addr: *mut sockaddr,
+    len: *mut socklen_t,
+    flags: c_int,
+) -> c_int {
+    accepted(|| unsafe { real(fd, addr, len, flags) })
+}
+
+pub(crate) unsafe fn shutdown(real: ShutdownFn, fd: c_int, how: c_int) -> c_int {
+    let result = unsafe { real(fd, how) };
+    if result == 0
+        && let Some(connection) = connections::shut(fd, how)
+        && let Some(_busy) = propagator::enter()
+    {
+        connection.end(c"shutdown");
+    }
+    result
+}
+
+pub(crate) unsafe fn close(real: CloseFn, fd: c_int) -> c_int {
+    // forgotten first: once closed, the number can be handed out to another thread
+    let connection = connections::forget(fd);
+    let result = unsafe { real(fd) };
+    if let Some(connection) = connection
+        && let Some(_busy) = propagator::enter()
+    {
+        connection.end(c"close");
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_are_read_by_family() {
+        let mut sin: sockaddr_in = unsafe { std::mem::zeroed() };
+        sin.sin_family = libc::AF_INET as libc::sa_family_t;
+        sin.sin_port = 8080u16.to_be();
+        sin.sin_addr.s_addr = u32::from(Ipv4Addr::new(10, 0, 0, 7)).to_be();
+        let v4 = (&raw const sin).cast::<sockaddr>();
+        let len = size_of::<sockaddr_in>() as socklen_t;
+        assert_eq!(
+            unsafe { address(v4, len) },
+            Some("10.0.0.7:8080".parse().unwrap())
+        );
+        // cut short
+        assert_eq!(unsafe { address(v4, len - 1) }, None);
+
+        let mut sin6: sockaddr_in6 = unsafe { std::mem::zeroed() };
+        sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+        sin6.sin6_port = 443u16.to_be();
+        sin6.sin6_addr.s6_addr = Ipv6Addr::LOCALHOST.octets();
+        let v6 = (&raw const sin6).cast::<sockaddr>();
+        assert_eq!(
+            unsafe { address(v6, size_of::<sockaddr_in6>() as socklen_t) },
+            Some("[::1]:443".parse().unwrap())
+        );
+
+        let mut unix: libc::sockaddr_un = unsafe { std::mem::zeroed() };
+        unix.sun_family = libc::AF_UNIX as libc::sa_family_t;
+        let unix = (&raw const unix).cast::<sockaddr>();
+        assert_eq!(
+            unsafe { address(unix, size_of::<libc::sockaddr_un>() as socklen_t) },
+            None
+        );
+        assert_eq!(unsafe { address(std::ptr::null(), len) }, None);
+    }
+}
@@ -0,0 +1,62 @@
+/* Connects to a listener of its own on the loopback interface, sends a line over the
+ * connection and reads it back, then shuts the client end down and closes both. Then
+ * connects to a port nothing listens on. Prints the line, the listener's port and the
+ * client's, and the port refused. */
+#include <arpa/inet.h>
+#include <errno.h>
+#include <netinet/in.h>
+#include <stdio.h>
+#include <string.h>
+#include <sys/socket.h>
+#include <unistd.h>
+
+#include "preload_testkit.h"
+
+int main(void) {
+    if (testkit_start() != 0)
+        return 1;
+    struct sockaddr_in addr = {.sin_family = AF_INET, .sin_addr.s_addr = htonl(INADDR_LOOPBACK)};
+    struct sockaddr_in local = addr, closed = addr;
+    socklen_t len = sizeof(addr);
+    int listener = socket(AF_INET, SOCK_STREAM, 0);
+    if (listener < 0 || bind(listener, (struct sockaddr *)&addr, len) != 0 ||
+        listen(listener, 1) != 0 || getsockname(listener, (struct sockaddr *)&addr, &len) != 0) {
+        perror("listen");
+        return 1;
+    }
+    int client = socket(AF_INET, SOCK_STREAM, 0);
+    if (client < 0 || connect(client, (struct sockaddr *)&addr, len) != 0 ||
+        getsockname(client, (struct sockaddr *)&local, &len) != 0) {
+        perror("connect");
+        return 1;
+    }
+    int server = accept(listener, NULL, NULL);
+    char line[6] = "";
+    if (server < 0 || write(client, "hello", 5) != 5 || read(server, line, 5) != 5) {
+        perror("echo");
+        return 1;
+    }
+    /* the client's end is over with the shutdown, the server's with the close */
+    shutdown(client, SHUT_RDWR);
+    close(server);
+    close(client);
+    close(listener);
+
+    /* bound and closed again without listening, so nothing is there */
+    int unused = socket(AF_INET, SOCK_STREAM, 0);
+    if (unused < 0 || bind(unused, (struct sockaddr *)&closed, len) != 0 ||
+        getsockname(unused, (struct sockaddr *)&closed, &len) != 0) {
+        perror("bind");
+        return 1;
+    }
+    close(unused);
+    int refused = socket(AF_INET, SOCK_STREAM, 0);
+    if (connect(refused, (struct sockaddr *)&closed, len) == 0 || errno != ECONNREFUSED) {
+        fputs("connect wasn't refused\n", stderr);
+        return 1;
+    }
+    close(refused);
+    printf("%s %d %d %d\n", line, ntohs(addr.sin_port), ntohs(local.sin_port),
+           ntohs(closed.sin_port));
+    return 0;
+}
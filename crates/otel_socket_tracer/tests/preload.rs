@@ -0,0 +1,68 @@
+#![cfg(target_os = "linux")]
+
+use preload_testkit::{Fixture, Run, SPAN, TRACE};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connections_are_recorded_from_both_ends() {
+        let program = Fixture::c("tests/fixtures/echo.c").compile();
+        let out = Run::traced(&program, "otel_socket_tracer")
+            .output()
+            .success();
+        let [line] = &out.lines()[..] else {
+            panic!("one line: {}", out.stdout);
+        };
+        let ports: Vec<i64> = line
+            .strip_prefix("hello ")
+            .unwrap()
+            .split(' ')
+            .map(|p| p.parse().unwrap())
+            .collect();
+        let [port, client_port, refused_port] = ports[..] else {
+            panic!("three ports: {line}");
+        };
+
+        let spans = out.spans();
+        let connects: Vec<_> = spans
+            .children_of(TRACE, SPAN)
+            .filter(|s| s.name == "connect")
+            .collect();
+        let connect = |port| {
+            connects
+                .iter()
+                .find(|s| s.int("network.peer.port") == Some(port))
+                .unwrap_or_else(|| panic!("no connect to {port} in\n{spans}"))
+        };
+        let client = connect(port);
+        assert_eq!(client.kind, "client");
+        assert_eq!(client.str("network.peer.address"), Some("127.0.0.1"));
+        assert_eq!(client.int("network.local.port"), Some(client_port));
+        assert_eq!(client.str("network.type"), Some("ipv4"));
+        assert_eq!(client.str("network.transport"), Some("tcp"));
+        assert_eq!(client.str("otel_posix.socket.closed_by"), Some("shutdown"));
+        for key in [
+            "otel_posix.socket.duration",
+            "otel_posix.socket.connect_duration",
+        ] {
+            assert!(client.float(key).is_some_and(|d| d >= 0.0), "{client}");
+        }
+
+        let accept = spans.child(TRACE, SPAN, "accept");
+        assert_eq!(accept.kind, "server");
+        assert_eq!(accept.int("network.peer.port"), Some(client_port));
+        assert_eq!(accept.int("network.local.port"), Some(port), "{accept}");
+        assert_eq!(accept.str("otel_posix.socket.closed_by"), Some("close"));
+        assert_eq!(accept.float("otel_posix.socket.connect_duration"), None);
+
+        let refused = connect(refused_port);
+        assert_eq!(
+            refused.int("otel_posix.socket.errno"),
+            Some(libc::ECONNREFUSED.into())
+        );
+        assert!(refused.str("error.type").is_some(), "{refused}");
+        assert_eq!(refused.str("otel_posix.socket.closed_by"), None);
+    }
+}
@@ -0,0 +1,82 @@
+// src/exec.rs
+//
+// The interposed sqlite3_exec, which prepares, steps and finalizes each
+// statement in its string itself: one run for the lot, recorded when it
+// returns. The rows are counted by standing in for the program's
+// callback, which is then called as it would have been; without a
+// callback there is no telling how many there were, and the span doesn't
+// say.
+//
+// Statements the callback runs on the same thread aren't recorded, the
+// thread being busy with the exec.
+
+use crate::record::{Query, Run};
+use crate::sqlite::Db;
+use libc::{c_char, c_int, c_void};
+use otel_posix_propagator_sys as propagator;
+
+pub(crate) type Callback =
+    unsafe extern "C" fn(*mut c_void, c_int, *mut *mut c_char, *mut *mut c_char) -> c_int;
+pub(crate) type ExecFn = unsafe extern "C" fn(
+    Db,
+    *const c_char,
+    Option<Callback>,
+    *mut c_void,
+    *mut *mut c_char,
+) -> c_int;
+
+/// The program's callback and its argument, and the rows it has been called with.
+struct Rows {
+    callback: Callback,
+    arg: *mut c_void,
+    count: i64,
+}
+
+/// Counts a row, then hands it to the program's callback.
+unsafe extern "C" fn count_row(
+    rows: *mut c_void,
+    columns: c_int,
+    values: *mut *mut c_char,
+    names: *mut *mut c_char,
+) -> c_int {
+    let rows = unsafe { &mut *rows.cast::<Rows>() };
+    rows.count += 1;
+    unsafe { (rows.callback)(rows.arg, columns, values, names) }
+}
+
+pub(crate) unsafe fn sqlite3_exec(
+    real: ExecFn,
+    db: Db,
+    sql: *const c_char,
+    callback: Option<Callback>,
+    arg: *mut c_void,
+    errmsg: *mut *mut c_char,
+) -> c_int {
+    let Some(_busy) = propagator::enter() else {
+        return unsafe { real(db, sql, callback, arg, errmsg) };
+    };
+    let Some(mut run) = Run::begin().filter(|_| !sql.is_null()) else {
+        return unsafe { real(db, sql, callback, arg, errmsg) };
+    };
+    let errno = unsafe { *libc::__errno_location() };
+    let query = Query::new(unsafe { std::ffi::CStr::from_ptr(sql) });
+    unsafe { *libc::__errno_location() = errno };
+    let result = match callback {
+        Some(callback) => {
+            let mut rows = Rows {
+                callback,
+                arg,
+                count: 0,
+            };
+            let result = unsafe { real(db, sql, Some(count_row), (&raw mut rows).cast(), errmsg) };
+            run.rows = Some(rows.count);
+            result
+        }
+        None => {
+            run.rows = None;
+            unsafe { real(db, sql, None, arg, errmsg) }
+        }
+    };
+    run.end(&query, db, Some(result));
+    result
+}
@@ -0,0 +1,106 @@
+// src/lib.rs
+//
+// An LD_PRELOAD tracer for services that keep their data in an embedded
+// SQLite database and can't be changed to trace their queries. Each
+// statement a thread serving a trace runs is recorded as a database client
+// span beneath the thread's span, with the statement's text, what it did
+// and the rows it returned (record.rs).
+//
+// A prepared statement's text is noted when sqlite3_prepare_v2 makes it
+// (statements.rs), and it runs from its first sqlite3_step to the step
+// that finishes it, or to the sqlite3_reset or sqlite3_finalize that cuts
+// it short (statement.rs); sqlite3_exec is a run of its own (exec.rs).
+// The text has its literals replaced with `?` by default (sanitize.rs), so
+// the values a program inlines into its SQL stay out of the trace;
+// OTEL_SQLITE_TRACER_SANITIZE=false records it as it is. SQLite's own
+// functions, looked up past this library, are in sqlite.rs.
+//
+// Statements prepared some other way (sqlite3_prepare_v3, the UTF-16 and
+// legacy versions) or before the library was loaded are never recorded,
+// and neither are the ones SQLite runs itself, reading the schema say.
+
+mod exec;
+mod record;
+mod sanitize;
+mod sqlite;
+mod statement;
+mod statements;
+
+use libc::{c_char, c_int, c_void};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static SANITIZE: AtomicBool = AtomicBool::new(true);
+
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+#[cfg_attr(target_os = "macos", unsafe(link_section = "__DATA,__mod_init_func"))]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(configure);
+}
+
+fn configure() {
+    let var = |name| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+    match var("OTEL_SQLITE_TRACER_SANITIZE").as_deref().map(str::trim) {
+        None | Some("true") => {}
+        Some("false") => set_sanitize(false),
+        Some(other) => eprintln!(
+            "otel_sqlite_tracer: invalid OTEL_SQLITE_TRACER_SANITIZE {other:?}, sanitizing"
+        ),
+    }
+}
+
+/// Sets whether the literals in a statement's text are replaced with `?` before it is
+/// recorded. Only statements prepared afterwards are affected.
+pub fn set_sanitize(sanitize: bool) {
+    SANITIZE.store(sanitize, Ordering::Relaxed);
+}
+
+fn sanitizing() -> bool {
+    SANITIZE.load(Ordering::Relaxed)
+}
+
+ld_interpose::interpose! {
+    statement {
+        /// Interposed `sqlite3_prepare_v2` that notes the statement's text.
+        fn sqlite3_prepare_v2(
+            db: *mut c_void,
+            sql: *const c_char,
+            bytes: c_int,
+            statement: *mut *mut c_void,
+            tail: *mut *const c_char,
+        ) -> c_int,
+            else sqlite::SQLITE_ERROR;
+
+        /// Interposed `sqlite3_step` that records a statement's run once it is done.
+        fn sqlite3_step(statement: *mut c_void) -> c_int,
+            else sqlite::SQLITE_MISUSE;
+
+        /// Interposed `sqlite3_reset` that records a run cut short.
+        fn sqlite3_reset(statement: *mut c_void) -> c_int,
+            else sqlite::SQLITE_MISUSE;
+
+        /// Interposed `sqlite3_finalize` that records a run cut short and forgets the
+        /// statement.
+        fn sqlite3_finalize(statement: *mut c_void) -> c_int,
+            else sqlite::SQLITE_MISUSE;
+    }
+    exec {
+        /// Interposed `sqlite3_exec` that records the statements it runs.
+        fn sqlite3_exec(
+            db: *mut c_void,
+            sql: *const c_char,
+            callback: Option<
+                unsafe extern "C" fn(*mut c_void, c_int, *mut *mut c_char, *mut *mut c_char) -> c_int,
+            >,
+            arg: *mut c_void,
+            errmsg: *mut *mut c_char,
+        ) -> c_int,
+            else sqlite::SQLITE_ERROR;
+    }
+}
@@ -0,0 +1,116 @@
+// src/record.rs
+//
+// A run of a statement, from its first step to its last, and its
+// recording. The run is only started on a thread serving a trace, and is
+// recorded once it is over as a client span beneath the span that thread
+// was serving, named for the statement's operation, or `sqlite` for a
+// batch of them, with the database it ran on and the rows it returned.
+//
+// The thread is marked busy for the length of an interposed call, so
+// SQLite calling back into its own API (an extension, a build that goes
+// through the dynamic symbols for its own calls) isn't recorded again.
+
+use crate::sanitize;
+use crate::sqlite::{self, Db, SQLITE_DONE, SQLITE_OK, SQLITE_ROW};
+use libc::c_int;
+use otel_posix_propagator_sys::{
+    self as propagator, Attribute, Errno, Kind, Span, Traceparent, now,
+};
+use std::ffi::{CStr, CString};
+
+/// What a span says about the SQL it ran.
+#[derive(Debug)]
+pub(crate) struct Query {
+    text: CString,
+    operation: Option<CString>,
+}
+
+impl Query {
+    /// The query for the text `sql`, sanitized if it is to be.
+    pub(crate) fn new(sql: &CStr) -> Query {
+        let sql = sql.to_string_lossy();
+        let text = if crate::sanitizing() {
+            sanitize::sanitize(&sql)
+        } else {
+            sql.as_ref().into()
+        };
+        Query {
+            text: CString::new(text.as_ref()).expect("text from a C string has no NUL"),
+            operation: sanitize::operation(&sql)
+                .map(|op| CString::new(op).expect("a keyword has no NUL")),
+        }
+    }
+}
+
+/// A run being recorded.
+#[derive(Debug)]
+pub(crate) struct Run {
+    pub(crate) traceparent: Traceparent,
+    pub(crate) start: u64,
+    // the rows returned so far, if they are counted
+    pub(crate) rows: Option<i64>,
+}
+
+impl Run {
+    /// Starts a run, if the thread is serving a trace.
+    pub(crate) fn begin() -> Option<Run> {
+        Some(Run {
+            traceparent: propagator::traceparent()?,
+            start: now(),
+            rows: Some(0),
+        })
+    }
+
+    /// Records the run of `query` on `db` as over now, having ended with `code`, or cut
+    /// short when that is `None`.
+    pub(crate) fn end(self, query: &Query, db: Db, code: Option<c_int>) {
+        let _errno = Errno::keep();
+        let end = now();
+        let mut attributes = vec![
+            Attribute::string(c"db.system.name", c"sqlite"),
+            Attribute::string(c"db.query.text", &query.text),
+        ];
+        attributes.extend(
+            query
+                .operation
+                .as_deref()
+                .map(|op| Attribute::string(c"db.operation.name", op)),
+        );
+        attributes.extend(sqlite::filename(db).map(|f| Attribute::string(c"db.namespace", f)));
+        attributes.extend(
+            self.rows
+                .map(|rows| Attribute::int(c"db.response.returned_rows", rows)),
+        );
+        let failed = code.filter(|&c| !matches!(c, SQLITE_OK | SQLITE_ROW | SQLITE_DONE));
+        let status = failed.map(|c| CString::new(c.to_string()).expect("a number has no NUL"));
+        if let (Some(code), Some(status)) = (failed, &status) {
+            attributes.push(Attribute::string(c"db.response.status_code", status));
+            attributes.push(Attribute::string(
+                c"error.type",
+                sqlite::errstr(code).unwrap_or(status),
+            ));
+        }
+
+        let name = query.operation.as_deref().unwrap_or(c"sqlite");
+        Span::new(self.traceparent.as_cstr(), name, self.start, end)
+            .kind(Kind::Client)
+            .record(&attributes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_are_sanitized_when_asked() {
+        let query = Query::new(c"select * from t where id = 7");
+        assert_eq!(&*query.text, c"select * from t where id = ?");
+        assert_eq!(query.operation.as_deref(), Some(c"SELECT"));
+        crate::set_sanitize(false);
+        let query = Query::new(c"BEGIN; DELETE FROM t WHERE id = 7");
+        crate::set_sanitize(true);
+        assert_eq!(&*query.text, c"BEGIN; DELETE FROM t WHERE id = 7");
+        assert_eq!(query.operation, None);
+    }
+}
@@ -0,0 +1,214 @@
+// src/sanitize.rs
+//
+// What the span says about a statement's text: the text itself, with its
+// literals replaced with `?` unless sanitizing is off, and the operation,
+// the keyword it starts with. A program that builds its SQL with the
+// values in rather than binding them would otherwise put them in the
+// trace, names, emails and tokens along with the numbers.
+//
+// SQLite has already parsed the text by the time it is read here, so this
+// only has to tell its literals apart from the rest: strings, blobs and
+// numbers are literals, while quoted names, parameters (`?1`, `:name`)
+// and comments are kept as they are.
+
+use std::borrow::Cow;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token {
+    /// A string, blob or number.
+    Literal,
+    /// A keyword or name, quoted or not.
+    Word,
+    /// Whitespace or a comment.
+    Space,
+    /// Anything else: punctuation, operators, parameters.
+    Other,
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || b >= 0x80
+}
+
+/// The end of the quoted run starting at `start` and closed by `close`, a doubled `close`
+/// standing for itself; an unclosed one runs to the end.
+fn quoted(sql: &[u8], start: usize, close: u8) -> usize {
+    let mut i = start + 1;
+    while i < sql.len() {
+        if sql[i] == close {
+            if sql.get(i + 1) == Some(&close) {
+                i += 2;
+                continue;
+            }
+            return i + 1;
+        }
+        i += 1;
+    }
+    sql.len()
+}
+
+/// The tokens of `sql`, with their text.
+fn tokens(sql: &str) -> impl Iterator<Item = (Token, &str)> {
+    let bytes = sql.as_bytes();
+    let mut i = 0;
+    std::iter::from_fn(move || {
+        let start = i;
+        let b = *bytes.get(i)?;
+        let next = bytes.get(i + 1).copied();
+        let token = match b {
+            b'\'' => {
+                i = quoted(bytes, i, b'\'');
+                Token::Literal
+            }
+            b'x' | b'X' if next == Some(b'\'') => {
+                i = quoted(bytes, i + 1, b'\'');
+                Token::Literal
+            }
+            b'"' | b'`' => {
+                i = quoted(bytes, i, b);
+                Token::Word
+            }
+            b'[' => {
+                i = bytes[i..]
+                    .iter()
+                    .position(|&c| c == b']')
+                    .map_or(bytes.len(), |n| i + n + 1);
+                Token::Word
+            }
+            b'-' if next == Some(b'-') => {
+                i = bytes[i..]
+                    .iter()
+                    .position(|&c| c == b'\n')
+                    .map_or(bytes.len(), |n| i + n + 1);
+                Token::Space
+            }
+            b'/' if next == Some(b'*') => {
+                i = bytes[i + 2..]
+                    .windows(2)
+                    .position(|w| w == b"*/")
+                    .map_or(bytes.len(), |n| i + 2 + n + 2);
+                Token::Space
+            }
+            b'0'..=b'9' => {
+                i = number(bytes, i);
+                Token::Literal
+            }
+            b'.' if next.is_some_and(|c| c.is_ascii_digit()) => {
+                i = number(bytes, i);
+                Token::Literal
+            }
+            // a numbered parameter, whose number isn't a literal
+            b'?' => {
+                i += 1;
+                while bytes.get(i).is_some_and(u8::is_ascii_digit) {
+                    i += 1;
+                }
+                Token::Other
+            }
+            _ if b.is_ascii_whitespace() => {
+                while bytes.get(i).is_some_and(u8::is_ascii_whitespace) {
+                    i += 1;
+                }
+                Token::Space
+            }
+            _ if is_word_byte(b) => {
+                while bytes.get(i).copied().is_some_and(is_word_byte) {
+                    i += 1;
+                }
+                Token::Word
+            }
+            _ => {
+                i += 1;
+                Token::Other
+            }
+        };
+        Some((token, &sql[start..i]))
+    })
+}
+
+/// The end of the number starting at `start`: digits, a fraction, an exponent, or a hex
+/// number's digits.
+fn number(sql: &[u8], start: usize) -> usize {
+    let mut i = start;
+    while let Some(&b) = sql.get(i) {
+        let sign = (b == b'+' || b == b'-')
+            && matches!(sql[i - 1], b'e' | b'E')
+            && !sql[start..].starts_with(b"0x")
+            && !sql[start..].starts_with(b"0X");
+        if !(b.is_ascii_alphanumeric() || b == b'.' || b == b'_' || sign) {
+            break;
+        }
+        i += 1;
+    }
+    i
+}
+
+/// `sql` with each of its literals replaced with `?`.
+pub(crate) fn sanitize(sql: &str) -> Cow<'_, str> {
+    if !tokens(sql).any(|(token, _)| token == Token::Literal) {
+        return Cow::Borrowed(sql);
+    }
+    Cow::Owned(
+        tokens(sql)
+            .map(|(token, text)| if token == Token::Literal { "?" } else { text })
+            .collect(),
+    )
+}
+
+/// The keyword `sql` starts with, in capitals, if it is a single statement.
+pub(crate) fn operation(sql: &str) -> Option<String> {
+    let mut tokens = tokens(sql).filter(|(token, _)| *token != Token::Space);
+    let (Token::Word, keyword) = tokens.next()? else {
+        return None;
+    };
+    if !keyword.bytes().all(|b| b.is_ascii_alphabetic()) {
+        return None;
+    }
+    // nothing but semicolons after the first one
+    let mut ended = false;
+    for (token, text) in tokens {
+        match (token, text) {
+            (Token::Other, ";") => ended = true,
+            _ if ended => return None,
+            _ => {}
+        }
+    }
+    Some(keyword.to_ascii_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literals_are_replaced() {
+        assert_eq!(
+            sanitize("SELECT * FROM users WHERE name = 'O''Brien' AND age > 42"),
+            "SELECT * FROM users WHERE name = ? AND age > ?"
+        );
+        assert_eq!(
+            sanitize("INSERT INTO t2 (a, \"b 1\") VALUES (x'00ff', -1.5e-3, 0x1F, .5)"),
+            "INSERT INTO t2 (a, \"b 1\") VALUES (?, -?, ?, ?)"
+        );
+        assert_eq!(
+            sanitize("UPDATE [t 3] SET c = ?1, d = :d -- 'kept'\nWHERE id = 7"),
+            "UPDATE [t 3] SET c = ?1, d = :d -- 'kept'\nWHERE id = ?"
+        );
+        assert!(matches!(
+            sanitize("SELECT col_1 FROM t1 WHERE id = ?"),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(sanitize("SELECT 'unclosed"), "SELECT ?");
+    }
+
+    #[test]
+    fn the_operation_is_the_first_keyword() {
+        assert_eq!(operation("  /* hi */ select 1;").as_deref(), Some("SELECT"));
+        assert_eq!(
+            operation("-- a\nINSERT INTO t VALUES (';')").as_deref(),
+            Some("INSERT")
+        );
+        assert_eq!(operation("BEGIN; INSERT INTO t VALUES (1); COMMIT"), None);
+        assert_eq!(operation("(SELECT 1)"), None);
+        assert_eq!(operation(""), None);
+    }
+}
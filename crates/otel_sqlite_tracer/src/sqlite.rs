@@ -0,0 +1,58 @@
+// src/sqlite.rs
+//
+// The parts of SQLite's C API this library uses beyond the functions it
+// interposes: the result codes, and the functions that say which database
+// a connection has open, the text a statement was prepared from and what a
+// code means. Those are SQLite's own, looked up past this library, and
+// only once a statement has been seen, so by then SQLite is loaded.
+
+use libc::{c_char, c_int, c_void};
+use std::ffi::CStr;
+use std::sync::atomic::AtomicPtr;
+
+/// A connection, `sqlite3 *`.
+pub(crate) type Db = *mut c_void;
+/// A prepared statement, `sqlite3_stmt *`.
+pub(crate) type Stmt = *mut c_void;
+
+pub(crate) const SQLITE_OK: c_int = 0;
+pub(crate) const SQLITE_ERROR: c_int = 1;
+pub(crate) const SQLITE_MISUSE: c_int = 21;
+pub(crate) const SQLITE_ROW: c_int = 100;
+pub(crate) const SQLITE_DONE: c_int = 101;
+
+type SqlFn = unsafe extern "C" fn(Stmt) -> *const c_char;
+type DbFilenameFn = unsafe extern "C" fn(Db, *const c_char) -> *const c_char;
+type ErrstrFn = unsafe extern "C" fn(c_int) -> *const c_char;
+
+static SQL: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static DB_FILENAME: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+static ERRSTR: AtomicPtr<c_void> = AtomicPtr::new(std::ptr::null_mut());
+
+/// SQLite's `symbol`, as an `F`.
+fn function<F>(cache: &AtomicPtr<c_void>, symbol: &'static CStr) -> Option<F> {
+    let f = ld_interpose::cached_next(cache, symbol)?;
+    Some(unsafe { std::mem::transmute_copy::<*mut c_void, F>(&f) })
+}
+
+/// The text `statement` was prepared from, which lasts as long as it does.
+pub(crate) fn sql<'a>(statement: Stmt) -> Option<&'a CStr> {
+    let f = function::<SqlFn>(&SQL, c"sqlite3_sql")?;
+    let s = unsafe { f(statement) };
+    (!s.is_null()).then(|| unsafe { CStr::from_ptr(s) })
+}
+
+/// The file of the main database `db` has open, which lasts as long as the connection; an
+/// in-memory or temporary database has none.
+pub(crate) fn filename<'a>(db: Db) -> Option<&'a CStr> {
+    let f = function::<DbFilenameFn>(&DB_FILENAME, c"sqlite3_db_filename")?;
+    let s = unsafe { f(db, c"main".as_ptr()) };
+    (!s.is_null() && unsafe { *s } != 0).then(|| unsafe { CStr::from_ptr(s) })
+}
+
+/// What SQLite says the result code `code` means.
+pub(crate) fn errstr(code: c_int) -> Option<&'static CStr> {
+    let f = function::<ErrstrFn>(&ERRSTR, c"sqlite3_errstr")?;
+    let s = unsafe { f(code) };
+    (!s.is_null()).then(|| unsafe { CStr::from_ptr(s) })
+}
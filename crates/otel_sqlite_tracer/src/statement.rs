@@ -0,0 +1,78 @@
+// src/statement.rs
+//
+// The interposed prepared-statement functions. sqlite3_prepare_v2 notes
+// the statement it made, with the text SQLite kept of it, which is the one
+// statement and not the rest of the program's string. sqlite3_step starts
+// a run on the first step and records it on the step that finishes it,
+// SQLITE_DONE or an error, counting the rows in between; sqlite3_reset and
+// sqlite3_finalize record a run that was cut short, a program reading only
+// the first row of a query being the usual case (statements.rs).
+
+use crate::record::{Query, Run};
+use crate::sqlite::{self, Db, SQLITE_OK, SQLITE_ROW, Stmt};
+use crate::statements;
+use libc::{c_char, c_int};
+use otel_posix_propagator_sys as propagator;
+
+pub(crate) type PrepareFn =
+    unsafe extern "C" fn(Db, *const c_char, c_int, *mut Stmt, *mut *const c_char) -> c_int;
+pub(crate) type StatementFn = unsafe extern "C" fn(Stmt) -> c_int;
+
+pub(crate) unsafe fn sqlite3_prepare_v2(
+    real: PrepareFn,
+    db: Db,
+    sql: *const c_char,
+    bytes: c_int,
+    statement: *mut Stmt,
+    tail: *mut *const c_char,
+) -> c_int {
+    let Some(_busy) = propagator::enter() else {
+        return unsafe { real(db, sql, bytes, statement, tail) };
+    };
+    let result = unsafe { real(db, sql, bytes, statement, tail) };
+    // null for a string of nothing but whitespace and comments
+    let prepared = match unsafe { statement.as_ref() } {
+        Some(&prepared) if result == SQLITE_OK && !prepared.is_null() => prepared,
+        _ => return result,
+    };
+    if let Some(text) = sqlite::sql(prepared) {
+        let errno = unsafe { *libc::__errno_location() };
+        statements::prepared(prepared, db, Query::new(text));
+        unsafe { *libc::__errno_location() = errno };
+    }
+    result
+}
+
+pub(crate) unsafe fn sqlite3_step(real: StatementFn, statement: Stmt) -> c_int {
+    let Some(_busy) = propagator::enter() else {
+        return unsafe { real(statement) };
+    };
+    if !statements::step(statement, Run::begin) {
+        return unsafe { real(statement) };
+    }
+    let result = unsafe { real(statement) };
+    if result == SQLITE_ROW {
+        statements::row(statement);
+    } else if let Some(ended) = statements::end(statement) {
+        ended.run.end(&ended.query, ended.db, Some(result));
+    }
+    result
+}
+
+pub(crate) unsafe fn sqlite3_reset(real: StatementFn, statement: Stmt) -> c_int {
+    if let Some(_busy) = propagator::enter()
+        && let Some(ended) = statements::end(statement)
+    {
+        ended.run.end(&ended.query, ended.db, None);
+    }
+    unsafe { real(statement) }
+}
+
+pub(crate) unsafe fn sqlite3_finalize(real: StatementFn, statement: Stmt) -> c_int {
+    if let Some(_busy) = propagator::enter()
+        && let Some(ended) = statements::forget(statement)
+    {
+        ended.run.end(&ended.query, ended.db, None);
+    }
+    unsafe { real(statement) }
+}
@@ -0,0 +1,137 @@
+// src/statements.rs
+//
+// What is known of each prepared statement: the query it was prepared
+// from, the connection it belongs to, and the run in progress on it, if
+// one is being recorded. A statement has an entry from sqlite3_prepare_v2
+// to sqlite3_finalize; one prepared some other way, or before the library
+// was loaded, has none and is never recorded.
+//
+// A run is started by the first step after the statement was prepared or
+// reset, and only then: a statement half way through its rows when its
+// thread starts serving a trace isn't recorded from the middle.
+
+use crate::record::{Query, Run};
+use crate::sqlite::{Db, Stmt};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+struct Statement {
+    query: Arc<Query>,
+    db: usize,
+    // between the first step and the last
+    stepping: bool,
+    run: Option<Run>,
+}
+
+static STATEMENTS: Mutex<BTreeMap<usize, Statement>> = Mutex::new(BTreeMap::new());
+
+fn lock() -> MutexGuard<'static, BTreeMap<usize, Statement>> {
+    STATEMENTS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A run that is over, with what is needed to record it.
+pub(crate) struct Ended {
+    pub(crate) run: Run,
+    pub(crate) query: Arc<Query>,
+    pub(crate) db: Db,
+}
+
+/// Notes `statement`, prepared on `db` from `query`.
+pub(crate) fn prepared(statement: Stmt, db: Db, query: Query) {
+    let entry = Statement {
+        query: Arc::new(query),
+        db: db as usize,
+        stepping: false,
+        run: None,
+    };
+    lock().insert(statement as usize, entry);
+}
+
+/// Notes a step of `statement`, starting a run with `begin` if it is the first; returns
+/// whether a run is being recorded.
+pub(crate) fn step(statement: Stmt, begin: impl FnOnce() -> Option<Run>) -> bool {
+    let mut statements = lock();
+    let Some(s) = statements.get_mut(&(statement as usize)) else {
+        return false;
+    };
+    if !s.stepping {
+        s.stepping = true;
+        s.run = begin();
+    }
+    s.run.is_some()
+}
+
+/// Counts a row returned by `statement`'s run.
+pub(crate) fn row(statement: Stmt) {
+    let mut statements = lock();
+    let run = statements
+        .get_mut(&(statement as usize))
+        .and_then(|s| s.run.as_mut());
+    if let Some(rows) = run.and_then(|run| run.rows.as_mut()) {
+        *rows += 1;
+    }
+}
+
+/// Ends `statement`'s run, which is over or being cut short, returning it if it was being
+/// recorded.
+pub(crate) fn end(statement: Stmt) -> Option<Ended> {
+    let mut statements = lock();
+    let s = statements.get_mut(&(statement as usize))?;
+    s.stepping = false;
+    Some(Ended {
+        run: s.run.take()?,
+        query: s.query.clone(),
+        db: s.db as Db,
+    })
+}
+
+/// Forgets `statement`, which is being finalized, returning its run if one was being
+/// recorded.
+pub(crate) fn forget(statement: Stmt) -> Option<Ended> {
+    let s = lock().remove(&(statement as usize))?;
+    Some(Ended {
+        run: s.run?,
+        query: s.query,
+        db: s.db as Db,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use otel_posix_propagator_sys::Traceparent;
+
+    const TP: &[u8] = b"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn a_run_starts_with_the_first_step() {
+        let (statement, db) = (0x1000 as Stmt, 0x2000 as Db);
+        let run = || {
+            Some(Run {
+                traceparent: Traceparent::new(TP).unwrap(),
+                start: 0,
+                rows: Some(0),
+            })
+        };
+        assert!(!step(statement, run));
+        prepared(statement, db, Query::new(c"SELECT 1"));
+
+        // not serving a trace when it started
+        assert!(!step(statement, || None));
+        assert!(!step(statement, run));
+        assert!(end(statement).is_none());
+
+        assert!(step(statement, run));
+        row(statement);
+        row(statement);
+        assert!(step(statement, || None));
+        let ended = end(statement).unwrap();
+        assert_eq!(ended.run.rows, Some(2));
+        assert_eq!(ended.db, db);
+
+        assert!(step(statement, run));
+        assert!(forget(statement).is_some());
+        assert!(!step(statement, run));
+        assert!(lock().is_empty());
+    }
+}
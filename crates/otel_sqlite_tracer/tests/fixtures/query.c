@@ -0,0 +1,71 @@
+/* Creates a table in the database it is given, then reads it back with a prepared
+ * statement, printing each row; steps through another statement twice, reset in
+ * between; then runs statements with sqlite3_exec, a query, one that fails and a batch,
+ * printing how many rows the query called back with and what the failure returned.
+ * Declares the little of SQLite it uses itself, since SQLite's headers may not be
+ * installed. */
+#include <stdio.h>
+
+#include "preload_testkit.h"
+
+typedef struct sqlite3 sqlite3;
+typedef struct sqlite3_stmt sqlite3_stmt;
+typedef int (*sqlite3_callback)(void *arg, int columns, char **values, char **names);
+
+#define SQLITE_OK 0
+#define SQLITE_ROW 100
+
+int sqlite3_open(const char *path, sqlite3 **db);
+int sqlite3_close(sqlite3 *db);
+int sqlite3_exec(sqlite3 *db, const char *sql, sqlite3_callback callback, void *arg,
+                 char **errmsg);
+int sqlite3_prepare_v2(sqlite3 *db, const char *sql, int bytes, sqlite3_stmt **statement,
+                       const char **tail);
+int sqlite3_step(sqlite3_stmt *statement);
+int sqlite3_reset(sqlite3_stmt *statement);
+int sqlite3_finalize(sqlite3_stmt *statement);
+const unsigned char *sqlite3_column_text(sqlite3_stmt *statement, int column);
+
+static int count(void *arg, int columns, char **values, char **names) {
+    (void)columns, (void)values, (void)names;
+    ++*(int *)arg;
+    return 0;
+}
+
+int main(int argc, char **argv) {
+    if (argc != 2 || testkit_start() != 0)
+        return 1;
+    sqlite3 *db;
+    sqlite3_stmt *statement, *first;
+    if (sqlite3_open(argv[1], &db) != SQLITE_OK ||
+        sqlite3_exec(db,
+                     "CREATE TABLE fruit (name TEXT, price INTEGER); "
+                     "INSERT INTO fruit VALUES ('apple', 3), ('pear', 5)",
+                     NULL, NULL, NULL) != SQLITE_OK ||
+        sqlite3_prepare_v2(db, "SELECT name FROM fruit WHERE price > 1 ORDER BY name", -1,
+                           &statement, NULL) != SQLITE_OK ||
+        sqlite3_prepare_v2(db, "select price from fruit", -1, &first, NULL) != SQLITE_OK) {
+        fprintf(stderr, "query: failed\n");
+        return 1;
+    }
+    while (sqlite3_step(statement) == SQLITE_ROW)
+        printf("%s\n", sqlite3_column_text(statement, 0));
+    sqlite3_finalize(statement);
+    /* the first row only, then the statement again from the start */
+    if (sqlite3_step(first) != SQLITE_ROW || sqlite3_reset(first) != SQLITE_OK ||
+        sqlite3_step(first) != SQLITE_ROW) {
+        fprintf(stderr, "query: no first row\n");
+        return 1;
+    }
+    sqlite3_finalize(first);
+
+    int rows = 0;
+    if (sqlite3_exec(db, "SELECT * FROM fruit", count, &rows, NULL) != SQLITE_OK)
+        return 1;
+    printf("called back %d times\n", rows);
+    printf("missing: %d\n", sqlite3_exec(db, "INSERT INTO missing VALUES (1)", NULL, NULL, NULL));
+    if (sqlite3_exec(db, "BEGIN; DELETE FROM fruit; COMMIT", NULL, NULL, NULL) != SQLITE_OK)
+        return 1;
+    sqlite3_close(db);
+    return 0;
+}
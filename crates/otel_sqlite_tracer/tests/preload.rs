@@ -0,0 +1,100 @@
+#![cfg(target_os = "linux")]
+
+use preload_testkit::{Fixture, Run, SPAN, Span, Spans, TRACE, find_library};
+use std::path::PathBuf;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs the fixture on a database of its own for the test `name`, returning the
+    /// database's path and the spans recorded, or `None` if SQLite isn't installed.
+    fn query(name: &str) -> Option<(PathBuf, Spans)> {
+        if find_library("libsqlite3.so.0").is_none() {
+            eprintln!("skipped: no libsqlite3.so.0");
+            return None;
+        }
+        let path = std::env::temp_dir().join(format!(
+            "otel_sqlite_tracer-{}-{name}.db",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+
+        let program = Fixture::c("tests/fixtures/query.c")
+            .flag("-l:libsqlite3.so.0")
+            .compile();
+        let out = Run::traced(&program, "otel_sqlite_tracer")
+            .arg(&path)
+            .output()
+            .success();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(
+            out.lines(),
+            ["apple", "pear", "called back 2 times", "missing: 1"]
+        );
+        Some((path, out.spans()))
+    }
+
+    /// The runs of the statement `text`.
+    fn runs<'a>(spans: &'a Spans, text: &str) -> Vec<&'a Span> {
+        spans
+            .children_of(TRACE, SPAN)
+            .filter(|s| s.str("db.query.text") == Some(text))
+            .collect()
+    }
+
+    #[test]
+    fn statements_are_recorded_from_first_step_to_last() {
+        let Some((path, spans)) = query("steps") else {
+            return;
+        };
+        let [select] = runs(
+            &spans,
+            "SELECT name FROM fruit WHERE price > ? ORDER BY name",
+        )[..] else {
+            panic!("one run: {spans:?}");
+        };
+        assert_eq!(select.name, "SELECT");
+        assert_eq!(select.kind, "client");
+        assert_eq!(select.str("db.system.name"), Some("sqlite"));
+        assert_eq!(select.str("db.operation.name"), Some("SELECT"));
+        assert_eq!(select.str("db.namespace"), path.to_str());
+        assert_eq!(select.int("db.response.returned_rows"), Some(2), "{select}");
+        assert_eq!(select.str("error.type"), None);
+        // the first row only, then again from the start
+        let first = runs(&spans, "select price from fruit");
+        assert_eq!(first.len(), 2, "{spans:?}");
+        for run in first {
+            assert_eq!(run.int("db.response.returned_rows"), Some(1), "{run}");
+        }
+    }
+
+    #[test]
+    fn exec_is_one_run_with_its_rows_counted() {
+        let Some((_, spans)) = query("exec") else {
+            return;
+        };
+        let [select] = runs(&spans, "SELECT * FROM fruit")[..] else {
+            panic!("one run: {spans:?}");
+        };
+        assert_eq!(select.name, "SELECT");
+        assert_eq!(select.int("db.response.returned_rows"), Some(2), "{select}");
+        let [missing] = runs(&spans, "INSERT INTO missing VALUES (?)")[..] else {
+            panic!("one run: {spans:?}");
+        };
+        assert_eq!(missing.name, "INSERT");
+        assert_eq!(missing.str("db.response.status_code"), Some("1"));
+        assert_eq!(missing.str("error.type"), Some("SQL logic error"));
+        let create = "CREATE TABLE fruit (name TEXT, price INTEGER); \
+                      INSERT INTO fruit VALUES (?, ?), (?, ?)";
+        for text in [create, "BEGIN; DELETE FROM fruit; COMMIT"] {
+            let [batch] = runs(&spans, text)[..] else {
+                panic!("one batch: {spans:?}");
+            };
+            assert_eq!(batch.name, "sqlite");
+            assert_eq!(batch.str("db.operation.name"), None);
+            assert_eq!(batch.int("db.response.returned_rows"), None);
+            assert_eq!(batch.str("error.type"), None);
+        }
+    }
+}
@@ -0,0 +1,78 @@
+// src/chain.rs
+//
+// The LD_PRELOAD the program is run again with: the shims, in the order the
+// config file lists them, then whatever else was preloaded already, minus
+// the chainloader itself. ld.so resolves a symbol to the first library in
+// the list that has it, and runs their constructors the other way round,
+// the last one's first.
+//
+// A program whose LD_PRELOAD already has every shim isn't run again, which
+// is what stops the run that was started with them (or a child it starts,
+// inheriting them) from starting yet another, even where the chainloader is
+// loaded from /etc/ld.so.preload and so is there every time.
+
+/// The entries of the LD_PRELOAD `list`, which the loader separates with colons or
+/// spaces.
+fn entries(list: &str) -> impl Iterator<Item = &str> {
+    list.split([':', ' ']).filter(|e| !e.is_empty())
+}
+
+/// The library file `entry` names, without its directory.
+fn file_name(entry: &str) -> &str {
+    entry.rsplit('/').next().unwrap_or(entry)
+}
+
+/// The LD_PRELOAD to run the program with to preload `shims`, given that it has `current`
+/// and that the chainloader's library is the file `own`; `None` if there are none, or it
+/// preloads them already.
+pub(crate) fn preload(shims: &[&str], current: &str, own: &str) -> Option<String> {
+    if shims
+        .iter()
+        .all(|shim| entries(current).any(|e| e == *shim))
+    {
+        return None;
+    }
+    let mut list: Vec<&str> = Vec::new();
+    let others = entries(current).filter(|e| file_name(e) != own);
+    for entry in shims.iter().copied().chain(others) {
+        if !list.contains(&entry) {
+            list.push(entry);
+        }
+    }
+    Some(list.join(":"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OWN: &str = "libpreload_chainloader.so";
+
+    #[test]
+    fn shims_go_first_in_their_order() {
+        let shims = ["/opt/libmalloc.so", "/opt/libprop.so"];
+        assert_eq!(
+            preload(&shims, "/opt/libpreload_chainloader.so", OWN).as_deref(),
+            Some("/opt/libmalloc.so:/opt/libprop.so")
+        );
+        assert_eq!(
+            preload(
+                &shims,
+                "libpreload_chainloader.so /usr/lib/libasan.so:/opt/libprop.so",
+                OWN
+            )
+            .as_deref(),
+            Some("/opt/libmalloc.so:/opt/libprop.so:/usr/lib/libasan.so")
+        );
+    }
+
+    #[test]
+    fn a_run_with_its_shims_is_left_alone() {
+        let shims = ["/opt/libmalloc.so", "/opt/libprop.so"];
+        assert_eq!(
+            preload(&shims, "/opt/libmalloc.so:/opt/libprop.so", OWN),
+            None
+        );
+        assert_eq!(preload(&[], "/opt/libpreload_chainloader.so", OWN), None);
+    }
+}
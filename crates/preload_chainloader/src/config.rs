@@ -0,0 +1,138 @@
+// src/config.rs
+//
+// The config file: the shims to preload, in order, each a `[[shim]]` table
+// with a name (for PRELOAD_CHAINLOADER_DISABLE and the warnings), the path
+// of its library and whether it is enabled:
+//
+//     [[shim]]
+//     name = "malloc"
+//     path = "/opt/otel/lib/libotel_malloc_profiler.so"
+//
+//     [[shim]]
+//     name = "fd"
+//     path = "/opt/otel/lib/libotel_fd_tracer.so"
+//     enabled = false
+//
+//     [[shim]]
+//     name = "propagator"
+//     path = "/opt/otel/lib/libotel_posix_pseudo_propegator.so"
+//
+// Anything else in the file is an error, rather than a shim that quietly
+// isn't loaded because its key was misspelled.
+
+/// A shim the file lists.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Shim {
+    pub(crate) name: String,
+    pub(crate) path: String,
+    pub(crate) enabled: bool,
+}
+
+/// The shims listed in the file at `path`, in order.
+pub(crate) fn read(path: &str) -> Result<Vec<Shim>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("can't read {path}: {e}"))?;
+    parse(&text).map_err(|e| format!("{path}: {e}"))
+}
+
+fn parse(s: &str) -> Result<Vec<Shim>, String> {
+    let mut table: toml::Table = s.parse().map_err(|e: toml::de::Error| e.to_string())?;
+    let shims = match table.remove("shim") {
+        None => Vec::new(),
+        Some(toml::Value::Array(shims)) => shims,
+        Some(_) => return Err("shim: expected [[shim]] tables".into()),
+    };
+    if let Some(key) = table.keys().next() {
+        return Err(format!("unknown key {key:?}"));
+    }
+    let mut parsed: Vec<Shim> = Vec::new();
+    for (i, shim) in shims.into_iter().enumerate() {
+        let toml::Value::Table(mut shim) = shim else {
+            return Err("shim: expected [[shim]] tables".into());
+        };
+        let mut string = |key: &str| match shim.remove(key) {
+            Some(toml::Value::String(s)) if !s.trim().is_empty() => Ok(s),
+            _ => Err(format!("shim {}: expected a {key}", i + 1)),
+        };
+        let (name, path) = (string("name")?, string("path")?);
+        // LD_PRELOAD separates its entries with either
+        if path.contains([':', ' ']) {
+            return Err(format!("shim {name:?}: a path can't hold ':' or ' '"));
+        }
+        let enabled = match shim.remove("enabled") {
+            None => true,
+            Some(toml::Value::Boolean(enabled)) => enabled,
+            Some(_) => return Err(format!("shim {name:?}: enabled is true or false")),
+        };
+        if let Some(key) = shim.keys().next() {
+            return Err(format!("shim {name:?}: unknown key {key:?}"));
+        }
+        if parsed.iter().any(|s| s.name == name) {
+            return Err(format!("shim {name:?} is listed twice"));
+        }
+        parsed.push(Shim {
+            name,
+            path,
+            enabled,
+        });
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shims_are_listed_in_order() {
+        let shims = parse(
+            r#"
+            [[shim]]
+            name = "fd"
+            path = "/opt/libotel_fd_tracer.so"
+            enabled = false
+
+            [[shim]]
+            name = "propagator"
+            path = "/opt/libotel_posix_pseudo_propegator.so"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            shims,
+            [
+                Shim {
+                    name: "fd".into(),
+                    path: "/opt/libotel_fd_tracer.so".into(),
+                    enabled: false,
+                },
+                Shim {
+                    name: "propagator".into(),
+                    path: "/opt/libotel_posix_pseudo_propegator.so".into(),
+                    enabled: true,
+                },
+            ]
+        );
+        assert_eq!(parse(""), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn mistakes_are_errors() {
+        let error = |s| parse(s).unwrap_err();
+        assert_eq!(error("shims = []"), "unknown key \"shims\"");
+        assert_eq!(error("[[shim]]\nname = \"fd\""), "shim 1: expected a path");
+        assert_eq!(
+            error("[[shim]]\nname = \"fd\"\npath = \"/a.so\"\nenable = false"),
+            "shim \"fd\": unknown key \"enable\""
+        );
+        assert_eq!(
+            error("[[shim]]\nname = \"fd\"\npath = \"/a.so:/b.so\""),
+            "shim \"fd\": a path can't hold ':' or ' '"
+        );
+        assert_eq!(
+            error(
+                "[[shim]]\nname = \"fd\"\npath = \"/a.so\"\n[[shim]]\nname = \"fd\"\npath = \"/b.so\""
+            ),
+            "shim \"fd\" is listed twice"
+        );
+    }
+}
@@ -0,0 +1,48 @@
+// src/exec.rs
+//
+// Running the program again, from the start, with the shims preloaded. It
+// is the same executable (/proc/self/exe, which is also right for a script,
+// whose executable is its interpreter) with the arguments it was started
+// with, read back from /proc/self/cmdline before main has had a chance to
+// change them, and the same environment but for LD_PRELOAD. The process
+// keeps its pid, its descriptors and its parent.
+
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+
+/// Replaces the process with a new run of its program, with `preload` as its LD_PRELOAD.
+/// Only returns if that couldn't be done.
+pub(crate) fn again(preload: &str) -> io::Error {
+    let cmdline = match std::fs::read("/proc/self/cmdline") {
+        Ok(cmdline) => cmdline,
+        Err(e) => return e,
+    };
+    let args: Vec<CString> = cmdline
+        .split(|&b| b == 0)
+        // each argument ends with a NUL, the last one included
+        .take(cmdline.iter().filter(|&&b| b == 0).count())
+        .map(|arg| CString::new(arg).expect("split at the NULs"))
+        .collect();
+    let mut env: Vec<CString> = std::env::vars_os()
+        .filter(|(key, _)| key != "LD_PRELOAD")
+        .filter_map(|(key, value)| {
+            let mut var = key.as_bytes().to_vec();
+            var.push(b'=');
+            var.extend_from_slice(value.as_bytes());
+            CString::new(var).ok()
+        })
+        .collect();
+    match CString::new(format!("LD_PRELOAD={preload}")) {
+        Ok(var) => env.push(var),
+        Err(e) => return io::Error::new(io::ErrorKind::InvalidInput, e),
+    }
+    let pointers = |strings: &[CString]| {
+        let mut pointers: Vec<_> = strings.iter().map(|s| s.as_ptr()).collect();
+        pointers.push(std::ptr::null());
+        pointers
+    };
+    let (argv, envp) = (pointers(&args), pointers(&env));
+    unsafe { libc::execve(c"/proc/self/exe".as_ptr(), argv.as_ptr(), envp.as_ptr()) };
+    io::Error::last_os_error()
+}
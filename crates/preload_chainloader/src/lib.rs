@@ -0,0 +1,98 @@
+// src/lib.rs
+//
+// A loader for the workspace's shims, so an operator preloads one library
+// instead of keeping a colon-separated LD_PRELOAD of several in the right
+// order by hand. Its config file (config.rs) lists the shims, in order,
+// each with a flag to turn it off; its constructor reads it and runs the
+// program again with them in its LD_PRELOAD (chain.rs, exec.rs).
+//
+// They have to be preloaded by ld.so, rather than dlopen'd from here: a
+// library opened after startup comes after libc in the lookup order, so
+// none of the functions a shim exports would be the ones the program
+// calls. Running the program again also leaves the order their
+// constructors run in to ld.so, which is the order it always uses for
+// preloads, whatever the chainloader was loaded alongside.
+//
+// PRELOAD_CHAINLOADER_CONFIG names the config file, by default
+// /etc/preload_chainloader.toml (without one it does nothing), and
+// PRELOAD_CHAINLOADER_DISABLE is a comma-separated list of shims, by name,
+// to leave out of this run. A setuid or setgid program is left as it is,
+// as ld.so would leave it.
+
+mod chain;
+mod config;
+mod exec;
+
+use libc::{AT_SECURE, c_void};
+
+/// The config file read when `PRELOAD_CHAINLOADER_CONFIG` doesn't name another.
+pub const DEFAULT_CONFIG: &str = "/etc/preload_chainloader.toml";
+
+#[used]
+#[cfg_attr(
+    any(target_os = "linux", target_os = "android"),
+    unsafe(link_section = ".init_array")
+)]
+static CONSTRUCTOR: extern "C" fn() = constructor;
+
+extern "C" fn constructor() {
+    // never unwind into the dynamic loader
+    let _ = std::panic::catch_unwind(chainload);
+}
+
+fn chainload() {
+    if unsafe { libc::getauxval(AT_SECURE) } != 0 {
+        return;
+    }
+    let var = |name| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+    let path = match var("PRELOAD_CHAINLOADER_CONFIG") {
+        Some(path) => path.trim().to_string(),
+        // without a config of its own, the chainloader has nothing to do
+        None if !std::path::Path::new(DEFAULT_CONFIG).exists() => return,
+        None => DEFAULT_CONFIG.to_string(),
+    };
+    let shims = match config::read(&path) {
+        Ok(shims) => shims,
+        Err(e) => return eprintln!("preload_chainloader: {e}, loading no shims"),
+    };
+    let disabled = var("PRELOAD_CHAINLOADER_DISABLE").unwrap_or_default();
+    let disabled: Vec<&str> = disabled.split(',').map(str::trim).collect();
+    for name in &disabled {
+        if !name.is_empty() && !shims.iter().any(|s| s.name == *name) {
+            eprintln!(
+                "preload_chainloader: PRELOAD_CHAINLOADER_DISABLE names {name:?}, which {path} doesn't list"
+            );
+        }
+    }
+    let mut preload: Vec<&str> = Vec::new();
+    for shim in &shims {
+        if !shim.enabled || disabled.contains(&shim.name.as_str()) {
+            continue;
+        }
+        // a bare file name is for ld.so to search for
+        if shim.path.contains('/') && !std::path::Path::new(&shim.path).exists() {
+            eprintln!(
+                "preload_chainloader: shim {:?}: {} doesn't exist, leaving it out",
+                shim.name, shim.path
+            );
+            continue;
+        }
+        preload.push(&shim.path);
+    }
+    let current = std::env::var("LD_PRELOAD").unwrap_or_default();
+    if let Some(preload) = chain::preload(&preload, &current, &own_file_name()) {
+        let e = exec::again(&preload);
+        eprintln!("preload_chainloader: can't run the program again with its shims: {e}");
+    }
+}
+
+/// The file name of the chainloader's own library, as ld.so loaded it.
+fn own_file_name() -> String {
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    let addr = &raw const CONSTRUCTOR as *const c_void;
+    if unsafe { libc::dladdr(addr, &mut info) } == 0 || info.dli_fname.is_null() {
+        return "libpreload_chainloader.so".to_string();
+    }
+    let path = unsafe { std::ffi::CStr::from_ptr(info.dli_fname) }.to_string_lossy();
+    path.rsplit('/').next().unwrap_or(&path).to_string()
+}
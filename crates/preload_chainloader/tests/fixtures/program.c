@@ -0,0 +1,11 @@
+#define _GNU_SOURCE
+#include <dlfcn.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+int main(int argc, char **argv) {
+    const char *(*shim_name)(void) = (const char *(*)(void))dlsym(RTLD_DEFAULT, "shim_name");
+    printf("shim_name %s\n", shim_name ? shim_name() : "none");
+    for (int i = 1; i < argc; i++) printf("arg %s\n", argv[i]);
+    return 0;
+}
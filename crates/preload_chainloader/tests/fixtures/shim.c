@@ -0,0 +1,8 @@
+// A stand-in for a shim: says when its constructor runs, and answers
+// shim_name() with its own name, so the program can tell which one ld.so
+// resolved it to.
+#include <stdio.h>
+
+const char *shim_name(void) { return SHIM; }
+
+__attribute__((constructor)) static void init(void) { printf("init %s\n", SHIM); }
@@ -0,0 +1,89 @@
+#![cfg(target_os = "linux")]
+
+use preload_testkit::{Fixture, Output, Run, cdylib, scratch_dir};
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shim(name: &str) -> PathBuf {
+        Fixture::c("tests/fixtures/shim.c")
+            .shared()
+            .name(&format!("shim_{name}"))
+            .flag(&format!("-DSHIM=\"{name}\""))
+            .compile()
+    }
+
+    /// A config file, `<test>.toml` in the scratch directory, listing `shims`.
+    fn config(test: &str, shims: &[(&str, &Path, bool)]) -> PathBuf {
+        let mut text = String::new();
+        for (name, path, enabled) in shims {
+            text += &format!(
+                "[[shim]]\nname = {name:?}\npath = {:?}\nenabled = {enabled}\n\n",
+                path.display()
+            );
+        }
+        let path = scratch_dir().join(format!("preload_chainloader_{test}.toml"));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn run(config: &Path, disable: &str) -> Output {
+        let program = Fixture::c("tests/fixtures/program.c").compile();
+        Run::new(&program)
+            .arg("one")
+            .arg("two words")
+            .preload(&[cdylib("preload_chainloader")])
+            .env("PRELOAD_CHAINLOADER_CONFIG", config)
+            .env("PRELOAD_CHAINLOADER_DISABLE", disable)
+            .output()
+            .success()
+    }
+
+    #[test]
+    fn shims_are_preloaded_in_the_configured_order() {
+        let (a, b) = (shim("a"), shim("b"));
+        let config = config("order", &[("a", &a, true), ("b", &b, true)]);
+        let out = run(&config, "");
+        // ld.so runs the preloads' constructors last first, and resolves to the first
+        assert_eq!(
+            out.lines(),
+            [
+                "init b",
+                "init a",
+                "shim_name a",
+                "arg one",
+                "arg two words"
+            ]
+        );
+        assert_eq!(out.stderr, "");
+    }
+
+    #[test]
+    fn disabled_shims_are_left_out() {
+        let (a, b, c) = (shim("a"), shim("b"), shim("c"));
+        let config = config(
+            "disabled",
+            &[("a", &a, true), ("b", &b, false), ("c", &c, true)],
+        );
+        let out = run(&config, "a");
+        assert_eq!(
+            out.lines(),
+            ["init c", "shim_name c", "arg one", "arg two words"]
+        );
+        assert_eq!(run(&config, "a, c").lines()[0], "shim_name none");
+    }
+
+    #[test]
+    fn a_bad_config_loads_nothing() {
+        let missing = scratch_dir().join("preload_chainloader_missing.toml");
+        let out = run(&missing, "");
+        assert_eq!(out.lines()[0], "shim_name none");
+        assert!(
+            out.stderr.starts_with("preload_chainloader: can't read"),
+            "{}",
+            out.stderr
+        );
+    }
+}
@@ -0,0 +1,8 @@
+// build.rs
+fn main() {
+    // fixtures and cdylibs are built for the target the tests that use them were built for
+    println!(
+        "cargo:rustc-env=PRELOAD_TESTKIT_TARGET={}",
+        std::env::var("TARGET").unwrap()
+    );
+}
@@ -0,0 +1,49 @@
+/* preload_testkit.h: for fixtures run under the preloaded propagator, on the include
+ * path of every Fixture.
+ *
+ * The propagator installs its exporter from a thread of its own once it is loaded, so
+ * a fixture that starts working right away can record its first calls before there is
+ * anything to record them with. testkit_start() waits for that install with
+ * otel_posix_wait_installed(), then installs one itself, synchronously, when the run
+ * asked for traces: a span started before a late install would be dropped when it
+ * ends, its provider shut down. It then seeds the main thread with the context the run
+ * was given, if any. */
+#ifndef PRELOAD_TESTKIT_H
+#define PRELOAD_TESTKIT_H
+
+#include <dlfcn.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+/* Returns 0, or -1 (having said why on stderr) when the propagator isn't loaded or
+ * wouldn't take the context. */
+static inline int testkit_start(void) {
+    int (*install)(const char *) =
+        (int (*)(const char *))dlsym(RTLD_DEFAULT, "otel_posix_install_provider");
+    int (*seed)(const char *) =
+        (int (*)(const char *))dlsym(RTLD_DEFAULT, "otel_posix_set_traceparent");
+    if (install == NULL || seed == NULL) {
+        fputs("preload_testkit: the propagator isn't loaded\n", stderr);
+        return -1;
+    }
+    /* a propagator built without its exporters has no install to wait for */
+    int (*wait_installed)(uint64_t) =
+        (int (*)(uint64_t))dlsym(RTLD_DEFAULT, "otel_posix_wait_installed");
+    if (wait_installed != NULL && wait_installed(10 * 1000) != 0) {
+        fputs("preload_testkit: the propagator's install didn't finish\n", stderr);
+        return -1;
+    }
+    if (getenv("OTEL_POSIX_PROP_TRACES_FILE") != NULL && install(NULL) != 0) {
+        fputs("preload_testkit: couldn't install a provider\n", stderr);
+        return -1;
+    }
+    const char *traceparent = getenv("TESTKIT_TRACEPARENT");
+    if (traceparent != NULL && seed(traceparent) != 0) {
+        fprintf(stderr, "preload_testkit: couldn't seed %s\n", traceparent);
+        return -1;
+    }
+    return 0;
+}
+
+#endif
@@ -0,0 +1,213 @@
+// src/artifact.rs
+//
+// The shared libraries a test preloads. The package under test is easy:
+// cargo builds its cdylib for the integration tests, next to them. Any
+// other is built here, with `cargo build`, because the copy cargo leaves
+// in deps/ for a dependency has whatever features the last build that
+// touched it unified, and is overwritten under the tests using it.
+//
+// So a Cdylib is built into a target directory of the harness's own
+// (cargo still holds the lock on the workspace's while tests run) and then
+// copied out under a name that says which features it has. The copy is
+// renamed into place, so a program still running the last one keeps its
+// file. Each is built once per test binary.
+
+use crate::{TARGET, scratch_dir};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, PoisonError};
+
+static BUILT: Mutex<Option<HashMap<String, PathBuf>>> = Mutex::new(None);
+
+/// The cdylib of the package under test, `lib<name>.so`. Cargo builds it for integration
+/// tests but only refreshes the copy in deps/, next to the test binary; the one in the
+/// profile directory is only there after a `cargo build`.
+pub fn cdylib(name: &str) -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    let deps = exe.parent().unwrap();
+    let file = format!(
+        "lib{}.{}",
+        name.replace('-', "_"),
+        std::env::consts::DLL_EXTENSION
+    );
+    [deps, deps.parent().unwrap()]
+        .iter()
+        .map(|dir| dir.join(&file))
+        .find(|lib| lib.exists())
+        .unwrap_or_else(|| panic!("no {file} next to {}", exe.display()))
+}
+
+/// `libs` as an `LD_PRELOAD` value, loaded (and so searched for symbols) in that order.
+pub fn preload_chain<P: AsRef<Path>>(libs: &[P]) -> OsString {
+    let mut chain = OsString::new();
+    for (i, lib) in libs.iter().enumerate() {
+        if i > 0 {
+            chain.push(":");
+        }
+        chain.push(lib.as_ref());
+    }
+    chain
+}
+
+/// A package's cdylib, built with the features a test needs.
+#[derive(Clone, Debug)]
+pub struct Cdylib {
+    package: String,
+    manifest: Option<PathBuf>,
+    features: Vec<String>,
+    default_features: bool,
+}
+
+impl Cdylib {
+    /// The cdylib of the workspace package `package`, with its default features.
+    pub fn new(package: &str) -> Self {
+        Cdylib {
+            package: package.to_string(),
+            manifest: None,
+            features: Vec::new(),
+            default_features: true,
+        }
+    }
+
+    /// Builds the package from the manifest at `path` instead of the current workspace.
+    pub fn manifest(mut self, path: impl Into<PathBuf>) -> Self {
+        self.manifest = Some(path.into());
+        self
+    }
+
+    /// Enables `features` as well.
+    pub fn features(mut self, features: &[&str]) -> Self {
+        self.features.extend(features.iter().map(|f| f.to_string()));
+        self
+    }
+
+    /// Leaves the default features out.
+    pub fn no_default_features(mut self) -> Self {
+        self.default_features = false;
+        self
+    }
+
+    /// A name for this build, which is also the directory its copy goes in.
+    fn key(&self) -> String {
+        let mut key = self.package.clone();
+        if !self.default_features {
+            key.push_str("-no-default");
+        }
+        for feature in &self.features {
+            key.push('-');
+            key.push_str(&feature.replace('/', "_"));
+        }
+        key
+    }
+
+    /// Builds the cdylib, the first time in this test binary, and returns its path.
+    pub fn build(&self) -> PathBuf {
+        let key = self.key();
+        // held across the build, so tests wanting the same one wait for it
+        let mut built = BUILT.lock().unwrap_or_else(PoisonError::into_inner);
+        let built = built.get_or_insert_with(HashMap::new);
+        if let Some(path) = built.get(&key) {
+            return path.clone();
+        }
+        let path = self.cargo_build(&key);
+        built.insert(key, path.clone());
+        path
+    }
+
+    fn cargo_build(&self, key: &str) -> PathBuf {
+        let scratch = scratch_dir();
+        let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+        let mut command = Command::new(cargo);
+        command
+            .args(["build", "--lib", "--message-format=json-render-diagnostics"])
+            .args(["-p", &self.package, "--target", TARGET])
+            .arg("--target-dir")
+            .arg(scratch.join("target"))
+            .env_remove("CARGO_TARGET_DIR")
+            .env_remove("CARGO_BUILD_TARGET_DIR");
+        if let Some(manifest) = &self.manifest {
+            command.arg("--manifest-path").arg(manifest);
+        }
+        if !self.default_features {
+            command.arg("--no-default-features");
+        }
+        if !self.features.is_empty() {
+            command.args(["--features", &self.features.join(",")]);
+        }
+        let out = command.output().unwrap();
+        assert!(
+            out.status.success(),
+            "failed to build {key}:\n{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        let built = cdylib_in(&self.package, &String::from_utf8_lossy(&out.stdout))
+            .unwrap_or_else(|| panic!("building {key} made no cdylib"));
+
+        let dir = scratch.join("lib").join(key);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(built.file_name().unwrap());
+        let partial = dir.join(".partial");
+        std::fs::copy(&built, &partial).unwrap();
+        std::fs::rename(&partial, &path).unwrap();
+        path
+    }
+}
+
+/// The cdylib of `package` in cargo's JSON build `messages`.
+fn cdylib_in(package: &str, messages: &str) -> Option<PathBuf> {
+    let name = package.replace('-', "_");
+    messages
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|m| m["reason"] == "compiler-artifact" && m["target"]["name"] == name.as_str())
+        .filter(|m| {
+            m["target"]["kind"]
+                .as_array()
+                .is_some_and(|kinds| kinds.iter().any(|k| k == "cdylib"))
+        })
+        .flat_map(|m| m["filenames"].as_array().cloned().unwrap_or_default())
+        .filter_map(|f| f.as_str().map(PathBuf::from))
+        .find(|f| f.extension() == Some(std::env::consts::DLL_EXTENSION.as_ref()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cdylibs_are_found_in_build_messages() {
+        let messages = [
+            r#"{"reason":"compiler-artifact","target":{"name":"libc","kind":["lib"]},"filenames":["/t/liblibc.rlib"]}"#,
+            "not json",
+            r#"{"reason":"compiler-artifact","target":{"name":"my_shim","kind":["cdylib","rlib"]},"filenames":["/t/libmy_shim.so","/t/libmy_shim.rlib"]}"#,
+            r#"{"reason":"build-finished","success":true}"#,
+        ]
+        .join("\n");
+        assert_eq!(
+            cdylib_in("my-shim", &messages),
+            Some(PathBuf::from("/t/libmy_shim.so"))
+        );
+        assert_eq!(cdylib_in("libc", &messages), None);
+    }
+
+    #[test]
+    fn chains_are_colon_separated() {
+        assert_eq!(
+            preload_chain(&["/a/libone.so", "libtwo.so"]),
+            "/a/libone.so:libtwo.so"
+        );
+        assert_eq!(preload_chain::<&str>(&[]), "");
+    }
+
+    #[test]
+    fn builds_are_named_for_their_features() {
+        let lib = Cdylib::new("shim")
+            .no_default_features()
+            .features(&["a", "dep/b"]);
+        assert_eq!(lib.key(), "shim-no-default-a-dep_b");
+        assert_eq!(Cdylib::new("shim").key(), "shim");
+    }
+}
@@ -0,0 +1,139 @@
+// src/fixture.rs
+//
+// C and C++ programs for a shim to be preloaded into, compiled with the cc
+// crate's idea of the compiler for the target (CC, CXX and the target's
+// own variables are honoured). Fixtures are built unoptimized, with
+// -pthread and -ldl, with the harness's include/ on the include path for
+// preload_testkit.h, and go in fixtures/ under the scratch directory.
+// Each is compiled to a file of its own and renamed into place, so tests
+// compiling the same fixture at once don't write over each other.
+
+use crate::{TARGET, scratch_dir};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static COMPILED: AtomicUsize = AtomicUsize::new(0);
+
+/// A C or C++ source file to compile into an executable or a shared library.
+#[derive(Clone, Debug)]
+pub struct Fixture {
+    source: PathBuf,
+    cpp: bool,
+    shared: bool,
+    includes: Vec<PathBuf>,
+    flags: Vec<String>,
+    name: Option<String>,
+}
+
+impl Fixture {
+    /// The C source at `source`, relative to the package being tested (where cargo runs
+    /// its tests).
+    pub fn c(source: impl Into<PathBuf>) -> Self {
+        Fixture {
+            source: source.into(),
+            cpp: false,
+            shared: false,
+            includes: Vec::new(),
+            flags: Vec::new(),
+            name: None,
+        }
+    }
+
+    /// The C++ source at `source`.
+    pub fn cpp(source: impl Into<PathBuf>) -> Self {
+        Fixture {
+            cpp: true,
+            ..Fixture::c(source)
+        }
+    }
+
+    /// Builds a shared library, `lib<name>.so`, instead of an executable.
+    pub fn shared(mut self) -> Self {
+        self.shared = true;
+        self
+    }
+
+    /// Adds `dir` to the include path.
+    pub fn include(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.includes.push(dir.into());
+        self
+    }
+
+    /// Passes `flag` to the compiler, after the source.
+    pub fn flag(mut self, flag: &str) -> Self {
+        self.flags.push(flag.to_string());
+        self
+    }
+
+    /// Names the output `name` instead of after the source, for a fixture built twice
+    /// with different flags.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Compiles the fixture and returns the path of what was built.
+    pub fn compile(&self) -> PathBuf {
+        let dir = scratch_dir().join("fixtures");
+        std::fs::create_dir_all(&dir).unwrap();
+        let stem = self.source.file_stem().unwrap().to_string_lossy();
+        let name = self.name.as_deref().unwrap_or(&stem);
+        let out = if self.shared {
+            dir.join(format!("lib{name}.so"))
+        } else {
+            dir.join(name)
+        };
+        let mut command = compiler(self.cpp).to_command();
+        command
+            .arg("-I")
+            .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/include"));
+        for include in &self.includes {
+            command.arg("-I").arg(include);
+        }
+        command.arg(&self.source).args(&self.flags);
+        if self.shared {
+            command.args(["-shared", "-fPIC"]);
+        }
+        let partial = dir.join(format!(
+            ".{name}.{}.{}",
+            std::process::id(),
+            COMPILED.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = command
+            .args(["-pthread", "-ldl", "-o"])
+            .arg(&partial)
+            .output()
+            .unwrap();
+        assert!(
+            result.status.success(),
+            "failed to compile fixture {}:\n{}",
+            self.source.display(),
+            String::from_utf8_lossy(&result.stderr)
+        );
+        std::fs::rename(&partial, &out).unwrap();
+        out
+    }
+}
+
+/// Where the compiler finds the shared library `file`, if it does.
+pub fn find_library(file: &str) -> Option<PathBuf> {
+    let out = compiler(false)
+        .to_command()
+        .arg(format!("-print-file-name={file}"))
+        .output()
+        .unwrap();
+    // the name comes back as it was when it isn't found
+    let path = String::from_utf8_lossy(&out.stdout).trim().to_string();
+    (path != file).then(|| PathBuf::from(path))
+}
+
+/// The C (or C++) compiler for the target the tests were built for.
+fn compiler(cpp: bool) -> cc::Tool {
+    cc::Build::new()
+        .cargo_metadata(false)
+        .cpp(cpp)
+        .opt_level(0)
+        .target(TARGET)
+        .host(TARGET)
+        .get_compiler()
+}
@@ -0,0 +1,43 @@
+// src/lib.rs
+//
+// The harness the workspace's preload shims share for their end-to-end
+// tests: the shim built as a cdylib (artifact.rs), a C or C++ program
+// compiled to load it into (fixture.rs), the program run with an
+// LD_PRELOAD chain and its output captured (run.rs), and the spans it
+// exported read back for assertions (spans.rs).
+//
+// Everything it builds goes under the workspace's target directory, in
+// preload_testkit/ beside the profile directories, so nothing is left in
+// the source tree and a `cargo clean` removes it. Whatever goes wrong
+// panics with what's needed to see why: the compiler's or the program's
+// output, or the spans that were there.
+
+mod artifact;
+mod fixture;
+mod run;
+mod spans;
+
+pub use artifact::{Cdylib, cdylib, preload_chain};
+pub use fixture::{Fixture, find_library};
+pub use run::{Output, Run};
+pub use spans::{Event, Span, Spans};
+
+use std::path::PathBuf;
+
+/// The trace a [`Run::traced`] program serves, and the span its calls are recorded under.
+pub const TRACE: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+pub const SPAN: &str = "00f067aa0ba902b7";
+
+/// The target the tests were built for, which fixtures and cdylibs are built for too.
+pub(crate) const TARGET: &str = env!("PRELOAD_TESTKIT_TARGET");
+
+/// The directory everything the harness builds goes in: preload_testkit/ beside the
+/// profile directory of the running test binary.
+pub fn scratch_dir() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    // target/<profile>/deps/<test binary>
+    let profile = exe.parent().and_then(|deps| deps.parent()).unwrap();
+    let dir = profile.parent().unwrap().join("preload_testkit");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
@@ -0,0 +1,238 @@
+// src/run.rs
+//
+// Running a program under a preload chain. The child starts from the
+// test's environment less anything that would load or export for it
+// unasked (an LD_PRELOAD of the test runner's, an OTLP endpoint), and has
+// its output read on threads of its own while it runs, so a chatty one
+// can't fill a pipe and stall. One still running at the timeout is
+// killed, and the test fails with what it had printed, instead of hanging
+// the whole run.
+//
+// With traces() the child writes its spans to a file of its own, through
+// the propagator's file exporter, for Output::spans to read back, and
+// traceparent() gives it a context to record under; a fixture takes both
+// up with testkit_start() from include/preload_testkit.h. traced() is the
+// run a shim's tests start from: the propagator and the shim preloaded,
+// serving TRACE, with its spans exported.
+
+use crate::spans::Spans;
+use crate::{Cdylib, SPAN, TRACE, cdylib, preload_chain, scratch_dir};
+use std::ffi::OsStr;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+// what a test's own environment may have that would change what the child does
+const CLEARED: &[&str] = &[
+    "LD_PRELOAD",
+    "LD_AUDIT",
+    "OTEL_EXPORTER_OTLP_ENDPOINT",
+    "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    "OTEL_POSIX_PROP_TRACES_FILE",
+    "OTEL_POSIX_PROP_CONFIG",
+    "TRACEPARENT",
+    "TRACESTATE",
+    "TESTKIT_TRACEPARENT",
+];
+
+static TRACES: AtomicUsize = AtomicUsize::new(0);
+
+/// A program to run, and how.
+#[derive(Debug)]
+pub struct Run {
+    command: Command,
+    timeout: Duration,
+    traces: Option<PathBuf>,
+}
+
+impl Run {
+    /// Runs `program`, with none of the preload or export settings of the test's own
+    /// environment.
+    pub fn new(program: impl AsRef<OsStr>) -> Self {
+        let mut command = Command::new(program);
+        for var in CLEARED {
+            command.env_remove(var);
+        }
+        Run {
+            command,
+            timeout: DEFAULT_TIMEOUT,
+            traces: None,
+        }
+    }
+
+    /// Runs `program` as a shim's tests do: with the propagator, built with its default
+    /// features, and the package under test's cdylib `shim` preloaded, seeded with
+    /// [`SPAN`] of [`TRACE`], and its spans exported for [`Output::spans`].
+    pub fn traced(program: impl AsRef<OsStr>, shim: &str) -> Self {
+        let propagator = Cdylib::new("otel_posix_pseudo_propegator").build();
+        Run::new(program)
+            .preload(&[propagator, cdylib(shim)])
+            .traceparent(&format!("00-{TRACE}-{SPAN}-01"))
+            .traces()
+    }
+
+    /// Passes `arg` to the program.
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.command.arg(arg);
+        self
+    }
+
+    /// Sets `key` to `value` in the program's environment.
+    pub fn env(mut self, key: impl AsRef<OsStr>, value: impl AsRef<OsStr>) -> Self {
+        self.command.env(key, value);
+        self
+    }
+
+    /// Preloads `libs`, in that order.
+    pub fn preload<P: AsRef<Path>>(self, libs: &[P]) -> Self {
+        self.env("LD_PRELOAD", preload_chain(libs))
+    }
+
+    /// Has the propagator, preloaded, export the program's spans to a file of its own.
+    pub fn traces(mut self) -> Self {
+        let dir = scratch_dir().join("traces");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!(
+            "{}-{}.jsonl",
+            std::process::id(),
+            TRACES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_file(&path);
+        self.command.env("OTEL_POSIX_PROP_TRACES_FILE", &path);
+        self.traces = Some(path);
+        self
+    }
+
+    /// Has `testkit_start()` seed the program's main thread with `traceparent`.
+    pub fn traceparent(self, traceparent: &str) -> Self {
+        self.env("TESTKIT_TRACEPARENT", traceparent)
+    }
+
+    /// Fails the test if the program is still running after `timeout` (a minute unless
+    /// set).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Runs the program to the end and returns what it printed.
+    pub fn output(mut self) -> Output {
+        let program = self.command.get_program().to_string_lossy().into_owned();
+        let mut child = self
+            .command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap_or_else(|e| panic!("failed to run {program}: {e}"));
+        let stdout = drain(child.stdout.take().unwrap());
+        let stderr = drain(child.stderr.take().unwrap());
+
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait().unwrap() {
+                break Some(status);
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        let stdout = stdout.join().unwrap();
+        let stderr = stderr.join().unwrap();
+        let Some(status) = status else {
+            panic!(
+                "{program} still running after {:?}\nstdout:\n{stdout}\nstderr:\n{stderr}",
+                self.timeout
+            );
+        };
+        Output {
+            program,
+            status,
+            stdout,
+            stderr,
+            traces: self.traces,
+        }
+    }
+}
+
+/// Reads `pipe` to the end on a thread of its own.
+fn drain(mut pipe: impl Read + Send + 'static) -> std::thread::JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut out = Vec::new();
+        let _ = pipe.read_to_end(&mut out);
+        String::from_utf8_lossy(&out).into_owned()
+    })
+}
+
+/// What a program run printed, and how it ended.
+#[derive(Debug)]
+pub struct Output {
+    program: String,
+    pub status: ExitStatus,
+    pub stdout: String,
+    pub stderr: String,
+    traces: Option<PathBuf>,
+}
+
+impl Output {
+    /// Fails the test, with the program's output, unless it exited with 0.
+    pub fn success(self) -> Self {
+        assert!(
+            self.status.success(),
+            "{} failed ({})\nstdout:\n{}\nstderr:\n{}",
+            self.program,
+            self.status,
+            self.stdout,
+            self.stderr
+        );
+        self
+    }
+
+    /// The lines the program wrote to stdout.
+    pub fn lines(&self) -> Vec<&str> {
+        self.stdout.lines().collect()
+    }
+
+    /// The spans the program exported, when it was run with [`Run::traces`].
+    pub fn spans(&self) -> Spans {
+        let path = self
+            .traces
+            .as_ref()
+            .unwrap_or_else(|| panic!("{} wasn't run with traces()", self.program));
+        // nothing exported leaves no file
+        let text = std::fs::read_to_string(path).unwrap_or_default();
+        Spans::parse(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_is_captured() {
+        let out = Run::new("sh")
+            .arg("-c")
+            .arg("echo one; echo two; echo oops >&2; echo \"$LD_PRELOAD\"")
+            .output()
+            .success();
+        assert_eq!(out.lines(), ["one", "two", ""]);
+        assert_eq!(out.stderr, "oops\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "still running")]
+    fn hung_programs_are_killed() {
+        Run::new("sleep")
+            .arg("10")
+            .timeout(Duration::from_millis(100))
+            .output();
+    }
+}
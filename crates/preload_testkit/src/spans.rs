@@ -0,0 +1,261 @@
+// src/spans.rs
+//
+// Spans read back from the propagator's file exporter, a line of JSON
+// each (see its src/file_export.rs), with the lookups the tests keep
+// writing: by name, by parent, by attribute. The ones that expect
+// something fail the test with every span there was, which is usually
+// all it takes to see what went wrong.
+
+use serde_json::{Map, Value};
+use std::fmt;
+
+/// A span as the propagator's file exporter wrote it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub trace_id: String,
+    /// Empty for a root span.
+    pub parent_span_id: String,
+    pub span_id: String,
+    pub name: String,
+    pub kind: String,
+    pub status: String,
+    pub pid: u32,
+    pub attributes: Map<String, Value>,
+    pub events: Vec<Event>,
+}
+
+/// An event on a span, as the file exporter wrote it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub name: String,
+    pub time_unix_nano: u64,
+    pub attributes: Map<String, Value>,
+}
+
+impl Event {
+    /// The attribute `key`.
+    pub fn attribute(&self, key: &str) -> Option<&Value> {
+        self.attributes.get(key)
+    }
+
+    /// The attribute `key`, if it is a string.
+    pub fn str(&self, key: &str) -> Option<&str> {
+        self.attribute(key)?.as_str()
+    }
+
+    /// The attribute `key`, if it is an integer.
+    pub fn int(&self, key: &str) -> Option<i64> {
+        self.attribute(key)?.as_i64()
+    }
+
+    /// The attribute `key`, if it is a number.
+    pub fn float(&self, key: &str) -> Option<f64> {
+        self.attribute(key)?.as_f64()
+    }
+}
+
+impl Span {
+    fn from_json(line: &str) -> Span {
+        let json: Value = serde_json::from_str(line)
+            .unwrap_or_else(|e| panic!("a span line that isn't JSON ({e}): {line}"));
+        let string = |key: &str| json[key].as_str().unwrap_or_default().to_string();
+        Span {
+            trace_id: string("trace_id"),
+            parent_span_id: string("parent_span_id"),
+            span_id: string("span_id"),
+            name: string("name"),
+            kind: string("kind"),
+            status: string("status"),
+            pid: json["pid"].as_u64().unwrap_or_default() as u32,
+            attributes: json["attributes"].as_object().cloned().unwrap_or_default(),
+            events: json["events"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|event| Event {
+                    name: event["name"].as_str().unwrap_or_default().to_string(),
+                    time_unix_nano: event["time_unix_nano"].as_u64().unwrap_or_default(),
+                    attributes: event["attributes"].as_object().cloned().unwrap_or_default(),
+                })
+                .collect(),
+        }
+    }
+
+    /// The events named `name`, in the order they were added.
+    pub fn events_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Event> {
+        self.events.iter().filter(move |e| e.name == name)
+    }
+
+    /// The attribute `key`.
+    pub fn attribute(&self, key: &str) -> Option<&Value> {
+        self.attributes.get(key)
+    }
+
+    /// The attribute `key`, if it is a string.
+    pub fn str(&self, key: &str) -> Option<&str> {
+        self.attribute(key)?.as_str()
+    }
+
+    /// The attribute `key`, if it is an integer.
+    pub fn int(&self, key: &str) -> Option<i64> {
+        self.attribute(key)?.as_i64()
+    }
+
+    /// The attribute `key`, if it is a number.
+    pub fn float(&self, key: &str) -> Option<f64> {
+        self.attribute(key)?.as_f64()
+    }
+
+    /// Whether this span's parent is the span `span_id` of the trace `trace_id`.
+    pub fn is_child_of(&self, trace_id: &str, span_id: &str) -> bool {
+        self.trace_id == trace_id && self.parent_span_id == span_id
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} {}-{} (parent {:?}, pid {}) {}",
+            self.name,
+            self.trace_id,
+            self.span_id,
+            self.parent_span_id,
+            self.pid,
+            Value::Object(self.attributes.clone())
+        )?;
+        for event in &self.events {
+            write!(
+                f,
+                " [{:?} {}]",
+                event.name,
+                Value::Object(event.attributes.clone())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The spans a program exported.
+#[derive(Clone, Debug, Default)]
+pub struct Spans(Vec<Span>);
+
+impl Spans {
+    /// The spans in `text`, one a line.
+    pub fn parse(text: &str) -> Self {
+        Spans(
+            text.lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(Span::from_json)
+                .collect(),
+        )
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Span> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The spans named `name`.
+    pub fn named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Span> {
+        self.iter().filter(move |s| s.name == name)
+    }
+
+    /// The spans whose parent is the span `span_id` of the trace `trace_id`.
+    pub fn children_of<'a>(
+        &'a self,
+        trace_id: &'a str,
+        span_id: &'a str,
+    ) -> impl Iterator<Item = &'a Span> {
+        self.iter()
+            .filter(move |s| s.is_child_of(trace_id, span_id))
+    }
+
+    /// The one span named `name`; fails the test if there are none or several.
+    pub fn one(&self, name: &str) -> &Span {
+        let mut named = self.iter().filter(|s| s.name == name);
+        match (named.next(), named.next()) {
+            (Some(span), None) => span,
+            (None, _) => panic!("no span named {name:?} in\n{self}"),
+            (Some(_), Some(_)) => panic!("more than one span named {name:?} in\n{self}"),
+        }
+    }
+
+    /// Fails the test unless the span named `name` is a child of the span `span_id` of
+    /// the trace `trace_id`, and returns it.
+    pub fn child(&self, trace_id: &str, span_id: &str, name: &str) -> &Span {
+        self.iter()
+            .find(|s| s.is_child_of(trace_id, span_id) && s.name == name)
+            .unwrap_or_else(|| {
+                panic!("no span named {name:?} under {trace_id}-{span_id} in\n{self}")
+            })
+    }
+}
+
+impl fmt::Display for Spans {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "  (no spans)");
+        }
+        for span in self.iter() {
+            writeln!(f, "  {span}")?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> IntoIterator for &'a Spans {
+    type Item = &'a Span;
+    type IntoIter = std::slice::Iter<'a, Span>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACE: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    #[test]
+    fn spans_are_read_from_json_lines() {
+        let spans = Spans::parse(concat!(
+            r#"{"trace_id":"4bf92f3577b34da6a3ce929d0e0e4736","span_id":"00f067aa0ba902b8","parent_span_id":"00f067aa0ba902b7","name":"open","kind":"internal","start_unix_nano":1000,"end_unix_nano":3500,"status":"unset","service":"app","pid":7,"attributes":{"file.path":"/tmp/x","otel_posix.fd.bytes":12}}"#,
+            "\n\n",
+            r#"{"trace_id":"4bf92f3577b34da6a3ce929d0e0e4736","span_id":"00f067aa0ba902b9","parent_span_id":"","name":"thread","kind":"internal","start_unix_nano":1000,"end_unix_nano":3500,"status":"error","service":"app","pid":7,"attributes":{},"events":[{"name":"poll","time_unix_nano":2000,"attributes":{"otel_posix.blocking.fds":2,"otel_posix.blocking.wait_time":0.25}}]}"#,
+            "\n",
+        ));
+        assert_eq!(spans.len(), 2);
+        let open = spans.child(TRACE, "00f067aa0ba902b7", "open");
+        assert_eq!(open.str("file.path"), Some("/tmp/x"));
+        assert_eq!(open.int("otel_posix.fd.bytes"), Some(12));
+        assert_eq!(open.pid, 7);
+        let thread = spans.one("thread");
+        assert_eq!(thread.status, "error");
+        assert!(open.events.is_empty());
+        let [poll] = &thread.events[..] else {
+            panic!("one event: {thread}");
+        };
+        assert_eq!(poll.name, "poll");
+        assert_eq!(poll.time_unix_nano, 2000);
+        assert_eq!(poll.int("otel_posix.blocking.fds"), Some(2));
+        assert_eq!(poll.float("otel_posix.blocking.wait_time"), Some(0.25));
+        assert_eq!(thread.events_named("poll").count(), 1);
+        assert_eq!(spans.children_of(TRACE, "00f067aa0ba902b8").count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "no span named \"close\"")]
+    fn missing_spans_fail_with_the_rest() {
+        Spans::parse("").one("close");
+    }
+}
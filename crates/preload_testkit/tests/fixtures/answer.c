@@ -0,0 +1,13 @@
+/* Prints what testkit_answer returns, when something loaded exports it. */
+#include <dlfcn.h>
+#include <stdio.h>
+
+int main(void) {
+    int (*answer)(void) = (int (*)(void))dlsym(RTLD_DEFAULT, "testkit_answer");
+    if (answer == NULL) {
+        puts("none");
+        return 1;
+    }
+    printf("%d\n", answer());
+    return 0;
+}
@@ -0,0 +1,8 @@
+// src/lib.rs
+//
+// Exports one function for the answer fixture to find once preloaded.
+
+#[unsafe(no_mangle)]
+pub extern "C" fn testkit_answer() -> i32 {
+    42
+}
@@ -0,0 +1,30 @@
+#![cfg(target_os = "linux")]
+
+use preload_testkit::{Cdylib, Fixture, Run};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixtures_find_what_is_preloaded() {
+        let shim = Cdylib::new("testkit_shim")
+            .manifest("tests/fixtures/shim/Cargo.toml")
+            .build();
+        assert!(shim.ends_with("testkit_shim/libtestkit_shim.so"));
+        // built once
+        assert_eq!(
+            Cdylib::new("testkit_shim")
+                .manifest("tests/fixtures/shim/Cargo.toml")
+                .build(),
+            shim
+        );
+
+        let answer = Fixture::c("tests/fixtures/answer.c").compile();
+        let out = Run::new(&answer).preload(&[&shim]).output().success();
+        assert_eq!(out.lines(), ["42"]);
+        let out = Run::new(&answer).output();
+        assert!(!out.status.success());
+        assert_eq!(out.lines(), ["none"]);
+    }
+}